@@ -56,6 +56,12 @@ pub enum QueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Simulated transaction is invalid: {context}")]
+    InvalidTransaction {
+        context: near_primitives::errors::InvalidTxError,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -188,9 +194,27 @@ pub enum Error {
     /// Invalid block merkle root.
     #[error("Invalid Block Merkle Root")]
     InvalidBlockMerkleRoot,
+    /// Epoch sync proof which doesn't match the blocks it's supposed to prove.
+    #[error("Invalid epoch sync proof: {0}")]
+    InvalidEpochSyncProof(String),
+    /// State parts which don't match the state root they are applied to, by
+    /// part id. They need to be downloaded again.
+    #[error("Invalid state parts: {0:?}")]
+    InvalidStateParts(Vec<u64>),
+    /// Some of the state parts of a shard couldn't be applied. Holds the id
+    /// and the error of every failed part.
+    #[error("Failed to apply state parts: {0:?}")]
+    StatePartsApplyFailed(Vec<(u64, String)>),
     /// Invalid split shard ids.
     #[error("Invalid Split Shard Ids when resharding. shard_id: {0}, parent_shard_id: {1}")]
     InvalidSplitShardsIds(u64, u64),
+    /// The state of the child shards built by resharding doesn't match the
+    /// state of the parent shard.
+    #[error("Invalid split state: {0}")]
+    InvalidSplitState(String),
+    /// The resharding job was cancelled because its result isn't needed.
+    #[error("Resharding cancelled")]
+    ReshardingCancelled,
     /// Someone is not a validator. Usually happens in signature verification
     #[error("Not A Validator")]
     NotAValidator,
@@ -255,6 +279,10 @@ impl Error {
             | Error::CannotBeFinalized
             | Error::StorageError(_)
             | Error::GCError(_)
+            | Error::InvalidSplitState(_)
+            | Error::ReshardingCancelled
+            | Error::StatePartsApplyFailed(_)
+            | Error::InvalidStateParts(_)
             | Error::DBNotFoundErr(_) => false,
             Error::InvalidBlockPastTime(_, _)
             | Error::InvalidBlockFutureTime(_)
@@ -292,6 +320,7 @@ impl Error {
             | Error::InvalidStateRequest(_)
             | Error::InvalidRandomnessBeaconOutput
             | Error::InvalidBlockMerkleRoot
+            | Error::InvalidEpochSyncProof(_)
             | Error::InvalidProtocolVersion
             | Error::NotAValidator
             | Error::InvalidChallengeRoot => true,
@@ -300,7 +329,11 @@ impl Error {
 
     pub fn is_error(&self) -> bool {
         match self {
-            Error::IOErr(_) | Error::Other(_) | Error::DBNotFoundErr(_) => true,
+            Error::IOErr(_)
+            | Error::Other(_)
+            | Error::DBNotFoundErr(_)
+            | Error::InvalidSplitState(_)
+            | Error::StatePartsApplyFailed(_) => true,
             _ => false,
         }
     }
@@ -0,0 +1,143 @@
+use chrono::DateTime;
+use near_primitives::hash::CryptoHash;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::BlockHeight;
+use near_primitives::views::{BlockTimelineStage, BlockTimelineStageView, BlockTimelineView};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Number of most recently received blocks whose timelines are kept.
+const BLOCK_TIMELINE_COUNT: usize = 100;
+
+/// Records when each stage of processing recent blocks started and how long
+/// it took, from the moment the chain starts processing a block until an
+/// approval for it is sent.  Unlike the histograms in `metrics`, this shows
+/// where a specific slow block spent its time.  The timelines are shown as
+/// a waterfall on the `block_timeline` debug page.
+///
+/// The recorder is cheap to clone and can be shared with the threads applying
+/// chunks and with the client.  Stages of blocks which aren't tracked (e.g.
+/// blocks applied during catchup, or blocks which fell out of the buffer) are
+/// ignored.
+#[derive(Clone, Default)]
+pub struct BlockTimelineRecorder {
+    timelines: Arc<Mutex<VecDeque<BlockTimeline>>>,
+}
+
+struct BlockTimeline {
+    height: BlockHeight,
+    hash: CryptoHash,
+    received: Instant,
+    received_utc: DateTime<chrono::Utc>,
+    stages: Vec<(BlockTimelineStage, Instant, Instant)>,
+}
+
+impl BlockTimelineRecorder {
+    /// Starts the timeline of given block.  Does nothing if the block is
+    /// already tracked, e.g. when it's reprocessed after leaving the orphan
+    /// pool.
+    pub fn mark_block_received(&self, hash: &CryptoHash, height: BlockHeight, received: Instant) {
+        let mut timelines = self.timelines.lock().unwrap();
+        if timelines.iter().any(|timeline| &timeline.hash == hash) {
+            return;
+        }
+        if timelines.len() == BLOCK_TIMELINE_COUNT {
+            timelines.pop_front();
+        }
+        timelines.push_back(BlockTimeline {
+            height,
+            hash: *hash,
+            received,
+            received_utc: StaticClock::utc(),
+            stages: vec![],
+        });
+    }
+
+    /// Records a stage of processing given block which lasted from `start`
+    /// until `end`.
+    pub fn record(
+        &self,
+        hash: &CryptoHash,
+        stage: BlockTimelineStage,
+        start: Instant,
+        end: Instant,
+    ) {
+        let mut timelines = self.timelines.lock().unwrap();
+        if let Some(timeline) = timelines.iter_mut().rev().find(|timeline| &timeline.hash == hash) {
+            timeline.stages.push((stage, start, end));
+        }
+    }
+
+    /// Records a stage of processing given block which happened at a single
+    /// point in time, i.e. now.
+    pub fn record_now(&self, hash: &CryptoHash, stage: BlockTimelineStage) {
+        let now = StaticClock::instant();
+        self.record(hash, stage, now, now);
+    }
+
+    /// Returns the timelines, the most recently received block first.
+    pub fn get_block_timelines(&self) -> Vec<BlockTimelineView> {
+        let timelines = self.timelines.lock().unwrap();
+        timelines
+            .iter()
+            .rev()
+            .map(|timeline| BlockTimelineView {
+                height: timeline.height,
+                hash: timeline.hash,
+                received_timestamp: timeline.received_utc,
+                stages: timeline
+                    .stages
+                    .iter()
+                    .map(|(stage, start, end)| BlockTimelineStageView {
+                        stage: *stage,
+                        start_ms: millis(start.saturating_duration_since(timeline.received)),
+                        duration_ms: millis(end.saturating_duration_since(*start)),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockTimelineRecorder, BLOCK_TIMELINE_COUNT};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::views::BlockTimelineStage;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_block_timeline() {
+        let recorder = BlockTimelineRecorder::default();
+        let received = Instant::now();
+        let hashes: Vec<_> = (0..=BLOCK_TIMELINE_COUNT as u64)
+            .map(|height| CryptoHash::hash_bytes(&height.to_le_bytes()))
+            .collect();
+        for (height, hash) in hashes.iter().enumerate() {
+            recorder.mark_block_received(hash, height as u64, received);
+        }
+        let last = hashes.last().unwrap();
+        recorder.record(
+            last,
+            BlockTimelineStage::ApplyChunk(0),
+            received + Duration::from_millis(5),
+            received + Duration::from_millis(15),
+        );
+        // Stages of blocks which are no longer tracked are ignored.
+        recorder.record_now(&hashes[0], BlockTimelineStage::HeadUpdate);
+
+        let timelines = recorder.get_block_timelines();
+        assert_eq!(timelines.len(), BLOCK_TIMELINE_COUNT);
+        assert_eq!(&timelines[0].hash, last);
+        assert_eq!(timelines[0].stages.len(), 1);
+        assert_eq!(timelines[0].stages[0].stage, BlockTimelineStage::ApplyChunk(0));
+        assert_eq!(timelines[0].stages[0].start_ms, 5.);
+        assert_eq!(timelines[0].stages[0].duration_ms, 10.);
+        assert_eq!(timelines.last().unwrap().hash, hashes[1]);
+    }
+}
@@ -3,6 +3,7 @@ use near_epoch_manager::EpochManagerAdapter;
 use near_primitives::block::{Block, Tip};
 use near_primitives::borsh::maybestd::collections::hash_map::Entry;
 use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::static_clock::StaticClock;
 use near_primitives::types::{BlockHeight, ShardId};
@@ -47,6 +48,8 @@ pub struct BlockTrackingStats {
     /// Timestamp when block was received.
     pub received_timestamp: Instant,
     pub received_utc_timestamp: DateTime<chrono::Utc>,
+    /// Peer which sent the block.
+    pub peer_id: PeerId,
     /// Timestamp when block was put to the orphan pool, if it ever was
     pub orphaned_timestamp: Option<Instant>,
     /// Timestamp when block was put to the missing chunks pool
@@ -136,6 +139,7 @@ impl BlocksDelayTracker {
     pub fn mark_block_received(
         &mut self,
         block: &Block,
+        peer_id: &PeerId,
         timestamp: Instant,
         utc_timestamp: DateTime<chrono::Utc>,
     ) {
@@ -162,6 +166,7 @@ impl BlocksDelayTracker {
             entry.insert(BlockTrackingStats {
                 received_timestamp: timestamp,
                 received_utc_timestamp: utc_timestamp,
+                peer_id: peer_id.clone(),
                 orphaned_timestamp: None,
                 missing_chunks_timestamp: None,
                 removed_from_orphan_timestamp: None,
@@ -175,6 +180,11 @@ impl BlocksDelayTracker {
         }
     }
 
+    /// Returns the peer which sent given block, if the block is tracked.
+    pub fn get_block_sender(&self, block_hash: &CryptoHash) -> Option<&PeerId> {
+        self.blocks.get(block_hash).map(|stats| &stats.peer_id)
+    }
+
     pub fn mark_block_dropped(&mut self, block_hash: &CryptoHash, reason: DroppedReason) {
         if let Some(block_entry) = self.blocks.get_mut(block_hash) {
             block_entry.dropped = Some(reason);
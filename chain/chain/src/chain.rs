@@ -1,14 +1,17 @@
 use crate::block_processing_utils::{
     BlockPreprocessInfo, BlockProcessingArtifact, BlocksInProcessing, DoneApplyChunkCallback,
 };
+use crate::block_timeline::BlockTimelineRecorder;
 use crate::blocks_delay_tracker::BlocksDelayTracker;
+use crate::chain_events::{ChainEvent, ChainEventBus};
+use crate::chunk_replay::ChunkReplayArtifact;
 use crate::crypto_hash_timer::CryptoHashTimer;
 use crate::lightclient::get_epoch_block_producers_view;
 use crate::migrations::check_if_block_is_first_with_chunk_of_version;
 use crate::missing_chunks::{BlockLike, MissingChunksPool};
 use crate::state_request_tracker::StateRequestTracker;
 use crate::state_snapshot_actor::MakeSnapshotCallback;
-use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode};
+use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCEstimate, GCMode};
 use crate::types::{
     AcceptedBlock, ApplySplitStateResult, ApplySplitStateResultOrStateChanges,
     ApplyTransactionResult, Block, BlockEconomicsConfig, BlockHeader, BlockStatus, ChainConfig,
@@ -25,6 +28,7 @@ use chrono::Duration;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use itertools::Itertools;
 use lru::LruCache;
+use near_chain_configs::{MutableConfigValue, ReshardingConfig};
 use near_chain_primitives::error::{BlockKnownError, Error, LogTransientStorageError};
 use near_epoch_manager::shard_tracker::ShardTracker;
 use near_epoch_manager::types::BlockHeaderInfo;
@@ -72,12 +76,14 @@ use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    BlockStatusView, DroppedReason, ExecutionOutcomeWithIdView, ExecutionStatusView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus,
-    LightClientBlockView, SignedTransactionView,
+    BlockStatusView, BlockTimelineStage, DroppedReason, ExecutionOutcomeWithIdView,
+    ExecutionStatusView, FinalExecutionOutcomeView, FinalExecutionOutcomeWithReceiptView,
+    FinalExecutionStatus, LightClientBlockView, ReceiptExecutionTreeView, SignedTransactionView,
+    SyncJobProgressView, TransactionExecutionTreeView,
 };
 use near_store::flat::{store_helper, FlatStorageReadyStatus, FlatStorageStatus};
 use near_store::get_genesis_state_roots;
+use near_store::trie::mem::MemTrieConsistencyReport;
 use near_store::{DBCol, ShardTries};
 use once_cell::sync::OnceCell;
 use rand::seq::SliceRandom;
@@ -86,6 +92,7 @@ use rand_chacha::ChaCha20Rng;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration as TimeDuration, Instant};
 use tracing::{debug, error, info, warn, Span};
@@ -148,6 +155,9 @@ pub struct Orphan {
     block: MaybeValidated<Block>,
     provenance: Provenance,
     added: Instant,
+    /// Whether the hash and the signature of the block have been verified
+    /// already, so that they aren't verified again when the block is released.
+    hash_and_signature_verified: bool,
 }
 
 impl BlockLike for Orphan {
@@ -235,6 +245,7 @@ impl OrphanBlockPool {
                 }
                 keep
             });
+            let evicted_too_old = old_len - self.orphans.len();
             let mut heights = self.height_idx.keys().cloned().collect::<Vec<u64>>();
             heights.sort_unstable();
             for h in heights.iter().rev() {
@@ -254,6 +265,12 @@ impl OrphanBlockPool {
             self.orphans_requested_missing_chunks.retain(|x| !removed_hashes.contains(x));
 
             self.evicted += old_len - self.orphans.len();
+            metrics::BLOCK_POOL_EVICTIONS
+                .with_label_values(&["orphan", "too_old"])
+                .inc_by(evicted_too_old as u64);
+            metrics::BLOCK_POOL_EVICTIONS
+                .with_label_values(&["orphan", "too_high"])
+                .inc_by((old_len - self.orphans.len() - evicted_too_old) as u64);
         }
         metrics::NUM_ORPHANS.set(self.orphans.len() as i64);
     }
@@ -425,6 +442,41 @@ pub fn check_known(
 
 type BlockApplyChunksResult = (CryptoHash, Vec<Result<ApplyChunkResult, Error>>);
 
+/// Limits the work done by a single garbage collection call, see `GCConfig`.
+struct GCBudget {
+    blocks_remaining: NumBlocks,
+    deadline: Option<Instant>,
+}
+
+impl GCBudget {
+    fn new(gc_config: &near_chain_configs::GCConfig) -> Self {
+        Self {
+            blocks_remaining: gc_config.gc_blocks_limit,
+            deadline: gc_config.gc_time_limit.map(|limit| StaticClock::instant() + limit),
+        }
+    }
+
+    fn spend_block(&mut self, mode: &str) {
+        self.blocks_remaining = self.blocks_remaining.saturating_sub(1);
+        metrics::GC_BLOCKS_CLEARED.with_label_values(&[mode]).inc();
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted_reason().is_some()
+    }
+
+    /// Returns which limit of the budget has been reached, if any.
+    fn exhausted_reason(&self) -> Option<&'static str> {
+        if self.blocks_remaining == 0 {
+            Some("blocks")
+        } else if self.deadline.map_or(false, |deadline| StaticClock::instant() >= deadline) {
+            Some("time")
+        } else {
+            None
+        }
+    }
+}
+
 /// Facade to the blockchain block processing and storage.
 /// Provides current view on the state according to the chain state.
 pub struct Chain {
@@ -441,6 +493,8 @@ pub struct Chain {
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
     pub blocks_delay_tracker: BlocksDelayTracker,
+    /// Timelines of processing recent blocks, shown on the debug page.
+    pub block_timelines: BlockTimelineRecorder,
     /// Processing a block is done in three stages: preprocess_block, async_apply_chunks and
     /// postprocess_block. The async_apply_chunks is done asynchronously from the ClientActor thread.
     /// `blocks_in_processing` keeps track of all the blocks that have been preprocessed but are
@@ -455,6 +509,12 @@ pub struct Chain {
     /// Prevents re-application of known-to-be-invalid blocks, so that in case of a
     /// protocol issue we can recover faster by focusing on correct blocks.
     invalid_blocks: LruCache<CryptoHash, ()>,
+    /// Directory replay artifacts of chunks are written to, see `chunk_replay`.
+    chunk_replay_artifacts_dir: Option<PathBuf>,
+    /// Throttling of resharding jobs scheduled by this chain.
+    pub(crate) resharding_config: MutableConfigValue<ReshardingConfig>,
+    /// Events of processed blocks are published here, see `chain_events`.
+    chain_event_bus: ChainEventBus,
 
     /// Support for sandbox's patch_state requests.
     ///
@@ -571,10 +631,17 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             blocks_delay_tracker: BlocksDelayTracker::default(),
+            block_timelines: BlockTimelineRecorder::default(),
             apply_chunks_sender: sc,
             apply_chunks_receiver: rc,
             last_time_head_updated: StaticClock::instant(),
             invalid_blocks: LruCache::new(INVALID_CHUNKS_POOL_SIZE),
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
+            chain_event_bus: ChainEventBus::default(),
             pending_state_patch: Default::default(),
             requested_state_parts: StateRequestTracker::new(),
             state_snapshot_helper: None,
@@ -731,12 +798,16 @@ impl Chain {
             blocks_with_missing_chunks: MissingChunksPool::new(),
             blocks_in_processing: BlocksInProcessing::new(),
             invalid_blocks: LruCache::new(INVALID_CHUNKS_POOL_SIZE),
+            chunk_replay_artifacts_dir: chain_config.chunk_replay_artifacts_dir.clone(),
+            resharding_config: chain_config.resharding_config.clone(),
+            chain_event_bus: ChainEventBus::default(),
             genesis: genesis.clone(),
             transaction_validity_period: chain_genesis.transaction_validity_period,
             epoch_length: chain_genesis.epoch_length,
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             blocks_delay_tracker: BlocksDelayTracker::default(),
+            block_timelines: BlockTimelineRecorder::default(),
             apply_chunks_sender: sc,
             apply_chunks_receiver: rc,
             last_time_head_updated: StaticClock::instant(),
@@ -914,7 +985,12 @@ impl Chain {
             return Err(e);
         }
         self.orphans.add(
-            Orphan { block, provenance: Provenance::NONE, added: StaticClock::instant() },
+            Orphan {
+                block,
+                provenance: Provenance::NONE,
+                added: StaticClock::instant(),
+                hash_and_signature_verified: false,
+            },
             requested_missing_chunks,
         );
         Ok(())
@@ -1012,35 +1088,109 @@ impl Chain {
         let _span = tracing::debug_span!(target: "chain", "clear_data").entered();
 
         let head = self.store.head()?;
-        let tail = self.store.tail()?;
         let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
         if gc_stop_height > head.height {
             return Err(Error::GCError("gc_stop_height cannot be larger than head.height".into()));
         }
+        // Roots of in-memory tries below the GC stop height will never be
+        // needed for block processing again.
+        let reclaimed = tries.delete_mem_tries_until_height(gc_stop_height);
+        if reclaimed > 0 {
+            debug!(target: "chain", gc_stop_height, reclaimed, "Reclaimed mem-trie memory");
+        }
         let prev_epoch_id = self.get_block_header(&head.prev_block_hash)?.epoch_id().clone();
         let epoch_change = prev_epoch_id != head.epoch_id;
-        let mut fork_tail = self.store.fork_tail()?;
-        metrics::TAIL_HEIGHT.set(tail as i64);
-        metrics::FORK_TAIL_HEIGHT.set(fork_tail as i64);
-        metrics::CHUNK_TAIL_HEIGHT.set(self.store.chunk_tail()? as i64);
+        if epoch_change {
+            self.spawn_mem_trie_consistency_checks(&tries, &head);
+        }
         metrics::GC_STOP_HEIGHT.set(gc_stop_height as i64);
-        if epoch_change && fork_tail < gc_stop_height {
+        if epoch_change && self.store.fork_tail()? < gc_stop_height {
             // if head doesn't change on the epoch boundary, we may update fork tail several times
             // but that is fine since it doesn't affect correctness and also we limit the number of
             // heights that fork cleaning goes through so it doesn't slow down client either.
             let mut chain_store_update = self.store.store_update();
             chain_store_update.update_fork_tail(gc_stop_height);
             chain_store_update.commit()?;
-            fork_tail = gc_stop_height;
         }
-        let mut gc_blocks_remaining = gc_config.gc_blocks_limit;
+
+        let mut budget = GCBudget::new(gc_config);
+        let result = self.clear_data_within_budget(tries, gc_config, gc_stop_height, &mut budget);
+        if let Some(reason) = budget.exhausted_reason() {
+            metrics::GC_BUDGET_EXHAUSTED.with_label_values(&[reason]).inc();
+        }
+
+        // Tails are persisted after every height so that the next call
+        // continues from where this one stopped.
+        let tail = self.store.tail()?;
+        let fork_tail = self.store.fork_tail()?;
+        metrics::TAIL_HEIGHT.set(tail as i64);
+        metrics::FORK_TAIL_HEIGHT.set(fork_tail as i64);
+        metrics::CHUNK_TAIL_HEIGHT.set(self.store.chunk_tail()? as i64);
+        metrics::GC_DEBT_HEIGHTS
+            .with_label_values(&["canonical"])
+            .set(gc_stop_height.saturating_sub(tail + 1) as i64);
+        metrics::GC_DEBT_HEIGHTS
+            .with_label_values(&["forks"])
+            .set(fork_tail.saturating_sub(tail) as i64);
+        result
+    }
+
+    /// Starts comparing the in-memory tries of the shards at the head against
+    /// the disk state in the background, if enabled by the store config.
+    /// The in-memory tries hold the state root at the head as long as they
+    /// were loaded before it, as chunk trie changes are applied to them.
+    /// Returns the handles of the started checks.
+    pub(crate) fn spawn_mem_trie_consistency_checks(
+        &self,
+        tries: &ShardTries,
+        head: &Tip,
+    ) -> Vec<std::thread::JoinHandle<Option<MemTrieConsistencyReport>>> {
+        let mut handles = Vec::new();
+        let shard_layout = match self.epoch_manager.get_shard_layout(&head.epoch_id) {
+            Ok(shard_layout) => shard_layout,
+            Err(err) => {
+                warn!(target: "chain", ?err, "Cannot get shard layout for mem-trie consistency check");
+                return handles;
+            }
+        };
+        for shard_uid in shard_layout.get_shard_uids() {
+            if tries.get_mem_tries(shard_uid).is_none() {
+                continue;
+            }
+            match self.get_chunk_extra(&head.last_block_hash, &shard_uid) {
+                Ok(chunk_extra) => {
+                    handles.extend(tries.spawn_mem_trie_consistency_check(
+                        shard_uid,
+                        *chunk_extra.state_root(),
+                        head.last_block_hash,
+                    ));
+                }
+                Err(err) => {
+                    warn!(target: "chain", %shard_uid, ?err, "Cannot get state root for mem-trie consistency check")
+                }
+            }
+        }
+        handles
+    }
+
+    /// Cleans forks and the canonical chain below the GC stop height until
+    /// the budget is exhausted.
+    fn clear_data_within_budget(
+        &mut self,
+        tries: ShardTries,
+        gc_config: &near_chain_configs::GCConfig,
+        gc_stop_height: BlockHeight,
+        budget: &mut GCBudget,
+    ) -> Result<(), Error> {
+        let tail = self.store.tail()?;
+        let fork_tail = self.store.fork_tail()?;
 
         // Forks Cleaning
         let gc_fork_clean_step = gc_config.gc_fork_clean_step;
         let stop_height = tail.max(fork_tail.saturating_sub(gc_fork_clean_step));
         for height in (stop_height..fork_tail).rev() {
-            self.clear_forks_data(tries.clone(), height, &mut gc_blocks_remaining)?;
-            if gc_blocks_remaining == 0 {
+            self.clear_forks_data(tries.clone(), height, budget)?;
+            if budget.is_exhausted() {
                 return Ok(());
             }
             let mut chain_store_update = self.store.store_update();
@@ -1050,7 +1200,7 @@ impl Chain {
 
         // Canonical Chain Clearing
         for height in tail + 1..gc_stop_height {
-            if gc_blocks_remaining == 0 {
+            if budget.is_exhausted() {
                 return Ok(());
             }
             let blocks_current_height = self
@@ -1074,7 +1224,7 @@ impl Chain {
                         *block_hash,
                         GCMode::Canonical(tries.clone()),
                     )?;
-                    gc_blocks_remaining -= 1;
+                    budget.spend_block("canonical");
                 } else {
                     return Err(Error::GCError(
                         "block on canonical chain shouldn't have refcount 0".into(),
@@ -1112,11 +1262,53 @@ impl Chain {
         chain_store_update.commit()
     }
 
-    pub fn clear_forks_data(
+    /// Estimates what `clear_data` would delete if it had no limits, without
+    /// deleting anything.  At most `height_limit` heights are included.
+    pub fn estimate_gc(&mut self, height_limit: BlockHeightDelta) -> Result<GCEstimate, Error> {
+        let head = self.store.head()?;
+        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+        self.store.estimate_gc(
+            self.epoch_manager.as_ref(),
+            self.runtime_adapter.get_tries(),
+            gc_stop_height,
+            height_limit,
+        )
+    }
+
+    /// Prunes data which is configured to be kept for fewer blocks than the
+    /// garbage collection keeps it, see `RetentionConfig`.
+    pub fn prune_data(&mut self, gc_config: &near_chain_configs::GCConfig) -> Result<(), Error> {
+        if !gc_config.retention.is_enabled() {
+            return Ok(());
+        }
+        let _span = tracing::debug_span!(target: "chain", "prune_data").entered();
+
+        let mut chain_store_update = self.store.store_update();
+        let prune_tails =
+            chain_store_update.prune_data(&gc_config.retention, gc_config.gc_blocks_limit)?;
+        chain_store_update.commit()?;
+        prune_tails.update_metrics();
+        Ok(())
+    }
+
+    /// Prunes transactions, receipts and execution outcomes which have been
+    /// copied to cold storage from the hot store of an archival node, see
+    /// `ChainStoreUpdate::prune_cold_data`.
+    pub fn prune_cold_data(&mut self, height_limit: BlockHeightDelta) -> Result<(), Error> {
+        let _span = tracing::debug_span!(target: "chain", "prune_cold_data").entered();
+
+        let mut chain_store_update = self.store.store_update();
+        let prune_tails = chain_store_update.prune_cold_data(height_limit)?;
+        chain_store_update.commit()?;
+        prune_tails.update_metrics();
+        Ok(())
+    }
+
+    fn clear_forks_data(
         &mut self,
         tries: ShardTries,
         height: BlockHeight,
-        gc_blocks_remaining: &mut NumBlocks,
+        budget: &mut GCBudget,
     ) -> Result<(), Error> {
         let blocks_current_height = self
             .store
@@ -1128,7 +1320,7 @@ impl Chain {
         for block_hash in blocks_current_height.iter() {
             let mut current_hash = *block_hash;
             loop {
-                if *gc_blocks_remaining == 0 {
+                if budget.is_exhausted() {
                     return Ok(());
                 }
                 // Block `block_hash` is not on the Canonical Chain
@@ -1147,7 +1339,7 @@ impl Chain {
                         GCMode::Fork(tries.clone()),
                     )?;
                     chain_store_update.commit()?;
-                    *gc_blocks_remaining -= 1;
+                    budget.spend_block("fork");
 
                     current_hash = prev_hash;
                 } else {
@@ -1160,7 +1352,7 @@ impl Chain {
         Ok(())
     }
 
-    fn maybe_mark_block_invalid(&mut self, block_hash: CryptoHash, error: &Error) {
+    fn maybe_mark_block_invalid(&mut self, block: &Block, error: &Error) {
         metrics::NUM_INVALID_BLOCKS.inc();
         // We only mark the block as invalid if the block has bad data (not for other errors that would
         // not be the fault of the block itself), except when the block has a bad signature which means
@@ -1168,7 +1360,14 @@ impl Chain {
         // OK if we miss some cases here because this is just an optimization to avoid reprocessing
         // known invalid blocks so the network recovers faster in case of any issues.
         if error.is_bad_data() && !matches!(error, Error::InvalidSignature) {
+            let block_hash = *block.hash();
             self.invalid_blocks.put(block_hash, ());
+            // Keep the block around so that the reason it was rejected can be
+            // investigated later.
+            let peer_id = self.blocks_delay_tracker.get_block_sender(&block_hash).cloned();
+            if let Err(err) = self.store.quarantine_block(block, error, peer_id) {
+                warn!(target: "chain", ?block_hash, ?err, "Failed to quarantine invalid block");
+            }
         }
     }
 
@@ -1520,12 +1719,24 @@ impl Chain {
     pub fn verify_block_hash_and_signature(
         &self,
         block: &Block,
+    ) -> Result<VerifyBlockHashAndSignatureResult, Error> {
+        Self::verify_block_hash_and_signature_impl(
+            self.epoch_manager.as_ref(),
+            &self.genesis,
+            block,
+        )
+    }
+
+    fn verify_block_hash_and_signature_impl(
+        epoch_manager: &dyn EpochManagerAdapter,
+        genesis_block: &Block,
+        block: &Block,
     ) -> Result<VerifyBlockHashAndSignatureResult, Error> {
         // skip the verification if we are processing the genesis block
-        if block.hash() == self.genesis.hash() {
+        if block.hash() == genesis_block.hash() {
             return Ok(VerifyBlockHashAndSignatureResult::Correct);
         }
-        let epoch_id = match self.epoch_manager.get_epoch_id(block.header().prev_hash()) {
+        let epoch_id = match epoch_manager.get_epoch_id(block.header().prev_hash()) {
             Ok(epoch_id) => epoch_id,
             Err(EpochError::MissingBlock(missing_block))
                 if &missing_block == block.header().prev_hash() =>
@@ -1534,7 +1745,7 @@ impl Chain {
             }
             Err(err) => return Err(err.into()),
         };
-        let epoch_protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+        let epoch_protocol_version = epoch_manager.get_epoch_protocol_version(&epoch_id)?;
         // Check that block body hash matches the block body. This makes sure that the block body
         // content is not tampered
         if checked_feature!("stable", BlockHeaderV4, epoch_protocol_version) {
@@ -1551,7 +1762,7 @@ impl Chain {
 
         // Verify the signature. Since the signature is signed on the hash of block header, this check
         // makes sure the block header content is not tampered
-        if !self.epoch_manager.verify_header_signature(block.header())? {
+        if !epoch_manager.verify_header_signature(block.header())? {
             tracing::error!("wrong signature");
             return Ok(VerifyBlockHashAndSignatureResult::Incorrect);
         }
@@ -1842,6 +2053,43 @@ impl Chain {
         provenance: Provenance,
         block_processing_artifacts: &mut BlockProcessingArtifact,
         apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) -> Result<(), Error> {
+        self.start_process_block_async_impl(
+            me,
+            block,
+            provenance,
+            false,
+            block_processing_artifacts,
+            apply_chunks_done_callback,
+        )
+    }
+
+    /// Starts processing a block released from the orphan or missing chunks pools.
+    fn start_process_released_block(
+        &mut self,
+        me: &Option<AccountId>,
+        orphan: Orphan,
+        block_processing_artifacts: &mut BlockProcessingArtifact,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) -> Result<(), Error> {
+        self.start_process_block_async_impl(
+            me,
+            orphan.block,
+            orphan.provenance,
+            orphan.hash_and_signature_verified,
+            block_processing_artifacts,
+            apply_chunks_done_callback,
+        )
+    }
+
+    fn start_process_block_async_impl(
+        &mut self,
+        me: &Option<AccountId>,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        hash_and_signature_verified: bool,
+        block_processing_artifacts: &mut BlockProcessingArtifact,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
     ) -> Result<(), Error> {
         let block_received_time = StaticClock::instant();
         metrics::BLOCK_PROCESSING_ATTEMPTS_TOTAL.inc();
@@ -1852,6 +2100,7 @@ impl Chain {
             me,
             block,
             provenance,
+            hash_and_signature_verified,
             block_processing_artifacts,
             apply_chunks_done_callback,
             block_received_time,
@@ -2135,6 +2384,7 @@ impl Chain {
         me: &Option<AccountId>,
         block: MaybeValidated<Block>,
         provenance: Provenance,
+        hash_and_signature_verified: bool,
         block_processing_artifact: &mut BlockProcessingArtifact,
         apply_chunks_done_callback: DoneApplyChunkCallback,
         block_received_time: Instant,
@@ -2143,7 +2393,8 @@ impl Chain {
         let _span = tracing::debug_span!(
             target: "chain",
             "start_process_block_impl",
-            height = block_height)
+            height = block_height,
+            block_hash = %block.hash())
         .entered();
         // 0) Before we proceed with any further processing, we first check that the block
         // hash and signature matches to make sure the block is indeed produced by the assigned
@@ -2154,16 +2405,21 @@ impl Chain {
         // the orphan pool. When the orphaned block is ready to be processed, we must perform this check.
         // Also note that we purposely separates the check from the rest of the block verification check in
         // preprocess_block.
-        if self.verify_block_hash_and_signature(&block)?
-            == VerifyBlockHashAndSignatureResult::Incorrect
-        {
-            return Err(Error::InvalidSignature);
-        }
+        // Blocks prevalidated in parallel with other blocks have been checked already.
+        let hash_and_signature_verified = hash_and_signature_verified
+            || match self.verify_block_hash_and_signature(&block)? {
+                VerifyBlockHashAndSignatureResult::Correct => true,
+                VerifyBlockHashAndSignatureResult::CannotVerifyBecauseBlockIsOrphan => false,
+                VerifyBlockHashAndSignatureResult::Incorrect => {
+                    return Err(Error::InvalidSignature)
+                }
+            };
 
         // 1) preprocess the block where we verify that the block is valid and ready to be processed
         //    No chain updates are applied at this step.
         let state_patch = self.pending_state_patch.take();
         let preprocess_timer = metrics::BLOCK_PREPROCESSING_TIME.start_timer();
+        let preprocess_start = StaticClock::instant();
         let preprocess_res = self.preprocess_block(
             me,
             &block,
@@ -2176,10 +2432,21 @@ impl Chain {
         let preprocess_res = match preprocess_res {
             Ok(preprocess_res) => {
                 preprocess_timer.observe_duration();
+                self.block_timelines.mark_block_received(
+                    block.hash(),
+                    block_height,
+                    block_received_time,
+                );
+                self.block_timelines.record(
+                    block.hash(),
+                    BlockTimelineStage::Preprocessing,
+                    preprocess_start,
+                    StaticClock::instant(),
+                );
                 preprocess_res
             }
             Err(e) => {
-                self.maybe_mark_block_invalid(*block.hash(), &e);
+                self.maybe_mark_block_invalid(&block, &e);
                 preprocess_timer.stop_and_discard();
                 match &e {
                     Error::Orphan => {
@@ -2200,7 +2467,12 @@ impl Chain {
 
                             let time = StaticClock::instant();
                             self.blocks_delay_tracker.mark_block_orphaned(block.hash(), time);
-                            let orphan = Orphan { block, provenance, added: time };
+                            let orphan = Orphan {
+                                block,
+                                provenance,
+                                added: time,
+                                hash_and_signature_verified,
+                            };
                             self.orphans.add(orphan, requested_missing_chunks);
 
                             debug!(
@@ -2226,7 +2498,8 @@ impl Chain {
                         });
                         let time = StaticClock::instant();
                         self.blocks_delay_tracker.mark_block_has_missing_chunks(block.hash(), time);
-                        let orphan = Orphan { block, provenance, added: time };
+                        let orphan =
+                            Orphan { block, provenance, added: time, hash_and_signature_verified };
                         self.blocks_with_missing_chunks
                             .add_block_with_missing_chunks(orphan, missing_chunk_hashes.clone());
                         debug!(
@@ -2322,7 +2595,9 @@ impl Chain {
         let mut chain_update = self.chain_update();
         let new_head =
             chain_update.postprocess_block(me, &block, block_preprocess_info, apply_results)?;
+        let events = std::mem::take(&mut chain_update.events);
         chain_update.commit()?;
+        self.chain_event_bus.publish(events);
         Ok(new_head)
     }
 
@@ -2338,6 +2613,7 @@ impl Chain {
         apply_chunks_done_callback: DoneApplyChunkCallback,
     ) -> Result<AcceptedBlock, Error> {
         let timer = metrics::BLOCK_POSTPROCESSING_TIME.start_timer();
+        let postprocess_start = StaticClock::instant();
         let (block, block_preprocess_info) =
             self.blocks_in_processing.remove(&block_hash).expect(&format!(
                 "block {:?} finished applying chunks but not in blocks_in_processing pool",
@@ -2348,7 +2624,8 @@ impl Chain {
         let _span = tracing::debug_span!(
             target: "chain",
             "postprocess_block",
-            height = block.header().height())
+            height = block.header().height(),
+            %block_hash)
         .entered();
 
         let prev_head = self.store.head()?;
@@ -2366,12 +2643,15 @@ impl Chain {
         let new_head =
             match self.postprocess_block_only(me, &block, block_preprocess_info, apply_results) {
                 Err(err) => {
-                    self.maybe_mark_block_invalid(*block.hash(), &err);
+                    self.maybe_mark_block_invalid(&block, &err);
                     self.blocks_delay_tracker.mark_block_errored(&block_hash, err.to_string());
                     return Err(err);
                 }
                 Ok(new_head) => new_head,
             };
+        if new_head.is_some() {
+            self.block_timelines.record_now(&block_hash, BlockTimelineStage::HeadUpdate);
+        }
 
         // Update flat storage head to be the last final block. Note that this update happens
         // in a separate db transaction from the update from block processing. This is intentional
@@ -2441,6 +2721,12 @@ impl Chain {
         self.blocks_delay_tracker.finish_block_processing(&block_hash, new_head.clone());
 
         timer.observe_duration();
+        self.block_timelines.record(
+            &block_hash,
+            BlockTimelineStage::Postprocessing,
+            postprocess_start,
+            StaticClock::instant(),
+        );
         let _timer = CryptoHashTimer::new_with_start(*block.hash(), block_start_processing_time);
 
         self.check_orphans(
@@ -2799,6 +3085,54 @@ impl Chain {
         will_care_about_shard && (will_shard_layout_change || !does_care_about_shard)
     }
 
+    /// Runs the checks of blocks which don't depend on the state of the chain
+    /// concurrently for all given blocks, i.e. verifies the block hashes and
+    /// signatures and the chunk header signatures.  Blocks released together
+    /// from the orphan or missing chunks pools are usually on different forks
+    /// (or are many blocks on one fork after a network partition) and these
+    /// checks are the bulk of the work of preprocessing them.
+    ///
+    /// The rest of preprocessing, which reads and depends on the chain state,
+    /// still runs sequentially when each block's processing starts and skips
+    /// the checks which passed here.  That's also where conflicts between the
+    /// blocks are detected, e.g. when the same block was released twice or
+    /// when processing one of the blocks made another one's fork too old.
+    /// Failed checks are simply repeated then so that errors are reported and
+    /// handled the same way as for blocks which weren't prevalidated.
+    fn prevalidate_blocks(&self, blocks: Vec<Orphan>) -> Vec<Orphan> {
+        if blocks.len() < 2 {
+            return blocks;
+        }
+        let _span =
+            tracing::debug_span!(target: "chain", "prevalidate_blocks", num_blocks = blocks.len())
+                .entered();
+        let epoch_manager = self.epoch_manager.as_ref();
+        let genesis = &self.genesis;
+        blocks
+            .into_par_iter()
+            .map(|mut orphan| {
+                if orphan.hash_and_signature_verified {
+                    return orphan;
+                }
+                orphan.hash_and_signature_verified = matches!(
+                    Self::verify_block_hash_and_signature_impl(
+                        epoch_manager,
+                        genesis,
+                        &orphan.block
+                    ),
+                    Ok(VerifyBlockHashAndSignatureResult::Correct)
+                );
+                if orphan.hash_and_signature_verified {
+                    // Marks the block as validated on success.
+                    let _ = orphan.block.validate_with(|block| {
+                        Chain::validate_block_impl(epoch_manager, genesis, block).map(|_| true)
+                    });
+                }
+                orphan
+            })
+            .collect()
+    }
+
     /// Check if any block with missing chunk is ready to be processed and start processing these blocks
     pub fn check_blocks_with_missing_chunks(
         &mut self,
@@ -2810,14 +3144,14 @@ impl Chain {
         if !blocks.is_empty() {
             debug!(target:"chain", "Got {} blocks that were missing chunks but now are ready.", blocks.len());
         }
+        let blocks = self.prevalidate_blocks(blocks);
         for block in blocks {
             let block_hash = *block.block.header().hash();
             let height = block.block.header().height();
             let time = StaticClock::instant();
-            let res = self.start_process_block_async(
+            let res = self.start_process_released_block(
                 me,
-                block.block,
-                block.provenance,
+                block,
                 block_processing_artifact,
                 apply_chunks_done_callback.clone(),
             );
@@ -2865,14 +3199,14 @@ impl Chain {
         }
         if let Some(orphans) = self.orphans.remove_by_prev_hash(prev_hash) {
             debug!(target: "chain", found_orphans = orphans.len(), "Check orphans");
+            let orphans = self.prevalidate_blocks(orphans);
             for orphan in orphans.into_iter() {
                 let block_hash = orphan.hash();
                 self.blocks_delay_tracker
                     .mark_block_unorphaned(&block_hash, StaticClock::instant());
-                let res = self.start_process_block_async(
+                let res = self.start_process_released_block(
                     me,
-                    orphan.block,
-                    orphan.provenance,
+                    orphan,
                     block_processing_artifacts,
                     apply_chunks_done_callback.clone(),
                 );
@@ -2909,9 +3243,10 @@ impl Chain {
         sync_hash: CryptoHash,
     ) -> Result<ShardStateSyncResponseHeader, Error> {
         // Consistency rules:
-        // 1. Everything prefixed with `sync_` indicates new epoch, for which we are syncing.
-        // 1a. `sync_prev` means the last of the prev epoch.
-        // 2. Empty prefix means the height where chunk was applied last time in the prev epoch.
+        // 1. Everything prefixed with `sync_` indicates the block we are syncing to, which is
+        //    either the first block of the new epoch or a final block of the current one.
+        // 1a. `sync_prev` means the block before it, e.g. the last of the prev epoch.
+        // 2. Empty prefix means the height where chunk was applied last time before `sync_prev`.
         //    Let's call it `current`.
         // 2a. `prev_` means we're working with height before current.
         // 3. In inner loops we use all prefixes with no relation to the context described above.
@@ -2919,19 +3254,14 @@ impl Chain {
             .get_block(&sync_hash)
             .log_storage_error("block has already been checked for existence")?;
         let sync_block_header = sync_block.header().clone();
-        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
         if shard_id as usize >= sync_block.chunks().len() {
             return Err(Error::InvalidStateRequest("shard_id out of bounds".into()));
         }
 
         // The chunk was applied at height `chunk_header.height_included`.
         // Getting the `current` state.
+        self.check_sync_block(&sync_block_header)?;
         let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
-        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
-            return Err(Error::InvalidStateRequest(
-                "sync_hash is not the first hash of the epoch".into(),
-            ));
-        }
         if shard_id as usize >= sync_prev_block.chunks().len() {
             return Err(Error::InvalidStateRequest("shard_id out of bounds".into()));
         }
@@ -3127,16 +3457,11 @@ impl Chain {
             .get_block(&sync_hash)
             .log_storage_error("block has already been checked for existence")?;
         let sync_block_header = sync_block.header().clone();
-        let sync_block_epoch_id = sync_block.header().epoch_id().clone();
         if shard_id as usize >= sync_block.chunks().len() {
             return Err(Error::InvalidStateRequest("shard_id out of bounds".into()));
         }
+        let is_epoch_start = self.check_sync_block(&sync_block_header)?;
         let sync_prev_block = self.get_block(sync_block_header.prev_hash())?;
-        if &sync_block_epoch_id == sync_prev_block.header().epoch_id() {
-            return Err(Error::InvalidStateRequest(
-                "sync_hash is not the first hash of the epoch".into(),
-            ));
-        }
         if shard_id as usize >= sync_prev_block.chunks().len() {
             return Err(Error::InvalidStateRequest("shard_id out of bounds".into()));
         }
@@ -3152,20 +3477,36 @@ impl Chain {
         if part_id >= num_parts {
             return Err(Error::InvalidStateRequest("part_id out of bound".to_string()));
         }
+        let method = if !is_epoch_start {
+            "trie"
+        } else if self.runtime_adapter.get_tries().state_part_memory_limit().is_some() {
+            "flat_storage_streaming"
+        } else {
+            "flat_storage"
+        };
         let current_time = Instant::now();
-        let state_part = self
-            .runtime_adapter
-            .obtain_state_part(
-                shard_id,
-                &sync_prev_prev_hash,
-                &state_root,
-                PartId::new(part_id, num_parts),
-            )
-            .log_storage_error("obtain_state_part fail")?;
+        let state_part = if is_epoch_start {
+            self.runtime_adapter
+                .obtain_state_part(
+                    shard_id,
+                    &sync_prev_prev_hash,
+                    &state_root,
+                    PartId::new(part_id, num_parts),
+                )
+                .log_storage_error("obtain_state_part fail")?
+        } else {
+            // State snapshots are only taken at epoch boundaries, so parts of
+            // a state in the middle of an epoch are read from the trie, whose
+            // nodes are kept until the block is garbage collected.
+            self.runtime_adapter
+                .get_trie_for_shard(shard_id, &sync_prev_prev_hash, state_root, false)?
+                .get_trie_nodes_for_part_without_flat_storage(PartId::new(part_id, num_parts))?
+                .try_to_vec()?
+        };
 
         let elapsed_ms = current_time.elapsed().as_millis();
         self.requested_state_parts
-            .save_state_part_elapsed(&sync_hash, &shard_id, &part_id, elapsed_ms);
+            .save_state_part_elapsed(&sync_hash, &shard_id, &part_id, method, elapsed_ms);
 
         // Before saving State Part data, we need to make sure we can calculate and save State Header
         self.get_state_response_header(shard_id, sync_hash)?;
@@ -3378,22 +3719,53 @@ impl Chain {
         num_parts: u64,
         state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
     ) -> Result<(), Error> {
+        let request = self.new_apply_state_parts_request(shard_id, sync_hash, num_parts, None)?;
+        state_parts_task_scheduler(request);
+        Ok(())
+    }
+
+    /// Schedules applying a single state part, while the other parts of the
+    /// shard may still be downloading.
+    pub fn schedule_apply_state_part(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: PartId,
+        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
+    ) -> Result<(), Error> {
+        let request = self.new_apply_state_parts_request(
+            shard_id,
+            sync_hash,
+            part_id.total,
+            Some(part_id.idx),
+        )?;
+        state_parts_task_scheduler(request);
+        Ok(())
+    }
+
+    fn new_apply_state_parts_request(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+        part_id: Option<u64>,
+    ) -> Result<ApplyStatePartsRequest, Error> {
         let epoch_id = self.get_block_header(&sync_hash)?.epoch_id().clone();
         let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, &epoch_id)?;
 
         let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
         let state_root = shard_state_header.chunk_prev_state_root();
 
-        state_parts_task_scheduler(ApplyStatePartsRequest {
+        Ok(ApplyStatePartsRequest {
             runtime_adapter: self.runtime_adapter.clone(),
             shard_uid,
             state_root,
             num_parts,
+            part_id,
             epoch_id,
             sync_hash,
-        });
-
-        Ok(())
+            priority: false,
+        })
     }
 
     pub fn set_state_finalize(
@@ -3551,6 +3923,7 @@ impl Chain {
                 block_hash: pending_block,
                 block_height: block.header().height(),
                 work,
+                priority: false,
             });
         }
 
@@ -3729,6 +4102,49 @@ impl Chain {
         Ok(FinalExecutionOutcomeWithReceiptView { final_outcome, receipts })
     }
 
+    fn get_receipt_execution_tree(
+        &self,
+        receipt_id: &CryptoHash,
+    ) -> Result<ReceiptExecutionTreeView, Error> {
+        let receipt = self.store.get_receipt(receipt_id)?.map(|r| Receipt::clone(&r).into());
+        let outcome = match self.get_execution_outcome(receipt_id) {
+            Ok(outcome) => Some(ExecutionOutcomeWithIdView::from(outcome)),
+            Err(Error::DBNotFoundErr(_)) => None,
+            Err(err) => return Err(err),
+        };
+        let children = match &outcome {
+            Some(outcome) => outcome
+                .outcome
+                .receipt_ids
+                .iter()
+                .map(|id| self.get_receipt_execution_tree(id))
+                .collect::<Result<_, _>>()?,
+            None => vec![],
+        };
+        Ok(ReceiptExecutionTreeView { receipt_id: *receipt_id, receipt, outcome, children })
+    }
+
+    /// Returns the transaction with all the receipts it caused, with their outcomes.
+    /// The receipts which haven't been executed yet have no outcome.
+    pub fn get_transaction_execution_tree(
+        &self,
+        transaction_hash: &CryptoHash,
+    ) -> Result<TransactionExecutionTreeView, Error> {
+        let transaction = self.store.get_transaction(transaction_hash)?.ok_or_else(|| {
+            Error::DBNotFoundErr(format!("Transaction {} is not found", transaction_hash))
+        })?;
+        let transaction: SignedTransactionView = SignedTransaction::clone(&transaction).into();
+        let transaction_outcome =
+            ExecutionOutcomeWithIdView::from(self.get_execution_outcome(transaction_hash)?);
+        let receipts = transaction_outcome
+            .outcome
+            .receipt_ids
+            .iter()
+            .map(|id| self.get_receipt_execution_tree(id))
+            .collect::<Result<_, _>>()?;
+        Ok(TransactionExecutionTreeView { transaction, transaction_outcome, receipts })
+    }
+
     /// Find a validator to forward transactions to
     pub fn find_chunk_producer_for_forwarding(
         &self,
@@ -3832,6 +4248,42 @@ impl Chain {
         })
     }
 
+    /// Writes the replay artifact of the last new chunk of given shard on the
+    /// chain ending with `prev_hash` if the artifacts directory is configured.
+    /// Called when the outcome of applying it doesn't match the one
+    /// `chunk_header` builds on.
+    fn dump_chunk_replay_artifact(
+        &self,
+        prev_hash: &CryptoHash,
+        shard_id: ShardId,
+        prev_chunk_height_included: BlockHeight,
+        chunk_header: &ShardChunkHeader,
+    ) {
+        let Some(dir) = &self.chunk_replay_artifacts_dir else { return };
+        let result = self
+            .store()
+            .get_block_hash_on_canonical_chain(prev_hash, prev_chunk_height_included)
+            .and_then(|block_hash| {
+                ChunkReplayArtifact::new(
+                    self.store(),
+                    self.epoch_manager.as_ref(),
+                    self.runtime_adapter.as_ref(),
+                    &block_hash,
+                    shard_id,
+                    chunk_header.prev_state_root(),
+                )
+            })
+            .and_then(|artifact| artifact.save(dir).map_err(Error::from));
+        match result {
+            Ok(path) => {
+                warn!(target: "chain", shard_id, path = %path.display(), "Saved chunk replay artifact")
+            }
+            Err(err) => {
+                warn!(target: "chain", shard_id, ?err, "Failed to save chunk replay artifact")
+            }
+        }
+    }
+
     fn get_split_state_roots(
         &self,
         block: &Block,
@@ -3894,7 +4346,9 @@ impl Chain {
                 );
 
                 match apply_chunk_job {
-                    Ok(Some(processor)) => Some(Ok(processor)),
+                    Ok(Some(processor)) => {
+                        Some(Ok(self.timed_apply_chunk_job(*block.hash(), shard_id, processor)))
+                    }
                     Ok(None) => None,
                     Err(err) => {
                         if err.is_bad_data() {
@@ -3907,6 +4361,28 @@ impl Chain {
             .collect()
     }
 
+    /// Wraps the job so that it records how long applying the chunk took in the
+    /// timeline of the block.
+    fn timed_apply_chunk_job(
+        &self,
+        block_hash: CryptoHash,
+        shard_id: usize,
+        job: ApplyChunkJob,
+    ) -> ApplyChunkJob {
+        let block_timelines = self.block_timelines.clone();
+        Box::new(move |parent_span| {
+            let start = StaticClock::instant();
+            let result = job(parent_span);
+            block_timelines.record(
+                &block_hash,
+                BlockTimelineStage::ApplyChunk(shard_id as ShardId),
+                start,
+                StaticClock::instant(),
+            );
+            result
+        })
+    }
+
     /// This method returns the closure that is responsible for applying of a single chunk.
     fn get_apply_chunk_job(
         &self,
@@ -4045,6 +4521,20 @@ impl Chain {
                 ?prev_chunk_extra,
                 ?chunk_header,
                 "Failed to validate chunk extra");
+            if matches!(
+                err,
+                Error::InvalidStateRoot
+                    | Error::InvalidOutcomesProof
+                    | Error::InvalidGasUsed
+                    | Error::InvalidBalanceBurnt
+            ) {
+                self.dump_chunk_replay_artifact(
+                    prev_hash,
+                    shard_id,
+                    prev_chunk_height_included,
+                    chunk_header,
+                );
+            }
             byzantine_assert!(false);
             match self.create_chunk_state_challenge(prev_block, block, chunk_header) {
                 Ok(chunk_state) => Error::InvalidChunkState(Box::new(chunk_state)),
@@ -4262,6 +4752,7 @@ impl Chain {
         let state_changes =
             self.store().get_state_changes_for_split_states(block.hash(), shard_id)?;
         let block_hash = *block.hash();
+        let block_height = block.header().height();
         Ok(Some(Box::new(move |parent_span| -> Result<ApplyChunkResult, Error> {
             let _span = tracing::debug_span!(
                 target: "chain",
@@ -4272,6 +4763,7 @@ impl Chain {
             .entered();
             let results = runtime.apply_update_to_split_states(
                 &block_hash,
+                block_height,
                 split_state_roots,
                 &next_epoch_shard_layout,
                 state_changes,
@@ -4598,6 +5090,20 @@ impl Chain {
         self.store.get_block_hash_by_height(height)
     }
 
+    /// Whether the block can never become part of the canonical chain because
+    /// a different block at its height is already final.
+    pub fn is_on_abandoned_fork(&self, block_hash: &CryptoHash) -> Result<bool, Error> {
+        let height = self.get_block_header(block_hash)?.height();
+        if height > self.final_head()?.height {
+            return Ok(false);
+        }
+        match self.get_block_hash_by_height(height) {
+            Ok(canonical_hash) => Ok(&canonical_hash != block_hash),
+            Err(Error::DBNotFoundErr(_)) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
     /// Gets a block header by hash.
     #[inline]
     pub fn get_block_header(&self, hash: &CryptoHash) -> Result<BlockHeader, Error> {
@@ -4620,6 +5126,17 @@ impl Chain {
         self.store.get_block_header_on_chain_by_height(sync_hash, height)
     }
 
+    /// Returns hash of the block at given height on the chain ending with
+    /// `from_hash`.
+    #[inline]
+    pub fn get_block_hash_on_canonical_chain(
+        &self,
+        from_hash: &CryptoHash,
+        target_height: BlockHeight,
+    ) -> Result<CryptoHash, Error> {
+        self.store.get_block_hash_on_canonical_chain(from_hash, target_height)
+    }
+
     /// Get previous block header.
     #[inline]
     pub fn get_previous_header(&self, header: &BlockHeader) -> Result<BlockHeader, Error> {
@@ -4733,6 +5250,12 @@ impl Chain {
         self.genesis.header()
     }
 
+    /// Returns the bus events of processed blocks are published on.
+    #[inline]
+    pub fn chain_event_bus(&self) -> &ChainEventBus {
+        &self.chain_event_bus
+    }
+
     /// Returns number of orphans currently in the orphan pool.
     #[inline]
     pub fn orphans_len(&self) -> usize {
@@ -4756,6 +5279,35 @@ impl Chain {
         self.orphans.len_evicted()
     }
 
+    /// Updates metrics breaking down the orphan and missing chunks pools by
+    /// distance of the blocks from the head and by the peers which sent them.
+    /// Unlike `near_num_orphans` this lets telling a flood of blocks from
+    /// a single peer apart from normal fork churn.
+    pub fn update_block_pools_metrics(&self) {
+        let head_height = match self.head() {
+            Ok(head) => head.height,
+            Err(_) => return,
+        };
+        let blocks =
+            self.orphans.orphans.values().map(|block| ("orphan", block)).chain(
+                self.blocks_with_missing_chunks.blocks().map(|block| ("missing_chunks", block)),
+            );
+        metrics::BLOCK_POOL_BLOCKS_BY_DISTANCE.reset();
+        metrics::BLOCK_POOL_BLOCKS_BY_PEER.reset();
+        for (pool, block) in blocks {
+            let distance = block.height() as i64 - head_height as i64;
+            metrics::BLOCK_POOL_BLOCKS_BY_DISTANCE
+                .with_label_values(&[pool, metrics::block_pool_distance_label(distance)])
+                .inc();
+            // Blocks received long ago may no longer be in the delay tracker.
+            let peer_id = self
+                .blocks_delay_tracker
+                .get_block_sender(&block.hash())
+                .map_or_else(|| "unknown".to_string(), |peer_id| peer_id.to_string());
+            metrics::BLOCK_POOL_BLOCKS_BY_PEER.with_label_values(&[pool, &peer_id]).inc();
+        }
+    }
+
     /// Check if hash is for a known orphan.
     #[inline]
     pub fn is_orphan(&self, hash: &CryptoHash) -> bool {
@@ -4794,14 +5346,45 @@ impl Chain {
         if head.epoch_id == *sync_block.header().epoch_id()
             || head.epoch_id == *sync_block.header().next_epoch_id()
         {
-            let prev_hash = *sync_block.header().prev_hash();
-            // If sync_hash is not on the Epoch boundary, it's malicious behavior
-            Ok(self.epoch_manager.is_next_block_epoch_start(&prev_hash)?)
+            // If sync_hash is neither on the Epoch boundary nor a final block
+            // of the current epoch, it's malicious behavior
+            match self.check_sync_block(sync_block.header()) {
+                Ok(_) => Ok(true),
+                Err(Error::InvalidStateRequest(_)) => Ok(false),
+                Err(err) => Err(err),
+            }
         } else {
             Ok(false) // invalid Epoch of sync_hash, possible malicious behavior
         }
     }
 
+    /// Checks that the state can be synced to the given block, which is either
+    /// the first block of an epoch or a final block in the middle of the
+    /// current epoch, and returns whether it's the first block of an epoch.
+    /// Syncing to the middle of an epoch which ends with a change of the shard
+    /// layout isn't supported, since the state would have to be split later.
+    fn check_sync_block(&self, sync_block_header: &BlockHeader) -> Result<bool, Error> {
+        let prev_hash = sync_block_header.prev_hash();
+        if self.epoch_manager.is_next_block_epoch_start(prev_hash)? {
+            return Ok(true);
+        }
+        if self.epoch_manager.will_shard_layout_change(prev_hash)? {
+            return Err(Error::InvalidStateRequest(
+                "sync_hash is in the epoch before a shard layout change".into(),
+            ));
+        }
+        let head = self.head()?;
+        if sync_block_header.epoch_id() != &head.epoch_id
+            || sync_block_header.height() > self.final_head()?.height
+            || !self.is_on_current_chain(sync_block_header).unwrap_or(false)
+        {
+            return Err(Error::InvalidStateRequest(
+                "sync_hash is neither the first hash of the epoch nor a final block".into(),
+            ));
+        }
+        Ok(false)
+    }
+
     /// Get transaction result for given hash of transaction or receipt id on the canonical chain
     pub fn get_execution_outcome(
         &self,
@@ -4952,6 +5535,8 @@ pub struct ChainUpdate<'a> {
     doomslug_threshold_mode: DoomslugThresholdMode,
     #[allow(unused)]
     transaction_validity_period: BlockHeightDelta,
+    /// Events to publish on the chain event bus once the update is committed.
+    events: Vec<ChainEvent>,
 }
 
 #[derive(Debug)]
@@ -5018,6 +5603,7 @@ impl<'a> ChainUpdate<'a> {
             chain_store_update,
             doomslug_threshold_mode,
             transaction_validity_period,
+            events: vec![],
         }
     }
 
@@ -5120,6 +5706,7 @@ impl<'a> ChainUpdate<'a> {
         if let Some(state_roots) = split_state_roots {
             let split_state_results = runtime_adapter.apply_update_to_split_states(
                 block_hash,
+                apply_result.trie_changes.block_height(),
                 state_roots,
                 &next_epoch_shard_layout,
                 state_changes,
@@ -5290,6 +5877,13 @@ impl<'a> ChainUpdate<'a> {
                 let (outcome_root, outcome_paths) =
                     ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
                 let shard_id = shard_uid.shard_id();
+                self.events.push(ChainEvent::ChunkApplied {
+                    block_hash: *block_hash,
+                    height,
+                    shard_uid,
+                    is_new_chunk: true,
+                    state_root: apply_result.new_root,
+                });
 
                 // Save state root after applying transactions.
                 self.chain_store_update.save_chunk_extra(
@@ -5341,6 +5935,13 @@ impl<'a> ChainUpdate<'a> {
 
                 let mut new_extra = ChunkExtra::clone(&old_extra);
                 *new_extra.state_root_mut() = apply_result.new_root;
+                self.events.push(ChainEvent::ChunkApplied {
+                    block_hash: *block_hash,
+                    height,
+                    shard_uid,
+                    is_new_chunk: false,
+                    state_root: apply_result.new_root,
+                });
 
                 let flat_storage_manager = self.runtime_adapter.get_flat_storage_manager();
                 let store_update = flat_storage_manager.save_flat_state_changes(
@@ -5533,6 +6134,7 @@ impl<'a> ChainUpdate<'a> {
         let header_head = self.chain_store_update.header_head()?;
         if header.height() > header_head.height {
             let tip = Tip::from_header(header);
+            self.report_reorg("header", &header_head, &tip)?;
             self.chain_store_update.save_header_head_if_not_challenged(&tip)?;
             debug!(target: "chain", "Header head updated to {} at {}", tip.last_block_hash, tip.height);
             metrics::HEADER_HEAD_HEIGHT.set(tip.height as i64);
@@ -5554,6 +6156,7 @@ impl<'a> ChainUpdate<'a> {
         if last_final_block_header.height() > final_head.height {
             let tip = Tip::from_header(&last_final_block_header);
             self.chain_store_update.save_final_head(&tip)?;
+            self.events.push(ChainEvent::NewFinalHead(tip.clone()));
             Ok(Some(tip))
         } else {
             Ok(None)
@@ -5569,6 +6172,15 @@ impl<'a> ChainUpdate<'a> {
         let head = self.chain_store_update.head()?;
         if header.height() > head.height {
             let tip = Tip::from_header(header);
+            if let Some((depth, rolled_back_blocks)) = self.report_reorg("block", &head, &tip)? {
+                self.events.push(ChainEvent::Reorg {
+                    old_head: head,
+                    new_head: tip.clone(),
+                    depth,
+                    rolled_back_blocks,
+                });
+            }
+            self.events.push(ChainEvent::NewHead(tip.clone()));
 
             self.chain_store_update.save_body_head(&tip)?;
             metrics::BLOCK_HEIGHT_HEAD.set(tip.height as i64);
@@ -5580,6 +6192,53 @@ impl<'a> ChainUpdate<'a> {
         }
     }
 
+    /// Updates the reorg metrics of given head if the new tip doesn't extend
+    /// the old one.  The depth of a reorg is the difference between the
+    /// heights of the old tip and of the last block it shares with the new
+    /// tip.  Returns the depth and the number of rolled back blocks if there
+    /// was a reorg.
+    fn report_reorg(
+        &mut self,
+        head_label: &str,
+        old_tip: &Tip,
+        new_tip: &Tip,
+    ) -> Result<Option<(BlockHeightDelta, u64)>, Error> {
+        if new_tip.epoch_id != old_tip.epoch_id {
+            metrics::CHAIN_REORGS_IN_EPOCH.with_label_values(&[head_label]).set(0);
+        }
+        if new_tip.prev_block_hash == old_tip.last_block_hash {
+            return Ok(None);
+        }
+        let mut old_header = self.chain_store_update.get_block_header(&old_tip.last_block_hash)?;
+        let mut new_header = self.chain_store_update.get_block_header(&new_tip.prev_block_hash)?;
+        let mut rolled_back_blocks = 0;
+        while old_header.hash() != new_header.hash() {
+            if old_header.height() >= new_header.height() {
+                old_header = self.chain_store_update.get_block_header(old_header.prev_hash())?;
+                rolled_back_blocks += 1;
+            } else {
+                new_header = self.chain_store_update.get_block_header(new_header.prev_hash())?;
+            }
+        }
+        let depth: BlockHeightDelta = old_tip.height - old_header.height();
+        debug!(
+            target: "chain",
+            head = head_label,
+            depth,
+            rolled_back_blocks,
+            old_tip = %old_tip.last_block_hash,
+            new_tip = %new_tip.last_block_hash,
+            "Chain reorg");
+        metrics::CHAIN_REORGS_TOTAL.with_label_values(&[head_label]).inc();
+        metrics::CHAIN_REORGS_IN_EPOCH.with_label_values(&[head_label]).inc();
+        metrics::CHAIN_REORG_DEPTH.with_label_values(&[head_label]).observe(depth as f64);
+        metrics::CHAIN_REORG_LAST_DEPTH.with_label_values(&[head_label]).set(depth as i64);
+        metrics::CHAIN_REORG_ROLLED_BACK_BLOCKS
+            .with_label_values(&[head_label])
+            .observe(rolled_back_blocks as f64);
+        Ok(Some((depth, rolled_back_blocks)))
+    }
+
     /// Marks a block as invalid,
     fn mark_block_as_challenged(
         &mut self,
@@ -5919,8 +6578,14 @@ pub struct ApplyStatePartsRequest {
     pub shard_uid: ShardUId,
     pub state_root: StateRoot,
     pub num_parts: u64,
+    /// If set, only this part is applied, while the other parts may still be
+    /// downloading. Otherwise all the parts are applied.
+    pub part_id: Option<u64>,
     pub epoch_id: EpochId,
     pub sync_hash: CryptoHash,
+    /// Whether the node produces chunks of the shard in the next epoch, which
+    /// makes the request run before the other requests to apply state parts.
+    pub priority: bool,
 }
 
 // Skip `runtime_adapter`, because it's a complex object that has complex logic
@@ -5932,8 +6597,10 @@ impl Debug for ApplyStatePartsRequest {
             .field("shard_uid", &self.shard_uid)
             .field("state_root", &self.state_root)
             .field("num_parts", &self.num_parts)
+            .field("part_id", &self.part_id)
             .field("epoch_id", &self.epoch_id)
             .field("sync_hash", &self.sync_hash)
+            .field("priority", &self.priority)
             .finish()
     }
 }
@@ -5943,7 +6610,19 @@ impl Debug for ApplyStatePartsRequest {
 pub struct ApplyStatePartsResponse {
     pub apply_result: Result<(), near_chain_primitives::error::Error>,
     pub shard_id: ShardId,
+    /// The part applied, if the request was for a single part.
+    pub part_id: Option<u64>,
+    pub sync_hash: CryptoHash,
+}
+
+/// Sent periodically from SyncJobsActor to ClientActor while the state parts
+/// of a shard are applied or its state is split.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct SyncJobProgress {
     pub sync_hash: CryptoHash,
+    pub shard_id: ShardId,
+    pub progress: SyncJobProgressView,
 }
 
 #[derive(actix::Message)]
@@ -5953,6 +6632,9 @@ pub struct BlockCatchUpRequest {
     pub block_hash: CryptoHash,
     pub block_height: BlockHeight,
     pub work: Vec<Box<dyn FnOnce(&Span) -> Result<ApplyChunkResult, Error> + Send>>,
+    /// Whether the node produces chunks of any of the caught up shards in the
+    /// next epoch, which makes the request run before the other block catchups.
+    pub priority: bool,
 }
 
 // Skip `work`, because displaying functions is not possible.
@@ -5963,6 +6645,7 @@ impl Debug for BlockCatchUpRequest {
             .field("block_hash", &self.block_hash)
             .field("block_height", &self.block_height)
             .field("work", &format!("<vector of length {}>", self.work.len()))
+            .field("priority", &self.priority)
             .finish()
     }
 }
@@ -0,0 +1,174 @@
+//! Subscription to events of the chain.
+//!
+//! `Chain` publishes `ChainEvent`s on its `ChainEventBus` once the changes
+//! made by processing a block are committed, so in-process consumers don't
+//! need to poll the store.  Every subscriber gets its own bounded queue.
+//! Events which don't fit in the queue of a subscriber that doesn't keep up
+//! are dropped and counted in `near_chain_events_dropped_total`, so a slow
+//! subscriber never blocks block processing.  The subscriber learns about it
+//! from an `EventsDropped` event, queued as soon as there is room again.
+//! Subscribers which dropped their receiver are removed on the next publish.
+
+use crate::metrics;
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use near_primitives::block::Tip;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{BlockHeight, BlockHeightDelta, StateRoot};
+use std::sync::{Arc, Mutex};
+
+/// Capacity of the queue of subscribers created with `subscribe`.
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainEvent {
+    /// The head moved to a new block.
+    NewHead(Tip),
+    /// The final head moved to a new block.
+    NewFinalHead(Tip),
+    /// The new head doesn't extend the old one.  Published right before the
+    /// `NewHead` event of the new head.
+    Reorg {
+        old_head: Tip,
+        new_head: Tip,
+        /// Difference between the heights of the old head and of the last
+        /// block it shares with the new head.
+        depth: BlockHeightDelta,
+        /// Number of blocks of the old head's chain which are no longer on
+        /// the chain.
+        rolled_back_blocks: u64,
+    },
+    /// A shard was applied while processing a block.
+    ChunkApplied {
+        block_hash: CryptoHash,
+        height: BlockHeight,
+        shard_uid: ShardUId,
+        /// False if the block has no new chunk for the shard.
+        is_new_chunk: bool,
+        state_root: StateRoot,
+    },
+    /// The given number of events were dropped because the queue of the
+    /// subscriber was full.  Received in place of the dropped events.
+    EventsDropped { count: u64 },
+}
+
+/// Broadcasts `ChainEvent`s to all subscribers.  Clones share subscribers.
+#[derive(Clone, Default)]
+pub struct ChainEventBus {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+struct Subscriber {
+    sender: Sender<ChainEvent>,
+    /// Number of events dropped since the subscriber was last notified.
+    dropped: u64,
+}
+
+impl Subscriber {
+    /// Queues the event, or counts it as dropped if the queue is full.
+    /// Returns false if the subscriber is gone.
+    fn send(&mut self, event: ChainEvent) -> bool {
+        if self.dropped > 0 {
+            match self.sender.try_send(ChainEvent::EventsDropped { count: self.dropped }) {
+                Ok(()) => self.dropped = 0,
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        // Nothing may be queued after a drop before the subscriber is notified.
+        if self.dropped == 0 {
+            match self.sender.try_send(event) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        metrics::CHAIN_EVENTS_DROPPED.inc();
+        self.dropped += 1;
+        true
+    }
+}
+
+impl ChainEventBus {
+    pub fn subscribe(&self) -> Receiver<ChainEvent> {
+        self.subscribe_with_capacity(DEFAULT_SUBSCRIBER_CAPACITY)
+    }
+
+    /// Subscribes to events published from now on.  Once `capacity` events
+    /// are queued for the subscriber, further events are dropped until it
+    /// receives some of them.
+    pub fn subscribe_with_capacity(&self, capacity: usize) -> Receiver<ChainEvent> {
+        let (sender, receiver) = bounded(capacity);
+        self.subscribers.lock().unwrap().push(Subscriber { sender, dropped: 0 });
+        receiver
+    }
+
+    pub fn num_subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    pub(crate) fn publish(&self, events: Vec<ChainEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|subscriber| events.iter().all(|event| subscriber.send(event.clone())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainEvent, ChainEventBus};
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::EpochId;
+
+    fn new_head(height: u64) -> ChainEvent {
+        ChainEvent::NewHead(Tip {
+            height,
+            last_block_hash: CryptoHash::hash_bytes(&height.to_le_bytes()),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        })
+    }
+
+    #[test]
+    fn test_chain_event_bus() {
+        let bus = ChainEventBus::default();
+        let receiver = bus.subscribe();
+        let slow_receiver = bus.clone().subscribe_with_capacity(2);
+        let dropped_receiver = bus.subscribe();
+        drop(dropped_receiver);
+        assert_eq!(bus.num_subscribers(), 3);
+
+        bus.publish((1..=3).map(new_head).collect());
+        // Subscribers which dropped their receiver are removed.
+        assert_eq!(bus.num_subscribers(), 2);
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            (1..=3).map(new_head).collect::<Vec<_>>()
+        );
+        // Events which don't fit in the queue are dropped.
+        assert_eq!(slow_receiver.try_iter().collect::<Vec<_>>(), vec![new_head(1), new_head(2)]);
+
+        // The subscriber is told about the dropped events before the next one.
+        bus.publish(vec![new_head(4)]);
+        assert_eq!(receiver.try_recv().unwrap(), new_head(4));
+        assert_eq!(
+            slow_receiver.try_iter().collect::<Vec<_>>(),
+            vec![ChainEvent::EventsDropped { count: 1 }, new_head(4)]
+        );
+
+        // Events published while the notification doesn't fit are counted too.
+        bus.publish((5..=8).map(new_head).collect());
+        assert_eq!(slow_receiver.try_iter().collect::<Vec<_>>(), vec![new_head(5), new_head(6)]);
+        bus.publish(vec![new_head(9)]);
+        assert_eq!(
+            slow_receiver.try_iter().collect::<Vec<_>>(),
+            vec![ChainEvent::EventsDropped { count: 2 }, new_head(9)]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Self-contained artifacts for replaying the application of a chunk.
+//!
+//! When the outcome of applying a chunk doesn't match the one the next chunk
+//! of the shard builds on (e.g. the state roots differ), the chain can write
+//! a `ChunkReplayArtifact` to the directory configured in
+//! `ChainConfig::chunk_replay_artifacts_dir`.  The artifact contains all the
+//! inputs of the application together with the part of the trie it touched,
+//! so `replay_chunk` can reproduce it without access to the node's storage,
+//! e.g. in a test.
+
+use crate::chain::collect_receipts_from_response;
+use crate::migrations::check_if_block_is_first_with_chunk_of_version;
+use crate::types::{ApplyTransactionResult, RuntimeAdapter};
+use crate::{ChainStore, ChainStoreAccess};
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_chain_primitives::Error;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::challenge::{ChallengesResult, PartialState};
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
+use near_primitives::types::{Balance, BlockHeight, Gas, ShardId, StateRoot};
+use near_store::PartialStorage;
+use std::path::{Path, PathBuf};
+
+/// Inputs and recorded outcome of applying a new chunk.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ChunkReplayArtifact {
+    pub shard_id: ShardId,
+    /// Height of the block which included the chunk.
+    pub height: BlockHeight,
+    pub block_hash: CryptoHash,
+    pub prev_block_hash: CryptoHash,
+    pub block_timestamp: u64,
+    pub prev_state_root: StateRoot,
+    pub receipts: Vec<Receipt>,
+    pub transactions: Vec<SignedTransaction>,
+    pub validator_proposals: Vec<ValidatorStake>,
+    pub gas_price: Balance,
+    pub gas_limit: Gas,
+    pub challenges_result: ChallengesResult,
+    pub random_seed: CryptoHash,
+    pub is_first_block_with_chunk_of_version: bool,
+    /// Trie nodes and values read while applying the chunk.
+    pub partial_state: PartialState,
+    /// Outcome of applying the chunk saved by this node.
+    pub chunk_extra: ChunkExtra,
+    /// State root the next chunk of the shard claims the chunk resulted in.
+    pub claimed_state_root: StateRoot,
+}
+
+impl ChunkReplayArtifact {
+    /// Collects the inputs of applying the new chunk of given shard included
+    /// in given block and applies it again to record the touched trie nodes.
+    /// Requires the state as of the previous block.
+    pub fn new(
+        chain_store: &ChainStore,
+        epoch_manager: &dyn EpochManagerAdapter,
+        runtime: &dyn RuntimeAdapter,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        claimed_state_root: StateRoot,
+    ) -> Result<Self, Error> {
+        let block = chain_store.get_block(block_hash)?;
+        let prev_block = chain_store.get_block(block.header().prev_hash())?;
+        let shard_uid = epoch_manager.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+        let chunk_extra = chain_store.get_chunk_extra(block_hash, &shard_uid)?;
+        let chunk_header = &block.chunks()[shard_id as usize];
+        if chunk_header.height_included() != block.header().height() {
+            return Err(Error::Other(format!(
+                "block {} has no new chunk for shard {}",
+                block_hash, shard_id
+            )));
+        }
+        let chunk = chain_store.get_chunk(&chunk_header.chunk_hash())?;
+        let receipts = chain_store.get_incoming_receipts_for_shard(
+            epoch_manager,
+            shard_id,
+            *block_hash,
+            prev_block.chunks()[shard_id as usize].height_included(),
+        )?;
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            chain_store,
+            epoch_manager,
+            prev_block.hash(),
+            shard_id,
+        )?;
+        let chunk_inner = chunk.cloned_header().take_inner();
+        let mut artifact = Self {
+            shard_id,
+            height: block.header().height(),
+            block_hash: *block_hash,
+            prev_block_hash: *prev_block.hash(),
+            block_timestamp: block.header().raw_timestamp(),
+            prev_state_root: chunk_inner.prev_state_root(),
+            receipts: collect_receipts_from_response(&receipts),
+            transactions: chunk.transactions().to_vec(),
+            validator_proposals: chunk_inner.prev_validator_proposals().collect(),
+            gas_price: prev_block.header().gas_price(),
+            gas_limit: chunk_inner.gas_limit(),
+            challenges_result: block.header().challenges_result().clone(),
+            random_seed: *block.header().random_value(),
+            is_first_block_with_chunk_of_version,
+            partial_state: PartialState::TrieValues(vec![]),
+            chunk_extra: ChunkExtra::clone(&chunk_extra),
+            claimed_state_root,
+        };
+        // Flat storage only has the state as of recent blocks so the state is
+        // read from the trie, which also records the touched nodes.
+        let apply_result = runtime.apply_transactions_with_optional_storage_proof(
+            shard_id,
+            &artifact.prev_state_root,
+            artifact.height,
+            artifact.block_timestamp,
+            &artifact.prev_block_hash,
+            &artifact.block_hash,
+            &artifact.receipts,
+            &artifact.transactions,
+            ValidatorStakeIter::new(&artifact.validator_proposals),
+            artifact.gas_price,
+            artifact.gas_limit,
+            &artifact.challenges_result,
+            artifact.random_seed,
+            true,
+            true,
+            artifact.is_first_block_with_chunk_of_version,
+            Default::default(),
+            false,
+        )?;
+        let proof = apply_result.proof.ok_or_else(|| {
+            Error::Other("applying the chunk didn't generate a storage proof".to_string())
+        })?;
+        artifact.partial_state = proof.nodes;
+        Ok(artifact)
+    }
+
+    /// Writes the artifact to given directory and returns the path of the
+    /// created file.
+    pub fn save(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}_{}_{}.borsh", self.height, self.shard_id, self.block_hash));
+        std::fs::write(&path, self.try_to_vec()?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Self::try_from_slice(&std::fs::read(path)?)
+    }
+}
+
+/// Applies the chunk recorded in the artifact again using only the state
+/// included in the artifact.  Compare the result's `new_root` with
+/// `artifact.chunk_extra` and `artifact.claimed_state_root` to find out which
+/// of them is reproducible.
+pub fn replay_chunk(
+    runtime: &dyn RuntimeAdapter,
+    artifact: &ChunkReplayArtifact,
+) -> Result<ApplyTransactionResult, Error> {
+    runtime.check_state_transition(
+        PartialStorage { nodes: artifact.partial_state.clone() },
+        artifact.shard_id,
+        &artifact.prev_state_root,
+        artifact.height,
+        artifact.block_timestamp,
+        &artifact.prev_block_hash,
+        &artifact.block_hash,
+        &artifact.receipts,
+        &artifact.transactions,
+        ValidatorStakeIter::new(&artifact.validator_proposals),
+        artifact.gas_price,
+        artifact.gas_limit,
+        &artifact.challenges_result,
+        artifact.random_seed,
+        true,
+        artifact.is_first_block_with_chunk_of_version,
+    )
+}
@@ -4,6 +4,7 @@ use std::time::{Duration, Instant};
 
 use crate::doomslug::trackable::TrackableBlockHeightValue;
 use crate::metrics;
+use near_chain_configs::DoomslugTimerConfig;
 use near_client_primitives::debug::{ApprovalAtHeightStatus, ApprovalHistoryEntry};
 use near_crypto::Signature;
 use near_primitives::block::{Approval, ApprovalInner};
@@ -48,6 +49,21 @@ pub enum DoomslugBlockProductionReadiness {
     ReadySince(Instant),
 }
 
+/// Lets tests and chaos tools make the node skip heights regardless of the
+/// doomslug timer.
+pub trait DoomslugSkipPolicy: Send + Sync {
+    /// Called when the tip at `target_height - 1` is about to be endorsed.
+    /// If it returns true, the tip isn't endorsed, and an approval skipping
+    /// `target_height` is sent right away instead.
+    fn should_skip(&self, target_height: BlockHeight) -> bool;
+}
+
+impl<F: Fn(BlockHeight) -> bool + Send + Sync> DoomslugSkipPolicy for F {
+    fn should_skip(&self, target_height: BlockHeight) -> bool {
+        self(target_height)
+    }
+}
+
 struct DoomslugTimer {
     started: Instant,
     last_endorsement_sent: Instant,
@@ -140,6 +156,8 @@ pub struct Doomslug {
     /// How many approvals to have before producing a block. In production should be always `HalfStake`,
     ///    but for many tests we use `NoApprovals` to invoke more forkfulness
     threshold_mode: DoomslugThresholdMode,
+    /// If set, decides which heights to skip regardless of the timer.
+    skip_policy: Option<Arc<dyn DoomslugSkipPolicy>>,
 
     /// Approvals that were created by this doomslug instance (for debugging only).
     /// Keeps up to MAX_HISTORY_SIZE entries.
@@ -391,10 +409,30 @@ impl Doomslug {
             },
             signer,
             threshold_mode,
+            skip_policy: None,
             history: VecDeque::new(),
         }
     }
 
+    /// Updates the delays of the timer.  Takes effect from the next call to
+    /// `process_timer`.
+    pub fn set_timer_config(&mut self, config: &DoomslugTimerConfig) {
+        self.timer.min_delay = config.min_delay;
+        self.timer.delay_step = config.delay_step;
+        self.timer.max_delay = config.max_delay;
+    }
+
+    /// Sets the policy deciding which heights to skip regardless of the timer,
+    /// or unsets it if `None`.
+    pub fn set_skip_policy(&mut self, skip_policy: Option<Arc<dyn DoomslugSkipPolicy>>) {
+        self.skip_policy = skip_policy;
+    }
+
+    /// Sets the signer of the approvals sent from now on.
+    pub fn set_signer(&mut self, signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.signer = signer;
+    }
+
     #[cfg(feature = "test_features")]
     pub fn adv_disable(&mut self) {
         self.threshold_mode = DoomslugThresholdMode::NoApprovals
@@ -464,6 +502,7 @@ impl Doomslug {
     #[must_use]
     pub fn process_timer(&mut self, cur_time: Instant) -> Vec<Approval> {
         let mut ret = vec![];
+        let mut skip_injected = false;
         for _ in 0..MAX_TIMER_ITERS {
             let skip_delay = self
                 .timer
@@ -482,27 +521,33 @@ impl Doomslug {
                 if tip_height >= self.largest_target_height.get() {
                     self.largest_target_height.set(tip_height + 1);
 
-                    if let Some(approval) = self.create_approval(tip_height + 1) {
-                        ret.push(approval);
+                    if self.skip_policy.as_ref().map_or(false, |p| p.should_skip(tip_height + 1)) {
+                        info!(target: "doomslug", height = tip_height + 1, "Skipping height as requested by the skip policy");
+                        skip_injected = true;
+                    } else {
+                        if let Some(approval) = self.create_approval(tip_height + 1) {
+                            ret.push(approval);
+                        }
+                        self.update_history(ApprovalHistoryEntry {
+                            parent_height: tip_height,
+                            target_height: tip_height + 1,
+                            timer_started_ago_millis: self
+                                .timer
+                                .last_endorsement_sent
+                                .elapsed()
+                                .as_millis()
+                                as u64,
+                            expected_delay_millis: self.timer.endorsement_delay.as_millis() as u64,
+                            approval_creation_time: chrono::Utc::now(),
+                        });
                     }
-                    self.update_history(ApprovalHistoryEntry {
-                        parent_height: tip_height,
-                        target_height: tip_height + 1,
-                        timer_started_ago_millis: self
-                            .timer
-                            .last_endorsement_sent
-                            .elapsed()
-                            .as_millis() as u64,
-                        expected_delay_millis: self.timer.endorsement_delay.as_millis() as u64,
-                        approval_creation_time: chrono::Utc::now(),
-                    });
                 }
 
                 self.timer.last_endorsement_sent = cur_time;
                 self.endorsement_pending = false;
             }
 
-            if cur_time >= self.timer.started + skip_delay {
+            if skip_injected || cur_time >= self.timer.started + skip_delay {
                 debug_assert!(!self.endorsement_pending);
 
                 self.largest_target_height
@@ -520,7 +565,12 @@ impl Doomslug {
                 });
 
                 // Restart the timer
-                self.timer.started += skip_delay;
+                if skip_injected {
+                    self.timer.started = cur_time;
+                    skip_injected = false;
+                } else {
+                    self.timer.started += skip_delay;
+                }
                 self.timer.height += 1;
             } else {
                 break;
@@ -746,15 +796,17 @@ mod tests {
     use std::sync::Arc;
     use std::time::Duration;
 
+    use near_chain_configs::DoomslugTimerConfig;
     use near_crypto::{KeyType, SecretKey};
     use near_primitives::block::{Approval, ApprovalInner};
     use near_primitives::hash::hash;
     use near_primitives::static_clock::StaticClock;
     use near_primitives::test_utils::create_test_signer;
-    use near_primitives::types::ApprovalStake;
+    use near_primitives::types::{ApprovalStake, BlockHeight};
 
     use crate::doomslug::{
-        DoomslugApprovalsTrackersAtHeight, DoomslugBlockProductionReadiness, DoomslugThresholdMode,
+        DoomslugApprovalsTrackersAtHeight, DoomslugBlockProductionReadiness, DoomslugSkipPolicy,
+        DoomslugThresholdMode,
     };
     use crate::Doomslug;
 
@@ -886,6 +938,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_skip_policy_and_timer_config() {
+        let mut ds = Doomslug::new(
+            0,
+            Duration::from_millis(400),
+            Duration::from_millis(1000),
+            Duration::from_millis(100),
+            Duration::from_millis(3000),
+            Some(Arc::new(create_test_signer("test"))),
+            DoomslugThresholdMode::TwoThirds,
+        );
+        let skip_policy: Arc<dyn DoomslugSkipPolicy> =
+            Arc::new(|target_height: BlockHeight| target_height == 3);
+        ds.set_skip_policy(Some(skip_policy));
+
+        let mut now = StaticClock::instant();
+
+        // Height 2 isn't skipped by the policy, so the tip is endorsed.
+        ds.set_tip(now, hash(&[1]), 1, 1);
+        let approvals = ds.process_timer(now + Duration::from_millis(400));
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].inner, ApprovalInner::Endorsement(hash(&[1])));
+        assert_eq!(approvals[0].target_height, 2);
+
+        // Height 3 is skipped right away instead of being endorsed.
+        now += Duration::from_millis(1000);
+        ds.set_tip(now, hash(&[2]), 2, 2);
+        let approvals = ds.process_timer(now + Duration::from_millis(400));
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].inner, ApprovalInner::Skip(2));
+        assert_eq!(approvals[0].target_height, 4);
+
+        // The timer restarts when the height is skipped, and uses the updated delays.
+        ds.set_skip_policy(None);
+        ds.set_timer_config(&DoomslugTimerConfig {
+            min_delay: Duration::from_millis(2000),
+            delay_step: Duration::from_millis(100),
+            max_delay: Duration::from_millis(3000),
+        });
+        assert_eq!(ds.process_timer(now + Duration::from_millis(2399)), vec![]);
+        let approvals = ds.process_timer(now + Duration::from_millis(2400));
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].inner, ApprovalInner::Skip(2));
+        assert_eq!(approvals[0].target_height, 5);
+    }
+
     #[test]
     fn test_doomslug_approvals() {
         let accounts: Vec<(&str, u128, u128)> =
@@ -0,0 +1,264 @@
+//! Proofs of epoch starts for epoch sync.
+//!
+//! Instead of downloading all the headers since genesis, a node doing epoch
+//! sync downloads an `EpochSyncProof` for the start of every epoch. The first
+//! block of an epoch commits to the epoch manager data of the previous, the
+//! current and the next epoch in its `epoch_sync_data_hash`, so a proof which
+//! is signed by the block producers of the epoch gives the block producers of
+//! the next epoch, which verify the next proof, and so on. Once the latest
+//! epochs are reached, the chain is initialized from the last proof and
+//! header sync continues from the last block of the epoch before it.
+
+#[cfg(feature = "new_epoch_sync")]
+use crate::store::ChainStoreAccess;
+use crate::types::Tip;
+use crate::{Chain, Doomslug, DoomslugThresholdMode};
+use near_chain_primitives::Error;
+use near_primitives::block::{Approval, ApprovalInner};
+use near_primitives::epoch_manager::block_info::BlockInfo;
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
+use near_primitives::hash::CryptoHash;
+#[cfg(feature = "new_epoch_sync")]
+use near_primitives::merkle::PartialMerkleTree;
+use near_primitives::types::{AccountId, ApprovalStake, EpochId};
+use std::collections::HashMap;
+
+fn invalid_proof(reason: &str) -> Error {
+    Error::InvalidEpochSyncProof(reason.to_string())
+}
+
+/// Checks that `proof` proves the start of the epoch `epoch_id`, whose info
+/// is `epoch_info`. The first block of the epoch must be signed by its block
+/// producer and approved by the block producers in the block after it, and
+/// the rest of the proof must match what the first block commits to.
+pub fn validate_epoch_sync_proof(
+    proof: &EpochSyncProof,
+    epoch_id: &EpochId,
+    epoch_info: &EpochInfo,
+) -> Result<(), Error> {
+    let EpochSyncProof { prev_epoch_sync_info, data, block_merkle_tree, first_header, next_header } =
+        proof;
+
+    if first_header.epoch_id() != epoch_id || next_header.epoch_id() != epoch_id {
+        return Err(Error::InvalidEpochHash);
+    }
+    if next_header.prev_hash() != first_header.hash() {
+        return Err(invalid_proof("next header doesn't follow the first header"));
+    }
+    for header in [first_header, next_header] {
+        let block_producer =
+            epoch_info.get_validator(epoch_info.sample_block_producer(header.height()));
+        if !header.verify_block_producer(block_producer.public_key()) {
+            return Err(Error::InvalidSignature);
+        }
+    }
+
+    // The first block commits to the epoch manager data, which is what makes
+    // the next epoch info trusted.
+    if first_header.epoch_sync_data_hash() != Some(CryptoHash::hash_borsh(data)) {
+        return Err(invalid_proof("epoch sync data doesn't match the first header"));
+    }
+    let (
+        prev_epoch_first_block_info,
+        prev_epoch_prev_last_block_info,
+        prev_epoch_last_block_info,
+        _,
+        this_epoch_info,
+        next_epoch_info,
+    ) = data;
+    if this_epoch_info.as_ref() != epoch_info {
+        return Err(invalid_proof("epoch info doesn't match the epoch"));
+    }
+
+    // Approvals in the next block show that the first block was accepted.
+    let approvals = next_header.approvals();
+    let mut approvers = get_approvers_ordered(epoch_info, None);
+    if approvals.len() > approvers.len() {
+        // The first block may be close enough to the end of the epoch to need
+        // approvals from the block producers of the next epoch too.
+        approvers = get_approvers_ordered(epoch_info, Some(next_epoch_info.as_ref()));
+    }
+    if approvals.len() > approvers.len() {
+        return Err(Error::InvalidApprovals);
+    }
+    let message_to_sign = Approval::get_data_for_sig(
+        &if first_header.height() + 1 == next_header.height() {
+            ApprovalInner::Endorsement(*first_header.hash())
+        } else {
+            ApprovalInner::Skip(first_header.height())
+        },
+        next_header.height(),
+    );
+    for (approver, approval) in approvers.iter().zip(approvals.iter()) {
+        if let Some(signature) = approval {
+            if !signature.verify(message_to_sign.as_ref(), &approver.public_key) {
+                return Err(Error::InvalidApprovals);
+            }
+        }
+    }
+    let stakes = approvers
+        .iter()
+        .map(|approver| (approver.stake_this_epoch, approver.stake_next_epoch, false))
+        .collect::<Vec<_>>();
+    if !Doomslug::can_approved_block_be_produced(
+        DoomslugThresholdMode::TwoThirds,
+        approvals,
+        &stakes,
+    ) {
+        return Err(Error::NotEnoughApprovals);
+    }
+
+    // Headers of the previous epoch must be the ones of the block infos.
+    let info = prev_epoch_sync_info;
+    if first_header.prev_hash() != prev_epoch_last_block_info.hash()
+        || info.last.header.hash() != prev_epoch_last_block_info.hash()
+        || info.prev_last.header.hash() != prev_epoch_prev_last_block_info.hash()
+        || info.first.header.hash() != prev_epoch_first_block_info.hash()
+        || info.last.header.prev_hash() != info.prev_last.header.hash()
+    {
+        return Err(invalid_proof("headers of the previous epoch don't match the block infos"));
+    }
+    for pair in [&info.first, &info.prev_last, &info.last] {
+        // The last final block of genesis isn't set, see `Chain::get_header_pair`.
+        let last_final_block = match pair.header.last_final_block() {
+            hash if hash == &CryptoHash::default() => pair.header.hash(),
+            hash => hash,
+        };
+        if pair.last_finalised_header.hash() != last_final_block {
+            return Err(invalid_proof("last final header doesn't match the header"));
+        }
+    }
+
+    let mut block_merkle_tree = block_merkle_tree.clone();
+    block_merkle_tree.insert(*first_header.prev_hash());
+    if &block_merkle_tree.root() != first_header.block_merkle_root() {
+        return Err(Error::InvalidBlockMerkleRoot);
+    }
+    Ok(())
+}
+
+/// Returns the approval stakes of the block producers which can approve a
+/// block in the order of the approvals, like
+/// `EpochManager::get_all_block_approvers_ordered` does given the infos of
+/// the epoch and, if the block needs approvals from it, the next epoch.
+fn get_approvers_ordered(
+    epoch_info: &EpochInfo,
+    next_epoch_info: Option<&EpochInfo>,
+) -> Vec<ApprovalStake> {
+    let mut settlement = epoch_info
+        .block_producers_settlement()
+        .iter()
+        .map(|&validator_id| epoch_info.get_validator(validator_id))
+        .collect::<Vec<_>>();
+    let settlement_epoch_boundary = settlement.len();
+    if let Some(next_epoch_info) = next_epoch_info {
+        settlement.extend(
+            next_epoch_info
+                .block_producers_settlement()
+                .iter()
+                .map(|&validator_id| next_epoch_info.get_validator(validator_id)),
+        );
+    }
+
+    let mut result: Vec<ApprovalStake> = vec![];
+    let mut validators: HashMap<AccountId, usize> = HashMap::new();
+    for (ord, validator_stake) in settlement.into_iter().enumerate() {
+        match validators.get(validator_stake.account_id()) {
+            None => {
+                validators.insert(validator_stake.account_id().clone(), result.len());
+                result.push(validator_stake.get_approval_stake(ord >= settlement_epoch_boundary));
+            }
+            Some(&old_ord) => {
+                if ord >= settlement_epoch_boundary {
+                    result[old_ord].stake_next_epoch = validator_stake.stake();
+                }
+            }
+        }
+    }
+    result
+}
+
+impl Chain {
+    /// Returns the proof of the start of the epoch after `prev_epoch_id`, or
+    /// None if the epoch hasn't started yet or the block after its first
+    /// block isn't final yet.
+    #[cfg(feature = "new_epoch_sync")]
+    pub fn get_epoch_sync_proof(
+        &self,
+        prev_epoch_id: &EpochId,
+    ) -> Result<Option<EpochSyncProof>, Error> {
+        let prev_epoch_sync_info = match self.store().get_epoch_sync_info(prev_epoch_id) {
+            Ok(info) => info,
+            Err(Error::DBNotFoundErr(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let prev_epoch_last_hash = *prev_epoch_sync_info.last.header.hash();
+        let Ok(first_hash) = self.store().get_next_block_hash(&prev_epoch_last_hash) else {
+            return Ok(None);
+        };
+        let Ok(next_hash) = self.store().get_next_block_hash(&first_hash) else {
+            return Ok(None);
+        };
+        let first_header = self.get_block_header(&first_hash)?;
+        let next_header = self.get_block_header(&next_hash)?;
+        if next_header.height() > self.final_head()?.height {
+            return Ok(None);
+        }
+
+        let data = self.epoch_manager.get_epoch_sync_data(
+            &prev_epoch_last_hash,
+            first_header.epoch_id(),
+            first_header.next_epoch_id(),
+        )?;
+        let block_merkle_tree =
+            PartialMerkleTree::clone(&self.store().get_block_merkle_tree(&prev_epoch_last_hash)?);
+        Ok(Some(EpochSyncProof {
+            prev_epoch_sync_info,
+            data,
+            block_merkle_tree,
+            first_header,
+            next_header,
+        }))
+    }
+
+    /// Initializes the epoch manager and the header head from a proof which
+    /// passed `validate_epoch_sync_proof`, so that header sync continues from
+    /// the last block of the epoch before the proven one.
+    pub fn apply_epoch_sync_proof(&mut self, proof: EpochSyncProof) -> Result<(), Error> {
+        let EpochSyncProof { prev_epoch_sync_info, data, block_merkle_tree, first_header, .. } =
+            proof;
+        let (
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            epoch_info,
+            next_epoch_info,
+        ) = data;
+        self.epoch_manager.epoch_sync_init_epoch_manager(
+            BlockInfo::clone(&prev_epoch_first_block_info),
+            BlockInfo::clone(&prev_epoch_prev_last_block_info),
+            BlockInfo::clone(&prev_epoch_last_block_info),
+            prev_epoch_last_block_info.epoch_id(),
+            EpochInfo::clone(&prev_epoch_info),
+            first_header.epoch_id(),
+            EpochInfo::clone(&epoch_info),
+            first_header.next_epoch_id(),
+            EpochInfo::clone(&next_epoch_info),
+        )?;
+
+        let info = prev_epoch_sync_info;
+        let tip = Tip::from_header(&info.last.header);
+        let mut chain_store_update = self.mut_store().store_update();
+        for pair in [&info.first, &info.prev_last, &info.last] {
+            chain_store_update.save_block_header_no_update_tree(pair.header.clone())?;
+            chain_store_update
+                .save_block_header_no_update_tree(pair.last_finalised_header.clone())?;
+        }
+        chain_store_update.save_epoch_sync_tip(&tip, block_merkle_tree)?;
+        chain_store_update.commit()?;
+        tracing::info!(target: "sync", height = tip.height, hash = ?tip.last_block_hash, "Applied epoch sync proof");
+        Ok(())
+    }
+}
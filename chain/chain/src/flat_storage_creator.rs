@@ -11,10 +11,10 @@
 
 use crate::types::RuntimeAdapter;
 use crate::{ChainStore, ChainStoreAccess};
-use assert_matches::assert_matches;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use near_chain_primitives::Error;
 use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::state::FlatStateValue;
 use near_primitives::state_part::PartId;
@@ -141,6 +141,93 @@ impl FlatStorageShardCreator {
         }
     }
 
+    /// Checks that deltas for all blocks after the chain final head are saved to disk, so that flat storage
+    /// created for the final head can later catch up with the chain.
+    fn has_deltas_after_final_head(&self, chain_store: &ChainStore) -> Result<bool, Error> {
+        let shard_id = self.shard_uid.shard_id();
+        let final_height = chain_store.final_head()?.height;
+        for height in final_height + 1..=chain_store.head()?.height {
+            // We skip heights for which there are no blocks, because certain heights can be skipped.
+            for (_, hashes) in chain_store.get_all_block_hashes_by_height(height)?.iter() {
+                for hash in hashes {
+                    debug!(target: "store", %shard_id, %height, %hash, "Checking delta existence");
+                    if !matches!(
+                        store_helper::get_delta_changes(chain_store.store(), self.shard_uid, *hash),
+                        Ok(Some(_))
+                    ) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns status of the first step of fetching state for the given block, splitting the shard state into
+    /// parts which fit into `STATE_PART_MEMORY_LIMIT`.
+    fn initial_fetching_state_status(
+        &self,
+        chain_store: &ChainStore,
+        block_hash: CryptoHash,
+    ) -> Result<FetchingStateStatus, Error> {
+        let store = self.runtime.store().clone();
+        let epoch_id = self.epoch_manager.get_epoch_id(&block_hash)?;
+        let shard_uid = self.epoch_manager.shard_id_to_uid(self.shard_uid.shard_id(), &epoch_id)?;
+        let trie_storage = TrieDBStorage::new(store, shard_uid);
+        let state_root = *chain_store.get_chunk_extra(&block_hash, &shard_uid)?.state_root();
+        let trie = Trie::new(Rc::new(trie_storage), state_root, None);
+        let root_node = trie.retrieve_root_node().unwrap();
+        let num_state_parts = root_node.memory_usage / STATE_PART_MEMORY_LIMIT.as_u64() + 1;
+        Ok(FetchingStateStatus {
+            block_hash,
+            part_id: 0,
+            num_parts_in_step: NUM_PARTS_IN_ONE_STEP,
+            num_parts: num_state_parts,
+        })
+    }
+
+    /// Starts rebuilding flat storage for the shard from scratch, e.g. if its data or status got corrupted.
+    /// Removes all flat state values and deltas up to the chain final head and switches status to fetching
+    /// state for the final head. Fetched ranges of state parts are checkpointed in the status, so the rebuild
+    /// survives restarts: both the node and `update_status` continue it from the last saved step.
+    /// Fails if deltas for blocks after the final head are missing, because flat storage couldn't catch up
+    /// with the chain after fetching state then.
+    pub fn start_rebuild(&self, chain_store: &ChainStore) -> Result<FetchingStateStatus, Error> {
+        let shard_id = self.shard_uid.shard_id();
+        let final_head = chain_store.final_head()?;
+        if !self.has_deltas_after_final_head(chain_store)? {
+            return Err(Error::Other(format!(
+                "Cannot rebuild flat storage for shard {shard_id}: some deltas after final head {} are missing",
+                final_head.last_block_hash
+            )));
+        }
+        let status = self.initial_fetching_state_status(chain_store, final_head.last_block_hash)?;
+        info!(target: "store", %shard_id, final_height = %final_head.height, ?status, "Starting flat storage rebuild");
+
+        let store = chain_store.store();
+        let mut store_update = store.store_update();
+        store_helper::remove_all_flat_state_values(&mut store_update, self.shard_uid);
+        for delta_metadata in store_helper::get_all_deltas_metadata(store, self.shard_uid)
+            .map_err(|err| Error::StorageError(err.into()))?
+        {
+            if delta_metadata.block.height <= final_head.height {
+                store_helper::remove_delta(
+                    &mut store_update,
+                    self.shard_uid,
+                    delta_metadata.block.hash,
+                );
+            }
+        }
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            self.shard_uid,
+            FlatStorageStatus::Creation(FlatStorageCreationStatus::FetchingState(status)),
+        );
+        store_update.commit()?;
+        self.metrics.set_flat_head_height(final_head.height);
+        Ok(status)
+    }
+
     /// Checks current flat storage creation status, execute work related to it and possibly switch to next status.
     /// Creates flat storage when all intermediate steps are finished.
     /// Returns boolean indicating if flat storage was created.
@@ -171,43 +258,11 @@ impl FlatStorageShardCreator {
                 if final_height > self.start_height {
                     // If it holds, deltas for all blocks after final head are saved to disk, because they have bigger
                     // heights than one on which we launched a node. Check that it is true:
-                    for height in final_height + 1..=chain_store.head()?.height {
-                        // We skip heights for which there are no blocks, because certain heights can be skipped.
-                        for (_, hashes) in
-                            chain_store.get_all_block_hashes_by_height(height)?.iter()
-                        {
-                            for hash in hashes {
-                                debug!(target: "store", %shard_id, %height, %hash, "Checking delta existence");
-                                assert_matches!(
-                                    store_helper::get_delta_changes(
-                                        chain_store.store(),
-                                        self.shard_uid,
-                                        *hash
-                                    ),
-                                    Ok(Some(_))
-                                );
-                            }
-                        }
-                    }
+                    assert!(self.has_deltas_after_final_head(chain_store)?);
 
                     // We continue saving deltas, and also start fetching state.
-                    let block_hash = final_head.last_block_hash;
-                    let store = self.runtime.store().clone();
-                    let epoch_id = self.epoch_manager.get_epoch_id(&block_hash)?;
-                    let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, &epoch_id)?;
-                    let trie_storage = TrieDBStorage::new(store, shard_uid);
-                    let state_root =
-                        *chain_store.get_chunk_extra(&block_hash, &shard_uid)?.state_root();
-                    let trie = Trie::new(Rc::new(trie_storage), state_root, None);
-                    let root_node = trie.retrieve_root_node().unwrap();
-                    let num_state_parts =
-                        root_node.memory_usage / STATE_PART_MEMORY_LIMIT.as_u64() + 1;
-                    let status = FetchingStateStatus {
-                        block_hash,
-                        part_id: 0,
-                        num_parts_in_step: NUM_PARTS_IN_ONE_STEP,
-                        num_parts: num_state_parts,
-                    };
+                    let status = self
+                        .initial_fetching_state_status(chain_store, final_head.last_block_hash)?;
                     info!(target: "store", %shard_id, %final_height, ?status, "Switching status to fetching state");
 
                     let mut store_update = chain_store.store().store_update();
@@ -290,7 +345,10 @@ impl FlatStorageShardCreator {
                                 num_parts_in_step,
                                 num_parts,
                             };
-                            debug!(target: "chain", %shard_id, %block_hash, ?new_status);
+                            info!(
+                                target: "chain", %shard_id, %block_hash, ?new_status,
+                                "Fetched {:.1}% of state", new_status.progress_percent()
+                            );
                             store_helper::set_flat_storage_status(
                                 &mut store_update,
                                 self.shard_uid,
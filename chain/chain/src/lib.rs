@@ -1,19 +1,27 @@
 pub use block_processing_utils::{BlockProcessingArtifact, DoneApplyChunkCallback};
 pub use chain::{check_known, collect_receipts, Chain, MAX_ORPHAN_SIZE};
-pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
+pub use chain_events::{ChainEvent, ChainEventBus};
+pub use chunk_replay::{replay_chunk, ChunkReplayArtifact};
+pub use doomslug::{
+    Doomslug, DoomslugBlockProductionReadiness, DoomslugSkipPolicy, DoomslugThresholdMode,
+};
 pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
 pub use near_chain_primitives::{self, Error};
 pub use near_primitives::receipt::ReceiptResult;
-pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
+pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCEstimate};
 pub use store_validator::{ErrorMessage, StoreValidator};
 pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance};
 
 mod block_processing_utils;
+pub mod block_timeline;
 pub mod blocks_delay_tracker;
 pub mod chain;
+pub mod chain_events;
+pub mod chunk_replay;
 pub mod chunks_store;
 pub mod crypto_hash_timer;
 mod doomslug;
+pub mod epoch_sync;
 pub mod flat_storage_creator;
 mod lightclient;
 mod metrics;
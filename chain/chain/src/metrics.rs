@@ -1,7 +1,9 @@
+use crate::types::ChunkApplyStats;
 use near_o11y::metrics::{
-    exponential_buckets, try_create_histogram, try_create_histogram_vec,
-    try_create_histogram_with_buckets, try_create_int_counter, try_create_int_gauge,
-    try_create_int_gauge_vec, Histogram, HistogramVec, IntCounter, IntGauge, IntGaugeVec,
+    exponential_buckets, try_create_gauge_vec, try_create_histogram, try_create_histogram_vec,
+    try_create_histogram_with_buckets, try_create_int_counter, try_create_int_counter_vec,
+    try_create_int_gauge, try_create_int_gauge_vec, GaugeVec, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -38,6 +40,38 @@ pub static APPLYING_CHUNKS_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static APPLYING_CHUNKS_TIME_BREAKDOWN: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_applying_chunks_time_breakdown",
+        "Time taken by parts of applying chunks per shard: storage reads, storage writes and wasm execution. Wasm execution includes host functions so it overlaps with storage reads",
+        &["shard_id", "part"],
+        Some(exponential_buckets(0.001, 1.6, 20).unwrap()),
+    )
+    .unwrap()
+});
+pub static APPLYING_CHUNKS_RECEIPTS: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_applying_chunks_receipts",
+        "Number of receipts executed when applying chunks per shard",
+        &["shard_id"],
+        Some(exponential_buckets(1.0, 2.0, 14).unwrap()),
+    )
+    .unwrap()
+});
+
+/// Reports the breakdown of applying a chunk in given shard.
+pub(crate) fn report_apply_stats(shard_id: &str, stats: &ChunkApplyStats) {
+    APPLYING_CHUNKS_TIME_BREAKDOWN
+        .with_label_values(&[shard_id, "storage_read"])
+        .observe(stats.storage_read_time.as_secs_f64());
+    APPLYING_CHUNKS_TIME_BREAKDOWN
+        .with_label_values(&[shard_id, "wasm"])
+        .observe(stats.wasm_time.as_secs_f64());
+    APPLYING_CHUNKS_RECEIPTS
+        .with_label_values(&[shard_id])
+        .observe(stats.receipts_processed as f64);
+}
+
 pub static BLOCK_PREPROCESSING_TIME: Lazy<Histogram> = Lazy::new(|| {
     try_create_histogram("near_block_preprocessing_time", "Time taken to preprocess blocks, only include the time when the preprocessing is successful")
         .unwrap()
@@ -70,9 +104,95 @@ pub static VALIDATOR_ACTIVE_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
 });
 pub static NUM_ORPHANS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_num_orphans", "Number of orphan blocks.").unwrap());
+pub static BLOCK_POOL_BLOCKS_BY_DISTANCE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_block_pool_blocks_by_distance",
+        "Number of blocks in the orphan and missing chunks pools by distance of their height from the head",
+        &["pool", "distance"],
+    )
+    .unwrap()
+});
+pub static BLOCK_POOL_BLOCKS_BY_PEER: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_block_pool_blocks_by_peer",
+        "Number of blocks in the orphan and missing chunks pools by the peer which sent them",
+        &["pool", "peer_id"],
+    )
+    .unwrap()
+});
+pub static BLOCK_POOL_EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_block_pool_evictions_total",
+        "Number of blocks evicted from, or not admitted to, the orphan and missing chunks pools by reason",
+        &["pool", "reason"],
+    )
+    .unwrap()
+});
+
+/// Label of given distance of a block's height from the head in
+/// `BLOCK_POOL_BLOCKS_BY_DISTANCE`.
+pub(crate) fn block_pool_distance_label(distance: i64) -> &'static str {
+    match distance {
+        i64::MIN..=0 => "not_above_head",
+        1 => "1",
+        2..=5 => "2_5",
+        6..=20 => "6_20",
+        21..=100 => "21_100",
+        _ => "over_100",
+    }
+}
 pub static HEADER_HEAD_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_header_head_height", "Height of the header head").unwrap()
 });
+pub static CHAIN_REORGS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chain_reorgs_total",
+        "Number of times the head or header head switched to a block which doesn't extend it",
+        &["head"],
+    )
+    .unwrap()
+});
+pub static CHAIN_REORGS_IN_EPOCH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_chain_reorgs_in_epoch",
+        "Number of reorgs of the head or header head since it entered the current epoch",
+        &["head"],
+    )
+    .unwrap()
+});
+pub static CHAIN_REORG_DEPTH: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chain_reorg_depth",
+        "Difference between the height of the old head and the height of the common ancestor of the old and new head",
+        &["head"],
+        Some(vec![1.0, 2.0, 3.0, 4.0, 5.0, 10.0, 20.0, 50.0, 100.0]),
+    )
+    .unwrap()
+});
+pub static CHAIN_REORG_LAST_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_chain_reorg_last_depth",
+        "Depth of the last reorg of the head or header head",
+        &["head"],
+    )
+    .unwrap()
+});
+pub static CHAIN_REORG_ROLLED_BACK_BLOCKS: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chain_reorg_rolled_back_blocks",
+        "Number of blocks of the old head's chain which are no longer on the chain after a reorg",
+        &["head"],
+        Some(vec![1.0, 2.0, 3.0, 4.0, 5.0, 10.0, 20.0, 50.0, 100.0]),
+    )
+    .unwrap()
+});
+pub static CHAIN_EVENTS_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_chain_events_dropped_total",
+        "Number of chain events not delivered to subscribers whose queue was full",
+    )
+    .unwrap()
+});
 pub static BOOT_TIME_SECONDS: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge(
         "near_boot_time_seconds",
@@ -88,6 +208,38 @@ pub static FORK_TAIL_HEIGHT: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_fork_tail_height", "Height of fork tail").unwrap());
 pub static GC_STOP_HEIGHT: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_gc_stop_height", "Target height of gc").unwrap());
+pub static PRUNE_TAIL_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_prune_tail_height",
+        "Height below which given class of data has been pruned",
+        &["data"],
+    )
+    .unwrap()
+});
+pub static GC_DEBT_HEIGHTS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_gc_debt_heights",
+        "Number of heights garbage collection still has to go through, for the canonical chain and for forks",
+        &["kind"],
+    )
+    .unwrap()
+});
+pub static GC_BLOCKS_CLEARED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_gc_blocks_cleared_total",
+        "Number of blocks removed by garbage collection from the canonical chain and from forks",
+        &["mode"],
+    )
+    .unwrap()
+});
+pub static GC_BUDGET_EXHAUSTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_gc_budget_exhausted_total",
+        "Number of garbage collection calls which stopped because they reached the limit of blocks or time",
+        &["limit"],
+    )
+    .unwrap()
+});
 pub static CHUNK_RECEIVED_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_chunk_receive_delay_seconds",
@@ -111,8 +263,8 @@ pub static BLOCK_MISSING_CHUNKS_DELAY: Lazy<Histogram> = Lazy::new(|| {
 pub static STATE_PART_ELAPSED: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_state_part_elapsed_sec",
-        "Time needed to create a state part",
-        &["shard_id"],
+        "Time needed to create a state part, by the method used to create it",
+        &["shard_id", "method"],
         Some(exponential_buckets(0.001, 1.6, 20).unwrap()),
     )
     .unwrap()
@@ -163,6 +315,12 @@ pub(crate) enum ReshardingStatus {
     BuildingState,
     /// The resharding is finished.
     Finished,
+    /// The resharding is paused by `resharding_config.paused`.
+    Paused,
+    /// The state of the child shards is built and being verified.
+    Verifying,
+    /// The resharding was cancelled by the client.
+    Cancelled,
 }
 
 impl From<ReshardingStatus> for i64 {
@@ -173,6 +331,9 @@ impl From<ReshardingStatus> for i64 {
             ReshardingStatus::Scheduled => 0,
             ReshardingStatus::BuildingState => 1,
             ReshardingStatus::Finished => 2,
+            ReshardingStatus::Paused => 3,
+            ReshardingStatus::Verifying => 4,
+            ReshardingStatus::Cancelled => 5,
         }
     }
 }
@@ -203,3 +364,48 @@ pub(crate) static RESHARDING_STATUS: Lazy<IntGaugeVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub(crate) static RESHARDING_PROGRESS: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "near_resharding_progress",
+        "Percentage of the entries of the parent shard processed by the resharding process.",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static RESHARDING_PROCESSED_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_resharding_processed_bytes",
+        "The number of bytes of the child shards written by the resharding process, including before a restart.",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static RESHARDING_TOTAL_ESTIMATED_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_resharding_total_estimated_bytes",
+        "Estimated number of bytes of the child shards the resharding process writes in total.",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static RESHARDING_BATCHES_PER_SECOND: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "near_resharding_batches_per_second",
+        "The number of batches committed per second since the resharding process started or resumed.",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static RESHARDING_ETA: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "near_resharding_eta_seconds",
+        "Estimated number of seconds until the resharding process finishes building the state, at the rate since it started or resumed.",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
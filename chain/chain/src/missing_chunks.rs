@@ -9,6 +9,8 @@ use std::collections::{
 };
 use tracing::{debug, warn};
 
+use crate::metrics;
+
 type BlockHash = CryptoHash;
 
 const MAX_BLOCKS_MISSING_CHUNKS: usize = 1024;
@@ -74,6 +76,11 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         self.blocks_waiting_for_chunks.len()
     }
 
+    /// Iterates over blocks which are waiting for chunks.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks_waiting_for_chunks.values()
+    }
+
     pub fn ready_blocks(&mut self) -> Vec<Block> {
         if self.blocks_ready_to_process.is_empty() {
             return Vec::new();
@@ -92,6 +99,7 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         // has gone horribly wrong, in which case these HashMaps will be lost anyways.
         if self.blocks_missing_chunks.len() >= MAX_BLOCKS_MISSING_CHUNKS {
             warn!(target: "chunks", "Not recording block with hash {} even though it is missing chunks. The missing chunks pool is full.", block_hash);
+            metrics::BLOCK_POOL_EVICTIONS.with_label_values(&["missing_chunks", "pool_full"]).inc();
             return;
         }
 
@@ -167,6 +175,9 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
             self.height_idx.keys().copied().take_while(|h| *h < height).collect();
         for h in heights_to_remove {
             if let Some(block_hashes) = self.height_idx.remove(&h) {
+                metrics::BLOCK_POOL_EVICTIONS
+                    .with_label_values(&["missing_chunks", "below_final_height"])
+                    .inc_by(block_hashes.len() as u64);
                 for block_hash in block_hashes {
                     self.blocks_waiting_for_chunks.remove(&block_hash);
                     if let Some(chunk_hashes) = self.blocks_missing_chunks.remove(&block_hash) {
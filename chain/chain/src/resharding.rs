@@ -4,29 +4,43 @@
 /// by the client_actor while the heavy resharding build_state_for_split_shards is done by SyncJobsActor
 /// so as to not affect client.
 use crate::metrics::{
-    ReshardingStatus, RESHARDING_BATCH_COUNT, RESHARDING_BATCH_SIZE, RESHARDING_STATUS,
+    ReshardingStatus, RESHARDING_BATCHES_PER_SECOND, RESHARDING_BATCH_COUNT, RESHARDING_BATCH_SIZE,
+    RESHARDING_ETA, RESHARDING_PROCESSED_BYTES, RESHARDING_PROGRESS, RESHARDING_STATUS,
+    RESHARDING_TOTAL_ESTIMATED_BYTES,
 };
 use crate::Chain;
+use borsh::{BorshDeserialize, BorshSerialize};
 use itertools::Itertools;
+use near_chain_configs::{MutableConfigValue, ReshardingConfig};
 use near_chain_primitives::error::Error;
 use near_primitives::errors::StorageError::StorageInconsistentState;
-use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout};
-use near_primitives::state::FlatStateValue;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::{account_id_to_shard_id, account_id_to_shard_uid, ShardLayout};
+use near_primitives::state::{FlatStateValue, ValueRef};
+use near_primitives::static_clock::StaticClock;
+use near_primitives::trie_key::trie_key_parsers::parse_account_id_from_raw_key;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{AccountId, ShardId, StateRoot};
+use near_primitives::views::SyncJobProgressView;
 use near_store::flat::{
-    store_helper, BlockInfo, FlatStorageManager, FlatStorageReadyStatus, FlatStorageStatus,
+    store_helper, BlockInfo, FlatStorageError, FlatStorageManager, FlatStorageReadyStatus,
+    FlatStorageStatus,
 };
 use near_store::split_state::get_delayed_receipts;
-use near_store::{ShardTries, ShardUId, Store, Trie, TrieDBStorage, TrieStorage};
-use std::collections::{HashMap, HashSet};
+use near_store::trie::mem::{MemTrieBuilder, MemTries};
+use near_store::{
+    get_delayed_receipt_indices, DBCol, ShardTries, ShardUId, Store, StoreUpdate,
+    TrieCachingStorage, TrieDBStorage, TrieIterator, TrieStorage,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::debug;
 
-// This is the approx batch size of the trie key, value pair entries that are written to the child shard trie.
-const RESHARDING_BATCH_MEMORY_LIMIT: bytesize::ByteSize = bytesize::ByteSize(300 * bytesize::MIB);
+// How often a paused resharding job checks whether it was resumed.
+const RESHARDING_PAUSE_CHECK_PERIOD: Duration = Duration::from_secs(1);
 
 /// StateSplitRequest has all the information needed to start a resharding job. This message is sent
 /// from ClientActor to SyncJobsActor. We do not want to stall the ClientActor with a long running
@@ -46,6 +60,10 @@ pub struct StateSplitRequest {
     // state root of the parent shardUId. This is different from block sync_hash
     pub state_root: StateRoot,
     pub next_epoch_shard_layout: ShardLayout,
+    // Throttling of the job, read before every batch so that changes apply to a running job.
+    pub config: MutableConfigValue<ReshardingConfig>,
+    // Checked between batches, the job stops once it's cancelled.
+    pub cancellation: ReshardingCancellation,
 }
 
 // Skip `runtime_adapter`, because it's a complex object that has complex logic
@@ -60,10 +78,36 @@ impl Debug for StateSplitRequest {
             .field("shard_uid", &self.shard_uid)
             .field("state_root", &self.state_root)
             .field("next_epoch_shard_layout", &self.next_epoch_shard_layout)
+            .field("config", &self.config.get())
+            .field("cancelled", &self.cancellation.is_cancelled())
             .finish()
     }
 }
 
+/// Lets the client cancel a resharding job whose result isn't needed anymore, e.g. because the
+/// block starting the epoch with the new shard layout is on an abandoned fork. The job fails
+/// with `Error::ReshardingCancelled` at the next batch.
+#[derive(Clone, Debug, Default)]
+pub struct ReshardingCancellation(Arc<AtomicBool>);
+
+impl ReshardingCancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::ReshardingCancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 // StateSplitResponse is the response sent from SyncJobsActor to ClientActor once resharding is completed.
 #[derive(actix::Message, Debug)]
 #[rtype(result = "()")]
@@ -94,32 +138,437 @@ fn get_checked_account_id_to_shard_uid_fn(
     }
 }
 
-// Format of the trie key, value pair that is used in tries.add_values_to_split_states() function
-type TrieEntry = (Vec<u8>, Option<Vec<u8>>);
+/// Progress of building the state of a child shard. It's saved in `DBCol::ReshardingProgress`
+/// together with every batch, so that a resharding job interrupted by a restart resumes from
+/// there instead of starting over.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct ReshardingCheckpoint {
+    /// The block hash whose state is split.
+    pub prev_hash: CryptoHash,
+    pub parent_shard_uid: ShardUId,
+    /// The last processed entry. It's the same for all child shards.
+    pub position: ReshardingPosition,
+    /// State root of the child shard. While trie nodes are written, it's the root of the
+    /// complete trie, which is only partially on disk.
+    pub state_root: StateRoot,
+    /// Number of trie nodes and delayed receipts processed so far.
+    pub processed_entries: u64,
+    /// Number of trie nodes and delayed receipts to process in total.
+    pub total_entries: u64,
+    /// Number of bytes of trie nodes and values written so far.
+    pub processed_bytes: u64,
+    /// Estimated number of bytes of trie nodes and values to write in total.
+    pub total_bytes: u64,
+}
+
+/// Position of a resharding job, which first writes the trie nodes of the child shards and
+/// then moves the delayed receipts.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum ReshardingPosition {
+    /// This many trie nodes were written, counting the nodes of all child shards in the order
+    /// of the shards and of `MemTries::iter_serialized_nodes`.
+    TrieNodes(u64),
+    /// All trie nodes and the delayed receipts before this index were processed.
+    DelayedReceipts(u64),
+}
 
-struct TrieUpdateBatch {
-    entries: Vec<TrieEntry>,
-    size: u64,
+// The tries of the child shards, built in memory.
+struct ChildMemTries {
+    shard_uid: ShardUId,
+    mem_tries: MemTries,
+    state_root: StateRoot,
 }
 
-// Function to return batches of trie key, value pairs from flat storage iter. We return None at the end of iter.
-// The batch size is roughly RESHARDING_BATCH_MEMORY_LIMIT (300 MB)
-fn get_trie_update_batch(
-    iter: &mut impl Iterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
-) -> Option<TrieUpdateBatch> {
-    let mut size: u64 = 0;
-    let mut entries = Vec::new();
-    while let Some((key, value)) = iter.next() {
-        size += key.len() as u64 + value.as_ref().map_or(0, |v| v.len() as u64);
-        entries.push((key, value));
-        if size > RESHARDING_BATCH_MEMORY_LIMIT.as_u64() {
-            break;
+// Calls `f` for every entry of the state of the parent shard which belongs to an account, in
+// the order of keys. Flat storage entries and delta entries are both sorted by key, so they are
+// merged into a single sorted sequence. Delayed receipts have no account and are skipped, as
+// they are moved to the child shards once the rest of the state is built.
+fn for_each_parent_entry(
+    flat_state_entries: impl Iterator<Item = Result<(Vec<u8>, FlatStateValue), FlatStorageError>>,
+    delta_entries: Vec<(Vec<u8>, Option<FlatStateValue>)>,
+    mut f: impl FnMut(AccountId, Vec<u8>, FlatStateValue) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut visit = |key: Vec<u8>, value: FlatStateValue| -> Result<(), Error> {
+        match parse_account_id_from_raw_key(&key).map_err(|err| {
+            StorageInconsistentState(format!("failed to parse trie key {key:?}: {err}"))
+        })? {
+            Some(account_id) => f(account_id, key, value),
+            None => Ok(()),
+        }
+    };
+
+    let mut delta_entries = delta_entries.into_iter().peekable();
+    for entry in flat_state_entries {
+        let (key, value) = entry.map_err(|err| StorageInconsistentState(err.to_string()))?;
+        // Delta entries override the flat storage entries with the same key.
+        let mut value = Some(value);
+        while let Some((delta_key, delta_value)) =
+            delta_entries.next_if(|(delta_key, _)| delta_key <= &key)
+        {
+            if delta_key == key {
+                value = delta_value;
+            } else if let Some(delta_value) = delta_value {
+                visit(delta_key, delta_value)?;
+            }
+        }
+        if let Some(value) = value {
+            visit(key, value)?;
         }
     }
-    if entries.is_empty() {
-        None
-    } else {
-        Some(TrieUpdateBatch { entries, size })
+    for (key, value) in delta_entries {
+        if let Some(value) = value {
+            visit(key, value)?;
+        }
+    }
+    Ok(())
+}
+
+// Builds the tries of the child shards in memory from the state of the parent shard, adding
+// every entry to the trie of the child shard of its account.
+fn build_child_mem_tries<'a>(
+    tries: &ShardTries,
+    flat_state_entries: impl Iterator<Item = Result<(Vec<u8>, FlatStateValue), FlatStorageError>>,
+    delta_entries: Vec<(Vec<u8>, Option<FlatStateValue>)>,
+    new_shards: &[ShardUId],
+    account_id_to_shard_uid: &(dyn Fn(&AccountId) -> ShardUId + 'a),
+    cancellation: &ReshardingCancellation,
+) -> Result<Vec<ChildMemTries>, Error> {
+    let mut builders: HashMap<_, _> = new_shards
+        .iter()
+        .map(|shard_uid| (*shard_uid, MemTrieBuilder::new(tries.new_mem_tries(*shard_uid))))
+        .collect();
+    for_each_parent_entry(flat_state_entries, delta_entries, |account_id, key, value| {
+        cancellation.check()?;
+        builders
+            .get_mut(&account_id_to_shard_uid(&account_id))
+            .unwrap()
+            .add(&key, value)
+            .map_err(|err| Error::Other(format!("failed to build child mem-trie: {err}")))
+    })?;
+
+    let mut child_tries = Vec::new();
+    for shard_uid in new_shards {
+        // The height is only used for garbage collection of the in-memory tries, which are
+        // dropped once written to disk.
+        let (mem_tries, state_root) = builders
+            .remove(shard_uid)
+            .unwrap()
+            .finish(0)
+            .map_err(|err| Error::Other(format!("failed to build child mem-trie: {err}")))?;
+        child_tries.push(ChildMemTries { shard_uid: *shard_uid, mem_tries, state_root });
+    }
+    Ok(child_tries)
+}
+
+// Writes the nodes of the child tries, along with their values, to disk in batches. Nodes
+// written before a restart, as recorded in `progress`, are skipped.
+fn write_child_tries(
+    tries: &ShardTries,
+    parent_trie_storage: &TrieDBStorage,
+    child_tries: &[ChildMemTries],
+    progress: &mut ReshardingProgress<'_>,
+    throttle: &mut ReshardingThrottle,
+) -> Result<(), Error> {
+    let shard_uid = progress.parent_shard_uid.to_string();
+    let mut num_written = match progress.position {
+        Some(ReshardingPosition::TrieNodes(num_written)) => num_written,
+        _ => 0,
+    };
+    let state_roots: HashMap<_, _> =
+        child_tries.iter().map(|child| (child.shard_uid, child.state_root)).collect();
+    let mut nodes = child_tries
+        .iter()
+        .flat_map(|child| {
+            child
+                .mem_tries
+                .iter_serialized_nodes(&child.state_root)
+                .map(move |node| (child.shard_uid, node))
+        })
+        .skip(num_written as usize)
+        .peekable();
+    while nodes.peek().is_some() {
+        let mut store_update = tries.store_update();
+        let mut size = 0;
+        let mut num_entries = 0;
+        let batch_size = throttle.batch_size().as_u64();
+        for (child_shard_uid, node) in nodes.by_ref() {
+            let key =
+                TrieCachingStorage::get_key_from_shard_uid_and_hash(child_shard_uid, &node.hash);
+            store_update.increment_refcount(DBCol::State, &key, &node.data);
+            size += node.data.len() as u64;
+            if let Some(value) = node.value {
+                let (value_hash, value) = match value {
+                    FlatStateValue::Ref(value_ref) => (
+                        value_ref.hash,
+                        parent_trie_storage.retrieve_raw_bytes(&value_ref.hash)?.to_vec(),
+                    ),
+                    FlatStateValue::Inlined(value) => (hash(&value), value),
+                };
+                let key = TrieCachingStorage::get_key_from_shard_uid_and_hash(
+                    child_shard_uid,
+                    &value_hash,
+                );
+                store_update.increment_refcount(DBCol::State, &key, &value);
+                size += value.len() as u64;
+            }
+            num_entries += 1;
+            if size > batch_size {
+                break;
+            }
+        }
+        num_written += num_entries;
+        progress.save(
+            &mut store_update,
+            ReshardingPosition::TrieNodes(num_written),
+            num_entries,
+            size,
+            &state_roots,
+        )?;
+        store_update.commit()?;
+        RESHARDING_BATCH_COUNT.with_label_values(&[&shard_uid]).inc();
+        RESHARDING_BATCH_SIZE.with_label_values(&[&shard_uid]).add(size as i64);
+        throttle.wait_after_batch(size)?;
+    }
+    Ok(())
+}
+
+// Tracks the progress of a resharding job, saves it together with every batch, exports it as
+// metrics and reports it to the caller of the job. The ETA is based on the rate of processing
+// since the job started or resumed.
+struct ReshardingProgress<'a> {
+    prev_hash: CryptoHash,
+    parent_shard_uid: ShardUId,
+    // None if nothing was processed yet.
+    position: Option<ReshardingPosition>,
+    processed_entries: u64,
+    total_entries: u64,
+    processed_bytes: u64,
+    total_bytes: u64,
+    // Start of the current run of the job and entries and batches processed since then.
+    run_start: Instant,
+    run_entries: u64,
+    run_batches: u64,
+    on_progress: &'a dyn Fn(SyncJobProgressView),
+}
+
+impl<'a> ReshardingProgress<'a> {
+    // Loads the progress saved before a restart together with the state roots of the child
+    // shards. The progress is only used if it's saved for all the child shards at the same
+    // position, and for the same split. Otherwise the job starts over.
+    fn load(
+        store: &Store,
+        prev_hash: CryptoHash,
+        parent_shard_uid: ShardUId,
+        child_shard_uids: &[ShardUId],
+        on_progress: &'a dyn Fn(SyncJobProgressView),
+    ) -> Result<Option<(Self, HashMap<ShardUId, StateRoot>)>, Error> {
+        let mut state_roots = HashMap::new();
+        let mut first_checkpoint: Option<ReshardingCheckpoint> = None;
+        for child_shard_uid in child_shard_uids {
+            let Some(checkpoint) = store.get_ser::<ReshardingCheckpoint>(
+                DBCol::ReshardingProgress,
+                &child_shard_uid.to_bytes(),
+            )?
+            else {
+                return Ok(None);
+            };
+            if checkpoint.prev_hash != prev_hash
+                || checkpoint.parent_shard_uid != parent_shard_uid
+                || first_checkpoint.as_ref().map_or(false, |first| {
+                    first.position != checkpoint.position
+                        || first.processed_entries != checkpoint.processed_entries
+                })
+            {
+                return Ok(None);
+            }
+            state_roots.insert(*child_shard_uid, checkpoint.state_root);
+            first_checkpoint.get_or_insert(checkpoint);
+        }
+        let Some(checkpoint) = first_checkpoint else { return Ok(None) };
+        let progress = Self {
+            prev_hash,
+            parent_shard_uid,
+            position: Some(checkpoint.position),
+            processed_entries: checkpoint.processed_entries,
+            total_entries: checkpoint.total_entries,
+            processed_bytes: checkpoint.processed_bytes,
+            total_bytes: checkpoint.total_bytes,
+            run_start: StaticClock::instant(),
+            run_entries: 0,
+            run_batches: 0,
+            on_progress,
+        };
+        Ok(Some((progress, state_roots)))
+    }
+
+    fn new(
+        prev_hash: CryptoHash,
+        parent_shard_uid: ShardUId,
+        on_progress: &'a dyn Fn(SyncJobProgressView),
+    ) -> Self {
+        Self {
+            prev_hash,
+            parent_shard_uid,
+            position: None,
+            processed_entries: 0,
+            // Known once the child tries are built.
+            total_entries: 0,
+            processed_bytes: 0,
+            total_bytes: 0,
+            run_start: StaticClock::instant(),
+            run_entries: 0,
+            run_batches: 0,
+            on_progress,
+        }
+    }
+
+    // Starts measuring the rate of processing anew, e.g. once the slow preparation before
+    // the first batch is done.
+    fn start_run(&mut self) {
+        self.run_start = StaticClock::instant();
+        self.run_entries = 0;
+        self.run_batches = 0;
+    }
+
+    // Records a batch of `num_entries` entries and `num_bytes` bytes ending at `position` and
+    // saves the progress in the `store_update` of the batch.
+    fn save(
+        &mut self,
+        store_update: &mut StoreUpdate,
+        position: ReshardingPosition,
+        num_entries: u64,
+        num_bytes: u64,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+    ) -> Result<(), Error> {
+        self.processed_entries += num_entries;
+        self.processed_bytes += num_bytes;
+        self.run_entries += num_entries;
+        self.run_batches += 1;
+        for (child_shard_uid, state_root) in state_roots {
+            let checkpoint = ReshardingCheckpoint {
+                prev_hash: self.prev_hash,
+                parent_shard_uid: self.parent_shard_uid,
+                position: position.clone(),
+                state_root: *state_root,
+                processed_entries: self.processed_entries,
+                total_entries: self.total_entries,
+                processed_bytes: self.processed_bytes,
+                total_bytes: self.total_bytes,
+            };
+            store_update.set_ser(
+                DBCol::ReshardingProgress,
+                &child_shard_uid.to_bytes(),
+                &checkpoint,
+            )?;
+        }
+        self.position = Some(position);
+        self.export_metrics();
+        (self.on_progress)(SyncJobProgressView {
+            done: self.processed_entries,
+            total: self.total_entries,
+            bytes_written: self.processed_bytes,
+        });
+        Ok(())
+    }
+
+    fn export_metrics(&self) {
+        let shard_uid = self.parent_shard_uid.to_string();
+        let percent = if self.total_entries == 0 {
+            100.0
+        } else {
+            (100.0 * self.processed_entries as f64 / self.total_entries as f64).min(100.0)
+        };
+        RESHARDING_PROGRESS.with_label_values(&[&shard_uid]).set(percent);
+        RESHARDING_PROCESSED_BYTES
+            .with_label_values(&[&shard_uid])
+            .set(self.processed_bytes as i64);
+        RESHARDING_TOTAL_ESTIMATED_BYTES
+            .with_label_values(&[&shard_uid])
+            .set(self.total_bytes as i64);
+        // The rate isn't known before the first batch of the run.
+        if self.run_entries == 0 {
+            return;
+        }
+        let elapsed = StaticClock::instant().duration_since(self.run_start).as_secs_f64();
+        let remaining_entries = self.total_entries.saturating_sub(self.processed_entries);
+        RESHARDING_BATCHES_PER_SECOND
+            .with_label_values(&[&shard_uid])
+            .set(self.run_batches as f64 / elapsed);
+        RESHARDING_ETA
+            .with_label_values(&[&shard_uid])
+            .set(elapsed * remaining_entries as f64 / self.run_entries as f64);
+    }
+}
+
+// Slows the resharding job down according to the resharding config, which is read anew before
+// every batch. Waits between batches for `batch_delay`, for as long as it takes to keep the
+// average write rate under `max_bytes_per_second` and for as long as the job is paused, and
+// fails once the job is cancelled.
+struct ReshardingThrottle {
+    config: MutableConfigValue<ReshardingConfig>,
+    cancellation: ReshardingCancellation,
+    shard_uid: ShardUId,
+    // Start of the period the write rate is averaged over and bytes written since then.
+    rate_period_start: Instant,
+    rate_period_bytes: u64,
+}
+
+impl ReshardingThrottle {
+    fn new(
+        config: MutableConfigValue<ReshardingConfig>,
+        cancellation: ReshardingCancellation,
+        shard_uid: ShardUId,
+    ) -> Self {
+        Self {
+            config,
+            cancellation,
+            shard_uid,
+            rate_period_start: StaticClock::instant(),
+            rate_period_bytes: 0,
+        }
+    }
+
+    fn batch_size(&self) -> bytesize::ByteSize {
+        self.config.get().batch_size
+    }
+
+    // Called after a batch of `size` bytes is committed.
+    fn wait_after_batch(&mut self, size: u64) -> Result<(), Error> {
+        let config = self.config.get();
+        self.rate_period_bytes += size;
+        let mut wait = config.batch_delay;
+        if let Some(max_bytes_per_second) = config.max_bytes_per_second {
+            let min_elapsed = Duration::from_secs_f64(
+                self.rate_period_bytes as f64 / max_bytes_per_second.as_u64() as f64,
+            );
+            let elapsed = StaticClock::instant().duration_since(self.rate_period_start);
+            wait = wait.max(min_elapsed.saturating_sub(elapsed));
+        }
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        self.wait_while_paused()
+    }
+
+    fn wait_while_paused(&mut self) -> Result<(), Error> {
+        if !self.config.get().paused {
+            return self.cancellation.check();
+        }
+        let shard_uid = self.shard_uid.to_string();
+        tracing::info!(target: "resharding", %shard_uid, "Resharding paused");
+        RESHARDING_STATUS.with_label_values(&[&shard_uid]).set(ReshardingStatus::Paused.into());
+        while self.config.get().paused && !self.cancellation.is_cancelled() {
+            std::thread::sleep(RESHARDING_PAUSE_CHECK_PERIOD);
+        }
+        self.cancellation.check()?;
+        tracing::info!(target: "resharding", %shard_uid, "Resharding resumed");
+        RESHARDING_STATUS
+            .with_label_values(&[&shard_uid])
+            .set(ReshardingStatus::BuildingState.into());
+        // The time spent paused must not allow a burst of writes afterwards.
+        self.rate_period_start = StaticClock::instant();
+        self.rate_period_bytes = 0;
+        Ok(())
     }
 }
 
@@ -129,27 +578,143 @@ fn apply_delayed_receipts<'a>(
     orig_state_root: StateRoot,
     state_roots: HashMap<ShardUId, StateRoot>,
     account_id_to_shard_uid: &(dyn Fn(&AccountId) -> ShardUId + 'a),
+    progress: &mut ReshardingProgress<'_>,
+    throttle: &mut ReshardingThrottle,
 ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
     let orig_trie_update = tries.new_trie_update_view(orig_shard_uid, orig_state_root);
 
-    let mut start_index = None;
+    let mut start_index = match progress.position {
+        Some(ReshardingPosition::DelayedReceipts(index)) => Some(index),
+        _ => None,
+    };
     let mut new_state_roots = state_roots;
     while let Some((next_index, receipts)) =
-        get_delayed_receipts(&orig_trie_update, start_index, RESHARDING_BATCH_MEMORY_LIMIT)?
+        get_delayed_receipts(&orig_trie_update, start_index, throttle.batch_size())?
     {
-        let (store_update, updated_state_roots) = tries.apply_delayed_receipts_to_split_states(
-            &new_state_roots,
-            &receipts,
-            account_id_to_shard_uid,
-        )?;
+        let (mut store_update, updated_state_roots) = tries
+            .apply_delayed_receipts_to_split_states(
+                &new_state_roots,
+                &receipts,
+                account_id_to_shard_uid,
+            )?;
         new_state_roots = updated_state_roots;
         start_index = Some(next_index);
+        // Delayed receipts are few, so their size isn't counted.
+        progress.save(
+            &mut store_update,
+            ReshardingPosition::DelayedReceipts(next_index),
+            receipts.len() as u64,
+            0,
+            &new_state_roots,
+        )?;
         store_update.commit()?;
+        throttle.wait_after_batch(0)?;
     }
 
     Ok(new_state_roots)
 }
 
+// Returns the next entry of a child trie which belongs to an account, skipping delayed receipts.
+fn next_account_entry(iter: &mut TrieIterator) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+    for item in iter {
+        let (key, value) = item?;
+        if parse_account_id_from_raw_key(&key)
+            .map_err(|err| {
+                StorageInconsistentState(format!("failed to parse trie key {key:?}: {err}"))
+            })?
+            .is_some()
+        {
+            return Ok(Some((key, value)));
+        }
+    }
+    Ok(None)
+}
+
+fn num_delayed_receipts(
+    tries: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: StateRoot,
+) -> Result<u64, Error> {
+    let indices = get_delayed_receipt_indices(&tries.new_trie_update_view(shard_uid, state_root))?;
+    Ok(indices.next_available_index - indices.first_index)
+}
+
+// Checks the state of the child shards as written to disk against the state of the parent
+// shard, independently of how the child tries were built. The entries of the parent shard and
+// of all the child tries are iterated in the order of keys side by side: every entry of the
+// parent shard must be the next entry of the trie of the child shard of its account, with the
+// same value, and no child trie may have any other entries, except for the delayed receipts.
+// Those are moved by receiver rather than by key, so only their total number is compared.
+fn verify_split_state<'a>(
+    tries: &ShardTries,
+    flat_state_entries: impl Iterator<Item = Result<(Vec<u8>, FlatStateValue), FlatStorageError>>,
+    delta_entries: Vec<(Vec<u8>, Option<FlatStateValue>)>,
+    parent_shard_uid: ShardUId,
+    parent_state_root: StateRoot,
+    state_roots: &HashMap<ShardUId, StateRoot>,
+    account_id_to_shard_uid: &(dyn Fn(&AccountId) -> ShardUId + 'a),
+    cancellation: &ReshardingCancellation,
+) -> Result<(), Error> {
+    let child_tries: Vec<_> = state_roots
+        .iter()
+        .map(|(shard_uid, state_root)| {
+            (*shard_uid, tries.get_trie_for_shard(*shard_uid, *state_root))
+        })
+        .collect();
+    let mut child_iters = HashMap::new();
+    for (shard_uid, trie) in &child_tries {
+        child_iters.insert(*shard_uid, trie.iter()?);
+    }
+
+    let mut num_entries: u64 = 0;
+    for_each_parent_entry(flat_state_entries, delta_entries, |account_id, key, value| {
+        cancellation.check()?;
+        let child_shard_uid = account_id_to_shard_uid(&account_id);
+        let iter = child_iters.get_mut(&child_shard_uid).unwrap();
+        match next_account_entry(iter)? {
+            Some((child_key, child_value)) if child_key == key => {
+                if ValueRef::new(&child_value) != value.to_value_ref() {
+                    return Err(Error::InvalidSplitState(format!(
+                        "key {key:?} has a different value in child shard {child_shard_uid} than in parent shard {parent_shard_uid}"
+                    )));
+                }
+            }
+            Some((child_key, _)) if child_key < key => {
+                return Err(Error::InvalidSplitState(format!(
+                    "key {child_key:?} in child shard {child_shard_uid} isn't in parent shard {parent_shard_uid}"
+                )));
+            }
+            _ => {
+                return Err(Error::InvalidSplitState(format!(
+                    "key {key:?} of parent shard {parent_shard_uid} is missing in child shard {child_shard_uid}"
+                )));
+            }
+        }
+        num_entries += 1;
+        Ok(())
+    })?;
+    for (child_shard_uid, iter) in &mut child_iters {
+        if let Some((child_key, _)) = next_account_entry(iter)? {
+            return Err(Error::InvalidSplitState(format!(
+                "key {child_key:?} in child shard {child_shard_uid} isn't in parent shard {parent_shard_uid}"
+            )));
+        }
+    }
+
+    let parent_delayed_receipts = num_delayed_receipts(tries, parent_shard_uid, parent_state_root)?;
+    let mut child_delayed_receipts = 0;
+    for (child_shard_uid, state_root) in state_roots {
+        child_delayed_receipts += num_delayed_receipts(tries, *child_shard_uid, *state_root)?;
+    }
+    if parent_delayed_receipts != child_delayed_receipts {
+        return Err(Error::InvalidSplitState(format!(
+            "parent shard {parent_shard_uid} has {parent_delayed_receipts} delayed receipts but its child shards have {child_delayed_receipts}"
+        )));
+    }
+    tracing::info!(target: "resharding", %parent_shard_uid, num_entries, num_delayed_receipts = parent_delayed_receipts, "Verified the state of the child shards");
+    Ok(())
+}
+
 // function to set up flat storage status to Ready after a resharding event
 // TODO(resharding) : Consolidate this with setting up flat storage during state sync logic
 fn set_flat_storage_state(
@@ -169,13 +734,108 @@ fn set_flat_storage_state(
     Ok(())
 }
 
+/// Number and size of the flat storage entries which would be assigned to a shard.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShardSizeEstimate {
+    pub num_keys: u64,
+    pub key_bytes: u64,
+    pub value_bytes: u64,
+}
+
+impl ShardSizeEstimate {
+    fn add(&mut self, key: &[u8], value: &FlatStateValue) {
+        self.num_keys += 1;
+        self.key_bytes += key.len() as u64;
+        self.value_bytes += value.value_len() as u64;
+    }
+}
+
+/// Expected state of the child shards of a shard, see `estimate_split_shards`.
+#[derive(Debug)]
+pub struct SplitShardsEstimate {
+    pub parent_shard_uid: ShardUId,
+    pub child_shards: BTreeMap<ShardUId, ShardSizeEstimate>,
+    /// Delayed receipts of the parent shard. They are not split by key but
+    /// moved to the shard of their receiver once the rest of the state is
+    /// built.
+    pub delayed_receipts: ShardSizeEstimate,
+}
+
+/// Estimates the number of keys and the size of the state of each child
+/// shard of `shard_uid` under `next_shard_layout`, without writing anything.
+/// The state is read from the flat storage of the parent shard as of its flat
+/// head, so the estimate doesn't include changes after the flat head.
+pub fn estimate_split_shards(
+    store: &Store,
+    shard_uid: ShardUId,
+    next_shard_layout: &ShardLayout,
+) -> Result<SplitShardsEstimate, Error> {
+    let shard_id = shard_uid.shard_id();
+    let new_shards =
+        next_shard_layout.get_split_shard_uids(shard_id).ok_or(Error::InvalidShardId(shard_id))?;
+    let account_id_to_shard_uid = get_checked_account_id_to_shard_uid_fn(
+        shard_uid,
+        new_shards.clone(),
+        next_shard_layout.clone(),
+    );
+    let mut estimate = SplitShardsEstimate {
+        parent_shard_uid: shard_uid,
+        child_shards: new_shards.into_iter().map(|uid| (uid, Default::default())).collect(),
+        delayed_receipts: Default::default(),
+    };
+    for entry in store_helper::iter_flat_state_entries(shard_uid, store, None, None) {
+        let (key, value) = entry.map_err(|err| StorageInconsistentState(err.to_string()))?;
+        let account_id = parse_account_id_from_raw_key(&key).map_err(|err| {
+            StorageInconsistentState(format!("failed to parse trie key {key:?}: {err}"))
+        })?;
+        let shard_estimate = match account_id {
+            Some(account_id) => {
+                estimate.child_shards.get_mut(&account_id_to_shard_uid(&account_id)).unwrap()
+            }
+            None => &mut estimate.delayed_receipts,
+        };
+        shard_estimate.add(&key, &value);
+    }
+    Ok(estimate)
+}
+
+/// Returns the shard layout resulting from splitting the shards of
+/// `shard_layout` at `new_boundary_accounts`, which can be used to estimate a
+/// proposed split with `estimate_split_shards`.  Every shard is split into the
+/// shards whose range of accounts falls into its own range.
+pub fn get_split_shard_layout(
+    shard_layout: &ShardLayout,
+    new_boundary_accounts: &[AccountId],
+) -> Result<ShardLayout, Error> {
+    let Some(boundary_accounts) = shard_layout.boundary_accounts() else {
+        return Err(Error::Other(
+            "only shard layouts with boundary accounts can be split".to_string(),
+        ));
+    };
+    let mut boundary_accounts = boundary_accounts.to_vec();
+    boundary_accounts.extend_from_slice(new_boundary_accounts);
+    boundary_accounts.sort();
+    boundary_accounts.dedup();
+
+    let mut shards_split_map = vec![vec![]; shard_layout.num_shards() as usize];
+    for shard_id in 0..=boundary_accounts.len() {
+        // The first account of the range of the shard determines its parent.
+        let parent_shard_id = match shard_id {
+            0 => 0,
+            _ => account_id_to_shard_id(&boundary_accounts[shard_id - 1], shard_layout),
+        };
+        shards_split_map[parent_shard_id as usize].push(shard_id as ShardId);
+    }
+    Ok(ShardLayout::v1(boundary_accounts, Some(shards_split_map), shard_layout.version() + 1))
+}
+
 impl Chain {
     pub fn build_state_for_split_shards_preprocessing(
         &self,
         sync_hash: &CryptoHash,
         shard_id: ShardId,
         state_split_scheduler: &dyn Fn(StateSplitRequest),
-    ) -> Result<(), Error> {
+    ) -> Result<ReshardingCancellation, Error> {
         let block_header = self.get_block_header(sync_hash)?;
         let shard_layout = self.epoch_manager.get_shard_layout(block_header.epoch_id())?;
         let next_epoch_shard_layout =
@@ -188,6 +848,7 @@ impl Chain {
         let prev_prev_hash = prev_block_header.prev_hash();
         let state_root = *self.get_chunk_extra(&prev_hash, &shard_uid)?.state_root();
 
+        let cancellation = ReshardingCancellation::default();
         state_split_scheduler(StateSplitRequest {
             tries: Arc::new(self.runtime_adapter.get_tries()),
             sync_hash: *sync_hash,
@@ -196,26 +857,40 @@ impl Chain {
             shard_uid,
             state_root,
             next_epoch_shard_layout,
+            config: self.resharding_config.clone(),
+            cancellation: cancellation.clone(),
         });
 
         RESHARDING_STATUS
             .with_label_values(&[&shard_uid.to_string()])
             .set(ReshardingStatus::Scheduled.into());
 
-        Ok(())
+        Ok(cancellation)
     }
 
+    /// Splits the state of the parent shard, reporting the progress to `on_progress` after
+    /// every batch.
     pub fn build_state_for_split_shards(
         state_split_request: StateSplitRequest,
+        on_progress: &dyn Fn(SyncJobProgressView),
     ) -> StateSplitResponse {
-        let shard_id = state_split_request.shard_uid.shard_id();
+        let shard_uid = state_split_request.shard_uid;
+        let shard_id = shard_uid.shard_id();
         let sync_hash = state_split_request.sync_hash;
-        let new_state_roots = Self::build_state_for_split_shards_impl(state_split_request);
+        let new_state_roots =
+            Self::build_state_for_split_shards_impl(state_split_request, on_progress);
+        if let Err(Error::ReshardingCancelled) = new_state_roots {
+            tracing::info!(target: "resharding", %shard_uid, %sync_hash, "Resharding cancelled");
+            RESHARDING_STATUS
+                .with_label_values(&[&shard_uid.to_string()])
+                .set(ReshardingStatus::Cancelled.into());
+        }
         StateSplitResponse { shard_id, sync_hash, new_state_roots }
     }
 
     fn build_state_for_split_shards_impl(
         state_split_request: StateSplitRequest,
+        on_progress: &dyn Fn(SyncJobProgressView),
     ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
         let StateSplitRequest {
             tries,
@@ -224,83 +899,118 @@ impl Chain {
             shard_uid,
             state_root,
             next_epoch_shard_layout,
+            config,
+            cancellation,
             ..
         } = state_split_request;
 
         RESHARDING_STATUS
             .with_label_values(&[&shard_uid.to_string()])
             .set(ReshardingStatus::BuildingState.into());
+        let mut throttle = ReshardingThrottle::new(config, cancellation.clone(), shard_uid);
+        // A job may be scheduled while resharding is paused, or cancelled before it started.
+        throttle.wait_while_paused()?;
 
         let shard_id = shard_uid.shard_id();
         let new_shards = next_epoch_shard_layout
             .get_split_shard_uids(shard_id)
             .ok_or(Error::InvalidShardId(shard_id))?;
-        let mut state_roots: HashMap<_, _> =
-            new_shards.iter().map(|shard_uid| (*shard_uid, Trie::EMPTY_ROOT)).collect();
+        let saved_progress = ReshardingProgress::load(
+            &tries.get_store(),
+            prev_hash,
+            shard_uid,
+            &new_shards,
+            on_progress,
+        )?;
 
-        // Build the required iterator from flat storage and delta changes. Note that we are
-        // working with iterators as we don't want to have all the state in memory at once.
-        //
-        // Iterator is built by chaining the following:
-        // 1. Flat storage iterator from the snapshot state as of `prev_prev_hash`.
-        // 2. Delta changes iterator from the snapshot state as of `prev_hash`.
+        // The state of the parent shard is read from flat storage and delta changes:
+        // 1. Flat storage entries from the snapshot state as of `prev_prev_hash`.
+        // 2. Delta changes from the snapshot state as of `prev_hash`.
         //
         // The snapshot when created has the flat head as of `prev_prev_hash`, i.e. the hash as
-        // of the second last block of the previous epoch. Hence we need to append the detla
+        // of the second last block of the previous epoch. Hence we need to apply the delta
         // changes on top of it.
         let (snapshot_store, flat_storage_manager) = tries.get_state_snapshot(&prev_prev_hash)?;
         let flat_storage_chunk_view =
             flat_storage_manager.chunk_view(shard_uid, prev_prev_hash).ok_or_else(|| {
                 StorageInconsistentState("Chunk view missing for snapshot flat storage".to_string())
             })?;
-        let flat_storage_iter =
-            flat_storage_chunk_view.iter_flat_state_entries(None, None).map(|entry| {
-                let (key, value) = entry.unwrap();
-                (key, Some(value))
-            });
 
         let delta = store_helper::get_delta_changes(&snapshot_store, shard_uid, prev_hash)
             .map_err(|e| StorageInconsistentState(e.to_string()))?
             .ok_or_else(|| {
                 StorageInconsistentState("Delta missing for snapshot flat storage".to_string())
             })?;
-        let delta_iter = delta.0.into_iter();
+        let mut delta_entries: Vec<_> = delta.0.into_iter().collect();
+        delta_entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let verification_delta_entries = delta_entries.clone();
 
-        let trie_storage = TrieDBStorage::new(tries.get_store(), shard_uid);
-        let flat_state_value_to_trie_value_fn = |value: FlatStateValue| -> Vec<u8> {
-            match value {
-                FlatStateValue::Ref(ref_value) => {
-                    trie_storage.retrieve_raw_bytes(&ref_value.hash).unwrap().to_vec()
-                }
-                FlatStateValue::Inlined(inline_value) => inline_value,
+        // function to map account id to shard uid in range of child shards
+        let checked_account_id_to_shard_uid = get_checked_account_id_to_shard_uid_fn(
+            shard_uid,
+            new_shards.clone(),
+            next_epoch_shard_layout,
+        );
+
+        let (mut progress, mut state_roots) = match saved_progress {
+            Some((progress, state_roots)) => {
+                tracing::info!(target: "resharding", %shard_uid, position = ?progress.position, processed_entries = progress.processed_entries, total_entries = progress.total_entries, "Resuming resharding");
+                (progress, state_roots)
             }
+            None => (ReshardingProgress::new(prev_hash, shard_uid, on_progress), HashMap::new()),
         };
-        let mut iter = flat_storage_iter.chain(delta_iter).map(
-            move |(key, value)| -> (Vec<u8>, Option<Vec<u8>>) {
-                (key, value.map(flat_state_value_to_trie_value_fn))
-            },
-        );
 
-        // function to map account id to shard uid in range of child shards
-        let checked_account_id_to_shard_uid =
-            get_checked_account_id_to_shard_uid_fn(shard_uid, new_shards, next_epoch_shard_layout);
-
-        // Once we build the iterator, we break it into batches using the get_trie_update_batch function.
-        while let Some(batch) = get_trie_update_batch(&mut iter) {
-            let TrieUpdateBatch { entries, size } = batch;
-            // TODO(#9435): This is highly inefficient as for each key in the batch, we are parsing the account_id
-            // A better way would be to use the boundary account to construct the from and to key range for flat storage iterator
-            let (store_update, new_state_roots) = tries.add_values_to_split_states(
-                &state_roots,
-                entries,
+        if !matches!(progress.position, Some(ReshardingPosition::DelayedReceipts(_))) {
+            // The child tries are built in memory, which is deterministic, so after a restart
+            // they are built again and only the nodes which weren't written yet are written.
+            let timer = Instant::now();
+            let child_tries = build_child_mem_tries(
+                &tries,
+                flat_storage_chunk_view.iter_flat_state_entries(None, None),
+                delta_entries,
+                &new_shards,
                 &checked_account_id_to_shard_uid,
+                &cancellation,
+            )?;
+            let new_state_roots: HashMap<_, _> =
+                child_tries.iter().map(|child| (child.shard_uid, child.state_root)).collect();
+            tracing::info!(target: "resharding", %shard_uid, ?new_state_roots, elapsed = ?timer.elapsed(), "Built the state of the child shards in memory");
+            if progress.position.is_some() && new_state_roots != state_roots {
+                return Err(StorageInconsistentState(format!(
+                    "Rebuilt state roots {new_state_roots:?} of the child shards differ from the saved ones {state_roots:?}"
+                ))
+                .into());
+            }
+            if progress.position.is_none() {
+                let mut num_nodes = 0;
+                for child in &child_tries {
+                    let stats = child.mem_tries.stats();
+                    num_nodes += stats.total_nodes().count;
+                    // The size of nodes in memory is close to their size on disk, values are
+                    // written separately.
+                    progress.total_bytes += stats.total_nodes().bytes
+                        + stats.inlined_values.bytes
+                        + stats.referenced_values.bytes;
+                }
+                let delayed_receipt_indices = get_delayed_receipt_indices(
+                    &tries.new_trie_update_view(shard_uid, state_root),
+                )?;
+                let num_delayed_receipts = delayed_receipt_indices.next_available_index
+                    - delayed_receipt_indices.first_index;
+                progress.total_entries = num_nodes + num_delayed_receipts;
+            }
+            progress.export_metrics();
+            progress.start_run();
+
+            let parent_trie_storage = TrieDBStorage::new(tries.get_store(), shard_uid);
+            write_child_tries(
+                &tries,
+                &parent_trie_storage,
+                &child_tries,
+                &mut progress,
+                &mut throttle,
             )?;
             state_roots = new_state_roots;
-            store_update.commit()?;
-            RESHARDING_BATCH_COUNT.with_label_values(&[shard_uid.to_string().as_str()]).inc();
-            RESHARDING_BATCH_SIZE
-                .with_label_values(&[shard_uid.to_string().as_str()])
-                .add(size as i64)
         }
 
         state_roots = apply_delayed_receipts(
@@ -309,6 +1019,24 @@ impl Chain {
             state_root,
             state_roots,
             &checked_account_id_to_shard_uid,
+            &mut progress,
+            &mut throttle,
+        )?;
+
+        // The new shard layout must not be activated with broken state, so the job fails if
+        // the verification does.
+        RESHARDING_STATUS
+            .with_label_values(&[&shard_uid.to_string()])
+            .set(ReshardingStatus::Verifying.into());
+        verify_split_state(
+            &tries,
+            flat_storage_chunk_view.iter_flat_state_entries(None, None),
+            verification_delta_entries,
+            shard_uid,
+            state_root,
+            &state_roots,
+            &checked_account_id_to_shard_uid,
+            &cancellation,
         )?;
 
         Ok(state_roots)
@@ -334,6 +1062,13 @@ impl Chain {
         }
         chain_store_update.commit()?;
 
+        // The state of the child shards is complete, resharding won't resume from here.
+        let mut store_update = self.runtime_adapter.store().store_update();
+        for shard_uid in &child_shard_uids {
+            store_update.delete(DBCol::ReshardingProgress, &shard_uid.to_bytes());
+        }
+        store_update.commit()?;
+
         for shard_uid in child_shard_uids {
             RESHARDING_STATUS
                 .with_label_values(&[&shard_uid.to_string()])
@@ -368,3 +1103,141 @@ impl Chain {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        estimate_split_shards, get_checked_account_id_to_shard_uid_fn, get_split_shard_layout,
+        verify_split_state, ReshardingCancellation, ShardSizeEstimate,
+    };
+    use assert_matches::assert_matches;
+    use near_chain_primitives::Error;
+    use near_primitives::shard_layout::{ShardLayout, ShardUId};
+    use near_primitives::state::FlatStateValue;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::{AccountId, StateRoot};
+    use near_store::flat::{store_helper, FlatStorageError};
+    use near_store::test_utils::{create_test_store, create_tries, test_populate_trie};
+    use near_store::Trie;
+    use std::collections::HashMap;
+
+    fn accounts(names: &[&str]) -> Vec<AccountId> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_get_split_shard_layout() {
+        let shard_layout = ShardLayout::v1(accounts(&["bbb", "ddd"]), None, 1);
+        let split_layout =
+            get_split_shard_layout(&shard_layout, &accounts(&["ccc", "eee"])).unwrap();
+        assert_eq!(split_layout.version(), 2);
+        assert_eq!(
+            split_layout.boundary_accounts().unwrap(),
+            accounts(&["bbb", "ccc", "ddd", "eee"])
+        );
+        assert_eq!(split_layout.get_split_shard_ids(0), Some(vec![0]));
+        assert_eq!(split_layout.get_split_shard_ids(1), Some(vec![1, 2]));
+        assert_eq!(split_layout.get_split_shard_ids(2), Some(vec![3, 4]));
+
+        assert!(get_split_shard_layout(&ShardLayout::v0(2, 0), &accounts(&["ccc"])).is_err());
+    }
+
+    #[test]
+    fn test_estimate_split_shards() {
+        let shard_layout = ShardLayout::v1(accounts(&["bbb", "ddd"]), None, 1);
+        let split_layout = get_split_shard_layout(&shard_layout, &accounts(&["ccc"])).unwrap();
+        let parent_shard_uid = ShardUId::from_shard_id_and_layout(1, &shard_layout);
+
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        let entries = [
+            (TrieKey::Account { account_id: "bbb1".parse().unwrap() }, vec![1; 10]),
+            (TrieKey::Account { account_id: "bbb2".parse().unwrap() }, vec![1; 5000]),
+            (TrieKey::Account { account_id: "ccc1".parse().unwrap() }, vec![1; 20]),
+            (TrieKey::DelayedReceiptIndices, vec![1; 16]),
+        ];
+        for (key, value) in &entries {
+            store_helper::set_flat_state_value(
+                &mut store_update,
+                parent_shard_uid,
+                key.to_vec(),
+                Some(FlatStateValue::on_disk(value)),
+            );
+        }
+        store_update.commit().unwrap();
+
+        let estimate = estimate_split_shards(&store, parent_shard_uid, &split_layout).unwrap();
+        let key_len = |i: usize| entries[i].0.to_vec().len() as u64;
+        let child_shards: Vec<_> = estimate.child_shards.into_values().collect();
+        assert_eq!(
+            child_shards,
+            vec![
+                ShardSizeEstimate {
+                    num_keys: 2,
+                    key_bytes: key_len(0) + key_len(1),
+                    value_bytes: 5010
+                },
+                ShardSizeEstimate { num_keys: 1, key_bytes: key_len(2), value_bytes: 20 },
+            ]
+        );
+        assert_eq!(
+            estimate.delayed_receipts,
+            ShardSizeEstimate { num_keys: 1, key_bytes: key_len(3), value_bytes: 16 }
+        );
+    }
+
+    #[test]
+    fn test_verify_split_state() {
+        let shard_layout = ShardLayout::v1(accounts(&["bbb", "ddd"]), None, 1);
+        let split_layout = get_split_shard_layout(&shard_layout, &accounts(&["ccc"])).unwrap();
+        let parent_shard_uid = ShardUId::from_shard_id_and_layout(1, &shard_layout);
+        let child_shard_uids = split_layout.get_split_shard_uids(1).unwrap();
+        let account_id_to_shard_uid = get_checked_account_id_to_shard_uid_fn(
+            parent_shard_uid,
+            child_shard_uids.clone(),
+            split_layout,
+        );
+
+        let tries = create_tries();
+        let entries: Vec<_> = accounts(&["bbb1", "bbb2", "ccc1"])
+            .into_iter()
+            .map(|account_id| (TrieKey::Account { account_id }.to_vec(), vec![1, 2, 3]))
+            .collect();
+        let populate = |shard_uid: ShardUId, entries: &[(Vec<u8>, Vec<u8>)]| {
+            let changes = entries.iter().map(|(key, value)| (key.clone(), Some(value.clone())));
+            test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.collect())
+        };
+        let parent_state_root = populate(parent_shard_uid, &entries);
+        let verify = |first: &[(Vec<u8>, Vec<u8>)], second: &[(Vec<u8>, Vec<u8>)]| {
+            let state_roots: HashMap<ShardUId, StateRoot> = [
+                (child_shard_uids[0], populate(child_shard_uids[0], first)),
+                (child_shard_uids[1], populate(child_shard_uids[1], second)),
+            ]
+            .into_iter()
+            .collect();
+            let flat_state_entries = entries.iter().map(|(key, value)| {
+                Ok::<_, FlatStorageError>((key.clone(), FlatStateValue::on_disk(value)))
+            });
+            verify_split_state(
+                &tries,
+                flat_state_entries,
+                vec![],
+                parent_shard_uid,
+                parent_state_root,
+                &state_roots,
+                &account_id_to_shard_uid,
+                &ReshardingCancellation::default(),
+            )
+        };
+
+        assert_matches!(verify(&entries[..2], &entries[2..]), Ok(()));
+        // A key is missing.
+        assert_matches!(verify(&entries[..1], &entries[2..]), Err(Error::InvalidSplitState(_)));
+        // A key is in the wrong child shard.
+        assert_matches!(verify(&entries[..1], &entries[1..]), Err(Error::InvalidSplitState(_)));
+        // A value is different.
+        let mut changed = entries[2..].to_vec();
+        changed[0].1 = vec![4];
+        assert_matches!(verify(&entries[..2], &changed), Err(Error::InvalidSplitState(_)));
+    }
+}
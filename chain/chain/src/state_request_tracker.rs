@@ -31,6 +31,7 @@ impl StateRequestTracker {
         crypto_hash: &CryptoHash,
         shard_id: &ShardId,
         part_id: &u64,
+        method: &str,
         elapsed_ms: u128,
     ) {
         self.requested_state_parts.get_or_insert(*crypto_hash, || HashMap::new());
@@ -38,7 +39,7 @@ impl StateRequestTracker {
         let elapsed = parts_per_shard.entry(*shard_id).or_insert_with(|| vec![]);
         elapsed.push(PartElapsedTimeView::new(part_id, elapsed_ms));
         metrics::STATE_PART_ELAPSED
-            .with_label_values(&[&shard_id.to_string()])
+            .with_label_values(&[&shard_id.to_string(), method])
             .observe(elapsed_ms as f64 / 1000.);
     }
 }
@@ -24,8 +24,8 @@ use near_primitives::sharding::{
     StateSyncInfo,
 };
 use near_primitives::state_sync::{
-    get_num_state_parts, ReceiptProofResponse, ShardStateSyncResponseHeader, StateHeaderKey,
-    StatePartKey, StateSyncDumpProgress,
+    ReceiptProofResponse, ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey,
+    StateSyncDumpProgress,
 };
 use near_primitives::transaction::{
     ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof, ExecutionOutcomeWithProof,
@@ -52,11 +52,25 @@ use near_store::{
 
 use crate::byzantine_assert;
 use crate::chunks_store::ReadOnlyChunksStore;
+use crate::metrics;
 use crate::types::{Block, BlockHeader, LatestKnown};
 use near_store::db::{StoreStatistics, STATE_SYNC_DUMP_KEY};
 use near_store::flat::store_helper;
 use std::sync::Arc;
 
+mod gc_estimate;
+mod pruning;
+mod quarantine;
+
+pub use gc_estimate::GCEstimate;
+pub use pruning::PruneTails;
+pub use quarantine::QuarantinedBlock;
+
+/// Columns which are deleted from the hot store of archival nodes as soon as
+/// they're copied to cold storage if `cold_store_eager_migration` is enabled.
+const COLD_FALLBACK_COLUMNS: [DBCol; 4] =
+    [DBCol::Transactions, DBCol::Receipts, DBCol::TransactionResultForBlock, DBCol::OutcomeIds];
+
 /// lru cache size
 #[cfg(not(feature = "no_cache"))]
 const CACHE_SIZE: usize = 100;
@@ -185,17 +199,64 @@ pub trait ChainStoreAccess {
         sync_hash: &CryptoHash,
         height: BlockHeight,
     ) -> Result<BlockHeader, Error> {
-        let mut header = self.get_block_header(sync_hash)?;
-        let mut hash = *sync_hash;
-        while header.height() > height {
-            hash = *header.prev_hash();
-            header = self.get_block_header(&hash)?;
+        let hash = self.get_block_hash_on_canonical_chain(sync_hash, height)?;
+        self.get_block_header(&hash)
+    }
+    /// Returns hash and height of the skip pointer of given block if it has
+    /// been saved, see `get_skip_height`.
+    fn get_block_skip_pointer(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<(CryptoHash, BlockHeight)>, Error>;
+    /// Returns hash of the block at given height on the chain ending with
+    /// `from_hash`, i.e. of its ancestor at that height or `from_hash` itself.
+    /// Fails with `InvalidBlockHeight` if there's no block at that height on
+    /// the chain.
+    fn get_block_hash_on_canonical_chain(
+        &self,
+        from_hash: &CryptoHash,
+        target_height: BlockHeight,
+    ) -> Result<CryptoHash, Error> {
+        let (hash, height) = self.get_ancestor_at_or_below(from_hash, target_height)?;
+        if height != target_height {
+            return Err(Error::InvalidBlockHeight(height));
         }
-        let header_height = header.height();
-        if header_height < height {
-            return Err(Error::InvalidBlockHeight(header_height));
+        Ok(hash)
+    }
+    /// Returns hash and height of the ancestor of given block (or the block
+    /// itself) with the greatest height not above `target_height`.
+    ///
+    /// Ancestors of blocks on the canonical chain are looked up by height.
+    /// Otherwise the chain is walked back following skip pointers where they
+    /// don't overshoot the target height, so that walking deep forks takes
+    /// a logarithmic rather than linear number of steps.
+    fn get_ancestor_at_or_below(
+        &self,
+        block_hash: &CryptoHash,
+        target_height: BlockHeight,
+    ) -> Result<(CryptoHash, BlockHeight), Error> {
+        let mut hash = *block_hash;
+        loop {
+            let header = self.get_block_header(&hash)?;
+            let height = header.height();
+            if height <= target_height {
+                return Ok((hash, height));
+            }
+            if self.get_block_hash_by_height(height).ok() == Some(hash) {
+                // There are no entries for heights skipped on the canonical
+                // chain so the first entry at or below the target height is
+                // the ancestor.
+                for height in (self.get_genesis_height()..=target_height).rev() {
+                    if let Ok(hash) = self.get_block_hash_by_height(height) {
+                        return Ok((hash, height));
+                    }
+                }
+            }
+            hash = match self.get_block_skip_pointer(&hash)? {
+                Some((skip_hash, skip_height)) if skip_height >= target_height => skip_hash,
+                _ => *header.prev_hash(),
+            };
         }
-        self.get_block_header(&hash)
     }
     /// Returns resulting receipt for given block.
     fn get_outgoing_receipts(
@@ -371,6 +432,23 @@ pub trait ChainStoreAccess {
     }
 }
 
+/// Returns height, relative to the genesis, of the ancestor a block at given
+/// relative height has its skip pointer to.  Same as in Bitcoin, the heights
+/// are chosen so that following skip pointers and previous block hashes any
+/// ancestor can be reached in a logarithmic number of steps.
+fn get_skip_height(height: BlockHeightDelta) -> BlockHeightDelta {
+    fn invert_lowest_one(n: u64) -> u64 {
+        n & n.wrapping_sub(1)
+    }
+    if height < 2 {
+        0
+    } else if height & 1 == 1 {
+        invert_lowest_one(invert_lowest_one(height - 1)) + 1
+    } else {
+        invert_lowest_one(height)
+    }
+}
+
 /// Given a vector of receipts return only the receipts that should be assigned
 /// to the target shard id in the target shard layout. Used when collecting the
 /// incoming receipts and the shard layout changed.
@@ -457,6 +535,9 @@ pub struct ChainStore {
     /// - archive is true, cold_store is configured and migration to split_storage is finished - node
     /// working in split storage mode needs trie changes in order to do garbage collection on hot.
     save_trie_changes: bool,
+    /// Cold store consulted when reading transactions, receipts and execution
+    /// outcomes which aren't in the store, see `set_cold_store`.
+    cold_store: Option<Store>,
 }
 
 fn option_to_not_found<T, F>(res: io::Result<Option<T>>, field_name: F) -> Result<T, Error>
@@ -499,9 +580,18 @@ impl ChainStore {
             block_ordinal_to_hash: CellLruCache::new(CACHE_SIZE),
             processed_block_heights: CellLruCache::new(CACHE_SIZE),
             save_trie_changes,
+            cold_store: None,
         }
     }
 
+    /// Makes reads of transactions, receipts and execution outcomes which
+    /// aren't in the store fall back to given cold store.  Needed on archival
+    /// nodes which delete them from the hot store as soon as they're copied to
+    /// cold storage, see `ChainStoreUpdate::prune_cold_data`.
+    pub fn set_cold_store(&mut self, cold_store: Store) {
+        self.cold_store = Some(cold_store);
+    }
+
     pub fn new_read_only_chunks_store(&self) -> ReadOnlyChunksStore {
         ReadOnlyChunksStore::new(self.store.clone())
     }
@@ -765,11 +855,25 @@ impl ChainStore {
         &self,
         id: &CryptoHash,
     ) -> Result<Vec<ExecutionOutcomeWithIdAndProof>, Error> {
-        self.store
+        let mut outcomes: Vec<_> = self
+            .store
             .iter_prefix_ser::<ExecutionOutcomeWithProof>(
                 DBCol::TransactionResultForBlock,
                 id.as_ref(),
             )
+            .collect();
+        if outcomes.is_empty() {
+            if let Some(cold_store) = &self.cold_store {
+                outcomes = cold_store
+                    .iter_prefix_ser::<ExecutionOutcomeWithProof>(
+                        DBCol::TransactionResultForBlock,
+                        id.as_ref(),
+                    )
+                    .collect();
+            }
+        }
+        outcomes
+            .into_iter()
             .map(|item| {
                 let (key, outcome_with_proof) = item?;
                 let (_, block_hash) = get_outcome_id_block_hash_rev(key.as_ref())?;
@@ -790,7 +894,7 @@ impl ChainStore {
         id: &CryptoHash,
         block_hash: &CryptoHash,
     ) -> Result<Option<ExecutionOutcomeWithProof>, Error> {
-        Ok(self.store.get_ser(
+        Ok(self.get_ser_with_cold_fallback(
             DBCol::TransactionResultForBlock,
             &get_outcome_id_block_hash(id, block_hash),
         )?)
@@ -803,8 +907,10 @@ impl ChainStore {
         shard_id: ShardId,
     ) -> Result<Vec<CryptoHash>, Error> {
         Ok(self
-            .store
-            .get_ser(DBCol::OutcomeIds, &get_block_shard_id(block_hash, shard_id))?
+            .get_ser_with_cold_fallback(
+                DBCol::OutcomeIds,
+                &get_block_shard_id(block_hash, shard_id),
+            )?
             .unwrap_or_default())
     }
 
@@ -1068,6 +1174,32 @@ impl ChainStore {
         Ok(None)
     }
 
+    /// Reads from the store and, for the columns pruned once they're copied
+    /// to cold storage, falls back to the cold store if one is set.
+    fn get_ser_with_cold_fallback<T: BorshDeserialize>(
+        &self,
+        col: DBCol,
+        key: &[u8],
+    ) -> io::Result<Option<T>> {
+        if let Some(result) = self.store.get_ser::<T>(col, key)? {
+            return Ok(Some(result));
+        }
+        self.get_ser_from_cold_store(col, key)
+    }
+
+    fn get_ser_from_cold_store<T: BorshDeserialize>(
+        &self,
+        col: DBCol,
+        key: &[u8],
+    ) -> io::Result<Option<T>> {
+        match &self.cold_store {
+            Some(cold_store) if COLD_FALLBACK_COLUMNS.contains(&col) => {
+                cold_store.get_ser(col, key)
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Constructs key 'STATE_SYNC_DUMP:<ShardId>',
     /// for example 'STATE_SYNC_DUMP:2' for shard_id=2.
     /// Doesn't contain epoch_id, because only one dump process per shard is allowed.
@@ -1353,13 +1485,21 @@ impl ChainStoreAccess for ChainStore {
         &self,
         tx_hash: &CryptoHash,
     ) -> Result<Option<Arc<SignedTransaction>>, Error> {
-        self.read_with_cache(DBCol::Transactions, &self.transactions, tx_hash.as_ref())
-            .map_err(|e| e.into())
+        if let Some(transaction) =
+            self.read_with_cache(DBCol::Transactions, &self.transactions, tx_hash.as_ref())?
+        {
+            return Ok(Some(transaction));
+        }
+        Ok(self.get_ser_from_cold_store(DBCol::Transactions, tx_hash.as_ref())?)
     }
 
     fn get_receipt(&self, receipt_id: &CryptoHash) -> Result<Option<Arc<Receipt>>, Error> {
-        self.read_with_cache(DBCol::Receipts, &self.receipts, receipt_id.as_ref())
-            .map_err(|e| e.into())
+        if let Some(receipt) =
+            self.read_with_cache(DBCol::Receipts, &self.receipts, receipt_id.as_ref())?
+        {
+            return Ok(Some(receipt));
+        }
+        Ok(self.get_ser_from_cold_store(DBCol::Receipts, receipt_id.as_ref())?)
     }
 
     fn get_genesis_height(&self) -> BlockHeight {
@@ -1380,6 +1520,13 @@ impl ChainStoreAccess for ChainStore {
         )
     }
 
+    fn get_block_skip_pointer(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<(CryptoHash, BlockHeight)>, Error> {
+        Ok(self.store.get_ser(DBCol::BlockSkipPointers, block_hash.as_ref())?)
+    }
+
     fn get_block_hash_from_ordinal(&self, block_ordinal: NumBlocks) -> Result<CryptoHash, Error> {
         option_to_not_found(
             self.read_with_cache(
@@ -1425,6 +1572,7 @@ struct ChainStoreCacheUpdate {
     receipts: HashMap<CryptoHash, Arc<Receipt>>,
     block_refcounts: HashMap<CryptoHash, u64>,
     block_merkle_tree: HashMap<CryptoHash, Arc<PartialMerkleTree>>,
+    block_skip_pointers: HashMap<CryptoHash, (CryptoHash, BlockHeight)>,
     block_ordinal_to_hash: HashMap<NumBlocks, CryptoHash>,
     processed_block_heights: HashSet<BlockHeight>,
 }
@@ -1780,6 +1928,19 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
         }
     }
 
+    fn get_block_skip_pointer(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<(CryptoHash, BlockHeight)>, Error> {
+        if let Some(skip_pointer) =
+            self.chain_store_cache_update.block_skip_pointers.get(block_hash)
+        {
+            Ok(Some(*skip_pointer))
+        } else {
+            self.chain_store.get_block_skip_pointer(block_hash)
+        }
+    }
+
     fn get_block_hash_from_ordinal(&self, block_ordinal: NumBlocks) -> Result<CryptoHash, Error> {
         if let Some(block_hash) =
             self.chain_store_cache_update.block_ordinal_to_hash.get(&block_ordinal)
@@ -1888,6 +2049,23 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(())
     }
 
+    /// Makes the last block of the epoch before the one proven by epoch sync
+    /// the header head and the final head, indexing it by height and ordinal
+    /// so that header sync can continue from it.
+    pub fn save_epoch_sync_tip(
+        &mut self,
+        t: &Tip,
+        block_merkle_tree: PartialMerkleTree,
+    ) -> Result<(), Error> {
+        self.chain_store_cache_update.height_to_hashes.insert(t.height, Some(t.last_block_hash));
+        self.chain_store_cache_update
+            .block_ordinal_to_hash
+            .insert(block_merkle_tree.size(), t.last_block_hash);
+        self.save_block_merkle_tree(t.last_block_hash, block_merkle_tree);
+        self.force_save_header_head(t)?;
+        self.save_final_head(t)
+    }
+
     /// Update header head and height to hash index for this branch.
     pub fn save_header_head_if_not_challenged(&mut self, t: &Tip) -> Result<(), Error> {
         if t.height > self.chain_store.genesis_height {
@@ -2011,6 +2189,20 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(())
     }
 
+    /// Saves skip pointer of given header to the ancestor at its skip height.
+    fn update_and_save_block_skip_pointer(&mut self, header: &BlockHeader) -> Result<(), Error> {
+        let prev_hash = *header.prev_hash();
+        if prev_hash == CryptoHash::default() {
+            return Ok(());
+        }
+        let genesis_height = self.get_genesis_height();
+        let skip_height =
+            genesis_height + get_skip_height(header.height().saturating_sub(genesis_height));
+        let skip_pointer = self.get_ancestor_at_or_below(&prev_hash, skip_height)?;
+        self.chain_store_cache_update.block_skip_pointers.insert(*header.hash(), skip_pointer);
+        Ok(())
+    }
+
     /// Used only in Epoch Sync finalization
     /// Validity of Header is checked by Epoch Sync methods
     pub fn save_block_header_no_update_tree(&mut self, header: BlockHeader) -> Result<(), Error> {
@@ -2020,6 +2212,7 @@ impl<'a> ChainStoreUpdate<'a> {
 
     pub fn save_block_header(&mut self, header: BlockHeader) -> Result<(), Error> {
         self.update_and_save_block_merkle_tree(&header)?;
+        self.update_and_save_block_skip_pointer(&header)?;
         self.chain_store_cache_update.headers.insert(*header.hash(), header);
         Ok(())
     }
@@ -2219,17 +2412,24 @@ impl<'a> ChainStoreUpdate<'a> {
         min_chunk_height: BlockHeight,
     ) -> Result<(), Error> {
         let chunk_tail = self.chunk_tail()?;
+        let prune_tails = self.chain_store.prune_tails()?;
         for height in chunk_tail..min_chunk_height {
             let chunk_hashes = self.chain_store.get_all_chunk_hashes_by_height(height)?;
             for chunk_hash in chunk_hashes {
-                // 1. Delete chunk-related data
+                // 1. Delete chunk-related data.  Transactions and receipts are
+                // reference counted so they mustn't be decremented again if
+                // they've already been pruned.
                 let chunk = self.get_chunk(&chunk_hash)?.clone();
                 debug_assert_eq!(chunk.cloned_header().height_created(), height);
-                for transaction in chunk.transactions() {
-                    self.gc_col(DBCol::Transactions, transaction.get_hash().as_bytes());
+                if height >= prune_tails.transactions {
+                    for transaction in chunk.transactions() {
+                        self.gc_col(DBCol::Transactions, transaction.get_hash().as_bytes());
+                    }
                 }
-                for receipt in chunk.prev_outgoing_receipts() {
-                    self.gc_col(DBCol::Receipts, receipt.get_hash().as_bytes());
+                if height >= prune_tails.receipts {
+                    for receipt in chunk.prev_outgoing_receipts() {
+                        self.gc_col(DBCol::Receipts, receipt.get_hash().as_bytes());
+                    }
                 }
 
                 // 2. Delete chunk_hash-indexed data
@@ -2390,16 +2590,10 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_outgoing_receipts(&block_hash, shard_id);
             self.gc_col(DBCol::IncomingReceipts, &block_shard_id);
 
-            // For incoming State Parts it's done in chain.clear_downloaded_parts()
-            // The following code is mostly for outgoing State Parts.
-            // However, if node crashes while State Syncing, it may never clear
-            // downloaded State parts in `clear_downloaded_parts`.
-            // We need to make sure all State Parts are removed.
-            if let Ok(shard_state_header) = self.chain_store.get_state_header(shard_id, block_hash)
-            {
-                let state_num_parts =
-                    get_num_state_parts(shard_state_header.state_root_node().memory_usage);
-                self.gc_col_state_parts(block_hash, shard_id, state_num_parts)?;
+            // State Parts aren't deleted here, they expire on their own (see
+            // `DBCol::default_ttl`).  Downloaded State Parts are also removed
+            // once applied, in `chain.clear_downloaded_parts()`.
+            if self.chain_store.get_state_header(shard_id, block_hash).is_ok() {
                 let key = StateHeaderKey(shard_id, block_hash).try_to_vec()?;
                 self.gc_col(DBCol::StateHeaders, &key);
             }
@@ -2494,12 +2688,8 @@ impl<'a> ChainStoreUpdate<'a> {
             // delete DBCol::ChunkExtra based on shard_uid since it's indexed by shard_uid in the storage
             self.gc_col(DBCol::ChunkExtra, &block_shard_id);
 
-            // delete state parts and state headers
-            if let Ok(shard_state_header) = self.chain_store.get_state_header(shard_id, block_hash)
-            {
-                let state_num_parts =
-                    get_num_state_parts(shard_state_header.state_root_node().memory_usage);
-                self.gc_col_state_parts(block_hash, shard_id, state_num_parts)?;
+            // delete state headers; state parts expire on their own
+            if self.chain_store.get_state_header(shard_id, block_hash).is_ok() {
                 let state_header_key = StateHeaderKey(shard_id, block_hash).try_to_vec()?;
                 self.gc_col(DBCol::StateHeaders, &state_header_key);
             }
@@ -2547,15 +2737,21 @@ impl<'a> ChainStoreUpdate<'a> {
 
     fn clear_chunk_data_at_height(&mut self, height: BlockHeight) -> Result<(), Error> {
         let chunk_hashes = self.chain_store.get_all_chunk_hashes_by_height(height)?;
+        let prune_tails = self.chain_store.prune_tails()?;
         for chunk_hash in chunk_hashes {
-            // 1. Delete chunk-related data
+            // 1. Delete chunk-related data, skipping pruned reference counted
+            // data.
             let chunk = self.get_chunk(&chunk_hash)?.clone();
             debug_assert_eq!(chunk.cloned_header().height_created(), height);
-            for transaction in chunk.transactions() {
-                self.gc_col(DBCol::Transactions, transaction.get_hash().as_bytes());
+            if height >= prune_tails.transactions {
+                for transaction in chunk.transactions() {
+                    self.gc_col(DBCol::Transactions, transaction.get_hash().as_bytes());
+                }
             }
-            for receipt in chunk.prev_outgoing_receipts() {
-                self.gc_col(DBCol::Receipts, receipt.get_hash().as_bytes());
+            if height >= prune_tails.receipts {
+                for receipt in chunk.prev_outgoing_receipts() {
+                    self.gc_col(DBCol::Receipts, receipt.get_hash().as_bytes());
+                }
             }
 
             // 2. Delete chunk_hash-indexed data
@@ -2783,7 +2979,12 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::BlockHeight  // block sync needs it + genesis should be accessible
             | DBCol::_Peers
             | DBCol::RecentOutboundConnections
+            | DBCol::PeerReputation
+            | DBCol::PeerAddrQuality
             | DBCol::BlockMerkleTree
+            | DBCol::BlockSkipPointers
+            | DBCol::QuarantinedBlocks
+            | DBCol::ReshardingProgress
             | DBCol::AccountAnnouncements
             | DBCol::EpochLightClientBlocks
             | DBCol::PeerComponent
@@ -3127,6 +3328,9 @@ impl<'a> ChainStoreUpdate<'a> {
         {
             store_update.set_ser(DBCol::BlockMerkleTree, block_hash.as_ref(), block_merkle_tree)?;
         }
+        for (block_hash, skip_pointer) in self.chain_store_cache_update.block_skip_pointers.iter() {
+            store_update.set_ser(DBCol::BlockSkipPointers, block_hash.as_ref(), skip_pointer)?;
+        }
         for (block_ordinal, block_hash) in
             self.chain_store_cache_update.block_ordinal_to_hash.iter()
         {
@@ -3142,7 +3346,12 @@ impl<'a> ChainStoreUpdate<'a> {
         // from the store.
         let mut deletions_store_update = self.store().store_update();
         for mut wrapped_trie_changes in self.trie_changes.drain(..) {
+            let shard_label = wrapped_trie_changes.shard_uid().shard_id.to_string();
+            let _timer = metrics::APPLYING_CHUNKS_TIME_BREAKDOWN
+                .with_label_values(&[&shard_label, "storage_write"])
+                .start_timer();
             wrapped_trie_changes.insertions_into(&mut store_update);
+            wrapped_trie_changes.apply_mem_changes()?;
             wrapped_trie_changes.deletions_into(&mut deletions_store_update);
             wrapped_trie_changes.state_changes_into(&mut store_update);
 
@@ -3279,6 +3488,7 @@ impl<'a> ChainStoreUpdate<'a> {
 
             outcomes: _,
             outcome_ids: _,
+            block_skip_pointers: _,
         } = self.chain_store_cache_update;
         for (hash, block) in blocks {
             self.chain_store.blocks.put(hash.into(), block);
@@ -3362,7 +3572,9 @@ impl<'a> ChainStoreUpdate<'a> {
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
+    use std::time::Duration;
 
+    use assert_matches::assert_matches;
     use near_chain_configs::{GCConfig, GenesisConfig};
     use near_epoch_manager::shard_tracker::ShardTracker;
     use near_epoch_manager::EpochManagerAdapter;
@@ -3642,6 +3854,86 @@ mod tests {
         }
     }
 
+    /// Garbage collection stops once it runs out of time and the next call
+    /// continues from where it stopped.
+    #[test]
+    fn test_clear_old_data_time_limit() {
+        let mut chain = get_chain_with_epoch_length(1);
+        let epoch_manager = chain.epoch_manager.clone();
+        let genesis = chain.get_block_by_height(0).unwrap();
+        let signer = Arc::new(create_test_signer("test1"));
+        let mut prev_block = genesis;
+        let mut blocks = vec![prev_block.clone()];
+        for i in 1..15 {
+            add_block(
+                &mut chain,
+                epoch_manager.as_ref(),
+                &mut prev_block,
+                &mut blocks,
+                signer.clone(),
+                i,
+            );
+        }
+
+        let trie = chain.runtime_adapter.get_tries();
+        let gc_config = GCConfig {
+            gc_blocks_limit: 100,
+            gc_time_limit: Some(Duration::ZERO),
+            ..GCConfig::default()
+        };
+        chain.clear_data(trie.clone(), &gc_config).unwrap();
+        assert_eq!(chain.tail().unwrap(), 0);
+        assert!(chain.get_block(blocks[1].hash()).is_ok());
+
+        let gc_config = GCConfig { gc_time_limit: None, ..gc_config };
+        chain.clear_data(trie, &gc_config).unwrap();
+        assert!(chain.tail().unwrap() > 0);
+        for i in 0..15 {
+            assert_eq!(chain.get_block(blocks[i].hash()).is_err(), i < 8, "height {i}");
+        }
+    }
+
+    /// The GC estimate deletes nothing and matches what GC deletes.
+    #[test]
+    fn test_estimate_gc() {
+        let mut chain = get_chain_with_epoch_length(1);
+        let epoch_manager = chain.epoch_manager.clone();
+        let genesis = chain.get_block_by_height(0).unwrap();
+        let signer = Arc::new(create_test_signer("test1"));
+        let mut prev_block = genesis;
+        let mut blocks = vec![prev_block.clone()];
+        for i in 1..15 {
+            add_block(
+                &mut chain,
+                epoch_manager.as_ref(),
+                &mut prev_block,
+                &mut blocks,
+                signer.clone(),
+                i,
+            );
+        }
+
+        let estimate = chain.estimate_gc(100).unwrap();
+        assert_eq!(estimate.tail, 0);
+        assert!(estimate.canonical_blocks > 0);
+        assert!(estimate.total().bytes > 0);
+        for block in &blocks {
+            assert!(chain.get_block(block.hash()).is_ok());
+        }
+
+        let trie = chain.runtime_adapter.get_tries();
+        chain.clear_data(trie, &GCConfig { gc_blocks_limit: 100, ..GCConfig::default() }).unwrap();
+        let deleted_blocks =
+            blocks.iter().filter(|block| chain.get_block(block.hash()).is_err()).count();
+        let estimated_blocks = estimate
+            .columns
+            .iter()
+            .find(|(col, _)| *col == DBCol::Block)
+            .map_or(0, |(_, stats)| stats.keys);
+        assert_eq!(estimated_blocks, deleted_blocks as u64);
+        assert_eq!(chain.estimate_gc(100).unwrap().canonical_blocks, 0);
+    }
+
     // Adds block to the chain at given height after prev_block.
     fn add_block(
         chain: &mut Chain,
@@ -3689,6 +3981,59 @@ mod tests {
         *prev_block = block.clone();
     }
 
+    #[test]
+    fn test_get_block_hash_on_canonical_chain() {
+        let mut chain = get_chain();
+        let epoch_manager = chain.epoch_manager.clone();
+        let genesis = chain.get_block_by_height(0).unwrap();
+        let signer = Arc::new(create_test_signer("test1"));
+        let mut prev_block = genesis;
+        let mut blocks = vec![prev_block.clone()];
+        for i in 1..10 {
+            add_block(
+                &mut chain,
+                epoch_manager.as_ref(),
+                &mut prev_block,
+                &mut blocks,
+                signer.clone(),
+                i,
+            );
+        }
+        // A long fork off block 5 which isn't on the canonical chain and skips
+        // every other height.
+        let mut fork = vec![blocks[5].clone()];
+        let mut store_update = chain.mut_store().store_update();
+        for height in (7..100).step_by(2) {
+            let block =
+                TestBlockBuilder::new(fork.last().unwrap(), signer.clone()).height(height).build();
+            store_update.save_block_header(block.header().clone()).unwrap();
+            fork.push(block);
+        }
+        store_update.commit().unwrap();
+
+        let store = chain.mut_store();
+        let fork_tip = fork.last().unwrap().hash();
+        for block in blocks[..5].iter().chain(fork.iter()) {
+            let hash =
+                store.get_block_hash_on_canonical_chain(fork_tip, block.header().height()).unwrap();
+            assert_eq!(&hash, block.hash());
+        }
+        for block in &blocks {
+            let hash = store
+                .get_block_hash_on_canonical_chain(blocks[9].hash(), block.header().height())
+                .unwrap();
+            assert_eq!(&hash, block.hash());
+        }
+        assert_matches!(
+            store.get_block_hash_on_canonical_chain(fork_tip, 8),
+            Err(near_chain_primitives::Error::InvalidBlockHeight(7))
+        );
+        // Skip pointers of blocks on the fork point further back than their
+        // previous blocks.
+        let (_, skip_height) = store.get_block_skip_pointer(fork_tip).unwrap().unwrap();
+        assert_eq!(skip_height, 65);
+    }
+
     #[test]
     fn test_clear_old_data_fixed_height() {
         let mut chain = get_chain();
@@ -0,0 +1,130 @@
+//! Estimation of how much data garbage collection would delete.
+//!
+//! The estimate runs the same steps as `Chain::clear_data`, cleaning forks
+//! and then the canonical chain up to the GC stop height, but collects all
+//! the changes in a single `ChainStoreUpdate` which is never committed.
+
+use super::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode};
+use near_chain_primitives::Error;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, BlockHeightDelta};
+use near_store::{DBCol, DeletionStats, ShardTries};
+use std::collections::HashSet;
+
+/// What garbage collection would delete, see `ChainStore::estimate_gc`.
+#[derive(Debug, Default)]
+pub struct GCEstimate {
+    pub tail: BlockHeight,
+    pub gc_stop_height: BlockHeight,
+    /// Heights up to (excluding) this one are included in the estimate.
+    pub estimated_until_height: BlockHeight,
+    pub canonical_blocks: u64,
+    pub fork_blocks: u64,
+    /// Deleted keys and bytes per column, the largest first.
+    pub columns: Vec<(DBCol, DeletionStats)>,
+}
+
+impl GCEstimate {
+    pub fn total(&self) -> DeletionStats {
+        let mut total = DeletionStats::default();
+        for (_, stats) in &self.columns {
+            total.keys += stats.keys;
+            total.bytes += stats.bytes;
+        }
+        total
+    }
+}
+
+impl ChainStore {
+    /// Estimates how many keys and bytes garbage collecting all heights from
+    /// the tail up to `gc_stop_height` would delete, without deleting
+    /// anything.  At most `height_limit` heights are included.
+    pub fn estimate_gc(
+        &mut self,
+        epoch_manager: &dyn EpochManagerAdapter,
+        tries: ShardTries,
+        gc_stop_height: BlockHeight,
+        height_limit: BlockHeightDelta,
+    ) -> Result<GCEstimate, Error> {
+        let tail = self.tail()?;
+        let stop_height = gc_stop_height.min(tail.saturating_add(height_limit).saturating_add(1));
+        let mut estimate = GCEstimate {
+            tail,
+            gc_stop_height,
+            estimated_until_height: stop_height.max(tail),
+            ..Default::default()
+        };
+        let mut chain_store_update = self.store_update();
+
+        // Forks Cleaning
+        let mut cleared = HashSet::<CryptoHash>::new();
+        for height in (tail..stop_height).rev() {
+            for block_hash in Self::block_hashes_at_height(&chain_store_update, height)? {
+                // Blocks deleted in this update are still in the store so
+                // they must be skipped explicitly.
+                let mut current_hash = block_hash;
+                while !cleared.contains(&current_hash)
+                    && chain_store_update.get_block_refcount(&current_hash)? == 0
+                {
+                    let prev_hash =
+                        *chain_store_update.get_block_header(&current_hash)?.prev_hash();
+                    chain_store_update.clear_block_data(
+                        epoch_manager,
+                        current_hash,
+                        GCMode::Fork(tries.clone()),
+                    )?;
+                    cleared.insert(current_hash);
+                    estimate.fork_blocks += 1;
+                    current_hash = prev_hash;
+                }
+            }
+        }
+
+        // Canonical Chain Clearing
+        for height in tail + 1..stop_height {
+            let Some(block_hash) = Self::block_hashes_at_height(&chain_store_update, height)?
+                .into_iter()
+                .find(|block_hash| !cleared.contains(block_hash))
+            else {
+                continue;
+            };
+            let prev_hash = *chain_store_update.get_block_header(&block_hash)?.prev_hash();
+            if chain_store_update.get_block_refcount(&prev_hash)? > 1 {
+                // Block of `prev_hash` starts a Fork, stopping
+                estimate.estimated_until_height = height;
+                break;
+            }
+            chain_store_update.clear_block_data(
+                epoch_manager,
+                block_hash,
+                GCMode::Canonical(tries.clone()),
+            )?;
+            chain_store_update.update_tail(height)?;
+            estimate.canonical_blocks += 1;
+        }
+
+        let mut columns: Vec<_> = chain_store_update.estimate_deletions()?.into_iter().collect();
+        columns.sort_by(|(_, a), (_, b)| b.bytes.cmp(&a.bytes));
+        estimate.columns = columns;
+        Ok(estimate)
+    }
+
+    fn block_hashes_at_height(
+        chain_store_update: &ChainStoreUpdate,
+        height: BlockHeight,
+    ) -> Result<Vec<CryptoHash>, Error> {
+        let block_hashes = chain_store_update.chain_store.get_all_block_hashes_by_height(height)?;
+        Ok(block_hashes.values().flatten().copied().collect())
+    }
+}
+
+impl<'a> ChainStoreUpdate<'a> {
+    /// Returns what committing the update would delete, see
+    /// `StoreUpdate::estimate_deletions`.  The update is discarded.
+    fn estimate_deletions(
+        mut self,
+    ) -> Result<std::collections::HashMap<DBCol, DeletionStats>, Error> {
+        Ok(self.finalize()?.estimate_deletions()?)
+    }
+}
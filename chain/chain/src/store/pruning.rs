@@ -0,0 +1,369 @@
+//! Pruning of data which isn't needed for processing blocks before garbage
+//! collection removes it.
+//!
+//! Each class of data configured in `RetentionConfig` has its own tail stored
+//! under `PRUNE_TAILS_KEY`.  Data at heights below the tail has been pruned.
+//! Pruning never goes below the chunk tail (or the tail for outcomes) since
+//! data there has already been garbage collected.  Transactions and receipts
+//! are reference counted so garbage collection must not decrement them again
+//! for heights below their prune tails.
+//!
+//! Archival nodes with split storage can instead prune transactions, receipts
+//! and execution outcomes as soon as the cold store loop has copied them, which
+//! keeps them out of the hot store's working set.  Reads of them fall back to
+//! cold storage.
+
+use super::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
+use crate::metrics;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_chain_configs::RetentionConfig;
+use near_chain_primitives::Error;
+use near_primitives::block::Tip;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::types::{BlockHeight, BlockHeightDelta};
+use near_store::{DBCol, COLD_HEAD_KEY, PRUNE_TAILS_KEY};
+
+/// Heights below which each class of data has been pruned.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneTails {
+    pub transactions: BlockHeight,
+    pub receipts: BlockHeight,
+    pub outcomes: BlockHeight,
+    pub chunk_parts: BlockHeight,
+}
+
+impl PruneTails {
+    pub(crate) fn update_metrics(&self) {
+        for (data, tail) in [
+            ("transactions", self.transactions),
+            ("receipts", self.receipts),
+            ("outcomes", self.outcomes),
+            ("chunk_parts", self.chunk_parts),
+        ] {
+            metrics::PRUNE_TAIL_HEIGHT.with_label_values(&[data]).set(tail as i64);
+        }
+    }
+}
+
+impl ChainStore {
+    pub fn prune_tails(&self) -> Result<PruneTails, Error> {
+        Ok(self.store.get_ser(DBCol::BlockMisc, PRUNE_TAILS_KEY)?.unwrap_or_default())
+    }
+}
+
+/// Heights up to which each class of data should be pruned, `None` if the
+/// data isn't pruned.
+struct PruneStopHeights {
+    transactions: Option<BlockHeight>,
+    receipts: Option<BlockHeight>,
+    outcomes: Option<BlockHeight>,
+    chunk_parts: Option<BlockHeight>,
+}
+
+impl<'a> ChainStoreUpdate<'a> {
+    /// Prunes the configured classes of data at heights more than their
+    /// retention behind the final head.
+    ///
+    /// `height_limit` limits how many non-empty heights are processed for each
+    /// class of data.
+    pub fn prune_data(
+        &mut self,
+        retention: &RetentionConfig,
+        height_limit: BlockHeightDelta,
+    ) -> Result<PruneTails, Error> {
+        let final_height = self.final_head()?.height;
+        let stop_height = |retention: Option<BlockHeightDelta>| {
+            retention.map(|retention| final_height.saturating_sub(retention))
+        };
+        let stop_heights = PruneStopHeights {
+            transactions: stop_height(retention.transactions),
+            receipts: stop_height(retention.receipts),
+            outcomes: stop_height(retention.outcomes),
+            chunk_parts: stop_height(retention.chunk_parts),
+        };
+        self.prune_until(&stop_heights, height_limit)
+    }
+
+    /// Prunes transactions, receipts and execution outcomes at heights up to
+    /// the cold head, i.e. the ones which have already been copied to cold
+    /// storage.  Does nothing until the cold store loop copies the first
+    /// height.
+    pub fn prune_cold_data(&mut self, height_limit: BlockHeightDelta) -> Result<PruneTails, Error> {
+        let cold_head: Option<Tip> = self.store().get_ser(DBCol::BlockMisc, COLD_HEAD_KEY)?;
+        let Some(cold_head) = cold_head else {
+            return self.chain_store.prune_tails();
+        };
+        let stop_height = Some(cold_head.height + 1);
+        let stop_heights = PruneStopHeights {
+            transactions: stop_height,
+            receipts: stop_height,
+            outcomes: stop_height,
+            chunk_parts: None,
+        };
+        self.prune_until(&stop_heights, height_limit)
+    }
+
+    fn prune_until(
+        &mut self,
+        stop_heights: &PruneStopHeights,
+        height_limit: BlockHeightDelta,
+    ) -> Result<PruneTails, Error> {
+        let mut tails = self.chain_store.prune_tails()?;
+        let chunk_tail = self.chunk_tail()?;
+
+        if let Some(stop_height) = stop_heights.transactions {
+            tails.transactions = self.prune_chunk_data(
+                tails.transactions.max(chunk_tail),
+                stop_height,
+                height_limit,
+                Self::prune_transactions,
+            )?;
+        }
+        if let Some(stop_height) = stop_heights.receipts {
+            tails.receipts = self.prune_chunk_data(
+                tails.receipts.max(chunk_tail),
+                stop_height,
+                height_limit,
+                Self::prune_receipts,
+            )?;
+        }
+        if let Some(stop_height) = stop_heights.chunk_parts {
+            tails.chunk_parts = self.prune_chunk_data(
+                tails.chunk_parts.max(chunk_tail),
+                stop_height,
+                height_limit,
+                Self::prune_chunk_parts,
+            )?;
+        }
+        if let Some(stop_height) = stop_heights.outcomes {
+            tails.outcomes =
+                self.prune_outcomes(tails.outcomes.max(self.tail()?), stop_height, height_limit)?;
+        }
+
+        let mut store_update = self.store().store_update();
+        store_update.set_ser(DBCol::BlockMisc, PRUNE_TAILS_KEY, &tails)?;
+        self.merge(store_update);
+        Ok(tails)
+    }
+
+    /// Calls `prune` for all chunks created at heights from `start` until
+    /// `stop_height` and returns the height pruning should continue from.
+    fn prune_chunk_data(
+        &mut self,
+        start: BlockHeight,
+        stop_height: BlockHeight,
+        height_limit: BlockHeightDelta,
+        prune: fn(&mut Self, &ChunkHash) -> Result<(), Error>,
+    ) -> Result<BlockHeight, Error> {
+        let mut height = start;
+        let mut remaining = height_limit;
+        while height < stop_height && remaining > 0 {
+            let chunk_hashes = self.chain_store.get_all_chunk_hashes_by_height(height)?;
+            height += 1;
+            if !chunk_hashes.is_empty() {
+                remaining -= 1;
+                for chunk_hash in chunk_hashes {
+                    prune(self, &chunk_hash)?;
+                }
+            }
+        }
+        Ok(height)
+    }
+
+    fn prune_transactions(&mut self, chunk_hash: &ChunkHash) -> Result<(), Error> {
+        let chunk = self.get_chunk(chunk_hash)?;
+        for transaction in chunk.transactions() {
+            self.gc_col(DBCol::Transactions, transaction.get_hash().as_bytes());
+        }
+        Ok(())
+    }
+
+    fn prune_receipts(&mut self, chunk_hash: &ChunkHash) -> Result<(), Error> {
+        let chunk = self.get_chunk(chunk_hash)?;
+        for receipt in chunk.prev_outgoing_receipts() {
+            self.gc_col(DBCol::Receipts, receipt.get_hash().as_bytes());
+        }
+        Ok(())
+    }
+
+    fn prune_chunk_parts(&mut self, chunk_hash: &ChunkHash) -> Result<(), Error> {
+        self.gc_col(DBCol::PartialChunks, chunk_hash.as_bytes());
+        self.gc_col(DBCol::InvalidChunks, chunk_hash.as_bytes());
+        Ok(())
+    }
+
+    /// Prunes outcomes of all blocks at heights from `start` until
+    /// `stop_height` and returns the height pruning should continue from.
+    fn prune_outcomes(
+        &mut self,
+        start: BlockHeight,
+        stop_height: BlockHeight,
+        height_limit: BlockHeightDelta,
+    ) -> Result<BlockHeight, Error> {
+        let mut height = start;
+        let mut remaining = height_limit;
+        while height < stop_height && remaining > 0 {
+            let block_hashes: Vec<_> = self
+                .chain_store
+                .get_all_block_hashes_by_height(height)?
+                .values()
+                .flatten()
+                .copied()
+                .collect();
+            height += 1;
+            if !block_hashes.is_empty() {
+                remaining -= 1;
+                for block_hash in block_hashes {
+                    let block = self.get_block(&block_hash)?;
+                    self.gc_outcomes(&block)?;
+                }
+            }
+        }
+        Ok(height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::{ChainStore, ChainStoreAccess, PruneTails};
+    use borsh::BorshSerialize;
+    use near_chain_configs::RetentionConfig;
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::sharding::{ChunkHash, EncodedShardChunk, ReedSolomonWrapper};
+    use near_primitives::transaction::SignedTransaction;
+    use near_primitives::types::{BlockHeight, EpochId};
+    use near_primitives::utils::index_to_bytes;
+    use near_primitives::validator_signer::EmptyValidatorSigner;
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_store::metadata::{DbKind, DB_VERSION};
+    use near_store::test_utils::{create_test_node_storage_with_cold, create_test_store};
+    use near_store::{DBCol, COLD_HEAD_KEY};
+    use std::collections::HashSet;
+
+    fn tip(height: BlockHeight) -> Tip {
+        Tip {
+            height,
+            last_block_hash: CryptoHash::default(),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        }
+    }
+
+    fn chunk_hash(height: BlockHeight) -> ChunkHash {
+        ChunkHash(CryptoHash::hash_bytes(&height.to_le_bytes()))
+    }
+
+    #[test]
+    fn test_prune_chunk_parts() {
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        for height in 1..20 {
+            let chunk_hashes = HashSet::from([chunk_hash(height)]);
+            store_update
+                .set_ser(DBCol::ChunkHashesByHeight, &index_to_bytes(height), &chunk_hashes)
+                .unwrap();
+            store_update.insert(DBCol::PartialChunks, chunk_hash(height).0.into(), vec![1]);
+        }
+        store_update.commit().unwrap();
+        let mut chain_store = ChainStore::new(store.clone(), 0, true);
+        let mut chain_store_update = chain_store.store_update();
+        chain_store_update.save_final_head(&tip(19)).unwrap();
+        chain_store_update.commit().unwrap();
+
+        let retention = RetentionConfig { chunk_parts: Some(5), ..Default::default() };
+        let mut chain_store_update = chain_store.store_update();
+        let tails = chain_store_update.prune_data(&retention, 10).unwrap();
+        chain_store_update.commit().unwrap();
+        // Only 10 non-empty heights are pruned at a time.
+        assert_eq!(tails.chunk_parts, 11);
+        let mut chain_store_update = chain_store.store_update();
+        let tails = chain_store_update.prune_data(&retention, 10).unwrap();
+        chain_store_update.commit().unwrap();
+        assert_eq!(tails.chunk_parts, 14);
+        assert_eq!(tails.transactions, 0);
+        assert_eq!(chain_store.prune_tails().unwrap(), tails);
+
+        for height in 1..20 {
+            let exists = store.exists(DBCol::PartialChunks, chunk_hash(height).as_bytes()).unwrap();
+            assert_eq!(exists, height >= 14, "height {height}");
+        }
+    }
+
+    #[test]
+    fn test_prune_cold_data() {
+        let store = create_test_store();
+        let mut chain_store = ChainStore::new(store.clone(), 0, true);
+        let mut chain_store_update = chain_store.store_update();
+        let tails = chain_store_update.prune_cold_data(10).unwrap();
+        chain_store_update.commit().unwrap();
+        // Nothing is pruned before the cold store loop copies anything.
+        assert_eq!(tails, PruneTails::default());
+
+        let mut store_update = store.store_update();
+        store_update.set_ser(DBCol::BlockMisc, COLD_HEAD_KEY, &tip(7)).unwrap();
+        store_update.commit().unwrap();
+        let mut chain_store_update = chain_store.store_update();
+        let tails = chain_store_update.prune_cold_data(10).unwrap();
+        chain_store_update.commit().unwrap();
+        // Data at the cold head has been copied so it's pruned as well.
+        assert_eq!(tails, PruneTails { transactions: 8, receipts: 8, outcomes: 8, chunk_parts: 0 });
+        assert_eq!(chain_store.prune_tails().unwrap(), tails);
+    }
+
+    #[test]
+    fn test_read_after_prune_cold_data() {
+        let (storage, ..) = create_test_node_storage_with_cold(DB_VERSION, DbKind::Hot);
+        let hot_store = storage.get_hot_store();
+        let cold_store = storage.get_cold_store().unwrap();
+        let transaction = SignedTransaction::empty(CryptoHash::default());
+        let tx_hash = transaction.get_hash();
+        let (encoded_chunk, _) = EncodedShardChunk::new(
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            5,
+            0,
+            &mut ReedSolomonWrapper::new(1, 2),
+            0,
+            1000,
+            0,
+            CryptoHash::default(),
+            vec![],
+            vec![transaction.clone()],
+            &[],
+            CryptoHash::default(),
+            &EmptyValidatorSigner::default(),
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        let mut chain_store = ChainStore::new(hot_store.clone(), 0, true);
+        chain_store.set_cold_store(cold_store.clone());
+        let mut chain_store_update = chain_store.store_update();
+        chain_store_update.save_chunk(encoded_chunk.decode_chunk(1).unwrap());
+        chain_store_update.commit().unwrap();
+
+        // Copy the transaction to cold storage the way the cold store loop
+        // does and move the cold head past its height.
+        let mut store_update = cold_store.store_update();
+        store_update.increment_refcount(
+            DBCol::Transactions,
+            tx_hash.as_bytes(),
+            &transaction.try_to_vec().unwrap(),
+        );
+        store_update.commit().unwrap();
+        let mut store_update = hot_store.store_update();
+        store_update.set_ser(DBCol::BlockMisc, COLD_HEAD_KEY, &tip(5)).unwrap();
+        store_update.commit().unwrap();
+
+        let mut chain_store_update = chain_store.store_update();
+        chain_store_update.prune_cold_data(10).unwrap();
+        chain_store_update.commit().unwrap();
+        assert!(!hot_store.exists(DBCol::Transactions, tx_hash.as_bytes()).unwrap());
+        // The pruned transaction is still readable through the cold store.
+        assert_eq!(chain_store.get_transaction(&tx_hash).unwrap().as_deref(), Some(&transaction));
+        let chain_store = ChainStore::new(hot_store, 0, true);
+        assert_eq!(chain_store.get_transaction(&tx_hash).unwrap(), None);
+    }
+}
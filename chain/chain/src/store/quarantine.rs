@@ -0,0 +1,147 @@
+//! Quarantine of blocks which failed validation.
+//!
+//! Blocks marked as invalid are saved in `DBCol::QuarantinedBlocks` together
+//! with the error and the peer which sent them so that they can be inspected
+//! through the debug API after the fact.  Only the `MAX_QUARANTINED_BLOCKS`
+//! most recently quarantined blocks are kept.
+
+use super::ChainStore;
+use borsh::{BorshDeserialize, BorshSerialize};
+use chrono::TimeZone;
+use near_chain_primitives::Error;
+use near_primitives::block::Block;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::views::{QuarantinedBlockDetailsView, QuarantinedBlockView};
+use near_store::DBCol;
+
+/// Maximum number of blocks kept in the quarantine.
+const MAX_QUARANTINED_BLOCKS: usize = 100;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct QuarantinedBlock {
+    pub block: Block,
+    pub error: String,
+    pub peer_id: Option<PeerId>,
+    /// Unix timestamp in nanoseconds.
+    pub quarantined_at: u64,
+}
+
+impl QuarantinedBlock {
+    fn to_view(&self) -> QuarantinedBlockView {
+        QuarantinedBlockView {
+            height: self.block.header().height(),
+            hash: *self.block.hash(),
+            prev_hash: *self.block.header().prev_hash(),
+            error: self.error.clone(),
+            peer_id: self.peer_id.clone(),
+            quarantined_timestamp: chrono::Utc.timestamp_nanos(self.quarantined_at as i64),
+        }
+    }
+}
+
+impl ChainStore {
+    /// Saves an invalid block in the quarantine, evicting the oldest entries
+    /// if the quarantine is full.
+    pub fn quarantine_block(
+        &self,
+        block: &Block,
+        error: &Error,
+        peer_id: Option<PeerId>,
+    ) -> Result<(), Error> {
+        let entry = QuarantinedBlock {
+            block: block.clone(),
+            error: error.to_string(),
+            peer_id,
+            quarantined_at: StaticClock::utc().timestamp_nanos() as u64,
+        };
+        let mut entries = self.get_quarantined_blocks()?;
+        entries.retain(|view| &view.hash != block.hash());
+        let mut store_update = self.store.store_update();
+        let num_evicted = (entries.len() + 1).saturating_sub(MAX_QUARANTINED_BLOCKS);
+        for view in entries.iter().rev().take(num_evicted) {
+            store_update.delete(DBCol::QuarantinedBlocks, view.hash.as_ref());
+        }
+        store_update.set_ser(DBCol::QuarantinedBlocks, block.hash().as_ref(), &entry)?;
+        store_update.commit()?;
+        Ok(())
+    }
+
+    /// Returns summaries of quarantined blocks, the most recently quarantined
+    /// first.
+    pub fn get_quarantined_blocks(&self) -> Result<Vec<QuarantinedBlockView>, Error> {
+        let mut entries = vec![];
+        for item in self.store.iter(DBCol::QuarantinedBlocks) {
+            let (_, value) = item?;
+            entries.push(QuarantinedBlock::try_from_slice(&value)?.to_view());
+        }
+        entries.sort_by(|a, b| b.quarantined_timestamp.cmp(&a.quarantined_timestamp));
+        Ok(entries)
+    }
+
+    pub fn get_quarantined_block(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<QuarantinedBlockDetailsView>, Error> {
+        let entry: Option<QuarantinedBlock> =
+            self.store.get_ser(DBCol::QuarantinedBlocks, block_hash.as_ref())?;
+        let Some(entry) = entry else { return Ok(None) };
+        Ok(Some(QuarantinedBlockDetailsView {
+            entry: entry.to_view(),
+            header: entry.block.header().clone().into(),
+            chunks: entry.block.chunks().iter().cloned().map(Into::into).collect(),
+            block: entry.block.try_to_vec()?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_QUARANTINED_BLOCKS;
+    use crate::test_utils::setup;
+    use near_chain_primitives::Error;
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::network::PeerId;
+    use near_primitives::static_clock::MockClockGuard;
+    use near_primitives::test_utils::TestBlockBuilder;
+
+    #[test]
+    fn test_quarantine_block() {
+        let (chain, _, _, signer) = setup();
+        let mut blocks = vec![chain.get_block_by_height(0).unwrap()];
+        for height in 1..MAX_QUARANTINED_BLOCKS as u64 + 3 {
+            let block = TestBlockBuilder::new(blocks.last().unwrap(), signer.clone())
+                .height(height)
+                .build();
+            blocks.push(block);
+        }
+        let peer_id = PeerId::new(PublicKey::empty(KeyType::ED25519));
+        let mock_clock_guard = MockClockGuard::default();
+        let start = chrono::Utc::now();
+        for (i, block) in blocks.iter().enumerate() {
+            mock_clock_guard.add_utc(start + chrono::Duration::seconds(i as i64));
+            chain
+                .store()
+                .quarantine_block(block, &Error::InvalidChunk, Some(peer_id.clone()))
+                .unwrap();
+        }
+
+        // Only the most recently quarantined blocks are kept.
+        let entries = chain.store().get_quarantined_blocks().unwrap();
+        assert_eq!(entries.len(), MAX_QUARANTINED_BLOCKS);
+        let last = blocks.last().unwrap();
+        assert_eq!(&entries[0].hash, last.hash());
+        assert_eq!(
+            &entries.last().unwrap().hash,
+            blocks[blocks.len() - MAX_QUARANTINED_BLOCKS].hash()
+        );
+        assert!(chain.store().get_quarantined_block(blocks[0].hash()).unwrap().is_none());
+
+        let details = chain.store().get_quarantined_block(last.hash()).unwrap().unwrap();
+        assert_eq!(details.entry.error, Error::InvalidChunk.to_string());
+        assert_eq!(details.entry.peer_id, Some(peer_id));
+        assert_eq!(details.header.height, last.header().height());
+        assert_eq!(details.chunks.len(), last.chunks().len());
+    }
+}
@@ -857,10 +857,12 @@ pub(crate) fn state_part_header_exists(
         StateHeaderKey(shard_id, block_hash).try_to_vec(),
         "Can't serialize StateHeaderKey"
     );
-    let header = unwrap_or_err_db!(
+    let header = unwrap_or_err!(
         sv.store.get_ser::<ShardStateSyncResponseHeader>(DBCol::StateHeaders, &state_header_key),
         "Can't get StateHeaderKey from DB"
     );
+    // State parts outlive garbage collected state headers until they expire.
+    let Some(header) = header else { return Ok(()) };
     let num_parts = get_num_state_parts(header.state_root_node().memory_usage);
     if part_id >= num_parts {
         err!("Invalid part_id {:?}, num_parts {:?}", part_id, num_parts)
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
@@ -27,6 +28,7 @@ use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::shard_layout;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::sharding::ChunkHash;
+use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{
     Action, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus,
     SignedTransaction, TransferAction,
@@ -39,7 +41,8 @@ use near_primitives::types::{
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
     AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    QueryRequest, QueryResponse, QueryResponseKind, SimulationRequest, SimulationResultView,
+    ViewStateResult,
 };
 use near_store::{
     set_genesis_hash, set_genesis_state_roots, DBCol, PartialStorage, ShardTries, Store,
@@ -1004,6 +1007,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         transactions: &mut dyn PoolIterator,
         _chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
         _current_protocol_version: ProtocolVersion,
+        _time_limit: Option<Duration>,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let mut res = vec![];
         while let Some(iter) = transactions.next() {
@@ -1016,7 +1020,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         &self,
         shard_id: ShardId,
         state_root: &StateRoot,
-        _height: BlockHeight,
+        height: BlockHeight,
         _block_timestamp: u64,
         _prev_block_hash: &CryptoHash,
         block_hash: &CryptoHash,
@@ -1171,6 +1175,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 TrieChanges::empty(state_root),
                 Default::default(),
                 *block_hash,
+                height,
             ),
             new_root: state_root,
             outcomes: tx_results,
@@ -1180,9 +1185,20 @@ impl RuntimeAdapter for KeyValueRuntime {
             total_balance_burnt: 0,
             proof: None,
             processed_delayed_receipts: vec![],
+            apply_stats: Default::default(),
         })
     }
 
+    fn prefetch_receipts(
+        &self,
+        _shard_id: ShardId,
+        _prev_hash: &CryptoHash,
+        _state_root: StateRoot,
+        _receipts: &[Receipt],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn check_state_transition(
         &self,
         _partial_storage: PartialStorage,
@@ -1260,6 +1276,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 kind: QueryResponseKind::ViewState(ViewStateResult {
                     values: Default::default(),
                     proof: vec![],
+                    continuation_token: None,
                 }),
                 block_height,
                 block_hash: *block_hash,
@@ -1275,6 +1292,21 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
     }
 
+    fn simulate(
+        &self,
+        _state_roots: &HashMap<ShardUId, StateRoot>,
+        _block_height: BlockHeight,
+        _block_timestamp: u64,
+        _prev_block_hash: &CryptoHash,
+        _block_hash: &CryptoHash,
+        _epoch_id: &EpochId,
+        _gas_price: Balance,
+        _state_overrides: &[StateRecord],
+        _request: &SimulationRequest,
+    ) -> Result<SimulationResultView, near_chain_primitives::error::QueryError> {
+        unimplemented!("simulate is not supported by KeyValueRuntime");
+    }
+
     fn obtain_state_part(
         &self,
         _shard_id: ShardId,
@@ -1391,6 +1423,7 @@ impl RuntimeAdapter for KeyValueRuntime {
     fn apply_update_to_split_states(
         &self,
         _block_hash: &CryptoHash,
+        _block_height: BlockHeight,
         _state_roots: HashMap<ShardUId, StateRoot>,
         _next_shard_layout: &ShardLayout,
         _state_changes: StateChangesForSplitStates,
@@ -11,9 +11,12 @@ use near_primitives::block::Block;
 use near_primitives::merkle::PartialMerkleTree;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::test_utils::{create_test_signer, TestBlockBuilder};
+use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{NumBlocks, NumShards, StateRoot};
+use near_store::config::MemTrieConsistencyCheckConfig;
+use near_store::flat::FlatStorageManager;
 use near_store::test_utils::{create_test_store, gen_changes};
-use near_store::{ShardTries, Trie, WrappedTrieChanges};
+use near_store::{ShardTries, Trie, TrieConfig, WrappedTrieChanges};
 use rand::Rng;
 
 fn get_chain(num_shards: NumShards) -> Chain {
@@ -139,6 +142,7 @@ fn do_fork(
                 trie_changes,
                 Default::default(),
                 *block.hash(),
+                block.header().height(),
             );
             store_update.save_trie_changes(wrapped_trie_changes);
 
@@ -717,3 +721,61 @@ fn test_fork_far_away_from_epoch_end() {
         );
     }
 }
+
+#[test]
+// The in-memory tries follow the chain as trie changes are committed, and are
+// compared against the disk state at the head once per epoch.
+fn test_mem_trie_consistency_check_on_epoch_change() {
+    let shard_uid = ShardUId::single_shard();
+    let mut chain = get_chain_with_epoch_length_and_num_shards(5, 1);
+    let store = chain.runtime_adapter.get_tries().get_store();
+    let trie_config = TrieConfig {
+        mem_trie_consistency_check: MemTrieConsistencyCheckConfig { enabled: true, num_keys: 10 },
+        ..TrieConfig::default()
+    };
+    let tries =
+        ShardTries::new(store.clone(), trie_config, &[shard_uid], FlatStorageManager::new(store));
+    tries.set_mem_tries(tries.new_mem_tries(shard_uid));
+
+    let genesis = chain.get_block_by_height(0).unwrap();
+    let mut states = vec![(genesis, vec![Trie::EMPTY_ROOT], vec![Vec::new()])];
+    loop {
+        let (prev_block, prev_state_roots, _) = states.last().unwrap().clone();
+        do_fork(
+            prev_block.clone(),
+            prev_state_roots,
+            tries.clone(),
+            &mut chain,
+            1,
+            &mut states,
+            10,
+            false,
+        );
+        let (block, _, _) = states.last().unwrap();
+        if block.header().height() > 1
+            && block.header().epoch_id() != prev_block.header().epoch_id()
+        {
+            break;
+        }
+    }
+    let (block, state_roots, _) = states.last().unwrap().clone();
+    let mut store_update = chain.mut_store().store_update();
+    store_update.save_chunk_extra(
+        block.hash(),
+        &shard_uid,
+        ChunkExtra::new_with_only_state_root(&state_roots[0]),
+    );
+    store_update.commit().unwrap();
+    let mem_tries = tries.get_mem_tries(shard_uid).unwrap();
+    assert!(mem_tries.read().unwrap().get_root(&state_roots[0]).is_some());
+
+    let head = chain.head().unwrap();
+    let handles = chain.spawn_mem_trie_consistency_checks(&tries, &head);
+    assert_eq!(handles.len(), 1);
+    for handle in handles {
+        let report = handle.join().unwrap().unwrap();
+        assert!(report.keys_checked > 0);
+        assert_eq!(report.divergences, vec![]);
+    }
+    chain.clear_data(tries, &GCConfig::default()).unwrap();
+}
@@ -1,10 +1,11 @@
 use crate::near_chain_primitives::error::BlockKnownError;
 use crate::test_utils::{setup, wait_for_all_blocks_in_processing};
-use crate::{Block, BlockProcessingArtifact, ChainStoreAccess, Error};
+use crate::{Block, BlockProcessingArtifact, ChainEvent, ChainStoreAccess, Error};
 use assert_matches::assert_matches;
 use chrono;
 use chrono::TimeZone;
 use near_o11y::testonly::init_test_logger;
+use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::static_clock::MockClockGuard;
 use near_primitives::test_utils::TestBlockBuilder;
@@ -140,6 +141,67 @@ fn build_chain_with_orphans() {
     );
 }
 
+/// Checks that orphans on many forks which are released together, and so are
+/// prevalidated in parallel, are all processed.
+#[test]
+fn build_chain_with_orphans_on_forks() {
+    init_test_logger();
+    let (mut chain, _, _, signer) = setup();
+    let genesis = chain.get_block_by_height(0).unwrap();
+    let b1 = TestBlockBuilder::new(&genesis, signer.clone()).height(1).build();
+    let forks: Vec<_> = (2..6)
+        .map(|height| TestBlockBuilder::new(&b1, signer.clone()).height(height).build())
+        .collect();
+    for block in &forks {
+        assert_matches!(chain.process_block_test(&None, block.clone()).unwrap_err(), Error::Orphan);
+    }
+    chain.process_block_test(&None, b1).unwrap();
+    while wait_for_all_blocks_in_processing(&mut chain) {
+        chain.postprocess_ready_blocks(
+            &None,
+            &mut BlockProcessingArtifact::default(),
+            Arc::new(|_| {}),
+        );
+    }
+    for block in &forks {
+        assert!(chain.block_exists(block.hash()).unwrap());
+    }
+    assert_eq!(&chain.head().unwrap().last_block_hash, forks.last().unwrap().hash());
+}
+
+#[test]
+fn chain_events_on_forks() {
+    init_test_logger();
+    let (mut chain, _, _, signer) = setup();
+    let events = chain.chain_event_bus().subscribe();
+    let genesis = chain.get_block_by_height(0).unwrap();
+    let b1 = TestBlockBuilder::new(&genesis, signer.clone()).height(1).build();
+    let b2 = TestBlockBuilder::new(&b1, signer.clone()).height(2).build();
+    let c3 = TestBlockBuilder::new(&genesis, signer.clone()).height(3).build();
+    for block in [&b1, &b2, &c3] {
+        chain.process_block_test(&None, block.clone()).unwrap();
+    }
+    let head_events: Vec<_> = events
+        .try_iter()
+        .filter(|event| matches!(event, ChainEvent::NewHead(_) | ChainEvent::Reorg { .. }))
+        .collect();
+    let tip = |block: &Block| Tip::from_header(block.header());
+    assert_eq!(
+        head_events,
+        vec![
+            ChainEvent::NewHead(tip(&b1)),
+            ChainEvent::NewHead(tip(&b2)),
+            ChainEvent::Reorg {
+                old_head: tip(&b2),
+                new_head: tip(&c3),
+                depth: 2,
+                rolled_back_blocks: 2,
+            },
+            ChainEvent::NewHead(tip(&c3)),
+        ]
+    );
+}
+
 /// Checks that chain successfully processes blocks with skipped blocks and forks, but doesn't process block behind
 /// final head.
 #[test]
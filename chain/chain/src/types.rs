@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use chrono::DateTime;
@@ -8,7 +9,7 @@ use near_store::flat::FlatStorageManager;
 use num_rational::Rational32;
 
 use crate::metrics;
-use near_chain_configs::{Genesis, ProtocolConfig};
+use near_chain_configs::{Genesis, MutableConfigValue, ProtocolConfig, ReshardingConfig};
 use near_chain_primitives::Error;
 use near_pool::types::PoolIterator;
 use near_primitives::challenge::ChallengesResult;
@@ -19,6 +20,7 @@ use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::state_part::PartId;
+use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
@@ -29,7 +31,9 @@ use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{QueryRequest, QueryResponse};
+use near_primitives::views::{
+    QueryRequest, QueryResponse, SimulationRequest, SimulationResultView,
+};
 use near_store::{PartialStorage, ShardTries, Store, Trie, WrappedTrieChanges};
 
 pub use near_epoch_manager::EpochManagerAdapter;
@@ -108,6 +112,19 @@ pub struct ApplyTransactionResult {
     pub total_balance_burnt: Balance,
     pub proof: Option<PartialStorage>,
     pub processed_delayed_receipts: Vec<Receipt>,
+    pub apply_stats: ChunkApplyStats,
+}
+
+/// Breakdown of the work done applying a chunk, reported per shard in
+/// `metrics::APPLYING_CHUNKS_TIME_BREAKDOWN` and `metrics::APPLYING_CHUNKS_RECEIPTS`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChunkApplyStats {
+    /// Time spent reading state, from flat storage or the trie.
+    pub storage_read_time: std::time::Duration,
+    /// Time spent executing contracts, including the host functions they call.
+    pub wasm_time: std::time::Duration,
+    /// Number of receipts executed.
+    pub receipts_processed: u64,
 }
 
 impl ApplyTransactionResult {
@@ -211,6 +228,11 @@ pub struct ChainConfig {
     /// Currently used for flat storage background creation.
     pub background_migration_threads: usize,
     pub state_snapshot_every_n_blocks: Option<u64>,
+    /// Directory replay artifacts of chunks whose outcome doesn't match the
+    /// one claimed by the next chunk are written to, see `chunk_replay`.
+    pub chunk_replay_artifacts_dir: Option<PathBuf>,
+    /// Throttling of building the state of the child shards during resharding.
+    pub resharding_config: MutableConfigValue<ReshardingConfig>,
 }
 
 impl ChainConfig {
@@ -219,6 +241,11 @@ impl ChainConfig {
             save_trie_changes: true,
             background_migration_threads: 1,
             state_snapshot_every_n_blocks: None,
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
         }
     }
 }
@@ -293,6 +320,8 @@ pub trait RuntimeAdapter: Send + Sync {
     /// against the given `chain_validate` closure and runtime's transaction verifier.
     /// If the transaction is valid for both, it's added to the result and the temporary state
     /// update is preserved for validation of next transactions.
+    /// If `time_limit` is set, stops pulling transactions once that much time has passed, and
+    /// returns the transactions selected so far.
     /// Throws an `Error` with `ErrorKind::StorageError` in case the runtime throws
     /// `RuntimeError::StorageError`.
     fn prepare_transactions(
@@ -306,6 +335,7 @@ pub trait RuntimeAdapter: Send + Sync {
         pool_iterator: &mut dyn PoolIterator,
         chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
         current_protocol_version: ProtocolVersion,
+        time_limit: Option<std::time::Duration>,
     ) -> Result<Vec<SignedTransaction>, Error>;
 
     /// Returns true if the shard layout will change in the next epoch
@@ -315,6 +345,19 @@ pub trait RuntimeAdapter: Send + Sync {
     /// Get the block height for which garbage collection should not go over
     fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight;
 
+    /// Warms up the storage caches for applying the given receipts on top of `state_root`, the
+    /// post state root of `prev_hash` in the given shard. Reads the accounts of the receivers
+    /// through flat storage, and the code of the contracts called by the receipts, which is also
+    /// compiled if it isn't in the compiled contract cache yet. Meant to be called on a
+    /// background thread before the chunk applying the receipts is applied.
+    fn prefetch_receipts(
+        &self,
+        shard_id: ShardId,
+        prev_hash: &CryptoHash,
+        state_root: StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<(), Error>;
+
     /// Apply transactions to given state root and return store update and new state root.
     /// Also returns transaction result for each transaction and new receipts.
     fn apply_transactions(
@@ -337,9 +380,9 @@ pub trait RuntimeAdapter: Send + Sync {
         state_patch: SandboxStatePatch,
         use_flat_storage: bool,
     ) -> Result<ApplyTransactionResult, Error> {
-        let _timer =
-            metrics::APPLYING_CHUNKS_TIME.with_label_values(&[&shard_id.to_string()]).start_timer();
-        self.apply_transactions_with_optional_storage_proof(
+        let shard_label = shard_id.to_string();
+        let _timer = metrics::APPLYING_CHUNKS_TIME.with_label_values(&[&shard_label]).start_timer();
+        let result = self.apply_transactions_with_optional_storage_proof(
             shard_id,
             state_root,
             height,
@@ -358,7 +401,9 @@ pub trait RuntimeAdapter: Send + Sync {
             is_first_block_with_chunk_of_version,
             state_patch,
             use_flat_storage,
-        )
+        )?;
+        metrics::report_apply_stats(&shard_label, &result.apply_stats);
+        Ok(result)
     }
 
     fn apply_transactions_with_optional_storage_proof(
@@ -416,6 +461,23 @@ pub trait RuntimeAdapter: Send + Sync {
         request: &QueryRequest,
     ) -> Result<QueryResponse, near_chain_primitives::error::QueryError>;
 
+    /// Runs the simulation on top of the state after the block, with the state overrides
+    /// applied. Nothing is written to the storage. `state_roots` are the roots of the state
+    /// after the block of the shards tracked by the node; the simulation fails if it needs
+    /// the state of another shard.
+    fn simulate(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        block_height: BlockHeight,
+        block_timestamp: u64,
+        prev_block_hash: &CryptoHash,
+        block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        gas_price: Balance,
+        state_overrides: &[StateRecord],
+        request: &SimulationRequest,
+    ) -> Result<SimulationResultView, near_chain_primitives::error::QueryError>;
+
     /// Get part of the state corresponding to the given state root.
     /// `prev_hash` is a block whose post state root is `state_root`.
     /// Returns error when storage is inconsistent.
@@ -434,6 +496,7 @@ pub trait RuntimeAdapter: Send + Sync {
     fn apply_update_to_split_states(
         &self,
         block_hash: &CryptoHash,
+        block_height: BlockHeight,
         state_roots: HashMap<ShardUId, StateRoot>,
         next_shard_layout: &ShardLayout,
         state_changes: StateChangesForSplitStates,
@@ -39,14 +39,27 @@ pub struct ShardedTransactionPool {
     /// while keeping the security of randomization of transactions in pool
     rng_seed: RngSeed,
 
-    /// If set, new transactions that bring the size of the pool over this limit will be rejected.
+    /// If set, new transactions that bring the size of the pool over this limit will either evict
+    /// transactions attaching less gas or be rejected.
     /// The size is tracked and enforced separately for each shard.
     pool_size_limit: Option<u64>,
+
+    /// Like `pool_size_limit`, but for the number of transactions in the pool of each shard.
+    pool_count_limit: Option<usize>,
 }
 
 impl ShardedTransactionPool {
-    pub fn new(rng_seed: RngSeed, pool_size_limit: Option<u64>) -> Self {
-        Self { tx_pools: HashMap::new(), rng_seed, pool_size_limit }
+    pub fn new(
+        rng_seed: RngSeed,
+        pool_size_limit: Option<u64>,
+        pool_count_limit: Option<usize>,
+    ) -> Self {
+        Self { tx_pools: HashMap::new(), rng_seed, pool_size_limit, pool_count_limit }
+    }
+
+    /// Returns the pools of all shards which had transactions so far.
+    pub fn pools(&self) -> impl Iterator<Item = (&ShardUId, &TransactionPool)> {
+        self.tx_pools.iter()
     }
 
     pub fn get_pool_iterator(&mut self, shard_uid: ShardUId) -> Option<PoolIteratorWrapper<'_>> {
@@ -84,6 +97,7 @@ impl ShardedTransactionPool {
             TransactionPool::new(
                 Self::random_seed(&self.rng_seed, shard_uid.shard_id()),
                 self.pool_size_limit,
+                self.pool_count_limit,
                 &shard_uid.to_string(),
             )
         })
@@ -100,8 +114,12 @@ impl ShardedTransactionPool {
         let pool = self.pool_for_shard(shard_uid);
         for tx in transactions {
             reintroduced_count += match pool.insert_transaction(tx.clone()) {
-                InsertTransactionResult::Success | InsertTransactionResult::Duplicate => 1,
-                InsertTransactionResult::NoSpaceLeft => 0,
+                InsertTransactionResult::Success
+                | InsertTransactionResult::Replaced
+                | InsertTransactionResult::Duplicate => 1,
+                InsertTransactionResult::NoSpaceLeft
+                | InsertTransactionResult::ReplacementUnderpriced
+                | InsertTransactionResult::TooManyFutureTransactions => 0,
             }
         }
         reintroduced_count
@@ -125,12 +143,18 @@ impl ShardedTransactionPool {
 
         let old_shard_uids = old_shard_layout.get_shard_uids();
         for old_shard_uid in old_shard_uids {
-            if let Some(mut iter) = self.get_pool_iterator(old_shard_uid) {
+            // Transactions held back because of nonce gaps are released when the iterator is
+            // dropped, so iterate until nothing is left.
+            while let Some(mut iter) = self.get_pool_iterator(old_shard_uid) {
+                let num_transactions = transactions.len();
                 while let Some(group) = iter.next() {
                     while let Some(tx) = group.next() {
                         transactions.push(tx);
                     }
                 }
+                if transactions.len() == num_transactions {
+                    break;
+                }
             }
         }
 
@@ -188,7 +212,7 @@ mod tests {
         let old_shard_layout = ShardLayout::get_simple_nightshade_layout();
         let new_shard_layout = ShardLayout::get_simple_nightshade_layout_v2();
 
-        let mut pool = ShardedTransactionPool::new(TEST_SEED, None);
+        let mut pool = ShardedTransactionPool::new(TEST_SEED, None, None);
 
         let mut shard_id_to_accounts = HashMap::new();
         shard_id_to_accounts.insert(0, vec!["aaa", "abcd", "a-a-a-a-a"]);
@@ -205,14 +229,18 @@ mod tests {
 
         let n = 100;
         tracing::info!("inserting {n} transactions into the pool using the old shard layout");
-        for i in 0..n {
+        // Nonces are contiguous per signer, as transactions following a nonce gap are held back.
+        let mut nonces = HashMap::new();
+        for _ in 0..n {
             let num_shards = old_shard_layout.num_shards();
             let signer_shard_id = rng.gen_range(0..num_shards);
             let receiver_shard_id = rng.gen_range(0..num_shards);
-            let nonce = i as u64;
 
             let signer_id = *shard_id_to_accounts[&signer_shard_id].choose(&mut rng).unwrap();
             let signer_id = AccountId::from_str(signer_id).unwrap();
+            let nonce = nonces.entry(signer_id.clone()).or_insert(0u64);
+            *nonce += 1;
+            let nonce = *nonce;
 
             let receiver_id = *shard_id_to_accounts[&receiver_shard_id].choose(&mut rng).unwrap();
             let receiver_id = AccountId::from_str(receiver_id).unwrap();
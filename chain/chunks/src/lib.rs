@@ -18,6 +18,8 @@
 //! - by receiving a PartialEncodedChunkForward, which is sent from part owners
 //!   to validators who track the shard, when a validator first receives a part
 //!   it owns.  TODO(#5886): this is actually not the current behavior.
+//!   With many validators, every validator is forwarded only a subset of the
+//!   parts, see ** Forwarding chunks.
 //! Note that last two messages can only be sent from validators to validators,
 //! so the only way a non-validator receives a partial encoded chunk is by
 //! requesting it.
@@ -47,9 +49,14 @@
 //! To save messages and time for chunks to propagate among validators, we implemented chunk part
 //! forwarding. When a validator receives a part it owns, it forwards the part to
 //! other validators who are assigned to track the shard through a PartialEncodedChunkForward message.
-//! This saves the number of requests validators need to send to get all parts they need. A forwarded
-//! part can only be processed after the node has the corresponding chunk header, either from blocks
-//! or partial chunk requests. Before that, they are temporarily stored in `chunk_forwards_cache`.
+//! This saves the number of requests validators need to send to get all parts they need.
+//! A validator only needs enough distinct parts to reconstruct the chunk, so once there are many
+//! parts, every validator is forwarded only a deterministic subset of them (see
+//! `logic::is_part_pushed_to`), which is somewhat larger than the number of data parts, so that
+//! slow or offline part owners don't delay the reconstruction. Parts still missing once the
+//! forwarding times out are requested as usual.
+//! A forwarded part can only be processed after the node has the corresponding chunk header,
+//! either from blocks or partial chunk requests. Before that, they are temporarily stored in `chunk_forwards_cache`.
 //! After that, they are processed as a PartialEncodedChunk message containing the cached parts.
 //!
 //! ** Processing chunks
@@ -83,8 +90,9 @@ use crate::logic::{cares_about_shard_this_or_next_epoch, chunk_needs_to_be_fetch
 use adapter::ShardsManagerRequestFromClient;
 use client::ShardsManagerResponse;
 use logic::{
-    decode_encoded_chunk, make_outgoing_receipts_proofs,
+    decode_encoded_chunk, is_part_pushed_to, make_outgoing_receipts_proofs,
     make_partial_encoded_chunk_from_owned_parts_and_needed_receipts, need_part, need_receipt,
+    num_pushed_parts,
 };
 use metrics::{
     PARTIAL_ENCODED_CHUNK_FORWARD_CACHED_WITHOUT_HEADER,
@@ -1710,11 +1718,6 @@ impl ShardsManager {
             return Ok(());
         }
 
-        let forward = PartialEncodedChunkForwardMsg::from_header_and_parts(
-            &partial_encoded_chunk.header,
-            owned_parts,
-        );
-
         let block_producers =
             self.epoch_manager.get_epoch_block_producers_ordered(&epoch_id, lastest_block_hash)?;
         let current_chunk_height = partial_encoded_chunk.header.height_created();
@@ -1737,29 +1740,56 @@ impl ShardsManager {
             // We don't because with the current implementation, we force all validators to track all
             // shards by making their config tracking all shards.
             // See https://github.com/near/nearcore/issues/7388
-            self.peer_manager_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-                NetworkRequests::PartialEncodedChunkForward {
-                    account_id: bp_account_id,
-                    forward: forward.clone(),
-                },
-            ));
+            self.forward_pushed_parts(&partial_encoded_chunk.header, &owned_parts, bp_account_id);
         }
 
         // We also forward chunk parts to incoming chunk producers because we want them to be able
         // to produce the next chunk without delays. For the same reason as above, we don't check if they
         // actually track this shard.
         for next_chunk_producer in next_chunk_producers {
-            self.peer_manager_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-                NetworkRequests::PartialEncodedChunkForward {
-                    account_id: next_chunk_producer,
-                    forward: forward.clone(),
-                },
-            ));
+            self.forward_pushed_parts(
+                &partial_encoded_chunk.header,
+                &owned_parts,
+                next_chunk_producer,
+            );
         }
 
         Ok(())
     }
 
+    /// Forwards to `account_id` those of `owned_parts` which are pushed to it,
+    /// see `is_part_pushed_to`.
+    fn forward_pushed_parts(
+        &self,
+        header: &ShardChunkHeader,
+        owned_parts: &[PartialEncodedChunkPart],
+        account_id: AccountId,
+    ) {
+        let chunk_hash = header.chunk_hash();
+        let num_total_parts = self.rs.total_shard_count();
+        let num_pushed_parts = num_pushed_parts(num_total_parts, self.rs.data_shard_count());
+        let parts: Vec<_> = owned_parts
+            .iter()
+            .filter(|part| {
+                is_part_pushed_to(
+                    &chunk_hash,
+                    &account_id,
+                    part.part_ord,
+                    num_total_parts,
+                    num_pushed_parts,
+                )
+            })
+            .cloned()
+            .collect();
+        if parts.is_empty() {
+            return;
+        }
+        let forward = PartialEncodedChunkForwardMsg::from_header_and_parts(header, parts);
+        self.peer_manager_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::PartialEncodedChunkForward { account_id, forward },
+        ));
+    }
+
     /// Returns true if we have all the necessary receipts for this chunk entry to process it.
     /// NOTE: this doesn't mean that we got *all* the receipts.
     /// It means that we have all receipts included in this chunk sending to the shards we track.
@@ -2270,6 +2300,83 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_pushed_parts() {
+        let chunk_hash = ChunkHash(hash(&[1]));
+        let accounts: Vec<AccountId> =
+            (0..100).map(|i| format!("test{i}").parse().unwrap()).collect();
+        // With few parts, all of them are pushed.
+        assert_eq!(logic::num_pushed_parts(12, 3), 12);
+        let num_pushed_parts = logic::num_pushed_parts(100, 33);
+        assert_eq!(num_pushed_parts, 50);
+        let mut pushed_to_anyone = HashSet::new();
+        for account_id in &accounts {
+            let pushed: Vec<u64> = (0..100)
+                .filter(|part_ord| {
+                    logic::is_part_pushed_to(
+                        &chunk_hash,
+                        account_id,
+                        *part_ord,
+                        100,
+                        num_pushed_parts,
+                    )
+                })
+                .collect();
+            assert_eq!(pushed.len(), num_pushed_parts);
+            pushed_to_anyone.extend(pushed);
+        }
+        assert_eq!(pushed_to_anyone.len(), 100);
+    }
+
+    #[test]
+    fn test_chunk_forwarding_to_subsets() {
+        // Tests that with many validators, each of them is forwarded only the parts pushed to it.
+        let fixture = ChunkTestFixture::new(false, 3, 40, 0, true);
+        let mut shards_manager = ShardsManager::new(
+            FakeClock::default().clock(),
+            Some(fixture.mock_chunk_part_owner.clone()),
+            Arc::new(fixture.epoch_manager.clone()),
+            fixture.shard_tracker.clone(),
+            fixture.mock_network.as_sender(),
+            fixture.mock_client_adapter.as_sender(),
+            fixture.chain_store.new_read_only_chunks_store(),
+            fixture.mock_chain_head.clone(),
+            fixture.mock_chain_head.clone(),
+        );
+        let partial_encoded_chunk = fixture.make_partial_encoded_chunk(&fixture.mock_part_ords);
+        shards_manager
+            .process_partial_encoded_chunk(MaybeValidated::from(partial_encoded_chunk))
+            .unwrap();
+
+        let num_total_parts = fixture.epoch_manager.num_total_parts();
+        let num_pushed_parts =
+            logic::num_pushed_parts(num_total_parts, fixture.epoch_manager.num_data_parts());
+        assert!(num_pushed_parts < num_total_parts);
+        let chunk_hash = fixture.mock_chunk_header.chunk_hash();
+        let mut num_forwards = 0;
+        while let Some(request) = fixture.mock_network.pop() {
+            if let NetworkRequests::PartialEncodedChunkForward { account_id, forward } =
+                request.as_network_requests_ref()
+            {
+                num_forwards += 1;
+                assert!(!forward.parts.is_empty());
+                for part in &forward.parts {
+                    assert!(fixture.mock_part_ords.contains(&part.part_ord));
+                    assert!(logic::is_part_pushed_to(
+                        &chunk_hash,
+                        account_id,
+                        part.part_ord,
+                        num_total_parts,
+                        num_pushed_parts,
+                    ));
+                }
+            }
+        }
+        assert!(num_forwards > 0);
+        // Without the subsets, the parts would be forwarded to all the other block producers.
+        assert!(num_forwards < 39);
+    }
+
     #[derive(PartialEq, Eq, Debug)]
     struct RequestChunksResult {
         marked_as_requested: bool,
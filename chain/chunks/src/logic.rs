@@ -7,8 +7,9 @@ use near_primitives::{
     merkle::{merklize, MerklePath},
     receipt::Receipt,
     sharding::{
-        EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart, PartialEncodedChunkV1,
-        PartialEncodedChunkV2, ReceiptProof, ShardChunk, ShardChunkHeader, ShardProof,
+        ChunkHash, EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart,
+        PartialEncodedChunkV1, PartialEncodedChunkV2, ReceiptProof, ShardChunk, ShardChunkHeader,
+        ShardProof,
     },
     types::{AccountId, ShardId},
 };
@@ -68,6 +69,40 @@ pub fn chunk_needs_to_be_fetched_from_archival(
         && chunk_epoch_id != head_next_epoch_id)
 }
 
+/// Minimal number of parts at which validators are pushed only a subset of
+/// the parts.  Below it, the savings are not worth the risk of waiting for a
+/// slow part owner.
+const MIN_TOTAL_PARTS_FOR_PUSHED_SUBSETS: usize = 30;
+
+/// Returns how many distinct parts of a chunk are forwarded to each
+/// validator by the part owners.  It is above the number of data parts, so
+/// that a few slow or offline part owners don't make the validator fall back
+/// to requesting the parts.
+pub fn num_pushed_parts(num_total_parts: usize, num_data_parts: usize) -> usize {
+    if num_total_parts < MIN_TOTAL_PARTS_FOR_PUSHED_SUBSETS {
+        return num_total_parts;
+    }
+    (num_data_parts + num_data_parts / 2 + 1).min(num_total_parts)
+}
+
+/// Returns true if the part `part_ord` of the chunk is forwarded to
+/// `account_id` by its owner.  Every validator is forwarded
+/// `num_pushed_parts` consecutive parts, starting at an offset derived from
+/// the chunk hash and the account, so that the parts are spread evenly and
+/// differently for every chunk.
+pub fn is_part_pushed_to(
+    chunk_hash: &ChunkHash,
+    account_id: &AccountId,
+    part_ord: u64,
+    num_total_parts: usize,
+    num_pushed_parts: usize,
+) -> bool {
+    let num_total_parts = num_total_parts as u64;
+    let seed = CryptoHash::hash_borsh((chunk_hash, account_id));
+    let offset = u64::from_le_bytes(seed.0[..8].try_into().unwrap()) % num_total_parts;
+    (part_ord + num_total_parts - offset) % num_total_parts < num_pushed_parts as u64
+}
+
 /// Constructs receipt proofs for specified chunk and returns them in an
 /// iterator.
 pub fn make_outgoing_receipts_proofs(
@@ -4,8 +4,9 @@ use crate::types::StatusError;
 use chrono::DateTime;
 use near_primitives::types::EpochId;
 use near_primitives::views::{
-    CatchupStatusView, ChainProcessingInfo, EpochValidatorInfo, RequestedStatePartsView,
-    SyncStatusView,
+    BlockTimelineView, CatchupStatusView, ChainProcessingInfo, ColumnStatsView, EpochValidatorInfo,
+    QuarantinedBlockDetailsView, QuarantinedBlockView, RequestedStatePartsView, SyncStatusView,
+    TransactionPoolView,
 };
 use near_primitives::{
     block_header::ApprovalInner,
@@ -147,6 +148,17 @@ pub struct ApprovalAtHeightStatus {
     pub ready_at: Option<DateTime<chrono::Utc>>,
 }
 
+// Latency of approvals received from a validator, measured from the start of
+// block production at the approval's target height.
+#[derive(serde::Serialize, Debug)]
+pub struct ApprovalLatencyView {
+    pub account_id: AccountId,
+    // Number of recent approvals the percentiles are computed from.
+    pub num_samples: usize,
+    pub p50_millis: u64,
+    pub p95_millis: u64,
+}
+
 #[derive(serde::Serialize, Debug)]
 pub struct ValidatorStatus {
     pub validator_name: Option<AccountId>,
@@ -158,6 +170,8 @@ pub struct ValidatorStatus {
     pub validators: Option<Vec<(AccountId, u64)>>,
     // All approvals that we've sent.
     pub approval_history: Vec<ApprovalHistoryEntry>,
+    // Latencies of approvals received from other validators, slowest first.
+    pub approval_latencies: Vec<ApprovalLatencyView>,
     // Blocks & chunks that we've produced or about to produce.
     // Sorted by block height inversely (high to low)
     // The range of heights are controlled by constants in client_actor.rs
@@ -185,6 +199,16 @@ pub enum DebugStatus {
     ChainProcessingStatus,
     // The state parts already requested.
     RequestedStateParts,
+    // Approximate sizes of the columns of the hot store.
+    StoreColumnStats,
+    // Timelines of processing the most recent blocks.
+    BlockTimelines,
+    // Blocks which failed validation.
+    QuarantinedBlocks,
+    // A single quarantined block with its contents.
+    QuarantinedBlock(CryptoHash),
+    // Occupancy of the transaction pools.
+    TransactionPool,
 }
 
 impl actix::Message for DebugStatus {
@@ -206,4 +230,14 @@ pub enum DebugStatusResponse {
     ChainProcessingStatus(ChainProcessingInfo),
     // The state parts already requested.
     RequestedStateParts(Vec<RequestedStatePartsView>),
+    // Approximate sizes of the columns of the hot store.
+    StoreColumnStats(Vec<ColumnStatsView>),
+    // Timelines of processing the most recent blocks, newest first.
+    BlockTimelines(Vec<BlockTimelineView>),
+    // Blocks which failed validation, most recently quarantined first.
+    QuarantinedBlocks(Vec<QuarantinedBlockView>),
+    // None if there's no such block in the quarantine.
+    QuarantinedBlock(Option<QuarantinedBlockDetailsView>),
+    // Occupancy of the transaction pool of every shard, ordered by shard.
+    TransactionPool(Vec<TransactionPoolView>),
 }
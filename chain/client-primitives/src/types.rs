@@ -8,16 +8,20 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::network::PeerId;
 use near_primitives::sharding::ChunkHash;
+use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, NumBlocks,
+    ShardId, StoreKey, TransactionOrReceiptId,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, DownloadStatusView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    GasPriceView, LightClientBlockLiteView, LightClientBlockView, MaintenanceWindowsView,
-    QueryRequest, QueryResponse, ReceiptView, ShardSyncDownloadView, SplitStorageInfoView,
-    StateChangesKindsView, StateChangesRequestView, StateChangesView, SyncStatusView, TxStatusView,
+    BlockView, ChunkView, DownloadStatusView, EpochParticipationView, EpochValidatorInfo,
+    ExecutionOutcomeWithIdView, GasCongestionView, GasPriceView, LightClientBlockLiteView,
+    LightClientBlockView, MaintenanceWindowsView, QueryRequest, QueryResponse, QueryResponseKind,
+    ReceiptView, ShardSyncDownloadView, SimulationRequest, SimulationResultView,
+    SplitStorageInfoView, StateChangesKindsView, StateChangesRequestView, StateChangesView,
+    StateProofView, SyncJobProgressView, SyncStatusView, TransactionExecutionTreeView,
+    TxPoolStatsView, TxStatusView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 use std::collections::HashMap;
@@ -150,6 +154,7 @@ impl From<ShardSyncDownload> for ShardSyncDownloadView {
         ShardSyncDownloadView {
             downloads: download.downloads.iter().map(|x| x.into()).collect(),
             status: download.status.to_string(),
+            progress: download.progress,
         }
     }
 }
@@ -164,6 +169,9 @@ pub struct ShardSyncDownload {
     /// shard as part of resharding.
     pub downloads: Vec<DownloadStatus>,
     pub status: ShardSyncStatus,
+    /// Progress reported by the job applying the state parts or splitting
+    /// the state, while the shard is in one of the applying statuses.
+    pub progress: Option<SyncJobProgressView>,
 }
 
 impl ShardSyncDownload {
@@ -172,6 +180,7 @@ impl ShardSyncDownload {
         Self {
             downloads: vec![DownloadStatus::new(now)],
             status: ShardSyncStatus::StateDownloadHeader,
+            progress: None,
         }
     }
 
@@ -183,7 +192,22 @@ impl ShardSyncDownload {
         for _ in 0..num_parts {
             downloads.push(DownloadStatus::new(now));
         }
-        Self { downloads, status: ShardSyncStatus::StateDownloadParts }
+        Self { downloads, status: ShardSyncStatus::StateDownloadParts, progress: None }
+    }
+
+    /// Returns the status along with the progress of the job of the status,
+    /// if there is any.
+    pub fn status_with_progress(&self) -> String {
+        match &self.progress {
+            Some(progress) => format!(
+                "{} {}/{}, {} bytes written",
+                self.status.to_string(),
+                progress.done,
+                progress.total,
+                progress.bytes_written
+            ),
+            None => self.status.to_string(),
+        }
     }
 }
 
@@ -232,7 +256,13 @@ pub fn format_shard_sync_phase(
             }
             format!("num_parts_done={num_parts_done} num_parts_not_done={num_parts_not_done}")
         }
-        status => format!("{status:?}"),
+        status => match &shard_sync_download.progress {
+            Some(progress) => format!(
+                "{status:?} done={} total={} bytes_written={}",
+                progress.done, progress.total, progress.bytes_written
+            ),
+            None => format!("{status:?}"),
+        },
     }
 }
 
@@ -473,6 +503,46 @@ impl Message for Query {
     type Result = Result<QueryResponse, QueryError>;
 }
 
+/// Runs multiple queries against the state of the same block.
+#[derive(Clone, Debug)]
+pub struct QueryBatch {
+    pub block_reference: BlockReference,
+    pub requests: Vec<QueryRequest>,
+}
+
+/// Results of the queries of a `QueryBatch`, in the order of the requests.
+#[derive(Debug)]
+pub struct QueryBatchResponse {
+    pub block_height: near_primitives::types::BlockHeight,
+    pub block_hash: CryptoHash,
+    pub results: Vec<Result<QueryResponseKind, QueryError>>,
+}
+
+impl Message for QueryBatch {
+    /// Fails as a whole only if the block can't be resolved.
+    type Result = Result<QueryBatchResponse, QueryError>;
+}
+
+/// Runs a view call or a transaction on top of the state of the block, with the given
+/// records overriding the state. Nothing is persisted.
+#[derive(Debug)]
+pub struct Simulate {
+    pub block_reference: BlockReference,
+    pub state_overrides: Vec<StateRecord>,
+    pub request: SimulationRequest,
+}
+
+#[derive(Debug)]
+pub struct SimulateResponse {
+    pub result: SimulationResultView,
+    pub block_height: BlockHeight,
+    pub block_hash: CryptoHash,
+}
+
+impl Message for Simulate {
+    type Result = Result<SimulateResponse, QueryError>;
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum QueryError {
     #[error("There are no fully synchronized blocks on the node yet")]
@@ -507,6 +577,12 @@ pub enum QueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Simulated transaction is invalid: {context}")]
+    InvalidTransaction {
+        context: near_primitives::errors::InvalidTxError,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("Access key for public key {public_key} has never been observed on the node at block #{block_height}")]
     UnknownAccessKey {
         public_key: near_crypto::PublicKey,
@@ -530,6 +606,11 @@ pub enum QueryError {
     },
     #[error("Block either has never been observed on the node or has been garbage collected: {block_reference:?}")]
     UnknownBlock { block_reference: near_primitives::types::BlockReference },
+    #[error("Continuation token is bound to block {token_block_hash}, not to the queried block {block_hash}")]
+    InvalidContinuationToken {
+        token_block_hash: near_primitives::hash::CryptoHash,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -671,11 +752,53 @@ impl From<near_chain_primitives::Error> for GetGasPriceError {
     }
 }
 
+/// Returns the gas usage of the shards in up to `num_blocks` most recent blocks.
+/// The pool fields of the shards are not set, the pool is owned by the client.
+#[derive(Debug)]
+pub struct GetGasCongestion {
+    pub num_blocks: NumBlocks,
+}
+
+impl Message for GetGasCongestion {
+    type Result = Result<GasCongestionView, GetGasCongestionError>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetGasCongestionError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetGasCongestionError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error {
+            near_chain_primitives::Error::IOErr(error) => Self::IOError(error.to_string()),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+/// Returns the number and size of the transactions in the pool of each tracked shard.
+#[derive(Debug)]
+pub struct GetTxPoolStats {}
+
+impl Message for GetTxPoolStats {
+    type Result = Result<Vec<TxPoolStatsView>, GetGasCongestionError>;
+}
+
 #[derive(Clone, Debug)]
 pub struct PeerInfo {
     pub id: PeerId,
     pub addr: Option<std::net::SocketAddr>,
     pub account_id: Option<AccountId>,
+    /// Reputation score of the peer, 0 is neutral.
+    pub reputation: f64,
 }
 
 #[derive(Clone, Debug)]
@@ -955,6 +1078,51 @@ impl Message for GetBlockProof {
     type Result = Result<GetBlockProofResponse, GetBlockProofError>;
 }
 
+/// Proves the value of a contract storage key in the state committed in the block header.
+#[derive(Debug)]
+pub struct GetStateProof {
+    pub block_hash: CryptoHash,
+    pub account_id: AccountId,
+    pub key: StoreKey,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetStateProofError {
+    #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
+    UnknownBlock { error_message: String },
+    #[error("Node doesn't track the shard {shard_id} of the account {account_id}")]
+    UnavailableShard { account_id: AccountId, shard_id: ShardId },
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {error_message}")]
+    Unreachable { error_message: String },
+}
+
+impl From<near_chain_primitives::error::Error> for GetStateProofError {
+    fn from(error: near_chain_primitives::error::Error) -> Self {
+        match error {
+            near_chain_primitives::error::Error::DBNotFoundErr(error_message) => {
+                Self::UnknownBlock { error_message }
+            }
+            near_chain_primitives::error::Error::StorageError(error) => {
+                Self::InternalError { error_message: error.to_string() }
+            }
+            near_chain_primitives::error::Error::Other(error_message) => {
+                Self::InternalError { error_message }
+            }
+            err => Self::Unreachable { error_message: err.to_string() },
+        }
+    }
+}
+
+impl Message for GetStateProof {
+    type Result = Result<StateProofView, GetStateProofError>;
+}
+
 #[derive(Debug)]
 pub struct GetReceipt {
     pub receipt_id: CryptoHash,
@@ -987,6 +1155,39 @@ impl Message for GetReceipt {
     type Result = Result<Option<ReceiptView>, GetReceiptError>;
 }
 
+/// Gets the transaction with the tree of the receipts it caused.
+#[derive(Debug)]
+pub struct GetTransactionExecutionTree {
+    pub transaction_hash: CryptoHash,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetTransactionExecutionTreeError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Transaction {0} has never been observed on this node")]
+    UnknownTransaction(near_primitives::hash::CryptoHash),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetTransactionExecutionTreeError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error {
+            near_chain_primitives::Error::IOErr(error) => Self::IOError(error.to_string()),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetTransactionExecutionTree {
+    type Result = Result<TransactionExecutionTreeView, GetTransactionExecutionTreeError>;
+}
+
 #[derive(Debug)]
 pub struct GetProtocolConfig(pub BlockReference);
 
@@ -1044,6 +1245,46 @@ impl From<near_chain_primitives::Error> for GetMaintenanceWindowsError {
     }
 }
 
+/// Stops the sync jobs and waits for up to `timeout` for the running ones to
+/// finish their current batch. Sent on shutdown, so that jobs aren't killed
+/// in the middle of writing a batch. Returns whether all jobs stopped in time.
+#[derive(Debug)]
+pub struct DrainSyncJobs {
+    pub timeout: std::time::Duration,
+}
+
+impl Message for DrainSyncJobs {
+    type Result = bool;
+}
+
+/// Returns the participation of validators in approving blocks in up to
+/// `num_epochs` most recent epochs, the most recent epoch first.
+#[derive(Debug)]
+pub struct GetValidatorParticipation {
+    pub num_epochs: usize,
+}
+
+impl Message for GetValidatorParticipation {
+    type Result = Result<Vec<EpochParticipationView>, GetValidatorParticipationError>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetValidatorParticipationError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetValidatorParticipationError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error {
+            near_chain_primitives::Error::IOErr(error) => Self::IOError(error.to_string()),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GetClientConfig {}
 
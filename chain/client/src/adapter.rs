@@ -7,6 +7,7 @@ use near_network::types::{
 use near_o11y::WithSpanContextExt;
 use near_primitives::block::{Approval, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
@@ -46,10 +47,10 @@ pub struct BlockResponse {
 #[rtype(result = "()")]
 pub struct BlockApproval(pub Approval, pub PeerId);
 
-/// Request headers.
+/// Request headers following the locator, at most the given number of them if set.
 #[derive(actix::Message, Debug)]
 #[rtype(result = "Option<Vec<BlockHeader>>")]
-pub(crate) struct BlockHeadersRequest(pub Vec<CryptoHash>);
+pub(crate) struct BlockHeadersRequest(pub Vec<CryptoHash>, pub Option<u64>);
 
 /// Headers response.
 #[derive(actix::Message, Debug)]
@@ -78,6 +79,16 @@ pub(crate) struct StateRequestPart {
 #[rtype(result = "()")]
 pub(crate) struct StateResponse(pub Box<StateResponseInfo>);
 
+/// Request of the proof of the start of the epoch after the given one.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Option<Box<EpochSyncProof>>")]
+pub(crate) struct EpochSyncRequest(pub EpochId);
+
+/// Response to epoch sync request.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Result<(),ReasonForBan>")]
+pub(crate) struct EpochSyncResponse(pub Box<EpochSyncProof>, pub PeerId);
+
 /// Account announcements that needs to be validated before being processed.
 /// They are paired with last epoch id known to this announcement, in order to accept only
 /// newer announcements.
@@ -133,6 +144,8 @@ pub enum ProcessTxResponse {
     /// The node being queried does not track the shard needed and therefore cannot provide userful
     /// response.
     DoesNotTrackShard,
+    /// The transaction is valid, but was rejected by the transaction pool for the given reason.
+    RejectedByPool(String),
 }
 
 pub struct Adapter {
@@ -267,8 +280,16 @@ impl near_network::client::Client for Adapter {
         }
     }
 
-    async fn block_headers_request(&self, hashes: Vec<CryptoHash>) -> Option<Vec<BlockHeader>> {
-        match self.view_client_addr.send(BlockHeadersRequest(hashes).with_span_context()).await {
+    async fn block_headers_request(
+        &self,
+        hashes: Vec<CryptoHash>,
+        max_headers: Option<u64>,
+    ) -> Option<Vec<BlockHeader>> {
+        match self
+            .view_client_addr
+            .send(BlockHeadersRequest(hashes, max_headers).with_span_context())
+            .await
+        {
             Ok(headers) => headers,
             Err(err) => {
                 tracing::error!("mailbox error: {err}");
@@ -313,6 +334,35 @@ impl near_network::client::Client for Adapter {
         }
     }
 
+    async fn epoch_sync_request(&self, prev_epoch_id: EpochId) -> Option<Box<EpochSyncProof>> {
+        match self.view_client_addr.send(EpochSyncRequest(prev_epoch_id).with_span_context()).await
+        {
+            Ok(proof) => proof,
+            Err(err) => {
+                tracing::error!("mailbox error: {err}");
+                None
+            }
+        }
+    }
+
+    async fn epoch_sync_response(
+        &self,
+        proof: EpochSyncProof,
+        peer_id: PeerId,
+    ) -> Result<(), ReasonForBan> {
+        match self
+            .client_addr
+            .send(EpochSyncResponse(Box::new(proof), peer_id).with_span_context())
+            .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                tracing::error!("mailbox error: {err}");
+                Ok(())
+            }
+        }
+    }
+
     async fn network_info(&self, info: NetworkInfo) {
         match self.client_addr.send(SetNetworkInfo(info).with_span_context()).await {
             Ok(()) => {}
@@ -0,0 +1,138 @@
+//! Tracking of how long after block production could start the approvals of
+//! each validator arrive.
+//!
+//! Block production for a height starts when the doomslug timer for the height
+//! starts, i.e. when the previous block becomes the head.  Approvals arriving
+//! before that are counted as arriving with no delay.  Only approvals for
+//! heights this node produces blocks at are tracked.
+
+use crate::metrics;
+use near_client_primitives::debug::ApprovalLatencyView;
+use near_primitives::types::{AccountId, BlockHeight};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Number of most recent approvals of each validator the percentiles shown on
+/// the debug page are computed from.
+const LATENCY_SAMPLES_PER_VALIDATOR: usize = 100;
+
+/// How many heights ahead of the timer height approvals which arrived early
+/// are remembered.
+const MAX_HEIGHTS_AHEAD: BlockHeight = 5;
+
+#[derive(Default)]
+pub(crate) struct ApprovalLatencyTracker {
+    /// Height for which the doomslug timer runs and when it started.
+    timer: Option<(BlockHeight, Instant)>,
+    /// Validators whose approval for the timer height has been recorded.
+    recorded: HashSet<AccountId>,
+    /// Validators whose approvals arrived before the timer for their target
+    /// height started.
+    early: HashMap<BlockHeight, HashSet<AccountId>>,
+    samples: HashMap<AccountId, VecDeque<Duration>>,
+}
+
+impl ApprovalLatencyTracker {
+    /// Called when the doomslug timer for given height starts.
+    pub(crate) fn on_timer_started(&mut self, height: BlockHeight, started: Instant) {
+        self.timer = Some((height, started));
+        self.recorded = self.early.remove(&height).unwrap_or_default();
+        for account_id in &self.recorded {
+            Self::record(&mut self.samples, account_id, Duration::ZERO);
+        }
+        self.early.retain(|early_height, _| *early_height > height);
+    }
+
+    /// Called when an approval from another validator for a height this node
+    /// produces a block at arrives.  Only the first approval of each validator
+    /// for a height is taken into account.
+    pub(crate) fn on_approval(
+        &mut self,
+        account_id: &AccountId,
+        target_height: BlockHeight,
+        now: Instant,
+    ) {
+        let Some((timer_height, started)) = self.timer else { return };
+        if target_height == timer_height {
+            if self.recorded.insert(account_id.clone()) {
+                let latency = now.saturating_duration_since(started);
+                Self::record(&mut self.samples, account_id, latency);
+            }
+        } else if target_height > timer_height && target_height <= timer_height + MAX_HEIGHTS_AHEAD
+        {
+            self.early.entry(target_height).or_default().insert(account_id.clone());
+        }
+    }
+
+    fn record(
+        samples: &mut HashMap<AccountId, VecDeque<Duration>>,
+        account_id: &AccountId,
+        latency: Duration,
+    ) {
+        metrics::APPROVAL_LATENCY
+            .with_label_values(&[account_id.as_str()])
+            .observe(latency.as_secs_f64());
+        let samples = samples.entry(account_id.clone()).or_default();
+        if samples.len() == LATENCY_SAMPLES_PER_VALIDATOR {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// Returns percentiles of recent approval latencies of each validator, the
+    /// slowest validators first.
+    pub(crate) fn get_approval_latencies(&self) -> Vec<ApprovalLatencyView> {
+        let mut latencies: Vec<_> = self
+            .samples
+            .iter()
+            .map(|(account_id, samples)| {
+                let mut samples: Vec<_> = samples.iter().copied().collect();
+                samples.sort();
+                let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_millis();
+                ApprovalLatencyView {
+                    account_id: account_id.clone(),
+                    num_samples: samples.len(),
+                    p50_millis: percentile(50) as u64,
+                    p95_millis: percentile(95) as u64,
+                }
+            })
+            .collect();
+        latencies.sort_by(|a, b| b.p95_millis.cmp(&a.p95_millis));
+        latencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApprovalLatencyTracker;
+    use near_primitives::types::AccountId;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_approval_latency() {
+        let fast: AccountId = "fast".parse().unwrap();
+        let slow: AccountId = "slow".parse().unwrap();
+        let mut tracker = ApprovalLatencyTracker::default();
+        let start = Instant::now();
+        tracker.on_timer_started(0, start);
+        for height in 1..=10 {
+            let started = start + Duration::from_secs(height);
+            // The fast validator's approval arrives before the timer starts.
+            tracker.on_approval(&fast, height, started - Duration::from_millis(10));
+            tracker.on_timer_started(height, started);
+            tracker.on_approval(&slow, height, started + Duration::from_millis(height * 100));
+            // Only the first approval for the height counts.
+            tracker.on_approval(&slow, height, started + Duration::from_millis(999));
+        }
+
+        let latencies = tracker.get_approval_latencies();
+        assert_eq!(latencies.len(), 2);
+        assert_eq!(latencies[0].account_id, slow);
+        assert_eq!(latencies[0].num_samples, 10);
+        assert_eq!(latencies[0].p50_millis, 500);
+        assert_eq!(latencies[0].p95_millis, 900);
+        assert_eq!(latencies[1].account_id, fast);
+        assert_eq!(latencies[1].num_samples, 10);
+        assert_eq!(latencies[1].p95_millis, 0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Time budget of chunk production.
+//!
+//! Producing a chunk is on the critical path of producing the next block, so
+//! it's limited to a fraction of the minimal block production delay. Selecting
+//! transactions stops once the budget is spent, and the chunk is produced with
+//! the transactions selected so far. The stages which can't be cut short are
+//! only measured against the budget. The time spent in every stage is recorded,
+//! so that operators can see where the budget went.
+
+use crate::metrics;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::ShardId;
+use std::time::{Duration, Instant};
+
+/// Stage of chunk production.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ChunkProductionStage {
+    /// Selecting transactions from the pool.
+    PrepareTransactions,
+    /// Applying the chunk, for chunks with the post state root.
+    Apply,
+    /// Collecting the outgoing receipts and encoding the chunk.
+    Encode,
+}
+
+/// Tracks the time spent producing a chunk against its budget.
+pub(crate) struct ChunkProductionBudget {
+    shard_label: String,
+    budget: Option<Duration>,
+    start: Instant,
+    stage_start: Instant,
+    stage_times: Vec<(ChunkProductionStage, Duration)>,
+    exceeded: bool,
+}
+
+impl ChunkProductionBudget {
+    /// Starts tracking the production of a chunk of the given shard, which may
+    /// take up to `budget`, or arbitrarily long if it's `None`.
+    pub fn new(shard_id: ShardId, budget: Option<Duration>) -> Self {
+        let now = StaticClock::instant();
+        Self {
+            shard_label: shard_id.to_string(),
+            budget,
+            start: now,
+            stage_start: now,
+            stage_times: vec![],
+            exceeded: false,
+        }
+    }
+
+    /// Returns the part of the budget which isn't spent yet, or `None` if
+    /// production isn't limited in time.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.budget.map(|budget| budget.saturating_sub(self.start.elapsed()))
+    }
+
+    /// Records the time spent in the given stage, which just finished.
+    pub fn finish_stage(&mut self, stage: ChunkProductionStage) {
+        let now = StaticClock::instant();
+        let elapsed = now.saturating_duration_since(self.stage_start);
+        self.stage_start = now;
+        self.stage_times.push((stage, elapsed));
+        metrics::CHUNK_PRODUCTION_STAGE_TIME
+            .with_label_values(&[&self.shard_label, stage.into()])
+            .observe(elapsed.as_secs_f64());
+        let exceeded =
+            self.budget.is_some_and(|budget| now.saturating_duration_since(self.start) >= budget);
+        if exceeded && !self.exceeded {
+            self.exceeded = true;
+            metrics::CHUNK_PRODUCTION_BUDGET_EXCEEDED
+                .with_label_values(&[&self.shard_label, stage.into()])
+                .inc();
+        }
+    }
+
+    /// Returns whether the budget was spent before the last finished stage
+    /// ended.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// Returns the time spent in each finished stage, in order.
+    pub fn stage_times(&self) -> &[(ChunkProductionStage, Duration)] {
+        &self.stage_times
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let mut budget = ChunkProductionBudget::new(0, None);
+        assert_eq!(budget.remaining(), None);
+        budget.finish_stage(ChunkProductionStage::PrepareTransactions);
+        assert!(!budget.exceeded());
+
+        let mut budget = ChunkProductionBudget::new(0, Some(Duration::from_secs(3600)));
+        assert!(budget.remaining().unwrap() > Duration::from_secs(3500));
+        budget.finish_stage(ChunkProductionStage::PrepareTransactions);
+        assert!(!budget.exceeded());
+
+        let mut budget = ChunkProductionBudget::new(0, Some(Duration::ZERO));
+        assert_eq!(budget.remaining(), Some(Duration::ZERO));
+        budget.finish_stage(ChunkProductionStage::PrepareTransactions);
+        budget.finish_stage(ChunkProductionStage::Encode);
+        assert!(budget.exceeded());
+        let stages: Vec<_> = budget.stage_times().iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(
+            stages,
+            vec![ChunkProductionStage::PrepareTransactions, ChunkProductionStage::Encode]
+        );
+    }
+}
@@ -2,12 +2,20 @@
 //! This client works completely synchronously and must be operated by some async actor outside.
 
 use crate::adapter::ProcessTxResponse;
+use crate::approval_latency::ApprovalLatencyTracker;
+use crate::chunk_production_budget::{ChunkProductionBudget, ChunkProductionStage};
 use crate::debug::BlockProductionTracker;
 use crate::debug::PRODUCTION_TIMES_CACHE_SIZE;
+use crate::optimistic_witness::{OptimisticStateWitness, OptimisticWitnessRecorder};
+use crate::production_diagnostics::ProductionDiagnostics;
+use crate::remote_signer::is_vrf_valid;
+use crate::sync::bandwidth::{BandwidthDirection, BandwidthLimiter};
 use crate::sync::block::BlockSync;
 use crate::sync::epoch::EpochSync;
 use crate::sync::header::HeaderSync;
 use crate::sync::state::{StateSync, StateSyncResult};
+use crate::validator_key_rotation::{KeyRotationAction, ValidatorKeyRotation};
+use crate::validator_participation::ValidatorParticipationTracker;
 use crate::{metrics, SyncStatus};
 use actix_rt::ArbiterHandle;
 use lru::LruCache;
@@ -71,14 +79,18 @@ use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::ValidatorStakeIter;
 use near_primitives::types::Gas;
 use near_primitives::types::StateRoot;
-use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, NumBlocks, ShardId};
+use near_primitives::types::{
+    AccountId, ApprovalStake, Balance, BlockHeight, EpochId, NumBlocks, ShardId,
+};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::{CatchupStatusView, DroppedReason};
+use near_primitives::views::{
+    BlockTimelineStage, CatchupStatusView, DroppedReason, QueryRequest, QueryResponseKind,
+};
 use near_store::metadata::DbKind;
-use near_store::ShardUId;
+use near_store::{ShardUId, Store};
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -90,11 +102,7 @@ const CHUNK_HEADERS_FOR_INCLUSION_CACHE_SIZE: usize = 2048;
 const NUM_EPOCH_CHUNK_PRODUCERS_TO_KEEP_IN_BLOCKLIST: usize = 1000;
 
 /// The time we wait for the response to a Epoch Sync request before retrying
-// TODO #3488 set 30_000
-pub const EPOCH_SYNC_REQUEST_TIMEOUT: Duration = Duration::from_millis(1_000);
-/// How frequently a Epoch Sync response can be sent to a particular peer
-// TODO #3488 set 60_000
-pub const EPOCH_SYNC_PEER_TIMEOUT: Duration = Duration::from_millis(10);
+pub const EPOCH_SYNC_REQUEST_TIMEOUT: Duration = Duration::from_millis(10_000);
 /// Drop blocks whose height are beyond head + horizon if it is not in the current epoch.
 const BLOCK_HORIZON: u64 = 500;
 
@@ -149,6 +157,10 @@ pub struct Client {
     pub block_sync: BlockSync,
     /// Keeps track of syncing state.
     pub state_sync: StateSync,
+    /// Limits the bandwidth used to download state parts, shared by state sync and catchups.
+    state_sync_download_limiter: Arc<BandwidthLimiter>,
+    /// Records the state witnesses of produced chunks in the background.
+    optimistic_witness_recorder: OptimisticWitnessRecorder,
     /// List of currently accumulated challenges.
     pub challenges: HashMap<CryptoHash, Challenge>,
     /// A ReedSolomon instance to reconstruct shard.
@@ -164,6 +176,14 @@ pub struct Client {
     pub block_production_info: BlockProductionTracker,
     /// Chunk production timing information. Used only for debug purposes.
     pub chunk_production_info: lru::LruCache<(BlockHeight, ShardId), ChunkProduction>,
+    /// Latencies of approvals received from other validators.
+    pub(crate) approval_latency: ApprovalLatencyTracker,
+    /// Approvals of validators included in the blocks of the recent epochs.
+    pub(crate) validator_participation: ValidatorParticipationTracker,
+    /// Reasons of missed block and chunk production.
+    pub(crate) production_diagnostics: ProductionDiagnostics,
+    /// Rotation of the validator key, if configured.
+    validator_key_rotation: Option<ValidatorKeyRotation>,
 
     /// Cached precomputed set of TIER1 accounts.
     /// See send_network_chain_info().
@@ -175,6 +195,8 @@ pub struct Client {
 impl Client {
     pub(crate) fn update_client_config(&self, update_client_config: UpdateableClientConfig) {
         self.config.expected_shutdown.update(update_client_config.expected_shutdown);
+        self.config.resharding_config.update(update_client_config.resharding_config);
+        self.config.doomslug_timer.update(update_client_config.doomslug_timer);
     }
 }
 
@@ -215,6 +237,7 @@ impl Client {
         enable_doomslug: bool,
         rng_seed: RngSeed,
         make_state_snapshot_callback: Option<MakeSnapshotCallback>,
+        cold_store: Option<Store>,
     ) -> Result<Self, Error> {
         let doomslug_threshold_mode = if enable_doomslug {
             DoomslugThresholdMode::TwoThirds
@@ -225,8 +248,10 @@ impl Client {
             save_trie_changes: config.save_trie_changes,
             background_migration_threads: config.client_background_migration_threads,
             state_snapshot_every_n_blocks: config.state_snapshot_every_n_blocks,
+            chunk_replay_artifacts_dir: config.chunk_replay_artifacts_dir.clone(),
+            resharding_config: config.resharding_config.clone(),
         };
-        let chain = Chain::new(
+        let mut chain = Chain::new(
             epoch_manager.clone(),
             shard_tracker.clone(),
             runtime_adapter.clone(),
@@ -235,6 +260,9 @@ impl Client {
             chain_config.clone(),
             make_state_snapshot_callback,
         )?;
+        if let Some(cold_store) = cold_store {
+            chain.mut_store().set_cold_store(cold_store);
+        }
         // Create flat storage or initiate migration to flat storage.
         let flat_storage_creator = FlatStorageCreator::new(
             epoch_manager.clone(),
@@ -242,24 +270,20 @@ impl Client {
             chain.store(),
             chain_config.background_migration_threads,
         )?;
-        let sharded_tx_pool =
-            ShardedTransactionPool::new(rng_seed, config.transaction_pool_size_limit);
+        let sharded_tx_pool = ShardedTransactionPool::new(
+            rng_seed,
+            config.transaction_pool_size_limit,
+            config.transaction_pool_count_limit,
+        );
         let sync_status = SyncStatus::AwaitingPeers;
         let genesis_block = chain.genesis_block();
         let epoch_sync = EpochSync::new(
             network_adapter.clone(),
             genesis_block.header().epoch_id().clone(),
-            genesis_block.header().next_epoch_id().clone(),
-            epoch_manager
-                .get_epoch_block_producers_ordered(
-                    genesis_block.header().epoch_id(),
-                    genesis_block.hash(),
-                )?
-                .iter()
-                .map(|x| x.0.clone())
-                .collect(),
+            EpochId(*genesis_block.hash()),
+            epoch_manager.get_epoch_info(&EpochId(*genesis_block.hash()))?,
+            config.epoch_length,
             EPOCH_SYNC_REQUEST_TIMEOUT,
-            EPOCH_SYNC_PEER_TIMEOUT,
         );
         let header_sync = HeaderSync::new(
             network_adapter.clone(),
@@ -274,26 +298,46 @@ impl Client {
             config.archive,
             config.state_sync_enabled,
         );
+        let state_sync_download_limiter = Arc::new(BandwidthLimiter::from_config(
+            BandwidthDirection::Download,
+            &config.state_sync.bandwidth,
+        ));
         let state_sync = StateSync::new(
             network_adapter.clone(),
             config.state_sync_timeout,
             &config.chain_id,
             &config.state_sync.sync,
+            config.state_sync.apply_parts_while_downloading,
             false,
+            state_sync_download_limiter.clone(),
         );
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = epoch_manager.num_data_parts();
         let parity_parts = epoch_manager.num_total_parts() - data_parts;
 
+        let doomslug_timer = config.doomslug_timer.get();
         let doomslug = Doomslug::new(
             chain.store().largest_target_height()?,
             config.min_block_production_delay,
-            config.max_block_production_delay,
-            config.max_block_production_delay / 10,
-            config.max_block_wait_delay,
+            doomslug_timer.min_delay,
+            doomslug_timer.delay_step,
+            doomslug_timer.max_delay,
             validator_signer.clone(),
             doomslug_threshold_mode,
         );
+        let production_diagnostics =
+            ProductionDiagnostics::new(config.production_diagnostics.clone());
+        let validator_key_rotation = match (&config.validator_key_rotation, &validator_signer) {
+            (Some(rotation_config), Some(signer)) => {
+                Some(ValidatorKeyRotation::new(rotation_config, signer.validator_id())?)
+            }
+            (Some(_), None) => {
+                return Err(Error::Other(
+                    "Validator key rotation is configured, but there's no validator key".into(),
+                ))
+            }
+            (None, _) => None,
+        };
         Ok(Self {
             #[cfg(feature = "test_features")]
             adv_produce_blocks: false,
@@ -328,12 +372,18 @@ impl Client {
             header_sync,
             block_sync,
             state_sync,
+            state_sync_download_limiter,
+            optimistic_witness_recorder: OptimisticWitnessRecorder::new(),
             challenges: Default::default(),
             rs_for_chunk_production: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: lru::LruCache::new(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: StaticClock::instant(),
             block_production_info: BlockProductionTracker::new(),
             chunk_production_info: lru::LruCache::new(PRODUCTION_TIMES_CACHE_SIZE),
+            approval_latency: ApprovalLatencyTracker::default(),
+            validator_participation: ValidatorParticipationTracker::default(),
+            production_diagnostics,
+            validator_key_rotation,
             tier1_accounts_cache: None,
             flat_storage_creator,
         })
@@ -759,6 +809,21 @@ impl Client {
             seen: block.header().raw_timestamp(),
         })?;
 
+        // A remote signer without a fallback key leaves the signature or the
+        // VRF invalid when the signing service fails, see `RemoteSignerFallback`.
+        let public_key = validator_signer.public_key();
+        if !block.header().verify_block_producer(&public_key)
+            || !is_vrf_valid(
+                &public_key,
+                prev_header.random_value().as_ref(),
+                block.vrf_value(),
+                block.vrf_proof(),
+            )
+        {
+            warn!(target: "client", next_height, "Failed to sign block, skipping it");
+            return Ok(None);
+        }
+
         metrics::BLOCK_PRODUCED_TOTAL.inc();
 
         Ok(Some(block))
@@ -775,6 +840,12 @@ impl Client {
         let timer = Instant::now();
         let _timer =
             metrics::PRODUCE_CHUNK_TIME.with_label_values(&[&shard_id.to_string()]).start_timer();
+        let mut budget = ChunkProductionBudget::new(
+            shard_id,
+            self.config
+                .chunk_production_time_budget
+                .map(|ratio| self.config.min_block_production_delay.mul_f64(ratio)),
+        );
         let _span = tracing::debug_span!(target: "client", "produce_chunk", next_height, shard_id, ?epoch_id).entered();
         let validator_signer = self
             .validator_signer
@@ -816,7 +887,11 @@ impl Client {
             last_header,
             next_height,
             shard_id,
+            &mut budget,
         )?;
+        if budget.exceeded() {
+            debug!(target: "client", next_height, shard_id, stage_times = ?budget.stage_times(), "Chunk production ran out of its time budget");
+        }
 
         metrics::CHUNK_PRODUCED_TOTAL.inc();
         self.chunk_production_info.put(
@@ -829,6 +904,16 @@ impl Client {
         Ok(Some(ret))
     }
 
+    /// Returns the optimistically recorded state witness of the chunk produced
+    /// by this node on top of `prev_block_hash`, if it's already recorded.
+    pub fn get_optimistic_state_witness(
+        &self,
+        prev_block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<Arc<OptimisticStateWitness>> {
+        self.optimistic_witness_recorder.get(prev_block_hash, shard_id)
+    }
+
     fn produce_pre_state_root_chunk(
         &mut self,
         validator_signer: &dyn ValidatorSigner,
@@ -837,6 +922,7 @@ impl Client {
         last_header: ShardChunkHeader,
         next_height: BlockHeight,
         shard_id: ShardId,
+        budget: &mut ChunkProductionBudget,
     ) -> Result<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>), Error> {
         let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, epoch_id)?;
         let chunk_extra = self
@@ -850,7 +936,9 @@ impl Client {
             chunk_extra.gas_limit(),
             *chunk_extra.state_root(),
             &prev_block_header,
+            budget.remaining(),
         )?;
+        budget.finish_stage(ChunkProductionStage::PrepareTransactions);
         let transactions = transactions;
         #[cfg(feature = "test_features")]
         let transactions = Self::maybe_insert_invalid_transaction(
@@ -859,6 +947,15 @@ impl Client {
             self.produce_invalid_tx_in_chunks,
         );
         let num_filtered_transactions = transactions.len();
+        // Start recording the state witness of the chunk right away, so that
+        // it's ready by the time the block including it is finalized.
+        let _ = self.optimistic_witness_recorder.start(
+            &self.runtime_adapter.get_tries(),
+            shard_uid,
+            prev_block_hash,
+            *chunk_extra.state_root(),
+            &transactions,
+        );
         let (tx_root, _) = merklize(&transactions);
         let outgoing_receipts = self.chain.get_outgoing_receipts_for_shard(
             prev_block_hash,
@@ -889,12 +986,22 @@ impl Client {
             &mut self.rs_for_chunk_production,
             protocol_version,
         )?;
+        budget.finish_stage(ChunkProductionStage::Encode);
+        if !encoded_chunk
+            .cloned_header()
+            .signature()
+            .verify(encoded_chunk.chunk_hash().as_ref(), &validator_signer.public_key())
+        {
+            warn!(target: "client", next_height, shard_id, "Failed to sign chunk, skipping it");
+            return Ok(None);
+        }
 
         debug!(
             target: "client",
             me=%validator_signer.validator_id(),
             chunk_hash=%encoded_chunk.chunk_hash().0,
             %prev_block_hash,
+            stage_times=?budget.stage_times(),
             "Produced chunk with {} txs and {} receipts",
             num_filtered_transactions,
             outgoing_receipts.len(),
@@ -912,6 +1019,7 @@ impl Client {
         last_header: ShardChunkHeader,
         next_height: BlockHeight,
         shard_id: ShardId,
+        budget: &mut ChunkProductionBudget,
     ) -> Result<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>), Error> {
         let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, epoch_id)?;
         let prev_block = self.chain.get_block(&prev_block_hash)?;
@@ -962,8 +1070,14 @@ impl Client {
         let prev_gas_used =
             if self.produce_invalid_chunks { prev_gas_used + 1 } else { prev_gas_used };
 
-        let transactions =
-            self.prepare_transactions(shard_uid, gas_limit, prev_state_root, prev_block_header)?;
+        let transactions = self.prepare_transactions(
+            shard_uid,
+            gas_limit,
+            prev_state_root,
+            prev_block_header,
+            budget.remaining(),
+        )?;
+        budget.finish_stage(ChunkProductionStage::PrepareTransactions);
         #[cfg(feature = "test_features")]
         let transactions = Self::maybe_insert_invalid_transaction(
             transactions,
@@ -986,6 +1100,7 @@ impl Client {
             gas_limit,
             last_header.height_included(),
         )?;
+        budget.finish_stage(ChunkProductionStage::Apply);
 
         let (transaction_receipts_parts, encoded_length) =
             EncodedShardChunk::encode_transaction_receipts(
@@ -1032,12 +1147,14 @@ impl Client {
             header: ShardChunkHeader::V3(header),
             content,
         });
+        budget.finish_stage(ChunkProductionStage::Encode);
 
         debug!(
             target: "client",
             me=%validator_signer.validator_id(),
             chunk_hash=%encoded_chunk.chunk_hash().0,
             %prev_block_hash,
+            stage_times=?budget.stage_times(),
             "Produced post-state-root chunk with {} txs and {} receipts",
             num_filtered_transactions,
             apply_result.outgoing_receipts.len(),
@@ -1089,13 +1206,15 @@ impl Client {
         txs
     }
 
-    /// Prepares an ordered list of valid transactions from the pool up the limits.
+    /// Prepares an ordered list of valid transactions from the pool up the limits, selecting
+    /// transactions for at most `time_limit` if it's set.
     fn prepare_transactions(
         &mut self,
         shard_uid: ShardUId,
         gas_limit: Gas,
         state_root: StateRoot,
         prev_block_header: &BlockHeader,
+        time_limit: Option<Duration>,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let Self { chain, sharded_tx_pool, epoch_manager, runtime_adapter: runtime, .. } = self;
 
@@ -1127,6 +1246,7 @@ impl Client {
                         .is_ok()
                 },
                 protocol_version,
+                time_limit,
             )?
         } else {
             vec![]
@@ -1214,6 +1334,7 @@ impl Client {
     ) -> Result<(), near_chain::Error> {
         self.chain.blocks_delay_tracker.mark_block_received(
             &block,
+            &peer_id,
             StaticClock::instant(),
             StaticClock::utc(),
         );
@@ -1553,6 +1674,93 @@ impl Client {
         Ok(())
     }
 
+    /// Counts which validators' approvals are included in a block that became the head.
+    fn record_validator_participation(&mut self, header: &BlockHeader) -> Result<(), Error> {
+        if header.prev_hash() == &CryptoHash::default() {
+            return Ok(());
+        }
+        let approvers = self.epoch_manager.get_epoch_block_approvers_ordered(header.prev_hash())?;
+        self.validator_participation.on_new_head(
+            header.epoch_id(),
+            header.height(),
+            approvers
+                .iter()
+                .zip(header.approvals())
+                .map(|((approver, _), approval)| (&approver.account_id, approval.is_some())),
+        );
+        Ok(())
+    }
+
+    /// Submits the stake proposal with the successor validator key and switches
+    /// to it when the rotation is due, see `validator_key_rotation`.
+    fn rotate_validator_key(&mut self, header: &BlockHeader) -> Result<(), Error> {
+        let Some(rotation) = &mut self.validator_key_rotation else { return Ok(()) };
+        let next_epoch_height =
+            self.epoch_manager.get_epoch_height_from_prev_block(header.hash())?;
+        match rotation.on_new_head(next_epoch_height) {
+            KeyRotationAction::None => {}
+            KeyRotationAction::ProposeStake => {
+                let account_id = rotation.successor().validator_id().clone();
+                let stake = self.get_locked_balance(header, &account_id)?;
+                let rotation = self.validator_key_rotation.as_ref().unwrap();
+                if let Some(tx) = rotation.stake_proposal(stake, header.height(), *header.hash()) {
+                    let tx_hash = tx.get_hash();
+                    let response = self.process_tx(tx, false, false);
+                    info!(target: "client", ?tx_hash, stake, ?response, "Submitted the stake proposal with the successor validator key");
+                }
+            }
+            KeyRotationAction::Rotate => {
+                let account_id = rotation.successor().validator_id().clone();
+                let next_epoch_id =
+                    self.epoch_manager.get_epoch_id_from_prev_block(header.hash())?;
+                let validator_key = match self.epoch_manager.get_validator_by_account_id(
+                    &next_epoch_id,
+                    header.hash(),
+                    &account_id,
+                ) {
+                    Ok((validator, _)) => Some(validator.take_public_key()),
+                    Err(EpochError::NotAValidator(..)) => None,
+                    Err(err) => return Err(err.into()),
+                };
+                let rotation = self.validator_key_rotation.as_mut().unwrap();
+                if let Some(signer) = rotation.try_rotate(next_epoch_height, validator_key.as_ref())
+                {
+                    self.doomslug.set_signer(Some(signer.clone()));
+                    self.validator_signer = Some(signer);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the balance locked by the account after the block.
+    fn get_locked_balance(
+        &self,
+        header: &BlockHeader,
+        account_id: &AccountId,
+    ) -> Result<Balance, Error> {
+        let shard_id = self.epoch_manager.account_id_to_shard_id(account_id, header.epoch_id())?;
+        let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, header.epoch_id())?;
+        let chunk_extra = self.chain.get_chunk_extra(header.hash(), &shard_uid)?;
+        let response = self
+            .runtime_adapter
+            .query(
+                shard_uid,
+                chunk_extra.state_root(),
+                header.height(),
+                header.raw_timestamp(),
+                header.prev_hash(),
+                header.hash(),
+                header.epoch_id(),
+                &QueryRequest::ViewAccount { account_id: account_id.clone() },
+            )
+            .map_err(|err| Error::Other(err.to_string()))?;
+        match response.kind {
+            QueryResponseKind::ViewAccount(account) => Ok(account.locked),
+            kind => Err(Error::Other(format!("Unexpected response to account query: {kind:?}"))),
+        }
+    }
+
     /// Checks if the latest hash known to Doomslug matches the current head, and updates it if not.
     pub fn check_and_update_doomslug_tip(&mut self) -> Result<(), Error> {
         let tip = self.chain.head()?;
@@ -1572,6 +1780,10 @@ impl Client {
                 tip.height,
                 last_final_height,
             );
+            self.approval_latency.on_timer_started(
+                self.doomslug.get_timer_height(),
+                self.doomslug.get_timer_start(),
+            );
         }
 
         Ok(())
@@ -1619,9 +1831,19 @@ impl Client {
         parent_hash: &CryptoHash,
         approval: Approval,
     ) -> Result<(), Error> {
+        if let Some(signer) = &self.validator_signer {
+            let data = Approval::get_data_for_sig(&approval.inner, approval.target_height);
+            if !approval.signature.verify(&data, &signer.public_key()) {
+                warn!(target: "client", target_height = approval.target_height, "Failed to sign approval, skipping it");
+                return Ok(());
+            }
+        }
         let next_epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(parent_hash)?;
         let next_block_producer =
             self.epoch_manager.get_block_producer(&next_epoch_id, approval.target_height)?;
+        if let ApprovalInner::Endorsement(block_hash) = &approval.inner {
+            self.chain.block_timelines.record_now(block_hash, BlockTimelineStage::ApprovalSent);
+        }
         if Some(&next_block_producer) == self.validator_signer.as_ref().map(|x| x.validator_id()) {
             self.collect_block_approval(&approval, ApprovalType::SelfApproval);
         } else {
@@ -1683,6 +1905,13 @@ impl Client {
             };
             self.chain.blocks_with_missing_chunks.prune_blocks_below_height(last_finalized_height);
 
+            if let Err(err) = self.record_validator_participation(block.header()) {
+                warn!(target: "client", ?err, "Failed to record participation of validators");
+            }
+            if let Err(err) = self.rotate_validator_key(block.header()) {
+                warn!(target: "client", ?err, "Failed to rotate the validator key");
+            }
+
             {
                 let _span = tracing::debug_span!(
                     target: "client",
@@ -1734,7 +1963,13 @@ impl Client {
                 && !self.sync_status.is_syncing()
                 && !skip_produce_chunk
             {
-                self.produce_chunks(&block, validator_id);
+                self.produce_chunks(&block, validator_id.clone());
+            }
+
+            if self.config.prefetch_receipts && !self.sync_status.is_syncing() {
+                if let Err(err) = self.prefetch_receipts(&block, &validator_id) {
+                    debug!(target: "client", ?err, block_hash = ?block.hash(), "Failed to start prefetching receipts");
+                }
             }
         }
 
@@ -1810,6 +2045,60 @@ impl Client {
         true
     }
 
+    /// Starts warming up the storage caches, on a background thread, for the receipts which the
+    /// next chunks of the shards this node cares about are going to apply. These are the outgoing
+    /// receipts of the chunks applied in `block`, so they are known as soon as it's accepted,
+    /// before the next chunks are even produced. Receipts which were outgoing from shards this
+    /// node doesn't track are not known and not prefetched.
+    fn prefetch_receipts(&self, block: &Block, validator_id: &AccountId) -> Result<(), Error> {
+        let block_hash = *block.hash();
+        let epoch_id = block.header().epoch_id();
+        let next_epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&block_hash)?;
+        // The receipts would have to be routed to the shards of the new layout, it's simpler to
+        // skip prefetching for the one block.
+        if self.epoch_manager.get_shard_layout(epoch_id)?
+            != self.epoch_manager.get_shard_layout(&next_epoch_id)?
+        {
+            return Ok(());
+        }
+        let mut receipts_by_shard: HashMap<ShardId, Vec<Receipt>> = HashMap::new();
+        for shard_id in 0..self.epoch_manager.num_shards(epoch_id)? {
+            let Ok(receipts) = self.chain.store().get_outgoing_receipts(&block_hash, shard_id)
+            else {
+                continue;
+            };
+            for receipt in receipts.iter() {
+                let to_shard_id =
+                    self.epoch_manager.account_id_to_shard_id(&receipt.receiver_id, epoch_id)?;
+                receipts_by_shard.entry(to_shard_id).or_default().push(receipt.clone());
+            }
+        }
+        let mut jobs = vec![];
+        for (shard_id, receipts) in receipts_by_shard {
+            if !self.shard_tracker.care_about_shard(Some(validator_id), &block_hash, shard_id, true)
+            {
+                continue;
+            }
+            let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, epoch_id)?;
+            let state_root = *self.chain.get_chunk_extra(&block_hash, &shard_uid)?.state_root();
+            jobs.push((shard_id, state_root, receipts));
+        }
+        if jobs.is_empty() {
+            return Ok(());
+        }
+        let runtime_adapter = self.runtime_adapter.clone();
+        std::thread::spawn(move || {
+            for (shard_id, state_root, receipts) in jobs {
+                if let Err(err) =
+                    runtime_adapter.prefetch_receipts(shard_id, &block_hash, state_root, &receipts)
+                {
+                    debug!(target: "client", shard_id, ?block_hash, ?err, "Failed to prefetch receipts");
+                }
+            }
+        });
+        Ok(())
+    }
+
     // Produce new chunks
     fn produce_chunks(&mut self, block: &Block, validator_id: AccountId) {
         let epoch_id =
@@ -1843,6 +2132,7 @@ impl Client {
                 Ok(None) => {}
                 Err(err) => {
                     error!(target: "client", "Error producing chunk {:?}", err);
+                    self.production_diagnostics.record_error(next_height, Some(shard_id), &err);
                 }
             }
         }
@@ -2101,7 +2391,11 @@ impl Client {
                     return;
                 }
             };
-        self.doomslug.on_approval_message(StaticClock::instant(), approval, &block_producer_stakes);
+        let now = StaticClock::instant();
+        if let ApprovalType::PeerApproval(_) = approval_type {
+            self.approval_latency.on_approval(account_id, *target_height, now);
+        }
+        self.doomslug.on_approval_message(now, approval, &block_producer_stakes);
     }
 
     /// Forwards given transaction to upcoming validators.
@@ -2277,6 +2571,23 @@ impl Client {
                         InsertTransactionResult::Success => {
                             trace!(target: "client", ?shard_uid, tx=?tx.get_hash(), "Recorded a transaction.");
                         }
+                        InsertTransactionResult::Replaced => {
+                            trace!(target: "client", ?shard_uid, tx=?tx.get_hash(), "Replaced a transaction with the same nonce.");
+                        }
+                        InsertTransactionResult::ReplacementUnderpriced => {
+                            trace!(target: "client", ?shard_uid, tx=?tx.get_hash(), "Transaction doesn't attach more gas than the one with the same nonce.");
+                            return Ok(ProcessTxResponse::RejectedByPool(format!(
+                                "a transaction with nonce {} is already pending, a replacement must attach more gas",
+                                tx.transaction.nonce
+                            )));
+                        }
+                        InsertTransactionResult::TooManyFutureTransactions => {
+                            trace!(target: "client", ?shard_uid, tx=?tx.get_hash(), "Too many transactions waiting for a nonce gap to close.");
+                            return Ok(ProcessTxResponse::RejectedByPool(format!(
+                                "too many transactions are waiting for the nonces before {} to be submitted",
+                                tx.transaction.nonce
+                            )));
+                        }
                         InsertTransactionResult::Duplicate => {
                             trace!(target: "client", ?shard_uid, tx=?tx.get_hash(), "Duplicate transaction, not forwarding it.");
                             return Ok(ProcessTxResponse::ValidTx);
@@ -2360,8 +2671,26 @@ impl Client {
         state_parts_arbiter_handle: &ArbiterHandle,
     ) -> Result<(), Error> {
         let me = &self.validator_signer.as_ref().map(|x| x.validator_id().clone());
+        // Catchups of the shards the node produces chunks of in the next epoch
+        // go first, so that the node is ready to produce them in time.
+        let mut state_sync_infos = vec![];
         for (sync_hash, state_sync_info) in self.chain.store().iterate_state_sync_infos()? {
+            let priority_shards = self.get_catchup_priority_shards(sync_hash, &state_sync_info, me);
+            state_sync_infos.push((sync_hash, state_sync_info, priority_shards));
+        }
+        state_sync_infos.sort_by_key(|(_, _, priority_shards)| priority_shards.is_empty());
+        for (sync_hash, state_sync_info, priority_shards) in state_sync_infos {
             assert_eq!(sync_hash, state_sync_info.epoch_tail_hash);
+            // The epoch started by a block on an abandoned fork never happens,
+            // so there is nothing to catch up and resharding for it is wasted
+            // work. The state sync info is removed by garbage collection.
+            if self.chain.is_on_abandoned_fork(&sync_hash)? {
+                if let Some((mut state_sync, _, _)) = self.catchup_state_syncs.remove(&sync_hash) {
+                    debug!(target: "catchup", ?sync_hash, "Block is on an abandoned fork, cancelling catchup");
+                    state_sync.cancel_state_splits();
+                }
+                continue;
+            }
             let network_adapter = self.network_adapter.clone();
 
             let shards_to_split = self.get_shards_to_split(sync_hash, &state_sync_info, me)?;
@@ -2380,7 +2709,9 @@ impl Client {
                             state_sync_timeout,
                             &self.config.chain_id,
                             &self.config.state_sync.sync,
+                            self.config.state_sync.apply_parts_while_downloading,
                             true,
+                            self.state_sync_download_limiter.clone(),
                         ),
                         shards_to_split,
                         BlocksCatchUpState::new(sync_hash, epoch_id),
@@ -2388,13 +2719,22 @@ impl Client {
                 });
 
             // For colour decorators to work, they need to printed directly. Otherwise the decorators get escaped, garble output and don't add colours.
-            debug!(target: "catchup", ?me, ?sync_hash, progress_per_shard = ?format_shard_sync_phase_per_shard(&shards_to_split, false), "Catchup");
+            debug!(target: "catchup", ?me, ?sync_hash, ?priority_shards, progress_per_shard = ?format_shard_sync_phase_per_shard(&shards_to_split, false), "Catchup");
             let use_colour = matches!(self.config.log_summary_style, LogSummaryStyle::Colored);
 
             // Initialize the new shard sync to contain the shards to split at
             // first. It will get updated with the shard sync download status
             // for other shards later.
             let new_shard_sync = shards_to_split;
+            // Parts of the priority shards are requested and applied first.
+            let mut tracking_shards: Vec<ShardId> =
+                state_sync_info.shards.iter().map(|tuple| tuple.0).collect();
+            tracking_shards.sort_by_key(|shard_id| !priority_shards.contains(shard_id));
+            let prioritized_state_parts_scheduler = |mut request: ApplyStatePartsRequest| {
+                request.priority =
+                    priority_shards.contains(&(request.shard_uid.shard_id as ShardId));
+                state_parts_task_scheduler(request)
+            };
             match state_sync.run(
                 me,
                 sync_hash,
@@ -2402,8 +2742,8 @@ impl Client {
                 &mut self.chain,
                 self.epoch_manager.as_ref(),
                 highest_height_peers,
-                state_sync_info.shards.iter().map(|tuple| tuple.0).collect(),
-                state_parts_task_scheduler,
+                tracking_shards,
+                &prioritized_state_parts_scheduler,
                 state_split_scheduler,
                 state_parts_arbiter_handle,
                 use_colour,
@@ -2415,11 +2755,16 @@ impl Client {
                 }
                 StateSyncResult::Completed => {
                     debug!(target: "catchup", "state sync completed now catch up blocks");
+                    let prioritized_block_catch_up_scheduler =
+                        |mut request: BlockCatchUpRequest| {
+                            request.priority = !priority_shards.is_empty();
+                            block_catch_up_task_scheduler(request)
+                        };
                     self.chain.catchup_blocks_step(
                         me,
                         &sync_hash,
                         blocks_catch_up_state,
-                        block_catch_up_task_scheduler,
+                        &prioritized_block_catch_up_scheduler,
                     )?;
 
                     if blocks_catch_up_state.is_finished() {
@@ -2442,6 +2787,28 @@ impl Client {
         Ok(())
     }
 
+    /// Returns the shards of the catchup which the node produces chunks of in
+    /// the epoch after the one started by `sync_hash`. If it can't be
+    /// determined, no shard gets priority.
+    fn get_catchup_priority_shards(
+        &self,
+        sync_hash: CryptoHash,
+        state_sync_info: &StateSyncInfo,
+        me: &Option<AccountId>,
+    ) -> HashSet<ShardId> {
+        let Some(account_id) = me else { return HashSet::new() };
+        state_sync_info
+            .shards
+            .iter()
+            .map(|tuple| tuple.0)
+            .filter(|&shard_id| {
+                self.epoch_manager
+                    .cares_about_shard_next_epoch_from_prev_block(&sync_hash, account_id, shard_id)
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
     /// This method checks which of the shards requested for state sync are already present.
     /// Any shard that is currently tracked needs not to be downloaded again.
     ///
@@ -2489,6 +2856,7 @@ impl Client {
             let shard_sync_download = ShardSyncDownload {
                 downloads: vec![],
                 status: ShardSyncStatus::StateSplitScheduling,
+                progress: None,
             };
             Some((shard_id, shard_sync_download))
         } else {
@@ -2538,7 +2906,8 @@ impl Client {
         // A RPC node should do regular garbage collection.
         if !self.config.archive {
             let tries = self.runtime_adapter.get_tries();
-            return self.chain.clear_data(tries, &self.config.gc);
+            self.chain.clear_data(tries, &self.config.gc)?;
+            return self.chain.prune_data(&self.config.gc);
         }
 
         // An archival node with split storage should perform garbage collection
@@ -2549,7 +2918,11 @@ impl Client {
         let kind = store.get_db_kind()?;
         if kind == Some(DbKind::Hot) {
             let tries = self.runtime_adapter.get_tries();
-            return self.chain.clear_data(tries, &self.config.gc);
+            self.chain.clear_data(tries, &self.config.gc)?;
+            if self.config.cold_store_eager_migration {
+                self.chain.prune_cold_data(self.config.gc.gc_blocks_limit)?;
+            }
+            return Ok(());
         }
 
         // An archival node with legacy storage or in the midst of migration to split
@@ -2697,7 +3070,7 @@ impl Client {
             let sync_block_height = self.chain.get_block_header(sync_hash)?.height();
             let shard_sync_status: HashMap<_, _> = shard_sync_state
                 .iter()
-                .map(|(shard_id, state)| (*shard_id, state.status.to_string()))
+                .map(|(shard_id, state)| (*shard_id, state.status_with_progress()))
                 .collect();
             ret.push(CatchupStatusView {
                 sync_block_hash: *sync_hash,
@@ -6,13 +6,14 @@
 //! https://github.com/near/nearcore/issues/7899
 
 use crate::adapter::{
-    BlockApproval, BlockHeadersResponse, BlockResponse, ProcessTxRequest, ProcessTxResponse,
-    RecvChallenge, SetNetworkInfo, StateResponse,
+    BlockApproval, BlockHeadersResponse, BlockResponse, EpochSyncResponse, ProcessTxRequest,
+    ProcessTxResponse, RecvChallenge, SetNetworkInfo, StateResponse,
 };
 use crate::client::{Client, EPOCH_START_INFO_BLOCKS};
 use crate::config_updater::ConfigUpdater;
 use crate::debug::new_network_info_view;
 use crate::info::{display_sync_status, InfoHelper};
+use crate::production_diagnostics::{MissedProductionKind, MAX_SKIPPED_HEIGHTS_TO_CHECK};
 use crate::sync::state::{StateSync, StateSyncResult};
 use crate::sync_jobs_actor::{create_sync_job_scheduler, SyncJobsActor};
 use crate::{metrics, StatusResponse};
@@ -22,6 +23,7 @@ use chrono::{DateTime, Utc};
 use near_async::messaging::{CanSend, Sender};
 use near_chain::chain::{
     ApplyStatePartsRequest, ApplyStatePartsResponse, BlockCatchUpRequest, BlockCatchUpResponse,
+    SyncJobProgress,
 };
 use near_chain::resharding::{StateSplitRequest, StateSplitResponse};
 use near_chain::state_snapshot_actor::MakeSnapshotCallback;
@@ -31,22 +33,24 @@ use near_chain::types::RuntimeAdapter;
 use near_chain::ChainStoreAccess;
 use near_chain::{
     byzantine_assert, near_chain_primitives, Block, BlockHeader, BlockProcessingArtifact,
-    ChainGenesis, DoneApplyChunkCallback, Provenance,
+    ChainEventBus, ChainGenesis, DoneApplyChunkCallback, Provenance,
 };
-use near_chain_configs::{ClientConfig, LogSummaryStyle};
+use near_chain_configs::{ClientConfig, LogSummaryStyle, SyncConfig};
 use near_chain_primitives::error::EpochErrorResultToChainError;
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::client::ShardsManagerResponse;
 use near_chunks::logic::cares_about_shard_this_or_next_epoch;
 use near_client_primitives::types::{
-    Error, GetClientConfig, GetClientConfigError, GetNetworkInfo, NetworkInfoResponse,
-    StateSyncStatus, Status, StatusError, StatusSyncInfo, SyncStatus,
+    DrainSyncJobs, Error, GetClientConfig, GetClientConfigError, GetGasCongestionError,
+    GetNetworkInfo, GetTxPoolStats, GetValidatorParticipation, GetValidatorParticipationError,
+    NetworkInfoResponse, ShardSyncStatus, StateSyncStatus, Status, StatusError, StatusSyncInfo,
+    SyncStatus,
 };
 use near_epoch_manager::shard_tracker::ShardTracker;
 use near_epoch_manager::EpochManagerAdapter;
 use near_network::types::ReasonForBan;
 use near_network::types::{
-    NetworkInfo, NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest,
+    NetworkInfo, NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest, SnapshotHostInfo,
 };
 use near_o11y::{handler_debug_span, OpenTelemetrySpanExt, WithSpanContext, WithSpanContextExt};
 use near_performance_metrics;
@@ -57,14 +61,16 @@ use near_primitives::epoch_manager::RngSeed;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::static_clock::StaticClock;
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{BlockHeight, ShardId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::{DetailedDebugStatus, ValidatorInfo};
+use near_primitives::views::{
+    DetailedDebugStatus, EpochParticipationView, TxPoolStatsView, ValidatorInfo,
+};
 #[cfg(feature = "test_features")]
-use near_store::DBCol;
+use near_store::{DBCol, Store};
 use near_telemetry::TelemetryActor;
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
@@ -81,6 +87,8 @@ const STATUS_WAIT_TIME_MULTIPLIER: u64 = 10;
 /// `max_block_production_time` times this multiplier is how long we wait before rebroadcasting
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
+/// How often to check whether a new state snapshot needs to be advertised to peers.
+const SNAPSHOT_HOST_INFO_CHECK_PERIOD: Duration = Duration::from_secs(10);
 
 pub struct ClientActor {
     /// Adversarial controls
@@ -105,6 +113,11 @@ pub struct ClientActor {
     // Last time when log_summary method was called.
     log_summary_timer_next_attempt: DateTime<Utc>,
 
+    /// Next time to check whether a new state snapshot needs to be advertised.
+    snapshot_host_info_next_attempt: DateTime<Utc>,
+    /// State parts which were last advertised as served from the state snapshot.
+    advertised_snapshot_host_info: Option<SnapshotHostInfo>,
+
     block_production_started: bool,
     doomslug_timer_next_attempt: DateTime<Utc>,
     sync_timer_next_attempt: DateTime<Utc>,
@@ -112,6 +125,7 @@ pub struct ClientActor {
     state_parts_task_scheduler: Box<dyn Fn(ApplyStatePartsRequest)>,
     block_catch_up_scheduler: Box<dyn Fn(BlockCatchUpRequest)>,
     state_split_scheduler: Box<dyn Fn(StateSplitRequest)>,
+    sync_jobs_actor_addr: Addr<SyncJobsActor>,
     state_parts_client_arbiter: Arbiter,
 
     #[cfg(feature = "sandbox")]
@@ -161,12 +175,14 @@ impl ClientActor {
     ) -> Result<Self, Error> {
         let state_parts_arbiter = Arbiter::new();
         let self_addr = ctx.address();
-        let self_addr_clone = self_addr;
+        let sync_jobs_actor = SyncJobsActor::new(self_addr, &config.sync_jobs).map_err(|err| {
+            Error::Other(format!("Failed to start the threads of sync jobs: {err}"))
+        })?;
         let sync_jobs_actor_addr = SyncJobsActor::start_in_arbiter(
             &state_parts_arbiter.handle(),
             move |ctx: &mut Context<SyncJobsActor>| -> SyncJobsActor {
                 ctx.set_mailbox_capacity(SyncJobsActor::MAILBOX_CAPACITY);
-                SyncJobsActor { client_addr: self_addr_clone }
+                sync_jobs_actor
             },
         );
         if let Some(vs) = &validator_signer {
@@ -197,6 +213,8 @@ impl ClientActor {
             info_helper,
             block_production_next_attempt: now,
             log_summary_timer_next_attempt: now,
+            snapshot_host_info_next_attempt: now,
+            advertised_snapshot_host_info: None,
             block_production_started: false,
             doomslug_timer_next_attempt: now,
             sync_timer_next_attempt: now,
@@ -208,8 +226,9 @@ impl ClientActor {
                 sync_jobs_actor_addr.clone(),
             ),
             state_split_scheduler: create_sync_job_scheduler::<StateSplitRequest>(
-                sync_jobs_actor_addr,
+                sync_jobs_actor_addr.clone(),
             ),
+            sync_jobs_actor_addr,
             state_parts_client_arbiter: state_parts_arbiter,
 
             #[cfg(feature = "sandbox")]
@@ -473,6 +492,7 @@ impl Handler<WithSpanContext<BlockHeadersResponse>> for ClientActor {
     ) -> Self::Result {
         self.wrap(msg, ctx, "BlockHeadersResponse", |this, msg| {
             let BlockHeadersResponse(headers, peer_id) = msg;
+            this.client.header_sync.on_response(&peer_id, headers.len());
             if this.receive_headers(headers, peer_id) {
                 Ok(())
             } else {
@@ -483,6 +503,26 @@ impl Handler<WithSpanContext<BlockHeadersResponse>> for ClientActor {
     }
 }
 
+impl Handler<WithSpanContext<EpochSyncResponse>> for ClientActor {
+    type Result = Result<(), ReasonForBan>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<EpochSyncResponse>,
+        ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        self.wrap(msg, ctx, "EpochSyncResponse", |this, msg| {
+            let EpochSyncResponse(proof, peer_id) = msg;
+            match this.client.epoch_sync.on_response(*proof, peer_id) {
+                Ok(()) => Ok(()),
+                Err(near_chain::Error::InvalidSignature) => Err(ReasonForBan::InvalidSignature),
+                Err(_) => Err(ReasonForBan::BadBlockHeader),
+            }
+        })
+    }
+}
+
 impl Handler<WithSpanContext<BlockApproval>> for ClientActor {
     type Result = ();
 
@@ -740,11 +780,15 @@ impl Handler<WithSpanContext<Status>> for ClientActor {
 }
 
 /// Private to public API conversion.
-fn make_peer_info(from: near_network::types::PeerInfo) -> near_client_primitives::types::PeerInfo {
+fn make_peer_info(
+    from: &near_network::types::ConnectedPeerInfo,
+) -> near_client_primitives::types::PeerInfo {
+    let peer_info = &from.full_peer_info.peer_info;
     near_client_primitives::types::PeerInfo {
-        id: from.id,
-        addr: from.addr,
-        account_id: from.account_id,
+        id: peer_info.id.clone(),
+        addr: peer_info.addr,
+        account_id: peer_info.account_id.clone(),
+        reputation: from.reputation,
     }
 }
 
@@ -774,7 +818,7 @@ impl Handler<WithSpanContext<GetNetworkInfo>> for ClientActor {
 
         Ok(NetworkInfoResponse {
             connected_peers: (self.network_info.connected_peers.iter())
-                .map(|fpi| make_peer_info(fpi.full_peer_info.peer_info.clone()))
+                .map(make_peer_info)
                 .collect(),
             num_connected_peers: self.network_info.num_connected_peers,
             peer_max_count: self.network_info.peer_max_count,
@@ -1069,6 +1113,7 @@ impl ClientActor {
                     if let Err(err) = self.produce_block(height) {
                         // If there is an error, report it and let it retry on the next loop step.
                         error!(target: "client", height, "Block production failed: {}", err);
+                        self.client.production_diagnostics.record_error(height, None, &err);
                     } else {
                         self.post_block_production();
                     }
@@ -1190,6 +1235,21 @@ impl ClientActor {
                 .to_std()
                 .unwrap_or(delay),
         );
+
+        self.snapshot_host_info_next_attempt = self.run_timer(
+            SNAPSHOT_HOST_INFO_CHECK_PERIOD,
+            self.snapshot_host_info_next_attempt,
+            ctx,
+            |act, _ctx| act.advertise_state_snapshot(),
+            "advertise_state_snapshot",
+        );
+        delay = core::cmp::min(
+            delay,
+            self.snapshot_host_info_next_attempt
+                .signed_duration_since(now)
+                .to_std()
+                .unwrap_or(delay),
+        );
         timer.observe_duration();
         delay
     }
@@ -1220,6 +1280,8 @@ impl ClientActor {
     fn try_doomslug_timer(&mut self, _: &mut Context<ClientActor>) {
         let _span = tracing::debug_span!(target: "client", "try_doomslug_timer").entered();
         let _ = self.client.check_and_update_doomslug_tip();
+        // Picks up the delays if they were updated while the node is running.
+        self.client.doomslug.set_timer_config(&self.client.config.doomslug_timer.get());
         let approvals = self.client.doomslug.process_timer(StaticClock::instant());
 
         // Important to save the largest approval target height before sending approvals, so
@@ -1346,10 +1408,100 @@ impl ClientActor {
             let block = self.client.chain.get_block(&accepted_block).unwrap().clone();
             self.send_chunks_metrics(&block);
             self.send_block_metrics(&block);
+            self.check_missed_production(&block);
             self.check_send_announce_account(*block.header().last_final_block());
         }
     }
 
+    /// Checks whether this node missed producing a block at the heights
+    /// skipped before a block on the canonical chain, or one of the chunks
+    /// missing in it, and records the misses.
+    fn check_missed_production(&mut self, block: &Block) {
+        let Some(validator_signer) = self.client.validator_signer.clone() else { return };
+        if self.client.sync_status.is_syncing() {
+            return;
+        }
+        let header = block.header();
+        let height = header.height();
+        if self.client.chain.get_block_hash_by_height(height).ok().as_ref() != Some(header.hash())
+            || !self.client.production_diagnostics.start_check(height)
+        {
+            return;
+        }
+        let Ok(prev_header) = self.client.chain.get_block_header(header.prev_hash()) else {
+            return;
+        };
+        let validator_id = validator_signer.validator_id();
+        let epoch_manager = self.client.epoch_manager.clone();
+        let epoch_id = header.epoch_id();
+
+        let mut missed = vec![];
+        let first_skipped_height = std::cmp::max(
+            prev_header.height() + 1,
+            height.saturating_sub(MAX_SKIPPED_HEIGHTS_TO_CHECK),
+        );
+        for skipped_height in first_skipped_height..height {
+            if epoch_manager.get_block_producer(epoch_id, skipped_height).ok().as_ref()
+                != Some(validator_id)
+            {
+                continue;
+            }
+            let produced = self
+                .client
+                .block_production_info
+                .get(skipped_height)
+                .block_production_time
+                .is_some();
+            missed.push(self.client.production_diagnostics.on_missed(
+                MissedProductionKind::Block,
+                skipped_height,
+                None,
+                produced,
+            ));
+        }
+        for (shard_id, &included) in header.chunk_mask().iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            if included
+                || epoch_manager.get_chunk_producer(epoch_id, height, shard_id).ok().as_ref()
+                    != Some(validator_id)
+            {
+                continue;
+            }
+            let produced = self
+                .client
+                .chunk_production_info
+                .peek(&(height, shard_id))
+                .map_or(false, |production| production.chunk_production_time.is_some());
+            missed.push(self.client.production_diagnostics.on_missed(
+                MissedProductionKind::Chunk,
+                height,
+                Some(shard_id),
+                produced,
+            ));
+        }
+        if missed.is_empty() {
+            return;
+        }
+
+        let block_production: Vec<_> = missed
+            .iter()
+            .filter(|missed| missed.kind == MissedProductionKind::Block)
+            .map(|missed| (missed.height, self.client.block_production_info.get(missed.height)))
+            .collect();
+        let client_state = serde_json::json!({
+            "block_hash": header.hash(),
+            "head": self.client.chain.head().ok(),
+            "header_head": self.client.chain.header_head().ok(),
+            "sync_status": format!("{:?}", self.client.sync_status),
+            "block_production": block_production,
+            "chunk_production": self.client.chunk_production_info.iter().collect::<Vec<_>>(),
+            "approval_history": self.client.doomslug.get_approval_history(),
+            "approval_latencies": self.client.approval_latency.get_approval_latencies(),
+            "network_info": new_network_info_view(&self.client.chain, &self.network_info),
+        });
+        self.client.production_diagnostics.save_bundle(missed, client_state);
+    }
+
     /// Returns the callback function that will be passed to various functions that may trigger
     /// the processing of new blocks. This callback will be called at the end of applying chunks
     /// for every block.
@@ -1469,11 +1621,26 @@ impl ClientActor {
     /// Select the block hash we are using to sync state. It will sync with the state before applying the
     /// content of such block.
     ///
-    /// The selected block will always be the first block on a new epoch:
-    /// <https://github.com/nearprotocol/nearcore/issues/2021#issuecomment-583039862>.
+    /// The selected block is the first block on a new epoch:
+    /// <https://github.com/nearprotocol/nearcore/issues/2021#issuecomment-583039862>,
+    /// unless `sync_to_latest_final_block` is set and the state is synced from
+    /// peers, in which case it's the latest final block of the current epoch
+    /// if possible.
     fn find_sync_hash(&mut self) -> Result<CryptoHash, near_chain::Error> {
         let header_head = self.client.chain.header_head()?;
         let sync_hash = header_head.last_block_hash;
+        let state_sync_config = &self.client.config.state_sync;
+        let final_sync_hash = if state_sync_config.sync_to_latest_final_block
+            && matches!(state_sync_config.sync, SyncConfig::Peers)
+        {
+            StateSync::get_final_sync_hash(&self.client.chain, &sync_hash)?
+        } else {
+            None
+        };
+        if let Some(final_sync_hash) = final_sync_hash {
+            tracing::debug!(target: "sync", ?header_head, ?final_sync_hash, "find_sync_hash");
+            return Ok(final_sync_hash);
+        }
         let epoch_start_sync_hash =
             StateSync::get_epoch_start_sync_hash(&mut self.client.chain, &sync_hash)?;
 
@@ -1595,6 +1762,21 @@ impl ClientActor {
                         "enabling sync: {}", &sync,
                     );
                 }
+                // Prove the epochs before downloading the headers of the last of them.
+                if self.client.config.epoch_sync_enabled
+                    && cfg!(feature = "new_epoch_sync")
+                    && !self.client.epoch_sync.done
+                {
+                    unwrap_and_report!(self.client.epoch_sync.run(
+                        &mut self.client.sync_status,
+                        &mut self.client.chain,
+                        highest_height,
+                        &self.network_info.highest_height_peers
+                    ));
+                    if !self.client.epoch_sync.done {
+                        return;
+                    }
+                }
                 // Run each step of syncing separately.
                 unwrap_and_report!(self.client.header_sync.run(
                     &mut self.client.sync_status,
@@ -1724,9 +1906,56 @@ impl ClientActor {
         }
     }
 
+    /// Advertises to peers the state parts which can be served from the state
+    /// snapshot, once a new snapshot is made.
+    fn advertise_state_snapshot(&mut self) {
+        let info = match self.get_snapshot_host_info() {
+            Ok(Some(info)) => info,
+            Ok(None) => return,
+            Err(err) => {
+                debug!(target: "client", ?err, "Failed to get the state snapshot info");
+                return;
+            }
+        };
+        if self.advertised_snapshot_host_info.as_ref() == Some(&info) {
+            return;
+        }
+        info!(target: "client", epoch_id = ?info.epoch_id, shards = ?info.shards, "Advertising state snapshot to peers");
+        self.advertised_snapshot_host_info = Some(info.clone());
+        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::SnapshotHostInfo(info),
+        ));
+    }
+
+    /// Returns the state parts which can be served from the state snapshot, if
+    /// there is one.
+    fn get_snapshot_host_info(&self) -> Result<Option<SnapshotHostInfo>, near_chain::Error> {
+        let Ok(snapshot_hash) = self.client.runtime_adapter.get_tries().get_state_snapshot_hash()
+        else {
+            return Ok(None);
+        };
+        // The snapshot is taken at the end of an epoch, so its state is synced
+        // to with the first block of the next epoch.
+        let epoch_id = self.client.epoch_manager.get_epoch_id(&snapshot_hash)?;
+        let sync_epoch_id = self.client.epoch_manager.get_next_epoch_id(&snapshot_hash)?;
+        let prev_hash = *self.client.chain.get_block_header(&snapshot_hash)?.prev_hash();
+        let me = self.client.validator_signer.as_ref().map(|signer| signer.validator_id().clone());
+        let num_shards = self.client.epoch_manager.num_shards(&epoch_id)?;
+        let shards = (0..num_shards)
+            .filter(|&shard_id| {
+                self.client.shard_tracker.care_about_shard(me.as_ref(), &prev_hash, shard_id, true)
+            })
+            .collect::<Vec<_>>();
+        if shards.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SnapshotHostInfo { epoch_id: sync_epoch_id, shards }))
+    }
+
     /// Print current summary.
     fn log_summary(&mut self) {
         let _span = tracing::debug_span!(target: "client", "log_summary").entered();
+        self.client.chain.update_block_pools_metrics();
         self.info_helper.log_summary(
             &self.client,
             &self.node_id,
@@ -1754,11 +1983,47 @@ impl Handler<WithSpanContext<ApplyStatePartsResponse>> for ClientActor {
     ) -> Self::Result {
         let (_span, msg) = handler_debug_span!(target: "client", msg);
         tracing::debug!(target: "client", ?msg);
-        if let Some((sync, _, _)) = self.client.catchup_state_syncs.get_mut(&msg.sync_hash) {
+        let sync = match self.client.catchup_state_syncs.get_mut(&msg.sync_hash) {
             // We are doing catchup
-            sync.set_apply_result(msg.shard_id, msg.apply_result);
-        } else {
-            self.client.state_sync.set_apply_result(msg.shard_id, msg.apply_result);
+            Some((sync, _, _)) => sync,
+            None => &mut self.client.state_sync,
+        };
+        match msg.part_id {
+            Some(part_id) => sync.set_part_apply_result(msg.shard_id, part_id, msg.apply_result),
+            None => sync.set_apply_result(msg.shard_id, msg.apply_result),
+        }
+    }
+}
+
+impl Handler<WithSpanContext<SyncJobProgress>> for ClientActor {
+    type Result = ();
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<SyncJobProgress>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let shard_sync = match self.client.catchup_state_syncs.get_mut(&msg.sync_hash) {
+            // We are doing catchup
+            Some((_, shard_sync, _)) => shard_sync,
+            None => match &mut self.client.sync_status {
+                SyncStatus::StateSync(status) if status.sync_hash == msg.sync_hash => {
+                    &mut status.sync_status
+                }
+                _ => return,
+            },
+        };
+        // The progress may arrive after the job is done.
+        if let Some(shard_sync_download) = shard_sync.get_mut(&msg.shard_id) {
+            if matches!(
+                shard_sync_download.status,
+                ShardSyncStatus::StateDownloadApplying | ShardSyncStatus::StateSplitApplying
+            ) {
+                shard_sync_download.progress = Some(msg.progress);
+            }
         }
     }
 }
@@ -1796,6 +2061,10 @@ impl Handler<WithSpanContext<StateSplitResponse>> for ClientActor {
     ) -> Self::Result {
         let (_span, msg) = handler_debug_span!(target: "client", msg);
         tracing::debug!(target: "client", ?msg);
+        // Nobody waits for the result of a cancelled split anymore.
+        if let Err(near_chain_primitives::Error::ReshardingCancelled) = msg.new_state_roots {
+            return;
+        }
         if let Some((sync, _, _)) = self.client.catchup_state_syncs.get_mut(&msg.sync_hash) {
             // We are doing catchup
             sync.set_split_result(msg.shard_id, msg.new_state_roots);
@@ -1852,6 +2121,68 @@ impl Handler<WithSpanContext<GetClientConfig>> for ClientActor {
     }
 }
 
+impl Handler<WithSpanContext<GetValidatorParticipation>> for ClientActor {
+    type Result = Result<Vec<EpochParticipationView>, GetValidatorParticipationError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<GetValidatorParticipation>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+
+        Ok(self.client.validator_participation.get_participation(msg.num_epochs))
+    }
+}
+
+impl Handler<WithSpanContext<GetTxPoolStats>> for ClientActor {
+    type Result = Result<Vec<TxPoolStatsView>, GetGasCongestionError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<GetTxPoolStats>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+
+        Ok(self
+            .client
+            .sharded_tx_pool
+            .pools()
+            .map(|(shard_uid, pool)| TxPoolStatsView {
+                shard_id: shard_uid.shard_id as ShardId,
+                num_transactions: pool.len() as u64,
+                size: pool.transaction_size(),
+            })
+            .collect())
+    }
+}
+
+impl Handler<WithSpanContext<DrainSyncJobs>> for ClientActor {
+    type Result = actix::ResponseFuture<bool>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<DrainSyncJobs>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let sync_jobs_actor_addr = self.sync_jobs_actor_addr.clone();
+        Box::pin(async move {
+            sync_jobs_actor_addr.send(msg.with_span_context()).await.unwrap_or_else(|err| {
+                tracing::error!(target: "client", ?err, "Failed to drain the sync jobs");
+                false
+            })
+        })
+    }
+}
+
 /// Returns random seed sampled from the current thread
 pub fn random_seed_from_thread() -> RngSeed {
     let mut rng_seed: RngSeed = [0; 32];
@@ -1859,7 +2190,8 @@ pub fn random_seed_from_thread() -> RngSeed {
     rng_seed
 }
 
-/// Starts client in a separate Arbiter (thread).
+/// Starts client in a separate Arbiter (thread).  Also returns the bus events
+/// of blocks processed by the client are published on.
 pub fn start_client(
     client_config: ClientConfig,
     chain_genesis: ChainGenesis,
@@ -1875,7 +2207,8 @@ pub fn start_client(
     sender: Option<broadcast::Sender<()>>,
     adv: crate::adversarial::Controls,
     config_updater: Option<ConfigUpdater>,
-) -> (Addr<ClientActor>, ArbiterHandle) {
+    cold_store: Option<Store>,
+) -> (Addr<ClientActor>, ArbiterHandle, ChainEventBus) {
     let client_arbiter = Arbiter::new();
     let client_arbiter_handle = client_arbiter.handle();
 
@@ -1892,8 +2225,10 @@ pub fn start_client(
         true,
         random_seed_from_thread(),
         make_state_snapshot_callback,
+        cold_store,
     )
     .unwrap();
+    let chain_event_bus = client.chain.chain_event_bus().clone();
     let client_addr = ClientActor::start_in_arbiter(&client_arbiter_handle, move |ctx| {
         ClientActor::new(
             client,
@@ -1910,5 +2245,5 @@ pub fn start_client(
         )
         .unwrap()
     });
-    (client_addr, client_arbiter_handle)
+    (client_addr, client_arbiter_handle, chain_event_bus)
 }
@@ -35,7 +35,8 @@ use near_network::types::{ConnectedPeerInfo, NetworkInfo, PeerType};
 use near_primitives::sharding::ShardChunkHeader;
 use near_primitives::static_clock::StaticClock;
 use near_primitives::views::{
-    AccountDataView, KnownProducerView, NetworkInfoView, PeerInfoView, Tier1ProxyView,
+    AccountDataView, ColumnStatsView, KnownProducerView, NetworkInfoView, PeerInfoView,
+    Tier1ProxyView, TransactionPoolView,
 };
 
 // Constants for debug requests.
@@ -179,11 +180,60 @@ impl Handler<WithSpanContext<DebugStatus>> for ClientActor {
             DebugStatus::ChainProcessingStatus => Ok(DebugStatusResponse::ChainProcessingStatus(
                 self.client.chain.get_chain_processing_info(),
             )),
+            DebugStatus::StoreColumnStats => {
+                Ok(DebugStatusResponse::StoreColumnStats(self.get_store_column_stats()))
+            }
+            DebugStatus::BlockTimelines => Ok(DebugStatusResponse::BlockTimelines(
+                self.client.chain.block_timelines.get_block_timelines(),
+            )),
+            DebugStatus::QuarantinedBlocks => Ok(DebugStatusResponse::QuarantinedBlocks(
+                self.client.chain.store().get_quarantined_blocks()?,
+            )),
+            DebugStatus::QuarantinedBlock(block_hash) => Ok(DebugStatusResponse::QuarantinedBlock(
+                self.client.chain.store().get_quarantined_block(&block_hash)?,
+            )),
+            DebugStatus::TransactionPool => {
+                Ok(DebugStatusResponse::TransactionPool(self.get_transaction_pool_view()))
+            }
         }
     }
 }
 
 impl ClientActor {
+    fn get_transaction_pool_view(&self) -> Vec<TransactionPoolView> {
+        let mut pools: Vec<_> = self.client.sharded_tx_pool.pools().collect();
+        pools.sort_by_key(|(shard_uid, _)| **shard_uid);
+        pools
+            .into_iter()
+            .map(|(shard_uid, pool)| TransactionPoolView {
+                shard_uid: shard_uid.to_string(),
+                num_transactions: pool.len() as u64,
+                num_future_transactions: pool.num_future_transactions() as u64,
+                num_groups: pool.num_groups() as u64,
+                size_bytes: pool.transaction_size(),
+                size_limit_bytes: pool.transaction_size_limit(),
+                count_limit: pool.transaction_count_limit().map(|limit| limit as u64),
+            })
+            .collect()
+    }
+
+    fn get_store_column_stats(&self) -> Vec<ColumnStatsView> {
+        self.client
+            .chain
+            .store()
+            .store()
+            .column_stats()
+            .into_iter()
+            .map(|(col, stats)| ColumnStatsView {
+                column: col.to_string(),
+                estimated_num_keys: stats.estimated_num_keys,
+                estimated_live_data_size: stats.estimated_live_data_size,
+                total_sst_files_size: stats.total_sst_files_size,
+                num_sst_files: stats.num_sst_files,
+            })
+            .collect()
+    }
+
     // Gets a list of block producers and chunk-only producers for a given epoch.
     fn get_producers_for_epoch(
         &self,
@@ -622,6 +672,7 @@ impl ClientActor {
             head_height: head.height,
             shards: self.client.epoch_manager.num_shards(&head.epoch_id).unwrap_or_default(),
             approval_history: self.client.doomslug.get_approval_history(),
+            approval_latencies: self.client.approval_latency.get_approval_latencies(),
             production: productions,
             banned_chunk_producers: self
                 .client
@@ -639,7 +639,7 @@ pub fn display_sync_status(
             let mut shard_statuses: Vec<_> = shard_statuses.iter().collect();
             shard_statuses.sort_by_key(|(shard_id, _)| *shard_id);
             for (shard_id, shard_status) in shard_statuses {
-                write!(res, "[{}: {}]", shard_id, shard_status.status.to_string(),).unwrap();
+                write!(res, "[{}: {}]", shard_id, shard_status.status_with_progress()).unwrap();
             }
             match state_sync_config {
                 SyncConfig::Peers => {
@@ -1,11 +1,13 @@
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetClientConfig, GetExecutionOutcome, GetExecutionOutcomeResponse,
-    GetExecutionOutcomesForBlock, GetGasPrice, GetMaintenanceWindows, GetNetworkInfo,
-    GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetSplitStorageInfo, GetStateChanges,
-    GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
-    QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    DrainSyncJobs, Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
+    GetChunk, GetClientConfig, GetExecutionOutcome, GetExecutionOutcomeResponse,
+    GetExecutionOutcomesForBlock, GetGasCongestion, GetGasPrice, GetMaintenanceWindows,
+    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetSplitStorageInfo,
+    GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
+    GetStateChangesWithCauseInBlockForTrackedShards, GetStateProof, GetTransactionExecutionTree,
+    GetTxPoolStats, GetValidatorInfo, GetValidatorOrdered, GetValidatorParticipation, Query,
+    QueryBatch, QueryBatchResponse, QueryError, Simulate, SimulateResponse, Status, StatusResponse,
+    SyncStatus, TxStatus, TxStatusError,
 };
 
 pub use near_client_primitives::debug::DebugStatus;
@@ -18,19 +20,28 @@ pub use crate::client::Client;
 pub use crate::client_actor::NetworkAdversarialMessage;
 pub use crate::client_actor::{start_client, ClientActor};
 pub use crate::config_updater::ConfigUpdater;
+pub use crate::optimistic_witness::OptimisticStateWitness;
+pub use crate::remote_signer::RemoteValidatorSigner;
 pub use crate::view_client::{start_view_client, ViewClientActor};
 
 pub mod adapter;
 pub mod adversarial;
+mod approval_latency;
+mod chunk_production_budget;
 mod client;
 mod client_actor;
 mod config_updater;
 pub mod debug;
 mod info;
 mod metrics;
+mod optimistic_witness;
+mod production_diagnostics;
+mod remote_signer;
 pub mod sync;
 mod sync_jobs_actor;
 pub mod test_utils;
 #[cfg(test)]
 mod tests;
+mod validator_key_rotation;
+mod validator_participation;
 mod view_client;
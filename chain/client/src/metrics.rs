@@ -175,6 +175,22 @@ pub(crate) static TRACKED_SHARDS: Lazy<IntGaugeVec> = Lazy::new(|| {
 pub(crate) static SYNC_STATUS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_sync_status", "Node sync status").unwrap());
 
+pub(crate) static HEADER_SYNC_HEADERS_PER_SECOND: Lazy<Gauge> = Lazy::new(|| {
+    try_create_gauge(
+        "near_header_sync_headers_per_second",
+        "Heights per second by which header sync advances the header head",
+    )
+    .unwrap()
+});
+
+pub(crate) static HEADER_SYNC_STALLED_REQUESTS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_header_sync_stalled_requests_total",
+        "Number of header requests which peers didn't answer in time",
+    )
+    .unwrap()
+});
+
 pub(crate) static EPOCH_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_epoch_height", "Height of the epoch at the head of the blockchain")
         .unwrap()
@@ -210,6 +226,15 @@ pub(crate) static CHUNK_SKIPPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static MISSED_PRODUCTION_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_missed_production_total",
+        "Number of blocks and chunks this node was assigned to produce which didn't make it into the canonical chain, by reason",
+        &["kind", "reason"],
+    )
+    .unwrap()
+});
+
 pub(crate) static CHUNK_PRODUCER_BANNED_FOR_EPOCH: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
         "near_chunk_producer_banned_for_epoch",
@@ -247,6 +272,34 @@ pub(crate) static CLIENT_MESSAGES_PROCESSING_TIME: Lazy<HistogramVec> = Lazy::ne
     .unwrap()
 });
 
+pub(crate) static APPROVAL_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_approval_latency_seconds",
+        "Time between the start of block production and arrival of approvals by validator. Only approvals for heights this node produces blocks at are counted",
+        &["account_id"],
+        Some(exponential_buckets(0.01, 1.5, 16).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static VALIDATOR_APPROVALS_EXPECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_validator_approvals_expected_total",
+        "Number of blocks which became the head of this node and could contain an approval of the validator",
+        &["account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static VALIDATOR_APPROVALS_INCLUDED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_validator_approvals_included_total",
+        "Number of blocks which became the head of this node and contain an approval of the validator",
+        &["account_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static CHECK_TRIGGERS_TIME: Lazy<Histogram> = Lazy::new(|| {
     try_create_histogram(
         "near_client_triggers_time",
@@ -365,6 +418,25 @@ pub(crate) static PRODUCE_CHUNK_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static CHUNK_PRODUCTION_STAGE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_production_stage_time",
+        "Time taken by each stage of producing a chunk",
+        &["shard_id", "stage"],
+        Some(exponential_buckets(0.001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static CHUNK_PRODUCTION_BUDGET_EXCEEDED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_production_budget_exceeded_total",
+        "Number of produced chunks whose production ran out of its time budget, by the stage in which it ran out",
+        &["shard_id", "stage"],
+    )
+    .unwrap()
+});
+
 pub(crate) static VIEW_CLIENT_MESSAGE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_view_client_messages_processing_time",
@@ -449,6 +521,15 @@ pub(crate) static STATE_SYNC_DISCARD_PARTS: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static STATE_SYNC_INVALID_PARTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_invalid_parts_total",
+        "Number of downloaded parts which didn't match the state root when applied",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static STATE_SYNC_EXTERNAL_PARTS_DONE: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_state_sync_external_parts_done_total",
@@ -487,6 +568,43 @@ pub(crate) static STATE_SYNC_EXTERNAL_PARTS_SIZE_DOWNLOADED: Lazy<IntCounterVec>
         .unwrap()
     });
 
+pub(crate) static STATE_SYNC_PARTS_SIZE_DOWNLOADED_BY_SOURCE: Lazy<IntCounterVec> =
+    Lazy::new(|| {
+        try_create_int_counter_vec(
+            "near_state_sync_parts_size_downloaded_by_source_bytes_total",
+            "Bytes of state parts downloaded from each source, either external storage or peers",
+            &["shard_id", "source"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static STATE_SYNC_SOURCE_FALLBACKS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_source_fallbacks_total",
+        "Number of times a range of state parts switched to the source in the label after failing to download from the other one",
+        &["shard_id", "source"],
+    )
+    .unwrap()
+});
+
+pub(crate) static STATE_SYNC_BANDWIDTH_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_bandwidth_bytes_total",
+        "Bytes of state parts downloaded or served, counted by the bandwidth limiter",
+        &["direction"],
+    )
+    .unwrap()
+});
+
+pub(crate) static STATE_SYNC_BANDWIDTH_THROTTLED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_state_sync_bandwidth_throttled_total",
+        "Number of state part transfers held back by the bandwidth limits or time windows of state sync",
+        &["direction", "reason"],
+    )
+    .unwrap()
+});
+
 pub(crate) static STATE_SYNC_DUMP_PUT_OBJECT_ELAPSED: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_state_sync_dump_put_object_elapsed_sec",
@@ -506,3 +624,68 @@ pub(crate) static STATE_SYNC_DUMP_LIST_OBJECT_ELAPSED: Lazy<HistogramVec> = Lazy
     )
     .unwrap()
 });
+
+pub(crate) static SYNC_JOBS_QUEUED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_sync_jobs_queued",
+        "Number of background jobs of state sync, catchup and resharding waiting to run",
+        &["job"],
+    )
+    .unwrap()
+});
+
+pub(crate) static SYNC_JOBS_RUNNING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_sync_jobs_running",
+        "Number of background jobs of state sync, catchup and resharding running",
+        &["job"],
+    )
+    .unwrap()
+});
+
+pub(crate) static OPTIMISTIC_STATE_WITNESS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_optimistic_state_witness_total",
+        "Number of produced chunks for which recording an optimistic state witness was attempted, by result",
+        &["shard_id", "result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static OPTIMISTIC_STATE_WITNESS_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_optimistic_state_witness_time_sec",
+        "Time taken to record an optimistic state witness in the background",
+        &["shard_id"],
+        Some(exponential_buckets(0.001, 1.6, 20).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static REMOTE_SIGNER_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_remote_signer_latency_sec",
+        "Time taken by the remote signing service to produce a signature, by the kind of the signed data",
+        &["kind"],
+        Some(exponential_buckets(0.001, 1.6, 20).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static REMOTE_SIGNER_REQUESTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_remote_signer_requests_total",
+        "Number of requests to the remote signing service, by the kind of the signed data and result",
+        &["kind", "result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static REMOTE_SIGNER_FALLBACK_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_remote_signer_fallback_total",
+        "Number of signatures not produced by the remote signing service, by the kind of the signed data and the fallback used",
+        &["kind", "fallback"],
+    )
+    .unwrap()
+});
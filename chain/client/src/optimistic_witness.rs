@@ -0,0 +1,219 @@
+//! Optimistic recording of the state witnesses of produced chunks.
+//!
+//! A stateless validator needs the storage proof of all the state read while
+//! applying a chunk. Recording it while the chunk is applied would put it on
+//! the critical path of block processing, so instead, as soon as a chunk is
+//! produced, the proof for the state it's expected to read is recorded on a
+//! background thread, from a snapshot of the mem-trie at the chunk's
+//! pre-state root. The snapshot keeps that version of the trie readable while
+//! the chain keeps applying blocks and garbage collecting old roots.
+//!
+//! The pre-state root is the state root of the previous chunk of the shard,
+//! which the mem-trie holds because the trie changes of every applied chunk
+//! are committed to it (see `ShardTries::apply_mem_trie_changes`).
+//!
+//! The witness is optimistic: it only covers the state read by the chunk's
+//! transactions and the delayed receipts queue, which is known at production
+//! time, and is only recorded for shards with a loaded mem-trie.
+
+use crate::metrics;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{ShardId, StateRoot};
+use near_store::trie::mem::MemTrieRecorder;
+use near_store::{PartialStorage, ShardTries, TrieDBStorage};
+use std::sync::{Arc, Mutex};
+
+/// Number of recorded witnesses to keep, a few per shard.
+const OPTIMISTIC_WITNESS_CACHE_SIZE: usize = 64;
+
+/// Storage proof recorded for a chunk produced on top of `prev_block_hash`.
+#[derive(Debug)]
+pub struct OptimisticStateWitness {
+    pub prev_block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    /// The pre-state root of the chunk, which the proof is rooted at.
+    pub state_root: StateRoot,
+    pub storage_proof: PartialStorage,
+}
+
+/// Records optimistic state witnesses in the background and keeps the most
+/// recent ones around until they are needed.
+pub struct OptimisticWitnessRecorder {
+    witnesses: Arc<Mutex<lru::LruCache<(CryptoHash, ShardId), Arc<OptimisticStateWitness>>>>,
+}
+
+impl OptimisticWitnessRecorder {
+    pub fn new() -> Self {
+        Self { witnesses: Arc::new(Mutex::new(lru::LruCache::new(OPTIMISTIC_WITNESS_CACHE_SIZE))) }
+    }
+
+    /// Starts recording the witness of a chunk with the given transactions,
+    /// produced on top of `prev_block_hash` with the given pre-state root.
+    /// Returns the handle of the recording thread, or `None` if the witness
+    /// can't be recorded optimistically.
+    pub fn start(
+        &self,
+        shard_tries: &ShardTries,
+        shard_uid: ShardUId,
+        prev_block_hash: CryptoHash,
+        state_root: StateRoot,
+        transactions: &[SignedTransaction],
+    ) -> Option<std::thread::JoinHandle<()>> {
+        let shard_id = shard_uid.shard_id as ShardId;
+        let shard_label = shard_id.to_string();
+        let Some(mem_tries) = shard_tries.get_mem_tries(shard_uid) else {
+            metrics::OPTIMISTIC_STATE_WITNESS_TOTAL
+                .with_label_values(&[&shard_label, "no_mem_trie"])
+                .inc();
+            return None;
+        };
+        // Only the refcount of the root is updated under the write lock, the
+        // recording itself happens under the read lock on another thread.
+        let snapshot = match mem_tries.write().unwrap().take_snapshot(&state_root) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                tracing::debug!(target: "client", shard_id, ?prev_block_hash, ?err, "Cannot record optimistic state witness");
+                metrics::OPTIMISTIC_STATE_WITNESS_TOTAL
+                    .with_label_values(&[&shard_label, "no_root"])
+                    .inc();
+                return None;
+            }
+        };
+        let keys = state_witness_keys(transactions);
+        let store = shard_tries.get_store();
+        let witnesses = self.witnesses.clone();
+        Some(std::thread::spawn(move || {
+            let _span = tracing::debug_span!(target: "client", "record_optimistic_state_witness", shard_id, ?prev_block_hash, num_keys = keys.len()).entered();
+            let _timer = metrics::OPTIMISTIC_STATE_WITNESS_TIME
+                .with_label_values(&[&shard_label])
+                .start_timer();
+            let mut recorder = MemTrieRecorder::new();
+            {
+                let mem_tries = mem_tries.read().unwrap();
+                for key in &keys {
+                    let _ = snapshot.record_lookup(&mem_tries, key, &mut recorder);
+                }
+            }
+            mem_tries.write().unwrap().release_snapshot(snapshot);
+            let start = StaticClock::instant();
+            match recorder.into_partial_storage(&TrieDBStorage::new(store, shard_uid)) {
+                Ok(storage_proof) => {
+                    tracing::debug!(target: "client", shard_id, ?prev_block_hash, num_values = storage_proof.nodes.len(), values_elapsed = ?start.elapsed(), "Recorded optimistic state witness");
+                    metrics::OPTIMISTIC_STATE_WITNESS_TOTAL
+                        .with_label_values(&[&shard_label, "recorded"])
+                        .inc();
+                    let witness = OptimisticStateWitness {
+                        prev_block_hash,
+                        shard_id,
+                        state_root,
+                        storage_proof,
+                    };
+                    witnesses.lock().unwrap().put((prev_block_hash, shard_id), Arc::new(witness));
+                }
+                Err(err) => {
+                    tracing::warn!(target: "client", shard_id, ?prev_block_hash, ?err, "Failed to record optimistic state witness");
+                    metrics::OPTIMISTIC_STATE_WITNESS_TOTAL
+                        .with_label_values(&[&shard_label, "failed"])
+                        .inc();
+                }
+            }
+        }))
+    }
+
+    /// Returns the recorded witness of the chunk produced on top of
+    /// `prev_block_hash`, if its recording has finished.
+    pub fn get(
+        &self,
+        prev_block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<Arc<OptimisticStateWitness>> {
+        self.witnesses.lock().unwrap().get(&(*prev_block_hash, shard_id)).cloned()
+    }
+}
+
+/// Returns the trie keys a chunk with the given transactions is expected to
+/// read: the accounts and access keys of the signers, the receiving accounts
+/// and the delayed receipts queue.
+fn state_witness_keys(transactions: &[SignedTransaction]) -> Vec<Vec<u8>> {
+    let mut keys = vec![TrieKey::DelayedReceiptIndices.to_vec()];
+    for tx in transactions {
+        let tx = &tx.transaction;
+        keys.push(TrieKey::Account { account_id: tx.signer_id.clone() }.to_vec());
+        keys.push(
+            TrieKey::AccessKey {
+                account_id: tx.signer_id.clone(),
+                public_key: tx.public_key.clone(),
+            }
+            .to_vec(),
+        );
+        keys.push(TrieKey::Account { account_id: tx.receiver_id.clone() }.to_vec());
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OptimisticWitnessRecorder;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::transaction::SignedTransaction;
+    use near_primitives::trie_key::TrieKey;
+    use near_store::test_utils::create_tries;
+    use near_store::Trie;
+
+    #[test]
+    fn test_record_optimistic_state_witness() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let recorder = OptimisticWitnessRecorder::new();
+        let signer =
+            InMemorySigner::from_seed("alice.near".parse().unwrap(), KeyType::ED25519, "alice");
+        let tx = SignedTransaction::send_money(
+            1,
+            "alice.near".parse().unwrap(),
+            "bob.near".parse().unwrap(),
+            &signer,
+            1,
+            CryptoHash::default(),
+        );
+        let prev_block_hash = CryptoHash::hash_bytes(b"prev");
+        let alice = TrieKey::Account { account_id: "alice.near".parse().unwrap() }.to_vec();
+        let bob = TrieKey::Account { account_id: "bob.near".parse().unwrap() }.to_vec();
+        let carol = TrieKey::Account { account_id: "carol.near".parse().unwrap() }.to_vec();
+
+        // Nothing is recorded for shards without a mem-trie.
+        let txs = [tx];
+        assert!(recorder
+            .start(&tries, shard_uid, prev_block_hash, Trie::EMPTY_ROOT, &txs)
+            .is_none());
+
+        // Apply a chunk, as the chain does, so that the mem-trie holds its
+        // post-state root, which is the pre-state root of the next chunk.
+        tries.set_mem_tries(tries.new_mem_tries(shard_uid));
+        let changes = vec![(alice.clone(), Some(vec![1; 100])), (carol, Some(vec![2; 10]))];
+        let trie_changes =
+            tries.get_trie_for_shard(shard_uid, Trie::EMPTY_ROOT).update(changes).unwrap();
+        tries.apply_mem_trie_changes(shard_uid, &trie_changes, 1).unwrap();
+        let mut store_update = tries.store_update();
+        let state_root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+        store_update.commit().unwrap();
+
+        recorder
+            .start(&tries, shard_uid, prev_block_hash, state_root, &txs)
+            .unwrap()
+            .join()
+            .unwrap();
+        let witness = recorder.get(&prev_block_hash, 0).unwrap();
+        assert_eq!(witness.state_root, state_root);
+        let trie = Trie::from_recorded_storage(witness.storage_proof.clone(), state_root, false);
+        assert_eq!(trie.get(&alice).unwrap(), Some(vec![1; 100]));
+        assert_eq!(trie.get(&bob).unwrap(), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! Diagnostics of missed block and chunk production.
+//!
+//! When a block on the canonical chain shows that this node missed producing
+//! a block at a skipped height or a chunk missing in the block, the miss is
+//! counted by its reason.  If configured, a diagnostic bundle is also written
+//! to a directory which keeps only the most recent bundles.  A bundle holds
+//! the state of the client around the miss and a dump of all the metrics of
+//! the node, which include the timings of the client, storage latencies and
+//! peer statistics.
+
+use crate::metrics;
+use near_chain_configs::ProductionDiagnosticsConfig;
+use near_o11y::metrics::{prometheus, Encoder, TextEncoder};
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::{BlockHeight, BlockHeightDelta, ShardId};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Number of recent production errors remembered to explain later misses.
+const PRODUCTION_ERRORS_CACHE_SIZE: usize = 100;
+
+/// Maximum number of heights skipped before a block which are checked for
+/// missed block production, so that a long gap doesn't take long to check.
+pub(crate) const MAX_SKIPPED_HEIGHTS_TO_CHECK: BlockHeightDelta = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, strum::IntoStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum MissedProductionKind {
+    Block,
+    Chunk,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, strum::IntoStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum MissReason {
+    /// It was produced, but didn't make it into the canonical chain.
+    NotIncluded,
+    /// Producing it failed with an error.
+    ProductionError,
+    /// It wasn't produced, e.g. because the previous block or the approvals
+    /// didn't arrive in time.
+    NotProduced,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct MissedProduction {
+    pub kind: MissedProductionKind,
+    pub height: BlockHeight,
+    pub shard_id: Option<ShardId>,
+    pub reason: MissReason,
+    pub error: Option<String>,
+}
+
+pub(crate) struct ProductionDiagnostics {
+    config: Option<ProductionDiagnosticsConfig>,
+    /// Errors of recent failed production attempts, by height and shard.  The
+    /// shard is `None` for blocks.
+    errors: lru::LruCache<(BlockHeight, Option<ShardId>), String>,
+    /// Height of the last block checked for misses.
+    last_checked_height: BlockHeight,
+    last_bundle_time: Option<Instant>,
+}
+
+impl ProductionDiagnostics {
+    pub(crate) fn new(config: Option<ProductionDiagnosticsConfig>) -> Self {
+        Self {
+            config,
+            errors: lru::LruCache::new(PRODUCTION_ERRORS_CACHE_SIZE),
+            last_checked_height: 0,
+            last_bundle_time: None,
+        }
+    }
+
+    /// Called when producing a block (if `shard_id` is `None`) or a chunk at
+    /// `height` fails.
+    pub(crate) fn record_error(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        err: &dyn std::fmt::Display,
+    ) {
+        self.errors.put((height, shard_id), err.to_string());
+    }
+
+    /// Returns whether the block at `height` should be checked for misses,
+    /// i.e. it's higher than all the blocks checked so far.
+    pub(crate) fn start_check(&mut self, height: BlockHeight) -> bool {
+        if height <= self.last_checked_height {
+            return false;
+        }
+        self.last_checked_height = height;
+        true
+    }
+
+    /// Records a miss of production assigned to this node.  `produced` tells
+    /// whether the node produced the block or chunk nevertheless.
+    pub(crate) fn on_missed(
+        &mut self,
+        kind: MissedProductionKind,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        produced: bool,
+    ) -> MissedProduction {
+        let error = self.errors.pop(&(height, shard_id));
+        let reason = if produced {
+            MissReason::NotIncluded
+        } else if error.is_some() {
+            MissReason::ProductionError
+        } else {
+            MissReason::NotProduced
+        };
+        metrics::MISSED_PRODUCTION_TOTAL.with_label_values(&[kind.into(), reason.into()]).inc();
+        warn!(target: "client", ?kind, height, ?shard_id, ?reason, ?error, "Missed production");
+        MissedProduction { kind, height, shard_id, reason, error }
+    }
+
+    /// Writes a bundle describing the misses on a background thread, unless
+    /// bundles are disabled or the previous one was written too recently.
+    pub(crate) fn save_bundle(
+        &mut self,
+        missed: Vec<MissedProduction>,
+        client_state: serde_json::Value,
+    ) {
+        let Some(config) = &self.config else { return };
+        let now = StaticClock::instant();
+        if self.last_bundle_time.map_or(false, |last| now < last + config.min_interval) {
+            return;
+        }
+        self.last_bundle_time = Some(now);
+
+        let Some(first) = missed.first() else { return };
+        let name = format!(
+            "{}_{}_{}",
+            StaticClock::utc().format("%Y%m%dT%H%M%S%.3f"),
+            <&str>::from(first.kind),
+            first.height
+        );
+        let dir = config.dir.clone();
+        let max_bundles = config.max_bundles;
+        let contents = serde_json::json!({ "missed": missed, "client": client_state });
+        std::thread::spawn(move || match write_bundle(&dir, max_bundles, &name, &contents) {
+            Ok(path) => {
+                info!(target: "client", path = %path.display(), "Saved production diagnostics")
+            }
+            Err(err) => {
+                warn!(target: "client", ?err, "Failed to save production diagnostics")
+            }
+        });
+    }
+}
+
+/// Writes a bundle into a new subdirectory of `dir` and deletes the oldest
+/// bundles so that at most `max_bundles` remain.
+fn write_bundle(
+    dir: &Path,
+    max_bundles: usize,
+    name: &str,
+    contents: &serde_json::Value,
+) -> std::io::Result<PathBuf> {
+    let path = dir.join(name);
+    std::fs::create_dir_all(&path)?;
+    std::fs::write(path.join("client.json"), serde_json::to_vec_pretty(contents)?)?;
+    let mut metrics = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut metrics)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    std::fs::write(path.join("metrics.txt"), metrics)?;
+
+    // The names of the bundles start with their creation time.
+    let mut bundles = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map_or(false, |file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    bundles.sort();
+    let num_to_remove = bundles.len().saturating_sub(max_bundles);
+    for bundle in &bundles[..num_to_remove] {
+        std::fs::remove_dir_all(bundle)?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_bundle, MissReason, MissedProductionKind, ProductionDiagnostics};
+
+    #[test]
+    fn test_miss_reasons() {
+        let mut diagnostics = ProductionDiagnostics::new(None);
+        assert!(diagnostics.start_check(10));
+        assert!(!diagnostics.start_check(10));
+        diagnostics.record_error(8, None, &"no chunks");
+        diagnostics.record_error(10, Some(1), &"storage error");
+
+        let missed = diagnostics.on_missed(MissedProductionKind::Block, 8, None, false);
+        assert_eq!(missed.reason, MissReason::ProductionError);
+        assert_eq!(missed.error.as_deref(), Some("no chunks"));
+        let missed = diagnostics.on_missed(MissedProductionKind::Block, 9, None, false);
+        assert_eq!(missed.reason, MissReason::NotProduced);
+        let missed = diagnostics.on_missed(MissedProductionKind::Chunk, 10, Some(0), true);
+        assert_eq!(missed.reason, MissReason::NotIncluded);
+        let missed = diagnostics.on_missed(MissedProductionKind::Chunk, 10, Some(1), false);
+        assert_eq!(missed.reason, MissReason::ProductionError);
+    }
+
+    #[test]
+    fn test_write_bundle_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let contents = serde_json::json!({ "missed": [] });
+        for name in ["a", "b", "c"] {
+            let path = write_bundle(dir.path(), 2, name, &contents).unwrap();
+            assert!(path.join("client.json").exists());
+            assert!(path.join("metrics.txt").exists());
+        }
+        let mut bundles: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        bundles.sort();
+        assert_eq!(bundles, vec!["b", "c"]);
+    }
+}
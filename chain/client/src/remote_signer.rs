@@ -0,0 +1,445 @@
+//! Validator signer which gets the signatures from an external signing
+//! service, so that the validator key doesn't have to be kept on the node.
+//!
+//! The data is hashed on the node like for a local key, and the service only
+//! signs the bytes it gets.  The service is called over HTTP, with mutual TLS
+//! if configured:
+//!
+//! * `POST <url>/sign` with `{"account_id", "public_key", "kind", "data"}`,
+//!   where `kind` tells what is signed (`block_header`, `chunk`, `approval`,
+//!   ...) and `data` is base64, responds with `{"signature"}` in the usual
+//!   `ed25519:...` format.
+//! * `POST <url>/vrf` with `{"account_id", "public_key", "data"}` responds with
+//!   the base64 `{"value", "proof"}` of the VRF of the data.
+//!
+//! Signing blocks the caller until the service responds or the timeout
+//! passes.  Signatures and VRF outputs are checked against the configured
+//! public key, and if the service fails to produce a valid one, the configured
+//! fallback is used.  Without a fallback key the output is left invalid, and
+//! the client skips producing the block, chunk or approval.
+//!
+//! The service has to be reached over TLS unless it runs on the same host.
+
+use crate::metrics;
+use anyhow::Context;
+use near_chain_configs::{RemoteSignerConfig, RemoteSignerFallback};
+use near_crypto::key_conversion::convert_public_key;
+use near_crypto::vrf::{Proof, Value};
+use near_crypto::{InMemorySigner, PublicKey, Signature, Signer};
+use near_primitives::block::{Approval, ApprovalInner, BlockHeader};
+use near_primitives::challenge::ChallengeBody;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::serialize::{from_base64, to_base64};
+use near_primitives::sharding::ChunkHash;
+use near_primitives::telemetry::TelemetryInfo;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_primitives::validator_signer::ValidatorSigner;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(serde::Deserialize)]
+struct SignResponse {
+    signature: Signature,
+}
+
+#[derive(serde::Deserialize)]
+struct VrfResponse {
+    value: String,
+    proof: String,
+}
+
+pub struct RemoteValidatorSigner {
+    account_id: AccountId,
+    public_key: PublicKey,
+    url: String,
+    timeout: Duration,
+    client: reqwest::Client,
+    /// Runtime of the thread sending the requests, so that signing works the
+    /// same from within and outside of async code.
+    runtime: tokio::runtime::Handle,
+    /// Local key used when the service fails, if configured.
+    fallback_signer: Option<InMemorySigner>,
+}
+
+/// Whether the VRF output of the data was computed with the given validator
+/// key.
+pub(crate) fn is_vrf_valid(
+    public_key: &PublicKey,
+    data: &[u8],
+    value: &Value,
+    proof: &Proof,
+) -> bool {
+    let PublicKey::ED25519(public_key) = public_key else {
+        return false;
+    };
+    convert_public_key(public_key)
+        .map_or(false, |public_key| public_key.is_vrf_valid(&data, value, proof))
+}
+
+/// Whether the URL points to the local host, so that the requests don't
+/// leave it.
+fn is_loopback_url(url: &str) -> anyhow::Result<bool> {
+    let url = reqwest::Url::parse(url).with_context(|| format!("parsing URL {url}"))?;
+    let Some(host) = url.host_str() else {
+        return Ok(false);
+    };
+    Ok(host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_or(false, |ip| ip.is_loopback()))
+}
+
+impl RemoteValidatorSigner {
+    /// Creates the signer from a config with absolute paths.
+    pub fn new(config: &RemoteSignerConfig) -> anyhow::Result<Self> {
+        // Anyone on the path to the service could otherwise read and forge
+        // its responses, e.g. to make the node produce nothing.
+        anyhow::ensure!(
+            config.tls.is_some() || is_loopback_url(&config.url)?,
+            "TLS must be configured for remote signer at {}, which isn't on the local host",
+            config.url
+        );
+        let mut builder = reqwest::Client::builder();
+        if let Some(tls) = &config.tls {
+            let ca_cert = std::fs::read(&tls.ca_cert_file)
+                .with_context(|| format!("reading {}", tls.ca_cert_file.display()))?;
+            let client_cert = std::fs::read(&tls.client_cert_file)
+                .with_context(|| format!("reading {}", tls.client_cert_file.display()))?;
+            let client_key = std::fs::read(&tls.client_key_file)
+                .with_context(|| format!("reading {}", tls.client_key_file.display()))?;
+            builder = builder
+                .tls_built_in_root_certs(false)
+                .add_root_certificate(reqwest::Certificate::from_pem(&ca_cert)?)
+                .identity(reqwest::Identity::from_pkcs8_pem(&client_cert, &client_key)?);
+        }
+        let client = builder.build()?;
+
+        let fallback_signer = match &config.fallback {
+            RemoteSignerFallback::Skip => None,
+            RemoteSignerFallback::LocalKey { key_file } => {
+                let signer = InMemorySigner::from_file(key_file)
+                    .with_context(|| format!("reading {}", key_file.display()))?;
+                anyhow::ensure!(
+                    signer.account_id == config.account_id
+                        && signer.public_key == config.public_key,
+                    "fallback key {} isn't the key of the remote signer",
+                    key_file.display()
+                );
+                Some(signer)
+            }
+        };
+
+        let (handle_sender, handle_receiver) = std::sync::mpsc::channel();
+        std::thread::Builder::new().name("remote_signer".to_string()).spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to create the runtime of the remote signer");
+            handle_sender.send(runtime.handle().clone()).unwrap();
+            runtime.block_on(std::future::pending::<()>());
+        })?;
+        let runtime = handle_receiver.recv()?;
+
+        Ok(Self {
+            account_id: config.account_id.clone(),
+            public_key: config.public_key.clone(),
+            url: config.url.trim_end_matches('/').to_string(),
+            timeout: config.timeout,
+            client,
+            runtime,
+            fallback_signer,
+        })
+    }
+
+    /// Sends a request to the service and waits for the response.
+    fn call<T: serde::de::DeserializeOwned + Send + 'static>(
+        &self,
+        method: &str,
+        body: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let request = self
+            .client
+            .post(format!("{}/{method}", self.url))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .timeout(self.timeout);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.runtime.spawn(async move {
+            let response: anyhow::Result<T> = async move {
+                let response = request.send().await?.error_for_status()?;
+                Ok(serde_json::from_slice(&response.bytes().await?)?)
+            }
+            .await;
+            let _ = sender.send(response);
+        });
+        // The request times out on its own, the margin only covers the time
+        // taken to schedule it.
+        receiver.recv_timeout(self.timeout + Duration::from_millis(100))?
+    }
+
+    fn remote_sign(&self, kind: &str, data: &[u8]) -> anyhow::Result<Signature> {
+        let response: SignResponse = self.call(
+            "sign",
+            serde_json::json!({
+                "account_id": self.account_id,
+                "public_key": self.public_key,
+                "kind": kind,
+                "data": to_base64(data),
+            }),
+        )?;
+        anyhow::ensure!(
+            response.signature.verify(data, &self.public_key),
+            "invalid signature {}",
+            response.signature
+        );
+        Ok(response.signature)
+    }
+
+    fn remote_vrf(&self, data: &[u8]) -> anyhow::Result<(Value, Proof)> {
+        let response: VrfResponse = self.call(
+            "vrf",
+            serde_json::json!({
+                "account_id": self.account_id,
+                "public_key": self.public_key,
+                "data": to_base64(data),
+            }),
+        )?;
+        let value = from_base64(&response.value)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid VRF value length"))?;
+        let proof = from_base64(&response.proof)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid VRF proof length"))?;
+        let (value, proof) = (Value(value), Proof(proof));
+        anyhow::ensure!(is_vrf_valid(&self.public_key, data, &value, &proof), "invalid VRF proof");
+        Ok((value, proof))
+    }
+
+    /// Runs `remote` with the metrics recorded, and uses `fallback` with the
+    /// local key, or the invalid `empty` output without it, if it fails.
+    fn with_fallback<T>(
+        &self,
+        kind: &str,
+        remote: impl FnOnce() -> anyhow::Result<T>,
+        fallback: impl FnOnce(&InMemorySigner) -> T,
+        empty: impl FnOnce() -> T,
+    ) -> T {
+        let timer = metrics::REMOTE_SIGNER_LATENCY.with_label_values(&[kind]).start_timer();
+        let result = remote();
+        timer.observe_duration();
+        let err = match result {
+            Ok(result) => {
+                metrics::REMOTE_SIGNER_REQUESTS_TOTAL.with_label_values(&[kind, "ok"]).inc();
+                return result;
+            }
+            Err(err) => err,
+        };
+        metrics::REMOTE_SIGNER_REQUESTS_TOTAL.with_label_values(&[kind, "error"]).inc();
+        let fallback_label = if self.fallback_signer.is_some() { "local_key" } else { "skip" };
+        metrics::REMOTE_SIGNER_FALLBACK_TOTAL.with_label_values(&[kind, fallback_label]).inc();
+        warn!(target: "client", kind, fallback = fallback_label, ?err, "Remote signer failed");
+        match &self.fallback_signer {
+            Some(signer) => fallback(signer),
+            None => empty(),
+        }
+    }
+
+    fn sign(&self, kind: &str, data: &[u8]) -> Signature {
+        self.with_fallback(
+            kind,
+            || self.remote_sign(kind, data),
+            |signer| signer.sign(data),
+            Signature::default,
+        )
+    }
+}
+
+impl ValidatorSigner for RemoteValidatorSigner {
+    fn validator_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value {
+        let mut value = serde_json::to_value(info).expect("Telemetry must serialize to JSON");
+        let content = serde_json::to_string(&value).expect("Telemetry must serialize to JSON");
+        value["signature"] = self.sign("telemetry", content.as_bytes()).to_string().into();
+        value
+    }
+
+    fn sign_block_header_parts(
+        &self,
+        prev_hash: CryptoHash,
+        inner_lite: &[u8],
+        inner_rest: &[u8],
+    ) -> (CryptoHash, Signature) {
+        let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
+        (hash, self.sign("block_header", hash.as_ref()))
+    }
+
+    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature {
+        self.sign("chunk", chunk_hash.as_ref())
+    }
+
+    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature {
+        self.sign("approval", &Approval::get_data_for_sig(inner, target_height))
+    }
+
+    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+        let hash = CryptoHash::hash_borsh(challenge_body);
+        (hash, self.sign("challenge", hash.as_ref()))
+    }
+
+    fn sign_account_announce(
+        &self,
+        account_id: &AccountId,
+        peer_id: &PeerId,
+        epoch_id: &EpochId,
+    ) -> Signature {
+        let hash = AnnounceAccount::build_header_hash(account_id, peer_id, epoch_id);
+        self.sign("account_announce", hash.as_ref())
+    }
+
+    fn sign_account_key_payload(&self, proto_bytes: &[u8]) -> Signature {
+        self.sign("account_key_payload", proto_bytes)
+    }
+
+    fn compute_vrf_with_proof(&self, data: &[u8]) -> (Value, Proof) {
+        self.with_fallback(
+            "vrf",
+            || self.remote_vrf(data),
+            |signer| signer.compute_vrf_with_proof(data),
+            || (Value([0; 32]), Proof([0; 64])),
+        )
+    }
+
+    fn write_to_file(&self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the key of a remote signer can't be written to a file",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RemoteValidatorSigner;
+    use near_chain_configs::{RemoteSignerConfig, RemoteSignerFallback};
+    use near_crypto::{InMemorySigner, KeyType, Signature, Signer};
+    use near_primitives::block::{Approval, ApprovalInner};
+    use near_primitives::hash::hash;
+    use near_primitives::serialize::to_base64;
+    use near_primitives::validator_signer::ValidatorSigner;
+    use std::io::{BufRead, Read, Write};
+    use std::time::Duration;
+
+    /// Serves the given JSON as the response to all the requests, returns
+    /// the URL of the server.
+    fn serve_json(response: serde_json::Value) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let body = response.to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(len) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; content_length]).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_remote_signer_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = InMemorySigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+        let key_file = dir.path().join("key.json");
+        key.write_to_file(&key_file).unwrap();
+        // Nothing listens on the port, so all the requests fail.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut config = RemoteSignerConfig {
+            url: format!("http://127.0.0.1:{port}"),
+            account_id: key.account_id.clone(),
+            public_key: key.public_key.clone(),
+            timeout: Duration::from_millis(100),
+            tls: None,
+            fallback: RemoteSignerFallback::Skip,
+        };
+        let inner = ApprovalInner::Endorsement(hash(&[1]));
+        let data = Approval::get_data_for_sig(&inner, 2);
+
+        let signer = RemoteValidatorSigner::new(&config).unwrap();
+        assert_eq!(signer.sign_approval(&inner, 2), Signature::default());
+
+        config.fallback = RemoteSignerFallback::LocalKey { key_file: key_file.clone() };
+        let signer = RemoteValidatorSigner::new(&config).unwrap();
+        assert!(signer.sign_approval(&inner, 2).verify(&data, &key.public_key));
+        let (value, _) = signer.compute_vrf_with_proof(&data);
+        assert!(value == key.compute_vrf_with_proof(&data).0);
+
+        // TLS is required unless the service runs on the same host.
+        config.url = format!("http://10.0.0.1:{port}");
+        assert!(RemoteValidatorSigner::new(&config).is_err());
+        config.url = format!("http://localhost:{port}");
+        assert!(RemoteValidatorSigner::new(&config).is_ok());
+
+        // The fallback key must be the key of the remote signer.
+        config.public_key =
+            InMemorySigner::from_seed(key.account_id.clone(), KeyType::ED25519, "other").public_key;
+        assert!(RemoteValidatorSigner::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_remote_signer_invalid_vrf() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = InMemorySigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+        let key_file = dir.path().join("key.json");
+        key.write_to_file(&key_file).unwrap();
+        let data = b"data";
+        // The service responds with the VRF computed with another key.
+        let other = InMemorySigner::from_seed(key.account_id.clone(), KeyType::ED25519, "other");
+        let (value, proof) = other.compute_vrf_with_proof(data);
+        let url = serve_json(serde_json::json!({
+            "value": to_base64(&value.0),
+            "proof": to_base64(&proof.0),
+        }));
+        let config = RemoteSignerConfig {
+            url,
+            account_id: key.account_id.clone(),
+            public_key: key.public_key.clone(),
+            timeout: Duration::from_secs(10),
+            tls: None,
+            fallback: RemoteSignerFallback::LocalKey { key_file },
+        };
+        let signer = RemoteValidatorSigner::new(&config).unwrap();
+        let (value, _) = signer.compute_vrf_with_proof(data);
+        assert!(value == key.compute_vrf_with_proof(data).0);
+        assert!(value != other.compute_vrf_with_proof(data).0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Limiting of the bandwidth used by state sync.
+//!
+//! The size of a state part is only known once it's built or downloaded, so
+//! a part may be transferred as long as the limit wasn't exceeded yet, and its
+//! size, or its estimated size when downloading, is accounted for afterwards.
+//! The limit is thus kept on average, while the parts which are transferred at
+//! the same time may exceed it briefly.
+
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use near_chain_configs::{StateSyncBandwidthConfig, StateSyncWindow};
+use near_primitives::static_clock::StaticClock;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Direction of the state parts transferred by the limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum BandwidthDirection {
+    Download,
+    Upload,
+}
+
+/// Limits the rate at which state parts are transferred in one direction,
+/// and the times of the day at which they are transferred at all.
+pub struct BandwidthLimiter {
+    direction: BandwidthDirection,
+    max_bytes_per_second: Option<u64>,
+    windows: Vec<StateSyncWindow>,
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    /// Number of bytes which may be transferred without exceeding the limit.
+    /// Refilled at `max_bytes_per_second` rate, up to one second worth of
+    /// bytes, and negative while parts larger than that are paid off.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl BandwidthLimiter {
+    pub fn new(
+        direction: BandwidthDirection,
+        max_bytes_per_second: Option<u64>,
+        windows: Vec<StateSyncWindow>,
+    ) -> Self {
+        let state = LimiterState {
+            tokens: max_bytes_per_second.unwrap_or(0) as f64,
+            last_refill: StaticClock::instant(),
+        };
+        Self { direction, max_bytes_per_second, windows, state: Mutex::new(state) }
+    }
+
+    /// Returns limiter which doesn't limit anything.
+    pub fn unlimited(direction: BandwidthDirection) -> Self {
+        Self::new(direction, None, vec![])
+    }
+
+    /// Returns limiter of the given direction configured by `config`.
+    pub fn from_config(direction: BandwidthDirection, config: &StateSyncBandwidthConfig) -> Self {
+        let max_bytes_per_second = match direction {
+            BandwidthDirection::Download => config.max_download_bytes_per_second,
+            BandwidthDirection::Upload => config.max_upload_bytes_per_second,
+        };
+        Self::new(
+            direction,
+            max_bytes_per_second.map(|bytes| bytes.as_u64()),
+            config.windows.clone(),
+        )
+    }
+
+    /// Returns whether a state part may be transferred now.  If it is, its
+    /// size must be passed to `record` once it's known.
+    pub fn try_start(&self) -> bool {
+        if self.max_bytes_per_second.is_none() && self.windows.is_empty() {
+            return true;
+        }
+        self.try_start_at(StaticClock::instant(), StaticClock::utc())
+    }
+
+    fn try_start_at(&self, now: Instant, utc: DateTime<Utc>) -> bool {
+        let time = utc.time();
+        if !self.windows.is_empty() && !self.windows.iter().any(|window| window.contains(time)) {
+            metrics::STATE_SYNC_BANDWIDTH_THROTTLED
+                .with_label_values(&[self.direction.into(), "window"])
+                .inc();
+            return false;
+        }
+        let Some(max_bytes_per_second) = self.max_bytes_per_second else { return true };
+        let rate = max_bytes_per_second.max(1) as f64;
+        let mut state = self.state.lock().unwrap();
+        let refill = now.saturating_duration_since(state.last_refill).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refill).min(rate);
+        state.last_refill = now;
+        if state.tokens <= 0.0 {
+            metrics::STATE_SYNC_BANDWIDTH_THROTTLED
+                .with_label_values(&[self.direction.into(), "rate"])
+                .inc();
+            return false;
+        }
+        true
+    }
+
+    /// Accounts for a transferred state part of the given size.
+    pub fn record(&self, bytes: u64) {
+        metrics::STATE_SYNC_BANDWIDTH_BYTES
+            .with_label_values(&[self.direction.into()])
+            .inc_by(bytes);
+        if self.max_bytes_per_second.is_some() {
+            self.state.lock().unwrap().tokens -= bytes as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, TimeZone};
+    use std::time::Duration;
+
+    fn at(hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, 1, hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = BandwidthLimiter::new(BandwidthDirection::Download, Some(1000), vec![]);
+        let start = limiter.state.lock().unwrap().last_refill;
+        assert!(limiter.try_start_at(start, at(0, 0)));
+        limiter.record(2500);
+        // The part which exceeded the limit is paid off in 1.5 seconds.
+        assert!(!limiter.try_start_at(start + Duration::from_secs(1), at(0, 0)));
+        assert!(limiter.try_start_at(start + Duration::from_secs(2), at(0, 0)));
+        // Unused bandwidth doesn't accumulate for more than a second.
+        assert!(limiter.try_start_at(start + Duration::from_secs(100), at(0, 0)));
+        limiter.record(1000);
+        assert!(!limiter.try_start_at(start + Duration::from_secs(100), at(0, 0)));
+    }
+
+    #[test]
+    fn test_windows() {
+        let window = |start, end| StateSyncWindow {
+            start: NaiveTime::from_hms_opt(start, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end, 0, 0).unwrap(),
+        };
+        let limiter = BandwidthLimiter::new(
+            BandwidthDirection::Upload,
+            None,
+            vec![window(2, 4), window(22, 1)],
+        );
+        let now = Instant::now();
+        assert!(!limiter.try_start_at(now, at(1, 30)));
+        assert!(limiter.try_start_at(now, at(2, 0)));
+        assert!(limiter.try_start_at(now, at(3, 59)));
+        assert!(!limiter.try_start_at(now, at(4, 0)));
+        assert!(limiter.try_start_at(now, at(23, 0)));
+        assert!(limiter.try_start_at(now, at(0, 30)));
+        assert!(!limiter.try_start_at(now, at(12, 0)));
+    }
+}
@@ -293,6 +293,7 @@ mod test {
                 highest_block_hash: Default::default(),
                 tracked_shards: vec![],
                 archival: false,
+                snapshot_host_info: None,
             })
             .collect()
     }
@@ -1,81 +1,153 @@
 use chrono::{DateTime, Duration, Utc};
-use near_network::types::PeerManagerAdapter;
-use near_primitives::hash::CryptoHash;
+use near_async::messaging::CanSend;
+use near_chain::epoch_sync::validate_epoch_sync_proof;
+use near_chain::Chain;
+use near_client_primitives::types::SyncStatus;
+use near_network::types::{
+    HighestHeightPeerInfo, NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest,
+};
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::network::PeerId;
 use near_primitives::static_clock::StaticClock;
-use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::EpochId;
-use std::collections::{HashMap, HashSet};
+use near_primitives::types::{BlockHeightDelta, EpochId};
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::sync::Arc;
 use std::time::Duration as TimeDuration;
+use tracing::{debug, info, warn};
 
-/// Helper to keep track of the Epoch Sync
-// TODO #3488
-#[allow(dead_code)]
+/// Helper to keep track of the Epoch Sync.
+///
+/// Starting from genesis, requests the proof of the start of every epoch from
+/// peers, validating each one with the block producers proven by the previous
+/// one. Once the proven epochs are close to the highest height, the chain is
+/// initialized from the last proof and header sync continues from there, so
+/// only the headers of the last few epochs are downloaded.
 pub struct EpochSync {
     network_adapter: PeerManagerAdapter,
-    /// Datastructure to keep track of when the last request to each peer was made.
-    /// Peers do not respond to Epoch Sync requests more frequently than once per a certain time
-    /// interval, thus there's no point in requesting more frequently.
-    peer_to_last_request_time: HashMap<PeerId, DateTime<Utc>>,
-    /// Tracks all the peers who have reported that we are already up to date
-    peers_reporting_up_to_date: HashSet<PeerId>,
-    /// The last epoch we are synced to
-    current_epoch_id: EpochId,
-    /// The next epoch id we need to sync
-    next_epoch_id: EpochId,
-    /// The block producers set to validate the light client block view for the next epoch
-    next_block_producers: Vec<ValidatorStake>,
-    /// The last epoch id that we have requested
-    requested_epoch_id: EpochId,
-    /// When and to whom was the last request made
-    last_request_time: DateTime<Utc>,
-    last_request_peer_id: Option<PeerId>,
-
-    /// How long to wait for a response before re-requesting the same light client block view
+    /// The epoch whose successor's start is requested next.
+    prev_epoch_id: EpochId,
+    /// The epoch whose start is requested next.
+    epoch_id: EpochId,
+    /// Info of `epoch_id`, which its proof is validated against.
+    epoch_info: Arc<EpochInfo>,
+    /// Number of epochs proven so far.
+    epoch_ord: u64,
+    /// The last valid proof, which the chain is initialized from at the end.
+    last_proof: Option<EpochSyncProof>,
+    /// When and to whom was the last request made.
+    last_request: Option<(DateTime<Utc>, PeerId)>,
+    /// How long to wait for a response before requesting the same proof again.
     request_timeout: Duration,
-    /// How frequently to send request to the same peer
-    peer_timeout: Duration,
-
-    /// True, if all peers agreed that we're at the last Epoch.
-    /// Only finalization is needed.
-    have_all_epochs: bool,
+    epoch_length: BlockHeightDelta,
     /// Whether the Epoch Sync was performed to completion previously.
     /// Current state machine allows for only one Epoch Sync.
     pub done: bool,
-
-    pub sync_hash: CryptoHash,
-
-    received_epoch: bool,
-
-    is_just_started: bool,
 }
 
 impl EpochSync {
+    /// `first_epoch_id` is the id of the epoch after the genesis one, which is
+    /// the first epoch whose start is proven.
     pub fn new(
         network_adapter: PeerManagerAdapter,
         genesis_epoch_id: EpochId,
-        genesis_next_epoch_id: EpochId,
-        first_epoch_block_producers: Vec<ValidatorStake>,
+        first_epoch_id: EpochId,
+        first_epoch_info: Arc<EpochInfo>,
+        epoch_length: BlockHeightDelta,
         request_timeout: TimeDuration,
-        peer_timeout: TimeDuration,
     ) -> Self {
         Self {
             network_adapter,
-            peer_to_last_request_time: HashMap::new(),
-            peers_reporting_up_to_date: HashSet::new(),
-            current_epoch_id: genesis_epoch_id.clone(),
-            next_epoch_id: genesis_next_epoch_id,
-            next_block_producers: first_epoch_block_producers,
-            requested_epoch_id: genesis_epoch_id,
-            last_request_time: StaticClock::utc(),
-            last_request_peer_id: None,
+            prev_epoch_id: genesis_epoch_id,
+            epoch_id: first_epoch_id,
+            epoch_info: first_epoch_info,
+            epoch_ord: 0,
+            last_proof: None,
+            last_request: None,
             request_timeout: Duration::from_std(request_timeout).unwrap(),
-            peer_timeout: Duration::from_std(peer_timeout).unwrap(),
-            received_epoch: false,
-            have_all_epochs: false,
+            epoch_length,
             done: false,
-            sync_hash: CryptoHash::default(),
-            is_just_started: true,
         }
     }
+
+    /// Height up to which the chain is proven by the received proofs.
+    fn synced_height(&self, chain: &Chain) -> u64 {
+        match &self.last_proof {
+            Some(proof) => proof.next_header.height(),
+            None => chain.genesis().height(),
+        }
+    }
+
+    pub fn run(
+        &mut self,
+        sync_status: &mut SyncStatus,
+        chain: &mut Chain,
+        highest_height: u64,
+        highest_height_peers: &[HighestHeightPeerInfo],
+    ) -> Result<(), near_chain::Error> {
+        let _span = tracing::debug_span!(target: "sync", "run", sync = "EpochSync").entered();
+        if self.done {
+            return Ok(());
+        }
+        // Headers of the last epochs are downloaded by header sync anyway, and
+        // a node which already has some headers continues from them.
+        if self.last_proof.is_none() && chain.header_head()?.height > chain.genesis().height() {
+            self.done = true;
+            return Ok(());
+        }
+        if self.synced_height(chain) + 2 * self.epoch_length >= highest_height {
+            if let Some(proof) = self.last_proof.take() {
+                info!(target: "sync", epoch_ord = self.epoch_ord, height = proof.first_header.height(), "Epoch sync done");
+                chain.apply_epoch_sync_proof(proof)?;
+            }
+            self.done = true;
+            return Ok(());
+        }
+
+        *sync_status = SyncStatus::EpochSync { epoch_ord: self.epoch_ord };
+        let now = StaticClock::utc();
+        if let Some((request_time, _)) = &self.last_request {
+            if now < *request_time + self.request_timeout {
+                return Ok(());
+            }
+        }
+        if let Some(peer) = highest_height_peers.choose(&mut thread_rng()) {
+            let peer_id = peer.peer_info.id.clone();
+            debug!(target: "sync", epoch_id = ?self.epoch_id, %peer_id, "Requesting epoch sync proof");
+            self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                NetworkRequests::EpochSyncRequest {
+                    epoch_id: self.prev_epoch_id.clone(),
+                    peer_id: peer_id.clone(),
+                },
+            ));
+            self.last_request = Some((now, peer_id));
+        }
+        Ok(())
+    }
+
+    /// Processes a proof received from a peer. Returns an error if the proof
+    /// is invalid, in which case the peer should be banned.
+    pub fn on_response(
+        &mut self,
+        proof: EpochSyncProof,
+        peer_id: PeerId,
+    ) -> Result<(), near_chain::Error> {
+        if self.done || proof.first_header.epoch_id() != &self.epoch_id {
+            debug!(target: "sync", %peer_id, "Ignoring unexpected epoch sync proof");
+            return Ok(());
+        }
+        if let Err(err) = validate_epoch_sync_proof(&proof, &self.epoch_id, &self.epoch_info) {
+            warn!(target: "sync", %peer_id, ?err, "Received invalid epoch sync proof");
+            return Err(err);
+        }
+        debug!(target: "sync", epoch_id = ?self.epoch_id, height = proof.first_header.height(), "Received epoch sync proof");
+        self.prev_epoch_id = self.epoch_id.clone();
+        self.epoch_id = proof.first_header.next_epoch_id().clone();
+        self.epoch_info = proof.data.5.clone();
+        self.epoch_ord += 1;
+        self.last_proof = Some(proof);
+        self.last_request = None;
+        Ok(())
+    }
 }
@@ -1,6 +1,8 @@
 use std::cmp::min;
+use std::collections::HashMap;
 use std::time::Duration as TimeDuration;
 
+use crate::metrics;
 use chrono::{DateTime, Duration, Utc};
 use near_async::messaging::CanSend;
 use near_chain::{Chain, ChainStoreAccess};
@@ -9,6 +11,7 @@ use near_network::types::PeerManagerMessageRequest;
 use near_network::types::{HighestHeightPeerInfo, NetworkRequests, PeerManagerAdapter};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::static_clock::StaticClock;
 use near_primitives::types::BlockHeight;
 use near_primitives::utils::to_timestamp;
@@ -19,6 +22,36 @@ use tracing::{debug, warn};
 /// Maximum number of block headers send over the network.
 pub const MAX_BLOCK_HEADERS: u64 = 512;
 
+/// Minimum number of block headers requested from a slow peer.
+pub const MIN_BLOCK_HEADERS_BATCH: u64 = 32;
+
+/// Maximum number of peers headers are requested from at the same time.
+pub const MAX_PARALLEL_HEADER_REQUESTS: usize = 3;
+
+/// Time in which a peer is expected to answer a header request. Batches are
+/// sized so that a peer answers in about this time at its measured throughput.
+const TARGET_RESPONSE_TIME_SECS: f64 = 2.0;
+
+/// Weight of the latest response in the moving average of peer throughput.
+const THROUGHPUT_SMOOTHING: f64 = 0.5;
+
+/// A peer which didn't send headers in time isn't asked for headers for the
+/// initial timeout times 2^(stalls - 1), up to 2^`MAX_DEMOTION_SHIFT`.
+const MAX_DEMOTION_SHIFT: u32 = 5;
+
+/// What is known about how well a peer sends headers.
+#[derive(Default)]
+struct PeerHeaderStats {
+    /// Moving average of the rate at which the peer sent headers.
+    headers_per_second: Option<f64>,
+    /// When the last request to the peer was sent, if it wasn't answered yet.
+    pending_request: Option<DateTime<Utc>>,
+    /// Number of requests in a row the peer didn't answer in time.
+    num_stalls: u32,
+    /// The peer isn't asked for headers until this time.
+    demoted_until: Option<DateTime<Utc>>,
+}
+
 /// Maximum number of block header hashes to send as part of a locator.
 pub const MAX_BLOCK_HEADER_HASHES: usize = 20;
 
@@ -26,11 +59,23 @@ pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
 /// Helper to keep track of sync headers.
 /// Handles major re-orgs by finding closest header that matches and re-downloading headers from that point.
+///
+/// Every time more headers are needed, the same headers are requested from a
+/// few peers at once, preferring the peers which sent headers the fastest
+/// before. A peer is asked for a batch of headers it is expected to send in
+/// about `TARGET_RESPONSE_TIME_SECS`, and a peer which doesn't answer in time
+/// isn't asked again for a while.
 pub struct HeaderSync {
     network_adapter: PeerManagerAdapter,
     prev_header_sync: (DateTime<Utc>, BlockHeight, BlockHeight, BlockHeight),
     syncing_peer: Option<HighestHeightPeerInfo>,
     stalling_ts: Option<DateTime<Utc>>,
+    /// Smallest batch of headers requested in the last round of requests.
+    batch_size: u64,
+    peer_stats: HashMap<PeerId, PeerHeaderStats>,
+    /// Time and header head height at which the headers per second metric was
+    /// last updated.
+    throughput_window: Option<(DateTime<Utc>, BlockHeight)>,
 
     initial_timeout: Duration,
     progress_timeout: Duration,
@@ -51,6 +96,9 @@ impl HeaderSync {
             prev_header_sync: (StaticClock::utc(), 0, 0, 0),
             syncing_peer: None,
             stalling_ts: None,
+            batch_size: MAX_BLOCK_HEADERS,
+            peer_stats: HashMap::new(),
+            throughput_window: None,
             initial_timeout: Duration::from_std(initial_timeout).unwrap(),
             progress_timeout: Duration::from_std(progress_timeout).unwrap(),
             stall_ban_timeout: Duration::from_std(stall_ban_timeout).unwrap(),
@@ -67,6 +115,7 @@ impl HeaderSync {
     ) -> Result<(), near_chain::Error> {
         let _span = tracing::debug_span!(target: "sync", "run", sync = "HeaderSync").entered();
         let header_head = chain.header_head()?;
+        self.update_throughput_metric(&header_head);
         if !self.header_sync_due(sync_status, &header_head, highest_height) {
             return Ok(());
         }
@@ -95,16 +144,123 @@ impl HeaderSync {
                 highest_height,
             };
             self.syncing_peer = None;
-            if let Some(peer) = highest_height_peers.choose(&mut thread_rng()).cloned() {
-                if peer.highest_block_height > header_head.height {
-                    self.syncing_peer = self.request_headers(chain, peer);
+            let mut batch_size = MAX_BLOCK_HEADERS;
+            for peer in self.choose_peers(header_head.height, highest_height_peers) {
+                let peer_batch_size = self.peer_batch_size(&peer.peer_info.id);
+                if let Some(peer) = self.request_headers(chain, peer, peer_batch_size) {
+                    batch_size = batch_size.min(peer_batch_size);
+                    // The first peer is the best one, which is held responsible
+                    // for the progress of header sync.
+                    if self.syncing_peer.is_none() {
+                        self.syncing_peer = Some(peer);
+                    }
                 }
             }
+            self.batch_size = batch_size;
         }
 
         Ok(())
     }
 
+    /// Records a response to a header request, measuring the throughput of
+    /// the peer if it's a response to the last request to it.
+    pub fn on_response(&mut self, peer_id: &PeerId, num_headers: usize) {
+        let Some(stats) = self.peer_stats.get_mut(peer_id) else { return };
+        let Some(request_time) = stats.pending_request.take() else { return };
+        let elapsed = (StaticClock::utc() - request_time)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64()
+            .max(0.001);
+        let headers_per_second = num_headers as f64 / elapsed;
+        stats.headers_per_second = Some(match stats.headers_per_second {
+            Some(old) => {
+                old * (1.0 - THROUGHPUT_SMOOTHING) + headers_per_second * THROUGHPUT_SMOOTHING
+            }
+            None => headers_per_second,
+        });
+        stats.num_stalls = 0;
+        stats.demoted_until = None;
+        debug!(target: "sync", %peer_id, num_headers, elapsed, headers_per_second = stats.headers_per_second, "Received headers");
+    }
+
+    /// Chooses the peers to request headers from, best first. Peers which
+    /// didn't answer the last request in time are demoted, and peers which
+    /// are still expected to answer are only chosen if there are no others.
+    fn choose_peers(
+        &mut self,
+        header_head_height: BlockHeight,
+        highest_height_peers: &[HighestHeightPeerInfo],
+    ) -> Vec<HighestHeightPeerInfo> {
+        let now = StaticClock::utc();
+        for (peer_id, stats) in self.peer_stats.iter_mut() {
+            let Some(request_time) = stats.pending_request else { continue };
+            if now <= request_time + self.initial_timeout {
+                continue;
+            }
+            stats.pending_request = None;
+            stats.num_stalls += 1;
+            stats.headers_per_second = stats.headers_per_second.map(|hps| hps / 2.0);
+            let factor = 1 << (stats.num_stalls - 1).min(MAX_DEMOTION_SHIFT);
+            stats.demoted_until = Some(now + self.initial_timeout * factor);
+            metrics::HEADER_SYNC_STALLED_REQUESTS.inc();
+            debug!(target: "sync", %peer_id, num_stalls = stats.num_stalls, "Peer didn't send headers in time, demoting it");
+        }
+
+        let mut peers: Vec<_> = highest_height_peers
+            .iter()
+            .filter(|peer| peer.highest_block_height > header_head_height)
+            .filter(|peer| {
+                self.peer_stats.get(&peer.peer_info.id).map_or(true, |stats| {
+                    stats.demoted_until.map_or(true, |demoted_until| now > demoted_until)
+                })
+            })
+            .cloned()
+            .collect();
+        peers.shuffle(&mut thread_rng());
+        // Peers which weren't asked yet go first, to measure them.
+        let score = |peer: &HighestHeightPeerInfo| {
+            self.peer_stats
+                .get(&peer.peer_info.id)
+                .and_then(|stats| stats.headers_per_second)
+                .unwrap_or(f64::INFINITY)
+        };
+        peers.sort_by(|a, b| score(b).total_cmp(&score(a)));
+        let (mut idle, busy): (Vec<_>, Vec<_>) = peers.into_iter().partition(|peer| {
+            self.peer_stats
+                .get(&peer.peer_info.id)
+                .map_or(true, |stats| stats.pending_request.is_none())
+        });
+        if idle.is_empty() {
+            idle = busy;
+        }
+        idle.truncate(MAX_PARALLEL_HEADER_REQUESTS);
+        idle
+    }
+
+    /// Number of headers to request from the peer, based on its throughput.
+    fn peer_batch_size(&self, peer_id: &PeerId) -> u64 {
+        match self.peer_stats.get(peer_id).and_then(|stats| stats.headers_per_second) {
+            Some(headers_per_second) => ((headers_per_second * TARGET_RESPONSE_TIME_SECS) as u64)
+                .clamp(MIN_BLOCK_HEADERS_BATCH, MAX_BLOCK_HEADERS),
+            None => MAX_BLOCK_HEADERS,
+        }
+    }
+
+    fn update_throughput_metric(&mut self, header_head: &Tip) {
+        let now = StaticClock::utc();
+        match self.throughput_window {
+            Some((start, height)) if now >= start + Duration::seconds(1) => {
+                let elapsed = (now - start).to_std().unwrap_or_default().as_secs_f64();
+                let heights = header_head.height.saturating_sub(height);
+                metrics::HEADER_SYNC_HEADERS_PER_SECOND.set(heights as f64 / elapsed);
+                self.throughput_window = Some((now, header_head.height));
+            }
+            Some(_) => {}
+            None => self.throughput_window = Some((now, header_head.height)),
+        }
+    }
+
     fn compute_expected_height(
         &self,
         old_height: BlockHeight,
@@ -127,8 +283,8 @@ impl HeaderSync {
             self.prev_header_sync;
 
         // Received all necessary header, can request more.
-        let all_headers_received =
-            header_head.height >= min(prev_height + MAX_BLOCK_HEADERS - 4, prev_highest_height);
+        let all_headers_received = header_head.height
+            >= min(prev_height + self.batch_size.saturating_sub(4), prev_highest_height);
 
         // Did we receive as many headers as we expected from the peer? Request more or ban peer.
         let stalling = header_head.height <= old_expected_height && now > timeout;
@@ -215,15 +371,19 @@ impl HeaderSync {
         &mut self,
         chain: &Chain,
         peer: HighestHeightPeerInfo,
+        batch_size: u64,
     ) -> Option<HighestHeightPeerInfo> {
         if let Ok(locator) = self.get_locator(chain) {
-            debug!(target: "sync", "Sync: request headers: asking {} for headers, {:?}", peer.peer_info.id, locator);
+            debug!(target: "sync", "Sync: request headers: asking {} for {} headers, {:?}", peer.peer_info.id, batch_size, locator);
             self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::BlockHeadersRequest {
                     hashes: locator,
+                    max_headers: (batch_size < MAX_BLOCK_HEADERS).then_some(batch_size),
                     peer_id: peer.peer_info.id.clone(),
                 },
             ));
+            self.peer_stats.entry(peer.peer_info.id.clone()).or_default().pending_request =
+                Some(StaticClock::utc());
             return Some(peer);
         }
         None
@@ -393,6 +553,7 @@ mod test {
                 },
                 tracked_shards: vec![],
                 archival: false,
+                snapshot_host_info: None,
                 last_block: Some(BlockInfo {
                     height: chain2.head().unwrap().height,
                     hash: chain2.head().unwrap().last_block_hash,
@@ -420,6 +581,7 @@ mod test {
                     .iter()
                     .map(|i| *chain.get_block_by_height(*i).unwrap().hash())
                     .collect(),
+                max_headers: None,
                 peer_id: peer1.peer_info.id
             }
         );
@@ -493,6 +655,7 @@ mod test {
                 },
                 tracked_shards: vec![],
                 archival: false,
+                snapshot_host_info: None,
                 last_block: Some(BlockInfo {
                     height: chain2.head().unwrap().height,
                     hash: chain2.head().unwrap().last_block_hash,
@@ -521,6 +684,7 @@ mod test {
                     .iter()
                     .map(|i| *chain.get_block_by_height(*i).unwrap().hash())
                     .collect(),
+                max_headers: None,
                 peer_id: peer1.peer_info.id
             }
         );
@@ -558,6 +722,7 @@ mod test {
                 highest_block_hash: Default::default(),
                 tracked_shards: vec![],
                 archival: false,
+                snapshot_host_info: None,
             });
             header_sync.syncing_peer.as_mut().unwrap().highest_block_height = highest_height;
         };
@@ -719,6 +884,7 @@ mod test {
                 },
                 tracked_shards: vec![],
                 archival: false,
+                snapshot_host_info: None,
                 last_block: Some(BlockInfo {
                     height: chain2.head().unwrap().height,
                     hash: chain2.head().unwrap().last_block_hash,
@@ -752,10 +918,12 @@ mod test {
                 }
             };
             match message {
-                NetworkRequests::BlockHeadersRequest { hashes, peer_id } => {
+                NetworkRequests::BlockHeadersRequest { hashes, max_headers, peer_id } => {
                     assert_eq!(peer_id, peer1.peer_info.id);
-                    let headers = chain2.retrieve_headers(hashes, MAX_BLOCK_HEADERS, None).unwrap();
+                    let max_headers = max_headers.unwrap_or(MAX_BLOCK_HEADERS);
+                    let headers = chain2.retrieve_headers(hashes, max_headers, None).unwrap();
                     assert!(!headers.is_empty(), "No headers were returned");
+                    header_sync.on_response(&peer_id, headers.len());
                     match chain.sync_block_headers(headers, &mut Vec::new()) {
                         Ok(_) => {}
                         Err(e) => {
@@ -775,4 +943,70 @@ mod test {
         let new_tip = chain.header_head().unwrap();
         assert_eq!(new_tip.last_block_hash, chain2.head().unwrap().last_block_hash);
     }
+
+    /// Checks that the fastest peers are asked for headers first, that slow
+    /// peers get smaller batches, and that a peer which doesn't answer in time
+    /// isn't asked again until its demotion ends.
+    #[test]
+    fn test_choose_peers() {
+        let mut header_sync = HeaderSync::new(
+            Arc::new(MockPeerManagerAdapter::default()).into(),
+            TimeDuration::from_secs(10),
+            TimeDuration::from_secs(2),
+            TimeDuration::from_secs(120),
+            1_000_000_000,
+        );
+        let peers: Vec<_> = (0..5)
+            .map(|_| HighestHeightPeerInfo {
+                peer_info: PeerInfo::random(),
+                genesis_id: Default::default(),
+                highest_block_height: 100,
+                highest_block_hash: Default::default(),
+                tracked_shards: vec![],
+                archival: false,
+                snapshot_host_info: None,
+            })
+            .collect();
+        for (peer, headers_per_second) in peers.iter().zip([10.0, 1000.0, 100.0, 50.0, 5.0]) {
+            header_sync.peer_stats.insert(
+                peer.peer_info.id.clone(),
+                PeerHeaderStats {
+                    headers_per_second: Some(headers_per_second),
+                    ..Default::default()
+                },
+            );
+        }
+        let ids = |chosen: Vec<HighestHeightPeerInfo>| {
+            chosen.into_iter().map(|peer| peer.peer_info.id).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(header_sync.choose_peers(0, &peers)),
+            [&peers[1], &peers[2], &peers[3]].map(|peer| peer.peer_info.id.clone())
+        );
+        assert!(header_sync.choose_peers(100, &peers).is_empty());
+
+        assert_eq!(header_sync.peer_batch_size(&peers[1].peer_info.id), MAX_BLOCK_HEADERS);
+        assert_eq!(header_sync.peer_batch_size(&peers[2].peer_info.id), 200);
+        assert_eq!(header_sync.peer_batch_size(&peers[4].peer_info.id), MIN_BLOCK_HEADERS_BATCH);
+        assert_eq!(header_sync.peer_batch_size(&PeerInfo::random().id), MAX_BLOCK_HEADERS);
+
+        // The fastest peer didn't answer in time.
+        let stalled = &peers[1].peer_info.id;
+        header_sync.peer_stats.get_mut(stalled).unwrap().pending_request =
+            Some(StaticClock::utc() - Duration::seconds(11));
+        let chosen = ids(header_sync.choose_peers(0, &peers));
+        assert!(!chosen.contains(stalled));
+        let stats = &header_sync.peer_stats[stalled];
+        assert_eq!(stats.num_stalls, 1);
+        assert_eq!(stats.headers_per_second, Some(500.0));
+        assert!(stats.demoted_until.unwrap() > StaticClock::utc() + Duration::seconds(9));
+
+        // A response ends the demotion.
+        header_sync.peer_stats.get_mut(stalled).unwrap().pending_request = Some(StaticClock::utc());
+        header_sync.on_response(stalled, 512);
+        let stats = &header_sync.peer_stats[stalled];
+        assert_eq!(stats.num_stalls, 0);
+        assert!(stats.demoted_until.is_none());
+        assert_eq!(ids(header_sync.choose_peers(0, &peers))[0], *stalled);
+    }
 }
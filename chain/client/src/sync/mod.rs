@@ -1,3 +1,4 @@
+pub mod bandwidth;
 pub mod block;
 pub mod epoch;
 pub mod external;
@@ -10,9 +10,13 @@
 //! Then it tries downloading the rest of the data in 'parts' (usually the part is around 1MB in size).
 //!
 //! For downloading - the code is picking the potential target nodes (all direct peers that are tracking the shard
-//! (and are high enough) + validators from that epoch that were tracking the shard)
+//! (and are high enough) + validators from that epoch that were tracking the shard). When syncing to the first block
+//! of an epoch, the peers which advertised that they can serve the parts from their state snapshot are preferred.
 //! Then for each part that we're missing, we're 'randomly' picking a target from whom we'll request it - but we make
-//! sure to not request more than MAX_STATE_PART_REQUESTS from each.
+//! sure to not have more than MAX_STATE_PART_REQUEST unanswered requests to each, and to retry a part that failed
+//! from a different target if possible.
+//! If external storage is configured, the parts are downloaded from it instead, and every range of parts which keeps
+//! failing to download switches between the external storage and the peers (see `StatePartsSourcePolicy`).
 //!
 //! WARNING: with the current design, we're putting quite a load on the validators - as we request a lot of data from
 //!         them (if you assume that we have 100 validators and 30 peers - we send 100/130 of requests to validators).
@@ -21,6 +25,7 @@
 //!
 
 use crate::metrics;
+use crate::sync::bandwidth::BandwidthLimiter;
 use crate::sync::external::{
     create_bucket_readonly, external_storage_location, ExternalConnection,
 };
@@ -30,7 +35,7 @@ use futures::{future, FutureExt};
 use near_async::messaging::CanSendAsync;
 use near_chain::chain::ApplyStatePartsRequest;
 use near_chain::near_chain_primitives;
-use near_chain::resharding::StateSplitRequest;
+use near_chain::resharding::{ReshardingCancellation, StateSplitRequest};
 use near_chain::Chain;
 use near_chain_configs::{ExternalStorageConfig, ExternalStorageLocation, SyncConfig};
 use near_client_primitives::types::format_shard_sync_phase_per_shard;
@@ -50,7 +55,7 @@ use near_primitives::state_sync::{get_num_state_parts, ShardStateSyncResponse};
 use near_primitives::static_clock::StaticClock;
 use near_primitives::types::{AccountId, EpochHeight, EpochId, ShardId, StateRoot};
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
+use rand::thread_rng;
 use std::collections::HashMap;
 use std::ops::Add;
 use std::sync::atomic::Ordering;
@@ -60,7 +65,7 @@ use std::time::Duration as TimeDuration;
 use tokio::sync::{Semaphore, TryAcquireError};
 use tracing::{debug, info};
 
-/// Maximum number of state parts to request per peer on each round when node is trying to download the state.
+/// Maximum number of state parts requested from a peer which it hasn't responded to yet, across all shards.
 pub const MAX_STATE_PART_REQUEST: u64 = 16;
 /// Number of state parts already requested stored as pending.
 /// This number should not exceed MAX_STATE_PART_REQUEST times (number of peers in the network).
@@ -100,31 +105,190 @@ pub struct StateSyncGetPartResult {
     part_id: PartId,
     part_result: Result<Vec<u8>, String>,
 }
-/// How to retrieve the state data.
-enum StateSyncInner {
-    /// Request both the state header and state parts from the peers.
-    Peers {
-        /// Which parts were requested from which peer and when.
-        last_part_id_requested: HashMap<(PeerId, ShardId), PendingRequestStatus>,
-        /// Map from which part we requested to whom.
-        requested_target: lru::LruCache<(u64, CryptoHash), PeerId>,
-    },
-    /// Requests the state header from peers but gets the state parts from an
-    /// external storage.
-    PartsFromExternal {
-        /// Chain ID.
-        chain_id: String,
-        /// This semaphore imposes a restriction on the maximum number of simultaneous downloads
-        semaphore: Arc<tokio::sync::Semaphore>,
-        /// Connection to the external storage.
-        external: ExternalConnection,
-    },
+/// Requests of state headers and parts to the peers.
+struct PeerRequests {
+    /// Which parts were requested from which peer and when.
+    last_part_id_requested: HashMap<(PeerId, ShardId), PendingRequestStatus>,
+    /// Map from which part we requested to whom.
+    requested_target: lru::LruCache<(u64, CryptoHash), PeerId>,
+}
+
+/// External storage the state parts are dumped to.
+struct ExternalStorage {
+    /// Chain ID.
+    chain_id: String,
+    /// This semaphore imposes a restriction on the maximum number of simultaneous downloads
+    semaphore: Arc<tokio::sync::Semaphore>,
+    /// Connection to the external storage.
+    external: ExternalConnection,
+}
+
+/// Number of consecutive state parts of a shard which are always downloaded
+/// from the same source.
+const STATE_PARTS_SOURCE_RANGE: u64 = 64;
+
+/// Where a state part is downloaded from.  The state header is always
+/// requested from the peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StatePartsSource {
+    External,
+    Peers,
+}
+
+impl StatePartsSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::External => "external",
+            Self::Peers => "peers",
+        }
+    }
+
+    fn other(&self) -> Self {
+        match self {
+            Self::External => Self::Peers,
+            Self::Peers => Self::External,
+        }
+    }
+}
+
+/// Source of a range of state parts of a shard.
+struct PartsRangeSource {
+    source: StatePartsSource,
+    /// Number of downloads of parts of the range from `source` which failed in a row.
+    num_failures: u32,
+}
+
+/// Chooses where to download every range of `STATE_PARTS_SOURCE_RANGE` state
+/// parts of every shard from.  Every range starts with the preferred source,
+/// and switches to the other source once downloading its parts fails
+/// `fallback_after_failures` times in a row, so that a stale or misconfigured
+/// external storage doesn't stop state sync, and neither do unhelpful peers.
+struct StatePartsSourcePolicy {
+    preferred: StatePartsSource,
+    /// Zero if parts are only downloaded from the preferred source.
+    fallback_after_failures: u32,
+    ranges: HashMap<(ShardId, u64), PartsRangeSource>,
+}
+
+impl StatePartsSourcePolicy {
+    fn new(preferred: StatePartsSource, fallback_after_failures: u32) -> Self {
+        Self { preferred, fallback_after_failures, ranges: HashMap::new() }
+    }
+
+    fn source(&self, shard_id: ShardId, part_id: u64) -> StatePartsSource {
+        self.ranges
+            .get(&(shard_id, part_id / STATE_PARTS_SOURCE_RANGE))
+            .map_or(self.preferred, |range| range.source)
+    }
+
+    /// Records a failed download of a part from `source`, and switches its
+    /// range to the other source if it failed too many times.
+    fn on_failure(&mut self, shard_id: ShardId, part_id: u64, source: StatePartsSource) {
+        if self.fallback_after_failures == 0 {
+            return;
+        }
+        let range_id = part_id / STATE_PARTS_SOURCE_RANGE;
+        let range = self
+            .ranges
+            .entry((shard_id, range_id))
+            .or_insert(PartsRangeSource { source: self.preferred, num_failures: 0 });
+        if range.source != source {
+            return;
+        }
+        range.num_failures += 1;
+        if range.num_failures >= self.fallback_after_failures {
+            range.source = source.other();
+            range.num_failures = 0;
+            metrics::STATE_SYNC_SOURCE_FALLBACKS
+                .with_label_values(&[&shard_id.to_string(), range.source.as_str()])
+                .inc();
+            tracing::warn!(target: "sync", %shard_id, range_id, from = source.as_str(), to = range.source.as_str(), "Failed to download state parts, switching to the other source");
+        }
+    }
+
+    /// Records a successful download of a part from `source`.
+    fn on_success(&mut self, shard_id: ShardId, part_id: u64, source: StatePartsSource) {
+        if let Some(range) = self.ranges.get_mut(&(shard_id, part_id / STATE_PARTS_SOURCE_RANGE)) {
+            if range.source == source {
+                range.num_failures = 0;
+            }
+        }
+    }
+
+    /// Forgets the sources of the parts of the shard, once its parts are
+    /// downloaded for a new sync hash.
+    fn reset_shard(&mut self, shard_id: ShardId) {
+        self.ranges.retain(|(range_shard_id, _), _| *range_shard_id != shard_id);
+    }
+}
+
+/// State parts of a shard applied as soon as they are downloaded.
+struct StreamedStateParts {
+    /// Whether the part was scheduled to be applied.
+    scheduled: Vec<bool>,
+    /// Whether the result of applying the part was received.
+    done: Vec<bool>,
+    num_done: usize,
+    /// Parts which failed to apply, with their errors.
+    failed: Vec<(u64, String)>,
+    /// Parts which don't match the state root.
+    invalid: Vec<u64>,
+}
+
+impl StreamedStateParts {
+    fn new(num_parts: usize) -> Self {
+        Self {
+            scheduled: vec![false; num_parts],
+            done: vec![false; num_parts],
+            num_done: 0,
+            failed: vec![],
+            invalid: vec![],
+        }
+    }
+
+    fn set_result(&mut self, part_id: u64, result: Result<(), near_chain::Error>) {
+        let Some(done) = self.done.get_mut(part_id as usize) else { return };
+        if std::mem::replace(done, true) {
+            return;
+        }
+        self.num_done += 1;
+        match result {
+            Ok(()) => {}
+            Err(near_chain::Error::InvalidStateParts(part_ids)) => self.invalid.extend(part_ids),
+            Err(err) => self.failed.push((part_id, err.to_string())),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.num_done == self.done.len()
+    }
+
+    fn into_result(mut self) -> Result<(), near_chain::Error> {
+        if !self.invalid.is_empty() {
+            self.invalid.sort();
+            return Err(near_chain::Error::InvalidStateParts(self.invalid));
+        }
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        self.failed.sort();
+        Err(near_chain::Error::StatePartsApplyFailed(self.failed))
+    }
 }
 
 /// Helper to track state sync.
 pub struct StateSync {
-    /// How to retrieve the state data.
-    inner: StateSyncInner,
+    /// Requests to the peers.
+    peers: PeerRequests,
+
+    /// External storage to download state parts from, if configured.
+    external: Option<ExternalStorage>,
+
+    /// Chooses whether to download each state part from the external storage or the peers.
+    parts_source: StatePartsSourcePolicy,
+
+    /// Limits the bandwidth used to download state parts.
+    download_limiter: Arc<BandwidthLimiter>,
 
     /// Is used for communication with the peers.
     network_adapter: PeerManagerAdapter,
@@ -140,9 +304,19 @@ pub struct StateSync {
     /// Maps shard_id to result of applying downloaded state.
     state_parts_apply_results: HashMap<ShardId, Result<(), near_chain_primitives::error::Error>>,
 
+    /// Whether state parts are applied as soon as they are downloaded, instead of once all the
+    /// parts of the shard are downloaded.
+    apply_parts_while_downloading: bool,
+
+    /// Maps shard_id to the state parts applied while the rest are downloaded.
+    streamed_parts: HashMap<ShardId, StreamedStateParts>,
+
     /// Maps shard_id to result of splitting state for resharding.
     split_state_roots: HashMap<ShardId, Result<HashMap<ShardUId, StateRoot>, near_chain::Error>>,
 
+    /// Maps shard_id to the cancellation of the scheduled state split for resharding.
+    split_state_cancellations: HashMap<ShardId, ReshardingCancellation>,
+
     /// Message queue to process the received state parts.
     state_parts_mpsc_tx: Sender<StateSyncGetPartResult>,
     state_parts_mpsc_rx: Receiver<StateSyncGetPartResult>,
@@ -154,17 +328,22 @@ impl StateSync {
         timeout: TimeDuration,
         chain_id: &str,
         sync_config: &SyncConfig,
+        apply_parts_while_downloading: bool,
         catchup: bool,
+        download_limiter: Arc<BandwidthLimiter>,
     ) -> Self {
-        let inner = match sync_config {
-            SyncConfig::Peers => StateSyncInner::Peers {
-                last_part_id_requested: Default::default(),
-                requested_target: lru::LruCache::new(MAX_PENDING_PART as usize),
-            },
+        let peers = PeerRequests {
+            last_part_id_requested: Default::default(),
+            requested_target: lru::LruCache::new(MAX_PENDING_PART as usize),
+        };
+        let (external, parts_source) = match sync_config {
+            SyncConfig::Peers => (None, StatePartsSourcePolicy::new(StatePartsSource::Peers, 0)),
             SyncConfig::ExternalStorage(ExternalStorageConfig {
                 location,
                 num_concurrent_requests,
                 num_concurrent_requests_during_catchup,
+                fallback_after_failures,
+                prefer_peers,
             }) => {
                 let external = match location {
                     ExternalStorageLocation::S3 { bucket, region, .. } => {
@@ -188,22 +367,34 @@ impl StateSync {
                 } else {
                     *num_concurrent_requests
                 } as usize;
-                StateSyncInner::PartsFromExternal {
+                let external = ExternalStorage {
                     chain_id: chain_id.to_string(),
                     semaphore: Arc::new(tokio::sync::Semaphore::new(num_permits)),
                     external,
-                }
+                };
+                let preferred = if *prefer_peers {
+                    StatePartsSource::Peers
+                } else {
+                    StatePartsSource::External
+                };
+                (Some(external), StatePartsSourcePolicy::new(preferred, *fallback_after_failures))
             }
         };
         let timeout = Duration::from_std(timeout).unwrap();
         let (tx, rx) = channel::<StateSyncGetPartResult>();
         StateSync {
-            inner,
+            peers,
+            external,
+            parts_source,
+            download_limiter,
             network_adapter,
             last_time_block_requested: None,
             timeout,
             state_parts_apply_results: HashMap::new(),
+            apply_parts_while_downloading,
+            streamed_parts: HashMap::new(),
             split_state_roots: HashMap::new(),
+            split_state_cancellations: HashMap::new(),
             state_parts_mpsc_rx: rx,
             state_parts_mpsc_tx: tx,
         }
@@ -299,6 +490,13 @@ impl StateSync {
                         )?;
                 }
                 ShardSyncStatus::StateDownloadParts => {
+                    self.schedule_downloaded_parts(
+                        shard_id,
+                        shard_sync_download,
+                        sync_hash,
+                        chain,
+                        state_parts_task_scheduler,
+                    )?;
                     let res =
                         self.sync_shards_download_parts_status(shard_id, shard_sync_download, now);
                     download_timeout = res.0;
@@ -439,6 +637,11 @@ impl StateSync {
                         chain,
                         msg.part_result,
                     );
+                    if part_download.done {
+                        self.parts_source.on_success(shard_id, part_id, StatePartsSource::External);
+                    } else if part_download.error {
+                        self.parts_source.on_failure(shard_id, part_id, StatePartsSource::External);
+                    }
                 }
             }
         }
@@ -454,36 +657,78 @@ impl StateSync {
         self.state_parts_apply_results.insert(shard_id, apply_result);
     }
 
+    // Called by the client actor, when it finished applying a part which was scheduled as soon as
+    // it was downloaded.
+    pub fn set_part_apply_result(
+        &mut self,
+        shard_id: ShardId,
+        part_id: u64,
+        apply_result: Result<(), near_chain::Error>,
+    ) {
+        if let Some(streamed_parts) = self.streamed_parts.get_mut(&shard_id) {
+            streamed_parts.set_result(part_id, apply_result);
+        }
+    }
+
+    /// Returns the result of applying the parts of the shard once all of them are applied.
+    fn take_apply_result(&mut self, shard_id: ShardId) -> Option<Result<(), near_chain::Error>> {
+        match self.streamed_parts.get(&shard_id) {
+            Some(streamed_parts) if streamed_parts.is_finished() => {
+                self.streamed_parts.remove(&shard_id).map(StreamedStateParts::into_result)
+            }
+            Some(_) => None,
+            None => self.state_parts_apply_results.remove(&shard_id),
+        }
+    }
+
     // Called by the client actor, when it finished splitting the state.
     pub fn set_split_result(
         &mut self,
         shard_id: ShardId,
         result: Result<HashMap<ShardUId, StateRoot>, near_chain::Error>,
     ) {
+        self.split_state_cancellations.remove(&shard_id);
         self.split_state_roots.insert(shard_id, result);
     }
 
+    // Called by the client when the result of the scheduled state splits isn't needed anymore.
+    pub fn cancel_state_splits(&mut self) {
+        for (shard_id, cancellation) in self.split_state_cancellations.drain() {
+            tracing::debug!(target: "sync", %shard_id, "Cancelling state split");
+            cancellation.cancel();
+        }
+    }
+
     /// Find the hash of the first block on the same epoch (and chain) of block with hash `sync_hash`.
     pub fn get_epoch_start_sync_hash(
         chain: &Chain,
         sync_hash: &CryptoHash,
     ) -> Result<CryptoHash, near_chain::Error> {
-        let mut header = chain.get_block_header(sync_hash)?;
-        let mut epoch_id = header.epoch_id().clone();
-        let mut hash = *header.hash();
-        let mut prev_hash = *header.prev_hash();
-        loop {
-            if prev_hash == CryptoHash::default() {
-                return Ok(hash);
-            }
-            header = chain.get_block_header(&prev_hash)?;
-            if &epoch_id != header.epoch_id() {
-                return Ok(hash);
-            }
-            epoch_id = header.epoch_id().clone();
-            hash = *header.hash();
-            prev_hash = *header.prev_hash();
+        let epoch_start_height = chain.epoch_manager.get_epoch_start_height(sync_hash)?;
+        chain.get_block_hash_on_canonical_chain(sync_hash, epoch_start_height)
+    }
+
+    /// Find the hash of the last final block of block with hash `sync_hash`, if
+    /// the state can be synced to it, i.e. if it's in the same epoch but isn't
+    /// its first block, and the epoch doesn't end with a shard layout change.
+    pub fn get_final_sync_hash(
+        chain: &Chain,
+        sync_hash: &CryptoHash,
+    ) -> Result<Option<CryptoHash>, near_chain::Error> {
+        let header = chain.get_block_header(sync_hash)?;
+        let final_header = match chain.get_block_header(header.last_final_block()) {
+            Ok(final_header) => final_header,
+            Err(near_chain::Error::DBNotFoundErr(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let prev_hash = final_header.prev_hash();
+        if final_header.epoch_id() != header.epoch_id()
+            || chain.epoch_manager.is_next_block_epoch_start(prev_hash)?
+            || chain.epoch_manager.will_shard_layout_change(prev_hash)?
+        {
+            return Ok(None);
         }
+        Ok(Some(*final_header.hash()))
     }
 
     // Function called when our node receives the network response with a part.
@@ -493,71 +738,73 @@ impl StateSync {
         shard_id: ShardId,
         sync_hash: CryptoHash,
     ) {
-        match &mut self.inner {
-            StateSyncInner::Peers { last_part_id_requested, requested_target } => {
-                let key = (part_id, sync_hash);
-                // Check that it came from the target that we requested it from.
-                if let Some(target) = requested_target.get(&key) {
-                    if last_part_id_requested.get_mut(&(target.clone(), shard_id)).map_or(
-                        false,
-                        |request| {
-                            request.missing_parts = request.missing_parts.saturating_sub(1);
-                            request.missing_parts == 0
-                        },
-                    ) {
-                        last_part_id_requested.remove(&(target.clone(), shard_id));
-                    }
-                }
-            }
-            StateSyncInner::PartsFromExternal { .. } => {
-                // Do nothing.
+        let PeerRequests { last_part_id_requested, requested_target } = &mut self.peers;
+        let key = (part_id, sync_hash);
+        // Check that it came from the target that we requested it from.
+        if let Some(target) = requested_target.get(&key) {
+            if last_part_id_requested.get_mut(&(target.clone(), shard_id)).map_or(
+                false,
+                |request| {
+                    request.missing_parts = request.missing_parts.saturating_sub(1);
+                    request.missing_parts == 0
+                },
+            ) {
+                last_part_id_requested.remove(&(target.clone(), shard_id));
             }
         }
     }
 
     /// Find possible targets to download state from.
     /// Candidates are peers at highest height.
-    /// Only select candidates that we have no pending request currently ongoing.
+    /// If `snapshot_epoch_id` is set, the peers which advertised that they can
+    /// serve the parts of the shard from their snapshot of the state at the
+    /// start of that epoch are the only candidates, unless there are none.
+    /// Only select candidates that can be sent more requests.
     fn possible_targets(
         &mut self,
         shard_id: ShardId,
+        snapshot_epoch_id: Option<&EpochId>,
         highest_height_peers: &[HighestHeightPeerInfo],
     ) -> Result<Vec<PeerId>, near_chain::Error> {
-        let peers = highest_height_peers
+        let snapshot_hosts: Vec<PeerId> = highest_height_peers
             .iter()
-            .filter_map(|peer| {
-                // Select peers that are high enough (if they are syncing themselves, they might not have the data that we want)
-                //  and that are tracking the shard.
-                // TODO: possible optimization - simply select peers that have height greater than the epoch start that we're asking for.
-                if peer.tracked_shards.contains(&shard_id) {
-                    Some(peer.peer_info.id.clone())
-                } else {
-                    None
-                }
+            .filter(|peer| {
+                peer.snapshot_host_info.as_ref().map_or(false, |info| {
+                    Some(&info.epoch_id) == snapshot_epoch_id && info.shards.contains(&shard_id)
+                })
             })
+            .map(|peer| peer.peer_info.id.clone())
             .collect();
-        Ok(self.select_peers(peers, shard_id)?)
+        let peers = if !snapshot_hosts.is_empty() {
+            snapshot_hosts
+        } else {
+            highest_height_peers
+                .iter()
+                .filter_map(|peer| {
+                    // Select peers that are high enough (if they are syncing themselves, they might not have the data that we want)
+                    //  and that are tracking the shard.
+                    // TODO: possible optimization - simply select peers that have height greater than the epoch start that we're asking for.
+                    if peer.tracked_shards.contains(&shard_id) {
+                        Some(peer.peer_info.id.clone())
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
+        Ok(self.select_peers(peers)?)
     }
 
-    /// Avoids peers that already have outstanding requests for parts.
-    fn select_peers(
-        &mut self,
-        peers: Vec<PeerId>,
-        shard_id: ShardId,
-    ) -> Result<Vec<PeerId>, near_chain::Error> {
-        let res = match &mut self.inner {
-            StateSyncInner::Peers { last_part_id_requested, .. } => {
-                last_part_id_requested.retain(|_, request| !request.expired());
-                peers
-                    .into_iter()
-                    .filter(|candidate| {
-                        // If we still have a pending request from this node - don't add another one.
-                        !last_part_id_requested.contains_key(&(candidate.clone(), shard_id))
-                    })
-                    .collect::<Vec<_>>()
-            }
-            StateSyncInner::PartsFromExternal { .. } => peers,
-        };
+    /// Avoids peers that already have as many outstanding requests for parts as allowed.
+    fn select_peers(&mut self, peers: Vec<PeerId>) -> Result<Vec<PeerId>, near_chain::Error> {
+        let last_part_id_requested = &mut self.peers.last_part_id_requested;
+        last_part_id_requested.retain(|_, request| !request.expired());
+        let res = peers
+            .into_iter()
+            .filter(|candidate| {
+                parts_in_flight(last_part_id_requested, candidate) < MAX_STATE_PART_REQUEST
+            })
+            .collect::<Vec<_>>();
         Ok(res)
     }
 
@@ -571,16 +818,22 @@ impl StateSync {
         highest_height_peers: &[HighestHeightPeerInfo],
         state_parts_arbiter_handle: &ArbiterHandle,
     ) -> Result<(), near_chain::Error> {
-        let possible_targets = self.possible_targets(shard_id, highest_height_peers)?;
-
-        if possible_targets.is_empty() {
-            // In most cases it means that all the targets are currently busy (that we have a pending request with them).
-            return Ok(());
-        }
+        let sync_block_header = chain.get_block_header(&sync_hash)?;
+        // Parts of the state at the start of an epoch are served from state snapshots.
+        let snapshot_epoch_id = chain
+            .epoch_manager
+            .is_next_block_epoch_start(sync_block_header.prev_hash())?
+            .then(|| sync_block_header.epoch_id().clone());
+        let possible_targets =
+            self.possible_targets(shard_id, snapshot_epoch_id.as_ref(), highest_height_peers)?;
 
         // Downloading strategy starts here
         match shard_sync_download.status {
             ShardSyncStatus::StateDownloadHeader => {
+                if possible_targets.is_empty() {
+                    // In most cases it means that all the targets are currently busy (that we have a pending request with them).
+                    return Ok(());
+                }
                 self.request_shard_header(
                     shard_id,
                     sync_hash,
@@ -646,23 +899,58 @@ impl StateSync {
         chain: &Chain,
         state_parts_arbiter_handle: &ArbiterHandle,
     ) {
+        let PeerRequests { last_part_id_requested, requested_target } = &mut self.peers;
+        // We'll select all the 'highest' peers + validators as candidates (excluding those that gave us timeout in the past).
+        // And each one of them will have at most 16 (MAX_STATE_PART_REQUEST) parts requested which it hasn't sent yet.
+        let mut capacities = possible_targets
+            .into_iter()
+            .map(|target| {
+                let in_flight = parts_in_flight(last_part_id_requested, &target);
+                (target, MAX_STATE_PART_REQUEST.saturating_sub(in_flight))
+            })
+            .collect::<Vec<_>>();
+        let mut peers_busy = capacities.is_empty();
+
+        let external = self.external.as_ref().map(|external| {
+            let sync_block_header = chain.get_block_header(&sync_hash).unwrap();
+            let epoch_id = sync_block_header.epoch_id().clone();
+            let epoch_info = chain.epoch_manager.get_epoch_info(&epoch_id).unwrap();
+            let epoch_height = epoch_info.epoch_height();
+
+            let shard_state_header = chain.get_state_header(shard_id, sync_hash).unwrap();
+            let state_num_parts =
+                get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+            (external, epoch_id, epoch_height, state_num_parts)
+        });
+        let mut external_busy = external.is_none();
+        // Parts are split by the memory usage of the trie, which is a good
+        // enough estimate of their size for limiting the bandwidth.
+        let num_parts = new_shard_sync_download.downloads.len().max(1) as u64;
+        let part_size_estimate = chain
+            .get_state_header(shard_id, sync_hash)
+            .map_or(0, |header| header.state_root_node().memory_usage / num_parts);
+
         // Iterate over all parts that needs to be requested (i.e. download.run_me is true).
         // Parts are ordered such that its index match its part_id.
-        match &mut self.inner {
-            StateSyncInner::Peers { last_part_id_requested, requested_target } => {
-                // We'll select all the 'highest' peers + validators as candidates (excluding those that gave us timeout in the past).
-                // And for each one of them, we'll ask for up to 16 (MAX_STATE_PART_REQUEST) parts.
-                let possible_targets_sampler =
-                    SamplerLimited::new(possible_targets, MAX_STATE_PART_REQUEST);
-
-                // For every part that needs to be requested it is selected one
-                // peer (target) randomly to request the part from.
-                // IMPORTANT: here we use 'zip' with possible_target_sampler -
-                // which is limited. So at any moment we'll not request more
-                // than possible_targets.len() * MAX_STATE_PART_REQUEST parts.
-                for ((part_id, download), target) in
-                    parts_to_fetch(new_shard_sync_download).zip(possible_targets_sampler)
-                {
+        // Every part is requested from the source chosen for it, until both sources are busy.
+        for (part_id, download) in parts_to_fetch(new_shard_sync_download) {
+            if (peers_busy && external_busy) || !self.download_limiter.try_start() {
+                break;
+            }
+            match self.parts_source.source(shard_id, part_id) {
+                StatePartsSource::Peers => {
+                    if peers_busy {
+                        continue;
+                    }
+                    // For every part that needs to be requested it is selected one
+                    // peer (target) randomly to request the part from, until all
+                    // the targets are busy.
+                    let Some(target) =
+                        choose_part_target(&mut capacities, download.last_target.as_ref())
+                    else {
+                        peers_busy = true;
+                        continue;
+                    };
                     sent_request_part(
                         target.clone(),
                         part_id,
@@ -680,36 +968,34 @@ impl StateSync {
                         sync_hash,
                         &self.network_adapter,
                     );
+                    self.download_limiter.record(part_size_estimate);
                 }
-            }
-            StateSyncInner::PartsFromExternal { chain_id, semaphore, external } => {
-                let sync_block_header = chain.get_block_header(&sync_hash).unwrap();
-                let epoch_id = sync_block_header.epoch_id();
-                let epoch_info = chain.epoch_manager.get_epoch_info(epoch_id).unwrap();
-                let epoch_height = epoch_info.epoch_height();
-
-                let shard_state_header = chain.get_state_header(shard_id, sync_hash).unwrap();
-                let state_num_parts =
-                    get_num_state_parts(shard_state_header.state_root_node().memory_usage);
-
-                for (part_id, download) in parts_to_fetch(new_shard_sync_download) {
+                StatePartsSource::External => {
+                    let Some((external, epoch_id, epoch_height, state_num_parts)) = &external
+                    else {
+                        continue;
+                    };
+                    if external_busy {
+                        continue;
+                    }
                     request_part_from_external_storage(
                         part_id,
                         download,
                         shard_id,
                         sync_hash,
                         epoch_id,
-                        epoch_height,
-                        state_num_parts,
-                        &chain_id.clone(),
-                        semaphore.clone(),
-                        external.clone(),
+                        *epoch_height,
+                        *state_num_parts,
+                        &external.chain_id,
+                        external.semaphore.clone(),
+                        external.external.clone(),
                         state_parts_arbiter_handle,
                         self.state_parts_mpsc_tx.clone(),
                     );
-                    if semaphore.available_permits() == 0 {
-                        break;
+                    if !download.run_me.load(Ordering::SeqCst) {
+                        self.download_limiter.record(part_size_estimate);
                     }
+                    external_busy = external.semaphore.available_permits() == 0;
                 }
             }
         }
@@ -837,10 +1123,26 @@ impl StateSync {
                             &data,
                         ) {
                             Ok(()) => {
+                                metrics::STATE_SYNC_PARTS_SIZE_DOWNLOADED_BY_SOURCE
+                                    .with_label_values(&[
+                                        &shard_id.to_string(),
+                                        StatePartsSource::Peers.as_str(),
+                                    ])
+                                    .inc_by(data.len() as u64);
+                                self.parts_source.on_success(
+                                    shard_id,
+                                    part_id,
+                                    StatePartsSource::Peers,
+                                );
                                 shard_sync_download.downloads[part_id as usize].done = true;
                             }
                             Err(err) => {
                                 tracing::error!(target: "sync", %shard_id, %hash, part_id, ?err, "State sync set_state_part error");
+                                self.parts_source.on_failure(
+                                    shard_id,
+                                    part_id,
+                                    StatePartsSource::Peers,
+                                );
                                 shard_sync_download.downloads[part_id as usize].error = true;
                             }
                         }
@@ -876,6 +1178,7 @@ impl StateSync {
             // Create the vector with entry for each part.
             *shard_sync_download =
                 ShardSyncDownload::new_download_state_parts(now, state_num_parts);
+            self.parts_source.reset_shard(shard_id);
             run_shard_state_download = true;
         } else {
             let prev = shard_sync_download.downloads[0].prev_update_time;
@@ -914,18 +1217,35 @@ impl StateSync {
         let mut parts_done = true;
         let num_parts = shard_sync_download.downloads.len();
         let mut num_parts_done = 0;
-        for part_download in shard_sync_download.downloads.iter_mut() {
+        for (part_id, part_download) in shard_sync_download.downloads.iter_mut().enumerate() {
+            let part_id = part_id as u64;
             if !part_download.done {
                 parts_done = false;
                 let prev = part_download.prev_update_time;
                 let part_timeout = now - prev > self.timeout; // Retry parts that failed.
                 if part_timeout || part_download.error {
                     download_timeout |= part_timeout;
-                    if part_timeout || part_download.last_target.is_some() {
+                    if part_timeout
+                        && !part_download.error
+                        && part_download.state_requests_count > 0
+                    {
+                        // Only requests to peers have a target.
+                        let source = if part_download.last_target.is_some() {
+                            StatePartsSource::Peers
+                        } else {
+                            StatePartsSource::External
+                        };
+                        self.parts_source.on_failure(shard_id, part_id, source);
+                    }
+                    if part_timeout
+                        || part_download.last_target.is_some()
+                        || self.parts_source.source(shard_id, part_id) == StatePartsSource::Peers
+                    {
                         // Don't immediately retry failed requests from external
-                        // storage. Most often error is a state part not
-                        // available. That error doesn't get fixed by retrying,
-                        // but rather by waiting.
+                        // storage, unless the part is now requested from peers.
+                        // Most often error is a state part not available. That
+                        // error doesn't get fixed by retrying, but rather by
+                        // waiting.
                         metrics::STATE_SYNC_RETRY_PART
                             .with_label_values(&[&shard_id.to_string()])
                             .inc();
@@ -954,12 +1274,45 @@ impl StateSync {
             *shard_sync_download = ShardSyncDownload {
                 downloads: vec![],
                 status: ShardSyncStatus::StateDownloadScheduling,
+                progress: None,
             };
             update_sync_status = true;
         }
         (download_timeout, run_shard_state_download, update_sync_status)
     }
 
+    /// Schedules applying the downloaded parts which weren't scheduled yet, if parts are applied
+    /// while downloading.
+    fn schedule_downloaded_parts(
+        &mut self,
+        shard_id: ShardId,
+        shard_sync_download: &ShardSyncDownload,
+        sync_hash: CryptoHash,
+        chain: &Chain,
+        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
+    ) -> Result<(), near_chain::Error> {
+        if !self.apply_parts_while_downloading {
+            return Ok(());
+        }
+        let num_parts = shard_sync_download.downloads.len();
+        let streamed_parts = self
+            .streamed_parts
+            .entry(shard_id)
+            .or_insert_with(|| StreamedStateParts::new(num_parts));
+        for (part_id, part_download) in shard_sync_download.downloads.iter().enumerate() {
+            if part_download.done && !streamed_parts.scheduled[part_id] {
+                chain.schedule_apply_state_part(
+                    shard_id,
+                    sync_hash,
+                    PartId::new(part_id as u64, num_parts as u64),
+                    state_parts_task_scheduler,
+                )?;
+                streamed_parts.scheduled[part_id] = true;
+            }
+        }
+        Ok(())
+    }
+
     fn sync_shards_download_scheduling_status(
         &mut self,
         shard_id: ShardId,
@@ -972,9 +1325,16 @@ impl StateSync {
         let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
         let state_num_parts =
             get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+        // The parts were scheduled to be applied as they were downloaded.
+        if self.streamed_parts.contains_key(&shard_id) {
+            *shard_sync_download = ShardSyncDownload {
+                downloads: vec![],
+                status: ShardSyncStatus::StateDownloadApplying,
+                progress: None,
+            };
+            return Ok(());
+        }
         // Now apply all the parts to the chain / runtime.
-        // TODO: not sure why this has to happen only after all the parts were downloaded -
-        //       as we could have done this in parallel after getting each part.
         match chain.schedule_apply_state_parts(
             shard_id,
             sync_hash,
@@ -985,6 +1345,7 @@ impl StateSync {
                 *shard_sync_download = ShardSyncDownload {
                     downloads: vec![],
                     status: ShardSyncStatus::StateDownloadApplying,
+                    progress: None,
                 }
             }
             Err(err) => {
@@ -1009,12 +1370,33 @@ impl StateSync {
     ) -> Result<(), near_chain::Error> {
         // Keep waiting until our shard is on the list of results
         // (these are set via callback from ClientActor - both for sync and catchup).
-        if let Some(result) = self.state_parts_apply_results.remove(&shard_id) {
+        if let Some(result) = self.take_apply_result(shard_id) {
+            if let Err(near_chain::Error::InvalidStateParts(part_ids)) = &result {
+                // Only the invalid parts are downloaded again, the rest of
+                // the parts are kept.
+                metrics::STATE_SYNC_INVALID_PARTS
+                    .with_label_values(&[&shard_id.to_string()])
+                    .inc_by(part_ids.len() as u64);
+                tracing::warn!(target: "sync", %shard_id, %sync_hash, ?part_ids, "Downloading invalid state parts again");
+                let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
+                let state_num_parts =
+                    get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+                *shard_sync_download =
+                    ShardSyncDownload::new_download_state_parts(now, state_num_parts);
+                for (part_id, download) in shard_sync_download.downloads.iter_mut().enumerate() {
+                    if !part_ids.contains(&(part_id as u64)) {
+                        download.done = true;
+                        download.run_me.store(false, Ordering::SeqCst);
+                    }
+                }
+                return Ok(());
+            }
             match chain.set_state_finalize(shard_id, sync_hash, result) {
                 Ok(()) => {
                     *shard_sync_download = ShardSyncDownload {
                         downloads: vec![],
                         status: ShardSyncStatus::StateDownloadComplete,
+                        progress: None,
                     }
                 }
                 Err(err) => {
@@ -1045,12 +1427,16 @@ impl StateSync {
             *shard_sync_download = ShardSyncDownload {
                 downloads: vec![],
                 status: ShardSyncStatus::StateSplitScheduling,
+                progress: None,
             };
             false
         } else {
             // If there is no layout change - we're done.
-            *shard_sync_download =
-                ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSyncDone };
+            *shard_sync_download = ShardSyncDownload {
+                downloads: vec![],
+                status: ShardSyncStatus::StateSyncDone,
+                progress: None,
+            };
             true
         }
     }
@@ -1064,14 +1450,18 @@ impl StateSync {
         state_split_scheduler: &dyn Fn(StateSplitRequest),
         me: &Option<AccountId>,
     ) -> Result<(), near_chain::Error> {
-        chain.build_state_for_split_shards_preprocessing(
+        let cancellation = chain.build_state_for_split_shards_preprocessing(
             &sync_hash,
             shard_id,
             state_split_scheduler,
         )?;
+        self.split_state_cancellations.insert(shard_id, cancellation);
         tracing::debug!(target: "sync", %shard_id, %sync_hash, ?me, "State sync split scheduled");
-        *shard_sync_download =
-            ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSplitApplying };
+        *shard_sync_download = ShardSyncDownload {
+            downloads: vec![],
+            status: ShardSyncStatus::StateSplitApplying,
+            progress: None,
+        };
         Ok(())
     }
 
@@ -1087,8 +1477,11 @@ impl StateSync {
         let mut shard_sync_done = false;
         if let Some(state_roots) = result {
             chain.build_state_for_split_shards_postprocessing(&sync_hash, state_roots?)?;
-            *shard_sync_download =
-                ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSyncDone };
+            *shard_sync_download = ShardSyncDownload {
+                downloads: vec![],
+                status: ShardSyncStatus::StateSyncDone,
+                progress: None,
+            };
             shard_sync_done = true;
         }
         Ok(shard_sync_done)
@@ -1199,6 +1592,35 @@ fn request_part_from_peers(
     );
 }
 
+/// Number of parts requested from the peer which it hasn't sent yet.
+fn parts_in_flight(
+    last_part_id_requested: &HashMap<(PeerId, ShardId), PendingRequestStatus>,
+    peer_id: &PeerId,
+) -> u64 {
+    last_part_id_requested
+        .iter()
+        .filter(|((target, _), _)| target == peer_id)
+        .map(|(_, request)| request.missing_parts as u64)
+        .sum()
+}
+
+/// Chooses a random target to request a part from among the targets which can
+/// be sent more requests, given with the number of requests they can be sent,
+/// and takes up one of its requests. Targets other than the one the part was
+/// last requested from are preferred, since that request failed or timed out.
+fn choose_part_target(
+    capacities: &mut [(PeerId, u64)],
+    last_target: Option<&PeerId>,
+) -> Option<PeerId> {
+    let available: Vec<usize> = (0..capacities.len()).filter(|&ix| capacities[ix].1 > 0).collect();
+    let preferred: Vec<usize> =
+        available.iter().copied().filter(|&ix| Some(&capacities[ix].0) != last_target).collect();
+    let mut rng = thread_rng();
+    let ix = *preferred.choose(&mut rng).or_else(|| available.choose(&mut rng))?;
+    capacities[ix].1 -= 1;
+    Some(capacities[ix].0.clone())
+}
+
 fn sent_request_part(
     peer_id: PeerId,
     part_id: u64,
@@ -1248,6 +1670,12 @@ fn process_part_response(
                     metrics::STATE_SYNC_EXTERNAL_PARTS_SIZE_DOWNLOADED
                         .with_label_values(&[&shard_id.to_string()])
                         .inc_by(data.len() as u64);
+                    metrics::STATE_SYNC_PARTS_SIZE_DOWNLOADED_BY_SOURCE
+                        .with_label_values(&[
+                            &shard_id.to_string(),
+                            StatePartsSource::External.as_str(),
+                        ])
+                        .inc_by(data.len() as u64);
                     part_download.done = true;
                     tracing::debug!(target: "sync", %shard_id, part_id, ?part_download, "Set state part success");
                 }
@@ -1274,71 +1702,10 @@ fn process_part_response(
     true
 }
 
-/// Create an abstract collection of elements to be shuffled.
-/// Each element will appear in the shuffled output exactly `limit` times.
-/// Use it as an iterator to access the shuffled collection.
-///
-/// ```rust,ignore
-/// let sampler = SamplerLimited::new(vec![1, 2, 3], 2);
-///
-/// let res = sampler.collect::<Vec<_>>();
-///
-/// assert!(res.len() == 6);
-/// assert!(res.iter().filter(|v| v == 1).count() == 2);
-/// assert!(res.iter().filter(|v| v == 2).count() == 2);
-/// assert!(res.iter().filter(|v| v == 3).count() == 2);
-/// ```
-///
-/// Out of the 90 possible values of `res` in the code above on of them is:
-///
-/// ```
-/// vec![1, 2, 1, 3, 3, 2];
-/// ```
-struct SamplerLimited<T> {
-    data: Vec<T>,
-    limit: Vec<u64>,
-}
-
-impl<T> SamplerLimited<T> {
-    fn new(data: Vec<T>, limit: u64) -> Self {
-        if limit == 0 {
-            Self { data: vec![], limit: vec![] }
-        } else {
-            let len = data.len();
-            Self { data, limit: vec![limit; len] }
-        }
-    }
-}
-
-impl<T: Clone> Iterator for SamplerLimited<T> {
-    type Item = T;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.limit.is_empty() {
-            None
-        } else {
-            let len = self.limit.len();
-            let ix = thread_rng().gen_range(0..len);
-            self.limit[ix] -= 1;
-
-            if self.limit[ix] == 0 {
-                if ix + 1 != len {
-                    self.limit[ix] = self.limit[len - 1];
-                    self.data.swap(ix, len - 1);
-                }
-
-                self.limit.pop();
-                self.data.pop()
-            } else {
-                Some(self.data[ix].clone())
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::sync::bandwidth::BandwidthDirection;
     use actix::System;
     use actix_rt::Arbiter;
     use near_actix_test_utils::run_actix;
@@ -1364,6 +1731,8 @@ mod test {
             "chain_id",
             &SyncConfig::Peers,
             false,
+            false,
+            Arc::new(BandwidthLimiter::unlimited(BandwidthDirection::Download)),
         );
         let mut new_shard_sync = HashMap::new();
 
@@ -1412,6 +1781,7 @@ mod test {
             highest_block_hash: Default::default(),
             tracked_shards: vec![0],
             archival: false,
+            snapshot_host_info: None,
         };
 
         run_actix(async {
@@ -1482,4 +1852,58 @@ mod test {
             System::current().stop()
         });
     }
+
+    #[test]
+    fn test_choose_part_target() {
+        let peer_id = |seed| {
+            PeerId::new(SecretKey::from_seed(near_crypto::KeyType::ED25519, seed).public_key())
+        };
+        let (a, b) = (peer_id("a"), peer_id("b"));
+        let mut capacities: Vec<(PeerId, u64)> = vec![(a.clone(), 1), (b.clone(), 2)];
+        // The target the part was last requested from is avoided while possible.
+        assert_eq!(choose_part_target(&mut capacities, Some(&b)), Some(a.clone()));
+        assert_eq!(choose_part_target(&mut capacities, Some(&b)), Some(b.clone()));
+        assert_eq!(choose_part_target(&mut capacities, Some(&a)), Some(b));
+        // All the targets are busy.
+        assert_eq!(choose_part_target(&mut capacities, None), None);
+    }
+
+    #[test]
+    fn test_state_parts_source_policy() {
+        use StatePartsSource::{External, Peers};
+        let mut policy = StatePartsSourcePolicy::new(External, 2);
+        assert_eq!(policy.source(0, 0), External);
+
+        // A success resets the failures.
+        policy.on_failure(0, 1, External);
+        policy.on_success(0, 2, External);
+        policy.on_failure(0, 3, External);
+        assert_eq!(policy.source(0, 0), External);
+
+        // The range falls back to peers, other ranges and shards don't.
+        policy.on_failure(0, 4, External);
+        assert_eq!(policy.source(0, 0), Peers);
+        assert_eq!(policy.source(0, STATE_PARTS_SOURCE_RANGE - 1), Peers);
+        assert_eq!(policy.source(0, STATE_PARTS_SOURCE_RANGE), External);
+        assert_eq!(policy.source(1, 0), External);
+
+        // Late failures of the previous source don't count.
+        policy.on_failure(0, 5, External);
+        policy.on_failure(0, 6, Peers);
+        assert_eq!(policy.source(0, 0), Peers);
+        policy.on_failure(0, 7, Peers);
+        assert_eq!(policy.source(0, 0), External);
+
+        policy.on_failure(0, 0, External);
+        policy.on_failure(0, 0, External);
+        policy.reset_shard(0);
+        assert_eq!(policy.source(0, 0), External);
+
+        // Without a fallback, the preferred source is always used.
+        let mut policy = StatePartsSourcePolicy::new(Peers, 0);
+        for _ in 0..10 {
+            policy.on_failure(0, 0, Peers);
+        }
+        assert_eq!(policy.source(0, 0), Peers);
+    }
 }
@@ -1,20 +1,38 @@
+use crate::metrics;
 use crate::ClientActor;
 use borsh::BorshSerialize;
 use near_chain::chain::{
     do_apply_chunks, ApplyStatePartsRequest, ApplyStatePartsResponse, BlockCatchUpRequest,
-    BlockCatchUpResponse,
+    BlockCatchUpResponse, SyncJobProgress,
 };
-use near_chain::resharding::StateSplitRequest;
+use near_chain::resharding::{ReshardingCancellation, StateSplitRequest};
 use near_chain::Chain;
+use near_chain_configs::SyncJobsConfig;
+use near_client_primitives::types::DrainSyncJobs;
 use near_o11y::{handler_debug_span, OpenTelemetrySpanExt, WithSpanContext, WithSpanContextExt};
 use near_performance_metrics_macros::perf;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::state_part::PartId;
 use near_primitives::state_sync::StatePartKey;
 use near_primitives::types::ShardId;
-use near_store::DBCol;
+use near_primitives::views::SyncJobProgressView;
+use near_store::{DBCol, Store};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
+/// How often long running jobs report their progress to the client.
+const PROGRESS_REPORT_PERIOD: Duration = Duration::from_secs(5);
+
+/// Runs the jobs of state sync, catchup and resharding on its own pool of
+/// threads, so that the jobs neither block each other nor the actor. Jobs are
+/// queued by their kind and started in the order of priority of the kinds,
+/// as long as there is a free thread and the kind has fewer jobs running than
+/// its concurrency limit.
 pub(crate) struct SyncJobsActor {
-    pub(crate) client_addr: actix::Addr<ClientActor>,
+    pool: Arc<SyncJobPool>,
 }
 
 pub(crate) fn create_sync_job_scheduler<M>(address: actix::Addr<SyncJobsActor>) -> Box<dyn Fn(M)>
@@ -37,49 +55,523 @@ where
     })
 }
 
-impl SyncJobsActor {
-    pub(crate) const MAILBOX_CAPACITY: usize = 100;
+/// Kinds of sync jobs, from the highest priority to the lowest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, strum::IntoStaticStr)]
+enum SyncJobKind {
+    BlockCatchUp,
+    ApplyStateParts,
+    StateSplit,
+}
 
-    fn apply_parts(
-        &mut self,
+enum SyncJob {
+    BlockCatchUp(BlockCatchUpRequest),
+    ApplyStateParts(ApplyStatePartsRequest),
+    StateSplit(StateSplitRequest),
+}
+
+impl SyncJob {
+    fn kind(&self) -> SyncJobKind {
+        match self {
+            SyncJob::BlockCatchUp(_) => SyncJobKind::BlockCatchUp,
+            SyncJob::ApplyStateParts(_) => SyncJobKind::ApplyStateParts,
+            SyncJob::StateSplit(_) => SyncJobKind::StateSplit,
+        }
+    }
+
+    /// Whether the job runs before the other jobs of its kind.
+    fn priority(&self) -> bool {
+        match self {
+            SyncJob::BlockCatchUp(msg) => msg.priority,
+            SyncJob::ApplyStateParts(msg) => msg.priority,
+            SyncJob::StateSplit(_) => false,
+        }
+    }
+
+    fn run(self, client_addr: &actix::Addr<ClientActor>, pool: &SyncJobPool) {
+        match self {
+            SyncJob::BlockCatchUp(msg) => {
+                tracing::debug!(target: "client", ?msg);
+                let results = do_apply_chunks(msg.block_hash, msg.block_height, msg.work);
+                client_addr.do_send(
+                    BlockCatchUpResponse {
+                        sync_hash: msg.sync_hash,
+                        block_hash: msg.block_hash,
+                        results,
+                    }
+                    .with_span_context(),
+                );
+            }
+            SyncJob::ApplyStateParts(msg) => {
+                let result = match msg.part_id {
+                    Some(part_id) => pool.apply_streamed_part(&msg, part_id),
+                    None => {
+                        let reporter = ProgressReporter::new(
+                            client_addr,
+                            msg.sync_hash,
+                            msg.shard_uid.shard_id as ShardId,
+                        );
+                        clear_flat_state(&msg).and_then(|()| {
+                            apply_parts(
+                                &msg,
+                                pool.config.apply_state_parts_parallelism,
+                                &pool.draining,
+                                &|progress| reporter.report(progress),
+                            )
+                        })
+                    }
+                };
+                client_addr.do_send(
+                    ApplyStatePartsResponse {
+                        apply_result: result,
+                        shard_id: msg.shard_uid.shard_id as ShardId,
+                        part_id: msg.part_id,
+                        sync_hash: msg.sync_hash,
+                    }
+                    .with_span_context(),
+                );
+            }
+            SyncJob::StateSplit(msg) => {
+                tracing::debug!(target: "client", ?msg);
+                let reporter = ProgressReporter::new(
+                    client_addr,
+                    msg.sync_hash,
+                    msg.shard_uid.shard_id as ShardId,
+                );
+                let response =
+                    Chain::build_state_for_split_shards(msg, &|progress| reporter.report(progress));
+                client_addr.do_send(response.with_span_context());
+            }
+        }
+    }
+}
+
+/// Applies the state parts on `parallelism` threads. Parts are independent of
+/// each other, so a failed part doesn't stop the others, and the errors of all
+/// failed parts are returned together. Parts which don't match the state root
+/// take precedence, as downloading them again fixes the state sync. Once
+/// `draining` is set, the threads stop after the parts they are applying.
+fn apply_parts(
+    msg: &ApplyStatePartsRequest,
+    parallelism: usize,
+    draining: &AtomicBool,
+    on_progress: &(dyn Fn(SyncJobProgressView) + Sync),
+) -> Result<(), near_chain_primitives::error::Error> {
+    let span = tracing::debug_span!(target: "client", "apply_parts", num_parts = msg.num_parts);
+    let _guard = span.enter();
+    let store = msg.runtime_adapter.store();
+
+    let next_part_id = AtomicU64::new(0);
+    let num_applied = AtomicU64::new(0);
+    let bytes_written = AtomicU64::new(0);
+    let failed_parts = Mutex::new(vec![]);
+    let num_threads = parallelism.clamp(1, msg.num_parts.max(1) as usize);
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| {
+                let _guard = span.enter();
+                loop {
+                    if draining.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let part_id = next_part_id.fetch_add(1, Ordering::Relaxed);
+                    if part_id >= msg.num_parts {
+                        break;
+                    }
+                    match apply_part(msg, &store, part_id) {
+                        Ok(part_size) => on_progress(SyncJobProgressView {
+                            done: num_applied.fetch_add(1, Ordering::Relaxed) + 1,
+                            total: msg.num_parts,
+                            bytes_written: bytes_written.fetch_add(part_size, Ordering::Relaxed)
+                                + part_size,
+                        }),
+                        Err(err) => {
+                            tracing::debug!(target: "client", part_id, ?err, "Failed to apply state part");
+                            failed_parts.lock().unwrap().push((part_id, err));
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let num_applied = num_applied.into_inner();
+    let mut failed_parts = failed_parts.into_inner().unwrap();
+    if failed_parts.is_empty() {
+        if num_applied < msg.num_parts {
+            tracing::info!(target: "client", shard_uid = ?msg.shard_uid, num_applied, "Applying state parts interrupted by draining the sync jobs");
+            return Err(near_chain_primitives::error::Error::Other(
+                "Applying state parts was interrupted".to_string(),
+            ));
+        }
+        return Ok(());
+    }
+    failed_parts.sort_by_key(|(part_id, _)| *part_id);
+    let (invalid_parts, failed_parts): (Vec<_>, Vec<_>) =
+        failed_parts.into_iter().partition(|(_, err)| {
+            matches!(err, near_chain_primitives::error::Error::InvalidStateParts(_))
+        });
+    if !invalid_parts.is_empty() {
+        if !failed_parts.is_empty() {
+            tracing::error!(target: "client", shard_uid = ?msg.shard_uid, ?failed_parts, "Failed to apply state parts");
+        }
+        let part_ids = invalid_parts.into_iter().map(|(part_id, _)| part_id).collect();
+        return Err(near_chain_primitives::error::Error::InvalidStateParts(part_ids));
+    }
+    let failed_parts =
+        failed_parts.into_iter().map(|(part_id, err)| (part_id, err.to_string())).collect();
+    Err(near_chain_primitives::error::Error::StatePartsApplyFailed(failed_parts))
+}
+
+/// Applies a single state part and returns its size.
+fn apply_part(
+    msg: &ApplyStatePartsRequest,
+    store: &Store,
+    part_id: u64,
+) -> Result<u64, near_chain_primitives::error::Error> {
+    let shard_id = msg.shard_uid.shard_id as ShardId;
+    let key = StatePartKey(msg.sync_hash, shard_id, part_id).try_to_vec()?;
+    let part = store.get(DBCol::StateParts, &key)?.ok_or_else(|| {
+        near_chain_primitives::error::Error::DBNotFoundErr(format!(
+            "state part {part_id} of shard {shard_id}"
+        ))
+    })?;
+
+    // The part was validated when it was downloaded, but it's checked again,
+    // as applying a part which doesn't match the state root corrupts the state.
+    let part_id = PartId::new(part_id, msg.num_parts);
+    if !msg.runtime_adapter.validate_state_part(&msg.state_root, part_id, &part) {
+        tracing::warn!(target: "client", shard_uid = ?msg.shard_uid, part_id = part_id.idx, "State part doesn't match the state root");
+        return Err(near_chain_primitives::error::Error::InvalidStateParts(vec![part_id.idx]));
+    }
+
+    msg.runtime_adapter.apply_state_part(
+        shard_id,
+        &msg.state_root,
+        part_id,
+        &part,
+        &msg.epoch_id,
+    )?;
+    Ok(part.len() as u64)
+}
+
+/// Sends the progress of a job to the client, at most once per
+/// `PROGRESS_REPORT_PERIOD` so that frequent updates don't flood the client.
+struct ProgressReporter<'a> {
+    client_addr: &'a actix::Addr<ClientActor>,
+    sync_hash: CryptoHash,
+    shard_id: ShardId,
+    last_report: Mutex<Instant>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(
+        client_addr: &'a actix::Addr<ClientActor>,
+        sync_hash: CryptoHash,
+        shard_id: ShardId,
+    ) -> Self {
+        Self { client_addr, sync_hash, shard_id, last_report: Mutex::new(Instant::now()) }
+    }
+
+    fn report(&self, progress: SyncJobProgressView) {
+        let mut last_report = self.last_report.lock().unwrap();
+        if last_report.elapsed() < PROGRESS_REPORT_PERIOD {
+            return;
+        }
+        *last_report = Instant::now();
+        self.client_addr.do_send(
+            SyncJobProgress { sync_hash: self.sync_hash, shard_id: self.shard_id, progress }
+                .with_span_context(),
+        );
+    }
+}
+
+/// Clears flat storage before applying state parts.
+fn clear_flat_state(
+    msg: &ApplyStatePartsRequest,
+) -> Result<(), near_chain_primitives::error::Error> {
+    let _span = tracing::debug_span!(target: "client", "clear_flat_state").entered();
+    if msg
+        .runtime_adapter
+        .get_flat_storage_manager()
+        .remove_flat_storage_for_shard(msg.shard_uid)?
+    {
+        tracing::debug!(target: "client", shard_uid = ?msg.shard_uid, "Deleted all Flat State");
+    } else {
+        // Can't panic here, because that breaks many KvRuntime tests.
+        tracing::error!(target: "client", shard_uid = ?msg.shard_uid, "Failed to delete Flat State, but proceeding with applying state parts.");
+    }
+    Ok(())
+}
+
+/// State parts of a shard applied one by one, as they are downloaded.
+struct StreamedStateParts {
+    flat_state_cleared: bool,
+    /// Bitmap of the applied parts.
+    applied: Vec<bool>,
+    num_applied: u64,
+}
+
+/// Jobs waiting to run, by kind, and the number of running jobs of every
+/// kind. Jobs with priority are queued before the other jobs of their kind.
+struct SyncJobQueue<J> {
+    limits: BTreeMap<SyncJobKind, usize>,
+    queued: BTreeMap<SyncJobKind, VecDeque<J>>,
+    /// Number of jobs with priority at the front of the queue of every kind.
+    queued_priority: BTreeMap<SyncJobKind, usize>,
+    running: BTreeMap<SyncJobKind, usize>,
+}
+
+impl<J> SyncJobQueue<J> {
+    fn new(config: &SyncJobsConfig) -> Self {
+        let limits = [
+            (SyncJobKind::BlockCatchUp, config.max_concurrent_block_catchups),
+            (SyncJobKind::ApplyStateParts, config.max_concurrent_apply_state_parts),
+            (SyncJobKind::StateSplit, config.max_concurrent_state_splits),
+        ];
+        Self {
+            limits: limits.into_iter().collect(),
+            queued: BTreeMap::new(),
+            queued_priority: BTreeMap::new(),
+            running: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, kind: SyncJobKind, job: J, priority: bool) {
+        let queued = self.queued.entry(kind).or_default();
+        if priority {
+            let queued_priority = self.queued_priority.entry(kind).or_default();
+            queued.insert(*queued_priority, job);
+            *queued_priority += 1;
+        } else {
+            queued.push_back(job);
+        }
+        metrics::SYNC_JOBS_QUEUED.with_label_values(&[kind.into()]).set(queued.len() as i64);
+    }
+
+    /// Takes the job to run next, which is the oldest job of the kind with
+    /// the highest priority among the kinds below their concurrency limit,
+    /// preferring the jobs with priority.
+    fn start_next(&mut self) -> Option<(SyncJobKind, J)> {
+        for (kind, queued) in &mut self.queued {
+            let running = self.running.entry(*kind).or_default();
+            if *running >= self.limits[kind] {
+                continue;
+            }
+            let Some(job) = queued.pop_front() else { continue };
+            if let Some(queued_priority) = self.queued_priority.get_mut(kind) {
+                *queued_priority = queued_priority.saturating_sub(1);
+            }
+            *running += 1;
+            metrics::SYNC_JOBS_QUEUED.with_label_values(&[(*kind).into()]).set(queued.len() as i64);
+            metrics::SYNC_JOBS_RUNNING.with_label_values(&[(*kind).into()]).set(*running as i64);
+            return Some((*kind, job));
+        }
+        None
+    }
+
+    fn num_running(&self) -> usize {
+        self.running.values().sum()
+    }
+
+    fn finish(&mut self, kind: SyncJobKind) {
+        let running = self.running.get_mut(&kind).unwrap();
+        *running -= 1;
+        metrics::SYNC_JOBS_RUNNING.with_label_values(&[kind.into()]).set(*running as i64);
+    }
+}
+
+/// The threads running the sync jobs, which take the jobs from the shared
+/// queue.
+struct SyncJobPool {
+    config: SyncJobsConfig,
+    state: Mutex<SyncJobPoolState>,
+    /// Shards whose state parts are applied as they are downloaded, by sync
+    /// hash and shard. A shard is removed once all of its parts are applied.
+    /// If state sync restarts the download, parts which were applied before
+    /// aren't applied again, and flat storage isn't cleared again.
+    streamed_parts: Mutex<HashMap<(CryptoHash, ShardUId), StreamedStateParts>>,
+    /// Set once the pool is drained, which makes the running jobs stop after
+    /// their current batch.
+    draining: AtomicBool,
+    /// Notified when a job is queued or finished, either of which may allow
+    /// another job to start, and when the pool is stopped.
+    changed: Condvar,
+}
+
+struct SyncJobPoolState {
+    queue: SyncJobQueue<(tracing::Span, SyncJob)>,
+    stopped: bool,
+    /// Cancellations of the running state splits, by the worker running
+    /// them, which stop the splits at the next batch when the pool is drained.
+    running_splits: HashMap<usize, ReshardingCancellation>,
+}
+
+impl SyncJobPool {
+    fn new(config: &SyncJobsConfig) -> Self {
+        let state = SyncJobPoolState {
+            queue: SyncJobQueue::new(config),
+            stopped: false,
+            running_splits: HashMap::new(),
+        };
+        Self {
+            config: *config,
+            state: Mutex::new(state),
+            streamed_parts: Mutex::new(HashMap::new()),
+            draining: AtomicBool::new(false),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn apply_streamed_part(
+        &self,
         msg: &ApplyStatePartsRequest,
+        part_id: u64,
     ) -> Result<(), near_chain_primitives::error::Error> {
-        let _span = tracing::debug_span!(target: "client", "apply_parts").entered();
-        let store = msg.runtime_adapter.store();
+        let key = (msg.sync_hash, msg.shard_uid);
+        {
+            // Flat storage is cleared while holding the lock, so that other
+            // parts of the shard wait for it.
+            let mut streamed_parts = self.streamed_parts.lock().unwrap();
+            let parts = streamed_parts.entry(key).or_insert_with(|| StreamedStateParts {
+                flat_state_cleared: false,
+                applied: vec![false; msg.num_parts as usize],
+                num_applied: 0,
+            });
+            if parts.applied[part_id as usize] {
+                tracing::debug!(target: "client", shard_uid = ?msg.shard_uid, part_id, "State part is already applied");
+                return Ok(());
+            }
+            if !parts.flat_state_cleared {
+                clear_flat_state(msg)?;
+                parts.flat_state_cleared = true;
+            }
+        }
 
-        let shard_id = msg.shard_uid.shard_id as ShardId;
-        for part_id in 0..msg.num_parts {
-            let key = StatePartKey(msg.sync_hash, shard_id, part_id).try_to_vec()?;
-            let part = store.get(DBCol::StateParts, &key)?.unwrap();
+        apply_part(msg, &msg.runtime_adapter.store(), part_id)?;
 
-            msg.runtime_adapter.apply_state_part(
-                shard_id,
-                &msg.state_root,
-                PartId::new(part_id, msg.num_parts),
-                &part,
-                &msg.epoch_id,
-            )?;
+        let mut streamed_parts = self.streamed_parts.lock().unwrap();
+        let parts = streamed_parts.get_mut(&key).unwrap();
+        if !std::mem::replace(&mut parts.applied[part_id as usize], true) {
+            parts.num_applied += 1;
+        }
+        if parts.num_applied == msg.num_parts {
+            streamed_parts.remove(&key);
         }
-
         Ok(())
     }
 
-    /// Clears flat storage before applying state parts.
-    /// Returns whether the flat storage state was cleared.
-    fn clear_flat_state(
-        &mut self,
-        msg: &ApplyStatePartsRequest,
-    ) -> Result<bool, near_chain_primitives::error::Error> {
-        let _span = tracing::debug_span!(target: "client", "clear_flat_state").entered();
-        Ok(msg
-            .runtime_adapter
-            .get_flat_storage_manager()
-            .remove_flat_storage_for_shard(msg.shard_uid)?)
+    fn push(&self, span: tracing::Span, job: SyncJob) {
+        let (kind, priority) = (job.kind(), job.priority());
+        self.state.lock().unwrap().queue.push(kind, (span, job), priority);
+        self.changed.notify_all();
+    }
+
+    /// Makes the threads exit instead of starting more jobs. Jobs which are
+    /// already running aren't interrupted.
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+
+    /// Stops the pool and makes the running jobs stop after their current
+    /// batch: state splits are cancelled, after saving their progress, and
+    /// state parts which weren't started yet aren't applied. Waits for up to
+    /// `timeout` for the running jobs and returns whether they all finished.
+    fn drain(&self, timeout: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        self.draining.store(true, Ordering::Relaxed);
+        for cancellation in state.running_splits.values() {
+            cancellation.cancel();
+        }
+        self.changed.notify_all();
+        let (state, result) = self
+            .changed
+            .wait_timeout_while(state, timeout, |state| state.queue.num_running() > 0)
+            .unwrap();
+        if result.timed_out() {
+            tracing::warn!(target: "client", num_running = state.queue.num_running(), "Sync jobs didn't finish in time");
+            return false;
+        }
+        true
+    }
+
+    fn run_worker(&self, worker_id: usize, client_addr: &actix::Addr<ClientActor>) {
+        loop {
+            let mut state = self.state.lock().unwrap();
+            let (kind, (span, job)) = loop {
+                if state.stopped {
+                    return;
+                }
+                if let Some(next) = state.queue.start_next() {
+                    break next;
+                }
+                state = self.changed.wait(state).unwrap();
+            };
+            if let SyncJob::StateSplit(msg) = &job {
+                state.running_splits.insert(worker_id, msg.cancellation.clone());
+            }
+            drop(state);
+            let span = span.entered();
+            job.run(client_addr, self);
+            drop(span);
+            let mut state = self.state.lock().unwrap();
+            state.running_splits.remove(&worker_id);
+            state.queue.finish(kind);
+            drop(state);
+            self.changed.notify_all();
+        }
+    }
+}
+
+impl SyncJobsActor {
+    pub(crate) const MAILBOX_CAPACITY: usize = 100;
+
+    pub(crate) fn new(
+        client_addr: actix::Addr<ClientActor>,
+        config: &SyncJobsConfig,
+    ) -> std::io::Result<Self> {
+        let pool = Arc::new(SyncJobPool::new(config));
+        for i in 0..config.num_threads {
+            let pool = pool.clone();
+            let client_addr = client_addr.clone();
+            std::thread::Builder::new()
+                .name(format!("sync_jobs_{i}"))
+                .spawn(move || pool.run_worker(i, &client_addr))?;
+        }
+        Ok(Self { pool })
+    }
+
+    // The span of the handler is exited here and entered again by the thread
+    // running the job.
+    fn schedule(&self, span: tracing::span::EnteredSpan, job: SyncJob) {
+        self.pool.push(span.exit(), job);
     }
 }
 
 impl actix::Actor for SyncJobsActor {
     type Context = actix::Context<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.pool.stop();
+    }
+}
+
+impl actix::Handler<WithSpanContext<DrainSyncJobs>> for SyncJobsActor {
+    type Result = bool;
+
+    // Blocks the actor until the jobs finish, which is fine as it's only used
+    // on shutdown.
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<DrainSyncJobs>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::info!(target: "client", timeout = ?msg.timeout, "Draining the sync jobs");
+        self.pool.drain(msg.timeout)
+    }
 }
 
 impl actix::Handler<WithSpanContext<ApplyStatePartsRequest>> for SyncJobsActor {
@@ -91,34 +583,8 @@ impl actix::Handler<WithSpanContext<ApplyStatePartsRequest>> for SyncJobsActor {
         msg: WithSpanContext<ApplyStatePartsRequest>,
         _: &mut Self::Context,
     ) -> Self::Result {
-        let (_span, msg) = handler_debug_span!(target: "client", msg);
-        let shard_id = msg.shard_uid.shard_id as ShardId;
-        match self.clear_flat_state(&msg) {
-            Err(err) => {
-                self.client_addr.do_send(
-                    ApplyStatePartsResponse {
-                        apply_result: Err(err),
-                        shard_id,
-                        sync_hash: msg.sync_hash,
-                    }
-                    .with_span_context(),
-                );
-                return;
-            }
-            Ok(false) => {
-                // Can't panic here, because that breaks many KvRuntime tests.
-                tracing::error!(target: "client", shard_uid = ?msg.shard_uid, "Failed to delete Flat State, but proceeding with applying state parts.");
-            }
-            Ok(true) => {
-                tracing::debug!(target: "client", shard_uid = ?msg.shard_uid, "Deleted all Flat State");
-            }
-        }
-
-        let result = self.apply_parts(&msg);
-        self.client_addr.do_send(
-            ApplyStatePartsResponse { apply_result: result, shard_id, sync_hash: msg.sync_hash }
-                .with_span_context(),
-        );
+        let (span, msg) = handler_debug_span!(target: "client", msg);
+        self.schedule(span, SyncJob::ApplyStateParts(msg));
     }
 }
 
@@ -131,14 +597,8 @@ impl actix::Handler<WithSpanContext<BlockCatchUpRequest>> for SyncJobsActor {
         msg: WithSpanContext<BlockCatchUpRequest>,
         _: &mut Self::Context,
     ) -> Self::Result {
-        let (_span, msg) = handler_debug_span!(target: "client", msg);
-        tracing::debug!(target: "client", ?msg);
-        let results = do_apply_chunks(msg.block_hash, msg.block_height, msg.work);
-
-        self.client_addr.do_send(
-            BlockCatchUpResponse { sync_hash: msg.sync_hash, block_hash: msg.block_hash, results }
-                .with_span_context(),
-        );
+        let (span, msg) = handler_debug_span!(target: "client", msg);
+        self.schedule(span, SyncJob::BlockCatchUp(msg));
     }
 }
 
@@ -151,9 +611,81 @@ impl actix::Handler<WithSpanContext<StateSplitRequest>> for SyncJobsActor {
         msg: WithSpanContext<StateSplitRequest>,
         _: &mut Self::Context,
     ) -> Self::Result {
-        let (_span, msg) = handler_debug_span!(target: "client", msg);
-        tracing::debug!(target: "client", ?msg);
-        let response = Chain::build_state_for_split_shards(msg);
-        self.client_addr.do_send(response.with_span_context());
+        let (span, msg) = handler_debug_span!(target: "client", msg);
+        self.schedule(span, SyncJob::StateSplit(msg));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyncJobKind, SyncJobQueue};
+    use near_chain_configs::SyncJobsConfig;
+
+    #[test]
+    fn test_sync_job_queue() {
+        let config = SyncJobsConfig {
+            num_threads: 4,
+            max_concurrent_block_catchups: 2,
+            max_concurrent_apply_state_parts: 1,
+            max_concurrent_state_splits: 1,
+            apply_state_parts_parallelism: 1,
+            drain_timeout: std::time::Duration::ZERO,
+        };
+        let mut queue = SyncJobQueue::new(&config);
+        assert_eq!(queue.start_next(), None);
+
+        queue.push(SyncJobKind::StateSplit, 1, false);
+        queue.push(SyncJobKind::StateSplit, 2, false);
+        queue.push(SyncJobKind::ApplyStateParts, 3, false);
+        queue.push(SyncJobKind::BlockCatchUp, 4, false);
+        queue.push(SyncJobKind::BlockCatchUp, 5, false);
+        queue.push(SyncJobKind::BlockCatchUp, 6, false);
+
+        // Higher priority kinds go first, up to their concurrency limit.
+        assert_eq!(queue.start_next(), Some((SyncJobKind::BlockCatchUp, 4)));
+        assert_eq!(queue.start_next(), Some((SyncJobKind::BlockCatchUp, 5)));
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 3)));
+        assert_eq!(queue.start_next(), Some((SyncJobKind::StateSplit, 1)));
+        assert_eq!(queue.start_next(), None);
+
+        // A finished job makes room for the next job of its kind only, while
+        // a running state split doesn't hold back block catchup.
+        queue.finish(SyncJobKind::StateSplit);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::StateSplit, 2)));
+        queue.finish(SyncJobKind::BlockCatchUp);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::BlockCatchUp, 6)));
+        assert_eq!(queue.start_next(), None);
+    }
+
+    #[test]
+    fn test_sync_job_queue_priority() {
+        let config = SyncJobsConfig {
+            num_threads: 4,
+            max_concurrent_block_catchups: 1,
+            max_concurrent_apply_state_parts: 1,
+            max_concurrent_state_splits: 1,
+            apply_state_parts_parallelism: 1,
+            drain_timeout: std::time::Duration::ZERO,
+        };
+        let mut queue = SyncJobQueue::new(&config);
+        queue.push(SyncJobKind::ApplyStateParts, 1, false);
+        queue.push(SyncJobKind::ApplyStateParts, 2, true);
+        queue.push(SyncJobKind::ApplyStateParts, 3, false);
+        queue.push(SyncJobKind::ApplyStateParts, 4, true);
+
+        // Jobs with priority go first, in the order they were queued.
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 2)));
+        queue.finish(SyncJobKind::ApplyStateParts);
+        queue.push(SyncJobKind::ApplyStateParts, 5, true);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 4)));
+        queue.finish(SyncJobKind::ApplyStateParts);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 5)));
+        queue.finish(SyncJobKind::ApplyStateParts);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 1)));
+        queue.finish(SyncJobKind::ApplyStateParts);
+        queue.push(SyncJobKind::ApplyStateParts, 6, true);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 6)));
+        queue.finish(SyncJobKind::ApplyStateParts);
+        assert_eq!(queue.start_next(), Some((SyncJobKind::ApplyStateParts, 3)));
     }
 }
@@ -254,7 +254,10 @@ pub fn run_catchup(
             catchup_done = false;
         }
         for msg in state_split_messages.write().unwrap().drain(..) {
-            let response = Chain::build_state_for_split_shards(msg);
+            let response = Chain::build_state_for_split_shards(msg, &|_| {});
+            if let Err(near_chain::Error::ReshardingCancelled) = response.new_state_roots {
+                continue;
+            }
             if let Some((sync, _, _)) = client.catchup_state_syncs.get_mut(&response.sync_hash) {
                 // We are doing catchup
                 sync.set_split_result(response.shard_id, response.new_state_roots);
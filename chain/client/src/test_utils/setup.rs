@@ -20,7 +20,7 @@ use near_chain::state_snapshot_actor::MakeSnapshotCallback;
 use near_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
 use near_chain::types::{ChainConfig, RuntimeAdapter};
 use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
-use near_chain_configs::ClientConfig;
+use near_chain_configs::{ClientConfig, MutableConfigValue, ReshardingConfig};
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::client::ShardsManagerResponse;
 use near_chunks::shards_manager_actor::start_shards_manager;
@@ -114,6 +114,11 @@ pub fn setup(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_snapshot_every_n_blocks: None,
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
         },
         None,
     )
@@ -144,6 +149,7 @@ pub fn setup(
         network_adapter.clone(),
         config.clone(),
         adv.clone(),
+        None,
     );
 
     let (shards_manager_addr, _) = start_shards_manager(
@@ -169,6 +175,7 @@ pub fn setup(
         enable_doomslug,
         TEST_SEED,
         None,
+        None,
     )
     .unwrap();
     let client_actor = ClientActor::new(
@@ -236,6 +243,11 @@ pub fn setup_only_view(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_snapshot_every_n_blocks: None,
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
         },
         None,
     )
@@ -265,6 +277,7 @@ pub fn setup_only_view(
         network_adapter,
         config,
         adv,
+        None,
     )
 }
 
@@ -512,6 +525,7 @@ pub fn setup_mock_all_validators(
                                         }),
                                         tracked_shards: vec![0, 1, 2, 3],
                                         archival: true,
+                                        snapshot_host_info: None,
                                     },
                                 },
                                 received_bytes_per_sec: 0,
@@ -521,6 +535,7 @@ pub fn setup_mock_all_validators(
                                 connection_established_time: near_async::time::Instant::now(),
                                 peer_type: PeerType::Outbound,
                                 nonce: 3,
+                                reputation: 0.,
                             })
                             .collect();
                         let peers2 = peers
@@ -649,7 +664,7 @@ pub fn setup_mock_all_validators(
                                 }
                             }
                         }
-                        NetworkRequests::BlockHeadersRequest { hashes, peer_id } => {
+                        NetworkRequests::BlockHeadersRequest { hashes, max_headers, peer_id } => {
                             for (i, peer_info) in key_pairs.iter().enumerate() {
                                 let peer_id = peer_id.clone();
                                 if peer_info.id == peer_id {
@@ -658,7 +673,7 @@ pub fn setup_mock_all_validators(
                                         connectors1[i]
                                             .view_client_actor
                                             .send(
-                                                BlockHeadersRequest(hashes.clone())
+                                                BlockHeadersRequest(hashes.clone(), *max_headers)
                                                     .with_span_context(),
                                             )
                                             .then(move |response| {
@@ -822,6 +837,8 @@ pub fn setup_mock_all_validators(
                         NetworkRequests::ForwardTx(_, _)
                         | NetworkRequests::BanPeer { .. }
                         | NetworkRequests::TxStatus(_, _, _)
+                        | NetworkRequests::SnapshotHostInfo(_)
+                        | NetworkRequests::EpochSyncRequest { .. }
                         | NetworkRequests::Challenge(_) => {}
                     };
                 }
@@ -940,6 +957,7 @@ pub fn setup_client_with_runtime(
         enable_doomslug,
         rng_seed,
         make_state_snapshot_callback,
+        None,
     )
     .unwrap();
     client.sync_status = SyncStatus::NoSync;
@@ -1004,6 +1022,11 @@ pub fn setup_synchronous_shards_manager(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_snapshot_every_n_blocks: None,
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
         }, // irrelevant
         None,
     )
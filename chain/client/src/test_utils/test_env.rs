@@ -350,6 +350,7 @@ impl TestEnv {
                     account_id,
                     prefix: vec![].into(),
                     include_proof: false,
+                    pagination: None,
                 },
             )
             .unwrap();
@@ -472,6 +473,9 @@ impl TestEnv {
             | ProcessTxResponse::ValidTx => (),
             ProcessTxResponse::InvalidTx(e) => return Err(e),
             ProcessTxResponse::DoesNotTrackShard => panic!("test setup is buggy"),
+            ProcessTxResponse::RejectedByPool(reason) => {
+                panic!("transaction rejected by the pool: {}", reason)
+            }
         }
         let max_iters = 100;
         let tip = self.clients[0].chain.head().unwrap();
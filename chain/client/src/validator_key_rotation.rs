@@ -0,0 +1,223 @@
+//! Rotation of the validator key to a successor key without a restart.
+//!
+//! The validators of an epoch are selected from the stake proposals included
+//! two epochs before it, so the stake proposal with the successor key is
+//! submitted once the next block belongs to the epoch two epochs before the
+//! rotation.  The proposal stakes the currently locked balance, which only
+//! changes the key of the validator.  The node switches to the successor key
+//! once the next block belongs to the rotation epoch or a later one, and the
+//! successor key is the key of the validator in that epoch.  If the proposal
+//! didn't make it in time, the node keeps signing with the current key, and
+//! switches as soon as an epoch selects the successor key.
+//!
+//! The TIER1 account data of the network and the telemetry are still signed
+//! with the key the node was started with, so the successor key should also
+//! be configured as the validator key before the next restart.
+
+use near_chain_configs::ValidatorKeyRotationConfig;
+use near_chain_primitives::Error;
+use near_crypto::{InMemorySigner, PublicKey, Signer};
+use near_primitives::account::AccessKey;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, SignedTransaction, StakeAction, Transaction};
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochHeight};
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// What the client should do about the rotation after a new head.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum KeyRotationAction {
+    None,
+    /// Submit the stake proposal with the successor key.
+    ProposeStake,
+    /// Start signing with the successor key, if it's the key of the validator
+    /// in the epoch of the next block.
+    Rotate,
+}
+
+pub(crate) struct ValidatorKeyRotation {
+    epoch_height: EpochHeight,
+    successor: Arc<dyn ValidatorSigner>,
+    stake_proposal_signer: Option<InMemorySigner>,
+    proposal_submitted: bool,
+    rotated: bool,
+    /// Epoch height the rotation was last reported as postponed at, so that
+    /// it's reported once per epoch.
+    last_postponed_epoch_height: Option<EpochHeight>,
+}
+
+impl ValidatorKeyRotation {
+    /// Loads the keys configured for the rotation of the key of `account_id`.
+    pub(crate) fn new(
+        config: &ValidatorKeyRotationConfig,
+        account_id: &AccountId,
+    ) -> Result<Self, Error> {
+        let successor =
+            InMemoryValidatorSigner::from_file(&config.successor_key_file).map_err(|err| {
+                Error::Other(format!(
+                    "Failed to load successor validator key from {}: {err}",
+                    config.successor_key_file.display()
+                ))
+            })?;
+        if successor.validator_id() != account_id {
+            return Err(Error::Other(format!(
+                "Successor validator key is of {}, but the validator is {account_id}",
+                successor.validator_id()
+            )));
+        }
+        let stake_proposal_signer = config
+            .stake_proposal_key_file
+            .as_ref()
+            .map(|path| {
+                InMemorySigner::from_file(path).map_err(|err| {
+                    Error::Other(format!(
+                        "Failed to load stake proposal key from {}: {err}",
+                        path.display()
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            epoch_height: config.epoch_height,
+            successor: Arc::new(successor),
+            stake_proposal_signer,
+            proposal_submitted: false,
+            rotated: false,
+            last_postponed_epoch_height: None,
+        })
+    }
+
+    pub(crate) fn successor(&self) -> &Arc<dyn ValidatorSigner> {
+        &self.successor
+    }
+
+    /// Called on a new head with the height of the epoch of the next block.
+    pub(crate) fn on_new_head(&mut self, next_epoch_height: EpochHeight) -> KeyRotationAction {
+        if self.rotated {
+            KeyRotationAction::None
+        } else if next_epoch_height >= self.epoch_height {
+            KeyRotationAction::Rotate
+        } else if !self.proposal_submitted && next_epoch_height + 2 >= self.epoch_height {
+            // Only one attempt is made, because a proposal which doesn't get
+            // included is most likely to fail again.
+            self.proposal_submitted = true;
+            KeyRotationAction::ProposeStake
+        } else {
+            KeyRotationAction::None
+        }
+    }
+
+    /// Builds the transaction staking `stake` with the successor key, or
+    /// returns `None` if there's no key configured to sign it.
+    pub(crate) fn stake_proposal(
+        &self,
+        stake: Balance,
+        height: BlockHeight,
+        block_hash: CryptoHash,
+    ) -> Option<SignedTransaction> {
+        let Some(signer) = &self.stake_proposal_signer else {
+            warn!(
+                target: "client",
+                epoch_height = self.epoch_height,
+                public_key = ?self.successor.public_key(),
+                "No stake proposal key configured, the stake proposal with the successor validator key has to be submitted manually");
+            return None;
+        };
+        let account_id = self.successor.validator_id().clone();
+        let transaction = Transaction {
+            signer_id: account_id.clone(),
+            public_key: signer.public_key(),
+            // The highest nonce allowed at the height, so that the nonce is
+            // above the nonces of the transactions submitted manually.
+            nonce: height * AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER - 1,
+            receiver_id: account_id,
+            block_hash,
+            actions: vec![Action::Stake(Box::new(StakeAction {
+                stake,
+                public_key: self.successor.public_key(),
+            }))],
+        };
+        let signature = signer.sign(transaction.get_hash_and_size().0.as_ref());
+        Some(SignedTransaction::new(signature, transaction))
+    }
+
+    /// Called with the key of the validator in the epoch of the next block,
+    /// or `None` if the account isn't a validator in that epoch.  Returns the
+    /// signer to switch to.
+    pub(crate) fn try_rotate(
+        &mut self,
+        next_epoch_height: EpochHeight,
+        validator_key: Option<&PublicKey>,
+    ) -> Option<Arc<dyn ValidatorSigner>> {
+        let successor_key = self.successor.public_key();
+        if validator_key.map_or(false, |key| key != &successor_key) {
+            if self.last_postponed_epoch_height != Some(next_epoch_height) {
+                self.last_postponed_epoch_height = Some(next_epoch_height);
+                warn!(
+                    target: "client",
+                    next_epoch_height,
+                    ?validator_key,
+                    ?successor_key,
+                    "The successor validator key isn't selected for the epoch, postponing the rotation");
+            }
+            return None;
+        }
+        info!(target: "client", next_epoch_height, ?successor_key, "Switching to the successor validator key");
+        self.rotated = true;
+        Some(self.successor.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyRotationAction, ValidatorKeyRotation};
+    use near_chain_configs::ValidatorKeyRotationConfig;
+    use near_crypto::{InMemorySigner, KeyType, PublicKey, Signer};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::transaction::Action;
+    use near_primitives::types::AccountId;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+
+    #[test]
+    fn test_validator_key_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let account_id: AccountId = "test".parse().unwrap();
+        let successor =
+            InMemoryValidatorSigner::from_seed(account_id.clone(), KeyType::ED25519, "successor");
+        successor.write_to_file(&dir.path().join("successor.json")).unwrap();
+        let account_key =
+            InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, "account");
+        account_key.write_to_file(&dir.path().join("account.json")).unwrap();
+        let config = ValidatorKeyRotationConfig {
+            successor_key_file: dir.path().join("successor.json"),
+            epoch_height: 10,
+            stake_proposal_key_file: Some(dir.path().join("account.json")),
+        };
+        assert!(ValidatorKeyRotation::new(&config, &"other".parse().unwrap()).is_err());
+        let mut rotation = ValidatorKeyRotation::new(&config, &account_id).unwrap();
+
+        assert_eq!(rotation.on_new_head(7), KeyRotationAction::None);
+        assert_eq!(rotation.on_new_head(8), KeyRotationAction::ProposeStake);
+        assert_eq!(rotation.on_new_head(9), KeyRotationAction::None);
+        let proposal = rotation.stake_proposal(100, 5, CryptoHash::default()).unwrap();
+        assert_eq!(proposal.transaction.signer_id, account_id);
+        assert_eq!(proposal.transaction.public_key, account_key.public_key);
+        match &proposal.transaction.actions[..] {
+            [Action::Stake(stake)] => {
+                assert_eq!(stake.stake, 100);
+                assert_eq!(stake.public_key, successor.public_key());
+            }
+            actions => panic!("unexpected actions {actions:?}"),
+        }
+
+        // The rotation waits for an epoch with the successor key.
+        assert_eq!(rotation.on_new_head(10), KeyRotationAction::Rotate);
+        let current_key = PublicKey::empty(KeyType::ED25519);
+        assert!(rotation.try_rotate(10, Some(&current_key)).is_none());
+        assert_eq!(rotation.on_new_head(11), KeyRotationAction::Rotate);
+        let signer = rotation.try_rotate(11, Some(&successor.public_key())).unwrap();
+        assert_eq!(signer.public_key(), successor.public_key());
+        assert_eq!(rotation.on_new_head(12), KeyRotationAction::None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Tracking of which validators approved the blocks at each height.
+//!
+//! Blocks are counted when they become the head, from the approvals included
+//! in them, so the numbers can be verified against the chain.  A block can
+//! contain an approval of each block producer of its epoch, and around epoch
+//! boundaries also of the block producers of the next epoch.  Only the most
+//! recent epochs are kept, and nothing is kept across restarts.
+
+use crate::metrics;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, NumBlocks};
+use near_primitives::views::{EpochParticipationView, ValidatorParticipationView};
+use std::collections::{HashMap, VecDeque};
+
+/// Number of most recent epochs the participation is kept for.
+pub(crate) const PARTICIPATION_EPOCHS_TO_KEEP: usize = 10;
+
+#[derive(Default, Clone, Copy)]
+struct Participation {
+    num_expected_approvals: NumBlocks,
+    num_included_approvals: NumBlocks,
+}
+
+struct EpochParticipation {
+    epoch_id: EpochId,
+    first_height: BlockHeight,
+    last_height: BlockHeight,
+    num_blocks: NumBlocks,
+    validators: HashMap<AccountId, Participation>,
+}
+
+#[derive(Default)]
+pub(crate) struct ValidatorParticipationTracker {
+    /// The most recent epoch last.
+    epochs: VecDeque<EpochParticipation>,
+}
+
+impl ValidatorParticipationTracker {
+    /// Called when a block becomes the head.  `approvals` lists the validators
+    /// which could approve the block, and whether the block contains their
+    /// approval.  Blocks not higher than the last counted block are ignored,
+    /// so that no height is counted twice.
+    pub(crate) fn on_new_head<'a>(
+        &mut self,
+        epoch_id: &EpochId,
+        height: BlockHeight,
+        approvals: impl Iterator<Item = (&'a AccountId, bool)>,
+    ) {
+        if self.epochs.back().map_or(false, |epoch| epoch.last_height >= height) {
+            return;
+        }
+        if self.epochs.back().map_or(true, |epoch| &epoch.epoch_id != epoch_id) {
+            if self.epochs.len() == PARTICIPATION_EPOCHS_TO_KEEP {
+                self.epochs.pop_front();
+            }
+            self.epochs.push_back(EpochParticipation {
+                epoch_id: epoch_id.clone(),
+                first_height: height,
+                last_height: height,
+                num_blocks: 0,
+                validators: HashMap::new(),
+            });
+        }
+        let epoch = self.epochs.back_mut().unwrap();
+        epoch.last_height = height;
+        epoch.num_blocks += 1;
+        for (account_id, included) in approvals {
+            let participation = epoch.validators.entry(account_id.clone()).or_default();
+            participation.num_expected_approvals += 1;
+            metrics::VALIDATOR_APPROVALS_EXPECTED.with_label_values(&[account_id.as_str()]).inc();
+            if included {
+                participation.num_included_approvals += 1;
+                metrics::VALIDATOR_APPROVALS_INCLUDED
+                    .with_label_values(&[account_id.as_str()])
+                    .inc();
+            }
+        }
+    }
+
+    /// Returns the participation in up to `num_epochs` most recent epochs, the
+    /// most recent epoch first.
+    pub(crate) fn get_participation(&self, num_epochs: usize) -> Vec<EpochParticipationView> {
+        self.epochs
+            .iter()
+            .rev()
+            .take(num_epochs)
+            .map(|epoch| {
+                let mut validators: Vec<_> = epoch
+                    .validators
+                    .iter()
+                    .map(|(account_id, participation)| ValidatorParticipationView {
+                        account_id: account_id.clone(),
+                        num_expected_approvals: participation.num_expected_approvals,
+                        num_included_approvals: participation.num_included_approvals,
+                    })
+                    .collect();
+                // Sorts by the ratio of included approvals, without dividing.
+                validators.sort_by(|a, b| {
+                    let a_ratio =
+                        a.num_included_approvals as u128 * b.num_expected_approvals as u128;
+                    let b_ratio =
+                        b.num_included_approvals as u128 * a.num_expected_approvals as u128;
+                    a_ratio.cmp(&b_ratio).then_with(|| a.account_id.cmp(&b.account_id))
+                });
+                EpochParticipationView {
+                    epoch_id: epoch.epoch_id.clone(),
+                    first_height: epoch.first_height,
+                    last_height: epoch.last_height,
+                    num_blocks: epoch.num_blocks,
+                    validators,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ValidatorParticipationTracker, PARTICIPATION_EPOCHS_TO_KEEP};
+    use near_primitives::hash::hash;
+    use near_primitives::types::{AccountId, EpochId};
+
+    #[test]
+    fn test_validator_participation() {
+        let online: AccountId = "online".parse().unwrap();
+        let flaky: AccountId = "flaky".parse().unwrap();
+        let mut tracker = ValidatorParticipationTracker::default();
+        let epoch_id = |i: u64| EpochId(hash(&i.to_le_bytes()));
+
+        for height in 1..=10 {
+            let approvals = [(&online, true), (&flaky, height % 2 == 0)];
+            tracker.on_new_head(&epoch_id(0), height, approvals.into_iter());
+        }
+        // Heights which were already counted are ignored.
+        tracker.on_new_head(&epoch_id(0), 10, [(&flaky, false)].into_iter());
+        tracker.on_new_head(&epoch_id(1), 12, [(&online, false)].into_iter());
+
+        let participation = tracker.get_participation(usize::MAX);
+        assert_eq!(participation.len(), 2);
+        assert_eq!(participation[0].epoch_id, epoch_id(1));
+        assert_eq!(participation[0].num_blocks, 1);
+        assert_eq!(participation[1].epoch_id, epoch_id(0));
+        assert_eq!((participation[1].first_height, participation[1].last_height), (1, 10));
+        assert_eq!(participation[1].num_blocks, 10);
+        let validators = &participation[1].validators;
+        assert_eq!(validators[0].account_id, flaky);
+        assert_eq!(validators[0].num_expected_approvals, 10);
+        assert_eq!(validators[0].num_included_approvals, 5);
+        assert_eq!(validators[1].account_id, online);
+        assert_eq!(validators[1].num_included_approvals, 10);
+        assert_eq!(tracker.get_participation(1).len(), 1);
+
+        // Only the most recent epochs are kept.
+        for i in 2..20 {
+            tracker.on_new_head(&epoch_id(i), 10 * i + 1, [(&online, true)].into_iter());
+        }
+        let participation = tracker.get_participation(usize::MAX);
+        assert_eq!(participation.len(), PARTICIPATION_EPOCHS_TO_KEEP);
+        assert_eq!(participation[0].epoch_id, epoch_id(19));
+    }
+}
@@ -2,9 +2,10 @@
 //! Useful for querying from RPC.
 
 use crate::adapter::{
-    AnnounceAccountRequest, BlockHeadersRequest, BlockRequest, StateRequestHeader,
-    StateRequestPart, StateResponse, TxStatusRequest, TxStatusResponse,
+    AnnounceAccountRequest, BlockHeadersRequest, BlockRequest, EpochSyncRequest,
+    StateRequestHeader, StateRequestPart, StateResponse, TxStatusRequest, TxStatusResponse,
 };
+use crate::sync::bandwidth::{BandwidthDirection, BandwidthLimiter};
 use crate::{
     metrics, sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
     GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
@@ -20,12 +21,14 @@ use near_chain_primitives::error::EpochErrorResultToChainError;
 use near_client_primitives::types::{
     Error, GetBlock, GetBlockError, GetBlockProof, GetBlockProofError, GetBlockProofResponse,
     GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome, GetExecutionOutcomeError,
-    GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError, GetMaintenanceWindows,
-    GetMaintenanceWindowsError, GetNextLightClientBlockError, GetProtocolConfig,
-    GetProtocolConfigError, GetReceipt, GetReceiptError, GetSplitStorageInfo,
-    GetSplitStorageInfoError, GetStateChangesError, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfoError, Query, QueryError,
-    TxStatus, TxStatusError,
+    GetExecutionOutcomesForBlock, GetGasCongestion, GetGasCongestionError, GetGasPrice,
+    GetGasPriceError, GetMaintenanceWindows, GetMaintenanceWindowsError,
+    GetNextLightClientBlockError, GetProtocolConfig, GetProtocolConfigError, GetReceipt,
+    GetReceiptError, GetSplitStorageInfo, GetSplitStorageInfoError, GetStateChangesError,
+    GetStateChangesWithCauseInBlock, GetStateChangesWithCauseInBlockForTrackedShards,
+    GetStateProof, GetStateProofError, GetTransactionExecutionTree,
+    GetTransactionExecutionTreeError, GetValidatorInfoError, Query, QueryBatch, QueryBatchResponse,
+    QueryError, Simulate, SimulateResponse, TxStatus, TxStatusError,
 };
 use near_epoch_manager::shard_tracker::ShardTracker;
 use near_epoch_manager::EpochManagerAdapter;
@@ -36,31 +39,37 @@ use near_network::types::{
 use near_o11y::{handler_debug_span, OpenTelemetrySpanExt, WithSpanContext, WithSpanContextExt};
 use near_performance_metrics_macros::perf;
 use near_primitives::block::{Block, BlockHeader};
+use near_primitives::challenge::PartialState;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, PartialMerkleTree};
 use near_primitives::network::AnnounceAccount;
 use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::ShardChunk;
 use near_primitives::state_sync::{
     ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
     ShardStateSyncResponseV2,
 };
 use near_primitives::static_clock::StaticClock;
+use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
     AccountId, BlockHeight, BlockId, BlockReference, EpochReference, Finality, MaybeBlockId,
-    ShardId, SyncCheckpoint, TransactionOrReceiptId, ValidatorInfoIdentifier,
+    ShardId, StateRoot, SyncCheckpoint, TransactionOrReceiptId, ValidatorInfoIdentifier,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView, ExecutionStatusView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockView,
-    MaintenanceWindowsView, QueryRequest, QueryResponse, ReceiptView, SplitStorageInfoView,
-    StateChangesKindsView, StateChangesView, TxExecutionStatus, TxStatusView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, GasCongestionView, GasPriceView,
+    LightClientBlockView, MaintenanceWindowsView, Pagination, QueryRequest, QueryResponse,
+    ReceiptView, ShardCongestionView, SplitStorageInfoView, StateChangesKindsView,
+    StateChangesView, StateProofView, TransactionExecutionTreeView, TxExecutionStatus,
+    TxStatusView,
 };
-use near_store::{DBCol, COLD_HEAD_KEY, FINAL_HEAD_KEY, HEAD_KEY};
+use near_store::{DBCol, Store, COLD_HEAD_KEY, FINAL_HEAD_KEY, HEAD_KEY};
 use std::cmp::Ordering;
-use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::hash::Hash;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
@@ -101,6 +110,8 @@ pub struct ViewClientActor {
     pub config: ClientConfig,
     request_manager: Arc<RwLock<ViewClientRequestManager>>,
     state_request_cache: Arc<Mutex<VecDeque<Instant>>>,
+    /// Limits the bandwidth used to serve state parts, shared by all the view client threads.
+    state_sync_upload_limiter: Arc<BandwidthLimiter>,
 }
 
 impl ViewClientRequestManager {
@@ -128,10 +139,12 @@ impl ViewClientActor {
         network_adapter: PeerManagerAdapter,
         config: ClientConfig,
         request_manager: Arc<RwLock<ViewClientRequestManager>>,
+        state_sync_upload_limiter: Arc<BandwidthLimiter>,
         adv: crate::adversarial::Controls,
+        cold_store: Option<Store>,
     ) -> Result<Self, Error> {
         // TODO: should we create shared ChainStore that is passed to both Client and ViewClient?
-        let chain = Chain::new_for_view_client(
+        let mut chain = Chain::new_for_view_client(
             epoch_manager.clone(),
             shard_tracker.clone(),
             runtime.clone(),
@@ -139,6 +152,9 @@ impl ViewClientActor {
             DoomslugThresholdMode::TwoThirds,
             config.save_trie_changes,
         )?;
+        if let Some(cold_store) = cold_store {
+            chain.mut_store().set_cold_store(cold_store);
+        }
         Ok(ViewClientActor {
             adv,
             validator_account_id,
@@ -150,6 +166,7 @@ impl ViewClientActor {
             config,
             request_manager,
             state_request_cache: Arc::new(Mutex::new(VecDeque::default())),
+            state_sync_upload_limiter,
         })
     }
 
@@ -305,20 +322,90 @@ impl ViewClientActor {
     }
 
     fn handle_query(&mut self, msg: Query) -> Result<QueryResponse, QueryError> {
-        let header = self.get_block_header_by_reference(&msg.block_reference);
-        let header = match header {
+        // The next pages are served from the block of the first page, whatever the block
+        // reference of the request.
+        let block_reference = match msg.request.pagination().and_then(Pagination::block_hash) {
+            Some(block_hash) => BlockReference::BlockId(BlockId::Hash(block_hash)),
+            None => msg.block_reference,
+        };
+        let header = self.get_query_block_header(block_reference)?;
+        self.query_at(&header, &msg.request)
+    }
+
+    fn handle_query_batch(&mut self, msg: QueryBatch) -> Result<QueryBatchResponse, QueryError> {
+        // All the queries are run against the state of the same block, so the results are
+        // consistent with each other.
+        let header = self.get_query_block_header(msg.block_reference)?;
+        let results = msg
+            .requests
+            .iter()
+            .map(|request| self.query_at(&header, request).map(|response| response.kind))
+            .collect();
+        Ok(QueryBatchResponse {
+            block_height: header.height(),
+            block_hash: *header.hash(),
+            results,
+        })
+    }
+
+    fn handle_simulate(&mut self, msg: Simulate) -> Result<SimulateResponse, QueryError> {
+        let header = self.get_query_block_header(msg.block_reference)?;
+        let shard_layout = self
+            .epoch_manager
+            .get_shard_layout(header.epoch_id())
+            .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        let mut state_roots = HashMap::new();
+        for shard_uid in shard_layout.get_shard_uids() {
+            match self.get_state_root(&header, shard_uid.shard_id as ShardId, &shard_uid) {
+                Ok(state_root) => {
+                    state_roots.insert(shard_uid, state_root);
+                }
+                // The simulation fails only if it needs the state of the shard.
+                Err(QueryError::UnavailableShard { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let result = self
+            .runtime
+            .simulate(
+                &state_roots,
+                header.height(),
+                header.raw_timestamp(),
+                header.prev_hash(),
+                header.hash(),
+                header.epoch_id(),
+                header.gas_price(),
+                &msg.state_overrides,
+                &msg.request,
+            )
+            .map_err(runtime_query_error)?;
+        Ok(SimulateResponse { result, block_height: header.height(), block_hash: *header.hash() })
+    }
+
+    fn get_query_block_header(
+        &self,
+        block_reference: BlockReference,
+    ) -> Result<BlockHeader, QueryError> {
+        match self.get_block_header_by_reference(&block_reference) {
             Ok(Some(header)) => Ok(header),
             Ok(None) => Err(QueryError::NoSyncedBlocks),
             Err(near_chain::near_chain_primitives::Error::DBNotFoundErr(_)) => {
-                Err(QueryError::UnknownBlock { block_reference: msg.block_reference })
+                Err(QueryError::UnknownBlock { block_reference })
             }
             Err(near_chain::near_chain_primitives::Error::IOErr(err)) => {
                 Err(QueryError::InternalError { error_message: err.to_string() })
             }
             Err(err) => Err(QueryError::Unreachable { error_message: err.to_string() }),
-        }?;
+        }
+    }
 
-        let account_id = match &msg.request {
+    /// Runs the query against the state of the block with the given header.
+    fn query_at(
+        &self,
+        header: &BlockHeader,
+        request: &QueryRequest,
+    ) -> Result<QueryResponse, QueryError> {
+        let account_id = match request {
             QueryRequest::ViewAccount { account_id, .. } => account_id,
             QueryRequest::ViewState { account_id, .. } => account_id,
             QueryRequest::ViewAccessKey { account_id, .. } => account_id,
@@ -326,6 +413,14 @@ impl ViewClientActor {
             QueryRequest::CallFunction { account_id, .. } => account_id,
             QueryRequest::ViewCode { account_id, .. } => account_id,
         };
+        if let Some(token_block_hash) = request.pagination().and_then(Pagination::block_hash) {
+            if &token_block_hash != header.hash() {
+                return Err(QueryError::InvalidContinuationToken {
+                    token_block_hash,
+                    block_hash: *header.hash(),
+                });
+            }
+        }
         let shard_id = self
             .epoch_manager
             .account_id_to_shard_id(account_id, header.epoch_id())
@@ -335,9 +430,31 @@ impl ViewClientActor {
             .shard_id_to_uid(shard_id, header.epoch_id())
             .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
 
+        let state_root = self.get_state_root(header, shard_id, &shard_uid)?;
+        self.runtime
+            .query(
+                shard_uid,
+                &state_root,
+                header.height(),
+                header.raw_timestamp(),
+                header.prev_hash(),
+                header.hash(),
+                header.epoch_id(),
+                request,
+            )
+            .map_err(runtime_query_error)
+    }
+
+    /// Returns the root of the state of the shard after the block.
+    fn get_state_root(
+        &self,
+        header: &BlockHeader,
+        shard_id: ShardId,
+        shard_uid: &ShardUId,
+    ) -> Result<StateRoot, QueryError> {
         let tip = self.chain.head();
         let chunk_extra =
-            self.chain.get_chunk_extra(header.hash(), &shard_uid).map_err(|err| match err {
+            self.chain.get_chunk_extra(header.hash(), shard_uid).map_err(|err| match err {
                 near_chain::near_chain_primitives::Error::DBNotFoundErr(_) => match tip {
                     Ok(tip) => {
                         let gc_stop_height = self.runtime.get_gc_stop_height(&tip.last_block_hash);
@@ -357,64 +474,7 @@ impl ViewClientActor {
                 }
                 _ => QueryError::Unreachable { error_message: err.to_string() },
             })?;
-
-        let state_root = chunk_extra.state_root();
-        match self.runtime.query(
-            shard_uid,
-            state_root,
-            header.height(),
-            header.raw_timestamp(),
-            header.prev_hash(),
-            header.hash(),
-            header.epoch_id(),
-            &msg.request,
-        ) {
-            Ok(query_response) => Ok(query_response),
-            Err(query_error) => Err(match query_error {
-                near_chain::near_chain_primitives::error::QueryError::InternalError {
-                    error_message,
-                    ..
-                } => QueryError::InternalError { error_message },
-                near_chain::near_chain_primitives::error::QueryError::InvalidAccount {
-                    requested_account_id,
-                    block_height,
-                    block_hash,
-                } => QueryError::InvalidAccount { requested_account_id, block_height, block_hash },
-                near_chain::near_chain_primitives::error::QueryError::UnknownAccount {
-                    requested_account_id,
-                    block_height,
-                    block_hash,
-                } => QueryError::UnknownAccount { requested_account_id, block_height, block_hash },
-                near_chain::near_chain_primitives::error::QueryError::NoContractCode {
-                    contract_account_id,
-                    block_height,
-                    block_hash,
-                } => QueryError::NoContractCode { contract_account_id, block_height, block_hash },
-                near_chain::near_chain_primitives::error::QueryError::UnknownAccessKey {
-                    public_key,
-                    block_height,
-                    block_hash,
-                } => QueryError::UnknownAccessKey { public_key, block_height, block_hash },
-                near_chain::near_chain_primitives::error::QueryError::ContractExecutionError {
-                    error_message,
-                    block_hash,
-                    block_height,
-                } => QueryError::ContractExecutionError {
-                    vm_error: error_message,
-                    block_height,
-                    block_hash,
-                },
-                near_chain::near_chain_primitives::error::QueryError::TooLargeContractState {
-                    requested_account_id,
-                    block_height,
-                    block_hash,
-                } => QueryError::TooLargeContractState {
-                    contract_account_id: requested_account_id,
-                    block_height,
-                    block_hash,
-                },
-            }),
-        }
+        Ok(*chunk_extra.state_root())
     }
 
     fn get_tx_execution_status(
@@ -538,8 +598,12 @@ impl ViewClientActor {
     fn retrieve_headers(
         &mut self,
         hashes: Vec<CryptoHash>,
+        max_headers: Option<u64>,
     ) -> Result<Vec<BlockHeader>, near_chain::Error> {
-        self.chain.retrieve_headers(hashes, sync::header::MAX_BLOCK_HEADERS, None)
+        let max_headers = max_headers.map_or(sync::header::MAX_BLOCK_HEADERS, |max_headers| {
+            max_headers.min(sync::header::MAX_BLOCK_HEADERS)
+        });
+        self.chain.retrieve_headers(hashes, max_headers, None)
     }
 
     fn check_signature_account_announce(
@@ -580,6 +644,57 @@ impl ViewClientActor {
     }
 }
 
+/// Converts the error of a query to the runtime.
+fn runtime_query_error(error: near_chain::near_chain_primitives::error::QueryError) -> QueryError {
+    match error {
+        near_chain::near_chain_primitives::error::QueryError::InternalError {
+            error_message,
+            ..
+        } => QueryError::InternalError { error_message },
+        near_chain::near_chain_primitives::error::QueryError::InvalidAccount {
+            requested_account_id,
+            block_height,
+            block_hash,
+        } => QueryError::InvalidAccount { requested_account_id, block_height, block_hash },
+        near_chain::near_chain_primitives::error::QueryError::UnknownAccount {
+            requested_account_id,
+            block_height,
+            block_hash,
+        } => QueryError::UnknownAccount { requested_account_id, block_height, block_hash },
+        near_chain::near_chain_primitives::error::QueryError::NoContractCode {
+            contract_account_id,
+            block_height,
+            block_hash,
+        } => QueryError::NoContractCode { contract_account_id, block_height, block_hash },
+        near_chain::near_chain_primitives::error::QueryError::UnknownAccessKey {
+            public_key,
+            block_height,
+            block_hash,
+        } => QueryError::UnknownAccessKey { public_key, block_height, block_hash },
+        near_chain::near_chain_primitives::error::QueryError::ContractExecutionError {
+            error_message,
+            block_hash,
+            block_height,
+        } => {
+            QueryError::ContractExecutionError { vm_error: error_message, block_height, block_hash }
+        }
+        near_chain::near_chain_primitives::error::QueryError::TooLargeContractState {
+            requested_account_id,
+            block_height,
+            block_hash,
+        } => QueryError::TooLargeContractState {
+            contract_account_id: requested_account_id,
+            block_height,
+            block_hash,
+        },
+        near_chain::near_chain_primitives::error::QueryError::InvalidTransaction {
+            context,
+            block_height,
+            block_hash,
+        } => QueryError::InvalidTransaction { context, block_height, block_hash },
+    }
+}
+
 impl Actor for ViewClientActor {
     type Context = SyncContext<Self>;
 }
@@ -596,6 +711,32 @@ impl Handler<WithSpanContext<Query>> for ViewClientActor {
     }
 }
 
+impl Handler<WithSpanContext<QueryBatch>> for ViewClientActor {
+    type Result = Result<QueryBatchResponse, QueryError>;
+
+    #[perf]
+    fn handle(&mut self, msg: WithSpanContext<QueryBatch>, _: &mut Self::Context) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer =
+            metrics::VIEW_CLIENT_MESSAGE_TIME.with_label_values(&["QueryBatch"]).start_timer();
+        self.handle_query_batch(msg)
+    }
+}
+
+impl Handler<WithSpanContext<Simulate>> for ViewClientActor {
+    type Result = Result<SimulateResponse, QueryError>;
+
+    #[perf]
+    fn handle(&mut self, msg: WithSpanContext<Simulate>, _: &mut Self::Context) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer =
+            metrics::VIEW_CLIENT_MESSAGE_TIME.with_label_values(&["Simulate"]).start_timer();
+        self.handle_simulate(msg)
+    }
+}
+
 /// Handles retrieving block from the chain.
 impl Handler<WithSpanContext<GetBlock>> for ViewClientActor {
     type Result = Result<BlockView, GetBlockError>;
@@ -1087,6 +1228,31 @@ impl Handler<WithSpanContext<GetReceipt>> for ViewClientActor {
     }
 }
 
+impl Handler<WithSpanContext<GetTransactionExecutionTree>> for ViewClientActor {
+    type Result = Result<TransactionExecutionTreeView, GetTransactionExecutionTreeError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<GetTransactionExecutionTree>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer = metrics::VIEW_CLIENT_MESSAGE_TIME
+            .with_label_values(&["GetTransactionExecutionTree"])
+            .start_timer();
+        // The whole tree is read in a single call, so that it is not garbage collected
+        // halfway through.
+        self.chain.get_transaction_execution_tree(&msg.transaction_hash).map_err(|err| match err {
+            near_chain::Error::DBNotFoundErr(_) => {
+                GetTransactionExecutionTreeError::UnknownTransaction(msg.transaction_hash)
+            }
+            err => err.into(),
+        })
+    }
+}
+
 impl Handler<WithSpanContext<GetBlockProof>> for ViewClientActor {
     type Result = Result<GetBlockProofResponse, GetBlockProofError>;
 
@@ -1109,6 +1275,67 @@ impl Handler<WithSpanContext<GetBlockProof>> for ViewClientActor {
     }
 }
 
+impl Handler<WithSpanContext<GetStateProof>> for ViewClientActor {
+    type Result = Result<StateProofView, GetStateProofError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<GetStateProof>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer =
+            metrics::VIEW_CLIENT_MESSAGE_TIME.with_label_values(&["GetStateProof"]).start_timer();
+        let block = self.chain.get_block(&msg.block_hash)?;
+        let header = block.header();
+        let shard_id = self
+            .epoch_manager
+            .account_id_to_shard_id(&msg.account_id, header.epoch_id())
+            .into_chain_error()?;
+        if !self.shard_tracker.care_about_shard(
+            self.validator_account_id.as_ref(),
+            header.prev_hash(),
+            shard_id,
+            true,
+        ) {
+            return Err(GetStateProofError::UnavailableShard {
+                account_id: msg.account_id,
+                shard_id,
+            });
+        }
+        // The state roots are the leaves of the prev_state_root of the header, see
+        // `Block::compute_state_root`.
+        let state_roots =
+            block.chunks().iter().map(|chunk| chunk.prev_state_root()).collect::<Vec<_>>();
+        let Some(&state_root) = state_roots.get(shard_id as usize) else {
+            return Err(GetStateProofError::InternalError {
+                error_message: format!(
+                    "Block {} has no chunk of shard {}",
+                    msg.block_hash, shard_id
+                ),
+            });
+        };
+        // Flat storage is bypassed, so that the trie nodes on the path to the key are recorded.
+        let trie = self
+            .runtime
+            .get_trie_for_shard(shard_id, header.prev_hash(), state_root, false)?
+            .recording_reads();
+        let key = TrieKey::ContractData { account_id: msg.account_id, key: msg.key.into() };
+        let value = trie.get(&key.to_vec()).map_err(near_chain::Error::from)?;
+        let PartialState::TrieValues(proof) =
+            trie.recorded_storage().expect("the trie records reads").nodes;
+        Ok(StateProofView {
+            shard_id,
+            state_root,
+            value: value.map(Into::into),
+            proof,
+            state_root_proof: merklize(&state_roots).1[shard_id as usize].clone(),
+        })
+    }
+}
+
 impl Handler<WithSpanContext<GetProtocolConfig>> for ViewClientActor {
     type Result = Result<ProtocolConfigView, GetProtocolConfigError>;
 
@@ -1259,11 +1486,11 @@ impl Handler<WithSpanContext<BlockHeadersRequest>> for ViewClientActor {
         let _timer = metrics::VIEW_CLIENT_MESSAGE_TIME
             .with_label_values(&["BlockHeadersRequest"])
             .start_timer();
-        let BlockHeadersRequest(hashes) = msg;
+        let BlockHeadersRequest(hashes, max_headers) = msg;
 
         if self.adv.disable_header_sync() {
             None
-        } else if let Ok(headers) = self.retrieve_headers(hashes) {
+        } else if let Ok(headers) = self.retrieve_headers(hashes, max_headers) {
             Some(headers)
         } else {
             None
@@ -1271,6 +1498,39 @@ impl Handler<WithSpanContext<BlockHeadersRequest>> for ViewClientActor {
     }
 }
 
+impl Handler<WithSpanContext<EpochSyncRequest>> for ViewClientActor {
+    type Result = Option<Box<EpochSyncProof>>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<EpochSyncRequest>,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer = metrics::VIEW_CLIENT_MESSAGE_TIME
+            .with_label_values(&["EpochSyncRequest"])
+            .start_timer();
+        let EpochSyncRequest(prev_epoch_id) = msg;
+
+        // Proofs are made of data which is only stored with the feature.
+        #[cfg(feature = "new_epoch_sync")]
+        return match self.chain.get_epoch_sync_proof(&prev_epoch_id) {
+            Ok(proof) => proof.map(Box::new),
+            Err(err) => {
+                error!(target: "sync", ?prev_epoch_id, ?err, "Failed to get epoch sync proof");
+                None
+            }
+        };
+        #[cfg(not(feature = "new_epoch_sync"))]
+        {
+            let _ = prev_epoch_id;
+            None
+        }
+    }
+}
+
 impl Handler<WithSpanContext<StateRequestHeader>> for ViewClientActor {
     type Result = Option<StateResponse>;
 
@@ -1379,11 +1639,18 @@ impl Handler<WithSpanContext<StateRequestPart>> for ViewClientActor {
         if !self.check_state_sync_request() {
             return None;
         }
+        if !self.state_sync_upload_limiter.try_start() {
+            tracing::debug!(target: "sync", shard_id, part_id, "Not serving a state part because of the bandwidth limits of state sync");
+            return None;
+        }
         tracing::debug!(target: "sync", ?shard_id, ?sync_hash, ?part_id, "Computing state request part");
         let state_response = match self.chain.check_sync_hash_validity(&sync_hash) {
             Ok(true) => {
                 let part = match self.chain.get_state_response_part(shard_id, part_id, sync_hash) {
-                    Ok(part) => Some((part_id, part)),
+                    Ok(part) => {
+                        self.state_sync_upload_limiter.record(part.len() as u64);
+                        Some((part_id, part))
+                    }
                     Err(e) => {
                         error!(target: "sync", "Cannot build sync part #{:?} (get_state_response_part): {}", part_id, e);
                         None
@@ -1493,6 +1760,66 @@ impl Handler<WithSpanContext<GetGasPrice>> for ViewClientActor {
     }
 }
 
+impl Handler<WithSpanContext<GetGasCongestion>> for ViewClientActor {
+    type Result = Result<GasCongestionView, GetGasCongestionError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        msg: WithSpanContext<GetGasCongestion>,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let (_span, msg) = handler_debug_span!(target: "client", msg);
+        tracing::debug!(target: "client", ?msg);
+        let _timer = metrics::VIEW_CLIENT_MESSAGE_TIME
+            .with_label_values(&["GetGasCongestion"])
+            .start_timer();
+        let head = self.chain.head()?;
+        let head_header = self.chain.get_block_header(&head.last_block_hash)?;
+        let mut shards = BTreeMap::new();
+        let mut num_blocks = 0;
+        let mut block_hash = head.last_block_hash;
+        while num_blocks < msg.num_blocks {
+            let block = self.chain.get_block(&block_hash)?;
+            num_blocks += 1;
+            for chunk in block.chunks().iter() {
+                if chunk.height_included() != block.header().height() {
+                    continue;
+                }
+                let shard = shards.entry(chunk.shard_id()).or_insert_with(|| ShardCongestionView {
+                    shard_id: chunk.shard_id(),
+                    num_chunks: 0,
+                    gas_used: 0,
+                    gas_limit: 0,
+                    num_transactions: Some(0),
+                    pool: None,
+                    estimated_inclusion_delay: None,
+                });
+                shard.num_chunks += 1;
+                shard.gas_used = shard.gas_used.saturating_add(chunk.prev_gas_used());
+                shard.gas_limit = shard.gas_limit.saturating_add(chunk.gas_limit());
+                // Only the chunks of the tracked shards are stored.
+                let chunk = self.chain.get_chunk(&chunk.chunk_hash()).ok();
+                shard.num_transactions = shard
+                    .num_transactions
+                    .zip(chunk)
+                    .map(|(n, chunk)| n + chunk.transactions().len() as u64);
+            }
+            if block.header().height() == self.chain.genesis().height() {
+                break;
+            }
+            block_hash = *block.header().prev_hash();
+        }
+        Ok(GasCongestionView {
+            block_hash: head.last_block_hash,
+            block_height: head.height,
+            gas_price: head_header.gas_price(),
+            num_blocks,
+            shards: shards.into_values().collect(),
+        })
+    }
+}
+
 impl Handler<WithSpanContext<GetMaintenanceWindows>> for ViewClientActor {
     type Result = Result<MaintenanceWindowsView, GetMaintenanceWindowsError>;
 
@@ -1545,8 +1872,13 @@ pub fn start_view_client(
     network_adapter: PeerManagerAdapter,
     config: ClientConfig,
     adv: crate::adversarial::Controls,
+    cold_store: Option<Store>,
 ) -> Addr<ViewClientActor> {
     let request_manager = Arc::new(RwLock::new(ViewClientRequestManager::new()));
+    let state_sync_upload_limiter = Arc::new(BandwidthLimiter::from_config(
+        BandwidthDirection::Upload,
+        &config.state_sync.bandwidth,
+    ));
     SyncArbiter::start(config.view_client_threads, move || {
         ViewClientActor::new(
             validator_account_id.clone(),
@@ -1557,7 +1889,9 @@ pub fn start_view_client(
             network_adapter.clone(),
             config.clone(),
             request_manager.clone(),
+            state_sync_upload_limiter.clone(),
             adv.clone(),
+            cold_store.clone(),
         )
         .unwrap()
     })
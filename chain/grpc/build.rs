@@ -0,0 +1,8 @@
+fn main() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed=proto/near.proto");
+    // Use the vendored protoc, so that building the node doesn't require protoc to be installed.
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    std::env::set_var("PROTOC_INCLUDE", protoc_bin_vendored::include_path()?);
+    tonic_build::compile_protos("proto/near.proto")?;
+    Ok(())
+}
@@ -0,0 +1,648 @@
+//! Conversions between the view structs and the messages of near.proto.
+use crate::proto;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_crypto::{PublicKey, Signature};
+use near_primitives::challenge::SlashedValidator;
+use near_primitives::errors::TxExecutionError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::merkle::{Direction, MerklePathItem};
+use near_primitives::sharding::ChunkHash;
+use near_primitives::types::{AccountId, BlockId, BlockReference, Finality};
+use near_primitives::views::validator_stake_view::ValidatorStakeView;
+use near_primitives::views::{
+    AccessKeyInfoView, AccessKeyList, AccessKeyPermissionView, AccessKeyView, AccountView,
+    ActionView, BlockHeaderView, BlockView, CallResult, ChunkHeaderView, ChunkView,
+    ContinuationToken, ContractCodeView, CostGasUsed, DataReceiverView, ExecutionMetadataView,
+    ExecutionOutcomeView, ExecutionOutcomeWithIdView, ExecutionStatusView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, Pagination,
+    QueryRequest, QueryResponse, QueryResponseKind, ReceiptEnumView, ReceiptView,
+    SignedTransactionView, StateItem, TxExecutionStatus, TxStatusView, ViewStateResult,
+};
+
+/// Request which doesn't match the API, reported as INVALID_ARGUMENT.
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub(crate) struct ParseError(String);
+
+impl From<ParseError> for tonic::Status {
+    fn from(err: ParseError) -> Self {
+        tonic::Status::invalid_argument(err.0)
+    }
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, ParseError> {
+    value.ok_or_else(|| ParseError(format!("{field} is required")))
+}
+
+pub(crate) fn parse_hash(bytes: &[u8], field: &str) -> Result<CryptoHash, ParseError> {
+    CryptoHash::try_from(bytes).map_err(|err| ParseError(format!("{field}: {err}")))
+}
+
+pub(crate) fn parse_account_id(account_id: String, field: &str) -> Result<AccountId, ParseError> {
+    account_id.parse().map_err(|err| ParseError(format!("{field}: {err}")))
+}
+
+fn parse_borsh<T: BorshDeserialize>(bytes: &[u8], field: &str) -> Result<T, ParseError> {
+    T::try_from_slice(bytes).map_err(|err| ParseError(format!("{field}: {err}")))
+}
+
+fn borsh<T: BorshSerialize>(value: &T) -> Vec<u8> {
+    value.try_to_vec().unwrap()
+}
+
+fn hash(hash: CryptoHash) -> Vec<u8> {
+    hash.0.to_vec()
+}
+
+fn hashes(hashes: Vec<CryptoHash>) -> Vec<Vec<u8>> {
+    hashes.into_iter().map(hash).collect()
+}
+
+impl From<u128> for proto::Uint128 {
+    fn from(x: u128) -> Self {
+        Self { lo: x as u64, hi: (x >> 64) as u64 }
+    }
+}
+
+impl From<proto::Uint128> for u128 {
+    fn from(x: proto::Uint128) -> Self {
+        (u128::from(x.hi) << 64) | u128::from(x.lo)
+    }
+}
+
+impl From<&PublicKey> for proto::PublicKey {
+    fn from(x: &PublicKey) -> Self {
+        Self { borsh: borsh(x) }
+    }
+}
+
+impl From<&Signature> for proto::Signature {
+    fn from(x: &Signature) -> Self {
+        Self { borsh: borsh(x) }
+    }
+}
+
+impl TryFrom<proto::BlockReference> for BlockReference {
+    type Error = ParseError;
+    fn try_from(x: proto::BlockReference) -> Result<Self, Self::Error> {
+        use proto::block_reference::Reference;
+        Ok(match required(x.reference, "block_reference")? {
+            Reference::BlockHeight(height) => BlockReference::BlockId(BlockId::Height(height)),
+            Reference::BlockHash(block_hash) => {
+                BlockReference::BlockId(BlockId::Hash(parse_hash(&block_hash, "block_hash")?))
+            }
+            Reference::Finality(finality) => {
+                BlockReference::Finality(match proto::Finality::from_i32(finality) {
+                    Some(proto::Finality::Optimistic) => Finality::None,
+                    Some(proto::Finality::NearFinal) => Finality::DoomSlug,
+                    Some(proto::Finality::Final) => Finality::Final,
+                    None => return Err(ParseError(format!("unknown finality {finality}"))),
+                })
+            }
+        })
+    }
+}
+
+impl TryFrom<proto::ChunkRequest> for near_client_primitives::types::GetChunk {
+    type Error = ParseError;
+    fn try_from(x: proto::ChunkRequest) -> Result<Self, Self::Error> {
+        use near_client_primitives::types::GetChunk;
+        use proto::block_shard_id::Block;
+        use proto::chunk_request::Chunk;
+        Ok(match required(x.chunk, "chunk")? {
+            Chunk::ChunkHash(chunk_hash) => {
+                GetChunk::ChunkHash(ChunkHash(parse_hash(&chunk_hash, "chunk_hash")?))
+            }
+            Chunk::BlockShardId(id) => match required(id.block, "block")? {
+                Block::BlockHeight(height) => GetChunk::Height(height, id.shard_id),
+                Block::BlockHash(block_hash) => {
+                    GetChunk::BlockHash(parse_hash(&block_hash, "block_hash")?, id.shard_id)
+                }
+            },
+        })
+    }
+}
+
+impl TryFrom<proto::QueryRequest> for near_client_primitives::types::Query {
+    type Error = ParseError;
+    fn try_from(x: proto::QueryRequest) -> Result<Self, Self::Error> {
+        use proto::query_request::Request;
+        let block_reference =
+            BlockReference::try_from(required(x.block_reference, "block_reference")?)?;
+        let request = match required(x.request, "request")? {
+            Request::ViewAccount(r) => QueryRequest::ViewAccount {
+                account_id: parse_account_id(r.account_id, "account_id")?,
+            },
+            Request::ViewCode(r) => {
+                QueryRequest::ViewCode { account_id: parse_account_id(r.account_id, "account_id")? }
+            }
+            Request::ViewState(r) => QueryRequest::ViewState {
+                account_id: parse_account_id(r.account_id, "account_id")?,
+                prefix: r.prefix.into(),
+                include_proof: r.include_proof,
+                pagination: match r.pagination {
+                    None => None,
+                    Some(p) if p.continuation_token.is_empty() => {
+                        Some(Pagination { continuation_token: None })
+                    }
+                    Some(p) => Some(Pagination {
+                        continuation_token: Some(parse_borsh::<ContinuationToken>(
+                            &p.continuation_token,
+                            "continuation_token",
+                        )?),
+                    }),
+                },
+            },
+            Request::ViewAccessKey(r) => QueryRequest::ViewAccessKey {
+                account_id: parse_account_id(r.account_id, "account_id")?,
+                public_key: parse_borsh(
+                    &required(r.public_key, "public_key")?.borsh,
+                    "public_key",
+                )?,
+            },
+            Request::ViewAccessKeyList(r) => QueryRequest::ViewAccessKeyList {
+                account_id: parse_account_id(r.account_id, "account_id")?,
+            },
+            Request::CallFunction(r) => QueryRequest::CallFunction {
+                account_id: parse_account_id(r.account_id, "account_id")?,
+                method_name: r.method_name,
+                args: r.args.into(),
+            },
+        };
+        Ok(Self::new(block_reference, request))
+    }
+}
+
+impl From<ValidatorStakeView> for proto::ValidatorStake {
+    fn from(x: ValidatorStakeView) -> Self {
+        match x {
+            ValidatorStakeView::V1(v) => Self {
+                account_id: v.account_id.into(),
+                public_key: Some((&v.public_key).into()),
+                stake: Some(v.stake.into()),
+            },
+        }
+    }
+}
+
+impl From<SlashedValidator> for proto::SlashedValidator {
+    fn from(x: SlashedValidator) -> Self {
+        Self { account_id: x.account_id.into(), is_double_sign: x.is_double_sign }
+    }
+}
+
+impl From<BlockHeaderView> for proto::BlockHeader {
+    fn from(x: BlockHeaderView) -> Self {
+        Self {
+            height: x.height,
+            prev_height: x.prev_height,
+            epoch_id: hash(x.epoch_id),
+            next_epoch_id: hash(x.next_epoch_id),
+            hash: hash(x.hash),
+            prev_hash: hash(x.prev_hash),
+            prev_state_root: hash(x.prev_state_root),
+            block_body_hash: x.block_body_hash.map(hash).unwrap_or_default(),
+            chunk_receipts_root: hash(x.chunk_receipts_root),
+            chunk_headers_root: hash(x.chunk_headers_root),
+            chunk_tx_root: hash(x.chunk_tx_root),
+            outcome_root: hash(x.outcome_root),
+            chunks_included: x.chunks_included,
+            challenges_root: hash(x.challenges_root),
+            timestamp_nanosec: x.timestamp_nanosec,
+            random_value: hash(x.random_value),
+            validator_proposals: x.validator_proposals.into_iter().map(Into::into).collect(),
+            chunk_mask: x.chunk_mask,
+            gas_price: Some(x.gas_price.into()),
+            block_ordinal: x.block_ordinal,
+            total_supply: Some(x.total_supply.into()),
+            challenges_result: x.challenges_result.into_iter().map(Into::into).collect(),
+            last_final_block: hash(x.last_final_block),
+            last_ds_final_block: hash(x.last_ds_final_block),
+            next_bp_hash: hash(x.next_bp_hash),
+            block_merkle_root: hash(x.block_merkle_root),
+            epoch_sync_data_hash: x.epoch_sync_data_hash.map(hash).unwrap_or_default(),
+            approvals: x
+                .approvals
+                .iter()
+                .map(|approval| match approval {
+                    Some(signature) => signature.as_ref().into(),
+                    None => proto::Signature::default(),
+                })
+                .collect(),
+            signature: Some((&x.signature).into()),
+            latest_protocol_version: x.latest_protocol_version,
+        }
+    }
+}
+
+impl From<ChunkHeaderView> for proto::ChunkHeader {
+    fn from(x: ChunkHeaderView) -> Self {
+        Self {
+            chunk_hash: hash(x.chunk_hash),
+            prev_block_hash: hash(x.prev_block_hash),
+            outcome_root: hash(x.outcome_root),
+            prev_state_root: hash(x.prev_state_root),
+            encoded_merkle_root: hash(x.encoded_merkle_root),
+            encoded_length: x.encoded_length,
+            height_created: x.height_created,
+            height_included: x.height_included,
+            shard_id: x.shard_id,
+            gas_used: x.gas_used,
+            gas_limit: x.gas_limit,
+            balance_burnt: Some(x.balance_burnt.into()),
+            outgoing_receipts_root: hash(x.outgoing_receipts_root),
+            tx_root: hash(x.tx_root),
+            validator_proposals: x.validator_proposals.into_iter().map(Into::into).collect(),
+            signature: Some((&x.signature).into()),
+        }
+    }
+}
+
+impl From<BlockView> for proto::Block {
+    fn from(x: BlockView) -> Self {
+        Self {
+            author: x.author.into(),
+            header: Some(x.header.into()),
+            chunks: x.chunks.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<AccessKeyView> for proto::AccessKey {
+    fn from(x: AccessKeyView) -> Self {
+        use proto::access_key::Permission;
+        Self {
+            nonce: x.nonce,
+            permission: Some(match x.permission {
+                AccessKeyPermissionView::FunctionCall { allowance, receiver_id, method_names } => {
+                    Permission::FunctionCall(proto::FunctionCallPermission {
+                        allowance: allowance.map(Into::into),
+                        receiver_id,
+                        method_names,
+                    })
+                }
+                AccessKeyPermissionView::FullAccess => Permission::FullAccess(proto::Empty {}),
+            }),
+        }
+    }
+}
+
+impl From<ActionView> for proto::Action {
+    fn from(x: ActionView) -> Self {
+        use proto::action::Action;
+        Self {
+            action: Some(match x {
+                ActionView::CreateAccount => Action::CreateAccount(proto::Empty {}),
+                ActionView::DeployContract { code } => {
+                    Action::DeployContract(proto::DeployContractAction { code })
+                }
+                ActionView::FunctionCall { method_name, args, gas, deposit } => {
+                    Action::FunctionCall(proto::FunctionCallAction {
+                        method_name,
+                        args: args.into(),
+                        gas,
+                        deposit: Some(deposit.into()),
+                    })
+                }
+                ActionView::Transfer { deposit } => {
+                    Action::Transfer(proto::TransferAction { deposit: Some(deposit.into()) })
+                }
+                ActionView::Stake { stake, public_key } => Action::Stake(proto::StakeAction {
+                    stake: Some(stake.into()),
+                    public_key: Some((&public_key).into()),
+                }),
+                ActionView::AddKey { public_key, access_key } => {
+                    Action::AddKey(proto::AddKeyAction {
+                        public_key: Some((&public_key).into()),
+                        access_key: Some(access_key.into()),
+                    })
+                }
+                ActionView::DeleteKey { public_key } => Action::DeleteKey(proto::DeleteKeyAction {
+                    public_key: Some((&public_key).into()),
+                }),
+                ActionView::DeleteAccount { beneficiary_id } => {
+                    Action::DeleteAccount(proto::DeleteAccountAction {
+                        beneficiary_id: beneficiary_id.into(),
+                    })
+                }
+                ActionView::Delegate { delegate_action, signature } => {
+                    Action::Delegate(proto::DelegateAction {
+                        borsh: borsh(&delegate_action),
+                        signature: Some((&signature).into()),
+                    })
+                }
+            }),
+        }
+    }
+}
+
+impl From<SignedTransactionView> for proto::SignedTransaction {
+    fn from(x: SignedTransactionView) -> Self {
+        Self {
+            signer_id: x.signer_id.into(),
+            public_key: Some((&x.public_key).into()),
+            nonce: x.nonce,
+            receiver_id: x.receiver_id.into(),
+            actions: x.actions.into_iter().map(Into::into).collect(),
+            signature: Some((&x.signature).into()),
+            hash: hash(x.hash),
+        }
+    }
+}
+
+impl From<DataReceiverView> for proto::DataReceiver {
+    fn from(x: DataReceiverView) -> Self {
+        Self { data_id: hash(x.data_id), receiver_id: x.receiver_id.into() }
+    }
+}
+
+impl From<ReceiptView> for proto::Receipt {
+    fn from(x: ReceiptView) -> Self {
+        use proto::receipt::Receipt;
+        Self {
+            predecessor_id: x.predecessor_id.into(),
+            receiver_id: x.receiver_id.into(),
+            receipt_id: hash(x.receipt_id),
+            receipt: Some(match x.receipt {
+                ReceiptEnumView::Action {
+                    signer_id,
+                    signer_public_key,
+                    gas_price,
+                    output_data_receivers,
+                    input_data_ids,
+                    actions,
+                } => Receipt::Action(proto::ActionReceipt {
+                    signer_id: signer_id.into(),
+                    signer_public_key: Some((&signer_public_key).into()),
+                    gas_price: Some(gas_price.into()),
+                    output_data_receivers: output_data_receivers
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    input_data_ids: hashes(input_data_ids),
+                    actions: actions.into_iter().map(Into::into).collect(),
+                }),
+                ReceiptEnumView::Data { data_id, data } => {
+                    Receipt::Data(proto::DataReceipt { data_id: hash(data_id), data })
+                }
+            }),
+        }
+    }
+}
+
+impl From<ChunkView> for proto::Chunk {
+    fn from(x: ChunkView) -> Self {
+        Self {
+            author: x.author.into(),
+            header: Some(x.header.into()),
+            transactions: x.transactions.into_iter().map(Into::into).collect(),
+            receipts: x.receipts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn tx_execution_error(x: &TxExecutionError) -> String {
+    serde_json::to_string(x).unwrap()
+}
+
+impl From<ExecutionStatusView> for proto::ExecutionStatus {
+    fn from(x: ExecutionStatusView) -> Self {
+        use proto::execution_status::Status;
+        Self {
+            status: Some(match x {
+                ExecutionStatusView::Unknown => Status::Unknown(proto::Empty {}),
+                ExecutionStatusView::Failure(err) => Status::Failure(tx_execution_error(&err)),
+                ExecutionStatusView::SuccessValue(value) => Status::SuccessValue(value),
+                ExecutionStatusView::SuccessReceiptId(id) => Status::SuccessReceiptId(hash(id)),
+            }),
+        }
+    }
+}
+
+impl From<CostGasUsed> for proto::CostGasUsed {
+    fn from(x: CostGasUsed) -> Self {
+        Self { cost_category: x.cost_category, cost: x.cost, gas_used: x.gas_used }
+    }
+}
+
+impl From<ExecutionMetadataView> for proto::ExecutionMetadata {
+    fn from(x: ExecutionMetadataView) -> Self {
+        Self {
+            version: x.version,
+            gas_profile: x.gas_profile.unwrap_or_default().into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ExecutionOutcomeView> for proto::ExecutionOutcome {
+    fn from(x: ExecutionOutcomeView) -> Self {
+        Self {
+            logs: x.logs,
+            receipt_ids: hashes(x.receipt_ids),
+            gas_burnt: x.gas_burnt,
+            tokens_burnt: Some(x.tokens_burnt.into()),
+            executor_id: x.executor_id.into(),
+            status: Some(x.status.into()),
+            metadata: Some(x.metadata.into()),
+        }
+    }
+}
+
+impl From<MerklePathItem> for proto::MerklePathItem {
+    fn from(x: MerklePathItem) -> Self {
+        Self {
+            hash: hash(x.hash),
+            direction: match x.direction {
+                Direction::Left => proto::Direction::Left,
+                Direction::Right => proto::Direction::Right,
+            } as i32,
+        }
+    }
+}
+
+impl From<ExecutionOutcomeWithIdView> for proto::ExecutionOutcomeWithId {
+    fn from(x: ExecutionOutcomeWithIdView) -> Self {
+        Self {
+            proof: x.proof.into_iter().map(Into::into).collect(),
+            block_hash: hash(x.block_hash),
+            id: hash(x.id),
+            outcome: Some(x.outcome.into()),
+        }
+    }
+}
+
+impl From<FinalExecutionStatus> for proto::FinalExecutionStatus {
+    fn from(x: FinalExecutionStatus) -> Self {
+        use proto::final_execution_status::Status;
+        Self {
+            status: Some(match x {
+                FinalExecutionStatus::NotStarted => Status::NotStarted(proto::Empty {}),
+                FinalExecutionStatus::Started => Status::Started(proto::Empty {}),
+                FinalExecutionStatus::Failure(err) => Status::Failure(tx_execution_error(&err)),
+                FinalExecutionStatus::SuccessValue(value) => Status::SuccessValue(value),
+            }),
+        }
+    }
+}
+
+impl From<FinalExecutionOutcomeView> for proto::FinalExecutionOutcome {
+    fn from(x: FinalExecutionOutcomeView) -> Self {
+        Self {
+            status: Some(x.status.into()),
+            transaction: Some(x.transaction.into()),
+            transaction_outcome: Some(x.transaction_outcome.into()),
+            receipts_outcome: x.receipts_outcome.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<TxStatusView> for proto::TransactionStatus {
+    fn from(x: TxStatusView) -> Self {
+        let (outcome, receipts) = match x.execution_outcome {
+            None => (None, vec![]),
+            Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(outcome)) => {
+                (Some(outcome), vec![])
+            }
+            Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(outcome)) => {
+                (Some(outcome.final_outcome), outcome.receipts)
+            }
+        };
+        Self {
+            status: match x.status {
+                TxExecutionStatus::None => proto::TxExecutionStatus::None,
+                TxExecutionStatus::Inclusion => proto::TxExecutionStatus::Inclusion,
+                TxExecutionStatus::InclusionFinal => proto::TxExecutionStatus::InclusionFinal,
+                TxExecutionStatus::Executed => proto::TxExecutionStatus::Executed,
+                TxExecutionStatus::Final => proto::TxExecutionStatus::Final,
+            } as i32,
+            outcome: outcome.map(Into::into),
+            receipts: receipts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<AccountView> for proto::Account {
+    fn from(x: AccountView) -> Self {
+        Self {
+            amount: Some(x.amount.into()),
+            locked: Some(x.locked.into()),
+            code_hash: hash(x.code_hash),
+            storage_usage: x.storage_usage,
+        }
+    }
+}
+
+impl From<StateItem> for proto::StateItem {
+    fn from(x: StateItem) -> Self {
+        Self { key: x.key.into(), value: x.value.into() }
+    }
+}
+
+impl From<ViewStateResult> for proto::ViewStateResult {
+    fn from(x: ViewStateResult) -> Self {
+        Self {
+            values: x.values.into_iter().map(Into::into).collect(),
+            proof: x.proof.iter().map(|node| node.to_vec()).collect(),
+            continuation_token: x.continuation_token.map(|t| borsh(&t)).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<AccessKeyInfoView> for proto::AccessKeyInfo {
+    fn from(x: AccessKeyInfoView) -> Self {
+        Self { public_key: Some((&x.public_key).into()), access_key: Some(x.access_key.into()) }
+    }
+}
+
+impl From<QueryResponse> for proto::QueryResponse {
+    fn from(x: QueryResponse) -> Self {
+        use proto::query_response::Kind;
+        Self {
+            block_height: x.block_height,
+            block_hash: hash(x.block_hash),
+            kind: Some(match x.kind {
+                QueryResponseKind::ViewAccount(account) => Kind::Account(account.into()),
+                QueryResponseKind::ViewCode(ContractCodeView { code, hash: code_hash }) => {
+                    Kind::Code(proto::ContractCode { code, hash: hash(code_hash) })
+                }
+                QueryResponseKind::ViewState(result) => Kind::ViewState(result.into()),
+                QueryResponseKind::CallResult(CallResult { result, logs }) => {
+                    Kind::CallResult(proto::CallResult { result, logs })
+                }
+                QueryResponseKind::AccessKey(access_key) => Kind::AccessKey(access_key.into()),
+                QueryResponseKind::AccessKeyList(AccessKeyList { keys }) => {
+                    Kind::AccessKeyList(proto::AccessKeyList {
+                        keys: keys.into_iter().map(Into::into).collect(),
+                    })
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_client_primitives::types::Query;
+    use near_crypto::KeyType;
+
+    #[test]
+    fn uint128() {
+        for x in [0, 1, u64::MAX as u128, u64::MAX as u128 + 1, u128::MAX] {
+            assert_eq!(x, u128::from(proto::Uint128::from(x)));
+        }
+    }
+
+    #[test]
+    fn parse_block_reference() {
+        let reference = |reference| proto::BlockReference { reference: Some(reference) };
+        use proto::block_reference::Reference;
+        assert_eq!(
+            BlockReference::BlockId(BlockId::Height(7)),
+            reference(Reference::BlockHeight(7)).try_into().unwrap()
+        );
+        let block_hash = CryptoHash::hash_bytes(b"block");
+        assert_eq!(
+            BlockReference::BlockId(BlockId::Hash(block_hash)),
+            reference(Reference::BlockHash(block_hash.0.to_vec())).try_into().unwrap()
+        );
+        assert_eq!(
+            BlockReference::Finality(Finality::DoomSlug),
+            reference(Reference::Finality(proto::Finality::NearFinal as i32)).try_into().unwrap()
+        );
+        assert!(BlockReference::try_from(reference(Reference::BlockHash(vec![1, 2]))).is_err());
+        assert!(BlockReference::try_from(reference(Reference::Finality(7))).is_err());
+        assert!(BlockReference::try_from(proto::BlockReference { reference: None }).is_err());
+    }
+
+    #[test]
+    fn parse_query() {
+        let public_key = PublicKey::from_seed(KeyType::ED25519, "test");
+        let request = proto::QueryRequest {
+            block_reference: Some(proto::BlockReference {
+                reference: Some(proto::block_reference::Reference::Finality(
+                    proto::Finality::Final as i32,
+                )),
+            }),
+            request: Some(proto::query_request::Request::ViewAccessKey(
+                proto::ViewAccessKeyRequest {
+                    account_id: "test.near".to_string(),
+                    public_key: Some((&public_key).into()),
+                },
+            )),
+        };
+        let query = Query::try_from(request.clone()).unwrap();
+        assert_eq!(BlockReference::Finality(Finality::Final), query.block_reference);
+        assert_eq!(
+            QueryRequest::ViewAccessKey { account_id: "test.near".parse().unwrap(), public_key },
+            query.request
+        );
+
+        let mut invalid = request;
+        invalid.request =
+            Some(proto::query_request::Request::ViewAccount(proto::ViewAccountRequest {
+                account_id: "Invalid Account".to_string(),
+            }));
+        assert!(Query::try_from(invalid).is_err());
+    }
+}
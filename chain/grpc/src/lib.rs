@@ -0,0 +1,395 @@
+//! gRPC API of the node, an alternative to the JSON RPC for the clients which prefer
+//! a typed binary protocol. It serves blocks, chunks, transaction statuses and queries,
+//! and streams the new blocks, see proto/near.proto for the definitions.
+//!
+//! The handlers forward the requests to the `ViewClientActor`, the same as the JSON RPC does,
+//! and convert the views it returns to the protobuf messages (see convert.rs).
+//! The block stream is driven by the `ChainEventBus` of the node.
+use actix::Addr;
+use futures::StreamExt;
+use near_chain::{ChainEvent, ChainEventBus};
+use near_client::ViewClientActor;
+use near_client_primitives::types::{
+    GetBlock, GetBlockError, GetChunk, GetChunkError, Query, QueryError, TxStatus, TxStatusError,
+};
+use near_o11y::{WithSpanContext, WithSpanContextExt};
+use near_primitives::types::{BlockId, BlockReference};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+mod convert;
+
+pub mod proto {
+    tonic::include_proto!("near.v1");
+}
+
+/// Number of blocks buffered for the block subscribers.
+const BLOCKS_CAPACITY: usize = 128;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GrpcConfig {
+    pub addr: String,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self { addr: "0.0.0.0:3050".to_owned() }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum BlockEvent {
+    Block {
+        is_final: bool,
+        block: Arc<proto::Block>,
+    },
+    /// Blocks may be missing from the stream: either the node dropped chain events because
+    /// forwarding them didn't keep up, or a block couldn't be fetched.
+    Missed {
+        reason: String,
+    },
+}
+
+struct NearService {
+    view_client: Addr<ViewClientActor>,
+    blocks: broadcast::Sender<BlockEvent>,
+}
+
+impl NearService {
+    async fn view_client_send<M, T, E>(&self, msg: M) -> Result<T, Status>
+    where
+        ViewClientActor: actix::Handler<WithSpanContext<M>>,
+        M: actix::Message<Result = Result<T, E>> + Send + 'static,
+        M::Result: Send,
+        Status: From<ErrorStatus<E>>,
+    {
+        match self.view_client.send(msg.with_span_context()).await {
+            Ok(result) => result.map_err(|err| ErrorStatus(err).into()),
+            Err(err) => Err(Status::internal(err.to_string())),
+        }
+    }
+}
+
+/// Wrapper mapping the errors of the view client to the gRPC status codes.
+struct ErrorStatus<E>(E);
+
+impl From<ErrorStatus<GetBlockError>> for Status {
+    fn from(ErrorStatus(err): ErrorStatus<GetBlockError>) -> Self {
+        let message = err.to_string();
+        match err {
+            GetBlockError::UnknownBlock { .. } => Status::not_found(message),
+            GetBlockError::NotSyncedYet => Status::unavailable(message),
+            GetBlockError::IOError { .. } | GetBlockError::Unreachable { .. } => {
+                Status::internal(message)
+            }
+        }
+    }
+}
+
+impl From<ErrorStatus<GetChunkError>> for Status {
+    fn from(ErrorStatus(err): ErrorStatus<GetChunkError>) -> Self {
+        let message = err.to_string();
+        match err {
+            GetChunkError::UnknownBlock { .. } | GetChunkError::UnknownChunk { .. } => {
+                Status::not_found(message)
+            }
+            GetChunkError::InvalidShardId { .. } => Status::invalid_argument(message),
+            GetChunkError::IOError { .. } | GetChunkError::Unreachable { .. } => {
+                Status::internal(message)
+            }
+        }
+    }
+}
+
+impl From<ErrorStatus<TxStatusError>> for Status {
+    fn from(ErrorStatus(err): ErrorStatus<TxStatusError>) -> Self {
+        match err {
+            TxStatusError::MissingTransaction(tx_hash) => {
+                Status::not_found(format!("Transaction {tx_hash} doesn't exist"))
+            }
+            TxStatusError::TimeoutError => Status::deadline_exceeded("Timeout"),
+            TxStatusError::ChainError(err) => Status::internal(err.to_string()),
+            TxStatusError::InternalError(message) => Status::unavailable(message),
+        }
+    }
+}
+
+impl From<ErrorStatus<QueryError>> for Status {
+    fn from(ErrorStatus(err): ErrorStatus<QueryError>) -> Self {
+        let message = err.to_string();
+        match err {
+            QueryError::UnknownAccount { .. }
+            | QueryError::NoContractCode { .. }
+            | QueryError::UnknownAccessKey { .. }
+            | QueryError::UnknownBlock { .. }
+            | QueryError::GarbageCollectedBlock { .. } => Status::not_found(message),
+            QueryError::InvalidAccount { .. }
+            | QueryError::InvalidTransaction { .. }
+            | QueryError::InvalidContinuationToken { .. } => Status::invalid_argument(message),
+            QueryError::UnavailableShard { .. }
+            | QueryError::TooLargeContractState { .. }
+            | QueryError::ContractExecutionError { .. } => Status::failed_precondition(message),
+            QueryError::NoSyncedBlocks | QueryError::InternalError { .. } => {
+                Status::unavailable(message)
+            }
+            QueryError::Unreachable { .. } => Status::internal(message),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl proto::near_server::Near for NearService {
+    async fn get_block(
+        &self,
+        request: Request<proto::BlockRequest>,
+    ) -> Result<Response<proto::Block>, Status> {
+        let block_reference = request.into_inner().block_reference;
+        let block_reference = BlockReference::try_from(
+            block_reference
+                .ok_or_else(|| Status::invalid_argument("block_reference is required"))?,
+        )?;
+        let block = self.view_client_send(GetBlock(block_reference)).await?;
+        Ok(Response::new(block.into()))
+    }
+
+    async fn get_chunk(
+        &self,
+        request: Request<proto::ChunkRequest>,
+    ) -> Result<Response<proto::Chunk>, Status> {
+        let request = GetChunk::try_from(request.into_inner())?;
+        let chunk = self.view_client_send(request).await?;
+        Ok(Response::new(chunk.into()))
+    }
+
+    async fn get_transaction_status(
+        &self,
+        request: Request<proto::TransactionStatusRequest>,
+    ) -> Result<Response<proto::TransactionStatus>, Status> {
+        let request = request.into_inner();
+        let request = TxStatus {
+            tx_hash: convert::parse_hash(&request.tx_hash, "tx_hash")?,
+            signer_account_id: convert::parse_account_id(
+                request.sender_account_id,
+                "sender_account_id",
+            )?,
+            fetch_receipt: request.fetch_receipts,
+        };
+        let status = self.view_client_send(request).await?;
+        Ok(Response::new(status.into()))
+    }
+
+    async fn query(
+        &self,
+        request: Request<proto::QueryRequest>,
+    ) -> Result<Response<proto::QueryResponse>, Status> {
+        let request = Query::try_from(request.into_inner())?;
+        let response = self.view_client_send(request).await?;
+        Ok(Response::new(response.into()))
+    }
+
+    type SubscribeBlocksStream =
+        Pin<Box<dyn futures::Stream<Item = Result<proto::Block, Status>> + Send>>;
+
+    async fn subscribe_blocks(
+        &self,
+        request: Request<proto::SubscribeBlocksRequest>,
+    ) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let final_only = request.into_inner().final_only;
+        let stream = BroadcastStream::new(self.blocks.subscribe()).filter_map(move |event| {
+            futures::future::ready(match event {
+                Ok(BlockEvent::Block { is_final, block }) if is_final == final_only => {
+                    Some(Ok((*block).clone()))
+                }
+                Ok(BlockEvent::Block { .. }) => None,
+                Ok(BlockEvent::Missed { reason }) => Some(Err(Status::data_loss(reason))),
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(Err(Status::data_loss(
+                    format!("The subscriber lagged behind the chain by {skipped} blocks"),
+                ))),
+            })
+        });
+        // The stream ends after the first error, so that the client resubscribes.
+        let stream = stream.scan(false, |failed, item| {
+            let done = *failed;
+            *failed = item.is_err();
+            futures::future::ready(if done { None } else { Some(item) })
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Forwards the new heads of the chain to the block subscribers.
+/// The blocks are fetched only while there are subscribers.
+fn forward_blocks(
+    chain_events: crossbeam_channel::Receiver<ChainEvent>,
+    view_client: Addr<ViewClientActor>,
+) -> broadcast::Sender<BlockEvent> {
+    let (events_sender, mut events) = mpsc::channel(BLOCKS_CAPACITY);
+    std::thread::Builder::new()
+        .name("grpc_chain_events".to_string())
+        .spawn(move || {
+            for event in chain_events {
+                match event {
+                    ChainEvent::NewHead(_)
+                    | ChainEvent::NewFinalHead(_)
+                    | ChainEvent::EventsDropped { .. } => {}
+                    ChainEvent::Reorg { .. } | ChainEvent::ChunkApplied { .. } => continue,
+                }
+                if events_sender.blocking_send(event).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("failed to spawn the thread forwarding chain events");
+    let (blocks, _) = broadcast::channel(BLOCKS_CAPACITY);
+    let sender = blocks.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if sender.receiver_count() == 0 {
+                continue;
+            }
+            let (block_hash, is_final) = match event {
+                ChainEvent::NewHead(tip) => (tip.last_block_hash, false),
+                ChainEvent::NewFinalHead(tip) => (tip.last_block_hash, true),
+                ChainEvent::EventsDropped { count } => {
+                    let reason = format!("The node dropped {count} chain events");
+                    let _ = sender.send(BlockEvent::Missed { reason });
+                    continue;
+                }
+                ChainEvent::Reorg { .. } | ChainEvent::ChunkApplied { .. } => continue,
+            };
+            let request = GetBlock(BlockReference::BlockId(BlockId::Hash(block_hash)));
+            let err = match view_client.send(request.with_span_context()).await {
+                Ok(Ok(block)) => {
+                    let block = Arc::new(block.into());
+                    let _ = sender.send(BlockEvent::Block { is_final, block });
+                    continue;
+                }
+                Ok(Err(err)) => err.to_string(),
+                Err(err) => err.to_string(),
+            };
+            tracing::debug!(target: "grpc", %err, %block_hash, "Failed to get block for subscribers");
+            let reason = format!("Failed to get block {block_hash}: {err}");
+            let _ = sender.send(BlockEvent::Missed { reason });
+        }
+    });
+    blocks
+}
+
+/// Starts the gRPC server in the background. Must be called within the tokio runtime.
+pub fn start_grpc(
+    config: GrpcConfig,
+    view_client: Addr<ViewClientActor>,
+    chain_event_bus: &ChainEventBus,
+) -> anyhow::Result<()> {
+    let listener = std::net::TcpListener::bind(&config.addr)?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    tracing::info!(target: "grpc", addr = %config.addr, "Starting gRPC server");
+    serve(listener, view_client, chain_event_bus.subscribe());
+    Ok(())
+}
+
+/// Serves the gRPC API on the listener in the background, streaming the blocks announced by
+/// the chain events.
+fn serve(
+    listener: tokio::net::TcpListener,
+    view_client: Addr<ViewClientActor>,
+    chain_events: crossbeam_channel::Receiver<ChainEvent>,
+) {
+    let service =
+        NearService { blocks: forward_blocks(chain_events, view_client.clone()), view_client };
+    tokio::spawn(async move {
+        if let Err(err) = tonic::transport::Server::builder()
+            .add_service(proto::near_server::NearServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+        {
+            tracing::error!(target: "grpc", ?err, "gRPC server failed");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{proto, serve};
+    use actix::System;
+    use near_actix_test_utils::run_actix;
+    use near_chain::ChainEvent;
+    use near_client::test_utils::setup_no_network;
+    use near_o11y::testonly::init_test_logger;
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::EpochId;
+    use proto::block_reference::Reference;
+
+    fn block_request(reference: Option<Reference>) -> proto::BlockRequest {
+        proto::BlockRequest { block_reference: Some(proto::BlockReference { reference }) }
+    }
+
+    #[test]
+    fn test_grpc_round_trip() {
+        init_test_logger();
+        run_actix(async {
+            let actor_handles = setup_no_network(
+                vec!["test1".parse().unwrap()],
+                "other".parse().unwrap(),
+                true,
+                false,
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (events_sender, events) = crossbeam_channel::unbounded();
+            serve(listener, actor_handles.view_client_actor, events);
+            let mut client =
+                proto::near_client::NearClient::connect(format!("http://{addr}")).await.unwrap();
+
+            let genesis = client
+                .get_block(block_request(Some(Reference::BlockHeight(0))))
+                .await
+                .unwrap()
+                .into_inner()
+                .header
+                .unwrap();
+            assert_eq!(genesis.height, 0);
+
+            // Errors of the view client and invalid requests are mapped to the status codes.
+            let err = client
+                .get_block(block_request(Some(Reference::BlockHash(vec![1; 32]))))
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::NotFound);
+            let err = client.get_block(block_request(None)).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+            let mut blocks = client
+                .subscribe_blocks(proto::SubscribeBlocksRequest { final_only: false })
+                .await
+                .unwrap()
+                .into_inner();
+            let tip = Tip {
+                height: 0,
+                last_block_hash: CryptoHash::try_from(&genesis.hash[..]).unwrap(),
+                prev_block_hash: CryptoHash::default(),
+                epoch_id: EpochId::default(),
+                next_epoch_id: EpochId::default(),
+            };
+            // Only the new heads are streamed to this subscriber.
+            events_sender.send(ChainEvent::NewFinalHead(tip.clone())).unwrap();
+            events_sender.send(ChainEvent::NewHead(tip)).unwrap();
+            let block = blocks.message().await.unwrap().unwrap();
+            assert_eq!(block.header.unwrap().hash, genesis.hash);
+
+            // Dropped chain events fail the stream, so that the client resubscribes.
+            events_sender.send(ChainEvent::EventsDropped { count: 3 }).unwrap();
+            let err = blocks.message().await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::DataLoss);
+            assert_eq!(blocks.message().await.unwrap(), None);
+
+            System::current().stop();
+        });
+    }
+}
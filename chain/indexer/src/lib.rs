@@ -89,6 +89,7 @@ pub struct Indexer {
     near_config: nearcore::NearConfig,
     view_client: actix::Addr<near_client::ViewClientActor>,
     client: actix::Addr<near_client::ClientActor>,
+    chain_event_bus: nearcore::ChainEventBus,
 }
 
 impl Indexer {
@@ -116,10 +117,10 @@ impl Indexer {
             ",
             indexer_config.home_dir.join("config.json").display()
         );
-        let nearcore::NearNode { client, view_client, .. } =
+        let nearcore::NearNode { client, view_client, chain_event_bus, .. } =
             nearcore::start_with_config(&indexer_config.home_dir, near_config.clone())
                 .with_context(|| "start_with_config")?;
-        Ok(Self { view_client, client, near_config, indexer_config })
+        Ok(Self { view_client, client, chain_event_bus, near_config, indexer_config })
     }
 
     /// Boots up `near_indexer::streamer`, so it monitors the new blocks with chunks, transactions, receipts, and execution outcomes inside. The returned stream handler should be drained and handled on the user side.
@@ -136,6 +137,13 @@ impl Indexer {
         receiver
     }
 
+    /// Bus of events of the chain, e.g. new heads and reorgs, published as
+    /// soon as the node processes blocks. Unlike `streamer` it doesn't wait
+    /// for blocks to be final.
+    pub fn chain_event_bus(&self) -> &nearcore::ChainEventBus {
+        &self.chain_event_bus
+    }
+
     /// Expose neard config
     pub fn near_config(&self) -> &nearcore::NearConfig {
         &self.near_config
@@ -35,6 +35,7 @@ pub enum RpcErrorKind {
 pub enum RpcRequestValidationErrorKind {
     MethodNotFound { method_name: String },
     ParseError { error_message: String },
+    TooManyRequests { method_name: String },
 }
 
 /// A general Server Error
@@ -148,6 +149,18 @@ impl RpcError {
             )),
         }
     }
+
+    /// Create an error for a request rejected by the rate limits.
+    pub fn too_many_requests(method: String) -> Self {
+        RpcError {
+            code: -32_000,
+            message: "Too many requests".to_owned(),
+            data: Some(Value::String(method.clone())),
+            error_struct: Some(RpcErrorKind::RequestValidationError(
+                RpcRequestValidationErrorKind::TooManyRequests { method_name: method },
+            )),
+        }
+    }
 }
 
 impl fmt::Display for RpcError {
@@ -0,0 +1,16 @@
+use near_primitives::views::PeerFilterRuleView;
+
+/// Body of `POST /admin/api/peer_filter/add`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RpcAddPeerFilterRuleRequest {
+    pub rule: PeerFilterRuleView,
+    /// The rule expires after that many seconds. It is kept until removed if not specified.
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+}
+
+/// Body of `POST /admin/api/peer_filter/remove`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RpcRemovePeerFilterRuleRequest {
+    pub rule: PeerFilterRuleView,
+}
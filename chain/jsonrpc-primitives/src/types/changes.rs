@@ -8,6 +8,9 @@ pub struct RpcStateChangesInBlockRequest {
 pub struct RpcStateChangesInBlockResponse {
     pub block_hash: near_primitives::hash::CryptoHash,
     pub changes: near_primitives::views::StateChangesView,
+    /// Set if the changes were paginated and there are more of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<near_primitives::views::ContinuationToken>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -16,6 +19,10 @@ pub struct RpcStateChangesInBlockByTypeRequest {
     pub block_reference: near_primitives::types::BlockReference,
     #[serde(flatten)]
     pub state_changes_request: near_primitives::views::StateChangesRequestView,
+    /// If set, the changes are returned in pages of limited size. The next pages are
+    /// served from the block of the first page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<near_primitives::views::Pagination>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -34,6 +41,8 @@ pub enum RpcStateChangesError {
     },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
+    #[error("Invalid continuation token: {error_message}")]
+    InvalidContinuationToken { error_message: String },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
@@ -0,0 +1,34 @@
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcTransactionExecutionTreeRequest {
+    pub tx_hash: near_primitives::hash::CryptoHash,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcTransactionExecutionTreeResponse {
+    #[serde(flatten)]
+    pub execution_tree: near_primitives::views::TransactionExecutionTreeView,
+}
+
+#[derive(thiserror::Error, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcTransactionExecutionTreeError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Transaction {transaction_hash} has never been observed on this node")]
+    UnknownTransaction { transaction_hash: near_primitives::hash::CryptoHash },
+}
+
+impl From<RpcTransactionExecutionTreeError> for crate::errors::RpcError {
+    fn from(error: RpcTransactionExecutionTreeError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcTransactionExecutionTreeError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -0,0 +1,52 @@
+use near_primitives::serialize::dec_format;
+use near_primitives::types::{Balance, NumBlocks, ShardId};
+use near_primitives::views::GasCongestionView;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcGasCongestionRequest {
+    /// Number of most recent blocks to count the gas usage over.
+    #[serde(default)]
+    pub num_blocks: Option<NumBlocks>,
+    /// Returns only the given shard if set.
+    #[serde(default)]
+    pub shard_id: Option<ShardId>,
+    /// Gas price the transaction is going to pay. The inclusion delay isn't estimated
+    /// if it is below the current gas price.
+    #[serde(default, with = "dec_format")]
+    pub gas_price: Option<Balance>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcGasCongestionResponse {
+    #[serde(flatten)]
+    pub congestion: GasCongestionView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcGasCongestionError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<RpcGasCongestionError> for crate::errors::RpcError {
+    fn from(error: RpcGasCongestionError) -> Self {
+        let error_data = match &error {
+            RpcGasCongestionError::InternalError { .. } => Some(Value::String(error.to_string())),
+        };
+
+        let error_data_value = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcGasCongestionError: {:?}", err),
+                )
+            }
+        };
+
+        Self::new_internal_or_handler_error(error_data, error_data_value)
+    }
+}
@@ -8,6 +8,17 @@ pub struct RpcLightClientExecutionProofRequest {
     pub light_client_head: near_primitives::hash::CryptoHash,
 }
 
+/// Proves the value of a contract storage key in the state committed in the header of
+/// `block_hash`, which must be final and not after `light_client_head`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RpcLightClientStateProofRequest {
+    pub account_id: near_primitives::types::AccountId,
+    #[serde(rename = "key_base64")]
+    pub key: near_primitives::types::StoreKey,
+    pub block_hash: near_primitives::hash::CryptoHash,
+    pub light_client_head: near_primitives::hash::CryptoHash,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct RpcLightClientNextBlockRequest {
     pub last_block_hash: near_primitives::hash::CryptoHash,
@@ -21,6 +32,14 @@ pub struct RpcLightClientExecutionProofResponse {
     pub block_proof: near_primitives::merkle::MerklePath,
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RpcLightClientStateProofResponse {
+    #[serde(flatten)]
+    pub state_proof: near_primitives::views::StateProofView,
+    pub block_header_lite: near_primitives::views::LightClientBlockLiteView,
+    pub block_proof: near_primitives::merkle::MerklePath,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct RpcLightClientNextBlockResponse {
     #[serde(flatten)]
@@ -53,6 +72,23 @@ pub enum RpcLightClientProofError {
     InternalError { error_message: String },
 }
 
+#[derive(thiserror::Error, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcLightClientStateProofError {
+    #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
+    UnknownBlock {
+        #[serde(skip_serializing)]
+        error_message: String,
+    },
+    #[error("Node doesn't track the shard {shard_id} of the account {account_id}")]
+    UnavailableShard {
+        account_id: near_primitives::types::AccountId,
+        shard_id: near_primitives::types::ShardId,
+    },
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+}
+
 #[derive(thiserror::Error, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcLightClientNextBlockError {
@@ -90,6 +126,29 @@ impl From<RpcLightClientProofError> for crate::errors::RpcError {
     }
 }
 
+impl From<RpcLightClientStateProofError> for crate::errors::RpcError {
+    fn from(error: RpcLightClientStateProofError) -> Self {
+        let error_data = match &error {
+            RpcLightClientStateProofError::UnknownBlock { error_message } => {
+                Some(Value::String(format!("DB Not Found Error: {}", error_message)))
+            }
+            _ => Some(Value::String(error.to_string())),
+        };
+
+        let error_data_value = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcLightClientStateProofError: {:?}", err),
+                )
+            }
+        };
+
+        Self::new_internal_or_handler_error(error_data, error_data_value)
+    }
+}
+
 impl From<RpcLightClientNextBlockError> for crate::errors::RpcError {
     fn from(error: RpcLightClientNextBlockError) -> Self {
         let error_data = match serde_json::to_value(error) {
@@ -1,9 +1,12 @@
+pub mod admin;
 pub mod blocks;
 pub mod changes;
 pub mod chunks;
 pub mod client_config;
 pub mod config;
 pub mod entity_debug;
+pub mod execution_tree;
+pub mod gas_congestion;
 pub mod gas_price;
 pub mod light_client;
 pub mod maintenance;
@@ -11,7 +14,10 @@ pub mod network_info;
 pub mod query;
 pub mod receipts;
 pub mod sandbox;
+pub mod simulation;
 pub mod split_storage;
 pub mod status;
+pub mod subscriptions;
 pub mod transactions;
 pub mod validator;
+pub mod validator_participation;
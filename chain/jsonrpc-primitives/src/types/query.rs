@@ -6,6 +6,14 @@ pub struct RpcQueryRequest {
     pub request: near_primitives::views::QueryRequest,
 }
 
+/// Multiple view requests, executed against the state of the same block.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcQueryBatchRequest {
+    #[serde(flatten)]
+    pub block_reference: near_primitives::types::BlockReference,
+    pub requests: Vec<near_primitives::views::QueryRequest>,
+}
+
 #[derive(thiserror::Error, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcQueryError {
@@ -48,6 +56,12 @@ pub enum RpcQueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Simulated transaction is invalid: {context}")]
+    InvalidTransaction {
+        context: near_primitives::errors::InvalidTxError,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("Access key for public key {public_key} has never been observed on the node")]
     UnknownAccessKey {
         public_key: near_crypto::PublicKey,
@@ -60,6 +74,11 @@ pub enum RpcQueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Continuation token is bound to block {token_block_hash}, not to the queried block {block_hash}")]
+    InvalidContinuationToken {
+        token_block_hash: near_primitives::hash::CryptoHash,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
@@ -72,6 +91,22 @@ pub struct RpcQueryResponse {
     pub block_hash: near_primitives::hash::CryptoHash,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcQueryBatchResponse {
+    /// Results of the requests, in the same order.
+    pub results: Vec<RpcQueryBatchResult>,
+    pub block_height: near_primitives::types::BlockHeight,
+    pub block_hash: near_primitives::hash::CryptoHash,
+}
+
+/// Result of a single request of the batch. Requests fail independently of each other.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcQueryBatchResult {
+    Result(QueryResponseKind),
+    Error(crate::errors::RpcError),
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 #[serde(untagged)]
 pub enum QueryResponseKind {
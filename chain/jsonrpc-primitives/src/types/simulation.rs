@@ -0,0 +1,22 @@
+use near_primitives::hash::CryptoHash;
+use near_primitives::state_record::StateRecord;
+use near_primitives::types::{BlockHeight, BlockReference};
+use near_primitives::views::{SimulationRequest, SimulationResultView};
+
+/// Runs a view call or a transaction on top of the state of the block, with the given
+/// state records overriding the state. Fails with `RpcQueryError`.
+#[derive(Debug, Clone)]
+pub struct RpcSimulateRequest {
+    pub block_reference: BlockReference,
+    /// Only `Account`, `AccessKey`, `Contract` and `Data` records are supported.
+    pub state_overrides: Vec<StateRecord>,
+    pub request: SimulationRequest,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcSimulateResponse {
+    #[serde(flatten)]
+    pub result: SimulationResultView,
+    pub block_height: BlockHeight,
+    pub block_hash: CryptoHash,
+}
@@ -4,8 +4,10 @@ use near_client_primitives::debug::{
 };
 #[cfg(feature = "debug_types")]
 use near_primitives::views::{
-    CatchupStatusView, ChainProcessingInfo, NetworkGraphView, NetworkRoutesView, PeerStoreView,
-    RecentOutboundConnectionsView, RequestedStatePartsView, SyncStatusView,
+    BlockTimelineView, CatchupStatusView, ChainProcessingInfo, ColumnStatsView,
+    NetworkBandwidthView, NetworkGraphView, NetworkRoutesView, PeerStoreView,
+    QuarantinedBlockDetailsView, QuarantinedBlockView, RecentOutboundConnectionsView,
+    RequestedStatePartsView, RouteTracesView, SyncStatusView, TransactionPoolView,
 };
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -30,9 +32,21 @@ pub enum DebugStatusResponse {
     ChainProcessingStatus(ChainProcessingInfo),
     // The state parts already requested.
     RequestedStateParts(Vec<RequestedStatePartsView>),
+    // Approximate sizes of the columns of the hot store.
+    StoreColumnStats(Vec<ColumnStatsView>),
+    // Timelines of processing the most recent blocks, newest first.
+    BlockTimelines(Vec<BlockTimelineView>),
+    // Blocks which failed validation, most recently quarantined first.
+    QuarantinedBlocks(Vec<QuarantinedBlockView>),
+    // None if there's no such block in the quarantine.
+    QuarantinedBlock(Option<QuarantinedBlockDetailsView>),
+    // Occupancy of the transaction pool of every shard, ordered by shard.
+    TransactionPool(Vec<TransactionPoolView>),
     NetworkGraph(NetworkGraphView),
     RecentOutboundConnections(RecentOutboundConnectionsView),
     Routes(NetworkRoutesView),
+    NetworkBandwidth(NetworkBandwidthView),
+    RouteTraces(RouteTracesView),
 }
 
 #[cfg(feature = "debug_types")]
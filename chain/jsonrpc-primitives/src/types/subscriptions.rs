@@ -0,0 +1,82 @@
+//! Requests and notifications of the websocket subscription endpoint (`/ws`).
+//!
+//! The endpoint speaks JSON RPC: a client calls the `subscribe` method with one of the
+//! `RpcSubscribeRequest` channels and gets the id of the subscription back. The node then
+//! sends a `subscription` notification with an `RpcSubscriptionNotification` for every update
+//! of the channel, until the client calls `unsubscribe` or closes the connection.
+//!
+//! If the node drops some updates, e.g. because the client doesn't keep up with the chain,
+//! it sends a `missed_updates` notification with an `RpcMissedUpdatesNotification`. The
+//! subscriptions stay active and the client should catch up with the regular methods, e.g.
+//! `block` and `EXPERIMENTAL_changes`.
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+pub enum RpcSubscribeRequest {
+    /// Every new head of the chain, as a `BlockView`.
+    NewHeads,
+    /// Every new final block, as a `BlockView`.
+    FinalizedBlocks,
+    /// State changes matching the request in every new head, as
+    /// `RpcStateChangesInBlockResponse`. Blocks without matching changes are skipped.
+    StateChanges {
+        #[serde(flatten)]
+        state_changes_request: near_primitives::views::StateChangesRequestView,
+    },
+    /// Outcome of the transaction, as `RpcTransactionResponse`, once it is known.
+    /// The subscription ends after the notification.
+    TxStatus {
+        tx_hash: near_primitives::hash::CryptoHash,
+        sender_account_id: near_primitives::types::AccountId,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcUnsubscribeRequest {
+    pub subscription: u64,
+}
+
+/// Params of the `subscription` notification.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcSubscriptionNotification {
+    pub subscription: u64,
+    pub result: serde_json::Value,
+}
+
+/// Params of the `missed_updates` notification.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcMissedUpdatesNotification {
+    /// Number of the updates of the chain (new heads and final blocks) which were dropped.
+    pub skipped: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RpcSubscribeRequest;
+    use near_primitives::views::StateChangesRequestView;
+
+    #[test]
+    fn test_subscribe_request_format() {
+        let request: RpcSubscribeRequest =
+            serde_json::from_str(r#"{"channel": "new_heads"}"#).unwrap();
+        assert!(matches!(request, RpcSubscribeRequest::NewHeads));
+
+        let request: RpcSubscribeRequest = serde_json::from_str(
+            r#"{
+                "channel": "state_changes",
+                "changes_type": "data_changes",
+                "account_ids": ["test.near"],
+                "key_prefix_base64": "c3RhdGU="
+            }"#,
+        )
+        .unwrap();
+        let RpcSubscribeRequest::StateChanges {
+            state_changes_request: StateChangesRequestView::DataChanges { account_ids, key_prefix },
+        } = request
+        else {
+            panic!("unexpected request");
+        };
+        assert_eq!(account_ids, vec!["test.near".parse().unwrap()]);
+        assert_eq!(*key_prefix, b"state".to_vec());
+    }
+}
@@ -31,6 +31,8 @@ pub enum RpcTransactionError {
     DoesNotTrackShard,
     #[error("Transaction with hash {transaction_hash} was routed")]
     RequestRouted { transaction_hash: near_primitives::hash::CryptoHash },
+    #[error("Transaction was rejected by the transaction pool: {reason}")]
+    RejectedByTransactionPool { reason: String },
     #[error("Transaction {requested_transaction_hash} doesn't exist")]
     UnknownTransaction { requested_transaction_hash: near_primitives::hash::CryptoHash },
     #[error("The node reached its limits. Try again later. More details: {debug_info}")]
@@ -46,6 +48,58 @@ pub struct RpcTransactionResponse {
     pub final_execution_status: near_primitives::views::TxExecutionStatus,
 }
 
+/// Status of the transaction which `send_tx` waits for before responding.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TxWaitUntil {
+    /// Respond as soon as the transaction is accepted by the node.
+    None,
+    /// Wait until the transaction is included into a block.
+    Included,
+    /// Wait until all the receipts of the transaction are executed. The blocks may be
+    /// not final yet.
+    #[default]
+    ExecutedOptimistic,
+    /// Wait until the execution of all the receipts of the transaction is final.
+    Final,
+}
+
+impl TxWaitUntil {
+    pub fn is_reached(&self, status: &near_primitives::views::TxExecutionStatus) -> bool {
+        use near_primitives::views::TxExecutionStatus;
+        match self {
+            TxWaitUntil::None => true,
+            TxWaitUntil::Included => *status != TxExecutionStatus::None,
+            TxWaitUntil::ExecutedOptimistic => {
+                matches!(status, TxExecutionStatus::Executed | TxExecutionStatus::Final)
+            }
+            TxWaitUntil::Final => *status == TxExecutionStatus::Final,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcSendTransactionRequest {
+    pub signed_transaction: near_primitives::transaction::SignedTransaction,
+    pub wait_until: TxWaitUntil,
+    /// How long to wait for `wait_until`, capped by the polling timeout of the node.
+    pub timeout: Option<std::time::Duration>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RpcSendTransactionResponse {
+    pub transaction_hash: near_primitives::hash::CryptoHash,
+    /// True if the node doesn't track the shard of the signer and has routed the transaction
+    /// to a node which does.
+    pub routed: bool,
+    /// True if the timeout expired before the transaction reached the requested status.
+    /// The transaction is still being processed, and its status can be polled with `tx`.
+    pub pending: bool,
+    #[serde(flatten)]
+    pub final_execution_outcome: Option<near_primitives::views::FinalExecutionOutcomeViewEnum>,
+    pub final_execution_status: near_primitives::views::TxExecutionStatus,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct RpcBroadcastTxSyncResponse {
     pub transaction_hash: near_primitives::hash::CryptoHash,
@@ -0,0 +1,46 @@
+use near_primitives::views::EpochParticipationView;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorParticipationRequest {
+    /// Number of most recent epochs to return.  All the epochs the node keeps
+    /// if not set.
+    #[serde(default)]
+    pub num_epochs: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorParticipationResponse {
+    /// The most recent epoch first.
+    pub epochs: Vec<EpochParticipationView>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcValidatorParticipationError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<RpcValidatorParticipationError> for crate::errors::RpcError {
+    fn from(error: RpcValidatorParticipationError) -> Self {
+        let error_data = match &error {
+            RpcValidatorParticipationError::InternalError { .. } => {
+                Some(Value::String(error.to_string()))
+            }
+        };
+
+        let error_data_value = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcValidatorParticipationError: {:?}", err),
+                )
+            }
+        };
+
+        Self::new_internal_or_handler_error(error_data, error_data_value)
+    }
+}
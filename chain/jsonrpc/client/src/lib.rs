@@ -2,16 +2,22 @@ use awc::{Client, Connector};
 use futures::{future, future::LocalBoxFuture, FutureExt, TryFutureExt};
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{from_slice, Message};
+use near_jsonrpc_primitives::types::admin::{
+    RpcAddPeerFilterRuleRequest, RpcRemovePeerFilterRuleRequest,
+};
 use near_jsonrpc_primitives::types::changes::{
     RpcStateChangesInBlockByTypeRequest, RpcStateChangesInBlockByTypeResponse,
 };
-use near_jsonrpc_primitives::types::transactions::RpcTransactionResponse;
+use near_jsonrpc_primitives::types::transactions::{
+    RpcSendTransactionResponse, RpcTransactionResponse, TxWaitUntil,
+};
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, BlockId, BlockReference, MaybeBlockId, ShardId};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, GasPriceView, StatusResponse,
+    BlockView, ChunkView, EpochValidatorInfo, GasPriceView, PeerFilterRuleView, PeerFilterView,
+    StatusResponse,
 };
 use std::time::Duration;
 
@@ -234,6 +240,52 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_light_client_state_proof(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofResponse>
+    {
+        call_method(
+            &self.client,
+            &self.server_addr,
+            "EXPERIMENTAL_light_client_state_proof",
+            request,
+        )
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_query_batch(
+        &self,
+        request: near_jsonrpc_primitives::types::query::RpcQueryBatchRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::query::RpcQueryBatchResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_query_batch", request)
+    }
+
+    /// Takes the params as JSON, as the transactions are sent base64-encoded,
+    /// e.g. `{"finality": "final", "simulation_type": "transaction", "signed_tx_base64": ..}`.
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_simulate(
+        &self,
+        params: serde_json::Value,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::simulation::RpcSimulateResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_simulate", params)
+    }
+
+    /// Sends the base64-encoded signed transaction and waits until it reaches the given
+    /// status.
+    pub fn send_tx(
+        &self,
+        signed_tx_base64: String,
+        wait_until: TxWaitUntil,
+    ) -> RpcRequest<RpcSendTransactionResponse> {
+        let params = serde_json::json!({
+            "signed_tx_base64": signed_tx_base64,
+            "wait_until": wait_until,
+        });
+        call_method(&self.client, &self.server_addr, "send_tx", params)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_receipt(
         &self,
@@ -242,6 +294,24 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_execution_tree(
+        &self,
+        request: near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeRequest,
+    ) -> RpcRequest<
+        near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeResponse,
+    > {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_execution_tree", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_gas_congestion(
+        &self,
+        request: near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_gas_congestion", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
@@ -284,3 +354,87 @@ http_client!(pub struct HttpClient {
 pub fn new_http_client(server_addr: &str) -> HttpClient {
     HttpClient::new(server_addr, create_client())
 }
+
+/// Parses the body of a response to an HTTP request.
+fn parse_http_response<R>(
+    request: awc::SendClientRequest,
+) -> impl futures::Future<Output = Result<R, String>>
+where
+    R: serde::de::DeserializeOwned + 'static,
+{
+    request.map_err(|err| err.to_string()).and_then(|mut response| {
+        let status = response.status();
+        response.body().map(move |body| match body {
+            Ok(bytes) if status.is_success() => {
+                serde_json::from_slice(&bytes).map_err(|err| err.to_string())
+            }
+            Ok(bytes) => Err(format!("{status}: {}", String::from_utf8_lossy(&bytes))),
+            Err(err) => Err(format!("Payload error: {err}")),
+        })
+    })
+}
+
+/// Client of the admin endpoints (`/admin/api/...`), authenticated with the admin token
+/// from the RPC config of the node.
+pub struct AdminClient {
+    server_addr: String,
+    token: String,
+    client: Client,
+}
+
+impl AdminClient {
+    pub fn new(server_addr: &str, token: &str, client: Client) -> Self {
+        AdminClient { server_addr: server_addr.to_string(), token: token.to_string(), client }
+    }
+
+    fn get<R>(&self, path: &str) -> HttpRequest<R>
+    where
+        R: serde::de::DeserializeOwned + 'static,
+    {
+        let request = self
+            .client
+            .get(format!("{}/{}", self.server_addr, path))
+            .bearer_auth(&self.token)
+            .send();
+        parse_http_response(request).boxed_local()
+    }
+
+    fn post<P, R>(&self, path: &str, body: &P) -> HttpRequest<R>
+    where
+        P: serde::Serialize,
+        R: serde::de::DeserializeOwned + 'static,
+    {
+        let request = self
+            .client
+            .post(format!("{}/{}", self.server_addr, path))
+            .bearer_auth(&self.token)
+            .send_json(body);
+        parse_http_response(request).boxed_local()
+    }
+
+    /// Returns the rules of the peer filter of the node.
+    pub fn get_peer_filter(&self) -> HttpRequest<PeerFilterView> {
+        self.get("admin/api/peer_filter")
+    }
+
+    /// Adds a rule to the peer filter, which expires after `ttl` if specified.
+    pub fn add_peer_filter_rule(
+        &self,
+        rule: PeerFilterRuleView,
+        ttl: Option<Duration>,
+    ) -> HttpRequest<PeerFilterView> {
+        let request =
+            RpcAddPeerFilterRuleRequest { rule, ttl_seconds: ttl.map(|ttl| ttl.as_secs()) };
+        self.post("admin/api/peer_filter/add", &request)
+    }
+
+    /// Removes a rule from the peer filter.
+    pub fn remove_peer_filter_rule(&self, rule: PeerFilterRuleView) -> HttpRequest<PeerFilterView> {
+        self.post("admin/api/peer_filter/remove", &RpcRemovePeerFilterRuleRequest { rule })
+    }
+}
+
+/// Create new admin client that connects to the given address, authenticated with `token`.
+pub fn new_admin_client(server_addr: &str, token: &str) -> AdminClient {
+    AdminClient::new(server_addr, token, create_client())
+}
@@ -52,6 +52,7 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
         actor_handles.view_client_actor.clone(),
         None,
         Arc::new(DummyEntityDebugHandler {}),
+        None,
     );
     (actor_handles.view_client_actor, addr)
 }
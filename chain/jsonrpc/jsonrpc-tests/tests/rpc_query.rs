@@ -8,7 +8,8 @@ use serde_json::json;
 use near_actix_test_utils::run_actix;
 use near_crypto::{KeyType, PublicKey, Signature};
 use near_jsonrpc::client::{new_client, ChunkId};
-use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionRequest;
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryBatchResult};
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_network::test_utils::wait_or_timeout;
 use near_o11y::testonly::init_test_logger;
@@ -288,6 +289,39 @@ fn test_query_access_key() {
     });
 }
 
+/// Connect to json rpc and query an account and its access key at the same block.
+#[test]
+fn test_query_batch() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let response = client
+            .EXPERIMENTAL_query_batch(near_jsonrpc_primitives::types::query::RpcQueryBatchRequest {
+                block_reference: BlockReference::latest(),
+                requests: vec![
+                    QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+                    QueryRequest::ViewAccessKeyList { account_id: "test".parse().unwrap() },
+                    QueryRequest::ViewAccount { account_id: "unknown.test".parse().unwrap() },
+                ],
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.block_height, 0);
+        assert_eq!(response.results.len(), 3);
+        match &response.results[0] {
+            RpcQueryBatchResult::Result(QueryResponseKind::ViewAccount(account)) => {
+                assert_eq!(account.amount, 0)
+            }
+            result => panic!("queried account, but received something else: {:?}", result),
+        }
+        match &response.results[1] {
+            RpcQueryBatchResult::Result(QueryResponseKind::AccessKeyList(list)) => {
+                assert_eq!(list.keys.len(), 1)
+            }
+            result => panic!("queried access keys, but received something else: {:?}", result),
+        }
+        assert!(matches!(response.results[2], RpcQueryBatchResult::Error(_)));
+    });
+}
+
 /// Connect to json rpc and query state.
 #[test]
 fn test_query_state() {
@@ -299,6 +333,7 @@ fn test_query_state() {
                     account_id: "test".parse().unwrap(),
                     prefix: vec![].into(),
                     include_proof: false,
+                    pagination: None,
                 },
             })
             .await
@@ -504,6 +539,33 @@ fn test_gas_price() {
     });
 }
 
+/// Retrieve gas usage of the shards with estimated inclusion delays
+#[test]
+fn test_gas_congestion() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let request = RpcGasCongestionRequest { num_blocks: None, shard_id: None, gas_price: None };
+        let congestion = client.EXPERIMENTAL_gas_congestion(request).await.unwrap().congestion;
+        assert!(congestion.gas_price > 0);
+        assert!(congestion.num_blocks >= 1);
+        assert!(!congestion.shards.is_empty());
+        for shard in &congestion.shards {
+            // The chunks are empty, so a new transaction makes it into the next chunk.
+            assert_eq!(shard.gas_used, 0);
+            assert_eq!(shard.estimated_inclusion_delay, Some(1));
+        }
+
+        let request = RpcGasCongestionRequest {
+            num_blocks: Some(1),
+            shard_id: Some(0),
+            gas_price: Some(congestion.gas_price - 1),
+        };
+        let congestion = client.EXPERIMENTAL_gas_congestion(request).await.unwrap().congestion;
+        assert_eq!(congestion.num_blocks, 1);
+        assert!(congestion.shards.iter().all(|shard| shard.shard_id == 0));
+        assert!(congestion.shards.iter().all(|shard| shard.estimated_inclusion_delay.is_none()));
+    });
+}
+
 #[test]
 fn test_invalid_methods() {
     test_with_client!(test_utils::NodeType::NonValidator, client, async move {
@@ -7,6 +7,8 @@ use futures::{future, FutureExt, TryFutureExt};
 use near_actix_test_utils::run_actix;
 use near_crypto::{InMemorySigner, KeyType};
 use near_jsonrpc::client::new_client;
+use near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeRequest;
+use near_jsonrpc_primitives::types::transactions::TxWaitUntil;
 use near_network::test_utils::WaitOrTimeoutActor;
 use near_o11y::testonly::{init_integration_logger, init_test_logger};
 use near_primitives::hash::{hash, CryptoHash};
@@ -107,6 +109,86 @@ fn test_send_tx_commit() {
     });
 }
 
+/// Test sending transactions which wait for different statuses.
+#[test]
+fn test_send_tx_wait_until() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let make_tx = |nonce| {
+            SignedTransaction::send_money(
+                nonce,
+                "test1".parse().unwrap(),
+                "test2".parse().unwrap(),
+                &signer,
+                100,
+                block_hash,
+            )
+        };
+
+        let tx = make_tx(1);
+        let bytes = tx.try_to_vec().unwrap();
+        let result = client.send_tx(to_base64(&bytes), TxWaitUntil::None).await.unwrap();
+        assert_eq!(result.transaction_hash, tx.get_hash());
+        assert!(!result.pending);
+        assert!(result.final_execution_outcome.is_none());
+
+        let tx = make_tx(2);
+        let bytes = tx.try_to_vec().unwrap();
+        let result =
+            client.send_tx(to_base64(&bytes), TxWaitUntil::ExecutedOptimistic).await.unwrap();
+        assert!(!result.pending);
+        assert!(
+            vec![TxExecutionStatus::Executed, TxExecutionStatus::Final]
+                .contains(&result.final_execution_status),
+            "All the receipts should be already executed"
+        );
+        assert_eq!(
+            result.final_execution_outcome.unwrap().into_outcome().status,
+            FinalExecutionStatus::SuccessValue(Vec::new())
+        );
+    });
+}
+
+/// Test retrieving the receipt execution tree of a committed transaction.
+#[test]
+fn test_execution_tree() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            block_hash,
+        );
+        let bytes = tx.try_to_vec().unwrap();
+        client.broadcast_tx_commit(to_base64(&bytes)).await.unwrap();
+        let tree = client
+            .EXPERIMENTAL_execution_tree(RpcTransactionExecutionTreeRequest {
+                tx_hash: tx.get_hash(),
+            })
+            .await
+            .unwrap()
+            .execution_tree;
+        assert_eq!(tree.transaction.hash, tx.get_hash());
+        assert_eq!(tree.transaction_outcome.id, tx.get_hash());
+        let receipt_ids: Vec<_> = tree.receipts.iter().map(|r| r.receipt_id).collect();
+        assert_eq!(receipt_ids, tree.transaction_outcome.outcome.receipt_ids);
+
+        let err = client
+            .EXPERIMENTAL_execution_tree(RpcTransactionExecutionTreeRequest {
+                tx_hash: CryptoHash::default(),
+            })
+            .await
+            .unwrap_err();
+        let err = serde_json::to_string(&err).unwrap();
+        assert!(err.contains("UNKNOWN_TRANSACTION"), "{err}");
+    });
+}
+
 /// Test that expired transaction should be rejected
 #[test]
 fn test_expired_tx() {
@@ -0,0 +1,39 @@
+use super::{Params, RpcFrom, RpcRequest};
+use near_client_primitives::types::GetTransactionExecutionTreeError;
+use near_jsonrpc_primitives::errors::RpcParseError;
+use near_jsonrpc_primitives::types::execution_tree::{
+    RpcTransactionExecutionTreeError, RpcTransactionExecutionTreeRequest,
+};
+use serde_json::Value;
+
+impl RpcRequest for RpcTransactionExecutionTreeRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
+impl RpcFrom<actix::MailboxError> for RpcTransactionExecutionTreeError {
+    fn rpc_from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl RpcFrom<GetTransactionExecutionTreeError> for RpcTransactionExecutionTreeError {
+    fn rpc_from(error: GetTransactionExecutionTreeError) -> Self {
+        match error {
+            GetTransactionExecutionTreeError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            GetTransactionExecutionTreeError::UnknownTransaction(transaction_hash) => {
+                Self::UnknownTransaction { transaction_hash }
+            }
+            GetTransactionExecutionTreeError::Unreachable(ref error_message) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcTransactionExecutionTreeError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use near_client_primitives::types::GetGasCongestionError;
+use near_jsonrpc_primitives::{
+    errors::RpcParseError,
+    types::gas_congestion::{RpcGasCongestionError, RpcGasCongestionRequest},
+};
+use serde_json::Value;
+
+use super::{Params, RpcFrom, RpcRequest};
+
+impl RpcRequest for RpcGasCongestionRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
+impl RpcFrom<actix::MailboxError> for RpcGasCongestionError {
+    fn rpc_from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl RpcFrom<GetGasCongestionError> for RpcGasCongestionError {
+    fn rpc_from(error: GetGasCongestionError) -> Self {
+        match error {
+            GetGasCongestionError::IOError(error_message) => Self::InternalError { error_message },
+            GetGasCongestionError::Unreachable(ref error_message) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcGasCongestionError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
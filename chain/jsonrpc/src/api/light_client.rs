@@ -3,12 +3,13 @@ use std::sync::Arc;
 use serde_json::Value;
 
 use near_client_primitives::types::{
-    GetBlockProofError, GetExecutionOutcomeError, GetNextLightClientBlockError,
+    GetBlockProofError, GetExecutionOutcomeError, GetNextLightClientBlockError, GetStateProofError,
 };
 use near_jsonrpc_primitives::errors::RpcParseError;
 use near_jsonrpc_primitives::types::light_client::{
     RpcLightClientExecutionProofRequest, RpcLightClientNextBlockError,
     RpcLightClientNextBlockRequest, RpcLightClientNextBlockResponse, RpcLightClientProofError,
+    RpcLightClientStateProofError, RpcLightClientStateProofRequest,
 };
 use near_primitives::views::LightClientBlockView;
 
@@ -20,6 +21,12 @@ impl RpcRequest for RpcLightClientExecutionProofRequest {
     }
 }
 
+impl RpcRequest for RpcLightClientStateProofRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
 impl RpcRequest for RpcLightClientNextBlockRequest {
     fn parse(value: Value) -> Result<Self, RpcParseError> {
         Params::new(value)
@@ -93,6 +100,55 @@ impl RpcFrom<GetBlockProofError> for RpcLightClientProofError {
     }
 }
 
+impl RpcFrom<actix::MailboxError> for RpcLightClientStateProofError {
+    fn rpc_from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl RpcFrom<GetStateProofError> for RpcLightClientStateProofError {
+    fn rpc_from(error: GetStateProofError) -> Self {
+        match error {
+            GetStateProofError::UnknownBlock { error_message } => {
+                Self::UnknownBlock { error_message }
+            }
+            GetStateProofError::UnavailableShard { account_id, shard_id } => {
+                Self::UnavailableShard { account_id, shard_id }
+            }
+            GetStateProofError::InternalError { error_message } => {
+                Self::InternalError { error_message }
+            }
+            GetStateProofError::Unreachable { ref error_message } => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcLightClientStateProofError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl RpcFrom<GetBlockProofError> for RpcLightClientStateProofError {
+    fn rpc_from(error: GetBlockProofError) -> Self {
+        match error {
+            GetBlockProofError::UnknownBlock { error_message } => {
+                Self::UnknownBlock { error_message }
+            }
+            GetBlockProofError::InternalError { error_message } => {
+                Self::InternalError { error_message }
+            }
+            GetBlockProofError::Unreachable { ref error_message } => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcLightClientStateProofError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
 impl RpcFrom<actix::MailboxError> for RpcLightClientNextBlockError {
     fn rpc_from(error: actix::MailboxError) -> Self {
         Self::InternalError { error_message: error.to_string() }
@@ -8,6 +8,8 @@ mod changes;
 mod chunks;
 mod client_config;
 mod config;
+mod execution_tree;
+mod gas_congestion;
 mod gas_price;
 mod light_client;
 mod maintenance;
@@ -15,10 +17,12 @@ mod network_info;
 mod query;
 mod receipts;
 mod sandbox;
+mod simulation;
 mod split_storage;
 mod status;
 mod transactions;
 mod validator;
+mod validator_participation;
 
 pub(crate) trait RpcRequest: Sized {
     fn parse(value: Value) -> Result<Self, RpcParseError>;
@@ -13,7 +13,12 @@ impl RpcFrom<actix::MailboxError> for RpcNetworkInfoError {
 
 impl RpcFrom<PeerInfo> for RpcPeerInfo {
     fn rpc_from(peer_info: PeerInfo) -> Self {
-        Self { id: peer_info.id, addr: peer_info.addr, account_id: peer_info.account_id }
+        Self {
+            id: peer_info.id,
+            addr: peer_info.addr,
+            account_id: peer_info.account_id,
+            reputation: peer_info.reputation,
+        }
     }
 }
 
@@ -1,16 +1,22 @@
 use serde_json::Value;
 
-use near_client_primitives::types::QueryError;
-use near_jsonrpc_primitives::errors::RpcParseError;
-use near_jsonrpc_primitives::types::query::{RpcQueryError, RpcQueryRequest, RpcQueryResponse};
+use near_client_primitives::types::{QueryBatchResponse, QueryError};
+use near_jsonrpc_primitives::errors::{RpcError, RpcParseError};
+use near_jsonrpc_primitives::types::query::{
+    RpcQueryBatchRequest, RpcQueryBatchResponse, RpcQueryBatchResult, RpcQueryError,
+    RpcQueryRequest, RpcQueryResponse,
+};
 use near_primitives::types::BlockReference;
 use near_primitives::views::{QueryRequest, QueryResponse};
 
-use super::{Params, RpcFrom, RpcRequest};
+use super::{Params, RpcFrom, RpcInto, RpcRequest};
 
 /// Max size of the query path (soft-deprecated)
 const QUERY_DATA_MAX_SIZE: usize = 10 * 1024;
 
+/// Max number of requests in a single query batch.
+const QUERY_BATCH_MAX_REQUESTS: usize = 100;
+
 /// Parses base58-encoded data from legacy path+data request format.
 fn parse_bs58_data(max_len: usize, encoded: String) -> Result<Vec<u8>, RpcParseError> {
     // N-byte encoded base58 string decodes to at most N bytes so there’s no
@@ -34,6 +40,24 @@ impl RpcRequest for RpcQueryRequest {
     }
 }
 
+impl RpcRequest for RpcQueryBatchRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        let request: Self = Params::parse(value)?;
+        if request.requests.len() > QUERY_BATCH_MAX_REQUESTS {
+            return Err(RpcParseError(format!(
+                "Too many requests in the batch: {} > {}",
+                request.requests.len(),
+                QUERY_BATCH_MAX_REQUESTS
+            )));
+        }
+        // Function calls can be arbitrarily expensive, so they are not batched.
+        if request.requests.iter().any(|r| matches!(r, QueryRequest::CallFunction { .. })) {
+            return Err(RpcParseError("call_function requests can't be batched".to_string()));
+        }
+        Ok(request)
+    }
+}
+
 fn parse_path_data(path: String, data: String) -> Result<RpcQueryRequest, RpcParseError> {
     // Handle a soft-deprecated version of the query API, which is based on
     // positional arguments with a "path"-style first argument.
@@ -71,6 +95,7 @@ fn parse_path_data(path: String, data: String) -> Result<RpcQueryRequest, RpcPar
             account_id,
             prefix: parse_data()?.into(),
             include_proof: false,
+            pagination: None,
         },
         "call" => match maybe_extra_arg {
             Some(method_name) => QueryRequest::CallFunction {
@@ -129,6 +154,12 @@ impl RpcFrom<QueryError> for RpcQueryError {
             QueryError::TooLargeContractState { contract_account_id, block_height, block_hash } => {
                 Self::TooLargeContractState { contract_account_id, block_height, block_hash }
             }
+            QueryError::InvalidContinuationToken { token_block_hash, block_hash } => {
+                Self::InvalidContinuationToken { token_block_hash, block_hash }
+            }
+            QueryError::InvalidTransaction { context, block_height, block_hash } => {
+                Self::InvalidTransaction { context, block_height, block_hash }
+            }
         }
     }
 }
@@ -143,6 +174,25 @@ impl RpcFrom<QueryResponse> for RpcQueryResponse {
     }
 }
 
+impl RpcFrom<QueryBatchResponse> for RpcQueryBatchResponse {
+    fn rpc_from(response: QueryBatchResponse) -> Self {
+        Self {
+            results: response
+                .results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(kind) => RpcQueryBatchResult::Result(kind.rpc_into()),
+                    Err(err) => {
+                        RpcQueryBatchResult::Error(RpcError::from(RpcQueryError::rpc_from(err)))
+                    }
+                })
+                .collect(),
+            block_height: response.block_height,
+            block_hash: response.block_hash,
+        }
+    }
+}
+
 impl RpcFrom<near_primitives::views::QueryResponseKind>
     for near_jsonrpc_primitives::types::query::QueryResponseKind
 {
@@ -0,0 +1,94 @@
+use serde_json::Value;
+use serde_with::base64::Base64;
+use serde_with::serde_as;
+
+use near_client_primitives::types::SimulateResponse;
+use near_jsonrpc_primitives::errors::RpcParseError;
+use near_jsonrpc_primitives::types::simulation::{RpcSimulateRequest, RpcSimulateResponse};
+use near_primitives::borsh::BorshDeserialize;
+use near_primitives::state_record::StateRecord;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, BlockReference, FunctionArgs};
+use near_primitives::views::SimulationRequest;
+
+use super::{Params, RpcFrom, RpcRequest};
+
+/// Max number of state records overridden in a single simulation.
+const SIMULATION_MAX_STATE_OVERRIDES: usize = 100;
+
+impl RpcRequest for RpcSimulateRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        #[serde_as]
+        #[derive(serde::Deserialize)]
+        #[serde(tag = "simulation_type", rename_all = "snake_case")]
+        enum Simulation {
+            CallFunction {
+                account_id: AccountId,
+                method_name: String,
+                args_base64: FunctionArgs,
+            },
+            Transaction {
+                #[serde_as(as = "Base64")]
+                signed_tx_base64: Vec<u8>,
+            },
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            #[serde(flatten)]
+            block_reference: BlockReference,
+            #[serde(default)]
+            state_overrides: Vec<StateRecord>,
+            #[serde(flatten)]
+            simulation: Simulation,
+        }
+
+        let payload = Params::<Payload>::parse(value)?;
+        if payload.state_overrides.len() > SIMULATION_MAX_STATE_OVERRIDES {
+            return Err(RpcParseError(format!(
+                "Too many state overrides: {} > {}",
+                payload.state_overrides.len(),
+                SIMULATION_MAX_STATE_OVERRIDES
+            )));
+        }
+        if !payload.state_overrides.iter().all(|record| {
+            matches!(
+                record,
+                StateRecord::Account { .. }
+                    | StateRecord::AccessKey { .. }
+                    | StateRecord::Contract { .. }
+                    | StateRecord::Data { .. }
+            )
+        }) {
+            return Err(RpcParseError(
+                "Only Account, AccessKey, Contract and Data state records can be overridden"
+                    .to_string(),
+            ));
+        }
+        let request = match payload.simulation {
+            Simulation::CallFunction { account_id, method_name, args_base64 } => {
+                SimulationRequest::CallFunction { account_id, method_name, args: args_base64 }
+            }
+            Simulation::Transaction { signed_tx_base64 } => SimulationRequest::Transaction(
+                SignedTransaction::try_from_slice(&signed_tx_base64).map_err(|err| {
+                    RpcParseError(format!("Failed to decode transaction: {}", err))
+                })?,
+            ),
+        };
+        Ok(Self {
+            block_reference: payload.block_reference,
+            state_overrides: payload.state_overrides,
+            request,
+        })
+    }
+}
+
+impl RpcFrom<SimulateResponse> for RpcSimulateResponse {
+    fn rpc_from(response: SimulateResponse) -> Self {
+        Self {
+            result: response.result,
+            block_height: response.block_height,
+            block_hash: response.block_hash,
+        }
+    }
+}
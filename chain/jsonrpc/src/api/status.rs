@@ -49,6 +49,21 @@ impl RpcFrom<near_client_primitives::debug::DebugStatusResponse>
                     x,
                 )
             }
+            near_client_primitives::debug::DebugStatusResponse::StoreColumnStats(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::StoreColumnStats(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::BlockTimelines(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::BlockTimelines(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::QuarantinedBlocks(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::QuarantinedBlocks(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::QuarantinedBlock(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::QuarantinedBlock(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::TransactionPool(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::TransactionPool(x)
+            }
         }
     }
 }
@@ -70,6 +85,12 @@ impl RpcFrom<near_network::debug::DebugStatus>
             near_network::debug::DebugStatus::Routes(x) => {
                 near_jsonrpc_primitives::types::status::DebugStatusResponse::Routes(x)
             }
+            near_network::debug::DebugStatus::Bandwidth(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::NetworkBandwidth(x)
+            }
+            near_network::debug::DebugStatus::RouteTraces(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::RouteTraces(x)
+            }
         }
     }
 }
@@ -5,8 +5,8 @@ use serde_with::serde_as;
 use near_client_primitives::types::TxStatusError;
 use near_jsonrpc_primitives::errors::RpcParseError;
 use near_jsonrpc_primitives::types::transactions::{
-    RpcBroadcastTransactionRequest, RpcTransactionError, RpcTransactionStatusCommonRequest,
-    TransactionInfo,
+    RpcBroadcastTransactionRequest, RpcSendTransactionRequest, RpcTransactionError,
+    RpcTransactionStatusCommonRequest, TransactionInfo, TxWaitUntil,
 };
 use near_primitives::borsh::BorshDeserialize;
 use near_primitives::transaction::SignedTransaction;
@@ -20,6 +20,29 @@ impl RpcRequest for RpcBroadcastTransactionRequest {
     }
 }
 
+impl RpcRequest for RpcSendTransactionRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        #[serde_as]
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            #[serde_as(as = "Base64")]
+            signed_tx_base64: Vec<u8>,
+            #[serde(default)]
+            wait_until: TxWaitUntil,
+            timeout_ms: Option<u64>,
+        }
+
+        let payload = Params::<Payload>::parse(value)?;
+        let signed_transaction = SignedTransaction::try_from_slice(&payload.signed_tx_base64)
+            .map_err(|err| RpcParseError(format!("Failed to decode transaction: {}", err)))?;
+        Ok(Self {
+            signed_transaction,
+            wait_until: payload.wait_until,
+            timeout: payload.timeout_ms.map(std::time::Duration::from_millis),
+        })
+    }
+}
+
 impl RpcRequest for RpcTransactionStatusCommonRequest {
     fn parse(value: Value) -> Result<Self, RpcParseError> {
         let transaction_info = Params::<TransactionInfo>::new(value)
@@ -0,0 +1,39 @@
+use near_client_primitives::types::GetValidatorParticipationError;
+use near_jsonrpc_primitives::{
+    errors::RpcParseError,
+    types::validator_participation::{
+        RpcValidatorParticipationError, RpcValidatorParticipationRequest,
+    },
+};
+use serde_json::Value;
+
+use super::{Params, RpcFrom, RpcRequest};
+
+impl RpcRequest for RpcValidatorParticipationRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
+impl RpcFrom<actix::MailboxError> for RpcValidatorParticipationError {
+    fn rpc_from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl RpcFrom<GetValidatorParticipationError> for RpcValidatorParticipationError {
+    fn rpc_from(error: GetValidatorParticipationError) -> Self {
+        match error {
+            GetValidatorParticipationError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            GetValidatorParticipationError::Unreachable(ref error_message) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcValidatorParticipationError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
@@ -9,13 +9,15 @@ use api::RpcRequest;
 pub use api::{RpcFrom, RpcInto};
 use futures::Future;
 use futures::FutureExt;
+use near_async::time;
 use near_chain_configs::GenesisConfig;
 use near_client::{
     ClientActor, DebugStatus, GetBlock, GetBlockProof, GetChunk, GetClientConfig,
-    GetExecutionOutcome, GetGasPrice, GetMaintenanceWindows, GetNetworkInfo,
+    GetExecutionOutcome, GetGasCongestion, GetGasPrice, GetMaintenanceWindows, GetNetworkInfo,
     GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, ProcessTxRequest,
-    ProcessTxResponse, Query, Status, TxStatus, ViewClientActor,
+    GetStateChangesInBlock, GetStateProof, GetTransactionExecutionTree, GetTxPoolStats,
+    GetValidatorInfo, GetValidatorOrdered, GetValidatorParticipation, ProcessTxRequest,
+    ProcessTxResponse, Query, QueryBatch, Simulate, Status, TxStatus, ViewClientActor,
 };
 use near_client_primitives::types::GetSplitStorageInfo;
 pub use near_jsonrpc_client as client;
@@ -31,10 +33,12 @@ use near_network::PeerManagerActor;
 use near_o11y::metrics::{prometheus, Encoder, TextEncoder};
 use near_o11y::{WithSpanContext, WithSpanContextExt};
 use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::{AccountId, BlockHeight};
+use near_primitives::types::{AccountId, BlockHeight, BlockId, BlockReference, NumBlocks};
 use near_primitives::views::{QueryRequest, TxExecutionStatus};
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -43,6 +47,15 @@ use tracing::{error, info};
 
 mod api;
 mod metrics;
+mod rate_limit;
+mod subscriptions;
+
+pub use rate_limit::{RpcRateLimitsConfig, TokenBucketConfig};
+
+/// Number of blocks `EXPERIMENTAL_gas_congestion` counts the gas usage over by default.
+const DEFAULT_GAS_CONGESTION_BLOCKS: NumBlocks = 10;
+/// Max number of blocks `EXPERIMENTAL_gas_congestion` counts the gas usage over.
+const MAX_GAS_CONGESTION_BLOCKS: NumBlocks = 100;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
 pub struct RpcPollingConfig {
@@ -63,11 +76,22 @@ impl Default for RpcPollingConfig {
 pub struct RpcLimitsConfig {
     /// Maximum byte size of the json payload.
     pub json_payload_max_size: usize,
+    /// Maximum byte size of the (serialized) changes in a page of paginated
+    /// `EXPERIMENTAL_changes` response.
+    #[serde(default = "default_changes_page_size_limit")]
+    pub changes_page_size_limit: usize,
+}
+
+fn default_changes_page_size_limit() -> usize {
+    1024 * 1024
 }
 
 impl Default for RpcLimitsConfig {
     fn default() -> Self {
-        Self { json_payload_max_size: 10 * 1024 * 1024 }
+        Self {
+            json_payload_max_size: 10 * 1024 * 1024,
+            changes_page_size_limit: default_changes_page_size_limit(),
+        }
     }
 }
 
@@ -75,6 +99,10 @@ fn default_enable_debug_rpc() -> bool {
     false
 }
 
+fn default_enable_websocket() -> bool {
+    false
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct RpcConfig {
     pub addr: tcp::ListenerAddr,
@@ -92,6 +120,19 @@ pub struct RpcConfig {
     // be read from this directory, instead of the contents compiled into the binary. This allows
     // for quick iterative development.
     pub experimental_debug_pages_src_path: Option<String>,
+    // If provided, enables the admin endpoints (`/admin/api/...`), which can change the
+    // behaviour of the node. Requests to them have to carry this token in the
+    // `Authorization: Bearer <token>` header.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    // If true, enable the websocket endpoint (`/ws`), through which clients can subscribe
+    // to new blocks, state changes and transaction outcomes.
+    #[serde(default = "default_enable_websocket")]
+    pub enable_websocket: bool,
+    // If provided, the JSON RPC requests and the new WebSocket connections are rate limited
+    // per client and per method. The other endpoints aren't limited, see rate_limit.rs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<RpcRateLimitsConfig>,
 }
 
 impl Default for RpcConfig {
@@ -104,6 +145,9 @@ impl Default for RpcConfig {
             limits_config: Default::default(),
             enable_debug_rpc: false,
             experimental_debug_pages_src_path: None,
+            admin_token: None,
+            enable_websocket: false,
+            rate_limits: None,
         }
     }
 }
@@ -146,6 +190,7 @@ impl near_jsonrpc_primitives::types::transactions::RpcTransactionError {
         match resp {
             ProcessTxResponse::InvalidTx(context) => Self::InvalidTransaction { context },
             ProcessTxResponse::NoResponse => Self::TimeoutError,
+            ProcessTxResponse::RejectedByPool(reason) => Self::RejectedByTransactionPool { reason },
             ProcessTxResponse::DoesNotTrackShard | ProcessTxResponse::RequestRouted => {
                 Self::DoesNotTrackShard
             }
@@ -224,6 +269,11 @@ struct JsonRpcHandler {
     enable_debug_rpc: bool,
     debug_pages_src_path: Option<PathBuf>,
     entity_debug_handler: Arc<dyn EntityDebugHandler>,
+    admin_token: Option<String>,
+    changes_page_size_limit: usize,
+    rate_limiter: Option<Arc<rate_limit::RateLimiter>>,
+    /// Events of the chain, if the websocket endpoint is enabled.
+    chain_events: Option<subscriptions::ChainEvents>,
 }
 
 impl JsonRpcHandler {
@@ -331,6 +381,7 @@ impl JsonRpcHandler {
                 process_method_call(request, |params| self.next_light_client_block(params)).await
             }
             "network_info" => process_method_call(request, |_params: ()| self.network_info()).await,
+            "send_tx" => process_method_call(request, |params| self.send_tx_wait(params)).await,
             "status" => process_method_call(request, |_params: ()| self.status()).await,
             "tx" => {
                 process_method_call(request, |params| self.tx_status_common(params, false)).await
@@ -348,6 +399,9 @@ impl JsonRpcHandler {
             "EXPERIMENTAL_changes_in_block" => {
                 process_method_call(request, |params| self.changes_in_block(params)).await
             }
+            "EXPERIMENTAL_gas_congestion" => {
+                process_method_call(request, |params| self.gas_congestion(params)).await
+            }
             "EXPERIMENTAL_genesis_config" => {
                 process_method_call(request, |_params: ()| async {
                     Result::<_, std::convert::Infallible>::Ok(&self.genesis_config)
@@ -360,12 +414,24 @@ impl JsonRpcHandler {
                 })
                 .await
             }
+            "EXPERIMENTAL_light_client_state_proof" => {
+                process_method_call(request, |params| self.light_client_state_proof(params)).await
+            }
+            "EXPERIMENTAL_query_batch" => {
+                process_method_call(request, |params| self.query_batch(params)).await
+            }
+            "EXPERIMENTAL_simulate" => {
+                process_method_call(request, |params| self.simulate(params)).await
+            }
             "EXPERIMENTAL_protocol_config" => {
                 process_method_call(request, |params| self.protocol_config(params)).await
             }
             "EXPERIMENTAL_receipt" => {
                 process_method_call(request, |params| self.receipt(params)).await
             }
+            "EXPERIMENTAL_execution_tree" => {
+                process_method_call(request, |params| self.execution_tree(params)).await
+            }
             "EXPERIMENTAL_tx_status" => {
                 process_method_call(request, |params| self.tx_status_common(params, true)).await
             }
@@ -378,6 +444,9 @@ impl JsonRpcHandler {
             "EXPERIMENTAL_split_storage_info" => {
                 process_method_call(request, |params| self.split_storage_info(params)).await
             }
+            "EXPERIMENTAL_validator_participation" => {
+                process_method_call(request, |params| self.validator_participation(params)).await
+            }
             #[cfg(feature = "sandbox")]
             "sandbox_patch_state" => {
                 process_method_call(request, |params| self.sandbox_patch_state(params)).await
@@ -695,6 +764,79 @@ impl JsonRpcHandler {
         }
     }
 
+    /// Sends the transaction and waits until it reaches the requested status, or the timeout
+    /// expires, in which case responds with the status reached so far.
+    async fn send_tx_wait(
+        &self,
+        request_data: near_jsonrpc_primitives::types::transactions::RpcSendTransactionRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::transactions::RpcSendTransactionResponse,
+        near_jsonrpc_primitives::types::transactions::RpcTransactionError,
+    > {
+        let tx = request_data.signed_transaction;
+        let tx_hash = tx.get_hash();
+        let signer_account_id = tx.transaction.signer_id.clone();
+        let routed = match self.send_tx(tx, false).await? {
+            ProcessTxResponse::ValidTx => false,
+            ProcessTxResponse::RequestRouted => true,
+            network_client_response => {
+                return Err(
+                    near_jsonrpc_primitives::types::transactions::RpcTransactionError::from_network_client_responses(
+                        network_client_response
+                    )
+                );
+            }
+        };
+        let mut response =
+            near_jsonrpc_primitives::types::transactions::RpcSendTransactionResponse {
+                transaction_hash: tx_hash,
+                routed,
+                pending: false,
+                final_execution_outcome: None,
+                final_execution_status: TxExecutionStatus::None,
+            };
+        let wait_until = request_data.wait_until;
+        if wait_until.is_reached(&response.final_execution_status) {
+            return Ok(response);
+        }
+        let polling_timeout =
+            request_data.timeout.map_or(self.polling_config.polling_timeout, |timeout| {
+                timeout.min(self.polling_config.polling_timeout)
+            });
+        let result = timeout(polling_timeout, async {
+            loop {
+                match self
+                    .view_client_send(TxStatus {
+                        tx_hash,
+                        signer_account_id: signer_account_id.clone(),
+                        fetch_receipt: false,
+                    })
+                    .await
+                {
+                    Ok(status) => {
+                        response.final_execution_status = status.status;
+                        response.final_execution_outcome = status.execution_outcome;
+                        if wait_until.is_reached(&response.final_execution_status) {
+                            break Ok(());
+                        }
+                    }
+                    // The transaction hasn't reached the node tracking its shard yet.
+                    Err(near_jsonrpc_primitives::types::transactions::RpcTransactionError::UnknownTransaction {
+                        ..
+                    }) => {}
+                    Err(err) => break Err(err),
+                }
+                sleep(self.polling_config.polling_interval).await;
+            }
+        })
+        .await;
+        match result {
+            Ok(result) => result?,
+            Err(_) => response.pending = true,
+        }
+        Ok(response)
+    }
+
     async fn send_tx_commit(
         &self,
         request_data: near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest,
@@ -805,6 +947,18 @@ impl JsonRpcHandler {
                     "/debug/api/requested_state_parts" => {
                         self.client_send(DebugStatus::RequestedStateParts).await?.rpc_into()
                     }
+                    "/debug/api/store_column_stats" => {
+                        self.client_send(DebugStatus::StoreColumnStats).await?.rpc_into()
+                    }
+                    "/debug/api/block_timelines" => {
+                        self.client_send(DebugStatus::BlockTimelines).await?.rpc_into()
+                    }
+                    "/debug/api/quarantined_blocks" => {
+                        self.client_send(DebugStatus::QuarantinedBlocks).await?.rpc_into()
+                    }
+                    "/debug/api/transaction_pool" => {
+                        self.client_send(DebugStatus::TransactionPool).await?.rpc_into()
+                    }
                     "/debug/api/peer_store" => self
                         .peer_manager_send(near_network::debug::GetDebugStatus::PeerStore)
                         .await?
@@ -823,6 +977,14 @@ impl JsonRpcHandler {
                         .peer_manager_send(near_network::debug::GetDebugStatus::Routes)
                         .await?
                         .rpc_into(),
+                    "/debug/api/network_bandwidth" => self
+                        .peer_manager_send(near_network::debug::GetDebugStatus::Bandwidth)
+                        .await?
+                        .rpc_into(),
+                    "/debug/api/route_traces" => self
+                        .peer_manager_send(near_network::debug::GetDebugStatus::RouteTraces)
+                        .await?
+                        .rpc_into(),
                     _ => return Ok(None),
                 };
             Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
@@ -851,6 +1013,74 @@ impl JsonRpcHandler {
         }
     }
 
+    pub async fn debug_quarantined_block(
+        &self,
+        block_hash: CryptoHash,
+    ) -> Result<
+        Option<near_jsonrpc_primitives::types::status::RpcDebugStatusResponse>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            let debug_status =
+                self.client_send(DebugStatus::QuarantinedBlock(block_hash)).await?.rpc_into();
+            Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
+                status_response: debug_status,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn debug_trace_route(
+        &self,
+        target: PeerId,
+    ) -> Result<
+        Option<near_jsonrpc_primitives::types::status::RpcDebugStatusResponse>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            let debug_status = self
+                .peer_manager_send(near_network::debug::GetDebugStatus::TraceRoute(target))
+                .await?
+                .rpc_into();
+            Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
+                status_response: debug_status,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Checks the bearer token of a request to an admin endpoint.
+    /// Returns the response to send instead, if the request is not authorized.
+    fn check_admin_token(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        let Some(admin_token) = &self.admin_token else {
+            return Some(HttpResponse::MethodNotAllowed().finish());
+        };
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) => None,
+            _ => Some(HttpResponse::Unauthorized().finish()),
+        }
+    }
+
+    pub async fn update_peer_filter(
+        &self,
+        msg: near_network::peer_filter::UpdatePeerFilter,
+    ) -> Result<
+        Result<near_primitives::views::PeerFilterView, near_network::peer_filter::PeerFilterError>,
+        MailboxError,
+    > {
+        match &self.peer_manager_addr {
+            Some(peer_manager_addr) => peer_manager_addr.send(msg).await,
+            None => Err(MailboxError::Closed),
+        }
+    }
+
     pub async fn protocol_config(
         &self,
         request_data: near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest,
@@ -876,6 +1106,39 @@ impl JsonRpcHandler {
         Ok(query_response.rpc_into())
     }
 
+    async fn query_batch(
+        &self,
+        request_data: near_jsonrpc_primitives::types::query::RpcQueryBatchRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::query::RpcQueryBatchResponse,
+        near_jsonrpc_primitives::types::query::RpcQueryError,
+    > {
+        let response = self
+            .view_client_send(QueryBatch {
+                block_reference: request_data.block_reference,
+                requests: request_data.requests,
+            })
+            .await?;
+        Ok(response.rpc_into())
+    }
+
+    async fn simulate(
+        &self,
+        request_data: near_jsonrpc_primitives::types::simulation::RpcSimulateRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::simulation::RpcSimulateResponse,
+        near_jsonrpc_primitives::types::query::RpcQueryError,
+    > {
+        let response = self
+            .view_client_send(Simulate {
+                block_reference: request_data.block_reference,
+                state_overrides: request_data.state_overrides,
+                request: request_data.request,
+            })
+            .await?;
+        Ok(response.rpc_into())
+    }
+
     async fn tx_status_common(
         &self,
         request_data: near_jsonrpc_primitives::types::transactions::RpcTransactionStatusCommonRequest,
@@ -933,6 +1196,23 @@ impl JsonRpcHandler {
         }
     }
 
+    async fn execution_tree(
+        &self,
+        request_data: near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeResponse,
+        near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeError,
+    > {
+        let execution_tree = self
+            .view_client_send(GetTransactionExecutionTree {
+                transaction_hash: request_data.tx_hash,
+            })
+            .await?;
+        Ok(near_jsonrpc_primitives::types::execution_tree::RpcTransactionExecutionTreeResponse {
+            execution_tree,
+        })
+    }
+
     async fn changes_in_block(
         &self,
         request: near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockRequest,
@@ -959,20 +1239,68 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse,
         near_jsonrpc_primitives::types::changes::RpcStateChangesError,
     > {
+        // The next pages are served from the block of the first page.
+        let (block_reference, offset) = match request
+            .pagination
+            .as_ref()
+            .and_then(|p| p.continuation_token.as_ref())
+        {
+            Some(token) => {
+                let offset = <[u8; 8]>::try_from(token.position.as_slice())
+                        .map(u64::from_le_bytes)
+                        .map_err(|_| {
+                            near_jsonrpc_primitives::types::changes::RpcStateChangesError::InvalidContinuationToken {
+                                error_message: "malformed position".to_string(),
+                            }
+                        })?;
+                (BlockReference::BlockId(BlockId::Hash(token.block_hash)), offset as usize)
+            }
+            None => (request.block_reference, 0),
+        };
         let block: near_primitives::views::BlockView =
-            self.view_client_send(GetBlock(request.block_reference)).await?;
+            self.view_client_send(GetBlock(block_reference)).await?;
 
         let block_hash = block.header.hash;
-        let changes = self
+        let mut changes = self
             .view_client_send(GetStateChanges {
                 block_hash,
                 state_changes_request: request.state_changes_request,
             })
             .await?;
 
+        let mut continuation_token = None;
+        if request.pagination.is_some() {
+            if offset > changes.len() {
+                return Err(
+                    near_jsonrpc_primitives::types::changes::RpcStateChangesError::InvalidContinuationToken {
+                        error_message: format!("position {offset} is out of range"),
+                    },
+                );
+            }
+            changes.drain(..offset);
+            // A page has at least one change, whatever its size.
+            let mut size = 0;
+            let mut page_len = 0;
+            for change in &changes {
+                if page_len > 0 && size >= self.changes_page_size_limit {
+                    break;
+                }
+                size += serde_json::to_vec(change).map_or(0, |change| change.len());
+                page_len += 1;
+            }
+            if page_len < changes.len() {
+                changes.truncate(page_len);
+                continuation_token = Some(near_primitives::views::ContinuationToken {
+                    block_hash,
+                    position: ((offset + page_len) as u64).to_le_bytes().to_vec(),
+                });
+            }
+        }
+
         Ok(near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse {
             block_hash: block.header.hash,
             changes,
+            continuation_token,
         })
     }
 
@@ -1019,6 +1347,34 @@ impl JsonRpcHandler {
         })
     }
 
+    async fn light_client_state_proof(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofResponse,
+        near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofError,
+    > {
+        let near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofRequest {
+            account_id,
+            key,
+            block_hash,
+            light_client_head,
+        } = request;
+
+        let state_proof =
+            self.view_client_send(GetStateProof { block_hash, account_id, key }).await?;
+
+        let block_proof: near_client_primitives::types::GetBlockProofResponse = self
+            .view_client_send(GetBlockProof { block_hash, head_block_hash: light_client_head })
+            .await?;
+
+        Ok(near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofResponse {
+            state_proof,
+            block_header_lite: block_proof.block_header_lite,
+            block_proof: block_proof.proof,
+        })
+    }
+
     async fn network_info(
         &self,
     ) -> Result<
@@ -1118,6 +1474,62 @@ impl JsonRpcHandler {
         let split_storage = self.view_client_send(GetSplitStorageInfo {}).await?;
         Ok(RpcSplitStorageInfoResponse { result: split_storage })
     }
+
+    /// Returns the recent gas usage of the shards and the transactions waiting in the pool
+    /// of this node, with the estimated number of blocks until a new transaction is included.
+    async fn gas_congestion(
+        &self,
+        request: near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionResponse,
+        near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionError,
+    > {
+        let num_blocks = request
+            .num_blocks
+            .unwrap_or(DEFAULT_GAS_CONGESTION_BLOCKS)
+            .clamp(1, MAX_GAS_CONGESTION_BLOCKS);
+        let mut congestion = self.view_client_send(GetGasCongestion { num_blocks }).await?;
+        let pools: HashMap<_, _> = self
+            .client_send(GetTxPoolStats {})
+            .await?
+            .into_iter()
+            .map(|pool| (pool.shard_id, pool))
+            .collect();
+        if let Some(shard_id) = request.shard_id {
+            congestion.shards.retain(|shard| shard.shard_id == shard_id);
+        }
+        let affordable = request.gas_price.map_or(true, |price| price >= congestion.gas_price);
+        for shard in &mut congestion.shards {
+            // The pools are created on the first transaction of the shard.
+            shard.pool = pools.get(&shard.shard_id).cloned().or_else(|| {
+                shard.num_transactions.map(|_| near_primitives::views::TxPoolStatsView {
+                    shard_id: shard.shard_id,
+                    num_transactions: 0,
+                    size: 0,
+                })
+            });
+            if affordable {
+                shard.estimated_inclusion_delay = shard.estimate_inclusion_delay();
+            }
+        }
+        Ok(near_jsonrpc_primitives::types::gas_congestion::RpcGasCongestionResponse { congestion })
+    }
+
+    /// Returns which share of the blocks of the recent epochs contain
+    /// approvals of each validator, as seen by this node.
+    async fn validator_participation(
+        &self,
+        request: near_jsonrpc_primitives::types::validator_participation::RpcValidatorParticipationRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator_participation::RpcValidatorParticipationResponse,
+        near_jsonrpc_primitives::types::validator_participation::RpcValidatorParticipationError,
+    > {
+        let num_epochs = request.num_epochs.unwrap_or(usize::MAX);
+        let epochs = self.client_send(GetValidatorParticipation { num_epochs }).await?;
+        Ok(near_jsonrpc_primitives::types::validator_participation::RpcValidatorParticipationResponse {
+            epochs,
+        })
+    }
 }
 
 #[cfg(feature = "sandbox")]
@@ -1330,9 +1742,20 @@ impl JsonRpcHandler {
 }
 
 fn rpc_handler(
+    req: HttpRequest,
     message: web::Json<Message>,
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    if let (Some(rate_limiter), Message::Request(request)) = (&handler.rate_limiter, &message.0) {
+        let api_key =
+            req.headers().get(rate_limit::API_KEY_HEADER).and_then(|value| value.to_str().ok());
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        if !rate_limiter.check(request, api_key, ip) {
+            let error = request.error(RpcError::too_many_requests(request.method.clone()));
+            return futures::future::ready(Ok(HttpResponse::TooManyRequests().json(&error)))
+                .boxed();
+        }
+    }
     let response = async move {
         let message = handler.process(message.0).await?;
         Ok(HttpResponse::Ok().json(&message))
@@ -1394,6 +1817,108 @@ async fn debug_block_status_handler(
     }
 }
 
+async fn debug_quarantined_block_handler(
+    path: web::Path<String>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let Ok(block_hash) = path.parse::<CryptoHash>() else {
+        return Ok(HttpResponse::BadRequest().body("invalid block hash"));
+    };
+    match handler.debug_quarantined_block(block_hash).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn debug_trace_route_handler(
+    path: web::Path<String>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let Ok(peer_id) = serde_json::from_value::<PeerId>(Value::String(path.into_inner())) else {
+        return Ok(HttpResponse::BadRequest().body("invalid peer id"));
+    };
+    match handler.debug_trace_route(peer_id).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+/// Compares the byte strings in time independent of their contents, so that
+/// the admin token can't be guessed by timing the responses.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn admin_peer_filter_handler(
+    req: HttpRequest,
+    msg: near_network::peer_filter::UpdatePeerFilter,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if let Some(response) = handler.check_admin_token(&req) {
+        return Ok(response);
+    }
+    match handler.update_peer_filter(msg).await {
+        Ok(Ok(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(Err(err)) => Ok(HttpResponse::BadRequest().body(err.to_string())),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn admin_get_peer_filter_handler(
+    req: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    admin_peer_filter_handler(req, near_network::peer_filter::UpdatePeerFilter::Get, handler).await
+}
+
+async fn admin_add_peer_filter_rule_handler(
+    req: HttpRequest,
+    body: web::Json<near_jsonrpc_primitives::types::admin::RpcAddPeerFilterRuleRequest>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let body = body.into_inner();
+    let msg = near_network::peer_filter::UpdatePeerFilter::Add {
+        rule: body.rule,
+        ttl: body.ttl_seconds.map(|s| time::Duration::seconds(s as i64)),
+    };
+    admin_peer_filter_handler(req, msg, handler).await
+}
+
+async fn admin_remove_peer_filter_rule_handler(
+    req: HttpRequest,
+    body: web::Json<near_jsonrpc_primitives::types::admin::RpcRemovePeerFilterRuleRequest>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let msg = near_network::peer_filter::UpdatePeerFilter::Remove(body.into_inner().rule);
+    admin_peer_filter_handler(req, msg, handler).await
+}
+
+async fn websocket_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let Some(chain_events) = &handler.chain_events else {
+        return Ok(HttpResponse::MethodNotAllowed().finish());
+    };
+    if let Some(rate_limiter) = &handler.rate_limiter {
+        let api_key =
+            req.headers().get(rate_limit::API_KEY_HEADER).and_then(|value| value.to_str().ok());
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        if !rate_limiter.check_method(rate_limit::WEBSOCKET_METHOD, api_key, ip) {
+            return Ok(HttpResponse::TooManyRequests().finish());
+        }
+    }
+    let Some(events) = chain_events.subscribe() else {
+        return Ok(HttpResponse::ServiceUnavailable().finish());
+    };
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(subscriptions::run_session(handler.into_inner(), events, session, stream));
+    Ok(response)
+}
+
 fn health_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
@@ -1494,6 +2019,8 @@ async fn display_debug_html(
         "sync.css" => Some(debug_page_string!("sync.css", handler)),
         "validator" => Some(debug_page_string!("validator.html", handler)),
         "validator.css" => Some(debug_page_string!("validator.css", handler)),
+        "store" => Some(debug_page_string!("store.html", handler)),
+        "block_timeline" => Some(debug_page_string!("block_timeline.html", handler)),
         _ => None,
     };
 
@@ -1523,6 +2050,7 @@ pub fn start_http(
     view_client_addr: Addr<ViewClientActor>,
     peer_manager_addr: Option<Addr<PeerManagerActor>>,
     entity_debug_handler: Arc<dyn EntityDebugHandler>,
+    chain_event_bus: Option<near_chain::ChainEventBus>,
 ) -> Vec<(&'static str, actix_web::dev::ServerHandle)> {
     let RpcConfig {
         addr,
@@ -1532,7 +2060,15 @@ pub fn start_http(
         limits_config,
         enable_debug_rpc,
         experimental_debug_pages_src_path: debug_pages_src_path,
+        admin_token,
+        enable_websocket,
+        rate_limits,
     } = config;
+    let rate_limiter = rate_limits
+        .map(|config| Arc::new(rate_limit::RateLimiter::new(time::Clock::real(), config)));
+    let chain_events = chain_event_bus
+        .filter(|_| enable_websocket)
+        .map(|bus| subscriptions::forward_chain_events(&bus, view_client_addr.clone()));
     let prometheus_addr = prometheus_addr.filter(|it| it != &addr.to_string());
     let cors_allowed_origins_clone = cors_allowed_origins.clone();
     info!(target:"network", "Starting http server at {}", addr);
@@ -1549,6 +2085,10 @@ pub fn start_http(
                 enable_debug_rpc,
                 debug_pages_src_path: debug_pages_src_path.clone().map(Into::into),
                 entity_debug_handler: entity_debug_handler.clone(),
+                admin_token: admin_token.clone(),
+                changes_page_size_limit: limits_config.changes_page_size_limit,
+                rate_limiter: rate_limiter.clone(),
+                chain_events: chain_events.clone(),
             }))
             .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
             .wrap(middleware::Logger::default())
@@ -1565,15 +2105,36 @@ pub fn start_http(
             )
             .service(web::resource("/network_info").route(web::get().to(network_info_handler)))
             .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
+            .service(web::resource("/ws").route(web::get().to(websocket_handler)))
             .service(web::resource("/debug/api/entity").route(web::post().to(handle_entity_debug)))
             .service(web::resource("/debug/api/{api}").route(web::get().to(debug_handler)))
             .service(
                 web::resource("/debug/api/block_status/{starting_height}")
                     .route(web::get().to(debug_block_status_handler)),
             )
+            .service(
+                web::resource("/debug/api/quarantined_blocks/{block_hash}")
+                    .route(web::get().to(debug_quarantined_block_handler)),
+            )
+            .service(
+                web::resource("/debug/api/trace_route/{peer_id}")
+                    .route(web::get().to(debug_trace_route_handler)),
+            )
             .service(
                 web::resource("/debug/client_config").route(web::get().to(client_config_handler)),
             )
+            .service(
+                web::resource("/admin/api/peer_filter")
+                    .route(web::get().to(admin_get_peer_filter_handler)),
+            )
+            .service(
+                web::resource("/admin/api/peer_filter/add")
+                    .route(web::post().to(admin_add_peer_filter_rule_handler)),
+            )
+            .service(
+                web::resource("/admin/api/peer_filter/remove")
+                    .route(web::post().to(admin_remove_peer_filter_rule_handler)),
+            )
             .service(debug_html)
             .service(display_debug_html)
     });
@@ -47,6 +47,22 @@ pub static RPC_ERROR_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static RPC_THROTTLED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_o11y::metrics::try_create_int_counter_vec(
+        "near_rpc_throttled_requests_total",
+        "Total count of RPC requests rejected by the rate limits, by method and exhausted limit",
+        &["method", "limit"],
+    )
+    .unwrap()
+});
+pub static RPC_REQUEST_COST: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_o11y::metrics::try_create_int_counter_vec(
+        "near_rpc_request_cost_total",
+        "Total cost of the RPC requests checked against the rate limits, by method",
+        &["method"],
+    )
+    .unwrap()
+});
 pub static RPC_UNREACHABLE_ERROR_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     near_o11y::metrics::try_create_int_counter_vec(
         "near_rpc_unreachable_errors_total",
@@ -0,0 +1,388 @@
+//! Rate limiting of the JSON RPC requests, so that the operators of public RPC nodes don't
+//! need an external gateway for basic protection against abusive clients.
+//!
+//! Every request has a cost, which depends on its method (and for `query`, on the type of the
+//! request), and is paid with tokens from two buckets: the bucket of the client and the bucket
+//! of the method, which is shared by all the clients. A client is identified by its API key
+//! (the `X-Api-Key` header), if the key has a limit of its own, and by its IP address otherwise.
+//! Requests which can't be paid for are rejected with HTTP 429.
+//!
+//! The limits apply to the JSON RPC requests POSTed to `/` and to opening WebSocket
+//! connections on `/ws`, priced as the `websocket` method. The requests sent over an open
+//! WebSocket are not limited, and neither are the other endpoints: `/status`, `/health`,
+//! `/network_info` and `/metrics` are cheap and used for monitoring, the debug pages are
+//! served only with `enable_debug_rpc` and the admin API requires the admin token.
+use crate::metrics;
+use lru::LruCache;
+use near_async::time;
+use near_jsonrpc_primitives::message::Request;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Header carrying the API key of the client.
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+/// Number of clients whose buckets are tracked. The clients which haven't sent requests
+/// recently are forgotten first, which refills their buckets.
+const MAX_TRACKED_CLIENTS: usize = 100_000;
+/// Method by which the unknown methods are priced and limited, the same as they are
+/// labelled in the other metrics.
+const UNSUPPORTED_METHOD: &str = "UNSUPPORTED_METHOD";
+/// Method by which opening a WebSocket connection is priced and limited.
+pub(crate) const WEBSOCKET_METHOD: &str = "websocket";
+
+/// Methods handled by `JsonRpcHandler::process_request_internal`, other than `query`.
+const METHODS: &[&str] = &[
+    "block",
+    "broadcast_tx_async",
+    "broadcast_tx_commit",
+    "chunk",
+    "client_config",
+    "gas_price",
+    "health",
+    "light_client_proof",
+    "network_info",
+    "next_light_client_block",
+    "send_tx",
+    "status",
+    "tx",
+    "validators",
+    "EXPERIMENTAL_broadcast_tx_sync",
+    "EXPERIMENTAL_changes",
+    "EXPERIMENTAL_changes_in_block",
+    "EXPERIMENTAL_execution_tree",
+    "EXPERIMENTAL_gas_congestion",
+    "EXPERIMENTAL_genesis_config",
+    "EXPERIMENTAL_light_client_proof",
+    "EXPERIMENTAL_light_client_state_proof",
+    "EXPERIMENTAL_maintenance_windows",
+    "EXPERIMENTAL_protocol_config",
+    "EXPERIMENTAL_query_batch",
+    "EXPERIMENTAL_receipt",
+    "EXPERIMENTAL_simulate",
+    "EXPERIMENTAL_split_storage_info",
+    "EXPERIMENTAL_tx_status",
+    "EXPERIMENTAL_validator_participation",
+    "EXPERIMENTAL_validators_ordered",
+    "sandbox_fast_forward",
+    "sandbox_patch_state",
+    "adv_check_store",
+    "adv_disable_doomslug",
+    "adv_disable_header_sync",
+    "adv_get_saved_blocks",
+    "adv_produce_blocks",
+    "adv_switch_to_height",
+];
+
+/// Types of the `query` requests, see `QueryRequest`.
+const QUERY_REQUEST_TYPES: &[&str] = &[
+    "view_account",
+    "view_code",
+    "view_state",
+    "view_access_key",
+    "view_access_key_list",
+    "call_function",
+];
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TokenBucketConfig {
+    /// Tokens added to the bucket per second.
+    pub refill_per_second: f64,
+    /// Max number of tokens in the bucket, i.e. the max cost of a burst of requests.
+    pub capacity: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RpcRateLimitsConfig {
+    /// Bucket of every client without an API key listed in `api_keys`.
+    pub per_client: TokenBucketConfig,
+    /// Buckets of the clients with the given API keys.
+    #[serde(default)]
+    pub api_keys: HashMap<String, TokenBucketConfig>,
+    /// Buckets of the methods, shared by all the clients. Methods not listed are not limited.
+    #[serde(default)]
+    pub per_method: HashMap<String, TokenBucketConfig>,
+    /// Costs of the requests by method, 1 for the methods not listed. The `query` requests
+    /// are priced by the request type, e.g. `query_view_state`.
+    #[serde(default = "default_method_costs")]
+    pub method_costs: HashMap<String, u32>,
+}
+
+impl RpcRateLimitsConfig {
+    fn cost(&self, method: &str) -> u32 {
+        self.method_costs.get(method).copied().unwrap_or(1)
+    }
+
+    /// Checks that every request can be paid for, i.e. that no cost exceeds the capacity of
+    /// a bucket it's charged to, since the bucket never holds more tokens than its capacity.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        // The bucket of a client is charged for the requests of every method.
+        let max_cost = self.method_costs.values().copied().max().unwrap_or(1).max(1);
+        let client_buckets = std::iter::once(("per_client".to_string(), &self.per_client))
+            .chain(self.api_keys.iter().map(|(key, config)| (format!("api_keys.{key}"), config)));
+        for (name, config) in client_buckets {
+            if (max_cost as f64) > config.capacity {
+                errors.push(format!(
+                    "{name}.capacity {} is less than {max_cost}, the max cost of a request",
+                    config.capacity
+                ));
+            }
+        }
+        for (method, config) in &self.per_method {
+            let cost = self.cost(method);
+            if (cost as f64) > config.capacity {
+                errors.push(format!(
+                    "per_method.{method}.capacity {} is less than the cost {cost} of {method}",
+                    config.capacity
+                ));
+            }
+        }
+        errors
+    }
+}
+
+fn default_method_costs() -> HashMap<String, u32> {
+    [
+        ("query_view_state", 10),
+        ("query_call_function", 10),
+        ("EXPERIMENTAL_query_batch", 20),
+        ("EXPERIMENTAL_simulate", 20),
+        ("EXPERIMENTAL_changes", 5),
+        ("EXPERIMENTAL_changes_in_block", 5),
+        ("EXPERIMENTAL_execution_tree", 5),
+        ("EXPERIMENTAL_gas_congestion", 5),
+        ("EXPERIMENTAL_tx_status", 3),
+        ("tx", 3),
+        ("send_tx", 3),
+        ("block", 2),
+        ("chunk", 2),
+        (WEBSOCKET_METHOD, 5),
+    ]
+    .into_iter()
+    .map(|(method, cost)| (method.to_string(), cost))
+    .collect()
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: time::Instant,
+}
+
+impl Bucket {
+    fn new(config: &TokenBucketConfig, now: time::Instant) -> Self {
+        Self { tokens: config.capacity, updated: now }
+    }
+
+    fn refill(&mut self, config: &TokenBucketConfig, now: time::Instant) {
+        let elapsed = (now - self.updated).as_seconds_f64().max(0.);
+        self.tokens = (self.tokens + elapsed * config.refill_per_second).min(config.capacity);
+        self.updated = now;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ClientId {
+    ApiKey(String),
+    Ip(IpAddr),
+}
+
+struct Buckets {
+    clients: LruCache<ClientId, Bucket>,
+    methods: HashMap<String, Bucket>,
+}
+
+pub(crate) struct RateLimiter {
+    clock: time::Clock,
+    config: RpcRateLimitsConfig,
+    buckets: Mutex<Buckets>,
+}
+
+/// Name of the method by which the request is priced and limited.
+/// Unknown methods and `query` request types are all limited as `UNSUPPORTED_METHOD`, so that
+/// the clients can't make up the keys of the method buckets and the labels of the metrics.
+fn method_key(request: &Request) -> String {
+    if request.method == "query" {
+        return match request.params.get("request_type") {
+            Some(Value::String(request_type)) if QUERY_REQUEST_TYPES.contains(&&**request_type) => {
+                format!("query_{request_type}")
+            }
+            Some(_) => UNSUPPORTED_METHOD.to_string(),
+            None => request.method.clone(),
+        };
+    }
+    if METHODS.contains(&&*request.method) {
+        request.method.clone()
+    } else {
+        UNSUPPORTED_METHOD.to_string()
+    }
+}
+
+impl RateLimiter {
+    pub fn new(clock: time::Clock, config: RpcRateLimitsConfig) -> Self {
+        let buckets =
+            Buckets { clients: LruCache::new(MAX_TRACKED_CLIENTS), methods: HashMap::new() };
+        Self { clock, config, buckets: Mutex::new(buckets) }
+    }
+
+    /// Charges the cost of the request to the buckets of the client and of the method.
+    /// Returns false (and charges nothing) if any of them doesn't have enough tokens.
+    pub fn check(&self, request: &Request, api_key: Option<&str>, ip: Option<IpAddr>) -> bool {
+        self.check_method(&method_key(request), api_key, ip)
+    }
+
+    /// Same as `check`, for a request of the given method.
+    pub fn check_method(&self, method: &str, api_key: Option<&str>, ip: Option<IpAddr>) -> bool {
+        let cost = self.config.cost(method) as f64;
+        metrics::RPC_REQUEST_COST.with_label_values(&[method]).inc_by(cost as u64);
+
+        let (client, client_config) =
+            match api_key.and_then(|key| Some((key, self.config.api_keys.get(key)?))) {
+                Some((key, config)) => (Some(ClientId::ApiKey(key.to_string())), config),
+                None => (ip.map(ClientId::Ip), &self.config.per_client),
+            };
+        let method_config = self.config.per_method.get(method);
+
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { clients, methods } = &mut *buckets;
+        let mut client_bucket = client.map(|client| {
+            if !clients.contains(&client) {
+                clients.put(client.clone(), Bucket::new(client_config, now));
+            }
+            let bucket = clients.get_mut(&client).unwrap();
+            bucket.refill(client_config, now);
+            bucket
+        });
+        if client_bucket.as_ref().map_or(false, |bucket| bucket.tokens < cost) {
+            metrics::RPC_THROTTLED_REQUESTS.with_label_values(&[method, "client"]).inc();
+            return false;
+        }
+        let mut method_bucket = method_config.map(|config| {
+            let bucket =
+                methods.entry(method.to_string()).or_insert_with(|| Bucket::new(config, now));
+            bucket.refill(config, now);
+            bucket
+        });
+        if method_bucket.as_ref().map_or(false, |bucket| bucket.tokens < cost) {
+            metrics::RPC_THROTTLED_REQUESTS.with_label_values(&[method, "method"]).inc();
+            return false;
+        }
+        for bucket in [&mut client_bucket, &mut method_bucket].into_iter().flatten() {
+            bucket.tokens -= cost;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_jsonrpc_primitives::message::Message;
+    use serde_json::json;
+
+    fn request(method: &str, params: Value) -> Request {
+        match Message::request(method.to_string(), params) {
+            Message::Request(request) => request,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn token_buckets() {
+        let clock = time::FakeClock::default();
+        let bucket = |capacity| TokenBucketConfig { refill_per_second: 1., capacity };
+        let config = RpcRateLimitsConfig {
+            per_client: bucket(10.),
+            api_keys: [("key".to_string(), bucket(100.))].into_iter().collect(),
+            per_method: [("block".to_string(), bucket(4.))].into_iter().collect(),
+            method_costs: default_method_costs(),
+        };
+        let limiter = RateLimiter::new(clock.clock(), config);
+        let ip0 = Some("192.0.2.1".parse().unwrap());
+        let ip1 = Some("192.0.2.2".parse().unwrap());
+        let view_state = request("query", json!({"request_type": "view_state"}));
+        let status = request("status", json!([]));
+
+        // view_state costs 10, which exhausts the bucket of the client, but not of the others.
+        assert!(limiter.check(&view_state, None, ip0));
+        assert!(!limiter.check(&status, None, ip0));
+        assert!(limiter.check(&status, None, ip1));
+        // Unknown API keys get the limits of the IP address.
+        assert!(!limiter.check(&status, Some("other"), ip0));
+        assert!(limiter.check(&view_state, Some("key"), ip0));
+        clock.advance(time::Duration::seconds(1));
+        assert!(limiter.check(&status, None, ip0));
+        assert!(!limiter.check(&status, None, ip0));
+
+        // The method limit is shared by all the clients.
+        let block = request("block", json!({"finality": "final"}));
+        assert!(limiter.check(&block, None, ip1));
+        assert!(limiter.check(&block, Some("key"), ip0));
+        assert!(!limiter.check(&block, Some("key"), ip0));
+    }
+
+    #[test]
+    fn unsupported_methods() {
+        let clock = time::FakeClock::default();
+        let bucket = |capacity| TokenBucketConfig { refill_per_second: 1., capacity };
+        let config = RpcRateLimitsConfig {
+            per_client: bucket(100.),
+            api_keys: HashMap::new(),
+            per_method: [(UNSUPPORTED_METHOD.to_string(), bucket(2.))].into_iter().collect(),
+            method_costs: default_method_costs(),
+        };
+        let limiter = RateLimiter::new(clock.clock(), config);
+        let ip = Some("192.0.2.1".parse().unwrap());
+
+        // Unknown methods and query request types share a single bucket.
+        assert_eq!(method_key(&request("foo", json!([]))), UNSUPPORTED_METHOD);
+        assert_eq!(
+            method_key(&request("query", json!({"request_type": "view_foo"}))),
+            UNSUPPORTED_METHOD
+        );
+        assert_eq!(
+            method_key(&request("query", json!({"request_type": "view_account"}))),
+            "query_view_account"
+        );
+        assert!(limiter.check(&request("foo", json!([])), None, ip));
+        assert!(limiter.check(&request("query", json!({"request_type": "bar"})), None, ip));
+        assert!(!limiter.check(&request("baz", json!([])), None, ip));
+        assert!(limiter.check(&request("status", json!([])), None, ip));
+
+        // Opening WebSocket connections is charged to the client.
+        for _ in 0..19 {
+            assert!(limiter.check_method(WEBSOCKET_METHOD, None, ip));
+        }
+        assert!(!limiter.check_method(WEBSOCKET_METHOD, None, ip));
+    }
+
+    #[test]
+    fn validate_costs() {
+        let bucket = |capacity| TokenBucketConfig { refill_per_second: 1., capacity };
+        let mut config = RpcRateLimitsConfig {
+            per_client: bucket(20.),
+            api_keys: [("key".to_string(), bucket(100.))].into_iter().collect(),
+            per_method: [("block".to_string(), bucket(2.))].into_iter().collect(),
+            method_costs: default_method_costs(),
+        };
+        assert_eq!(config.validate(), Vec::<String>::new());
+
+        config.per_client.capacity = 10.;
+        config.api_keys.insert("small".to_string(), bucket(0.5));
+        config.per_method.insert("status".to_string(), bucket(0.));
+        config.method_costs.insert("block".to_string(), 3);
+        let mut errors = config.validate();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "api_keys.small.capacity 0.5 is less than 20, the max cost of a request",
+                "per_client.capacity 10 is less than 20, the max cost of a request",
+                "per_method.block.capacity 2 is less than the cost 3 of block",
+                "per_method.status.capacity 0 is less than the cost 1 of status",
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Websocket endpoint (`/ws`), which pushes updates of the chain to the subscribed clients,
+//! so that they don't need to poll `block` or `EXPERIMENTAL_changes`.
+//!
+//! The updates are driven by the `ChainEventBus` of the node: a single subscriber of the bus
+//! fetches the block of every new head and final block once and broadcasts it to the websocket
+//! sessions. A session which doesn't keep up misses the events it lagged behind on, and so do
+//! all the sessions if the node drops chain events; the clients are notified about that.
+//! See `near_jsonrpc_primitives::types::subscriptions` for the protocol.
+use crate::api::{Params, RpcRequest};
+use crate::JsonRpcHandler;
+use actix::Addr;
+use futures::StreamExt;
+use near_chain::{ChainEvent, ChainEventBus};
+use near_client::{GetBlock, GetStateChanges, TxStatus, ViewClientActor};
+use near_jsonrpc_primitives::errors::{RpcError, RpcParseError};
+use near_jsonrpc_primitives::message::{self, Message};
+use near_jsonrpc_primitives::types::changes::{
+    RpcStateChangesError, RpcStateChangesInBlockResponse,
+};
+use near_jsonrpc_primitives::types::subscriptions::{
+    RpcMissedUpdatesNotification, RpcSubscribeRequest, RpcSubscriptionNotification,
+    RpcUnsubscribeRequest,
+};
+use near_jsonrpc_primitives::types::transactions::{RpcTransactionError, RpcTransactionResponse};
+use near_o11y::WithSpanContextExt;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockId, BlockReference};
+use near_primitives::views::BlockView;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, OwnedSemaphorePermit, Semaphore};
+
+/// Number of events buffered for the websocket sessions.
+const CHAIN_EVENTS_CAPACITY: usize = 1024;
+/// Max number of active subscriptions of a single websocket session.
+const MAX_SUBSCRIPTIONS_PER_SESSION: usize = 32;
+/// Max number of websocket sessions, each of which costs a task and the view client requests
+/// for its subscriptions on every new head.
+const MAX_SESSIONS: usize = 1000;
+
+/// Update of the chain sent to the websocket sessions.
+#[derive(Clone, Debug)]
+enum SessionEvent {
+    /// New head or new final block of the chain. The block is fetched once for all the
+    /// sessions, and is None if that failed.
+    Block { block_hash: CryptoHash, is_final: bool, block: Option<Arc<BlockView>> },
+    /// The node dropped chain events, so the sessions missed updates.
+    Missed { skipped: u64 },
+}
+
+/// Chain events for the websocket sessions, see `forward_chain_events`.
+#[derive(Clone)]
+pub(crate) struct ChainEvents {
+    sender: broadcast::Sender<SessionEvent>,
+    sessions: Arc<Semaphore>,
+}
+
+/// Chain events of a single websocket session. The session counts against `MAX_SESSIONS`
+/// until this is dropped.
+pub(crate) struct SessionEvents {
+    receiver: broadcast::Receiver<SessionEvent>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ChainEvents {
+    /// Returns None if there are `MAX_SESSIONS` sessions already.
+    pub fn subscribe(&self) -> Option<SessionEvents> {
+        let permit = self.sessions.clone().try_acquire_owned().ok()?;
+        Some(SessionEvents { receiver: self.sender.subscribe(), _permit: permit })
+    }
+}
+
+impl RpcRequest for RpcSubscribeRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
+impl RpcRequest for RpcUnsubscribeRequest {
+    fn parse(value: Value) -> Result<Self, RpcParseError> {
+        Params::parse(value)
+    }
+}
+
+/// Subscribes to the bus and forwards its new heads and final blocks to the websocket
+/// sessions, fetching the blocks only while there are sessions. Must be called within the
+/// tokio runtime.
+pub(crate) fn forward_chain_events(
+    bus: &ChainEventBus,
+    view_client: Addr<ViewClientActor>,
+) -> ChainEvents {
+    let receiver = bus.subscribe();
+    let (events_sender, mut events) = mpsc::channel(CHAIN_EVENTS_CAPACITY);
+    std::thread::Builder::new()
+        .name("rpc_chain_events".to_string())
+        .spawn(move || {
+            for event in receiver {
+                match event {
+                    ChainEvent::NewHead(_)
+                    | ChainEvent::NewFinalHead(_)
+                    | ChainEvent::EventsDropped { .. } => {}
+                    ChainEvent::Reorg { .. } | ChainEvent::ChunkApplied { .. } => continue,
+                }
+                if events_sender.blocking_send(event).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("failed to spawn the thread forwarding chain events");
+    let (sender, _) = broadcast::channel(CHAIN_EVENTS_CAPACITY);
+    let forward = sender.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if forward.receiver_count() == 0 {
+                continue;
+            }
+            let (block_hash, is_final) = match event {
+                ChainEvent::NewHead(tip) => (tip.last_block_hash, false),
+                ChainEvent::NewFinalHead(tip) => (tip.last_block_hash, true),
+                ChainEvent::EventsDropped { count } => {
+                    let _ = forward.send(SessionEvent::Missed { skipped: count });
+                    continue;
+                }
+                ChainEvent::Reorg { .. } | ChainEvent::ChunkApplied { .. } => continue,
+            };
+            let request = GetBlock(BlockReference::BlockId(BlockId::Hash(block_hash)));
+            let block = match view_client.send(request.with_span_context()).await {
+                Ok(Ok(block)) => Some(Arc::new(block)),
+                Ok(Err(err)) => {
+                    tracing::debug!(target: "jsonrpc", ?err, %block_hash, "Failed to get block for subscriptions");
+                    None
+                }
+                Err(err) => {
+                    tracing::debug!(target: "jsonrpc", ?err, %block_hash, "Failed to get block for subscriptions");
+                    None
+                }
+            };
+            // Fails only if there are no websocket sessions at the moment.
+            let _ = forward.send(SessionEvent::Block { block_hash, is_final, block });
+        }
+    });
+    ChainEvents { sender, sessions: Arc::new(Semaphore::new(MAX_SESSIONS)) }
+}
+
+/// Error of a well-formed request which can't be served, e.g. unsubscribing an unknown id.
+fn invalid_params(message: String) -> RpcError {
+    RpcError::new(-32_602, "Invalid params".to_owned(), Some(Value::String(message)))
+}
+
+struct Session {
+    handler: Arc<JsonRpcHandler>,
+    subscriptions: BTreeMap<u64, RpcSubscribeRequest>,
+    next_id: u64,
+}
+
+impl Session {
+    fn handle_request(&mut self, text: &str) -> Message {
+        let request = match message::from_str(text) {
+            Ok(Message::Request(request)) => request,
+            Ok(_) => {
+                return Message::error(RpcError::parse_error(
+                    "JSON RPC Request format was expected".to_owned(),
+                ))
+            }
+            Err(broken) => return broken.reply(),
+        };
+        let result = match request.method.as_str() {
+            "subscribe" => RpcRequest::parse(request.params)
+                .map_err(RpcError::from)
+                .and_then(|request| self.subscribe(request)),
+            "unsubscribe" => RpcRequest::parse(request.params)
+                .map_err(RpcError::from)
+                .and_then(|request| self.unsubscribe(request)),
+            _ => Err(RpcError::method_not_found(request.method.clone())),
+        };
+        Message::response(request.id, result)
+    }
+
+    fn subscribe(&mut self, request: RpcSubscribeRequest) -> Result<Value, RpcError> {
+        if self.subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_SESSION {
+            return Err(invalid_params(format!(
+                "Too many subscriptions, the limit is {MAX_SUBSCRIPTIONS_PER_SESSION}"
+            )));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscriptions.insert(id, request);
+        Ok(Value::from(id))
+    }
+
+    fn unsubscribe(&mut self, request: RpcUnsubscribeRequest) -> Result<Value, RpcError> {
+        match self.subscriptions.remove(&request.subscription) {
+            Some(_) => Ok(Value::Bool(true)),
+            None => Err(invalid_params(format!("Unknown subscription {}", request.subscription))),
+        }
+    }
+
+    /// Computes the notifications caused by the event.
+    async fn notifications(&mut self, event: SessionEvent) -> Vec<Message> {
+        if self.subscriptions.is_empty() {
+            return vec![];
+        }
+        let (block_hash, is_final, block) = match event {
+            SessionEvent::Block { block_hash, is_final, block } => (block_hash, is_final, block),
+            SessionEvent::Missed { skipped } => {
+                let params = RpcMissedUpdatesNotification { skipped };
+                return vec![Message::notification(
+                    "missed_updates".to_string(),
+                    serde_json::to_value(params).unwrap(),
+                )];
+            }
+        };
+        let mut notifications = vec![];
+        let mut finished = vec![];
+        for (id, subscription) in &self.subscriptions {
+            let result = match subscription {
+                RpcSubscribeRequest::NewHeads if !is_final => {
+                    block.as_ref().map(|block| serde_json::to_value(&**block))
+                }
+                RpcSubscribeRequest::FinalizedBlocks if is_final => {
+                    block.as_ref().map(|block| serde_json::to_value(&**block))
+                }
+                RpcSubscribeRequest::StateChanges { state_changes_request } if !is_final => {
+                    let request = GetStateChanges {
+                        block_hash,
+                        state_changes_request: state_changes_request.clone(),
+                    };
+                    match self
+                        .handler
+                        .view_client_send::<_, _, RpcStateChangesError, _>(request)
+                        .await
+                    {
+                        Ok(changes) if changes.is_empty() => None,
+                        Ok(changes) => Some(serde_json::to_value(RpcStateChangesInBlockResponse {
+                            block_hash,
+                            changes,
+                            continuation_token: None,
+                        })),
+                        Err(err) => {
+                            tracing::debug!(target: "jsonrpc", ?err, %block_hash, "Failed to get state changes for subscription");
+                            None
+                        }
+                    }
+                }
+                RpcSubscribeRequest::TxStatus { tx_hash, sender_account_id } if !is_final => {
+                    let request = TxStatus {
+                        tx_hash: *tx_hash,
+                        signer_account_id: sender_account_id.clone(),
+                        fetch_receipt: false,
+                    };
+                    match self
+                        .handler
+                        .view_client_send::<_, _, RpcTransactionError, _>(request)
+                        .await
+                    {
+                        Ok(status) => status.execution_outcome.map(|outcome| {
+                            finished.push(*id);
+                            serde_json::to_value(RpcTransactionResponse {
+                                final_execution_outcome: Some(outcome),
+                                final_execution_status: status.status,
+                            })
+                        }),
+                        // The transaction is not known yet.
+                        Err(_) => None,
+                    }
+                }
+                _ => None,
+            };
+            match result {
+                Some(Ok(result)) => {
+                    let params = RpcSubscriptionNotification { subscription: *id, result };
+                    notifications.push(Message::notification(
+                        "subscription".to_string(),
+                        serde_json::to_value(params).unwrap(),
+                    ));
+                }
+                Some(Err(err)) => {
+                    tracing::warn!(target: "jsonrpc", ?err, "Failed to serialize subscription notification")
+                }
+                None => {}
+            }
+        }
+        for id in finished {
+            self.subscriptions.remove(&id);
+        }
+        notifications
+    }
+}
+
+/// Input of a websocket session.
+enum Input {
+    Client(Option<Result<actix_ws::Message, actix_ws::ProtocolError>>),
+    Event(Result<SessionEvent, broadcast::error::RecvError>),
+}
+
+/// Serves a websocket session until the client disconnects.
+pub(crate) async fn run_session(
+    handler: Arc<JsonRpcHandler>,
+    mut events: SessionEvents,
+    mut ws: actix_ws::Session,
+    mut stream: actix_ws::MessageStream,
+) {
+    let mut session = Session { handler, subscriptions: BTreeMap::new(), next_id: 0 };
+    loop {
+        let input = tokio::select! {
+            msg = stream.next() => Input::Client(msg),
+            event = events.receiver.recv() => Input::Event(event),
+        };
+        let replies = match input {
+            Input::Client(Some(Ok(actix_ws::Message::Text(text)))) => {
+                vec![session.handle_request(&text)]
+            }
+            Input::Client(Some(Ok(actix_ws::Message::Ping(bytes)))) => {
+                if ws.pong(&bytes).await.is_err() {
+                    return;
+                }
+                vec![]
+            }
+            Input::Client(Some(Ok(actix_ws::Message::Close(reason)))) => {
+                let _ = ws.close(reason).await;
+                return;
+            }
+            Input::Client(Some(Ok(_))) => vec![],
+            Input::Client(Some(Err(_)) | None) => break,
+            Input::Event(Ok(event)) => session.notifications(event).await,
+            Input::Event(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                tracing::debug!(target: "jsonrpc", skipped, "Websocket session lagged behind chain events");
+                session.notifications(SessionEvent::Missed { skipped }).await
+            }
+            Input::Event(Err(broadcast::error::RecvError::Closed)) => break,
+        };
+        for reply in replies {
+            let text: String = reply.into();
+            if ws.text(text).await.is_err() {
+                return;
+            }
+        }
+    }
+    let _ = ws.close(None).await;
+}
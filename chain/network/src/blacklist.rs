@@ -43,6 +43,77 @@ impl std::str::FromStr for Entry {
     }
 }
 
+fn to_ipv6(ip: net::IpAddr) -> net::Ipv6Addr {
+    match ip {
+        net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        net::IpAddr::V6(ip) => ip,
+    }
+}
+
+/// Range of IP addresses in the CIDR notation, e.g. "192.0.2.0/24".
+/// A single IP address is parsed as a range containing just that address.
+/// Like the Entry, IPv4 ranges are stored as the ranges of IPv4-mapped IPv6 addresses.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct IpRange {
+    addr: net::Ipv6Addr,
+    prefix_len: u32,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseIpRangeError {
+    #[error("IP address: {0}")]
+    Addr(#[source] std::net::AddrParseError),
+    #[error("invalid prefix length: {0}")]
+    PrefixLen(String),
+}
+
+impl IpRange {
+    fn mask(prefix_len: u32) -> u128 {
+        u128::MAX.checked_shl(128 - prefix_len).unwrap_or(0)
+    }
+
+    pub fn new(ip: net::IpAddr, prefix_len: u32) -> Result<Self, ParseIpRangeError> {
+        let max_prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return Err(ParseIpRangeError::PrefixLen(prefix_len.to_string()));
+        }
+        let prefix_len = prefix_len + 128 - max_prefix_len;
+        let addr = (u128::from(to_ipv6(ip)) & Self::mask(prefix_len)).into();
+        Ok(Self { addr, prefix_len })
+    }
+
+    pub fn contains(&self, ip: net::IpAddr) -> bool {
+        u128::from(to_ipv6(ip)) & Self::mask(self.prefix_len) == u128::from(self.addr)
+    }
+}
+
+impl std::str::FromStr for IpRange {
+    type Err = ParseIpRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix_len) = match s.split_once('/') {
+            Some((ip, len)) => (ip, Some(len)),
+            None => (s, None),
+        };
+        let ip: net::IpAddr = ip.parse().map_err(ParseIpRangeError::Addr)?;
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse().map_err(|_| ParseIpRangeError::PrefixLen(len.to_string()))?,
+            None if ip.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(ip, prefix_len)
+    }
+}
+
+impl std::fmt::Display for IpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.addr.to_ipv4_mapped() {
+            Some(ip) if self.prefix_len >= 96 => write!(f, "{}/{}", ip, self.prefix_len - 96),
+            _ => write!(f, "{}/{}", self.addr, self.prefix_len),
+        }
+    }
+}
+
 /// A blacklist for socket addresses.  Supports adding individual IP:port tuples
 /// to the blacklist or entire IPs.
 #[derive(Debug, Default, Clone)]
@@ -124,4 +195,29 @@ mod test {
         assert!(blacklist.contains(SocketAddr::new(mapped_ip, 42)));
         assert!(!blacklist.contains(SocketAddr::new(mapped_ip, 8080)));
     }
+
+    #[test]
+    fn test_ip_range() {
+        fn parse(value: &str) -> Option<IpRange> {
+            value.parse().ok()
+        }
+
+        assert_eq!(None, parse("foo"));
+        assert_eq!(None, parse("192.0.2.0/33"));
+        assert_eq!(None, parse("2001:db8::/129"));
+        assert_eq!(None, parse("192.0.2.0/x"));
+
+        let range = parse("192.0.2.77/24").unwrap();
+        assert_eq!("192.0.2.0/24", range.to_string());
+        assert!(range.contains("192.0.2.4".parse().unwrap()));
+        assert!(range.contains("::ffff:192.0.2.4".parse().unwrap()));
+        assert!(!range.contains("192.0.3.4".parse().unwrap()));
+        assert!(parse("192.0.2.4").unwrap().contains("192.0.2.4".parse().unwrap()));
+        assert!(!parse("192.0.2.4").unwrap().contains("192.0.2.5".parse().unwrap()));
+
+        let range = parse("2001:db8::/32").unwrap();
+        assert!(range.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!range.contains("2001:db9::1".parse().unwrap()));
+        assert!(parse("::/0").unwrap().contains(LO4));
+    }
 }
@@ -4,6 +4,7 @@ use crate::types::{NetworkInfo, ReasonForBan};
 
 use near_primitives::block::{Approval, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::transaction::SignedTransaction;
@@ -44,7 +45,11 @@ pub trait Client: Send + Sync + 'static {
 
     async fn block_request(&self, hash: CryptoHash) -> Option<Box<Block>>;
 
-    async fn block_headers_request(&self, hashes: Vec<CryptoHash>) -> Option<Vec<BlockHeader>>;
+    async fn block_headers_request(
+        &self,
+        hashes: Vec<CryptoHash>,
+        max_headers: Option<u64>,
+    ) -> Option<Vec<BlockHeader>>;
 
     async fn block(&self, block: Block, peer_id: PeerId, was_requested: bool);
 
@@ -56,6 +61,14 @@ pub trait Client: Send + Sync + 'static {
 
     async fn challenge(&self, challenge: Challenge);
 
+    async fn epoch_sync_request(&self, prev_epoch_id: EpochId) -> Option<Box<EpochSyncProof>>;
+
+    async fn epoch_sync_response(
+        &self,
+        proof: EpochSyncProof,
+        peer_id: PeerId,
+    ) -> Result<(), ReasonForBan>;
+
     async fn network_info(&self, info: NetworkInfo);
 
     async fn announce_account(
@@ -105,7 +118,11 @@ impl Client for Noop {
         None
     }
 
-    async fn block_headers_request(&self, _hashes: Vec<CryptoHash>) -> Option<Vec<BlockHeader>> {
+    async fn block_headers_request(
+        &self,
+        _hashes: Vec<CryptoHash>,
+        _max_headers: Option<u64>,
+    ) -> Option<Vec<BlockHeader>> {
         None
     }
 
@@ -121,6 +138,18 @@ impl Client for Noop {
 
     async fn challenge(&self, _challenge: Challenge) {}
 
+    async fn epoch_sync_request(&self, _prev_epoch_id: EpochId) -> Option<Box<EpochSyncProof>> {
+        None
+    }
+
+    async fn epoch_sync_response(
+        &self,
+        _proof: EpochSyncProof,
+        _peer_id: PeerId,
+    ) -> Result<(), ReasonForBan> {
+        Ok(())
+    }
+
     async fn network_info(&self, _info: NetworkInfo) {}
 
     async fn announce_account(
@@ -1,3 +1,5 @@
+use near_async::time;
+
 /// Config of a rate limiter algorithm, which behaves like a semaphore
 /// - with maximal capacity `burst`
 /// - with a new ticket added automatically every 1/qps seconds (qps stands for "queries per
@@ -25,3 +27,33 @@ impl Limit {
         Ok(())
     }
 }
+
+/// Non-blocking implementation of the `Limit` algorithm: a request which doesn't get a ticket
+/// right away is rejected rather than delayed.
+#[derive(Clone)]
+pub struct Bucket {
+    limit: Limit,
+    tickets: f64,
+    updated_at: time::Instant,
+}
+
+impl Bucket {
+    /// Creates a full bucket.
+    pub fn new(limit: Limit, now: time::Instant) -> Self {
+        Self { limit, tickets: limit.burst as f64, updated_at: now }
+    }
+
+    /// Takes a ticket if there is one available.
+    pub fn try_acquire(&mut self, now: time::Instant) -> bool {
+        if now > self.updated_at {
+            let refill = (now - self.updated_at).as_seconds_f64() * self.limit.qps;
+            self.tickets = (self.tickets + refill).min(self.limit.burst as f64);
+            self.updated_at = now;
+        }
+        if self.tickets < 1. {
+            return false;
+        }
+        self.tickets -= 1.;
+        true
+    }
+}
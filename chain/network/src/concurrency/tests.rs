@@ -1,6 +1,7 @@
 use crate::concurrency::arc_mutex::ArcMutex;
 use crate::concurrency::demux;
 use crate::concurrency::rate;
+use near_async::time;
 
 #[tokio::test]
 async fn test_demux() {
@@ -97,3 +98,22 @@ fn arc_mutex_update() {
     );
     assert_eq!(v3, *m.load());
 }
+
+#[test]
+fn rate_bucket() {
+    let clock = time::FakeClock::default();
+    let mut bucket = rate::Bucket::new(rate::Limit { qps: 2., burst: 3 }, clock.now());
+    for _ in 0..3 {
+        assert!(bucket.try_acquire(clock.now()));
+    }
+    assert!(!bucket.try_acquire(clock.now()));
+    clock.advance(time::Duration::milliseconds(500));
+    assert!(bucket.try_acquire(clock.now()));
+    assert!(!bucket.try_acquire(clock.now()));
+    // The bucket doesn't refill above `burst`.
+    clock.advance(time::Duration::seconds(10));
+    for _ in 0..3 {
+        assert!(bucket.try_acquire(clock.now()));
+    }
+    assert!(!bucket.try_acquire(clock.now()));
+}
@@ -30,6 +30,14 @@ pub const MAX_PEER_ADDRS: usize = 10;
 /// Maximum number of peers to include in a PeersResponse message.
 pub const PEERS_RESPONSE_MAX_PEERS: u32 = 512;
 
+/// Maximum value of outbound_diversity_bias. Higher values make the odds of the peers from
+/// the connected network groups negligible anyway.
+pub const MAX_OUTBOUND_DIVERSITY_BIAS: f64 = 10.;
+
+/// Maximal zstd compression level of the messages. Higher levels are too slow to be used
+/// for every message.
+pub const MAX_MESSAGE_COMPRESSION_LEVEL: i32 = 19;
+
 /// ValidatorProxies are nodes with public IP (aka proxies) that this validator trusts to be honest
 /// and willing to forward traffic to this validator. Whenever this node is a TIER1 validator
 /// (i.e. whenever it is a block producer/chunk producer/approver for the given epoch),
@@ -86,6 +94,19 @@ pub struct Tier1 {
     /// - a node will try to start outbound TIER1 connections iff `enable_outbound` is true.
     pub enable_inbound: bool,
     pub enable_outbound: bool,
+    /// Interval between pings sent over TIER1 connections to measure their health.
+    /// Messages are not sent over the connections which turn out to be degraded.
+    pub probe_interval: time::Duration,
+}
+
+/// Compression of the messages sent to the peers which support it,
+/// see `network_protocol::capabilities::ZSTD_COMPRESSION`.
+#[derive(Clone, Debug)]
+pub struct MessageCompression {
+    /// Only messages of at least this size (after serialization) are compressed.
+    pub threshold_bytes: usize,
+    /// zstd compression level.
+    pub level: i32,
 }
 
 /// Validated configuration for the peer-to-peer manager.
@@ -97,6 +118,13 @@ pub struct NetworkConfig {
 
     pub peer_store: peer_store::Config,
     pub whitelist_nodes: Vec<PeerInfo>,
+    /// Nodes through which this node can be reached if it is not reachable from the internet.
+    /// The connections to them are kept open, and if this node is a validator,
+    /// they are its TIER1 proxies.
+    pub relay_nodes: Vec<PeerInfo>,
+    /// Protocol used to map the listening port on the NAT gateway, see `crate::port_mapping`.
+    /// Requires the `port_mapping` feature.
+    pub port_mapping: Option<crate::port_mapping::Protocol>,
     pub handshake_timeout: time::Duration,
 
     /// Whether to re-establish connection to known reliable peers from previous neard run(s).
@@ -151,6 +179,14 @@ pub struct NetworkConfig {
     pub accounts_data_broadcast_rate_limit: rate::Limit,
     /// Maximal rate at which RoutingTable can be recomputed.
     pub routing_table_update_rate_limit: rate::Limit,
+    /// Maximal rate of new inbound connections from a single IP address.
+    pub inbound_connections_per_ip_rate_limit: rate::Limit,
+    /// Maximal rate of new inbound connections from a single subnet
+    /// (/24 for IPv4, /64 for IPv6).
+    pub inbound_connections_per_subnet_rate_limit: rate::Limit,
+    /// Maximal rate at which the signatures of inbound handshakes are verified.
+    /// Handshakes above it are rejected before the verification.
+    pub inbound_handshakes_rate_limit: rate::Limit,
     /// Config of the TIER1 network.
     pub tier1: Option<Tier1>,
 
@@ -161,10 +197,48 @@ pub struct NetworkConfig {
     //   * ignoring received deleted edges as well
     pub skip_tombstones: Option<time::Duration>,
 
+    /// Whether to also accept and open connections over QUIC, see `crate::quic`.
+    /// Requires the `quic` feature.
+    ///
+    /// QUIC requires TLS, but the certificates are self-signed and not verified: peers are
+    /// authenticated by the signed Handshake exchanged over the connection, exactly as on TCP,
+    /// which has no TLS at all. A man in the middle can therefore at most read the messages,
+    /// which are not secret, not impersonate a peer.
+    ///
+    /// Establishing a QUIC connection times out after 1s, the same as for TCP. Connecting then
+    /// falls back to TCP, so peers whose UDP traffic is blocked are only delayed by that much.
+    pub quic_enabled: bool,
+
+    /// If set, blocks, chunks and state parts sent to the peers which support it are compressed.
+    /// Compressed messages are accepted regardless.
+    pub message_compression: Option<MessageCompression>,
+
     /// TEST-ONLY
     /// TODO(gprusak): make it pub(crate), once all integration tests
     /// are merged into near_network.
     pub event_sink: Sink<Event>,
+
+    /// TEST-ONLY: simulated network to listen and connect on instead of TCP.
+    #[cfg(test)]
+    pub(crate) simnet: Option<Arc<crate::testonly::simnet::Simnet>>,
+}
+
+/// Parses a comma separated list of peers, each of which is required to specify both PeerId
+/// and IP:port.
+fn parse_peers_with_addrs(peers: &str) -> anyhow::Result<Vec<PeerInfo>> {
+    if peers.is_empty() {
+        return Ok(vec![]);
+    }
+    peers
+        .split(',')
+        .map(|peer| match peer.parse::<PeerInfo>() {
+            Ok(peer) if peer.addr.is_none() => {
+                anyhow::bail!("both PeerId and IP:port are required for {peer}")
+            }
+            Ok(peer) => Ok(peer),
+            Err(err) => Err(err.into()),
+        })
+        .collect()
 }
 
 impl NetworkConfig {
@@ -207,6 +281,24 @@ impl NetworkConfig {
         ) {
             self.routing_table_update_rate_limit = rate::Limit { qps, burst }
         }
+        if let (Some(qps), Some(burst)) = (
+            overrides.inbound_connections_per_ip_rate_limit_qps,
+            overrides.inbound_connections_per_ip_rate_limit_burst,
+        ) {
+            self.inbound_connections_per_ip_rate_limit = rate::Limit { qps, burst }
+        }
+        if let (Some(qps), Some(burst)) = (
+            overrides.inbound_connections_per_subnet_rate_limit_qps,
+            overrides.inbound_connections_per_subnet_rate_limit_burst,
+        ) {
+            self.inbound_connections_per_subnet_rate_limit = rate::Limit { qps, burst }
+        }
+        if let (Some(qps), Some(burst)) = (
+            overrides.inbound_handshakes_rate_limit_qps,
+            overrides.inbound_handshakes_rate_limit_burst,
+        ) {
+            self.inbound_handshakes_rate_limit = rate::Limit { qps, burst }
+        }
     }
 
     pub fn new(
@@ -215,6 +307,16 @@ impl NetworkConfig {
         validator_signer: Option<Arc<dyn ValidatorSigner>>,
         archive: bool,
     ) -> anyhow::Result<Self> {
+        let relay_nodes = parse_peers_with_addrs(&cfg.relay_nodes).context("relay_nodes")?;
+        if relay_nodes.len() > MAX_PEER_ADDRS {
+            anyhow::bail!(
+                "relay_nodes has {} entries, limit is {MAX_PEER_ADDRS}",
+                relay_nodes.len()
+            );
+        }
+        if !relay_nodes.is_empty() && !cfg.public_addrs.is_empty() {
+            anyhow::bail!("relay_nodes and public_addrs can't be set together: relays are the TIER1 proxies of the node.");
+        }
         if cfg.public_addrs.len() > MAX_PEER_ADDRS {
             anyhow::bail!(
                 "public_addrs has {} entries, limit is {MAX_PEER_ADDRS}",
@@ -252,6 +354,16 @@ impl NetworkConfig {
                 signer,
                 proxies: if !cfg.public_addrs.is_empty() {
                     ValidatorProxies::Static(cfg.public_addrs)
+                } else if !relay_nodes.is_empty() {
+                    ValidatorProxies::Static(
+                        relay_nodes
+                            .iter()
+                            .map(|relay| PeerAddr {
+                                addr: relay.addr.unwrap(),
+                                peer_id: relay.id.clone(),
+                            })
+                            .collect(),
+                    )
                 } else {
                     ValidatorProxies::Dynamic(cfg.trusted_stun_servers)
                 },
@@ -282,22 +394,14 @@ impl NetworkConfig {
                 connect_only_to_boot_nodes: cfg.experimental.connect_only_to_boot_nodes,
                 ban_window: cfg.ban_window.try_into()?,
                 peer_expiration_duration: cfg.peer_expiration_duration.try_into()?,
+                network_group_ipv4_prefix_len: cfg.network_group_ipv4_prefix_len,
+                network_group_ipv6_prefix_len: cfg.network_group_ipv6_prefix_len,
+                outbound_diversity_bias: cfg.outbound_diversity_bias,
             },
-            whitelist_nodes: if cfg.whitelist_nodes.is_empty() {
-                vec![]
-            } else {
-                cfg.whitelist_nodes
-                    .split(',')
-                    .map(|peer| match peer.parse::<PeerInfo>() {
-                        Ok(peer) if peer.addr.is_none() => anyhow::bail!(
-                            "whitelist_nodes are required to specify both PeerId and IP:port"
-                        ),
-                        Ok(peer) => Ok(peer),
-                        Err(err) => Err(err.into()),
-                    })
-                    .collect::<anyhow::Result<_>>()
-                    .context("whitelist_nodes")?
-            },
+            whitelist_nodes: parse_peers_with_addrs(&cfg.whitelist_nodes)
+                .context("whitelist_nodes")?,
+            relay_nodes,
+            port_mapping: cfg.port_mapping,
             connect_to_reliable_peers_on_startup: true,
             handshake_timeout: cfg.handshake_timeout.try_into()?,
             monitor_peers_max_period: cfg.monitor_peers_max_period.try_into()?,
@@ -319,12 +423,16 @@ impl NetworkConfig {
             archive,
             accounts_data_broadcast_rate_limit: rate::Limit { qps: 0.1, burst: 1 },
             routing_table_update_rate_limit: rate::Limit { qps: 1., burst: 1 },
+            inbound_connections_per_ip_rate_limit: rate::Limit { qps: 0.2, burst: 10 },
+            inbound_connections_per_subnet_rate_limit: rate::Limit { qps: 2., burst: 50 },
+            inbound_handshakes_rate_limit: rate::Limit { qps: 50., burst: 200 },
             tier1: Some(Tier1 {
                 connect_interval: cfg.experimental.tier1_connect_interval.try_into()?,
                 new_connections_per_attempt: cfg.experimental.tier1_new_connections_per_attempt,
                 advertise_proxies_interval: time::Duration::minutes(15),
                 enable_inbound: cfg.experimental.tier1_enable_inbound,
                 enable_outbound: cfg.experimental.tier1_enable_outbound,
+                probe_interval: time::Duration::seconds(1),
             }),
             inbound_disabled: cfg.experimental.inbound_disabled,
             skip_tombstones: if cfg.experimental.skip_sending_tombstones_seconds > 0 {
@@ -332,7 +440,18 @@ impl NetworkConfig {
             } else {
                 None
             },
+            quic_enabled: cfg.experimental.quic_enabled,
+            message_compression: if cfg.experimental.message_compression_enabled {
+                Some(MessageCompression {
+                    threshold_bytes: cfg.experimental.message_compression_threshold_bytes,
+                    level: cfg.experimental.message_compression_level,
+                })
+            } else {
+                None
+            },
             event_sink: Sink::null(),
+            #[cfg(test)]
+            simnet: None,
         };
         this.override_config(cfg.experimental.network_config_overrides);
         Ok(this)
@@ -363,8 +482,13 @@ impl NetworkConfig {
                 ban_window: time::Duration::seconds(1),
                 peer_expiration_duration: time::Duration::seconds(60 * 60),
                 connect_only_to_boot_nodes: false,
+                network_group_ipv4_prefix_len: 16,
+                network_group_ipv6_prefix_len: 32,
+                outbound_diversity_bias: 2.,
             },
             whitelist_nodes: vec![],
+            relay_nodes: vec![],
+            port_mapping: None,
             handshake_timeout: time::Duration::seconds(5),
             connect_to_reliable_peers_on_startup: true,
             monitor_peers_max_period: time::Duration::seconds(100),
@@ -387,6 +511,10 @@ impl NetworkConfig {
             archive: false,
             accounts_data_broadcast_rate_limit: rate::Limit { qps: 100., burst: 1000000 },
             routing_table_update_rate_limit: rate::Limit { qps: 10., burst: 1 },
+            // All the test nodes connect from the loopback address.
+            inbound_connections_per_ip_rate_limit: rate::Limit { qps: 1000., burst: 1000000 },
+            inbound_connections_per_subnet_rate_limit: rate::Limit { qps: 1000., burst: 1000000 },
+            inbound_handshakes_rate_limit: rate::Limit { qps: 1000., burst: 1000000 },
             tier1: Some(Tier1 {
                 // Interval is very large, so that it doesn't happen spontaneously in tests.
                 // It should rather be triggered manually in tests.
@@ -395,9 +523,14 @@ impl NetworkConfig {
                 advertise_proxies_interval: time::Duration::hours(1000),
                 enable_inbound: true,
                 enable_outbound: true,
+                probe_interval: time::Duration::hours(1000),
             }),
             skip_tombstones: None,
+            quic_enabled: false,
+            message_compression: Some(MessageCompression { threshold_bytes: 8 * 1024, level: 3 }),
             event_sink: Sink::null(),
+            #[cfg(test)]
+            simnet: None,
         }
     }
 
@@ -425,6 +558,31 @@ impl NetworkConfig {
             );
         }
 
+        if self.quic_enabled && !cfg!(feature = "quic") {
+            anyhow::bail!("quic_enabled is set, but the node was built without the quic feature");
+        }
+
+        if let Some(compression) = &self.message_compression {
+            if !(1..=MAX_MESSAGE_COMPRESSION_LEVEL).contains(&compression.level) {
+                anyhow::bail!(
+                    "message_compression_level({}) should be between 1 and {}",
+                    compression.level,
+                    MAX_MESSAGE_COMPRESSION_LEVEL
+                );
+            }
+        }
+
+        if self.port_mapping.is_some() {
+            if !cfg!(feature = "port_mapping") {
+                anyhow::bail!(
+                    "port_mapping is set, but the node was built without the port_mapping feature"
+                );
+            }
+            if self.node_addr.is_none() {
+                anyhow::bail!("port_mapping is set, but the node doesn't listen on any address");
+            }
+        }
+
         if UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE * 2 > self.peer_recent_time_window {
             anyhow::bail!(
                 "Very short peer_recent_time_window({}). it should be at least twice update_interval_last_time_received_message({}).",
@@ -440,12 +598,37 @@ impl NetworkConfig {
             );
         }
 
+        if !(self.peer_store.network_group_ipv4_prefix_len <= 32
+            && self.peer_store.network_group_ipv6_prefix_len <= 128)
+        {
+            anyhow::bail!(
+                "network_group_ipv4_prefix_len({}) can be at most 32 and network_group_ipv6_prefix_len({}) at most 128",
+                self.peer_store.network_group_ipv4_prefix_len,
+                self.peer_store.network_group_ipv6_prefix_len
+            );
+        }
+
+        if !(0. ..=MAX_OUTBOUND_DIVERSITY_BIAS).contains(&self.peer_store.outbound_diversity_bias) {
+            anyhow::bail!(
+                "outbound_diversity_bias({}) should be between 0 and {}",
+                self.peer_store.outbound_diversity_bias,
+                MAX_OUTBOUND_DIVERSITY_BIAS
+            );
+        }
+
         self.accounts_data_broadcast_rate_limit
             .validate()
             .context("accounts_Data_broadcast_rate_limit")?;
         self.routing_table_update_rate_limit
             .validate()
             .context("routing_table_update_rate_limit")?;
+        self.inbound_connections_per_ip_rate_limit
+            .validate()
+            .context("inbound_connections_per_ip_rate_limit")?;
+        self.inbound_connections_per_subnet_rate_limit
+            .validate()
+            .context("inbound_connections_per_subnet_rate_limit")?;
+        self.inbound_handshakes_rate_limit.validate().context("inbound_handshakes_rate_limit")?;
         Ok(VerifiedConfig { node_id: self.node_id(), inner: self })
     }
 }
@@ -510,6 +693,41 @@ mod test {
         assert!(nc.verify().is_err());
     }
 
+    #[test]
+    fn relay_nodes() {
+        let mut rng = make_rng(921847);
+        let relays: Vec<_> =
+            (0..3).map(|_| data::make_peer_addr(&mut rng, data::make_ipv4(&mut rng))).collect();
+        let mut cfg = crate::config_json::Config::default();
+        cfg.relay_nodes = relays
+            .iter()
+            .map(|relay| format!("{}@{}", relay.peer_id, relay.addr))
+            .collect::<Vec<_>>()
+            .join(",");
+        let node_key = data::make_secret_key(&mut rng);
+        let signer: std::sync::Arc<dyn near_primitives::validator_signer::ValidatorSigner> =
+            std::sync::Arc::new(data::make_validator_signer(&mut rng));
+
+        // Relays are the TIER1 proxies of a validator.
+        let nc =
+            config::NetworkConfig::new(cfg.clone(), node_key.clone(), Some(signer.clone()), false)
+                .unwrap();
+        assert_eq!(3, nc.relay_nodes.len());
+        match nc.validator.unwrap().proxies {
+            config::ValidatorProxies::Static(proxies) => assert_eq!(relays, proxies),
+            config::ValidatorProxies::Dynamic(_) => panic!("expected static proxies"),
+        }
+
+        // Relays can't be combined with public_addrs.
+        let mut cfg2 = cfg.clone();
+        cfg2.public_addrs = vec![relays[0].clone()];
+        assert!(config::NetworkConfig::new(cfg2, node_key.clone(), Some(signer), false).is_err());
+
+        // Relays need an address.
+        cfg.relay_nodes = relays[0].peer_id.to_string();
+        assert!(config::NetworkConfig::new(cfg, node_key, None, false).is_err());
+    }
+
     #[test]
     fn test_network_config_override() {
         fn check_override_field<T: std::cmp::PartialEq>(
@@ -573,6 +791,16 @@ mod test {
                 &after.accounts_data_broadcast_rate_limit.qps,
                 &overrides.accounts_data_broadcast_rate_limit_qps
             ));
+            assert!(check_override_field(
+                &before.inbound_connections_per_ip_rate_limit.burst,
+                &after.inbound_connections_per_ip_rate_limit.burst,
+                &overrides.inbound_connections_per_ip_rate_limit_burst
+            ));
+            assert!(check_override_field(
+                &before.inbound_connections_per_ip_rate_limit.qps,
+                &after.inbound_connections_per_ip_rate_limit.qps,
+                &overrides.inbound_connections_per_ip_rate_limit_qps
+            ));
         };
         let no_overrides = NetworkConfigOverrides::default();
         let mut overrides = NetworkConfigOverrides::default();
@@ -581,6 +809,8 @@ mod test {
         overrides.routed_message_ttl = Some(43);
         overrides.accounts_data_broadcast_rate_limit_burst = Some(44);
         overrides.accounts_data_broadcast_rate_limit_qps = Some(45.0);
+        overrides.inbound_connections_per_ip_rate_limit_burst = Some(46);
+        overrides.inbound_connections_per_ip_rate_limit_qps = Some(47.0);
 
         let nc_before =
             config::NetworkConfig::from_seed("123", tcp::ListenerAddr::reserve_for_test());
@@ -55,6 +55,18 @@ fn default_peer_states_cache_size() -> u32 {
 fn default_peer_expiration_duration() -> Duration {
     Duration::from_secs(7 * 24 * 60 * 60)
 }
+/// IPv4 addresses in the same /16 are considered to be in the same network group.
+fn default_network_group_ipv4_prefix_len() -> u32 {
+    16
+}
+/// IPv6 addresses in the same /32 are considered to be in the same network group.
+fn default_network_group_ipv6_prefix_len() -> u32 {
+    32
+}
+/// How strongly to prefer connecting to peers from the less connected network groups.
+fn default_outbound_diversity_bias() -> f64 {
+    2.
+}
 
 /// If non-zero - we'll skip sending tombstones during initial sync and for that many seconds after start.
 fn default_skip_tombstones() -> i64 {
@@ -93,6 +105,14 @@ pub struct Config {
     ///   ed25519:86EtEy7epneKyrcJwSWP7zsisTkfDRH5CFVszt4qiQYw@nearnode.com:24567
     #[serde(default)]
     pub whitelist_nodes: String,
+    /// Comma separated list of relay nodes, in the same format as whitelist_nodes.
+    /// Meant for nodes which are not reachable from the internet (e.g. behind a NAT):
+    /// the node keeps connections to all its relays open, so that other nodes can reach it
+    /// through them. If the node is a validator, the relays are used as its TIER1 proxies
+    /// (see public_addrs), so the two can't be set together.
+    /// The relays should have this node on their whitelist_nodes.
+    #[serde(default)]
+    pub relay_nodes: String,
     /// Maximum number of active peers. Hard limit.
     #[serde(default = "default_max_num_peers")]
     pub max_num_peers: u32,
@@ -142,6 +162,18 @@ pub struct Config {
     // Remove peers that were not active for this amount of time.
     #[serde(default = "default_peer_expiration_duration")]
     pub peer_expiration_duration: Duration,
+    /// Length of the prefix of the IPv4 addresses which are considered to be in the same
+    /// network group (roughly, operated by the same entity). Outbound connections are spread
+    /// across the network groups.
+    #[serde(default = "default_network_group_ipv4_prefix_len")]
+    pub network_group_ipv4_prefix_len: u32,
+    /// Same as network_group_ipv4_prefix_len, but for IPv6 addresses.
+    #[serde(default = "default_network_group_ipv6_prefix_len")]
+    pub network_group_ipv6_prefix_len: u32,
+    /// The odds of choosing a peer to connect to are divided by (1+n)^outbound_diversity_bias,
+    /// where n is the number of connected peers in its network group. 0 disables the preference.
+    #[serde(default = "default_outbound_diversity_bias")]
+    pub outbound_diversity_bias: f64,
 
     /// List of the public addresses (in the format "<node public key>@<IP>:<port>") of trusted nodes,
     /// which are willing to route messages to this node. Useful only if this node is a validator.
@@ -188,6 +220,13 @@ pub struct Config {
     /// such a case.
     #[serde(default = "default_trusted_stun_servers")]
     pub trusted_stun_servers: Vec<stun::ServerAddr>,
+    /// Protocol (`upnp` or `nat_pmp`) used to map the listening port on the NAT gateway of
+    /// the local network, so that the node behind the NAT can accept inbound connections.
+    /// If this node is a validator and public_addrs is empty, the external address reported by
+    /// the gateway is used instead of querying the trusted_stun_servers.
+    /// Requires the `port_mapping` feature.
+    #[serde(default)]
+    pub port_mapping: Option<crate::port_mapping::Protocol>,
     // Experimental part of the JSON config. Regular users/validators should not have to set any values there.
     // Field names in here can change/disappear at any moment without warning.
     #[serde(default)]
@@ -209,6 +248,20 @@ fn default_tier1_new_connections_per_attempt() -> u64 {
     50
 }
 
+fn default_message_compression_enabled() -> bool {
+    true
+}
+
+/// Smaller messages don't compress well enough to be worth the CPU.
+fn default_message_compression_threshold_bytes() -> usize {
+    8 * 1024
+}
+
+/// Default level of zstd, which is fast enough to compress blocks and chunks on the fly.
+fn default_message_compression_level() -> i32 {
+    3
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct ExperimentalConfig {
     // If true - don't allow any inbound connections.
@@ -246,6 +299,22 @@ pub struct ExperimentalConfig {
     /// Fields set here will override the NetworkConfig fields.
     #[serde(default)]
     pub network_config_overrides: NetworkConfigOverrides,
+
+    /// See `near_network::config::NetworkConfig::quic_enabled`.
+    #[serde(default)]
+    pub quic_enabled: bool,
+
+    /// Whether to compress blocks, chunks and state parts sent to the peers which support it.
+    #[serde(default = "default_message_compression_enabled")]
+    pub message_compression_enabled: bool,
+
+    /// See `near_network::config::MessageCompression::threshold_bytes`.
+    #[serde(default = "default_message_compression_threshold_bytes")]
+    pub message_compression_threshold_bytes: usize,
+
+    /// See `near_network::config::MessageCompression::level`.
+    #[serde(default = "default_message_compression_level")]
+    pub message_compression_level: i32,
 }
 
 /// Overrides values from NetworkConfig.
@@ -263,6 +332,12 @@ pub struct NetworkConfigOverrides {
     pub accounts_data_broadcast_rate_limit_qps: Option<f64>,
     pub routing_table_update_rate_limit_burst: Option<u64>,
     pub routing_table_update_rate_limit_qps: Option<f64>,
+    pub inbound_connections_per_ip_rate_limit_burst: Option<u64>,
+    pub inbound_connections_per_ip_rate_limit_qps: Option<f64>,
+    pub inbound_connections_per_subnet_rate_limit_burst: Option<u64>,
+    pub inbound_connections_per_subnet_rate_limit_qps: Option<f64>,
+    pub inbound_handshakes_rate_limit_burst: Option<u64>,
+    pub inbound_handshakes_rate_limit_qps: Option<f64>,
 }
 
 impl Default for ExperimentalConfig {
@@ -276,6 +351,10 @@ impl Default for ExperimentalConfig {
             tier1_connect_interval: default_tier1_connect_interval(),
             tier1_new_connections_per_attempt: default_tier1_new_connections_per_attempt(),
             network_config_overrides: Default::default(),
+            quic_enabled: false,
+            message_compression_enabled: default_message_compression_enabled(),
+            message_compression_threshold_bytes: default_message_compression_threshold_bytes(),
+            message_compression_level: default_message_compression_level(),
         }
     }
 }
@@ -286,6 +365,7 @@ impl Default for Config {
             addr: "0.0.0.0:24567".to_string(),
             boot_nodes: "".to_string(),
             whitelist_nodes: "".to_string(),
+            relay_nodes: "".to_string(),
             max_num_peers: default_max_num_peers(),
             minimum_outbound_peers: default_minimum_outbound_connections(),
             ideal_connections_lo: default_ideal_connections_lo(),
@@ -302,9 +382,13 @@ impl Default for Config {
             peer_stats_period: default_peer_stats_period(),
             monitor_peers_max_period: default_monitor_peers_max_period(),
             peer_expiration_duration: default_peer_expiration_duration(),
+            network_group_ipv4_prefix_len: default_network_group_ipv4_prefix_len(),
+            network_group_ipv6_prefix_len: default_network_group_ipv6_prefix_len(),
+            outbound_diversity_bias: default_outbound_diversity_bias(),
             public_addrs: vec![],
             allow_private_ip_in_public_addrs: false,
             trusted_stun_servers: default_trusted_stun_servers(),
+            port_mapping: None,
             experimental: Default::default(),
         }
     }
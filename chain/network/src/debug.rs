@@ -1,6 +1,8 @@
 use ::actix::Message;
+use near_primitives::network::PeerId;
 use near_primitives::views::{
-    NetworkGraphView, NetworkRoutesView, PeerStoreView, RecentOutboundConnectionsView,
+    NetworkBandwidthView, NetworkGraphView, NetworkRoutesView, PeerStoreView,
+    RecentOutboundConnectionsView, RouteTracesView,
 };
 
 // Different debug requests that can be sent by HTML pages, via GET.
@@ -9,6 +11,10 @@ pub enum GetDebugStatus {
     Graph,
     RecentOutboundConnections,
     Routes,
+    Bandwidth,
+    RouteTraces,
+    /// Sends a traced message to the peer and returns the recent traces.
+    TraceRoute(PeerId),
 }
 
 #[derive(actix::MessageResponse, Debug)]
@@ -17,6 +23,8 @@ pub enum DebugStatus {
     Graph(NetworkGraphView),
     RecentOutboundConnections(RecentOutboundConnectionsView),
     Routes(NetworkRoutesView),
+    Bandwidth(NetworkBandwidthView),
+    RouteTraces(RouteTracesView),
 }
 
 impl Message for GetDebugStatus {
@@ -6,6 +6,8 @@ mod network_protocol;
 mod peer;
 mod peer_manager;
 mod private_actix;
+#[cfg(feature = "quic")]
+mod quic;
 mod stats;
 mod store;
 mod stun;
@@ -17,6 +19,8 @@ pub mod concurrency;
 pub mod config;
 pub mod config_json;
 pub mod debug;
+pub mod peer_filter;
+pub mod port_mapping;
 pub mod raw;
 pub mod routing;
 pub mod shards_manager;
@@ -14,6 +14,7 @@ impl From<&net::Handshake> for mem::Handshake {
             sender_chain_info: x.sender_chain_info.clone(),
             partial_edge_info: x.partial_edge_info.clone(),
             owned_account: None,
+            capabilities: 0,
         }
     }
 }
@@ -162,7 +163,7 @@ impl TryFrom<&net::PeerMessage> for mem::PeerMessage {
                 mem::PeerMessage::PeersResponse(PeersResponse { peers: pis, direct_peers: vec![] })
             }
             net::PeerMessage::BlockHeadersRequest(bhs) => {
-                mem::PeerMessage::BlockHeadersRequest(bhs)
+                mem::PeerMessage::BlockHeadersRequest(bhs, None)
             }
             net::PeerMessage::BlockHeaders(bhs) => mem::PeerMessage::BlockHeaders(bhs),
             net::PeerMessage::BlockRequest(bh) => mem::PeerMessage::BlockRequest(bh),
@@ -172,6 +173,7 @@ impl TryFrom<&net::PeerMessage> for mem::PeerMessage {
                 msg: *r,
                 created_at: None,
                 num_hops: Some(0),
+                trace: None,
             })),
             net::PeerMessage::Disconnect => mem::PeerMessage::Disconnect(mem::Disconnect {
                 // This flag is used by the disconnecting peer to advise the other peer that there
@@ -229,10 +231,17 @@ impl From<&mem::PeerMessage> for net::PeerMessage {
             mem::PeerMessage::SyncAccountsData(_) => {
                 net::PeerMessage::SyncRoutingTable(net::RoutingTableUpdate::default())
             }
+            // This message is not supported either.
+            mem::PeerMessage::SnapshotHostInfo(_) => {
+                net::PeerMessage::SyncRoutingTable(net::RoutingTableUpdate::default())
+            }
+            mem::PeerMessage::EpochSyncRequest(_) | mem::PeerMessage::EpochSyncResponse(_) => {
+                net::PeerMessage::SyncRoutingTable(net::RoutingTableUpdate::default())
+            }
 
             mem::PeerMessage::PeersRequest(_) => net::PeerMessage::PeersRequest,
             mem::PeerMessage::PeersResponse(pr) => net::PeerMessage::PeersResponse(pr.peers),
-            mem::PeerMessage::BlockHeadersRequest(bhs) => {
+            mem::PeerMessage::BlockHeadersRequest(bhs, _) => {
                 net::PeerMessage::BlockHeadersRequest(bhs)
             }
             mem::PeerMessage::BlockHeaders(bhs) => net::PeerMessage::BlockHeaders(bhs),
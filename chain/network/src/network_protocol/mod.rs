@@ -22,6 +22,8 @@ pub use _proto::network as proto;
 use crate::network_protocol::proto_conv::trace_context::{
     extract_span_context, inject_trace_context,
 };
+use crate::peer::stream::NETWORK_MESSAGE_MAX_SIZE_BYTES;
+use crate::stats::metrics;
 use borsh::{BorshDeserialize as _, BorshSerialize as _};
 use near_async::time;
 use near_crypto::PublicKey;
@@ -29,6 +31,7 @@ use near_crypto::Signature;
 use near_o11y::OpenTelemetrySpanExt;
 use near_primitives::block::{Approval, Block, BlockHeader, GenesisId};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::combine_hash;
 use near_primitives::network::{AnnounceAccount, PeerId};
@@ -38,13 +41,14 @@ use near_primitives::sharding::{
 use near_primitives::state_sync::{ShardStateSyncResponse, ShardStateSyncResponseV1};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::AccountId;
-use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::views::FinalExecutionOutcomeView;
 use protobuf::Message as _;
 use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Debug;
+use std::io::Read as _;
 use std::sync::Arc;
 use tracing::Span;
 
@@ -333,6 +337,20 @@ pub struct Handshake {
     pub(crate) partial_edge_info: PartialEdgeInfo,
     /// Account owned by the sender.
     pub(crate) owned_account: Option<SignedOwnedAccount>,
+    /// Optional features supported by the sender, see `capabilities`.
+    pub(crate) capabilities: u64,
+}
+
+/// Bits of Handshake::capabilities.
+pub(crate) mod capabilities {
+    /// The sender accepts QUIC connections on the UDP port with the same number as
+    /// Handshake::sender_listen_port.
+    pub const QUIC: u64 = 1 << 0;
+    /// The sender accepts Ping and Pong routed messages on TIER1 connections, which are used
+    /// to measure their health.
+    pub const TIER1_PROBES: u64 = 1 << 1;
+    /// The sender accepts zstd-compressed messages, see `PeerMessage::compress`.
+    pub const ZSTD_COMPRESSION: u64 = 1 << 2;
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, strum::IntoStaticStr)]
@@ -350,6 +368,15 @@ pub struct SyncAccountsData {
     pub incremental: bool,
 }
 
+/// See SnapshotHostInfo in network_protocol/network.proto.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SnapshotHostInfo {
+    /// Epoch whose first block the state can be synced to.
+    pub epoch_id: EpochId,
+    /// Shards whose state parts can be served.
+    pub shards: Vec<ShardId>,
+}
+
 /// Message sent to request a PeersResponse
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct PeersRequest {
@@ -395,7 +422,9 @@ pub enum PeerMessage {
     PeersRequest(PeersRequest),
     PeersResponse(PeersResponse),
 
-    BlockHeadersRequest(Vec<CryptoHash>),
+    /// Locator hashes and the maximum number of headers to send back, if
+    /// fewer than the default.
+    BlockHeadersRequest(Vec<CryptoHash>, Option<u64>),
     BlockHeaders(Vec<BlockHeader>),
 
     BlockRequest(CryptoHash),
@@ -411,6 +440,11 @@ pub enum PeerMessage {
     StateRequestHeader(ShardId, CryptoHash),
     StateRequestPart(ShardId, CryptoHash, u64),
     VersionedStateResponse(StateResponseInfo),
+    SnapshotHostInfo(SnapshotHostInfo),
+
+    /// Request of the proof of the start of the epoch after the given one.
+    EpochSyncRequest(EpochId),
+    EpochSyncResponse(Box<EpochSyncProof>),
 }
 
 impl fmt::Display for PeerMessage {
@@ -435,6 +469,30 @@ pub enum ParsePeerMessageError {
     ProtoDecode(#[source] protobuf::Error),
     #[error("ProtoConv")]
     ProtoConv(#[source] proto_conv::ParsePeerMessageError),
+    #[error("Decompress")]
+    Decompress(#[source] std::io::Error),
+    #[error("decompressed message is larger than {max_bytes}B")]
+    DecompressedTooLarge { max_bytes: usize },
+}
+
+/// Decompresses a zstd frame, failing as soon as the output exceeds `max_bytes`.
+/// The output is decoded in chunks, so the memory used is bounded by the actual size of the
+/// message rather than allocated upfront, and never exceeds `max_bytes`.
+fn decompress(data: &[u8], max_bytes: usize) -> Result<Vec<u8>, ParsePeerMessageError> {
+    let mut decoder =
+        zstd::stream::read::Decoder::new(data).map_err(ParsePeerMessageError::Decompress)?;
+    let mut out = Vec::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = decoder.read(&mut buf).map_err(ParsePeerMessageError::Decompress)?;
+        if n == 0 {
+            return Ok(out);
+        }
+        if out.len() + n > max_bytes {
+            return Err(ParsePeerMessageError::DecompressedTooLarge { max_bytes });
+        }
+        out.extend_from_slice(&buf[..n]);
+    }
 }
 
 impl PeerMessage {
@@ -463,8 +521,20 @@ impl PeerMessage {
                 .try_into()
                 .map_err(ParsePeerMessageError::BorshConv)?,
             Encoding::Proto => {
-                let proto_msg: proto::PeerMessage = proto::PeerMessage::parse_from_bytes(data)
+                let mut proto_msg: proto::PeerMessage = proto::PeerMessage::parse_from_bytes(data)
                     .map_err(ParsePeerMessageError::ProtoDecode)?;
+                if let Some(proto::peer_message::Message_type::Compressed(c)) =
+                    &proto_msg.message_type
+                {
+                    let _timer = metrics::PEER_MESSAGE_COMPRESSION_TIME
+                        .with_label_values(&["decompress"])
+                        .start_timer();
+                    // The decompressed message is subject to the same limit as the messages
+                    // received uncompressed.
+                    let data = decompress(&c.zstd, NETWORK_MESSAGE_MAX_SIZE_BYTES)?;
+                    proto_msg = proto::PeerMessage::parse_from_bytes(&data)
+                        .map_err(ParsePeerMessageError::ProtoDecode)?;
+                }
                 if let Ok(extracted_span_context) = extract_span_context(&proto_msg.trace_context) {
                     span.clone().or_current().add_link(extracted_span_context);
                 }
@@ -473,6 +543,48 @@ impl PeerMessage {
         })
     }
 
+    /// Compresses a message serialized with `Encoding::Proto`. The result can be parsed with
+    /// `PeerMessage::deserialize` too, but only by the peers with
+    /// `capabilities::ZSTD_COMPRESSION`. Returns None if compression doesn't make the message
+    /// smaller.
+    pub(crate) fn compress(data: &[u8], level: i32) -> Option<Vec<u8>> {
+        let _timer =
+            metrics::PEER_MESSAGE_COMPRESSION_TIME.with_label_values(&["compress"]).start_timer();
+        let msg = proto::PeerMessage {
+            message_type: Some(proto::peer_message::Message_type::Compressed(
+                proto::CompressedPeerMessage {
+                    zstd: zstd::bulk::compress(data, level).ok()?,
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        };
+        let compressed = msg.write_to_bytes().unwrap();
+        if compressed.len() >= data.len() {
+            return None;
+        }
+        Some(compressed)
+    }
+
+    /// Whether the message is of a type which is worth compressing: blocks, chunks and state
+    /// parts. Small messages are not compressed anyway, see `config::MessageCompression`.
+    pub(crate) fn is_compressible(&self) -> bool {
+        match self {
+            PeerMessage::Block(_)
+            | PeerMessage::BlockHeaders(_)
+            | PeerMessage::VersionedStateResponse(_)
+            | PeerMessage::EpochSyncResponse(_) => true,
+            PeerMessage::Routed(msg) => matches!(
+                msg.body,
+                RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                    | RoutedMessageBody::PartialEncodedChunkResponse(_)
+                    | RoutedMessageBody::PartialEncodedChunkForward(_)
+                    | RoutedMessageBody::StateResponse(_)
+            ),
+            _ => false,
+        }
+    }
+
     pub(crate) fn msg_variant(&self) -> &'static str {
         match self {
             PeerMessage::Routed(routed_msg) => routed_msg.body_variant(),
@@ -524,6 +636,9 @@ pub enum RoutedMessageBody {
     VersionedPartialEncodedChunk(PartialEncodedChunk),
     _UnusedVersionedStateResponse,
     PartialEncodedChunkForward(PartialEncodedChunkForwardMsg),
+    /// Sent by the target of a traced message back to its author. Contains the hash of the
+    /// traced message; the trace itself is carried in `RoutedMessageV2::trace`.
+    RouteTraceEcho(CryptoHash),
 }
 
 impl RoutedMessageBody {
@@ -588,6 +703,7 @@ impl fmt::Debug for RoutedMessageBody {
             RoutedMessageBody::Ping(_) => write!(f, "Ping"),
             RoutedMessageBody::Pong(_) => write!(f, "Pong"),
             RoutedMessageBody::_UnusedVersionedStateResponse => write!(f, "VersionedStateResponse"),
+            RoutedMessageBody::RouteTraceEcho(hash) => write!(f, "RouteTraceEcho({})", hash),
         }
     }
 }
@@ -625,6 +741,41 @@ pub struct RoutedMessageV2 {
     /// Number of peers this routed message travelled through.
     /// Doesn't include the peers that are the source and the destination of the message.
     pub num_hops: Option<i32>,
+    /// Set if the author asked for the route of the message to be traced: every node which
+    /// receives the message records itself in the trace, and the target echoes the trace back
+    /// to the author. The trace is not signed, so it is meant for debugging only.
+    pub trace: Option<RouteTrace>,
+}
+
+/// Maximum number of hops recorded in a RouteTrace.
+pub const MAX_ROUTE_TRACE_HOPS: usize = 16;
+
+/// Node which received a traced message and the time it did, according to its own clock.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RouteTraceHop {
+    pub peer_id: PeerId,
+    pub received_at: time::Utc,
+}
+
+/// Nodes which received a traced message, starting with its author.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct RouteTrace {
+    pub hops: Vec<RouteTraceHop>,
+}
+
+impl RoutedMessageV2 {
+    /// Records a node which received the message, if the message is traced.
+    /// Echoes are not traced, they carry the trace of the original message instead.
+    pub fn record_trace_hop(&mut self, peer_id: PeerId, received_at: time::Utc) {
+        if let RoutedMessageBody::RouteTraceEcho(_) = self.msg.body {
+            return;
+        }
+        if let Some(trace) = &mut self.trace {
+            if trace.hops.len() < MAX_ROUTE_TRACE_HOPS {
+                trace.hops.push(RouteTraceHop { peer_id, received_at });
+            }
+        }
+    }
 }
 
 impl std::ops::Deref for RoutedMessageV2 {
@@ -828,6 +979,7 @@ impl RawRoutedMessage {
             },
             created_at: now,
             num_hops: Some(0),
+            trace: None,
         }
     }
 }
@@ -5,15 +5,19 @@ use crate::network_protocol::proto::peer_message::Message_type as ProtoMT;
 use crate::network_protocol::proto::{self};
 use crate::network_protocol::{
     AdvertisedPeerDistance, Disconnect, DistanceVector, PeerMessage, PeersRequest, PeersResponse,
-    RoutingTableUpdate, SyncAccountsData,
+    RoutingTableUpdate, SnapshotHostInfo, SyncAccountsData,
+};
+use crate::network_protocol::{
+    RouteTrace, RouteTraceHop, RoutedMessage, RoutedMessageV2, MAX_ROUTE_TRACE_HOPS,
 };
-use crate::network_protocol::{RoutedMessage, RoutedMessageV2};
 use crate::types::StateResponseInfo;
 use borsh::{BorshDeserialize as _, BorshSerialize as _};
 use near_async::time::error::ComponentRange;
 use near_primitives::block::{Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::EpochId;
 use protobuf::MessageField as MF;
 use std::sync::Arc;
 
@@ -205,9 +209,10 @@ impl From<&PeerMessage> for proto::PeerMessage {
                     direct_peers: pr.direct_peers.iter().map(Into::into).collect(),
                     ..Default::default()
                 }),
-                PeerMessage::BlockHeadersRequest(bhs) => {
+                PeerMessage::BlockHeadersRequest(bhs, max_headers) => {
                     ProtoMT::BlockHeadersRequest(proto::BlockHeadersRequest {
                         block_hashes: bhs.iter().map(Into::into).collect(),
+                        max_headers: max_headers.unwrap_or(0),
                         ..Default::default()
                     })
                 }
@@ -233,6 +238,7 @@ impl From<&PeerMessage> for proto::PeerMessage {
                     borsh: r.msg.try_to_vec().unwrap(),
                     created_at: MF::from_option(r.created_at.as_ref().map(utc_to_proto)),
                     num_hops: r.num_hops,
+                    trace: MF::from_option(r.trace.as_ref().map(Into::into)),
                     ..Default::default()
                 }),
                 PeerMessage::Disconnect(r) => ProtoMT::Disconnect(proto::Disconnect {
@@ -264,16 +270,85 @@ impl From<&PeerMessage> for proto::PeerMessage {
                         ..Default::default()
                     })
                 }
+                PeerMessage::SnapshotHostInfo(info) => {
+                    ProtoMT::SnapshotHostInfo(proto::SnapshotHostInfo {
+                        epoch_id: MF::some((&info.epoch_id.0).into()),
+                        shards: info.shards.clone(),
+                        ..Default::default()
+                    })
+                }
+                PeerMessage::EpochSyncRequest(epoch_id) => {
+                    ProtoMT::EpochSyncRequest(proto::EpochSyncRequest {
+                        epoch_id: MF::some((&epoch_id.0).into()),
+                        ..Default::default()
+                    })
+                }
+                PeerMessage::EpochSyncResponse(proof) => {
+                    ProtoMT::EpochSyncResponse(proto::EpochSyncResponse {
+                        borsh: proof.try_to_vec().unwrap(),
+                        ..Default::default()
+                    })
+                }
             }),
             ..Default::default()
         }
     }
 }
 
+//////////////////////////////////////////
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseRouteTraceError {
+    #[error("too many hops: {0}")]
+    TooManyHops(usize),
+    #[error("peer_id: {0}")]
+    PeerId(ParseRequiredError<ParsePublicKeyError>),
+    #[error("received_at: {0}")]
+    ReceivedAt(ParseRequiredError<ParseTimestampError>),
+}
+
+impl From<&RouteTrace> for proto::RouteTrace {
+    fn from(x: &RouteTrace) -> Self {
+        Self {
+            hops: x
+                .hops
+                .iter()
+                .map(|h| proto::RouteTraceHop {
+                    peer_id: MF::some((&h.peer_id).into()),
+                    received_at: MF::some(utc_to_proto(&h.received_at)),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<&proto::RouteTrace> for RouteTrace {
+    type Error = ParseRouteTraceError;
+    fn try_from(x: &proto::RouteTrace) -> Result<Self, Self::Error> {
+        if x.hops.len() > MAX_ROUTE_TRACE_HOPS {
+            return Err(Self::Error::TooManyHops(x.hops.len()));
+        }
+        let mut hops = vec![];
+        for h in &x.hops {
+            hops.push(RouteTraceHop {
+                peer_id: try_from_required(&h.peer_id).map_err(Self::Error::PeerId)?,
+                received_at: map_from_required(&h.received_at, utc_from_proto)
+                    .map_err(Self::Error::ReceivedAt)?,
+            });
+        }
+        Ok(Self { hops })
+    }
+}
+
+//////////////////////////////////////////
+
 pub type ParsePeersRequestError = borsh::maybestd::io::Error;
 pub type ParseTransactionError = borsh::maybestd::io::Error;
 pub type ParseRoutedError = borsh::maybestd::io::Error;
 pub type ParseChallengeError = borsh::maybestd::io::Error;
+pub type ParseEpochSyncProofError = borsh::maybestd::io::Error;
 
 #[derive(thiserror::Error, Debug)]
 pub enum ParsePeerMessageError {
@@ -313,10 +388,22 @@ pub enum ParsePeerMessageError {
     Challenge(ParseChallengeError),
     #[error("routed_created_at: {0}")]
     RoutedCreatedAtTimestamp(ComponentRange),
+    #[error("routed_trace: {0}")]
+    RoutedTrace(ParseRouteTraceError),
     #[error("sync_accounts_data: {0}")]
     SyncAccountsData(ParseVecError<ParseSignedAccountDataError>),
     #[error("state_response: {0}")]
     StateResponse(ParseRequiredError<ParseStateInfoError>),
+    #[error("snapshot_host_info: {0}")]
+    SnapshotHostInfo(ParseRequiredError<ParseCryptoHashError>),
+    #[error("epoch_sync_request: {0}")]
+    EpochSyncRequest(ParseRequiredError<ParseCryptoHashError>),
+    #[error("epoch_sync_response: {0}")]
+    EpochSyncResponse(ParseEpochSyncProofError),
+    /// Compressed messages are unwrapped by `PeerMessage::deserialize`, so a compressed
+    /// message here must have been nested in another one.
+    #[error("unexpected compressed message")]
+    UnexpectedCompressed,
 }
 
 impl TryFrom<&proto::PeerMessage> for PeerMessage {
@@ -374,6 +461,7 @@ impl TryFrom<&proto::PeerMessage> for PeerMessage {
             }),
             ProtoMT::BlockHeadersRequest(bhr) => PeerMessage::BlockHeadersRequest(
                 try_from_slice(&bhr.block_hashes).map_err(Self::Error::BlockHeadersRequest)?,
+                Some(bhr.max_headers).filter(|max_headers| *max_headers > 0),
             ),
             ProtoMT::BlockHeadersResponse(bhr) => PeerMessage::BlockHeaders(
                 try_from_slice(&bhr.block_headers).map_err(Self::Error::BlockHeadersResponse)?,
@@ -396,6 +484,7 @@ impl TryFrom<&proto::PeerMessage> for PeerMessage {
                     .transpose()
                     .map_err(Self::Error::RoutedCreatedAtTimestamp)?,
                 num_hops: r.num_hops,
+                trace: try_from_optional(&r.trace).map_err(Self::Error::RoutedTrace)?,
             })),
             ProtoMT::Disconnect(d) => PeerMessage::Disconnect(Disconnect {
                 remove_from_connection_store: d.remove_from_connection_store,
@@ -415,6 +504,20 @@ impl TryFrom<&proto::PeerMessage> for PeerMessage {
             ProtoMT::StateResponse(t) => PeerMessage::VersionedStateResponse(
                 try_from_required(&t.state_response_info).map_err(Self::Error::StateResponse)?,
             ),
+            ProtoMT::SnapshotHostInfo(shi) => PeerMessage::SnapshotHostInfo(SnapshotHostInfo {
+                epoch_id: EpochId(
+                    try_from_required(&shi.epoch_id).map_err(Self::Error::SnapshotHostInfo)?,
+                ),
+                shards: shi.shards.clone(),
+            }),
+            ProtoMT::EpochSyncRequest(esr) => PeerMessage::EpochSyncRequest(EpochId(
+                try_from_required(&esr.epoch_id).map_err(Self::Error::EpochSyncRequest)?,
+            )),
+            ProtoMT::EpochSyncResponse(esr) => PeerMessage::EpochSyncResponse(Box::new(
+                EpochSyncProof::try_from_slice(&esr.borsh)
+                    .map_err(Self::Error::EpochSyncResponse)?,
+            )),
+            ProtoMT::Compressed(_) => return Err(Self::Error::UnexpectedCompressed),
         })
     }
 }
@@ -5,6 +5,7 @@ use crate::testonly::make_rng;
 use crate::types::{Disconnect, HandshakeFailureReason, PeerMessage};
 use crate::types::{PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg};
 use anyhow::{bail, Context as _};
+use assert_matches::assert_matches;
 use itertools::Itertools as _;
 use near_async::time;
 use rand::Rng as _;
@@ -61,6 +62,14 @@ fn serialize_deserialize_protobuf_only() {
     let mut rng = make_rng(39521947542);
     let mut clock = time::FakeClock::default();
     let chain = data::Chain::make(&mut clock, &mut rng, 12);
+    let mut traced = data::make_routed_message(
+        &mut rng,
+        RoutedMessageBody::Ping(Ping { nonce: 1, source: data::make_peer_id(&mut rng) }),
+    );
+    traced.trace = Some(RouteTrace::default());
+    for _ in 0..3 {
+        traced.record_trace_hop(data::make_peer_id(&mut rng), clock.now_utc());
+    }
     let msgs = [
         PeerMessage::Tier1Handshake(data::make_handshake(&mut rng, &chain)),
         PeerMessage::SyncAccountsData(SyncAccountsData {
@@ -70,6 +79,16 @@ fn serialize_deserialize_protobuf_only() {
             incremental: true,
             requesting_full_sync: true,
         }),
+        PeerMessage::SnapshotHostInfo(SnapshotHostInfo {
+            epoch_id: EpochId(*chain.blocks[5].hash()),
+            shards: vec![0, 2],
+        }),
+        PeerMessage::EpochSyncRequest(EpochId(*chain.blocks[5].hash())),
+        PeerMessage::BlockHeadersRequest(
+            chain.blocks.iter().map(|b| *b.hash()).collect(),
+            Some(100),
+        ),
+        PeerMessage::Routed(Box::new(traced)),
     ];
     for m in msgs {
         let m2 = PeerMessage::deserialize(Encoding::Proto, &m.serialize(Encoding::Proto))
@@ -79,6 +98,69 @@ fn serialize_deserialize_protobuf_only() {
     }
 }
 
+#[test]
+fn compression() {
+    let mut rng = make_rng(39521947542);
+    let mut clock = time::FakeClock::default();
+    let chain = data::Chain::make(&mut clock, &mut rng, 12);
+    let m = PeerMessage::BlockHeaders(chain.get_block_headers());
+    let data = m.serialize(Encoding::Proto);
+    let compressed = PeerMessage::compress(&data, 3).unwrap();
+    assert!(compressed.len() < data.len());
+    assert_eq!(m, PeerMessage::deserialize(Encoding::Proto, &compressed).unwrap());
+
+    // Compressed messages can't be nested.
+    let nested = proto::PeerMessage {
+        message_type: Some(proto::peer_message::Message_type::Compressed(
+            proto::CompressedPeerMessage {
+                zstd: zstd::bulk::compress(&compressed, 3).unwrap(),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    };
+    let nested = nested.write_to_bytes().unwrap();
+    assert!(PeerMessage::deserialize(Encoding::Proto, &nested).is_err());
+}
+
+#[test]
+fn decompression_limit() {
+    // A frame of a few hundred bytes which expands to 1MiB.
+    let data = vec![7; 1 << 20];
+    let compressed = zstd::bulk::compress(&data, 3).unwrap();
+    assert!(compressed.len() < 1000);
+    assert_eq!(data, decompress(&compressed, data.len()).unwrap());
+    assert_matches!(
+        decompress(&compressed, 1000),
+        Err(ParsePeerMessageError::DecompressedTooLarge { max_bytes: 1000 })
+    );
+}
+
+#[test]
+fn route_trace_hops() {
+    let mut rng = make_rng(73425893);
+    let clock = time::FakeClock::default();
+    let mut msg = data::make_routed_message(
+        &mut rng,
+        RoutedMessageBody::Ping(Ping { nonce: 1, source: data::make_peer_id(&mut rng) }),
+    );
+    // Messages which are not traced are not affected.
+    msg.record_trace_hop(data::make_peer_id(&mut rng), clock.now_utc());
+    assert_eq!(None, msg.trace);
+    // The number of hops is bounded.
+    msg.trace = Some(RouteTrace::default());
+    for _ in 0..MAX_ROUTE_TRACE_HOPS + 5 {
+        msg.record_trace_hop(data::make_peer_id(&mut rng), clock.now_utc());
+    }
+    assert_eq!(MAX_ROUTE_TRACE_HOPS, msg.trace.as_ref().unwrap().hops.len());
+    // Echoes carry the trace of the original message unchanged.
+    let mut echo =
+        data::make_routed_message(&mut rng, RoutedMessageBody::RouteTraceEcho(msg.hash()));
+    echo.trace = Some(RouteTrace::default());
+    echo.record_trace_hop(data::make_peer_id(&mut rng), clock.now_utc());
+    assert_eq!(Some(RouteTrace::default()), echo.trace);
+}
+
 #[test]
 fn serialize_deserialize() -> anyhow::Result<()> {
     let mut rng = make_rng(89028037453);
@@ -120,7 +202,7 @@ fn serialize_deserialize() -> anyhow::Result<()> {
             peers: (0..5).map(|_| data::make_peer_info(&mut rng)).collect(),
             direct_peers: vec![], // TODO: populate this field once borsh support is dropped
         }),
-        PeerMessage::BlockHeadersRequest(chain.blocks.iter().map(|b| *b.hash()).collect()),
+        PeerMessage::BlockHeadersRequest(chain.blocks.iter().map(|b| *b.hash()).collect(), None),
         PeerMessage::BlockHeaders(chain.get_block_headers()),
         PeerMessage::BlockRequest(*chain.blocks[5].hash()),
         PeerMessage::Block(chain.blocks[5].clone()),
@@ -3,13 +3,14 @@ use crate::concurrency::atomic_cell::AtomicCell;
 use crate::concurrency::demux;
 use crate::config::PEERS_RESPONSE_MAX_PEERS;
 use crate::network_protocol::{
-    DistanceVector, Edge, EdgeState, Encoding, OwnedAccount, ParsePeerMessageError,
+    capabilities, DistanceVector, Edge, EdgeState, Encoding, OwnedAccount, ParsePeerMessageError,
     PartialEdgeInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, PeersRequest, PeersResponse,
     RawRoutedMessage, RoutedMessageBody, RoutingTableUpdate, StateResponseInfo, SyncAccountsData,
 };
 use crate::peer::stream;
 use crate::peer::tracker::Tracker;
 use crate::peer_manager::connection;
+use crate::peer_manager::inbound_limiter::RejectReason;
 use crate::peer_manager::network_state::{NetworkState, PRUNE_EDGES_AFTER};
 use crate::peer_manager::peer_manager_actor::Event;
 use crate::peer_manager::peer_manager_actor::MAX_TIER2_PEERS;
@@ -88,6 +89,8 @@ pub struct HandshakeCompletedEvent {
 pub(crate) enum ClosingReason {
     #[error("too many inbound connections in connecting state")]
     TooManyInbound,
+    #[error("inbound connection rate limit exceeded")]
+    InboundRateLimited,
     #[error("outbound not allowed: {0}")]
     OutboundNotAllowed(connection::PoolError),
 
@@ -122,6 +125,7 @@ impl ClosingReason {
     pub(crate) fn remove_from_connection_store(&self) -> bool {
         match self {
             ClosingReason::TooManyInbound => false, // outbound may be still be OK
+            ClosingReason::InboundRateLimited => false, // outbound may be still be OK
             ClosingReason::OutboundNotAllowed(_) => true, // outbound not allowed
             ClosingReason::Ban(_) => true,          // banned
             ClosingReason::HandshakeFailed => false, // handshake may simply time out
@@ -241,13 +245,22 @@ impl PeerActor {
         network_state: Arc<NetworkState>,
     ) -> Result<(actix::Addr<Self>, HandshakeSignal), ClosingReason> {
         let connecting_status = match &stream.type_ {
-            tcp::StreamType::Inbound => ConnectingStatus::Inbound(
-                network_state
-                    .inbound_handshake_permits
-                    .clone()
-                    .try_acquire_owned()
-                    .map_err(|_| ClosingReason::TooManyInbound)?,
-            ),
+            tcp::StreamType::Inbound => {
+                if let Err(reason) =
+                    network_state.inbound_limiter.check_connection(&clock, stream.peer_addr.ip())
+                {
+                    reason.record();
+                    return Err(ClosingReason::InboundRateLimited);
+                }
+                ConnectingStatus::Inbound(
+                    network_state.inbound_handshake_permits.clone().try_acquire_owned().map_err(
+                        |_| {
+                            RejectReason::TooManyPending.record();
+                            ClosingReason::TooManyInbound
+                        },
+                    )?,
+                )
+            }
             tcp::StreamType::Outbound { tier, peer_id } => ConnectingStatus::Outbound {
                 _permit: match tier {
                     tcp::Tier::T1 => network_state
@@ -377,8 +390,25 @@ impl PeerActor {
         self.send_message_with_encoding(msg, Encoding::Borsh);
     }
 
+    /// Compresses the serialized message, if the peer accepts compressed messages and the message
+    /// is worth compressing. Peers which don't support compression get the message as is.
+    fn compress(&self, msg: &PeerMessage, enc: Encoding, bytes: &[u8]) -> Option<Vec<u8>> {
+        let cfg = self.network_state.config.message_compression.as_ref()?;
+        let PeerStatus::Ready(conn) = &self.peer_status else {
+            return None;
+        };
+        if enc != Encoding::Proto
+            || conn.capabilities & capabilities::ZSTD_COMPRESSION == 0
+            || bytes.len() < cfg.threshold_bytes
+            || !msg.is_compressible()
+        {
+            return None;
+        }
+        PeerMessage::compress(bytes, cfg.level)
+    }
+
     fn send_message_with_encoding(&self, msg: &PeerMessage, enc: Encoding) {
-        let msg_type: &str = msg.msg_variant();
+        let msg_type: &'static str = msg.msg_variant();
         let _span = tracing::trace_span!(
             target: "network",
             "send_message_with_encoding",
@@ -391,7 +421,7 @@ impl PeerActor {
             // peers to update its height at the peer. In the future we will introduce a new
             // peer message type for that and then we can enable this check again.
             //PeerMessage::Block(b) if self.tracker.lock().has_received(b.hash()) => return,
-            PeerMessage::BlockRequest(h) => self.tracker.lock().push_request(*h),
+            PeerMessage::BlockRequest(h) => self.tracker.lock().push_request(&self.clock, *h),
             PeerMessage::SyncAccountsData(d) => metrics::SYNC_ACCOUNTS_DATA
                 .with_label_values(&[
                     "sent",
@@ -402,7 +432,13 @@ impl PeerActor {
             _ => (),
         };
 
-        let bytes = msg.serialize(enc);
+        let mut bytes = msg.serialize(enc);
+        if let Some(compressed) = self.compress(msg, enc, &bytes) {
+            metrics::PEER_MESSAGE_COMPRESSION_SAVED_BYTES
+                .with_label_values(&[msg_type])
+                .inc_by((bytes.len() - compressed.len()) as u64);
+            bytes = compressed;
+        }
         self.tracker.lock().increment_sent(&self.clock, bytes.len() as u64);
         let bytes_len = bytes.len();
         tracing::trace!(target: "network", msg_len = bytes_len);
@@ -412,6 +448,7 @@ impl PeerActor {
         metrics::PEER_MESSAGE_SENT_BY_TYPE_BYTES
             .with_label_values(&[msg_type])
             .inc_by(bytes_len as u64);
+        *self.stats.sent_bytes_by_type.lock().entry(msg_type).or_default() += bytes_len as u64;
     }
 
     fn send_handshake(&self, spec: HandshakeSpec) {
@@ -443,6 +480,7 @@ impl PeerActor {
                 }
                 .sign(vc.signer.as_ref())
             }),
+            capabilities: self.network_state.capabilities(),
         };
         let msg = match spec.tier {
             tcp::Tier::T1 => PeerMessage::Tier1Handshake(handshake),
@@ -465,6 +503,13 @@ impl PeerActor {
     }
 
     /// `PeerId` of the other node.
+    /// Records the rejection of the connection in the metrics, if it is inbound.
+    fn record_inbound_rejection(&self, reason: RejectReason) {
+        if self.peer_type == PeerType::Inbound {
+            reason.record();
+        }
+    }
+
     fn other_peer_id(&self) -> Option<&PeerId> {
         self.peer_info.as_ref().as_ref().map(|peer_info| &peer_info.id)
     }
@@ -519,6 +564,7 @@ impl PeerActor {
                         target: "network",
                         version = handshake.protocol_version,
                         "Received connection from node with unsupported PROTOCOL_VERSION.");
+                    RejectReason::Incompatible.record();
                     self.send_message_or_log(&PeerMessage::HandshakeFailure(
                         self.my_node_info.clone(),
                         HandshakeFailureReason::ProtocolVersionMismatch {
@@ -531,6 +577,7 @@ impl PeerActor {
                 let genesis_id = self.network_state.genesis_id.clone();
                 if handshake.sender_chain_info.genesis_id != genesis_id {
                     tracing::debug!(target: "network", "Received connection from node with different genesis.");
+                    RejectReason::Incompatible.record();
                     self.send_message_or_log(&PeerMessage::HandshakeFailure(
                         self.my_node_info.clone(),
                         HandshakeFailureReason::GenesisMismatch(genesis_id),
@@ -539,6 +586,7 @@ impl PeerActor {
                 }
                 if handshake.target_peer_id != self.my_node_info.id {
                     tracing::debug!(target: "network", "Received handshake from {:?} to {:?} but I am {:?}", handshake.sender_peer_id, handshake.target_peer_id, self.my_node_info.id);
+                    RejectReason::InvalidHandshake.record();
                     self.send_message_or_log(&PeerMessage::HandshakeFailure(
                         self.my_node_info.clone(),
                         HandshakeFailureReason::InvalidTarget,
@@ -549,6 +597,7 @@ impl PeerActor {
                 // Verify if nonce is sane.
                 if let Err(err) = verify_nonce(&self.clock, handshake.partial_edge_info.nonce) {
                     tracing::debug!(target: "network", nonce=?handshake.partial_edge_info.nonce, my_node_id = ?self.my_node_id(), peer_id=?handshake.sender_peer_id, "bad nonce, disconnecting: {err}");
+                    RejectReason::InvalidHandshake.record();
                     self.stop(ctx, ClosingReason::HandshakeFailed);
                     return;
                 }
@@ -563,20 +612,32 @@ impl PeerActor {
                         return;
                     }
                 }
+                // The signatures are verified only up to a rate limit, so that a flood of
+                // handshakes cannot exhaust the CPU.
+                if let Err(reason) = self.network_state.inbound_limiter.check_handshake(&self.clock)
+                {
+                    tracing::debug!(target: "network", peer_id=?handshake.sender_peer_id, "too many inbound handshakes, disconnecting");
+                    reason.record();
+                    self.stop(ctx, ClosingReason::InboundRateLimited);
+                    return;
+                }
             }
         }
 
         // Verify that handshake.owned_account is valid.
         if let Some(owned_account) = &handshake.owned_account {
             if let Err(_) = owned_account.payload().verify(&owned_account.account_key) {
+                self.record_inbound_rejection(RejectReason::InvalidHandshake);
                 self.stop(ctx, ClosingReason::Ban(ReasonForBan::InvalidSignature));
                 return;
             }
             if owned_account.peer_id != handshake.sender_peer_id {
+                self.record_inbound_rejection(RejectReason::InvalidHandshake);
                 self.stop(ctx, ClosingReason::OwnedAccountMismatch);
                 return;
             }
             if (owned_account.timestamp - self.clock.now_utc()).abs() >= MAX_CLOCK_SKEW {
+                self.record_inbound_rejection(RejectReason::InvalidHandshake);
                 self.stop(ctx, ClosingReason::TooLargeClockSkew);
                 return;
             }
@@ -612,6 +673,7 @@ impl PeerActor {
                 .map(|port| SocketAddr::new(self.peer_addr.ip(), port)),
             account_id: None,
         };
+        self.network_state.set_peer_capabilities(&peer_info.id, handshake.capabilities);
 
         let now = self.clock.now();
         let conn = Arc::new(connection::Connection {
@@ -623,8 +685,11 @@ impl PeerActor {
             tracked_shards: handshake.sender_chain_info.tracked_shards.clone(),
             archival: handshake.sender_chain_info.archival,
             last_block: Default::default(),
+            snapshot_host_info: Default::default(),
             peer_type: self.peer_type,
             stats: self.stats.clone(),
+            capabilities: handshake.capabilities,
+            health: Default::default(),
             _peer_connections_metric: metrics::PEER_CONNECTIONS.new_point(&metrics::Connection {
                 type_: self.peer_type,
                 encoding: self.encoding(),
@@ -644,6 +709,19 @@ impl PeerActor {
             time::Interval::new(clock.now(), self.network_state.config.peer_stats_period);
         ctx.spawn({
             let conn = conn.clone();
+            // The gauges are removed when the connection is closed.
+            let bandwidth_gauge = |direction: &str| {
+                metrics::MetricGuard::new(
+                    &*metrics::PEER_BANDWIDTH_BYTES_PER_SEC,
+                    vec![
+                        conn.peer_info.id.to_string(),
+                        conn.tier.as_ref().to_string(),
+                        direction.to_string(),
+                    ],
+                )
+            };
+            let received_gauge = bandwidth_gauge("received");
+            let sent_gauge = bandwidth_gauge("sent");
             wrap_future(async move {
                 loop {
                     interval.tick(&clock).await;
@@ -653,6 +731,8 @@ impl PeerActor {
                         .received_bytes_per_sec
                         .store(received.bytes_per_min / 60, Ordering::Relaxed);
                     conn.stats.sent_bytes_per_sec.store(sent.bytes_per_min / 60, Ordering::Relaxed);
+                    received_gauge.set((received.bytes_per_min / 60) as i64);
+                    sent_gauge.set((sent.bytes_per_min / 60) as i64);
                 }
             })
         });
@@ -776,6 +856,10 @@ impl PeerActor {
                                     }
                                 }));
                             }
+                            // Let the peer know which state parts it can sync from us.
+                            if let Some(info) = act.network_state.snapshot_host_info.load().as_ref() {
+                                conn.send_message(Arc::new(PeerMessage::SnapshotHostInfo(info.clone())));
+                            }
                             // Sync the RoutingTable.
                             act.sync_routing_table();
                         }
@@ -1001,6 +1085,14 @@ impl PeerActor {
                 });
                 let mut tracker = self.tracker.lock();
                 tracker.push_received(hash);
+                let reputation_store = &self.network_state.reputation_store;
+                match tracker.take_request_time(&hash) {
+                    Some(sent) => {
+                        let latency = self.clock.now() - sent;
+                        reputation_store.response_received(&self.clock, &conn.peer_info.id, latency)
+                    }
+                    None => reputation_store.block_received(&self.clock, &conn.peer_info.id, hash),
+                }
                 tracker.has_request(&hash)
             }
             _ => false,
@@ -1024,8 +1116,8 @@ impl PeerActor {
                 PeerMessage::BlockRequest(hash) => {
                     network_state.client.block_request(hash).await.map(|b|PeerMessage::Block(*b))
                 }
-                PeerMessage::BlockHeadersRequest(hashes) => {
-                    network_state.client.block_headers_request(hashes).await.map(PeerMessage::BlockHeaders)
+                PeerMessage::BlockHeadersRequest(hashes, max_headers) => {
+                    network_state.client.block_headers_request(hashes, max_headers).await.map(PeerMessage::BlockHeaders)
                 }
                 PeerMessage::Block(block) => {
                     network_state.client.block(block, peer_id, was_requested).await;
@@ -1058,6 +1150,13 @@ impl PeerActor {
                     network_state.client.state_response(info).await;
                     None
                 }
+                PeerMessage::EpochSyncRequest(epoch_id) => {
+                    network_state.client.epoch_sync_request(epoch_id).await.map(PeerMessage::EpochSyncResponse)
+                }
+                PeerMessage::EpochSyncResponse(proof) => {
+                    network_state.client.epoch_sync_response(*proof, peer_id).await?;
+                    None
+                }
                 msg => {
                     tracing::error!(target: "network", "Peer received unexpected type: {:?}", msg);
                     None
@@ -1251,6 +1350,10 @@ impl PeerActor {
                     message_processed_event();
                 }));
             }
+            PeerMessage::SnapshotHostInfo(info) => {
+                conn.snapshot_host_info.store(Arc::new(Some(info)));
+                message_processed_event();
+            }
             PeerMessage::Routed(mut msg) => {
                 tracing::trace!(
                     target: "network",
@@ -1303,7 +1406,14 @@ impl PeerActor {
                 }
 
                 self.network_state.add_route_back(&self.clock, &conn, msg.as_ref());
+                msg.record_trace_hop(self.network_state.config.node_id(), self.clock.now_utc());
                 if for_me {
+                    // Echoes carry the trace back to its author, they are not traced themselves.
+                    if msg.trace.is_some()
+                        && !matches!(msg.body, RoutedMessageBody::RouteTraceEcho(_))
+                    {
+                        self.network_state.send_route_trace_echo(&self.clock, &msg);
+                    }
                     // Handle Ping and Pong message if they are for us without sending to client.
                     // i.e. Return false in case of Ping and Pong
                     match &msg.body {
@@ -1320,9 +1430,20 @@ impl PeerActor {
                             message_processed_event();
                         }
                         RoutedMessageBody::Pong(pong) => {
+                            if let Some(rtt) =
+                                conn.health.pong_received(pong.nonce, self.clock.now())
+                            {
+                                metrics::TIER1_PROBE_RTT.observe(rtt.as_seconds_f64());
+                                metrics::TIER1_PROBES.with_label_values(&["answered"]).inc();
+                            }
                             self.network_state.config.event_sink.push(Event::Pong(pong.clone()));
                             message_processed_event();
                         }
+                        RoutedMessageBody::RouteTraceEcho(hash) => {
+                            self.network_state.route_trace_echo_received(hash, msg.trace.clone());
+                            self.network_state.config.event_sink.push(Event::RouteTraceEcho(*hash));
+                            message_processed_event();
+                        }
                         _ => self.receive_message(ctx, &conn, PeerMessage::Routed(msg)),
                     }
                 } else {
@@ -1408,6 +1529,7 @@ impl actix::Actor for PeerActor {
             move |act, ctx| match act.peer_status {
                 PeerStatus::Connecting { .. } => {
                     tracing::info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
+                    act.record_inbound_rejection(RejectReason::HandshakeTimeout);
                     act.stop(ctx, ClosingReason::HandshakeFailed);
                 }
                 _ => {}
@@ -1561,6 +1683,8 @@ impl actix::Handler<stream::Frame> for PeerActor {
             metrics::PEER_MESSAGE_RECEIVED_BY_TYPE_BYTES
                 .with_label_values(&labels)
                 .inc_by(msg.len() as u64);
+            *self.stats.received_bytes_by_type.lock().entry(labels[0]).or_default() +=
+                msg.len() as u64;
         }
         match &self.peer_status {
             PeerStatus::Connecting { .. } => self.handle_msg_connecting(ctx, peer_msg),
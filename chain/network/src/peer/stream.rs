@@ -13,13 +13,10 @@ use tokio::io::AsyncWriteExt as _;
 
 /// Maximum size of network message in encoded format.
 /// We encode length as `u32`, and therefore maximum size can't be larger than `u32::MAX`.
-const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
+pub(crate) const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
 /// Maximum capacity of write buffer in bytes.
 const MAX_WRITE_BUFFER_CAPACITY_BYTES: usize = GIB as usize;
 
-type ReadHalf = tokio::io::ReadHalf<tokio::net::TcpStream>;
-type WriteHalf = tokio::io::WriteHalf<tokio::net::TcpStream>;
-
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum SendError {
     #[error("IO error: {0}")]
@@ -74,33 +71,92 @@ where
         stream: tcp::Stream,
         stats: Arc<connection::Stats>,
     ) -> Self {
-        let (tcp_recv, tcp_send) = tokio::io::split(stream.stream);
         let (queue_send, queue_recv) = tokio::sync::mpsc::unbounded_channel();
         let send_buf_size_metric = Arc::new(metrics::MetricGuard::new(
             &*metrics::PEER_DATA_WRITE_BUFFER_SIZE,
             vec![stream.peer_addr.to_string()],
         ));
-        ctx.spawn(wrap_future({
-            let addr = ctx.address();
-            let stats = stats.clone();
-            let m = send_buf_size_metric.clone();
-            async move {
-                if let Err(err) = Self::run_send_loop(tcp_send, queue_recv, stats, m).await {
-                    addr.do_send(Error::Send(SendError::IO(err)));
-                }
+        match stream.stream {
+            tcp::Transport::Tcp(tcp_stream) => {
+                let (tcp_recv, tcp_send) = tokio::io::split(tcp_stream);
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    let m = send_buf_size_metric.clone();
+                    async move {
+                        if let Err(err) = Self::run_send_loop(tcp_send, queue_recv, stats, m).await
+                        {
+                            addr.do_send(Error::Send(SendError::IO(err)));
+                        }
+                    }
+                }));
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    async move {
+                        if let Err(err) =
+                            Self::run_recv_loop(stream.peer_addr, tcp_recv, addr.clone(), stats)
+                                .await
+                        {
+                            addr.do_send(Error::Recv(err));
+                        }
+                    }
+                }));
             }
-        }));
-        ctx.spawn(wrap_future({
-            let addr = ctx.address();
-            let stats = stats.clone();
-            async move {
-                if let Err(err) =
-                    Self::run_recv_loop(stream.peer_addr, tcp_recv, addr.clone(), stats).await
-                {
-                    addr.do_send(Error::Recv(err));
-                }
+            // Messages are framed on the QUIC stream just like on TCP.
+            #[cfg(feature = "quic")]
+            tcp::Transport::Quic(crate::quic::Connection { conn, send, recv }) => {
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    let m = send_buf_size_metric.clone();
+                    async move {
+                        if let Err(err) = Self::run_send_loop(send, queue_recv, stats, m).await {
+                            addr.do_send(Error::Send(SendError::IO(err)));
+                        }
+                    }
+                }));
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    async move {
+                        let res =
+                            Self::run_recv_loop(stream.peer_addr, recv, addr.clone(), stats).await;
+                        // Keep the connection open until the stream is closed.
+                        drop(conn);
+                        if let Err(err) = res {
+                            addr.do_send(Error::Recv(err));
+                        }
+                    }
+                }));
+            }
+            #[cfg(test)]
+            tcp::Transport::Sim(link) => {
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    let m = send_buf_size_metric.clone();
+                    async move {
+                        if let Err(err) =
+                            Self::run_sim_send_loop(link.send, queue_recv, stats, m).await
+                        {
+                            addr.do_send(Error::Send(SendError::IO(err)));
+                        }
+                    }
+                }));
+                ctx.spawn(wrap_future({
+                    let addr = ctx.address();
+                    let stats = stats.clone();
+                    async move {
+                        if let Err(err) =
+                            Self::run_sim_recv_loop(link.recv, addr.clone(), stats).await
+                        {
+                            addr.do_send(Error::Recv(err));
+                        }
+                    }
+                }));
             }
-        }));
+        }
         Self { queue_send, stats, send_buf_size_metric, addr: ctx.address() }
     }
 
@@ -138,7 +194,7 @@ where
     // directly from the stream.
     async fn run_recv_loop(
         peer_addr: SocketAddr,
-        read: ReadHalf,
+        read: impl tokio::io::AsyncRead + Unpin,
         addr: actix::Addr<Actor>,
         stats: Arc<connection::Stats>,
     ) -> Result<(), RecvError> {
@@ -176,7 +232,7 @@ where
         }
     }
     async fn run_send_loop(
-        tcp_send: WriteHalf,
+        tcp_send: impl tokio::io::AsyncWrite + Unpin,
         mut queue_recv: tokio::sync::mpsc::UnboundedReceiver<Frame>,
         stats: Arc<connection::Stats>,
         buf_size_metric: Arc<metrics::IntGaugeGuard>,
@@ -212,4 +268,45 @@ where
         }
         Ok(())
     }
+
+    /// Like run_recv_loop, but for the connections of the simulated network,
+    /// see crate::testonly::simnet.
+    #[cfg(test)]
+    async fn run_sim_recv_loop(
+        mut recv: crate::testonly::simnet::RecvHalf,
+        addr: actix::Addr<Actor>,
+        stats: Arc<connection::Stats>,
+    ) -> Result<(), RecvError> {
+        loop {
+            let buf = recv.recv().await.map_err(RecvError::IO)?;
+            stats.received_messages.fetch_add(1, Ordering::Relaxed);
+            stats.received_bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+            if let Err(_) = addr.send(Frame(buf)).await {
+                // Actor has stopped, so we should just close the connection.
+                return Ok(());
+            }
+        }
+    }
+
+    /// Like run_send_loop, but for the connections of the simulated network.
+    #[cfg(test)]
+    async fn run_sim_send_loop(
+        mut send: crate::testonly::simnet::SendHalf,
+        mut queue_recv: tokio::sync::mpsc::UnboundedReceiver<Frame>,
+        stats: Arc<connection::Stats>,
+        buf_size_metric: Arc<metrics::IntGaugeGuard>,
+    ) -> io::Result<()> {
+        while let Some(Frame(msg)) = queue_recv.recv().await {
+            let len = msg.len();
+            if len > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+                metrics::MessageDropped::InputTooLong.inc_unknown_msg();
+            } else {
+                send.send(msg)?;
+            }
+            stats.messages_to_send.fetch_sub(1, Ordering::Release);
+            stats.bytes_to_send.fetch_sub(len as u64, Ordering::Release);
+            buf_size_metric.sub(len as i64);
+        }
+        Ok(())
+    }
 }
@@ -92,7 +92,8 @@ async fn test_peer_communication(
 
     tracing::info!(target:"test","BlockHeadersRequest");
     let mut events = inbound.events.from_now();
-    let want = PeerMessage::BlockHeadersRequest(chain.blocks.iter().map(|b| *b.hash()).collect());
+    let want =
+        PeerMessage::BlockHeadersRequest(chain.blocks.iter().map(|b| *b.hash()).collect(), None);
     outbound.send(want.clone()).await;
     events.recv_until(message_processed(want)).await;
 
@@ -210,6 +211,7 @@ async fn test_handshake(outbound_encoding: Option<Encoding>, inbound_encoding: O
         sender_chain_info: outbound_cfg.chain.get_peer_chain_info(),
         partial_edge_info: outbound_cfg.partial_edge_info(&inbound.cfg.id(), 1),
         owned_account: None,
+        capabilities: 0,
     };
     // We will also introduce chain_id mismatch, but ProtocolVersionMismatch is expected to take priority.
     handshake.sender_chain_info.genesis_id.chain_id = "unknown_chain".to_string();
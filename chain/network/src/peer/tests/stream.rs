@@ -90,3 +90,45 @@ async fn send_recv() {
         }
     }
 }
+
+#[cfg(feature = "quic")]
+#[tokio::test]
+async fn send_recv_quic() {
+    use crate::network_protocol::PeerInfo;
+    use crate::quic;
+
+    let mut rng = make_rng(98324532);
+    let server = quic::Endpoint::new("[::1]:0".parse().unwrap()).unwrap();
+    let client = quic::Endpoint::new("[::1]:0".parse().unwrap()).unwrap();
+    let peer_info = PeerInfo {
+        id: data::make_peer_id(&mut rng),
+        addr: Some(server.local_addr().unwrap()),
+        account_id: None,
+    };
+    let (s1, incoming) = tokio::join!(client.connect(&peer_info, tcp::Tier::T2), server.accept());
+    let a1 = Actor::spawn(s1.unwrap()).await;
+    // The stream is accepted once the first message is sent on it.
+    let (s2, _) = tokio::join!(
+        incoming.unwrap().accept(),
+        a1.system.addr.send(SendFrame(stream::Frame(vec![0; 10])))
+    );
+    let mut a2 = Actor::spawn(s2.unwrap()).await;
+    assert_eq!(a2.queue_recv.recv().await.unwrap(), stream::Frame(vec![0; 10]));
+
+    let want: Vec<_> = (0..10)
+        .map(|_| {
+            let size = rng.gen_range(0..100000);
+            let mut msg = vec![0; size];
+            rng.fill(&mut msg[..]);
+            msg
+        })
+        .collect();
+    for msg in &want {
+        a1.system.addr.send(SendFrame(stream::Frame(msg.clone()))).await.unwrap();
+    }
+    // Messages are received in the order they were sent.
+    for want in &want {
+        let got = a2.queue_recv.recv().await.unwrap();
+        assert_eq!(&got.0, want);
+    }
+}
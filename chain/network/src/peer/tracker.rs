@@ -47,6 +47,8 @@ pub(crate) struct Tracker {
     pub(crate) received_bytes: TransferStats,
     /// Sent requests.
     requested: CircularUniqueQueue,
+    /// Time the requests which haven't been responded to yet were sent.
+    request_times: lru::LruCache<CryptoHash, time::Instant>,
     /// Received elements.
     received: CircularUniqueQueue,
 }
@@ -57,6 +59,7 @@ impl Default for Tracker {
             sent_bytes: TransferStats::default(),
             received_bytes: TransferStats::default(),
             requested: CircularUniqueQueue::new(MAX_TRACK_SIZE),
+            request_times: lru::LruCache::new(MAX_TRACK_SIZE),
             received: CircularUniqueQueue::new(MAX_TRACK_SIZE),
         }
     }
@@ -86,8 +89,14 @@ impl Tracker {
         self.requested.contains(hash)
     }
 
-    pub(crate) fn push_request(&mut self, hash: CryptoHash) {
+    pub(crate) fn push_request(&mut self, clock: &time::Clock, hash: CryptoHash) {
         self.requested.push(hash);
+        self.request_times.put(hash, clock.now());
+    }
+
+    /// Returns the time the request was sent, if it's the first response to it.
+    pub(crate) fn take_request_time(&mut self, hash: &CryptoHash) -> Option<time::Instant> {
+        self.request_times.pop(hash)
     }
 }
 
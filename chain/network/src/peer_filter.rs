@@ -0,0 +1,203 @@
+//! Rules deciding which peers this node may be connected to, which the node operator can
+//! change at runtime via the admin endpoints of the JSON RPC. They complement the blacklist and
+//! whitelist_nodes from the config, which can't be changed without a restart, e.g. when
+//! mitigating an ongoing attack.
+//!
+//! The rules take effect immediately: connected peers which get banned are disconnected.
+//! A rule may be added with a TTL, after which it expires. The rules are not persisted.
+use crate::blacklist::{IpRange, ParseIpRangeError};
+use crate::network_protocol::PeerInfo;
+use near_async::time;
+use near_primitives::network::PeerId;
+use near_primitives::types::AccountId;
+use near_primitives::views::{PeerFilterEntryView, PeerFilterRuleView, PeerFilterView};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Rule {
+    BanPeer(PeerId),
+    BanAccount(AccountId),
+    BanIpRange(IpRange),
+    AllowPeer(PeerId),
+}
+
+impl TryFrom<&PeerFilterRuleView> for Rule {
+    type Error = PeerFilterError;
+    fn try_from(x: &PeerFilterRuleView) -> Result<Self, Self::Error> {
+        Ok(match x {
+            PeerFilterRuleView::BanPeer(peer_id) => Rule::BanPeer(peer_id.clone()),
+            PeerFilterRuleView::BanAccount(account_id) => Rule::BanAccount(account_id.clone()),
+            PeerFilterRuleView::BanIpRange(range) => {
+                Rule::BanIpRange(range.parse().map_err(PeerFilterError::IpRange)?)
+            }
+            PeerFilterRuleView::AllowPeer(peer_id) => Rule::AllowPeer(peer_id.clone()),
+        })
+    }
+}
+
+impl From<&Rule> for PeerFilterRuleView {
+    fn from(x: &Rule) -> Self {
+        match x {
+            Rule::BanPeer(peer_id) => PeerFilterRuleView::BanPeer(peer_id.clone()),
+            Rule::BanAccount(account_id) => PeerFilterRuleView::BanAccount(account_id.clone()),
+            Rule::BanIpRange(range) => PeerFilterRuleView::BanIpRange(range.to_string()),
+            Rule::AllowPeer(peer_id) => PeerFilterRuleView::AllowPeer(peer_id.clone()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PeerFilterError {
+    #[error("ban_ip_range: {0}")]
+    IpRange(#[source] ParseIpRangeError),
+    #[error("no such rule")]
+    NotFound,
+}
+
+/// Changes the rules of the peer filter. Responds with the rules in effect afterwards.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Result<PeerFilterView, PeerFilterError>")]
+pub enum UpdatePeerFilter {
+    /// Adds the rule, or updates its TTL if it already exists.
+    Add {
+        rule: PeerFilterRuleView,
+        ttl: Option<time::Duration>,
+    },
+    Remove(PeerFilterRuleView),
+    /// Doesn't change anything.
+    Get,
+}
+
+/// Rules with their expiration times.
+#[derive(Default)]
+pub(crate) struct PeerFilter(Mutex<HashMap<Rule, Option<time::Instant>>>);
+
+impl PeerFilter {
+    pub fn add(
+        &self,
+        clock: &time::Clock,
+        rule: &PeerFilterRuleView,
+        ttl: Option<time::Duration>,
+    ) -> Result<(), PeerFilterError> {
+        let rule = Rule::try_from(rule)?;
+        self.0.lock().insert(rule, ttl.map(|ttl| clock.now() + ttl));
+        Ok(())
+    }
+
+    pub fn remove(
+        &self,
+        clock: &time::Clock,
+        rule: &PeerFilterRuleView,
+    ) -> Result<(), PeerFilterError> {
+        let rule = Rule::try_from(rule)?;
+        self.rules(clock).remove(&rule).map(|_| ()).ok_or(PeerFilterError::NotFound)
+    }
+
+    /// Locks the rules, dropping the expired ones.
+    fn rules(
+        &self,
+        clock: &time::Clock,
+    ) -> parking_lot::MutexGuard<'_, HashMap<Rule, Option<time::Instant>>> {
+        let now = clock.now();
+        let mut rules = self.0.lock();
+        rules.retain(|_, expires_at| expires_at.map_or(true, |t| now < t));
+        rules
+    }
+
+    /// Whether the peer is allowed to connect even if the connection limit has been reached.
+    pub fn is_allowed(&self, clock: &time::Clock, peer_id: &PeerId) -> bool {
+        self.rules(clock).contains_key(&Rule::AllowPeer(peer_id.clone()))
+    }
+
+    /// Whether the connections with the peer are banned.
+    /// `account_owner` returns the peer which announced the given account.
+    pub fn is_banned(
+        &self,
+        clock: &time::Clock,
+        peer_info: &PeerInfo,
+        account_owner: impl Fn(&AccountId) -> Option<PeerId>,
+    ) -> bool {
+        let rules = self.rules(clock);
+        if rules.contains_key(&Rule::BanPeer(peer_info.id.clone())) {
+            return true;
+        }
+        let allowed = rules.contains_key(&Rule::AllowPeer(peer_info.id.clone()));
+        rules.keys().any(|rule| match rule {
+            Rule::BanAccount(account_id) => account_owner(account_id) == Some(peer_info.id.clone()),
+            Rule::BanIpRange(range) => {
+                !allowed && peer_info.addr.map_or(false, |addr| range.contains(addr.ip()))
+            }
+            Rule::BanPeer(_) | Rule::AllowPeer(_) => false,
+        })
+    }
+
+    pub fn view(&self, clock: &time::Clock) -> PeerFilterView {
+        let now = clock.now();
+        let now_utc = clock.now_utc();
+        let mut entries: Vec<_> = self
+            .rules(clock)
+            .iter()
+            .map(|(rule, expires_at)| PeerFilterEntryView {
+                rule: rule.into(),
+                expires_at_ms: expires_at
+                    .map(|t| ((now_utc + (t - now)).unix_timestamp_nanos() / 1_000_000) as i64),
+            })
+            .collect();
+        entries.sort_by_key(|e| format!("{:?}", e.rule));
+        PeerFilterView { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_protocol::testonly as data;
+    use crate::testonly::make_rng;
+
+    fn no_owner(_: &AccountId) -> Option<PeerId> {
+        None
+    }
+
+    #[test]
+    fn filter_rules() {
+        let mut rng = make_rng(921853233);
+        let clock = time::FakeClock::default();
+        let filter = PeerFilter::default();
+        let peer = PeerInfo {
+            id: data::make_peer_id(&mut rng),
+            addr: Some("192.0.2.4:24567".parse().unwrap()),
+            account_id: None,
+        };
+        let account_id: AccountId = "test.near".parse().unwrap();
+        assert!(!filter.is_banned(&clock.clock(), &peer, no_owner));
+
+        // IP range bans expire, and don't apply to the allowed peers.
+        let ip_ban = PeerFilterRuleView::BanIpRange("192.0.2.0/24".to_string());
+        filter.add(&clock.clock(), &ip_ban, Some(time::Duration::minutes(1))).unwrap();
+        assert!(filter.is_banned(&clock.clock(), &peer, no_owner));
+        let allow = PeerFilterRuleView::AllowPeer(peer.id.clone());
+        filter.add(&clock.clock(), &allow, None).unwrap();
+        assert!(filter.is_allowed(&clock.clock(), &peer.id));
+        assert!(!filter.is_banned(&clock.clock(), &peer, no_owner));
+        filter.remove(&clock.clock(), &allow).unwrap();
+        assert!(filter.is_banned(&clock.clock(), &peer, no_owner));
+        clock.advance(time::Duration::minutes(1));
+        assert!(!filter.is_banned(&clock.clock(), &peer, no_owner));
+        assert!(matches!(filter.remove(&clock.clock(), &ip_ban), Err(PeerFilterError::NotFound)));
+
+        // Account bans apply to the peer which announced the account.
+        let account_ban = PeerFilterRuleView::BanAccount(account_id.clone());
+        filter.add(&clock.clock(), &account_ban, None).unwrap();
+        assert!(!filter.is_banned(&clock.clock(), &peer, no_owner));
+        assert!(filter.is_banned(&clock.clock(), &peer, |_| Some(peer.id.clone())));
+
+        assert!(filter
+            .add(&clock.clock(), &PeerFilterRuleView::BanIpRange("x".into()), None)
+            .is_err());
+        assert_eq!(
+            vec![PeerFilterEntryView { rule: account_ban, expires_at_ms: None }],
+            filter.view(&clock.clock()).entries
+        );
+    }
+}
@@ -3,7 +3,7 @@ use crate::concurrency::atomic_cell::AtomicCell;
 use crate::concurrency::demux;
 use crate::network_protocol::{
     PeerInfo, PeerMessage, RoutedMessageBody, SignedAccountData, SignedOwnedAccount,
-    SyncAccountsData,
+    SnapshotHostInfo, SyncAccountsData,
 };
 use crate::peer::peer_actor;
 use crate::peer::peer_actor::PeerActor;
@@ -47,6 +47,8 @@ impl tcp::Tier {
         match body {
             RoutedMessageBody::BlockApproval(..) => true,
             RoutedMessageBody::VersionedPartialEncodedChunk(..) => true,
+            // Used to probe the TIER1 connections, see `Health`.
+            RoutedMessageBody::Ping(..) | RoutedMessageBody::Pong(..) => true,
             _ => self == tcp::Tier::T2,
         }
     }
@@ -62,6 +64,10 @@ pub(crate) struct Stats {
     pub received_bytes_per_sec: AtomicU64,
     /// Avg sent bytes/s, based on the last few minutes of traffic.
     pub sent_bytes_per_sec: AtomicU64,
+    /// Number of bytes received since the connection was opened, by message type.
+    pub received_bytes_by_type: parking_lot::Mutex<HashMap<&'static str, u64>>,
+    /// Number of bytes sent since the connection was opened, by message type.
+    pub sent_bytes_by_type: parking_lot::Mutex<HashMap<&'static str, u64>>,
 
     /// Number of messages in the buffer to send.
     pub messages_to_send: AtomicU64,
@@ -69,6 +75,77 @@ pub(crate) struct Stats {
     pub bytes_to_send: AtomicU64,
 }
 
+/// Pings which are not answered within this time are counted as lost.
+pub(crate) const PROBE_TIMEOUT: time::Duration = time::Duration::seconds(2);
+/// Weight of the latest probe in the averaged round trip time and loss rate.
+const PROBE_WEIGHT: f64 = 0.3;
+/// A connection is degraded if the averaged loss rate of its probes is above this.
+const DEGRADED_LOSS_RATE: f64 = 0.5;
+/// A connection is degraded if the averaged round trip time of its probes is above this.
+const DEGRADED_RTT: time::Duration = time::Duration::seconds(1);
+
+#[derive(Default)]
+struct HealthInner {
+    /// Send times of the pings which are not answered yet, by nonce.
+    pending: HashMap<u64, time::Instant>,
+    /// Averaged round trip time of the answered pings.
+    rtt: Option<time::Duration>,
+    /// Averaged fraction of the pings which were lost.
+    loss_rate: f64,
+}
+
+/// Health of a connection, measured by sending pings over it periodically.
+/// It allows to stop using a connection which has degraded long before it is
+/// detected as broken. Only TIER1 connections are probed, connections without
+/// probes are considered healthy.
+#[derive(Default)]
+pub(crate) struct Health(parking_lot::Mutex<HealthInner>);
+
+impl Health {
+    pub fn ping_sent(&self, nonce: u64, now: time::Instant) {
+        self.0.lock().pending.insert(nonce, now);
+    }
+
+    /// Returns the round trip time of the ping, or None if the ping wasn't sent
+    /// over this connection or was already counted as lost.
+    pub fn pong_received(&self, nonce: u64, now: time::Instant) -> Option<time::Duration> {
+        let mut inner = self.0.lock();
+        let rtt = now - inner.pending.remove(&nonce)?;
+        inner.rtt = Some(match inner.rtt {
+            Some(avg) => avg * (1. - PROBE_WEIGHT) + rtt * PROBE_WEIGHT,
+            None => rtt,
+        });
+        inner.loss_rate *= 1. - PROBE_WEIGHT;
+        Some(rtt)
+    }
+
+    /// Counts the pings which were not answered in time as lost.
+    /// Returns the number of the newly lost pings.
+    pub fn expire_pings(&self, now: time::Instant) -> usize {
+        let mut inner = self.0.lock();
+        let pending = inner.pending.len();
+        inner.pending.retain(|_, sent| now - *sent < PROBE_TIMEOUT);
+        let lost = pending - inner.pending.len();
+        for _ in 0..lost {
+            inner.loss_rate = inner.loss_rate * (1. - PROBE_WEIGHT) + PROBE_WEIGHT;
+        }
+        lost
+    }
+
+    pub fn rtt(&self) -> Option<time::Duration> {
+        self.0.lock().rtt
+    }
+
+    pub fn loss_rate(&self) -> f64 {
+        self.0.lock().loss_rate
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        let inner = self.0.lock();
+        inner.loss_rate > DEGRADED_LOSS_RATE || inner.rtt.map_or(false, |rtt| rtt > DEGRADED_RTT)
+    }
+}
+
 /// Contains information relevant to a connected peer.
 pub(crate) struct Connection {
     // TODO(gprusak): add rate limiting on TIER1 connections for defence in-depth.
@@ -87,6 +164,8 @@ pub(crate) struct Connection {
     /// Denote if a node is running in archival mode or not.
     pub archival: bool,
     pub last_block: ArcSwap<Option<BlockInfo>>,
+    /// State parts the peer can serve from its state snapshot, as last advertised by the peer.
+    pub snapshot_host_info: ArcSwap<Option<SnapshotHostInfo>>,
 
     /// Who started connection. Inbound (other) or Outbound (us).
     pub peer_type: PeerType,
@@ -99,6 +178,10 @@ pub(crate) struct Connection {
     pub last_time_received_message: AtomicCell<time::Instant>,
     /// Connection stats
     pub stats: Arc<Stats>,
+    /// Optional features supported by the peer, see `network_protocol::capabilities`.
+    pub capabilities: u64,
+    /// Health of the connection, measured for TIER1 connections only.
+    pub health: Health,
     /// prometheus gauge point guard.
     pub _peer_connections_metric: metrics::GaugePoint,
 
@@ -123,6 +206,7 @@ impl Connection {
             last_block: *self.last_block.load().as_ref(),
             tracked_shards: self.tracked_shards.clone(),
             archival: self.archival,
+            snapshot_host_info: self.snapshot_host_info.load().as_ref().clone(),
         };
         FullPeerInfo { peer_info: self.peer_info.clone(), chain_info }
     }
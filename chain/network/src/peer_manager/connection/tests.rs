@@ -120,3 +120,33 @@ async fn duplicate_connections() {
     );
     drop(conn1);
 }
+
+#[test]
+fn connection_health() {
+    let clock = time::FakeClock::default();
+    let health = connection::Health::default();
+    assert!(!health.is_degraded());
+
+    // Answered pings update the round trip time.
+    health.ping_sent(1, clock.now());
+    clock.advance(time::Duration::milliseconds(100));
+    assert_eq!(Some(time::Duration::milliseconds(100)), health.pong_received(1, clock.now()));
+    assert_eq!(None, health.pong_received(1, clock.now()));
+    assert_eq!(Some(time::Duration::milliseconds(100)), health.rtt());
+    assert!(!health.is_degraded());
+
+    // Unanswered pings expire and make the connection degraded.
+    for nonce in 2..5 {
+        health.ping_sent(nonce, clock.now());
+        clock.advance(connection::PROBE_TIMEOUT);
+        assert_eq!(1, health.expire_pings(clock.now()));
+    }
+    assert!(health.is_degraded());
+
+    // Answered pings make it healthy again.
+    for nonce in 5..10 {
+        health.ping_sent(nonce, clock.now());
+        health.pong_received(nonce, clock.now());
+    }
+    assert!(!health.is_degraded());
+}
@@ -0,0 +1,132 @@
+use crate::concurrency::rate;
+use crate::stats::metrics;
+use lru::LruCache;
+use near_async::time;
+use parking_lot::Mutex;
+use std::net::IpAddr;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of addresses (and separately subnets) whose connection rate is tracked.
+/// Addresses which haven't connected recently are forgotten first, which
+/// resets their limit.
+const TRACKED_ADDRS_LIMIT: usize = 10000;
+/// Length of the prefix of the IPv4 addresses grouped into a subnet.
+const IPV4_SUBNET_PREFIX_LEN: u32 = 24;
+/// Length of the prefix of the IPv6 addresses grouped into a subnet.
+const IPV6_SUBNET_PREFIX_LEN: u32 = 64;
+
+/// Reason for rejecting an inbound connection before or during the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum RejectReason {
+    /// Too many connections from the IP address.
+    IpRateLimit,
+    /// Too many connections from the subnet of the IP address.
+    SubnetRateLimit,
+    /// Too many connections waiting for the handshake.
+    TooManyPending,
+    /// Too many handshakes to verify.
+    HandshakeRateLimit,
+    /// The handshake was of an incompatible protocol version or a different chain.
+    Incompatible,
+    /// The handshake was malformed, e.g. had an invalid nonce or signature.
+    InvalidHandshake,
+    /// The handshake didn't arrive in time.
+    HandshakeTimeout,
+}
+
+impl RejectReason {
+    pub fn record(self) {
+        metrics::REJECTED_INBOUND_CONNECTIONS.with_label_values(&[self.into()]).inc();
+    }
+}
+
+/// Returns the subnet `ip` belongs to, i.e. `ip` with the host bits cleared.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            IpAddr::V4((u32::from(ip) & !(u32::MAX >> IPV4_SUBNET_PREFIX_LEN)).into())
+        }
+        IpAddr::V6(ip) => {
+            IpAddr::V6((u128::from(ip) & !(u128::MAX >> IPV6_SUBNET_PREFIX_LEN)).into())
+        }
+    }
+}
+
+/// Takes a ticket from the bucket of `key`, creating a full bucket if there's none.
+fn try_acquire<K: std::hash::Hash + Eq>(
+    buckets: &mut LruCache<K, rate::Bucket>,
+    key: K,
+    limit: rate::Limit,
+    now: time::Instant,
+) -> bool {
+    if let Some(bucket) = buckets.get_mut(&key) {
+        return bucket.try_acquire(now);
+    }
+    let mut bucket = rate::Bucket::new(limit, now);
+    let ok = bucket.try_acquire(now);
+    buckets.put(key, bucket);
+    ok
+}
+
+struct Inner {
+    per_ip: LruCache<IpAddr, rate::Bucket>,
+    per_subnet: LruCache<IpAddr, rate::Bucket>,
+    handshakes: Option<rate::Bucket>,
+}
+
+/// InboundLimiter protects the node from being flooded with inbound connections,
+/// which are cheap to open but expensive to handle.  It limits the rate of the new
+/// connections from every IP address and subnet, and the rate of the handshakes
+/// whose signatures get verified.
+pub(crate) struct InboundLimiter {
+    per_ip_limit: rate::Limit,
+    per_subnet_limit: rate::Limit,
+    handshakes_limit: rate::Limit,
+    inner: Mutex<Inner>,
+}
+
+impl InboundLimiter {
+    pub fn new(
+        per_ip_limit: rate::Limit,
+        per_subnet_limit: rate::Limit,
+        handshakes_limit: rate::Limit,
+    ) -> Self {
+        Self {
+            per_ip_limit,
+            per_subnet_limit,
+            handshakes_limit,
+            inner: Mutex::new(Inner {
+                per_ip: LruCache::new(TRACKED_ADDRS_LIMIT),
+                per_subnet: LruCache::new(TRACKED_ADDRS_LIMIT),
+                handshakes: None,
+            }),
+        }
+    }
+
+    /// Called on a new inbound connection from `ip`.
+    pub fn check_connection(&self, clock: &time::Clock, ip: IpAddr) -> Result<(), RejectReason> {
+        let now = clock.now();
+        let mut inner = self.inner.lock();
+        if !try_acquire(&mut inner.per_ip, ip, self.per_ip_limit, now) {
+            return Err(RejectReason::IpRateLimit);
+        }
+        if !try_acquire(&mut inner.per_subnet, subnet(ip), self.per_subnet_limit, now) {
+            return Err(RejectReason::SubnetRateLimit);
+        }
+        Ok(())
+    }
+
+    /// Called before verifying the signatures of an inbound handshake.
+    pub fn check_handshake(&self, clock: &time::Clock) -> Result<(), RejectReason> {
+        let now = clock.now();
+        let mut inner = self.inner.lock();
+        let limit = self.handshakes_limit;
+        if !inner.handshakes.get_or_insert_with(|| rate::Bucket::new(limit, now)).try_acquire(now) {
+            return Err(RejectReason::HandshakeRateLimit);
+        }
+        Ok(())
+    }
+}
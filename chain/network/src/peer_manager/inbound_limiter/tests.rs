@@ -0,0 +1,50 @@
+use crate::concurrency::rate;
+use crate::peer_manager::inbound_limiter::{subnet, InboundLimiter, RejectReason};
+use near_async::time;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn subnets() {
+    assert_eq!(subnet(ip("1.2.3.4")), ip("1.2.3.0"));
+    assert_eq!(subnet(ip("2001:db8:1:2:3:4:5:6")), ip("2001:db8:1:2::"));
+}
+
+#[test]
+fn connection_rate_limits() {
+    let clock = time::FakeClock::default();
+    let limiter = InboundLimiter::new(
+        rate::Limit { qps: 1., burst: 2 },
+        rate::Limit { qps: 1., burst: 3 },
+        rate::Limit { qps: 1., burst: 1 },
+    );
+    let clock = clock.clock();
+    assert_eq!(Ok(()), limiter.check_connection(&clock, ip("1.2.3.4")));
+    assert_eq!(Ok(()), limiter.check_connection(&clock, ip("1.2.3.4")));
+    assert_eq!(Err(RejectReason::IpRateLimit), limiter.check_connection(&clock, ip("1.2.3.4")));
+    // Other addresses in the subnet share its limit.
+    assert_eq!(Ok(()), limiter.check_connection(&clock, ip("1.2.3.5")));
+    assert_eq!(Err(RejectReason::SubnetRateLimit), limiter.check_connection(&clock, ip("1.2.3.6")));
+    // Other subnets are not affected.
+    assert_eq!(Ok(()), limiter.check_connection(&clock, ip("1.2.4.4")));
+}
+
+#[test]
+fn limits_are_refilled() {
+    let clock = time::FakeClock::default();
+    let limiter = InboundLimiter::new(
+        rate::Limit { qps: 1., burst: 1 },
+        rate::Limit { qps: 1., burst: 1 },
+        rate::Limit { qps: 1., burst: 1 },
+    );
+    assert_eq!(Ok(()), limiter.check_connection(&clock.clock(), ip("::1")));
+    assert_eq!(Ok(()), limiter.check_handshake(&clock.clock()));
+    assert_eq!(Err(RejectReason::IpRateLimit), limiter.check_connection(&clock.clock(), ip("::1")));
+    assert_eq!(Err(RejectReason::HandshakeRateLimit), limiter.check_handshake(&clock.clock()));
+    clock.advance(time::Duration::seconds(1));
+    assert_eq!(Ok(()), limiter.check_connection(&clock.clock(), ip("::1")));
+    assert_eq!(Ok(()), limiter.check_handshake(&clock.clock()));
+}
@@ -1,8 +1,10 @@
 pub(crate) mod connection;
 pub(crate) mod connection_store;
+pub(crate) mod inbound_limiter;
 pub(crate) mod network_state;
 pub(crate) mod peer_manager_actor;
 pub(crate) mod peer_store;
+pub(crate) mod reputation_store;
 
 #[cfg(test)]
 pub(crate) mod testonly;
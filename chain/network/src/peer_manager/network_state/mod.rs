@@ -5,15 +5,19 @@ use crate::concurrency::demux;
 use crate::concurrency::runtime::Runtime;
 use crate::config;
 use crate::network_protocol::{
-    Edge, EdgeState, PartialEdgeInfo, PeerIdOrHash, PeerInfo, PeerMessage, RawRoutedMessage,
-    RoutedMessageBody, RoutedMessageV2, SignedAccountData,
+    capabilities, Edge, EdgeState, PartialEdgeInfo, PeerIdOrHash, PeerInfo, PeerMessage,
+    RawRoutedMessage, RouteTrace, RouteTraceHop, RoutedMessageBody, RoutedMessageV2,
+    SignedAccountData, SnapshotHostInfo,
 };
 use crate::peer::peer_actor::PeerActor;
 use crate::peer::peer_actor::{ClosingReason, ConnectionClosedEvent};
+use crate::peer_filter::PeerFilter;
 use crate::peer_manager::connection;
 use crate::peer_manager::connection_store;
+use crate::peer_manager::inbound_limiter::InboundLimiter;
 use crate::peer_manager::peer_manager_actor::Event;
 use crate::peer_manager::peer_store;
+use crate::peer_manager::reputation_store::{self, ReputationEvent};
 use crate::private_actix::RegisterPeerError;
 use crate::routing::route_back_cache::RouteBackCache;
 use crate::routing::NetworkTopologyChange;
@@ -31,6 +35,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
 use near_primitives::types::AccountId;
 use parking_lot::Mutex;
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
@@ -51,6 +56,9 @@ const IMPORTANT_MESSAGE_RESENT_COUNT: usize = 3;
 /// production of 1 block should fit).
 const RECENT_ROUTED_MESSAGES_CACHE_SIZE: usize = 10000;
 
+/// Number of the most recent route traces initiated by this node, which are kept for debugging.
+const ROUTE_TRACES_LIMIT: usize = 20;
+
 /// How long a peer has to be unreachable, until we prune it from the in-memory graph.
 const PRUNE_UNREACHABLE_PEERS_AFTER: time::Duration = time::Duration::hours(1);
 
@@ -74,6 +82,15 @@ impl WhitelistNode {
     }
 }
 
+/// Route trace initiated by this node, see `NetworkState::trace_route`.
+#[derive(Clone, Debug)]
+pub struct RouteTraceResult {
+    pub target: PeerId,
+    pub sent_at: time::Utc,
+    /// Trace returned by the target, None until the echo arrives.
+    pub trace: Option<RouteTrace>,
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub(crate) struct WhitelistNode {
     id: PeerId,
@@ -102,6 +119,8 @@ pub(crate) struct NetworkState {
 
     /// Network-related info about the chain.
     pub chain_info: ArcSwap<Option<ChainInfo>>,
+    /// State parts this node can serve from its state snapshot, advertised to every new peer.
+    pub snapshot_host_info: ArcSwap<Option<SnapshotHostInfo>>,
     /// AccountsData for TIER1 accounts.
     pub accounts_data: Arc<AccountDataCache>,
     /// AnnounceAccounts mapping TIER1 account ids to peer ids.
@@ -111,12 +130,26 @@ pub(crate) struct NetworkState {
     pub tier1: connection::Pool,
     /// Semaphore limiting inflight inbound handshakes.
     pub inbound_handshake_permits: Arc<tokio::sync::Semaphore>,
+    /// Rate limits of the inbound connections and handshakes.
+    pub inbound_limiter: InboundLimiter,
     /// Peer store that provides read/write access to peers.
     pub peer_store: peer_store::PeerStore,
     /// Connection store that provides read/write access to stored connections.
     pub connection_store: connection_store::ConnectionStore,
+    /// Reputation store that keeps track of the scores of the peers.
+    pub reputation_store: reputation_store::ReputationStore,
+    /// Rules managed at runtime by the node operator, see `crate::peer_filter`.
+    pub peer_filter: PeerFilter,
     /// List of peers to which we should re-establish a connection
     pub pending_reconnect: Mutex<Vec<PeerInfo>>,
+    /// QUIC endpoint, set once it starts listening.
+    #[cfg(feature = "quic")]
+    pub quic_endpoint: once_cell::sync::OnceCell<crate::quic::Endpoint>,
+    /// Peers which advertised in their last Handshake that they accept QUIC connections.
+    quic_peers: Mutex<HashSet<PeerId>>,
+    /// External address of this node, as reported by the NAT gateway on which the listening
+    /// port is mapped, see `crate::port_mapping`.
+    pub mapped_addr: Mutex<Option<SocketAddr>>,
     /// A graph of the whole NEAR network.
     pub graph: Arc<crate::routing::Graph>,
     /// A sparsified graph of the whole NEAR network.
@@ -126,6 +159,8 @@ pub(crate) struct NetworkState {
     /// Hashes of the body of recently received routed messages.
     /// It allows us to determine whether messages arrived faster over TIER1 or TIER2 network.
     pub recent_routed_messages: Mutex<lru::LruCache<CryptoHash, ()>>,
+    /// Recent route traces initiated by this node, by the hash of the traced message.
+    pub route_traces: Mutex<lru::LruCache<CryptoHash, RouteTraceResult>>,
 
     /// Hash of messages that requires routing back to respective previous hop.
     pub tier2_route_back: Mutex<RouteBackCache>,
@@ -185,12 +220,24 @@ impl NetworkState {
             client,
             shards_manager_adapter,
             chain_info: Default::default(),
+            snapshot_host_info: Default::default(),
             tier2: connection::Pool::new(config.node_id()),
             tier1: connection::Pool::new(config.node_id()),
             inbound_handshake_permits: Arc::new(tokio::sync::Semaphore::new(LIMIT_PENDING_PEERS)),
+            inbound_limiter: InboundLimiter::new(
+                config.inbound_connections_per_ip_rate_limit,
+                config.inbound_connections_per_subnet_rate_limit,
+                config.inbound_handshakes_rate_limit,
+            ),
             peer_store,
             connection_store: connection_store::ConnectionStore::new(store.clone()).unwrap(),
+            reputation_store: reputation_store::ReputationStore::new(store.clone()),
+            peer_filter: PeerFilter::default(),
             pending_reconnect: Mutex::new(Vec::<PeerInfo>::new()),
+            #[cfg(feature = "quic")]
+            quic_endpoint: Default::default(),
+            quic_peers: Mutex::new(HashSet::new()),
+            mapped_addr: Mutex::new(None),
             accounts_data: Arc::new(AccountDataCache::new()),
             account_announcements: Arc::new(AnnounceAccountCache::new(store)),
             tier2_route_back: Mutex::new(RouteBackCache::default()),
@@ -198,6 +245,7 @@ impl NetworkState {
             recent_routed_messages: Mutex::new(lru::LruCache::new(
                 RECENT_ROUTED_MESSAGES_CACHE_SIZE,
             )),
+            route_traces: Mutex::new(lru::LruCache::new(ROUTE_TRACES_LIMIT)),
             txns_since_last_block: AtomicUsize::new(0),
             whitelist_nodes,
             add_edges_demux: demux::Demux::new(config.routing_table_update_rate_limit),
@@ -236,25 +284,136 @@ impl NetworkState {
         if let Some(peer) = tier2.ready.get(peer_id) {
             peer.stop(Some(ban_reason));
         } else {
+            self.reputation_store.record(clock, peer_id, ReputationEvent::Banned(ban_reason));
             if let Err(err) = self.peer_store.peer_ban(clock, peer_id, ban_reason) {
                 tracing::error!(target: "network", ?err, "Failed to save peer data");
             }
         }
     }
 
-    /// is_peer_whitelisted checks whether a peer is a whitelisted node.
-    /// whitelisted nodes are allowed to connect, even if the inbound connections limit has
-    /// been reached. This predicate should be evaluated AFTER the Handshake.
-    pub fn is_peer_whitelisted(&self, peer_info: &PeerInfo) -> bool {
+    /// Optional features supported by this node, advertised in the Handshake.
+    pub fn capabilities(&self) -> u64 {
+        #[allow(unused_mut)]
+        let mut caps = capabilities::TIER1_PROBES | capabilities::ZSTD_COMPRESSION;
+        #[cfg(feature = "quic")]
+        if self.quic_endpoint.get().is_some() {
+            caps |= capabilities::QUIC;
+        }
+        caps
+    }
+
+    /// Records the features supported by the peer, as advertised in its Handshake.
+    pub fn set_peer_capabilities(&self, peer_id: &PeerId, peer_capabilities: u64) {
+        let mut quic_peers = self.quic_peers.lock();
+        if peer_capabilities & capabilities::QUIC != 0 {
+            quic_peers.insert(peer_id.clone());
+        } else {
+            quic_peers.remove(peer_id);
+        }
+    }
+
+    /// Opens a connection to the peer. The connection is made over QUIC if it is enabled
+    /// and the peer has advertised that it accepts QUIC connections, and over TCP otherwise
+    /// or if connecting over QUIC fails.
+    pub async fn connect(
+        &self,
+        peer_info: &PeerInfo,
+        tier: tcp::Tier,
+    ) -> anyhow::Result<tcp::Stream> {
+        #[cfg(test)]
+        if let Some(simnet) = &self.config.simnet {
+            let addr = self.config.node_addr.as_ref().context("node_addr is not set")?;
+            return simnet.connect(addr.ip(), peer_info, tier);
+        }
+        #[cfg(feature = "quic")]
+        if let Some(endpoint) = self.quic_endpoint.get() {
+            if self.quic_peers.lock().contains(&peer_info.id) {
+                match endpoint.connect(peer_info, tier).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => {
+                        tracing::debug!(target: "network", ?err, peer_id = ?peer_info.id, "Failed to connect over QUIC, falling back to TCP")
+                    }
+                }
+            }
+        }
+        tcp::Stream::connect(peer_info, tier).await.context("tcp::Stream::connect()")
+    }
+
+    /// is_peer_whitelisted checks whether a peer is a whitelisted node, or is allowed by
+    /// the peer filter. whitelisted nodes are allowed to connect, even if the inbound
+    /// connections limit has been reached. This predicate should be evaluated AFTER the Handshake.
+    pub fn is_peer_whitelisted(&self, clock: &time::Clock, peer_info: &PeerInfo) -> bool {
         self.whitelist_nodes
             .iter()
             .filter(|wn| wn.id == peer_info.id)
             .filter(|wn| Some(wn.addr) == peer_info.addr)
             .any(|wn| wn.account_id.is_none() || wn.account_id == peer_info.account_id)
+            || self.peer_filter.is_allowed(clock, &peer_info.id)
+    }
+
+    /// Whether the connections with the peer are banned by the peer filter.
+    pub fn is_peer_filtered(&self, clock: &time::Clock, peer_info: &PeerInfo) -> bool {
+        self.peer_filter.is_banned(clock, peer_info, |account_id| {
+            self.account_announcements.get_account_owner(account_id)
+        })
+    }
+
+    /// Closes the connections with the peers banned by the peer filter.
+    pub fn disconnect_filtered_peers(&self, clock: &time::Clock) {
+        for pool in [&self.tier1, &self.tier2] {
+            for conn in pool.load().ready.values() {
+                if self.is_peer_filtered(clock, &conn.peer_info) {
+                    tracing::info!(target: "network", peer_info = ?conn.peer_info, "Disconnecting peer banned by the peer filter");
+                    conn.stop(None);
+                }
+            }
+        }
+    }
+
+    pub fn is_relay(&self, peer_id: &PeerId) -> bool {
+        self.config.relay_nodes.iter().any(|relay| &relay.id == peer_id)
+    }
+
+    /// Starts listening for the inbound connections on `addr`.
+    pub(crate) fn listen(&self, addr: &tcp::ListenerAddr) -> std::io::Result<tcp::Listener> {
+        #[cfg(test)]
+        if let Some(simnet) = &self.config.simnet {
+            return simnet.listen(**addr).map(tcp::Listener::Sim);
+        }
+        addr.listener()
+    }
+
+    /// Connects to the relays to which there is no connection.
+    pub async fn connect_to_relays(self: &Arc<Self>, clock: &time::Clock) {
+        let tier2 = self.tier2.load();
+        let relays = self.config.relay_nodes.iter().filter(|relay| {
+            !tier2.ready.contains_key(&relay.id) && !tier2.outbound_handshakes.contains(&relay.id)
+        });
+        futures_util::future::join_all(
+            relays.map(|relay| self.reconnect(clock.clone(), relay.clone(), 1)),
+        )
+        .await;
+    }
+
+    /// Maps the listening port on the NAT gateway, or renews the mapping.
+    #[cfg(feature = "port_mapping")]
+    pub async fn map_port(&self, protocol: crate::port_mapping::Protocol, port: u16) {
+        let addr = match crate::port_mapping::map_port(protocol, port).await {
+            Ok(addr) => Some(addr),
+            Err(err) => {
+                tracing::warn!(target: "network", ?protocol, ?err, "Failed to map the listening port");
+                None
+            }
+        };
+        metrics::PORT_MAPPED.set(addr.is_some() as i64);
+        let prev = std::mem::replace(&mut *self.mapped_addr.lock(), addr);
+        if addr.is_some() && prev != addr {
+            tracing::info!(target: "network", ?protocol, ?addr, "Mapped the listening port");
+        }
     }
 
     /// predicate checking whether we should allow an inbound connection from peer_info.
-    fn is_inbound_allowed(&self, peer_info: &PeerInfo) -> bool {
+    fn is_inbound_allowed(&self, clock: &time::Clock, peer_info: &PeerInfo) -> bool {
         // Check if we have spare inbound connections capacity.
         let tier2 = self.tier2.load();
         if tier2.ready.len() + tier2.outbound_handshakes.len() < self.config.max_num_peers as usize
@@ -264,7 +423,7 @@ impl NetworkState {
         }
         // Whitelisted nodes are allowed to connect, even if the inbound connections limit has
         // been reached.
-        if self.is_peer_whitelisted(peer_info) {
+        if self.is_peer_whitelisted(clock, peer_info) {
             return true;
         }
         false
@@ -297,6 +456,11 @@ impl NetworkState {
                 return Err(RegisterPeerError::Banned);
             }
 
+            if this.is_peer_filtered(&clock, peer_info) {
+                tracing::debug!(target: "network", peer_info = ?peer_info, "Dropping connection from peer banned by the peer filter");
+                return Err(RegisterPeerError::Banned);
+            }
+
             match conn.tier {
                 tcp::Tier::T1 => {
                     if conn.peer_type == PeerType::Inbound {
@@ -316,7 +480,7 @@ impl NetworkState {
                 }
                 tcp::Tier::T2 => {
                     if conn.peer_type == PeerType::Inbound {
-                        if !this.is_inbound_allowed(&peer_info) {
+                        if !this.is_inbound_allowed(&clock, &peer_info) {
                             // TODO(1896): Gracefully drop inbound connection for other peer.
                             let tier2 = this.tier2.load();
                             tracing::debug!(target: "network",
@@ -388,6 +552,11 @@ impl NetworkState {
             // Save the fact that we are disconnecting to the PeerStore.
             let res = match reason {
                 ClosingReason::Ban(ban_reason) => {
+                    this.reputation_store.record(
+                        &clock,
+                        &conn.peer_info.id,
+                        ReputationEvent::Banned(ban_reason),
+                    );
                     this.peer_store.peer_ban(&clock, &conn.peer_info.id, ban_reason)
                 }
                 _ => this.peer_store.peer_disconnected(&clock, &conn.peer_info.id),
@@ -420,9 +589,7 @@ impl NetworkState {
             interval.tick(&clock).await;
 
             let result = async {
-                let stream = tcp::Stream::connect(&peer_info, tcp::Tier::T2)
-                    .await
-                    .context("tcp::Stream::connect()")?;
+                let stream = self.connect(&peer_info, tcp::Tier::T2).await?;
                 PeerActor::spawn_and_handshake(clock.clone(), stream, None, self.clone())
                     .await
                     .context("PeerActor::spawn()")?;
@@ -474,6 +641,44 @@ impl NetworkState {
         self.send_message_to_peer(clock, tier, self.sign_message(clock, msg));
     }
 
+    /// Sends a traced Ping to `target` over TIER2. The target returns the recorded route
+    /// in a RouteTraceEcho message. Returns the hash of the traced message.
+    pub fn trace_route(&self, clock: &time::Clock, target: PeerId) -> CryptoHash {
+        let my_peer_id = self.config.node_id();
+        let body = RoutedMessageBody::Ping(crate::network_protocol::Ping {
+            nonce: rand::random(),
+            source: my_peer_id.clone(),
+        });
+        let raw = RawRoutedMessage { target: PeerIdOrHash::PeerId(target.clone()), body };
+        let mut msg = self.sign_message(clock, raw);
+        let sent_at = clock.now_utc();
+        msg.trace = Some(RouteTrace {
+            hops: vec![RouteTraceHop { peer_id: my_peer_id, received_at: sent_at }],
+        });
+        let hash = msg.hash();
+        self.route_traces.lock().put(hash, RouteTraceResult { target, sent_at, trace: None });
+        self.send_message_to_peer(clock, tcp::Tier::T2, msg);
+        hash
+    }
+
+    /// Returns the trace recorded by `msg` to its author.
+    pub fn send_route_trace_echo(&self, clock: &time::Clock, msg: &RoutedMessageV2) {
+        let raw = RawRoutedMessage {
+            target: PeerIdOrHash::PeerId(msg.author.clone()),
+            body: RoutedMessageBody::RouteTraceEcho(msg.hash()),
+        };
+        let mut echo = self.sign_message(clock, raw);
+        echo.trace = msg.trace.clone();
+        self.send_message_to_peer(clock, tcp::Tier::T2, echo);
+    }
+
+    /// Stores the trace returned in a RouteTraceEcho, if the trace was initiated by this node.
+    pub fn route_trace_echo_received(&self, hash: &CryptoHash, trace: Option<RouteTrace>) {
+        if let Some(result) = self.route_traces.lock().peek_mut(hash) {
+            result.trace = trace;
+        }
+    }
+
     pub fn sign_message(&self, clock: &time::Clock, msg: RawRoutedMessage) -> Box<RoutedMessageV2> {
         Box::new(msg.sign(
             &self.config.node_key,
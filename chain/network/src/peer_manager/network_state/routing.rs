@@ -6,6 +6,7 @@ use crate::network_protocol::{
 use crate::peer_manager::connection;
 use crate::peer_manager::network_state::PeerIdOrHash;
 use crate::peer_manager::peer_manager_actor::Event;
+use crate::peer_manager::reputation_store;
 use crate::routing::routing_table_view::FindRouteError;
 use crate::routing::NetworkTopologyChange;
 use crate::stats::metrics;
@@ -151,9 +152,14 @@ impl NetworkState {
     ) -> Result<PeerId, FindRouteError> {
         match target {
             PeerIdOrHash::PeerId(peer_id) => {
-                match self.graph.routing_table.find_next_hop_for_target(peer_id) {
+                // Peers with a low reputation are avoided as the next hop, unless
+                // they are the only route.
+                let avoid = |peer_id: &PeerId| {
+                    self.reputation_store.get(clock, peer_id) < reputation_store::LOW_REPUTATION
+                };
+                match self.graph.routing_table.find_next_hop_for_target(peer_id, avoid) {
                     Ok(peer_id) => Ok(peer_id),
-                    Err(_) => self.graph_v2.routing_table.find_next_hop_for_target(peer_id),
+                    Err(_) => self.graph_v2.routing_table.find_next_hop_for_target(peer_id, avoid),
                 }
             }
             PeerIdOrHash::Hash(hash) => self
@@ -187,6 +193,7 @@ impl NetworkState {
 
     pub(crate) fn compare_route_back(&self, hash: CryptoHash, peer_id: &PeerId) -> bool {
         self.tier2_route_back.lock().get(&hash).map_or(false, |value| value == peer_id)
+            || self.tier1_route_back.lock().get(&hash).map_or(false, |value| value == peer_id)
     }
 
     /// Accepts NetworkTopologyChange events.
@@ -1,10 +1,12 @@
 use crate::accounts_data::{AccountDataCacheSnapshot, LocalAccountData};
 use crate::config;
 use crate::network_protocol::{
-    AccountData, PeerAddr, PeerInfo, PeerMessage, SignedAccountData, SyncAccountsData,
+    capabilities, AccountData, PeerAddr, PeerIdOrHash, PeerInfo, PeerMessage, Ping,
+    RawRoutedMessage, RoutedMessageBody, SignedAccountData, SyncAccountsData,
 };
 use crate::peer::peer_actor::PeerActor;
 use crate::peer_manager::connection;
+use crate::stats::metrics;
 use crate::stun;
 use crate::tcp;
 use crate::types::PeerType;
@@ -47,7 +49,7 @@ impl super::NetworkState {
             }
             handles.push(async move {
                 let res = async {
-                    let stream = tcp::Stream::connect(
+                    let stream = self.connect(
                         &PeerInfo {
                             id: proxy.peer_id.clone(),
                             addr: Some(proxy.addr),
@@ -94,9 +96,15 @@ impl super::NetworkState {
         let accounts_data = self.accounts_data.load();
 
         let vc = self.tier1_validator_config(&accounts_data)?;
+        let mapped_addr = *self.mapped_addr.lock();
         let proxies = match (&self.config.node_addr, &vc.proxies) {
             (None, _) => vec![],
             (_, config::ValidatorProxies::Static(peer_addrs)) => peer_addrs.clone(),
+            // If Dynamic are specified and the listening port is mapped on the NAT gateway,
+            // use the external address reported by the gateway.
+            (Some(_), config::ValidatorProxies::Dynamic(_)) if mapped_addr.is_some() => {
+                vec![PeerAddr { peer_id: self.config.node_id(), addr: mapped_addr.unwrap() }]
+            }
             // If Dynamic are specified,
             // it means that this node is its own proxy.
             // Discover the public IP of this node using those STUN servers.
@@ -320,15 +328,16 @@ impl super::NetworkState {
                 if let Some(proxy) = proxy {
                     let proxy = (*proxy).clone();
                     handles.push(async move {
-                        let stream = tcp::Stream::connect(
-                            &PeerInfo {
-                                id: proxy.peer_id,
-                                addr: Some(proxy.addr),
-                                account_id: None,
-                            },
-                            tcp::Tier::T1,
-                        )
-                        .await?;
+                        let stream = self
+                            .connect(
+                                &PeerInfo {
+                                    id: proxy.peer_id,
+                                    addr: Some(proxy.addr),
+                                    account_id: None,
+                                },
+                                tcp::Tier::T1,
+                            )
+                            .await?;
                         PeerActor::spawn_and_handshake(clock.clone(), stream, None, self.clone())
                             .await
                     });
@@ -348,19 +357,53 @@ impl super::NetworkState {
     /// It is expected to perform <10 lookups total on average,
     /// so the call latency should be negligible wrt sending a TCP packet.
     // TODO(gprusak): If not, consider precomputing the AccountKey -> Connection mapping.
+    /// Degraded connections are skipped, in which case the message is delivered over TIER2 only.
     pub fn get_tier1_proxy(&self, data: &SignedAccountData) -> Option<Arc<connection::Connection>> {
         let tier1 = self.tier1.load();
+        let usable = |conn: &&Arc<connection::Connection>| !conn.health.is_degraded();
         // Prefer direct connections.
-        if let Some(conn) = tier1.ready_by_account_key.get(&data.account_key) {
+        if let Some(conn) = tier1.ready_by_account_key.get(&data.account_key).filter(usable) {
             return Some(conn.clone());
         }
         // In case there is no direct connection and our node is a TIER1 validator, use a proxy.
         // TODO(gprusak): add a check that our node is actually a TIER1 validator.
         for proxy in &data.proxies {
-            if let Some(conn) = tier1.ready.get(&proxy.peer_id) {
+            if let Some(conn) = tier1.ready.get(&proxy.peer_id).filter(usable) {
                 return Some(conn.clone());
             }
         }
         None
     }
+
+    /// Sends a ping over every TIER1 connection to a peer which accepts it, and updates the
+    /// health of the connections, see `connection::Health`.
+    pub fn tier1_probe_connections(&self, clock: &time::Clock) {
+        let now = clock.now();
+        let mut degraded = 0;
+        for conn in self.tier1.load().ready.values() {
+            if conn.capabilities & capabilities::TIER1_PROBES == 0 {
+                continue;
+            }
+            let lost = conn.health.expire_pings(now);
+            metrics::TIER1_PROBES.with_label_values(&["lost"]).inc_by(lost as u64);
+            if conn.health.is_degraded() {
+                degraded += 1;
+                tracing::debug!(target: "network", peer_id = ?conn.peer_info.id, rtt = ?conn.health.rtt(), loss_rate = conn.health.loss_rate(), "TIER1 connection degraded");
+            }
+            let nonce = rand::random();
+            let msg = self.sign_message(
+                clock,
+                RawRoutedMessage {
+                    target: PeerIdOrHash::PeerId(conn.peer_info.id.clone()),
+                    body: RoutedMessageBody::Ping(Ping { nonce, source: self.config.node_id() }),
+                },
+            );
+            // Remember that the Pong is for us.
+            self.tier1_route_back.lock().insert(clock, msg.hash(), self.config.node_id());
+            conn.health.ping_sent(nonce, now);
+            conn.send_message(Arc::new(PeerMessage::Routed(msg)));
+            metrics::TIER1_PROBES.with_label_values(&["sent"]).inc();
+        }
+        metrics::TIER1_DEGRADED_CONNECTIONS.set(degraded);
+    }
 }
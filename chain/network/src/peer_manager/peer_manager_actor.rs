@@ -5,6 +5,7 @@ use crate::network_protocol::{
     Disconnect, Edge, PeerIdOrHash, PeerMessage, Ping, Pong, RawRoutedMessage, RoutedMessageBody,
 };
 use crate::peer::peer_actor::PeerActor;
+use crate::peer_filter::{PeerFilterError, UpdatePeerFilter};
 use crate::peer_manager::connection;
 use crate::peer_manager::network_state::{NetworkState, WhitelistNode};
 use crate::peer_manager::peer_store;
@@ -25,16 +26,18 @@ use near_async::time;
 use near_o11y::{handler_debug_span, handler_trace_span, OpenTelemetrySpanExt, WithSpanContext};
 use near_performance_metrics_macros::perf;
 use near_primitives::block::GenesisId;
+use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::views::{
-    ConnectionInfoView, EdgeView, KnownPeerStateView, NetworkGraphView, PeerStoreView,
-    RecentOutboundConnectionsView,
+    ConnectionInfoView, EdgeView, KnownPeerStateView, NetworkBandwidthView, NetworkGraphView,
+    PeerBandwidthView, PeerFilterView, PeerStoreView, RecentOutboundConnectionsView,
+    RouteTraceHopView, RouteTraceView, RouteTracesView,
 };
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
 use rand::Rng;
 use std::cmp::min;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tracing::Instrument as _;
@@ -78,8 +81,14 @@ const PREFER_PREVIOUSLY_CONNECTED_PEER: f64 = 0.6;
 
 /// How often to update the connections in storage.
 pub(crate) const UPDATE_CONNECTION_STORE_INTERVAL: time::Duration = time::Duration::minutes(1);
+/// How often to save the reputations of the peers in storage.
+const SAVE_PEER_REPUTATIONS_INTERVAL: time::Duration = time::Duration::minutes(1);
+/// How often to save the quality of the peer addresses in storage.
+const SAVE_ADDR_QUALITY_INTERVAL: time::Duration = time::Duration::minutes(1);
 /// How often to poll the NetworkState for closed connections we'd like to re-establish.
 pub(crate) const POLL_CONNECTION_STORE_INTERVAL: time::Duration = time::Duration::minutes(1);
+/// How often to re-establish the missing connections to the relays.
+const CONNECT_TO_RELAYS_INTERVAL: time::Duration = time::Duration::seconds(10);
 
 /// Actor that manages peers connections.
 pub struct PeerManagerActor {
@@ -107,6 +116,8 @@ pub enum Event {
     EdgesAdded(Vec<Edge>),
     Ping(Ping),
     Pong(Pong),
+    // Reported when the echo of a route trace initiated by this node has been received.
+    RouteTraceEcho(CryptoHash),
     // Reported once a message has been processed.
     // In contrast to typical RPC protocols, many P2P messages do not trigger
     // sending a response at the end of processing.
@@ -176,6 +187,28 @@ impl actix::Actor for PeerManagerActor {
             }
         }));
 
+        // Periodically save the reputations of the peers.
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        ctx.spawn(wrap_future(async move {
+            let mut interval = time::Interval::new(clock.now(), SAVE_PEER_REPUTATIONS_INTERVAL);
+            loop {
+                interval.tick(&clock).await;
+                state.reputation_store.save(&clock);
+            }
+        }));
+
+        // Periodically save the quality of the peer addresses.
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        ctx.spawn(wrap_future(async move {
+            let mut interval = time::Interval::new(clock.now(), SAVE_ADDR_QUALITY_INTERVAL);
+            loop {
+                interval.tick(&clock).await;
+                state.peer_store.save_addr_quality();
+            }
+        }));
+
         // Periodically prints bandwidth stats for each peer.
         self.report_bandwidth_stats_trigger(ctx, REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL);
 
@@ -207,8 +240,9 @@ impl PeerManagerActor {
     ) -> anyhow::Result<actix::Addr<Self>> {
         let config = config.verify().context("config")?;
         let store = store::Store::from(store);
-        let peer_store = peer_store::PeerStore::new(&clock, config.peer_store.clone())
-            .context("PeerStore::new")?;
+        let peer_store =
+            peer_store::PeerStore::new(&clock, config.peer_store.clone(), store.clone())
+                .context("PeerStore::new")?;
         tracing::debug!(target: "network",
                len = peer_store.len(),
                boot_nodes = config.peer_store.boot_nodes.len(),
@@ -243,7 +277,7 @@ impl PeerManagerActor {
                 // Start server if address provided.
                 if let Some(server_addr) = &state.config.node_addr {
                     tracing::debug!(target: "network", at = ?server_addr, "starting public server");
-                    let mut listener = match server_addr.listener() {
+                    let mut listener = match state.listen(server_addr) {
                         Ok(it) => it,
                         Err(e) => {
                             panic!("failed to start listening on server_addr={server_addr:?} e={e:?}")
@@ -271,6 +305,68 @@ impl PeerManagerActor {
                             }
                         }
                     });
+                    #[cfg(feature = "quic")]
+                    if state.config.quic_enabled {
+                        // QUIC connections are accepted on the UDP port with the same number.
+                        let endpoint = match crate::quic::Endpoint::new(**server_addr) {
+                            Ok(it) => it,
+                            Err(e) => {
+                                panic!("failed to start QUIC endpoint on server_addr={server_addr:?} e={e:?}")
+                            }
+                        };
+                        let _ = state.quic_endpoint.set(endpoint);
+                        arbiter.spawn({
+                            let arbiter = arbiter.clone();
+                            let clock = clock.clone();
+                            let state = state.clone();
+                            async move {
+                                let endpoint = state.quic_endpoint.get().unwrap();
+                                while let Some(incoming) = endpoint.accept().await {
+                                    // Establishing the connection takes a round trip, so it
+                                    // mustn't delay accepting the next one.
+                                    arbiter.spawn({
+                                        let clock = clock.clone();
+                                        let state = state.clone();
+                                        async move {
+                                            let stream = match incoming.accept().await {
+                                                Ok(stream) => stream,
+                                                Err(err) => {
+                                                    tracing::debug!(target: "network", ?err, "failed to accept QUIC connection");
+                                                    return;
+                                                }
+                                            };
+                                            tracing::debug!(target: "network", from = ?stream.peer_addr, "got new QUIC connection");
+                                            if let Err(err) =
+                                                PeerActor::spawn(clock, stream, None, state)
+                                            {
+                                                tracing::info!(target:"network", ?err, "PeerActor::spawn()");
+                                            }
+                                        }
+                                    });
+                                }
+                            }
+                        });
+                    }
+                    #[cfg(feature = "port_mapping")]
+                    if let Some(protocol) = state.config.port_mapping {
+                        // Map the port before the TIER1 proxies are advertised for the first time.
+                        let port = server_addr.port();
+                        state.map_port(protocol, port).await;
+                        arbiter.spawn({
+                            let clock = clock.clone();
+                            let state = state.clone();
+                            let mut interval = time::Interval::new(
+                                clock.now() + crate::port_mapping::RENEW_INTERVAL,
+                                crate::port_mapping::RENEW_INTERVAL,
+                            );
+                            async move {
+                                loop {
+                                    interval.tick(&clock).await;
+                                    state.map_port(protocol, port).await;
+                                }
+                            }
+                        });
+                    }
                 }
                 if let Some(cfg) = state.config.tier1.clone() {
                     // Connect to TIER1 proxies and broadcast the list those connections periodically.
@@ -299,6 +395,32 @@ impl PeerManagerActor {
                             }
                         }
                     });
+                    // Probe the health of TIER1 connections periodically.
+                    arbiter.spawn({
+                        let clock = clock.clone();
+                        let state = state.clone();
+                        let mut interval = time::Interval::new(clock.now(), cfg.probe_interval);
+                        async move {
+                            loop {
+                                interval.tick(&clock).await;
+                                state.tier1_probe_connections(&clock);
+                            }
+                        }
+                    });
+                }
+                if !state.config.relay_nodes.is_empty() {
+                    // Keep the connections to the relays open.
+                    arbiter.spawn({
+                        let clock = clock.clone();
+                        let state = state.clone();
+                        let mut interval = time::Interval::new(clock.now(), CONNECT_TO_RELAYS_INTERVAL);
+                        async move {
+                            loop {
+                                interval.tick(&clock).await;
+                                state.connect_to_relays(&clock).await;
+                            }
+                        }
+                    });
                 }
                 // Periodically poll the connection store for connections we'd like to re-establish
                 arbiter.spawn({
@@ -455,17 +577,19 @@ impl PeerManagerActor {
             .collect()
     }
 
-    /// Check if the number of connections (excluding whitelisted ones) exceeds ideal_connections_hi.
-    /// If so, constructs a safe set of peers and selects one random peer outside of that set
-    /// and sends signal to stop connection to it gracefully.
+    /// Check if the number of connections (excluding whitelisted ones and relays) exceeds
+    /// ideal_connections_hi.
+    /// If so, constructs a safe set of peers and selects the peer with the lowest reputation
+    /// outside of that set (a random one among equals) and sends signal to stop connection to
+    /// it gracefully.
     ///
     /// Safe set contruction process:
-    /// 1. Add all whitelisted peers to the safe set.
+    /// 1. Add all whitelisted peers and relays to the safe set.
     /// 2. If the number of outbound connections is less or equal than minimum_outbound_connections,
     ///    add all outbound connections to the safe set.
     /// 3. Find all peers who sent us a message within the last peer_recent_time_window,
-    ///    and add them one by one to the safe_set (starting from earliest connection time)
-    ///    until safe set has safe_set_size elements.
+    ///    and add them one by one to the safe_set (starting from the highest reputation, then
+    ///    earliest connection time) until safe set has safe_set_size elements.
     fn maybe_stop_active_connection(&self) {
         let tier2 = self.state.tier2.load();
         let filter_peers = |predicate: &dyn Fn(&connection::Connection) -> bool| -> Vec<_> {
@@ -480,8 +604,11 @@ impl PeerManagerActor {
         // Build safe set
         let mut safe_set = HashSet::new();
 
-        // Add whitelisted nodes to the safe set.
-        let whitelisted_peers = filter_peers(&|p| self.state.is_peer_whitelisted(&p.peer_info));
+        // Add whitelisted nodes and relays to the safe set.
+        let whitelisted_peers = filter_peers(&|p| {
+            self.state.is_peer_whitelisted(&self.clock, &p.peer_info)
+                || self.state.is_relay(&p.peer_info.id)
+        });
         safe_set.extend(whitelisted_peers);
 
         // If there is not enough non-whitelisted peers, return without disconnecting anyone.
@@ -519,8 +646,15 @@ impl PeerManagerActor {
             .cloned()
             .collect();
 
-        // Sort by established time.
-        active_peers.sort_by_key(|p| p.established_time);
+        // Sort by reputation, then by established time.
+        let reputation = |p: &connection::Connection| {
+            self.state.reputation_store.get(&self.clock, &p.peer_info.id)
+        };
+        active_peers.sort_by(|a, b| {
+            reputation(b)
+                .total_cmp(&reputation(a))
+                .then(a.established_time.cmp(&b.established_time))
+        });
         // Saturate safe set with recently active peers.
         let set_limit = self.state.config.safe_set_size as usize;
         for p in active_peers {
@@ -531,8 +665,13 @@ impl PeerManagerActor {
         }
 
         // Build valid candidate list to choose the peer to be removed. All peers outside the safe set.
-        let candidates = tier2.ready.values().filter(|p| !safe_set.contains(&p.peer_info.id));
-        if let Some(p) = candidates.choose(&mut rand::thread_rng()) {
+        let mut candidates: Vec<_> =
+            tier2.ready.values().filter(|p| !safe_set.contains(&p.peer_info.id)).collect();
+        // Shuffle, so that a random one of the peers with the lowest reputation is selected.
+        candidates.shuffle(&mut rand::thread_rng());
+        if let Some(p) =
+            candidates.into_iter().min_by(|a, b| reputation(a).total_cmp(&reputation(b)))
+        {
             tracing::debug!(target: "network", id = ?p.peer_info.id,
                 tier2_len = tier2.ready.len(),
                 ideal_connections_hi = self.state.config.ideal_connections_hi,
@@ -578,6 +717,8 @@ impl PeerManagerActor {
                     || self.state.config.node_addr.as_ref().map(|a|**a) == peer_state.peer_info.addr
                     // Or to peers we are currently trying to connect to
                     || tier2.outbound_handshakes.contains(&peer_state.peer_info.id)
+                    // Or to peers banned by the node operator
+                    || self.state.is_peer_filtered(&self.clock, &peer_state.peer_info)
                 },
                 prefer_previously_connected_peer,
             ) {
@@ -591,7 +732,7 @@ impl PeerManagerActor {
                     let clock = self.clock.clone();
                     async move {
                         let result = async {
-                            let stream = tcp::Stream::connect(&peer_info, tcp::Tier::T2).await.context("tcp::Stream::connect()")?;
+                            let stream = state.connect(&peer_info, tcp::Tier::T2).await?;
                             PeerActor::spawn_and_handshake(clock.clone(),stream,None,state.clone()).await.context("PeerActor::spawn()")?;
                             anyhow::Ok(())
                         }.await;
@@ -662,6 +803,7 @@ impl PeerManagerActor {
                 Some(e) => e.nonce(),
                 None => 0,
             },
+            reputation: self.state.reputation_store.get(&self.clock, &cp.peer_info.id),
         };
         NetworkInfo {
             connected_peers: tier2.ready.values().map(connected_peer).collect(),
@@ -697,6 +839,34 @@ impl PeerManagerActor {
         }
     }
 
+    fn route_traces_view(&self) -> RouteTracesView {
+        let unix_ms = |t: time::Utc| (t.unix_timestamp_nanos() / 1_000_000) as i64;
+        let traces = self
+            .state
+            .route_traces
+            .lock()
+            .iter()
+            .map(|(hash, r)| RouteTraceView {
+                msg_hash: *hash,
+                target: r.target.clone(),
+                sent_at_ms: unix_ms(r.sent_at),
+                hops: r.trace.as_ref().map(|trace| {
+                    trace
+                        .hops
+                        .iter()
+                        .map(|hop| RouteTraceHopView {
+                            peer_id: hop.peer_id.clone(),
+                            received_at_ms: unix_ms(hop.received_at),
+                            since_sent_ms: (hop.received_at - r.sent_at).whole_milliseconds()
+                                as i64,
+                        })
+                        .collect()
+                }),
+            })
+            .collect();
+        RouteTracesView { traces }
+    }
+
     fn push_network_info_trigger(&self, ctx: &mut actix::Context<Self>, interval: time::Duration) {
         let _span = tracing::trace_span!(target: "network", "push_network_info_trigger").entered();
         let network_info = self.get_network_info();
@@ -752,12 +922,11 @@ impl PeerManagerActor {
                     NetworkResponses::RouteNotFound
                 }
             }
-            NetworkRequests::BlockHeadersRequest { hashes, peer_id } => {
-                if self
-                    .state
-                    .tier2
-                    .send_message(peer_id, Arc::new(PeerMessage::BlockHeadersRequest(hashes)))
-                {
+            NetworkRequests::BlockHeadersRequest { hashes, max_headers, peer_id } => {
+                if self.state.tier2.send_message(
+                    peer_id,
+                    Arc::new(PeerMessage::BlockHeadersRequest(hashes, max_headers)),
+                ) {
                     NetworkResponses::NoResponse
                 } else {
                     NetworkResponses::RouteNotFound
@@ -783,6 +952,22 @@ impl PeerManagerActor {
                     NetworkResponses::RouteNotFound
                 }
             }
+            NetworkRequests::SnapshotHostInfo(info) => {
+                self.state.snapshot_host_info.store(Arc::new(Some(info.clone())));
+                self.state.tier2.broadcast_message(Arc::new(PeerMessage::SnapshotHostInfo(info)));
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::EpochSyncRequest { epoch_id, peer_id } => {
+                if self
+                    .state
+                    .tier2
+                    .send_message(peer_id, Arc::new(PeerMessage::EpochSyncRequest(epoch_id)))
+                {
+                    NetworkResponses::NoResponse
+                } else {
+                    NetworkResponses::RouteNotFound
+                }
+            }
             NetworkRequests::BanPeer { peer_id, ban_reason } => {
                 self.state.disconnect_and_ban(&self.clock, &peer_id, ban_reason);
                 NetworkResponses::NoResponse
@@ -1072,6 +1257,56 @@ impl actix::Handler<GetDebugStatus> for PeerManagerActor {
                 })
             }
             GetDebugStatus::Routes => DebugStatus::Routes(self.state.graph_v2.get_debug_view()),
+            GetDebugStatus::Bandwidth => {
+                let tier1 = self.state.tier1.load();
+                let tier2 = self.state.tier2.load();
+                let bytes_by_type = |bytes: &parking_lot::Mutex<HashMap<&'static str, u64>>| {
+                    bytes.lock().iter().map(|(t, b)| (t.to_string(), *b)).collect()
+                };
+                let mut peers: Vec<_> = tier1
+                    .ready
+                    .values()
+                    .chain(tier2.ready.values())
+                    .map(|conn| PeerBandwidthView {
+                        peer_id: conn.peer_info.id.clone(),
+                        addr: format!("{:?}", conn.peer_info.addr),
+                        tier: conn.tier.as_ref().to_string(),
+                        received_bytes_per_sec: conn
+                            .stats
+                            .received_bytes_per_sec
+                            .load(Ordering::Relaxed),
+                        sent_bytes_per_sec: conn.stats.sent_bytes_per_sec.load(Ordering::Relaxed),
+                        received_bytes_by_type: bytes_by_type(&conn.stats.received_bytes_by_type),
+                        sent_bytes_by_type: bytes_by_type(&conn.stats.sent_bytes_by_type),
+                    })
+                    .collect();
+                peers.sort_by_key(|p| {
+                    std::cmp::Reverse(p.received_bytes_per_sec + p.sent_bytes_per_sec)
+                });
+                DebugStatus::Bandwidth(NetworkBandwidthView { peers })
+            }
+            GetDebugStatus::RouteTraces => DebugStatus::RouteTraces(self.route_traces_view()),
+            GetDebugStatus::TraceRoute(target) => {
+                self.state.trace_route(&self.clock, target);
+                DebugStatus::RouteTraces(self.route_traces_view())
+            }
+        }
+    }
+}
+
+impl actix::Handler<UpdatePeerFilter> for PeerManagerActor {
+    type Result = Result<PeerFilterView, PeerFilterError>;
+    #[perf]
+    fn handle(&mut self, msg: UpdatePeerFilter, _ctx: &mut actix::Context<Self>) -> Self::Result {
+        tracing::info!(target: "network", ?msg, "UpdatePeerFilter");
+        match &msg {
+            UpdatePeerFilter::Add { rule, ttl } => {
+                self.state.peer_filter.add(&self.clock, rule, *ttl)?;
+                self.state.disconnect_filtered_peers(&self.clock);
+            }
+            UpdatePeerFilter::Remove(rule) => self.state.peer_filter.remove(&self.clock, rule)?,
+            UpdatePeerFilter::Get => {}
         }
+        Ok(self.state.peer_filter.view(&self.clock))
     }
 }
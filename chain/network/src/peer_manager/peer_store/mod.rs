@@ -1,6 +1,7 @@
 use crate::blacklist;
 use crate::network_protocol::PeerInfo;
-use crate::types::{KnownPeerState, KnownPeerStatus, ReasonForBan};
+use crate::store;
+use crate::types::{AddrQuality, KnownPeerState, KnownPeerStatus, ReasonForBan};
 use anyhow::bail;
 use im::hashmap::Entry;
 use im::{HashMap, HashSet};
@@ -8,9 +9,9 @@ use lru::LruCache;
 use near_async::time;
 use near_primitives::network::PeerId;
 use parking_lot::Mutex;
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Not;
 
 #[cfg(test)]
@@ -18,6 +19,12 @@ mod testonly;
 #[cfg(test)]
 mod tests;
 
+/// Outcomes of at most this many recent attempts to connect to an address are taken into
+/// account, so that its quality follows the changes of its reachability.
+const MAX_TRACKED_ATTEMPTS: u32 = 32;
+/// Maximum number of addresses whose quality is tracked (and saved).
+const ADDR_QUALITY_CACHE_SIZE: usize = 10000;
+
 /// The PeerStore is an in-memory cache of known peer states. It is used to:
 ///     - Store information about known peers in the network. Peers may be discovered
 ///       by connecting to them directly or by learning about them from other peers.
@@ -25,7 +32,14 @@ mod tests;
 ///     - Select peers to which we may try to connect directly (see PeerStore::unconnected_peer).
 ///
 /// Contents of the PeerStore are not persisted to the database. Upon starting a node,
-/// the PeerStore is initialized from the boot nodes in its config.
+/// the PeerStore is initialized from the boot nodes in its config. The only exception is
+/// the quality of the addresses, i.e. the outcomes of the recent connection attempts to them,
+/// which is saved periodically (see PeerStore::save_addr_quality).
+///
+/// Peers to connect to are chosen at random, preferring the addresses of good quality and
+/// the network groups (address prefixes, a rough approximation of the AS) with fewer connected
+/// peers. Spreading the connections across the network groups makes it harder to eclipse
+/// the node or to cut it off the network by a failure of a single operator.
 
 /// Level of trust we have about a new (PeerId, Addr) pair.
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
@@ -71,6 +85,20 @@ pub struct Config {
     pub peer_expiration_duration: time::Duration,
     /// Duration of the ban for misbehaving peers.
     pub ban_window: time::Duration,
+    /// Length of the prefix of the IPv4 addresses grouped into a network group.
+    pub network_group_ipv4_prefix_len: u32,
+    /// Length of the prefix of the IPv6 addresses grouped into a network group.
+    pub network_group_ipv6_prefix_len: u32,
+    /// The odds of choosing a peer to connect to are divided by (1+n)^bias,
+    /// where n is the number of connected peers in its network group.
+    /// 0 disables the preference for the diverse network groups.
+    pub outbound_diversity_bias: f64,
+}
+
+/// Estimated probability that an attempt to connect to the address succeeds.
+/// Addresses which were never attempted get 1/2.
+fn quality(q: Option<&AddrQuality>) -> f64 {
+    q.map_or(0.5, |q| (q.successes + 1) as f64 / (q.successes + q.failures + 2) as f64)
 }
 
 /// Known peers store, maintaining cache of known peers
@@ -84,6 +112,8 @@ struct Inner {
     // It can happens that some peers don't have known address, so
     // they will not be present in this list, otherwise they will be present.
     addr_peers: HashMap<SocketAddr, VerifiedPeer>,
+    store: store::Store,
+    addr_quality: LruCache<SocketAddr, AddrQuality>,
 }
 
 impl Inner {
@@ -180,6 +210,66 @@ impl Inner {
             .collect()
     }
 
+    /// Returns the network group of `ip`, i.e. `ip` with the bits after the prefix cleared.
+    fn network_group(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let host_bits =
+                    u32::MAX.checked_shr(self.config.network_group_ipv4_prefix_len).unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & !host_bits).into())
+            }
+            IpAddr::V6(ip) => {
+                let host_bits =
+                    u128::MAX.checked_shr(self.config.network_group_ipv6_prefix_len).unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & !host_bits).into())
+            }
+        }
+    }
+
+    /// Choose a random peer with an address based on filter. The odds of a peer are
+    /// proportional to the quality of its address and decrease with the number of
+    /// connected peers in its network group.
+    fn choose_peer<F>(&self, filter: F) -> Option<PeerInfo>
+    where
+        F: Fn(&KnownPeerState) -> bool,
+    {
+        let connected_groups: Vec<IpAddr> = (self.peer_states.iter().map(|(_, v)| v))
+            .filter(|p| p.status == KnownPeerStatus::Connected)
+            .filter_map(|p| Some(self.network_group(p.peer_info.addr?.ip())))
+            .collect();
+        let candidates: Vec<(&KnownPeerState, f64)> = (self.peer_states.iter().map(|(_, v)| v))
+            .filter(|p| filter(p))
+            .filter_map(|p| {
+                let addr = p.peer_info.addr?;
+                let group = self.network_group(addr.ip());
+                let connected = connected_groups.iter().filter(|g| **g == group).count();
+                let diversity = (1. + connected as f64).powf(-self.config.outbound_diversity_bias);
+                Some((p, quality(self.addr_quality.peek(&addr)) * diversity))
+            })
+            .collect();
+        let (peer_state, _) = candidates.choose_weighted(&mut thread_rng(), |(_, w)| *w).ok()?;
+        Some(peer_state.peer_info.clone())
+    }
+
+    /// Records the outcome of an attempt to connect to the address.
+    fn record_attempt(&mut self, now: time::Utc, addr: SocketAddr, success: bool) {
+        if !self.addr_quality.contains(&addr) {
+            self.addr_quality
+                .put(addr, AddrQuality { addr, successes: 0, failures: 0, last_attempt: now });
+        }
+        let q = self.addr_quality.get_mut(&addr).unwrap();
+        if success {
+            q.successes += 1;
+        } else {
+            q.failures += 1;
+        }
+        if q.successes + q.failures > MAX_TRACKED_ATTEMPTS {
+            q.successes /= 2;
+            q.failures /= 2;
+        }
+        q.last_attempt = now;
+    }
+
     /// Create new pair between peer_info.id and peer_addr removing
     /// old pairs if necessary.
     fn update_peer_info(
@@ -287,7 +377,7 @@ impl Inner {
 pub(crate) struct PeerStore(Mutex<Inner>);
 
 impl PeerStore {
-    pub fn new(clock: &time::Clock, config: Config) -> anyhow::Result<Self> {
+    pub fn new(clock: &time::Clock, config: Config, store: store::Store) -> anyhow::Result<Self> {
         let boot_nodes: HashSet<_> = config.boot_nodes.iter().map(|p| p.id.clone()).collect();
         // A mapping from `PeerId` to `KnownPeerState`.
         let mut peerid_2_state = LruCache::new(config.peer_states_cache_size as usize);
@@ -334,8 +424,21 @@ impl PeerStore {
             }
         }
 
-        let inner =
-            Inner { config, boot_nodes, peer_states: peerid_2_state, addr_peers: addr_2_peer };
+        let mut addr_quality = LruCache::new(ADDR_QUALITY_CACHE_SIZE);
+        let mut qualities = store.get_addr_qualities();
+        qualities.sort_by_key(|q| q.last_attempt);
+        for q in qualities {
+            addr_quality.put(q.addr, q);
+        }
+
+        let inner = Inner {
+            config,
+            boot_nodes,
+            peer_states: peerid_2_state,
+            addr_peers: addr_2_peer,
+            store,
+            addr_quality,
+        };
         Ok(PeerStore(Mutex::new(inner)))
     }
 
@@ -392,17 +495,22 @@ impl PeerStore {
         result: Result<(), anyhow::Error>,
     ) -> anyhow::Result<()> {
         let mut inner = self.0.lock();
+        let now = clock.now_utc();
+        let success = result.is_ok();
 
-        if let Some(peer_state) = inner.peer_states.get_mut(peer_id) {
+        let addr = if let Some(peer_state) = inner.peer_states.get_mut(peer_id) {
             if result.is_err() {
                 // Marks the peer status as Unknown (as we failed to connect to it).
                 peer_state.status = KnownPeerStatus::Unknown;
             }
-            peer_state.last_outbound_attempt =
-                Some((clock.now_utc(), result.map_err(|err| err.to_string())));
-            peer_state.last_seen = clock.now_utc();
+            peer_state.last_outbound_attempt = Some((now, result.map_err(|err| err.to_string())));
+            peer_state.last_seen = now;
+            peer_state.peer_info.addr
         } else {
             bail!("Peer {} is missing in the peer store", peer_id);
+        };
+        if let Some(addr) = addr {
+            inner.record_attempt(now, addr, success);
         }
 
         Ok(())
@@ -435,36 +543,26 @@ impl PeerStore {
     ) -> Option<PeerInfo> {
         let inner = self.0.lock();
         if prefer_previously_connected_peer {
-            let preferred_peer = inner.find_peers(
-                |p| {
-                    (p.status == KnownPeerStatus::NotConnected)
-                        && !ignore_fn(p)
-                        && p.peer_info.addr.is_some()
-                        // if we're connecting only to the boot nodes - filter out the nodes that are not bootnodes.
-                        && (!inner.config.connect_only_to_boot_nodes || inner.boot_nodes.contains(&p.peer_info.id))
-                },
-                1,
-            )
-            .get(0)
-            .cloned();
+            let preferred_peer = inner.choose_peer(|p| {
+                (p.status == KnownPeerStatus::NotConnected)
+                    && !ignore_fn(p)
+                    && p.peer_info.addr.is_some()
+                    // if we're connecting only to the boot nodes - filter out the nodes that are not bootnodes.
+                    && (!inner.config.connect_only_to_boot_nodes || inner.boot_nodes.contains(&p.peer_info.id))
+            });
             // If we found a preferred peer - return it.
             if preferred_peer.is_some() {
                 return preferred_peer;
             };
             // otherwise, pick a peer from the wider pool below.
         }
-        inner.find_peers(
-            |p| {
-                (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
-                    && !ignore_fn(p)
-                    && p.peer_info.addr.is_some()
-                    // If we're connecting only to the boot nodes - filter out the nodes that are not boot nodes.
-                    && (!inner.config.connect_only_to_boot_nodes || inner.boot_nodes.contains(&p.peer_info.id))
-            },
-            1,
-        )
-        .get(0)
-        .cloned()
+        inner.choose_peer(|p| {
+            (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
+                && !ignore_fn(p)
+                && p.peer_info.addr.is_some()
+                // If we're connecting only to the boot nodes - filter out the nodes that are not boot nodes.
+                && (!inner.config.connect_only_to_boot_nodes || inner.boot_nodes.contains(&p.peer_info.id))
+        })
     }
 
     /// Return healthy known peers up to given amount.
@@ -512,6 +610,15 @@ impl PeerStore {
         self.0.lock().add_peer(clock, peer_info, TrustLevel::Direct)
     }
 
+    /// Saves the quality of the addresses to the DB.
+    pub fn save_addr_quality(&self) {
+        let mut inner = self.0.lock();
+        let qualities: Vec<_> = inner.addr_quality.iter().map(|(_, q)| q.clone()).collect();
+        if let Err(err) = inner.store.set_addr_qualities(&qualities) {
+            tracing::error!(target: "network", ?err, "Failed to save the quality of peer addresses");
+        }
+    }
+
     pub fn load(&self) -> HashMap<PeerId, KnownPeerState> {
         self.0.lock().peer_states.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
     }
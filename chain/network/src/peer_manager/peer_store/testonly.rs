@@ -1,7 +1,12 @@
-use crate::types::KnownPeerState;
+use crate::types::{AddrQuality, KnownPeerState};
+use std::net::SocketAddr;
 
 impl super::PeerStore {
     pub fn dump(&self) -> Vec<KnownPeerState> {
         self.0.lock().peer_states.iter().map(|(_, v)| v.clone()).collect()
     }
+
+    pub fn addr_quality(&self, addr: &SocketAddr) -> Option<AddrQuality> {
+        self.0.lock().addr_quality.peek(addr).cloned()
+    }
 }
@@ -0,0 +1,182 @@
+use crate::stats::metrics;
+use crate::store;
+use crate::types::{PeerReputation, ReasonForBan};
+use lru::LruCache;
+use near_async::time;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+/// Time after which a score decays to half of its value, so that peers can
+/// recover from past misbehavior and can't live off past merits forever.
+pub(crate) const REPUTATION_HALF_LIFE: time::Duration = time::Duration::hours(24);
+/// Scores are kept within [-MAX_REPUTATION, MAX_REPUTATION].
+pub(crate) const MAX_REPUTATION: f64 = 1000.;
+/// Peers with a score below this are avoided as the next hop of routed
+/// messages, unless there's no other route.
+pub(crate) const LOW_REPUTATION: f64 = -50.;
+/// Requested data arriving later than this counts as a slow response.
+pub(crate) const SLOW_RESPONSE_THRESHOLD: time::Duration = time::Duration::seconds(2);
+/// A block arriving from a peer this long after it arrived from another peer
+/// counts as late.
+pub(crate) const LATE_BLOCK_THRESHOLD: time::Duration = time::Duration::milliseconds(500);
+/// Maximum number of scores stored in the DB, the scores furthest from
+/// neutral are kept.
+const STORED_REPUTATIONS_LIMIT: usize = 1000;
+/// Number of recent blocks whose first arrival is remembered.
+const BLOCK_ARRIVALS_CACHE_SIZE: usize = 100;
+
+/// Something a peer did which changes its reputation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ReputationEvent {
+    /// The peer was banned, e.g. for violating the protocol or sending an
+    /// invalid block.
+    Banned(ReasonForBan),
+    /// The peer sent a block long after another peer did.
+    LateBlock,
+    /// The peer responded to a request slowly.
+    SlowResponse,
+    /// The peer responded to a request in time, or was the first to send a
+    /// block.
+    UsefulData,
+}
+
+impl ReputationEvent {
+    fn score(&self) -> f64 {
+        match self {
+            Self::Banned(_) => -100.,
+            Self::LateBlock => -1.,
+            Self::SlowResponse => -2.,
+            Self::UsefulData => 1.,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Banned(_) => "banned",
+            Self::LateBlock => "late_block",
+            Self::SlowResponse => "slow_response",
+            Self::UsefulData => "useful_data",
+        }
+    }
+}
+
+/// Returns `score` decayed from `updated_at` to `now`.
+fn decayed(score: f64, updated_at: time::Utc, now: time::Utc) -> f64 {
+    let elapsed = (now - updated_at).max(time::Duration::ZERO);
+    score * 0.5f64.powf(elapsed.as_seconds_f64() / REPUTATION_HALF_LIFE.as_seconds_f64())
+}
+
+struct Inner {
+    store: store::Store,
+    /// Scores of the peers as of the time they were last updated.
+    reputations: HashMap<PeerId, (f64, time::Utc)>,
+    /// Time each of the recent blocks was first received.
+    block_arrivals: LruCache<CryptoHash, time::Instant>,
+}
+
+/// ReputationStore keeps a score of each peer, which goes up when the peer
+/// serves useful data and down when it misbehaves or is slow.  Scores decay
+/// towards neutral over time.  They are used to pick which connection to drop
+/// when there are too many, and which peer to route messages through.
+/// Scores are saved to the DB periodically and loaded on start.
+pub(crate) struct ReputationStore(Mutex<Inner>);
+
+impl ReputationStore {
+    pub fn new(store: store::Store) -> Self {
+        let reputations = store
+            .get_peer_reputations()
+            .into_iter()
+            .map(|r| (r.peer_id, (r.score, r.updated_at)))
+            .collect();
+        Self(Mutex::new(Inner {
+            store,
+            reputations,
+            block_arrivals: LruCache::new(BLOCK_ARRIVALS_CACHE_SIZE),
+        }))
+    }
+
+    /// Returns the current score of the peer, 0 for unknown peers.
+    pub fn get(&self, clock: &time::Clock, peer_id: &PeerId) -> f64 {
+        let inner = self.0.lock();
+        inner
+            .reputations
+            .get(peer_id)
+            .map_or(0., |(score, updated_at)| decayed(*score, *updated_at, clock.now_utc()))
+    }
+
+    pub fn record(&self, clock: &time::Clock, peer_id: &PeerId, event: ReputationEvent) {
+        metrics::PEER_REPUTATION_EVENTS.with_label_values(&[event.name()]).inc();
+        if let ReputationEvent::Banned(reason) = event {
+            tracing::debug!(target: "network", ?peer_id, ?reason, "Lowering reputation of banned peer");
+        }
+        let now = clock.now_utc();
+        let mut inner = self.0.lock();
+        let (score, updated_at) = inner.reputations.entry(peer_id.clone()).or_insert((0., now));
+        *score = (decayed(*score, *updated_at, now) + event.score())
+            .clamp(-MAX_REPUTATION, MAX_REPUTATION);
+        *updated_at = now;
+    }
+
+    /// Called when a peer sends a block which we didn't request.
+    pub fn block_received(&self, clock: &time::Clock, peer_id: &PeerId, hash: CryptoHash) {
+        let now = clock.now();
+        let first_arrival = {
+            let mut inner = self.0.lock();
+            let first_arrival = inner.block_arrivals.get(&hash).copied();
+            if first_arrival.is_none() {
+                inner.block_arrivals.put(hash, now);
+            }
+            first_arrival
+        };
+        let event = match first_arrival {
+            None => ReputationEvent::UsefulData,
+            Some(first_arrival) if now - first_arrival > LATE_BLOCK_THRESHOLD => {
+                ReputationEvent::LateBlock
+            }
+            Some(_) => return,
+        };
+        self.record(clock, peer_id, event);
+    }
+
+    /// Called when a peer responds to a request `latency` after it was sent.
+    pub fn response_received(
+        &self,
+        clock: &time::Clock,
+        peer_id: &PeerId,
+        latency: time::Duration,
+    ) {
+        let event = if latency > SLOW_RESPONSE_THRESHOLD {
+            ReputationEvent::SlowResponse
+        } else {
+            ReputationEvent::UsefulData
+        };
+        self.record(clock, peer_id, event);
+    }
+
+    /// Saves the scores furthest from neutral to the DB.
+    pub fn save(&self, clock: &time::Clock) {
+        let now = clock.now_utc();
+        let mut inner = self.0.lock();
+        let mut reputations: Vec<_> = inner
+            .reputations
+            .iter()
+            .map(|(peer_id, (score, updated_at))| PeerReputation {
+                peer_id: peer_id.clone(),
+                score: decayed(*score, *updated_at, now),
+                updated_at: now,
+            })
+            .collect();
+        reputations.sort_by(|a, b| b.score.abs().total_cmp(&a.score.abs()));
+        reputations.truncate(STORED_REPUTATIONS_LIMIT);
+        if let Err(err) = inner.store.set_peer_reputations(&reputations) {
+            tracing::error!(target: "network", ?err, "Failed to save peer reputations");
+        }
+        inner.reputations =
+            reputations.into_iter().map(|r| (r.peer_id, (r.score, r.updated_at))).collect();
+    }
+}
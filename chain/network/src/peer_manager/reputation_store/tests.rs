@@ -0,0 +1,86 @@
+use crate::network_protocol::testonly::make_peer_id;
+use crate::peer_manager::reputation_store::{
+    ReputationEvent, ReputationStore, LATE_BLOCK_THRESHOLD, REPUTATION_HALF_LIFE,
+    SLOW_RESPONSE_THRESHOLD,
+};
+use crate::store;
+use crate::testonly::make_rng;
+use crate::types::ReasonForBan;
+use near_async::time;
+use near_primitives::hash::CryptoHash;
+
+#[test]
+fn test_reputation_decay() {
+    let mut rng = make_rng(921853233);
+    let clock = time::FakeClock::default();
+    let rs = ReputationStore::new(store::Store::from(near_store::db::TestDB::new()));
+    let peer_id = make_peer_id(&mut rng);
+
+    assert_eq!(rs.get(&clock.clock(), &peer_id), 0.);
+    rs.record(&clock.clock(), &peer_id, ReputationEvent::Banned(ReasonForBan::BadBlock));
+    assert_eq!(rs.get(&clock.clock(), &peer_id), -100.);
+    clock.advance(REPUTATION_HALF_LIFE);
+    assert_eq!(rs.get(&clock.clock(), &peer_id), -50.);
+    rs.record(&clock.clock(), &peer_id, ReputationEvent::UsefulData);
+    assert_eq!(rs.get(&clock.clock(), &peer_id), -49.);
+}
+
+#[test]
+fn test_block_received() {
+    let mut rng = make_rng(921853233);
+    let clock = time::FakeClock::default();
+    let rs = ReputationStore::new(store::Store::from(near_store::db::TestDB::new()));
+    let first = make_peer_id(&mut rng);
+    let second = make_peer_id(&mut rng);
+    let late = make_peer_id(&mut rng);
+    let hash = CryptoHash::hash_bytes(b"block");
+
+    rs.block_received(&clock.clock(), &first, hash);
+    rs.block_received(&clock.clock(), &second, hash);
+    clock.advance(LATE_BLOCK_THRESHOLD + time::Duration::milliseconds(1));
+    rs.block_received(&clock.clock(), &late, hash);
+    // Only the first peer to send the block is rewarded, and only the peers
+    // sending it much later are penalized.
+    assert_eq!(rs.get(&clock.clock(), &first), 1.);
+    assert_eq!(rs.get(&clock.clock(), &second), 0.);
+    assert_eq!(rs.get(&clock.clock(), &late), -1.);
+}
+
+#[test]
+fn test_response_received() {
+    let mut rng = make_rng(921853233);
+    let clock = time::FakeClock::default();
+    let rs = ReputationStore::new(store::Store::from(near_store::db::TestDB::new()));
+    let fast = make_peer_id(&mut rng);
+    let slow = make_peer_id(&mut rng);
+
+    rs.response_received(&clock.clock(), &fast, SLOW_RESPONSE_THRESHOLD);
+    rs.response_received(
+        &clock.clock(),
+        &slow,
+        SLOW_RESPONSE_THRESHOLD + time::Duration::milliseconds(1),
+    );
+    assert_eq!(rs.get(&clock.clock(), &fast), 1.);
+    assert_eq!(rs.get(&clock.clock(), &slow), -2.);
+}
+
+#[test]
+fn test_reload_from_storage() {
+    let mut rng = make_rng(921853233);
+    let clock = time::FakeClock::default();
+    let store = store::Store::from(near_store::db::TestDB::new());
+    let good = make_peer_id(&mut rng);
+    let bad = make_peer_id(&mut rng);
+    {
+        let rs = ReputationStore::new(store.clone());
+        rs.record(&clock.clock(), &good, ReputationEvent::UsefulData);
+        rs.record(&clock.clock(), &bad, ReputationEvent::Banned(ReasonForBan::Abusive));
+        rs.save(&clock.clock());
+    }
+    let rs = ReputationStore::new(store);
+    assert_eq!(rs.get(&clock.clock(), &good), 1.);
+    assert_eq!(rs.get(&clock.clock(), &bad), -100.);
+    // Decay continues from the time the scores were saved.
+    clock.advance(REPUTATION_HALF_LIFE);
+    assert_eq!(rs.get(&clock.clock(), &bad), -50.);
+}
@@ -146,7 +146,7 @@ impl ActorHandler {
     pub async fn send_outbound_connect(&self, peer_info: &PeerInfo, tier: tcp::Tier) {
         let addr = self.actix.addr.clone();
         let peer_info = peer_info.clone();
-        let stream = tcp::Stream::connect(&peer_info, tier).await.unwrap();
+        let stream = self.connect(&peer_info, tier).await.unwrap();
         addr.do_send(PeerManagerMessageRequest::OutboundTcpConnect(stream).with_span_context());
     }
 
@@ -158,8 +158,9 @@ impl ActorHandler {
         let addr = self.actix.addr.clone();
         let events = self.events.clone();
         let peer_info = peer_info.clone();
+        let stream = self.connect(&peer_info, tier);
         async move {
-            let stream = tcp::Stream::connect(&peer_info, tier).await.unwrap();
+            let stream = stream.await.unwrap();
             let mut events = events.from_now();
             let stream_id = stream.id();
             addr.do_send(PeerManagerMessageRequest::OutboundTcpConnect(stream).with_span_context());
@@ -179,6 +180,24 @@ impl ActorHandler {
         }
     }
 
+    /// Opens a connection to the peer, through the simulated network if the node is a part of one.
+    fn connect(
+        &self,
+        peer_info: &PeerInfo,
+        tier: tcp::Tier,
+    ) -> impl 'static + Send + Future<Output = anyhow::Result<tcp::Stream>> {
+        let simnet = self.cfg.simnet.clone().map(|simnet| {
+            simnet.connect(self.cfg.node_addr.as_ref().unwrap().ip(), peer_info, tier)
+        });
+        let peer_info = peer_info.clone();
+        async move {
+            match simnet {
+                Some(stream) => stream,
+                None => tcp::Stream::connect(&peer_info, tier).await,
+            }
+        }
+    }
+
     pub async fn with_state<R: 'static + Send, Fut: 'static + Send + Future<Output = R>>(
         &self,
         f: impl 'static + Send + FnOnce(Arc<NetworkState>) -> Fut,
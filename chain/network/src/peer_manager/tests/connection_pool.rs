@@ -2,6 +2,7 @@ use crate::network_protocol::testonly as data;
 use crate::network_protocol::PeerMessage;
 use crate::network_protocol::{Encoding, Handshake, OwnedAccount, PartialEdgeInfo};
 use crate::peer::peer_actor::ClosingReason;
+use crate::peer_filter::UpdatePeerFilter;
 use crate::peer_manager;
 use crate::peer_manager::connection;
 use crate::peer_manager::network_state::LIMIT_PENDING_PEERS;
@@ -14,6 +15,7 @@ use crate::testonly::stream::Stream;
 use near_async::time;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::views::PeerFilterRuleView;
 use std::sync::Arc;
 
 #[tokio::test]
@@ -103,6 +105,7 @@ async fn loop_connection() {
                 &pm.cfg.node_key,
             ),
             owned_account: None,
+            capabilities: 0,
         }))
         .await;
     let reason = events
@@ -171,6 +174,7 @@ async fn owned_account_mismatch() {
                 }
                 .sign(vc.signer.as_ref()),
             ),
+            capabilities: 0,
         }))
         .await;
     let reason = events
@@ -284,6 +288,7 @@ async fn invalid_edge() {
                     }
                     .sign(vc.signer.as_ref()),
                 ),
+                capabilities: 0,
             };
             let handshake = match tier {
                 tcp::Tier::T1 => PeerMessage::Tier1Handshake(handshake),
@@ -310,3 +315,51 @@ async fn invalid_edge() {
         }
     }
 }
+
+#[tokio::test]
+async fn peer_filter_ban() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    let pm = peer_manager::testonly::start(
+        clock.clock(),
+        near_store::db::TestDB::new(),
+        chain.make_config(rng),
+        chain.clone(),
+    )
+    .await;
+    let cfg = chain.make_config(rng);
+    let _peer = pm.start_inbound(chain.clone(), cfg.clone()).await.handshake(&clock.clock()).await;
+
+    tracing::info!(target:"test", "ban the connected peer");
+    let mut events = pm.events.from_now();
+    let rule = PeerFilterRuleView::BanPeer(cfg.node_id());
+    pm.actix
+        .addr
+        .send(UpdatePeerFilter::Add { rule: rule.clone(), ttl: None })
+        .await
+        .unwrap()
+        .unwrap();
+    events
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::ConnectionClosed(_)) => Some(()),
+            _ => None,
+        })
+        .await;
+
+    tracing::info!(target:"test", "the banned peer can't reconnect");
+    let reason = pm
+        .start_inbound(chain.clone(), cfg.clone())
+        .await
+        .manager_fail_handshake(&clock.clock())
+        .await;
+    assert_eq!(ClosingReason::RejectedByPeerManager(RegisterPeerError::Banned), reason);
+
+    tracing::info!(target:"test", "the peer can reconnect once the rule is removed");
+    let view = pm.actix.addr.send(UpdatePeerFilter::Remove(rule)).await.unwrap().unwrap();
+    assert!(view.entries.is_empty());
+    pm.start_inbound(chain.clone(), cfg.clone()).await.handshake(&clock.clock()).await;
+}
@@ -2,5 +2,6 @@ mod accounts_data;
 mod connection_pool;
 mod nonce;
 mod routing;
+mod simnet;
 mod tier1;
 mod tier2;
@@ -417,6 +417,58 @@ async fn ping_jump() {
     wait_for_pong(&mut pm0_ev, Pong { nonce: 0, source: id2.clone() }).await;
 }
 
+// test that a traced message records every hop and the trace is echoed back to the author
+#[tokio::test]
+async fn trace_route() {
+    abort_on_panic();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+
+    tracing::info!(target:"test", "start three nodes");
+    let pm0 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let pm1 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+    let pm2 = start_pm(clock.clock(), TestDB::new(), chain.make_config(rng), chain.clone()).await;
+
+    let id0 = pm0.cfg.node_id();
+    let id1 = pm1.cfg.node_id();
+    let id2 = pm2.cfg.node_id();
+
+    tracing::info!(target:"test", "connect nodes in a line");
+    pm0.connect_to(&pm1.peer_info(), tcp::Tier::T2).await;
+    pm1.connect_to(&pm2.peer_info(), tcp::Tier::T2).await;
+    pm0.wait_for_routing_table(&[
+        (id1.clone(), vec![id1.clone()]),
+        (id2.clone(), vec![id1.clone()]),
+    ])
+    .await;
+    pm2.wait_for_routing_table(&[
+        (id0.clone(), vec![id1.clone()]),
+        (id1.clone(), vec![id1.clone()]),
+    ])
+    .await;
+
+    tracing::info!(target:"test", "trace route from {id0} to {id2}");
+    let mut pm0_ev = pm0.events.from_now();
+    let target = id2.clone();
+    let clock_ = clock.clock();
+    let hash = pm0.with_state(move |s| async move { s.trace_route(&clock_, target) }).await;
+    pm0_ev
+        .recv_until(|ev| match ev {
+            Event::PeerManager(PME::RouteTraceEcho(h)) if h == hash => Some(()),
+            _ => None,
+        })
+        .await;
+
+    let trace = pm0
+        .with_state(move |s| async move { s.route_traces.lock().peek(&hash).unwrap().clone() })
+        .await;
+    assert_eq!(id2, trace.target);
+    let hops: Vec<_> = trace.trace.unwrap().hops.into_iter().map(|hop| hop.peer_id).collect();
+    assert_eq!(vec![id0, id1, id2], hops);
+}
+
 // test that ping over an indirect connection with ttl=2 is delivered
 #[tokio::test]
 async fn test_dont_drop_after_ttl() {
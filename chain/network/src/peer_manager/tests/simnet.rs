@@ -0,0 +1,99 @@
+use crate::broadcast;
+use crate::network_protocol::testonly as data;
+use crate::peer_manager::peer_manager_actor::Event as PME;
+use crate::peer_manager::testonly::start as start_pm;
+use crate::peer_manager::testonly::Event;
+use crate::tcp;
+use crate::testonly::simnet::{Change, LinkConfig, Simnet};
+use crate::testonly::{make_rng, Rng};
+use near_async::time;
+use near_o11y::testonly::init_test_logger;
+use near_store::db::TestDB;
+use rand::Rng as _;
+use std::sync::Arc;
+
+fn make_config(simnet: &Arc<Simnet>, rng: &mut Rng) -> crate::config::NetworkConfig {
+    let mut cfg = simnet.make_config(&rng.gen::<u64>().to_string());
+    cfg.outbound_disabled = true;
+    cfg
+}
+
+/// Advances the clock in small steps until the Pong with the given nonce arrives.
+/// Returns the (fake) time it took.
+async fn wait_for_pong(
+    clock: &time::FakeClock,
+    events: &mut broadcast::Receiver<Event>,
+    nonce: u64,
+) -> time::Duration {
+    let start = clock.now();
+    loop {
+        while let Some(ev) = events.try_recv() {
+            match ev {
+                Event::PeerManager(PME::Pong(pong)) if pong.nonce == nonce => {
+                    return clock.now() - start
+                }
+                _ => {}
+            }
+        }
+        clock.advance(time::Duration::milliseconds(10));
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn latency_and_partition() {
+    init_test_logger();
+    let mut rng = make_rng(921853233);
+    let rng = &mut rng;
+    let mut clock = time::FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, rng, 10));
+    let simnet = Simnet::new(clock.clock(), 4253);
+
+    let pm0 =
+        start_pm(clock.clock(), TestDB::new(), make_config(&simnet, rng), chain.clone()).await;
+    let pm1 =
+        start_pm(clock.clock(), TestDB::new(), make_config(&simnet, rng), chain.clone()).await;
+    let pm2 =
+        start_pm(clock.clock(), TestDB::new(), make_config(&simnet, rng), chain.clone()).await;
+    let id1 = pm1.cfg.node_id();
+    let id2 = pm2.cfg.node_id();
+
+    tracing::info!(target:"test", "connect nodes in a line");
+    pm0.connect_to(&pm1.peer_info(), tcp::Tier::T2).await;
+    pm1.connect_to(&pm2.peer_info(), tcp::Tier::T2).await;
+    pm0.wait_for_routing_table(&[
+        (id1.clone(), vec![id1.clone()]),
+        (id2.clone(), vec![id1.clone()]),
+    ])
+    .await;
+
+    tracing::info!(target:"test", "ping {id2} over links with latency");
+    let latency = time::Duration::milliseconds(100);
+    simnet.apply(Change::SetDefaultLink(LinkConfig { latency, ..LinkConfig::default() }));
+    let mut pm0_ev = pm0.events.from_now();
+    pm0.send_ping(&clock.clock(), 0, id2.clone()).await;
+    // Ping and Pong take 2 hops each.
+    assert!(wait_for_pong(&clock, &mut pm0_ev, 0).await >= latency * 4);
+
+    tracing::info!(target:"test", "partition {id2} from the other nodes");
+    let mut pm1_ev = pm1.events.from_now();
+    let mut pm2_ev = pm2.events.from_now();
+    simnet.apply(Change::Partition(vec![vec![pm2.cfg.node_addr.unwrap().ip()]]));
+    for events in [&mut pm1_ev, &mut pm2_ev] {
+        events
+            .recv_until(|ev| match ev {
+                Event::PeerManager(PME::ConnectionClosed(ev)) => Some(ev),
+                _ => None,
+            })
+            .await;
+    }
+    let peer_info = pm2.peer_info();
+    let res = pm1
+        .with_state(|s| async move { s.connect(&peer_info, tcp::Tier::T2).await.is_err() })
+        .await;
+    assert!(res);
+
+    tracing::info!(target:"test", "heal the partition");
+    simnet.apply(Change::Heal);
+    pm1.connect_to(&pm2.peer_info(), tcp::Tier::T2).await;
+}
@@ -0,0 +1,165 @@
+//! Mapping of the listening port on the NAT gateway of the local network.
+//!
+//! A node behind a NAT (e.g. a validator run at home) can open outbound connections, but it
+//! can't accept inbound ones, which makes it unusable as its own TIER1 proxy. If configured,
+//! the node asks the gateway to forward the listening TCP port to it, over UPnP IGD or NAT-PMP.
+//! The gateways drop the mappings once their lease expires, so the mapping is renewed
+//! periodically. The external address of the node reported by the gateway is then used
+//! instead of querying the STUN servers, see `config::ValidatorProxies::Dynamic`.
+use near_async::time;
+
+/// Interval between the renewals of the mapping.
+pub(crate) const RENEW_INTERVAL: time::Duration = time::Duration::minutes(20);
+
+/// Protocol used to map the port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Upnp,
+    NatPmp,
+}
+
+/// Lease of the mapping, in seconds. Long enough to survive a few failed renewals.
+#[cfg(feature = "port_mapping")]
+const LEASE_SECONDS: u32 = 3600;
+#[cfg(feature = "port_mapping")]
+const DESCRIPTION: &str = "neard";
+
+/// Maps TCP `port` of the gateway to the same port of this node.
+/// Returns the external address of the node.
+#[cfg(feature = "port_mapping")]
+pub(crate) async fn map_port(
+    protocol: Protocol,
+    port: u16,
+) -> anyhow::Result<std::net::SocketAddr> {
+    match protocol {
+        Protocol::Upnp => map_port_upnp(port).await,
+        Protocol::NatPmp => map_port_natpmp(port).await,
+    }
+}
+
+#[cfg(feature = "port_mapping")]
+async fn map_port_upnp(port: u16) -> anyhow::Result<std::net::SocketAddr> {
+    use anyhow::Context as _;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    let gateway = igd_next::aio::tokio::search_gateway(Default::default())
+        .await
+        .context("search_gateway()")?;
+    // UPnP requires the address of the node in the local network. Find it out by checking
+    // which interface would be used to reach the gateway.
+    let unspecified: SocketAddr = match gateway.addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = tokio::net::UdpSocket::bind(unspecified).await?;
+    socket.connect(gateway.addr).await?;
+    let local_addr = SocketAddr::new(socket.local_addr()?.ip(), port);
+    gateway
+        .add_port(igd_next::PortMappingProtocol::TCP, port, local_addr, LEASE_SECONDS, DESCRIPTION)
+        .await
+        .context("add_port()")?;
+    let ip = gateway.get_external_ip().await.context("get_external_ip()")?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+#[cfg(feature = "port_mapping")]
+const NATPMP_PORT: u16 = 5351;
+/// Number of attempts of a NAT-PMP request. The timeout starts at 250ms and doubles after
+/// every attempt, as RFC 6886 recommends, but gives up earlier than the 9 attempts it allows.
+#[cfg(feature = "port_mapping")]
+const NATPMP_ATTEMPTS: usize = 4;
+
+/// NAT-PMP (RFC 6886) is simple enough not to need a library: a request is a single UDP
+/// datagram sent to the default gateway. The gateway is read from the kernel routing table,
+/// so NAT-PMP is only supported on Linux.
+#[cfg(feature = "port_mapping")]
+async fn map_port_natpmp(port: u16) -> anyhow::Result<std::net::SocketAddr> {
+    use anyhow::Context as _;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    let routes = std::fs::read_to_string("/proc/net/route").context("read /proc/net/route")?;
+    let gateway = parse_default_gateway(&routes).context("no default gateway")?;
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect((gateway, NATPMP_PORT)).await?;
+
+    // Version 0, opcode 0: external address request.
+    let resp = natpmp_request(&socket, &[0, 0], 12).await.context("public address request")?;
+    let ip = Ipv4Addr::new(resp[8], resp[9], resp[10], resp[11]);
+
+    // Version 0, opcode 2: TCP mapping request, followed by 2 reserved bytes, the internal
+    // port, the suggested external port and the lease.
+    let mut req = vec![0, 2, 0, 0];
+    req.extend_from_slice(&port.to_be_bytes());
+    req.extend_from_slice(&port.to_be_bytes());
+    req.extend_from_slice(&LEASE_SECONDS.to_be_bytes());
+    let resp = natpmp_request(&socket, &req, 16).await.context("port mapping request")?;
+    // The gateway may assign a different external port than requested.
+    let external_port = u16::from_be_bytes([resp[10], resp[11]]);
+    Ok(SocketAddr::new(ip.into(), external_port))
+}
+
+/// Sends a NAT-PMP request and waits for the response of at least `resp_len` bytes,
+/// retrying on timeouts. Fails if the gateway reports an error.
+#[cfg(feature = "port_mapping")]
+async fn natpmp_request(
+    socket: &tokio::net::UdpSocket,
+    req: &[u8],
+    resp_len: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut timeout = std::time::Duration::from_millis(250);
+    let mut buf = [0u8; 16];
+    for _ in 0..NATPMP_ATTEMPTS {
+        socket.send(req).await?;
+        let Ok(n) = tokio::time::timeout(timeout, socket.recv(&mut buf)).await else {
+            timeout *= 2;
+            continue;
+        };
+        let resp = &buf[..n?];
+        // Responses have the opcode of the request plus 128.
+        anyhow::ensure!(
+            resp.len() >= resp_len && resp[0] == 0 && resp[1] == 128 + req[1],
+            "malformed response {resp:?}"
+        );
+        let result_code = u16::from_be_bytes([resp[2], resp[3]]);
+        anyhow::ensure!(result_code == 0, "result code {result_code}");
+        return Ok(resp.to_vec());
+    }
+    anyhow::bail!("no response from the gateway")
+}
+
+/// Finds the IPv4 default gateway in the contents of `/proc/net/route`, which lists the
+/// addresses as hex numbers in the host byte order.
+#[cfg(feature = "port_mapping")]
+fn parse_default_gateway(routes: &str) -> Option<std::net::Ipv4Addr> {
+    const RTF_GATEWAY: u16 = 0x2;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_iface, destination, gateway, flags, ..] = fields[..] else {
+            return None;
+        };
+        let flags = u16::from_str_radix(flags, 16).ok()?;
+        if destination != "00000000" || flags & RTF_GATEWAY == 0 {
+            return None;
+        }
+        let gateway = u32::from_str_radix(gateway, 16).ok()?;
+        Some(gateway.to_ne_bytes().into())
+    })
+}
+
+// The example routing table is from a little endian host.
+#[cfg(all(test, feature = "port_mapping", target_endian = "little"))]
+mod tests {
+    #[test]
+    fn parse_default_gateway() {
+        let routes = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0
+";
+        assert_eq!(super::parse_default_gateway(routes), Some([192, 168, 1, 1].into()));
+        let without_default_route: String =
+            routes.lines().take(2).map(|l| l.to_owned() + "\n").collect();
+        assert_eq!(super::parse_default_gateway(&without_default_route), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! QUIC transport for the peer connections.
+//!
+//! A node with QUIC enabled accepts QUIC connections on the UDP port with the same number
+//! as its TCP listener port and advertises it in its Handshake. Connections to the peers which
+//! advertised QUIC are opened over QUIC, falling back to TCP if that fails.
+//!
+//! The connecting node opens a single bidirectional stream, on which messages are framed just
+//! like on TCP: each is prefixed with its length. Messages are therefore received in the order
+//! they were sent, which the peer protocol relies on (e.g. nothing may arrive before the
+//! Handshake). Compared to TCP, QUIC connections survive a change of the address of the
+//! connecting node (connection migration) and recover from packet loss faster.
+//!
+//! TLS is used only for encryption: peers are authenticated by the Handshake, just like on TCP,
+//! so the certificates are self-signed and not verified.
+use crate::network_protocol::PeerInfo;
+use crate::tcp;
+use anyhow::{anyhow, Context as _};
+use std::io;
+use std::sync::Arc;
+
+/// ALPN protocol identifying the NEAR peer connections.
+const ALPN_PROTOCOL: &[u8] = b"near";
+/// Name the certificates are issued for. It is not verified.
+const SERVER_NAME: &str = "near";
+/// Same as the timeout of establishing a TCP connection, see `tcp::Stream::connect`. Like the
+/// TCP one, the QUIC handshake takes a single round trip, and if it doesn't complete in time
+/// (e.g. because UDP is blocked) the connection falls back to TCP, so a longer timeout would
+/// only delay connecting to such peers.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+/// The connecting node opens the stream by sending its Handshake right after connecting, so an
+/// inbound connection without a stream after this long is dropped.
+const ACCEPT_STREAM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const KEEP_ALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const MAX_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn transport_config() -> Arc<quinn::TransportConfig> {
+    let mut config = quinn::TransportConfig::default();
+    config.max_concurrent_uni_streams(0u32.into());
+    config.max_concurrent_bidi_streams(1u32.into());
+    config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    config.max_idle_timeout(Some(MAX_IDLE_TIMEOUT.try_into().unwrap()));
+    Arc::new(config)
+}
+
+/// Accepts any server certificate, see the module documentation.
+struct SkipServerVerification;
+
+impl rustls::client::ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+/// QUIC endpoint accepting the inbound connections and opening the outbound ones.
+pub(crate) struct Endpoint(quinn::Endpoint);
+
+impl Endpoint {
+    /// Binds the endpoint to the UDP `addr`.
+    pub fn new(addr: std::net::SocketAddr) -> anyhow::Result<Self> {
+        let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+        let key = rustls::PrivateKey(cert.serialize_private_key_der());
+        let cert = rustls::Certificate(cert.serialize_der()?);
+
+        let mut server_crypto = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)?;
+        server_crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(server_crypto));
+        server_config.transport_config(transport_config()).migration(true);
+
+        let mut client_crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+            .with_no_client_auth();
+        client_crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+        let mut client_config = quinn::ClientConfig::new(Arc::new(client_crypto));
+        client_config.transport_config(transport_config());
+
+        let mut endpoint =
+            quinn::Endpoint::server(server_config, addr).context("quinn::Endpoint::server()")?;
+        endpoint.set_default_client_config(client_config);
+        Ok(Self(endpoint))
+    }
+
+    #[cfg(test)]
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.0.local_addr()
+    }
+
+    pub async fn connect(
+        &self,
+        peer_info: &PeerInfo,
+        tier: tcp::Tier,
+    ) -> anyhow::Result<tcp::Stream> {
+        let addr =
+            peer_info.addr.ok_or(anyhow!("Trying to connect to peer with no public address"))?;
+        let conn = tokio::time::timeout(CONNECT_TIMEOUT, self.0.connect(addr, SERVER_NAME)?)
+            .await?
+            .context("quinn::Connecting")?;
+        // Opening a stream doesn't wait for the peer; it learns about the stream once the
+        // Handshake is sent on it.
+        let (send, recv) = conn.open_bi().await.context("quinn::Connection::open_bi()")?;
+        Ok(tcp::Stream::new_quic(
+            Connection { conn, send, recv },
+            self.0.local_addr()?,
+            tcp::StreamType::Outbound { peer_id: peer_info.id.clone(), tier },
+        ))
+    }
+
+    /// Waits for the next inbound connection. Returns None once the endpoint is closed.
+    /// The connection has to be established with `Incoming::accept`, which should be done
+    /// concurrently with accepting the following connections.
+    pub async fn accept(&self) -> Option<Incoming> {
+        let connecting = self.0.accept().await?;
+        Some(Incoming { connecting, local_addr: self.0.local_addr() })
+    }
+}
+
+/// Inbound connection which is not established yet.
+pub(crate) struct Incoming {
+    connecting: quinn::Connecting,
+    local_addr: io::Result<std::net::SocketAddr>,
+}
+
+impl Incoming {
+    /// Completes the QUIC handshake and waits for the peer to open the stream.
+    pub async fn accept(self) -> anyhow::Result<tcp::Stream> {
+        let conn = tokio::time::timeout(CONNECT_TIMEOUT, self.connecting)
+            .await?
+            .context("quinn::Connecting")?;
+        let (send, recv) = tokio::time::timeout(ACCEPT_STREAM_TIMEOUT, conn.accept_bi())
+            .await?
+            .context("quinn::Connection::accept_bi()")?;
+        Ok(tcp::Stream::new_quic(
+            Connection { conn, send, recv },
+            self.local_addr?,
+            tcp::StreamType::Inbound,
+        ))
+    }
+}
+
+/// Established QUIC connection together with its only stream.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    pub conn: quinn::Connection,
+    pub send: quinn::SendStream,
+    pub recv: quinn::RecvStream,
+}
@@ -214,6 +214,7 @@ fn new_handshake(
         },
         partial_edge_info: PartialEdgeInfo::new(my_peer_id, target_peer_id, nonce, secret_key),
         owned_account: None,
+        capabilities: 0,
     })
 }
 
@@ -369,7 +370,7 @@ impl Connection {
             }
             DirectMessage::BlockRequest(h) => PeerMessage::BlockRequest(h),
             DirectMessage::Block(b) => PeerMessage::Block(b),
-            DirectMessage::BlockHeadersRequest(h) => PeerMessage::BlockHeadersRequest(h),
+            DirectMessage::BlockHeadersRequest(h) => PeerMessage::BlockHeadersRequest(h, None),
             DirectMessage::BlockHeaders(h) => PeerMessage::BlockHeaders(h),
             DirectMessage::StateRequestHeader(shard_id, sync_hash) => {
                 PeerMessage::StateRequestHeader(shard_id, sync_hash)
@@ -486,7 +487,7 @@ impl Connection {
                 PeerMessage::Block(b) => {
                     return Ok((Message::Direct(DirectMessage::Block(b)), timestamp));
                 }
-                PeerMessage::BlockHeadersRequest(hashes) => {
+                PeerMessage::BlockHeadersRequest(hashes, _) => {
                     return Ok((
                         Message::Direct(DirectMessage::BlockHeadersRequest(hashes)),
                         timestamp,
@@ -540,13 +541,13 @@ impl PeerStream {
         let mut msg = msg.serialize(Encoding::Proto);
         let mut buf = (msg.len() as u32).to_le_bytes().to_vec();
         buf.append(&mut msg);
-        self.stream.stream.write_all(&buf).await
+        self.stream.stream.tcp_mut().write_all(&buf).await
     }
 
     async fn do_read(&mut self) -> io::Result<()> {
         let n = tokio::time::timeout(
             self.recv_timeout.try_into().unwrap(),
-            self.stream.stream.read_buf(&mut self.buf),
+            self.stream.stream.tcp_mut().read_buf(&mut self.buf),
         )
         .await??;
         tracing::trace!(target: "network", "Read {} bytes from {:?}", n, self.stream.peer_addr);
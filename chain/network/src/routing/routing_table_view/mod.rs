@@ -25,12 +25,17 @@ struct Inner {
 
 impl Inner {
     /// Select a connected peer on some shortest path to `peer_id`.
-    /// If there are several such peers, pick the least recently used one.
-    fn find_next_hop(&mut self, peer_id: &PeerId) -> Result<PeerId, FindRouteError> {
+    /// If there are several such peers, pick the least recently used one,
+    /// preferring the peers for which `avoid` returns false.
+    fn find_next_hop(
+        &mut self,
+        peer_id: &PeerId,
+        avoid: impl Fn(&PeerId) -> bool,
+    ) -> Result<PeerId, FindRouteError> {
         let peers = self.next_hops.get(peer_id).ok_or(FindRouteError::PeerUnreachable)?;
         let next_hop = peers
             .iter()
-            .min_by_key(|p| self.last_routed.get(*p).copied().unwrap_or(0))
+            .min_by_key(|p| (avoid(p), self.last_routed.get(*p).copied().unwrap_or(0)))
             .ok_or(FindRouteError::PeerUnreachable)?;
         self.last_routed.put(next_hop.clone(), self.find_route_calls);
         self.find_route_calls += 1;
@@ -65,12 +70,14 @@ impl RoutingTableView {
     }
 
     // Given a PeerId to which we wish to route a message, returns the first hop on a
-    // route to the target. If no route is known, produces FindRouteError.
+    // route to the target. Hops for which `avoid` returns true are only selected if
+    // there are no other hops. If no route is known, produces FindRouteError.
     pub(crate) fn find_next_hop_for_target(
         &self,
         target: &PeerId,
+        avoid: impl Fn(&PeerId) -> bool,
     ) -> Result<PeerId, FindRouteError> {
-        self.0.lock().find_next_hop(target)
+        self.0.lock().find_next_hop(target, avoid)
     }
 
     pub(crate) fn view_route(&self, peer_id: &PeerId) -> Option<Vec<PeerId>> {
@@ -23,7 +23,27 @@ fn find_route() {
     rtv.update(next_hops.clone());
     for _ in 0..1000 {
         let p = peers.choose(rng).unwrap();
-        let got = rtv.find_next_hop_for_target(&p).unwrap();
+        let got = rtv.find_next_hop_for_target(&p, |_| false).unwrap();
         assert!(next_hops.get(p).unwrap().contains(&got));
     }
 }
+
+#[test]
+fn find_route_avoiding_peers() {
+    let mut rng = make_rng(385305732);
+    let rng = &mut rng;
+
+    let target = data::make_peer_id(rng);
+    let good = data::make_peer_id(rng);
+    let bad = data::make_peer_id(rng);
+    let mut next_hops = routing::NextHopTable::new();
+    next_hops.insert(target.clone(), vec![good.clone(), bad.clone()]);
+
+    // Avoided peers are selected only if there's no other next hop.
+    let rtv = RoutingTableView::new();
+    rtv.update(Arc::new(next_hops));
+    for _ in 0..10 {
+        assert_eq!(rtv.find_next_hop_for_target(&target, |p| p == &bad).unwrap(), good);
+    }
+    assert_eq!(rtv.find_next_hop_for_target(&target, |_| true).unwrap(), bad);
+}
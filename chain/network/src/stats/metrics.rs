@@ -184,6 +184,23 @@ pub(crate) static PEER_MESSAGE_SENT_BY_TYPE_BYTES: Lazy<IntCounterVec> = Lazy::n
     )
     .unwrap()
 });
+pub(crate) static PEER_MESSAGE_COMPRESSION_SAVED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_message_compression_saved_bytes",
+        "Number of bytes saved by compressing the messages sent to peers, by message types",
+        &["type"],
+    )
+    .unwrap()
+});
+pub(crate) static PEER_MESSAGE_COMPRESSION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_peer_message_compression_time",
+        "Time spent on compressing and decompressing the messages exchanged with peers",
+        &["operation"],
+        Some(exponential_buckets(0.00001, 2., 16).unwrap()),
+    )
+    .unwrap()
+});
 pub(crate) static PEER_MESSAGE_SENT_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_peer_message_sent_by_type_total",
@@ -192,6 +209,60 @@ pub(crate) static PEER_MESSAGE_SENT_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Lazy::n
     )
     .unwrap()
 });
+pub(crate) static PEER_BANDWIDTH_BYTES_PER_SEC: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_peer_bandwidth_bytes_per_sec",
+        "Average bandwidth used by a connected peer over the last minute",
+        &["peer_id", "tier", "direction"],
+    )
+    .unwrap()
+});
+pub(crate) static PEER_REPUTATION_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_reputation_events_total",
+        "Number of events changing the reputation of peers, by event type",
+        &["event"],
+    )
+    .unwrap()
+});
+pub(crate) static REJECTED_INBOUND_CONNECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_rejected_inbound_connections_total",
+        "Number of inbound connections rejected before or during the handshake, by reason",
+        &["reason"],
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_PROBES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_tier1_probes_total",
+        "Number of pings sent to measure the health of TIER1 connections, by result",
+        &["result"],
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_PROBE_RTT: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "near_tier1_probe_rtt_seconds",
+        "Round trip time of the pings sent over TIER1 connections",
+        exponential_buckets(0.001, 2., 14).unwrap(),
+    )
+    .unwrap()
+});
+pub(crate) static TIER1_DEGRADED_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_tier1_degraded_connections",
+        "Number of TIER1 connections which are not used because of high latency or loss",
+    )
+    .unwrap()
+});
+pub(crate) static PORT_MAPPED: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_port_mapped",
+        "Whether the listening port is currently mapped on the NAT gateway",
+    )
+    .unwrap()
+});
 pub(crate) static SYNC_ACCOUNTS_DATA: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_sync_accounts_data",
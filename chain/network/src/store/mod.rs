@@ -2,7 +2,7 @@
 /// All transactions should be implemented within this module,
 /// in particular schema::StoreUpdate is not exported.
 use crate::network_protocol::Edge;
-use crate::types::ConnectionInfo;
+use crate::types::{AddrQuality, ConnectionInfo, PeerReputation};
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::AccountId;
 use std::collections::HashSet;
@@ -137,6 +137,32 @@ impl Store {
     }
 }
 
+// ReputationStore storage.
+impl Store {
+    pub fn set_peer_reputations(&mut self, reputations: &Vec<PeerReputation>) -> Result<(), Error> {
+        let mut update = self.0.new_update();
+        update.set::<schema::PeerReputation>(&(), &reputations);
+        self.0.commit(update).map_err(Error)
+    }
+
+    pub fn get_peer_reputations(&self) -> Vec<PeerReputation> {
+        self.0.get::<schema::PeerReputation>(&()).unwrap_or(Some(vec![])).unwrap_or(vec![])
+    }
+}
+
+// PeerStore storage.
+impl Store {
+    pub fn set_addr_qualities(&mut self, qualities: &Vec<AddrQuality>) -> Result<(), Error> {
+        let mut update = self.0.new_update();
+        update.set::<schema::PeerAddrQuality>(&(), &qualities);
+        self.0.commit(update).map_err(Error)
+    }
+
+    pub fn get_addr_qualities(&self) -> Vec<AddrQuality> {
+        self.0.get::<schema::PeerAddrQuality>(&()).unwrap_or(Some(vec![])).unwrap_or(vec![])
+    }
+}
+
 impl From<Arc<dyn near_store::db::Database>> for Store {
     fn from(store: Arc<dyn near_store::db::Database>) -> Self {
         Self(schema::Store::from(store))
@@ -59,6 +59,67 @@ impl BorshRepr for ConnectionInfoRepr {
     }
 }
 
+/// A Borsh representation of the primitives::PeerReputation.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(super) struct PeerReputationRepr {
+    peer_id: PeerId,
+    score: f64,
+    /// UNIX timestamp in nanos.
+    updated_at: u64,
+}
+
+impl BorshRepr for PeerReputationRepr {
+    type T = primitives::PeerReputation;
+    fn to_repr(s: &primitives::PeerReputation) -> Self {
+        Self {
+            peer_id: s.peer_id.clone(),
+            score: s.score,
+            updated_at: s.updated_at.unix_timestamp_nanos() as u64,
+        }
+    }
+
+    fn from_repr(s: Self) -> Result<primitives::PeerReputation, Error> {
+        Ok(primitives::PeerReputation {
+            peer_id: s.peer_id,
+            score: s.score,
+            updated_at: time::Utc::from_unix_timestamp_nanos(s.updated_at as i128)
+                .map_err(invalid_data)?,
+        })
+    }
+}
+
+/// A Borsh representation of the primitives::AddrQuality.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(super) struct AddrQualityRepr {
+    addr: std::net::SocketAddr,
+    successes: u32,
+    failures: u32,
+    /// UNIX timestamp in nanos.
+    last_attempt: u64,
+}
+
+impl BorshRepr for AddrQualityRepr {
+    type T = primitives::AddrQuality;
+    fn to_repr(s: &primitives::AddrQuality) -> Self {
+        Self {
+            addr: s.addr,
+            successes: s.successes,
+            failures: s.failures,
+            last_attempt: s.last_attempt.unix_timestamp_nanos() as u64,
+        }
+    }
+
+    fn from_repr(s: Self) -> Result<primitives::AddrQuality, Error> {
+        Ok(primitives::AddrQuality {
+            addr: s.addr,
+            successes: s.successes,
+            failures: s.failures,
+            last_attempt: time::Utc::from_unix_timestamp_nanos(s.last_attempt as i128)
+                .map_err(invalid_data)?,
+        })
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub(super) struct EdgeRepr {
     key: (PeerId, PeerId),
@@ -103,6 +164,20 @@ impl Column for RecentOutboundConnections {
     type Value = Vec<ConnectionInfoRepr>;
 }
 
+pub(super) struct PeerReputation;
+impl Column for PeerReputation {
+    const COL: DBCol = DBCol::PeerReputation;
+    type Key = Borsh<()>;
+    type Value = Vec<PeerReputationRepr>;
+}
+
+pub(super) struct PeerAddrQuality;
+impl Column for PeerAddrQuality {
+    const COL: DBCol = DBCol::PeerAddrQuality;
+    type Key = Borsh<()>;
+    type Value = Vec<AddrQualityRepr>;
+}
+
 pub(super) struct PeerComponent;
 impl Column for PeerComponent {
     const COL: DBCol = DBCol::PeerComponent;
@@ -33,9 +33,35 @@ pub(crate) enum StreamType {
     Outbound { peer_id: PeerId, tier: Tier },
 }
 
+/// Connection underlying a Stream.
+#[derive(Debug)]
+pub(crate) enum Transport {
+    Tcp(tokio::net::TcpStream),
+    /// See crate::quic for how messages are sent over QUIC.
+    #[cfg(feature = "quic")]
+    Quic(crate::quic::Connection),
+    /// See crate::testonly::simnet.
+    #[cfg(test)]
+    Sim(crate::testonly::simnet::Link),
+}
+
+impl Transport {
+    /// Returns the underlying TCP stream, for the code which reads and writes raw bytes, and
+    /// hence only connects over TCP.
+    pub(crate) fn tcp_mut(&mut self) -> &mut tokio::net::TcpStream {
+        match self {
+            Self::Tcp(stream) => stream,
+            #[cfg(feature = "quic")]
+            Self::Quic(_) => panic!("not a TCP stream"),
+            #[cfg(test)]
+            Self::Sim(_) => panic!("not a TCP stream"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Stream {
-    pub(crate) stream: tokio::net::TcpStream,
+    pub(crate) stream: Transport,
     pub(crate) type_: StreamType,
     /// cached stream.local_addr()
     pub(crate) local_addr: std::net::SocketAddr,
@@ -79,7 +105,26 @@ impl Socket {
 
 impl Stream {
     fn new(stream: tokio::net::TcpStream, type_: StreamType) -> std::io::Result<Self> {
-        Ok(Self { peer_addr: stream.peer_addr()?, local_addr: stream.local_addr()?, stream, type_ })
+        Ok(Self {
+            peer_addr: stream.peer_addr()?,
+            local_addr: stream.local_addr()?,
+            stream: Transport::Tcp(stream),
+            type_,
+        })
+    }
+
+    #[cfg(feature = "quic")]
+    pub(crate) fn new_quic(
+        conn: crate::quic::Connection,
+        local_addr: std::net::SocketAddr,
+        type_: StreamType,
+    ) -> Self {
+        Self {
+            peer_addr: conn.conn.remote_address(),
+            local_addr,
+            stream: Transport::Quic(conn),
+            type_,
+        }
     }
 
     pub async fn connect(peer_info: &PeerInfo, tier: Tier) -> anyhow::Result<Stream> {
@@ -191,7 +236,11 @@ impl ListenerAddr {
 
     /// Constructs a std::net::TcpListener, for usage outside of near_network.
     pub fn std_listener(&self) -> std::io::Result<std::net::TcpListener> {
-        self.listener()?.0.into_std()
+        match self.listener()? {
+            Listener::Tcp(listener) => listener.into_std(),
+            #[cfg(test)]
+            Listener::Sim(_) => unreachable!(),
+        }
     }
 
     /// Constructs a Listener out of ListenerAddr.
@@ -205,15 +254,26 @@ impl ListenerAddr {
         }
         socket.set_reuseaddr(true)?;
         socket.bind(self.0)?;
-        Ok(Listener(socket.listen(LISTENER_BACKLOG)?))
+        Ok(Listener::Tcp(socket.listen(LISTENER_BACKLOG)?))
     }
 }
 
-pub(crate) struct Listener(tokio::net::TcpListener);
+pub(crate) enum Listener {
+    Tcp(tokio::net::TcpListener),
+    /// See crate::testonly::simnet.
+    #[cfg(test)]
+    Sim(crate::testonly::simnet::Listener),
+}
 
 impl Listener {
     pub async fn accept(&mut self) -> std::io::Result<Stream> {
-        let (stream, _) = self.0.accept().await?;
-        Stream::new(stream, StreamType::Inbound)
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Stream::new(stream, StreamType::Inbound)
+            }
+            #[cfg(test)]
+            Self::Sim(listener) => listener.accept().await,
+        }
     }
 }
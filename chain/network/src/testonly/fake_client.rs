@@ -6,6 +6,7 @@ use crate::types::{NetworkInfo, ReasonForBan, StateResponseInfoV2};
 use near_async::messaging;
 use near_primitives::block::{Approval, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::{ChunkHash, PartialEncodedChunkPart};
@@ -25,6 +26,8 @@ pub enum Event {
     Challenge(Challenge),
     Chunk(Vec<PartialEncodedChunkPart>),
     ChunkRequest(ChunkHash),
+    EpochSyncRequest(EpochId),
+    EpochSyncResponse(Box<EpochSyncProof>),
     Transaction(SignedTransaction),
 }
 
@@ -86,7 +89,11 @@ impl client::Client for Fake {
         None
     }
 
-    async fn block_headers_request(&self, hashes: Vec<CryptoHash>) -> Option<Vec<BlockHeader>> {
+    async fn block_headers_request(
+        &self,
+        hashes: Vec<CryptoHash>,
+        _max_headers: Option<u64>,
+    ) -> Option<Vec<BlockHeader>> {
         self.event_sink.push(Event::BlockHeadersRequest(hashes));
         None
     }
@@ -108,6 +115,20 @@ impl client::Client for Fake {
         self.event_sink.push(Event::Challenge(challenge));
     }
 
+    async fn epoch_sync_request(&self, prev_epoch_id: EpochId) -> Option<Box<EpochSyncProof>> {
+        self.event_sink.push(Event::EpochSyncRequest(prev_epoch_id));
+        None
+    }
+
+    async fn epoch_sync_response(
+        &self,
+        proof: EpochSyncProof,
+        _peer_id: PeerId,
+    ) -> Result<(), ReasonForBan> {
+        self.event_sink.push(Event::EpochSyncResponse(Box::new(proof)));
+        Ok(())
+    }
+
     async fn network_info(&self, _info: NetworkInfo) {}
 
     async fn announce_account(
@@ -0,0 +1,319 @@
+//! Simulated network, which connects in-process nodes (PeerManagerActors) over links with
+//! programmable latency, jitter, bandwidth and loss. The network can also be partitioned,
+//! either directly or according to a schedule, see `Simnet::run_schedule`.
+//!
+//! Every node gets a distinct IP address in the simulated network. A node joins the network
+//! if `NetworkConfig::simnet` is set: it then listens and connects through the simulator
+//! instead of TCP, see `NetworkState::listen` and `NetworkState::connect`.
+//!
+//! Delivery times of the messages are computed from the clock and a seeded rng, so with a
+//! FakeClock a scenario is reproducible, as long as the nodes send the same messages.
+//! Like TCP, the connections are reliable and ordered: a lost message is retransmitted,
+//! which delays the messages sent after it. Establishing a connection is instant.
+use crate::config;
+use crate::network_protocol::PeerInfo;
+use crate::tcp;
+use crate::testonly::{make_rng, Rng};
+use anyhow::{anyhow, Context as _};
+use near_async::time;
+use parking_lot::Mutex;
+use rand::Rng as _;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+mod tests;
+
+/// Port on which all the simulated nodes listen.
+const LISTENER_PORT: u16 = 24567;
+/// First port assigned to the outbound connections.
+const FIRST_OUTBOUND_PORT: u16 = 30000;
+/// Delay after which a lost message is retransmitted.
+pub(crate) const RETRANSMISSION_TIMEOUT: time::Duration = time::Duration::milliseconds(200);
+
+/// Properties of the link from one node to another.
+#[derive(Clone, Debug, Default)]
+pub struct LinkConfig {
+    /// Time it takes a message to reach the other end.
+    pub latency: time::Duration,
+    /// Maximal random delay added to the latency of a message.
+    pub jitter: time::Duration,
+    /// Throughput in bytes per second, unlimited if None.
+    pub bandwidth: Option<u64>,
+    /// Probability that a message is lost (and retransmitted).
+    pub loss: f64,
+}
+
+/// Change of the simulated network.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Sets the config of the link from one node to another.
+    SetLink { from: IpAddr, to: IpAddr, link: LinkConfig },
+    /// Sets the config of the links which are not set explicitly.
+    SetDefaultLink(LinkConfig),
+    /// Partitions the network into the given groups and the group of all the other nodes.
+    /// Nodes from different groups can't connect and their connections are closed.
+    Partition(Vec<Vec<IpAddr>>),
+    /// Removes the partition.
+    Heal,
+}
+
+struct Inner {
+    rng: Rng,
+    next_ip: u32,
+    next_outbound_port: u16,
+    listeners: HashMap<SocketAddr, mpsc::UnboundedSender<tcp::Stream>>,
+    default_link: LinkConfig,
+    links: HashMap<(IpAddr, IpAddr), LinkConfig>,
+    /// Group of the partition, for the nodes outside of the default group.
+    groups: HashMap<IpAddr, usize>,
+}
+
+impl Inner {
+    fn partitioned(&self, a: IpAddr, b: IpAddr) -> bool {
+        self.groups.get(&a) != self.groups.get(&b)
+    }
+}
+
+pub struct Simnet {
+    clock: time::Clock,
+    inner: Mutex<Inner>,
+    /// Incremented whenever the network is partitioned, so that the connections between
+    /// the partitioned nodes can be closed.
+    partitions: watch::Sender<u64>,
+}
+
+impl fmt::Debug for Simnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Simnet")
+    }
+}
+
+impl Simnet {
+    pub fn new(clock: time::Clock, seed: u64) -> Arc<Self> {
+        Arc::new(Self {
+            clock,
+            inner: Mutex::new(Inner {
+                rng: make_rng(seed),
+                next_ip: 1,
+                next_outbound_port: FIRST_OUTBOUND_PORT,
+                listeners: HashMap::new(),
+                default_link: LinkConfig::default(),
+                links: HashMap::new(),
+                groups: HashMap::new(),
+            }),
+            partitions: watch::channel(0).0,
+        })
+    }
+
+    /// Assigns an address to a new node.
+    pub fn new_addr(&self) -> tcp::ListenerAddr {
+        let mut inner = self.inner.lock();
+        let ip = Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 0, 0, 0)) + inner.next_ip);
+        inner.next_ip += 1;
+        tcp::ListenerAddr::new(SocketAddr::new(ip.into(), LISTENER_PORT))
+    }
+
+    /// Config of a new node of the simulated network, with given seed used for peer id.
+    pub fn make_config(self: &Arc<Self>, seed: &str) -> config::NetworkConfig {
+        let mut cfg = config::NetworkConfig::from_seed(seed, self.new_addr());
+        cfg.simnet = Some(self.clone());
+        cfg
+    }
+
+    pub fn apply(&self, change: Change) {
+        tracing::debug!(target: "test", ?change, "simnet");
+        let mut inner = self.inner.lock();
+        match change {
+            Change::SetLink { from, to, link } => {
+                inner.links.insert((from, to), link);
+            }
+            Change::SetDefaultLink(link) => inner.default_link = link,
+            Change::Partition(groups) => {
+                inner.groups = groups
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(i, group)| group.into_iter().map(move |ip| (ip, i)))
+                    .collect();
+                self.partitions.send_modify(|n| *n += 1);
+            }
+            Change::Heal => inner.groups.clear(),
+        }
+    }
+
+    /// Applies the changes, each after the given time since the call.
+    pub async fn run_schedule(&self, schedule: Vec<(time::Duration, Change)>) {
+        let start = self.clock.now();
+        for (after, change) in schedule {
+            self.clock.sleep_until(start + after).await;
+            self.apply(change);
+        }
+    }
+
+    pub(crate) fn listen(&self, addr: SocketAddr) -> io::Result<Listener> {
+        let mut inner = self.inner.lock();
+        if inner.listeners.get(&addr).map_or(false, |send| !send.is_closed()) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        let (send, recv) = mpsc::unbounded_channel();
+        inner.listeners.insert(addr, send);
+        Ok(Listener(recv))
+    }
+
+    /// Connects a node with IP `local_ip` to the peer.
+    pub(crate) fn connect(
+        self: &Arc<Self>,
+        local_ip: IpAddr,
+        peer_info: &PeerInfo,
+        tier: tcp::Tier,
+    ) -> anyhow::Result<tcp::Stream> {
+        let peer_addr =
+            peer_info.addr.ok_or(anyhow!("Trying to connect to peer with no public address"))?;
+        let mut inner = self.inner.lock();
+        if inner.partitioned(local_ip, peer_addr.ip()) {
+            return Err(anyhow!("{peer_addr} is unreachable"));
+        }
+        let listener = inner.listeners.get(&peer_addr).context("connection refused")?.clone();
+        let local_addr = SocketAddr::new(local_ip, inner.next_outbound_port);
+        inner.next_outbound_port += 1;
+        drop(inner);
+
+        let (outbound_send, outbound_recv) = self.pipe(local_addr.ip(), peer_addr.ip());
+        let (inbound_send, inbound_recv) = self.pipe(peer_addr.ip(), local_addr.ip());
+        let inbound = tcp::Stream {
+            stream: tcp::Transport::Sim(Link { send: inbound_send, recv: outbound_recv }),
+            type_: tcp::StreamType::Inbound,
+            local_addr: peer_addr,
+            peer_addr: local_addr,
+        };
+        listener.send(inbound).map_err(|_| anyhow!("connection refused"))?;
+        Ok(tcp::Stream {
+            stream: tcp::Transport::Sim(Link { send: outbound_send, recv: inbound_recv }),
+            type_: tcp::StreamType::Outbound { peer_id: peer_info.id.clone(), tier },
+            local_addr,
+            peer_addr,
+        })
+    }
+
+    fn pipe(self: &Arc<Self>, from: IpAddr, to: IpAddr) -> (SendHalf, RecvHalf) {
+        let (send, recv) = mpsc::unbounded_channel();
+        let now = self.clock.now();
+        (
+            SendHalf { simnet: self.clone(), from, to, send, busy_until: now, last_delivery: now },
+            RecvHalf {
+                simnet: self.clone(),
+                from,
+                to,
+                recv,
+                partitions: self.partitions.subscribe(),
+                next: None,
+            },
+        )
+    }
+
+    /// Computes the time at which a message of `len` bytes sent now from `from` to `to` will
+    /// be delivered, or None if the nodes are partitioned.
+    fn delivery_time(
+        &self,
+        from: IpAddr,
+        to: IpAddr,
+        len: usize,
+        busy_until: &mut time::Instant,
+    ) -> Option<time::Instant> {
+        let mut inner = self.inner.lock();
+        if inner.partitioned(from, to) {
+            return None;
+        }
+        let link = inner.links.get(&(from, to)).unwrap_or(&inner.default_link).clone();
+        // Messages are transmitted one after another.
+        *busy_until = (*busy_until).max(self.clock.now());
+        if let Some(bandwidth) = link.bandwidth {
+            *busy_until += time::Duration::seconds_f64(len as f64 / bandwidth as f64);
+        }
+        let mut delay = link.latency + link.jitter * inner.rng.gen::<f64>();
+        if inner.rng.gen_bool(link.loss) {
+            delay += RETRANSMISSION_TIMEOUT;
+        }
+        Some(*busy_until + delay)
+    }
+}
+
+/// Accepts the inbound connections of a node.
+pub(crate) struct Listener(mpsc::UnboundedReceiver<tcp::Stream>);
+
+impl Listener {
+    pub async fn accept(&mut self) -> io::Result<tcp::Stream> {
+        self.0.recv().await.ok_or(io::ErrorKind::NotConnected.into())
+    }
+}
+
+/// Connection of the simulated network, sending messages rather than bytes.
+#[derive(Debug)]
+pub(crate) struct Link {
+    pub send: SendHalf,
+    pub recv: RecvHalf,
+}
+
+#[derive(Debug)]
+pub(crate) struct SendHalf {
+    simnet: Arc<Simnet>,
+    from: IpAddr,
+    to: IpAddr,
+    send: mpsc::UnboundedSender<(time::Instant, Vec<u8>)>,
+    /// Time until which the previous messages are being transmitted.
+    busy_until: time::Instant,
+    last_delivery: time::Instant,
+}
+
+impl SendHalf {
+    pub fn send(&mut self, msg: Vec<u8>) -> io::Result<()> {
+        let delivery = self
+            .simnet
+            .delivery_time(self.from, self.to, msg.len(), &mut self.busy_until)
+            .ok_or(io::Error::from(io::ErrorKind::ConnectionReset))?;
+        // The messages are delivered in order, even if they have different jitter.
+        self.last_delivery = self.last_delivery.max(delivery);
+        self.send.send((self.last_delivery, msg)).map_err(|_| io::ErrorKind::ConnectionReset.into())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RecvHalf {
+    simnet: Arc<Simnet>,
+    from: IpAddr,
+    to: IpAddr,
+    recv: mpsc::UnboundedReceiver<(time::Instant, Vec<u8>)>,
+    partitions: watch::Receiver<u64>,
+    /// Next message, waiting for its delivery time.
+    next: Option<(time::Instant, Vec<u8>)>,
+}
+
+impl RecvHalf {
+    fn check_partition(&self) -> io::Result<()> {
+        if self.simnet.inner.lock().partitioned(self.from, self.to) {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+        Ok(())
+    }
+
+    /// Waits for the next message. Returns an error once the connection is closed
+    /// or the nodes get partitioned. Cancel-safe.
+    pub async fn recv(&mut self) -> io::Result<Vec<u8>> {
+        while self.next.is_none() {
+            self.check_partition()?;
+            tokio::select! {
+                item = self.recv.recv() => {
+                    self.next = Some(item.ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?);
+                }
+                _ = self.partitions.changed() => {}
+            }
+        }
+        let delivery = self.next.as_ref().unwrap().0;
+        self.simnet.clock.sleep_until(delivery).await;
+        self.check_partition()?;
+        Ok(self.next.take().unwrap().1)
+    }
+}
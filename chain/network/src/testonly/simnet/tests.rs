@@ -0,0 +1,101 @@
+use crate::network_protocol::testonly as data;
+use crate::network_protocol::PeerInfo;
+use crate::tcp;
+use crate::testonly::make_rng;
+use crate::testonly::simnet::{Change, Link, LinkConfig, Simnet, RETRANSMISSION_TIMEOUT};
+use futures::FutureExt as _;
+use near_async::time;
+use std::sync::Arc;
+
+/// Connects 2 new nodes of the simnet. Returns the addresses of the nodes and
+/// the (outbound, inbound) connection between them.
+async fn connect(simnet: &Arc<Simnet>) -> (tcp::ListenerAddr, tcp::ListenerAddr, Link, Link) {
+    let mut rng = make_rng(89028037453);
+    let addr0 = simnet.new_addr();
+    let addr1 = simnet.new_addr();
+    let mut listener = simnet.listen(*addr1).unwrap();
+    let peer_info =
+        PeerInfo { id: data::make_peer_id(&mut rng), addr: Some(*addr1), account_id: None };
+    let outbound = simnet.connect(addr0.ip(), &peer_info, tcp::Tier::T2).unwrap();
+    let inbound = listener.accept().await.unwrap();
+    match (outbound.stream, inbound.stream) {
+        (tcp::Transport::Sim(outbound), tcp::Transport::Sim(inbound)) => {
+            (addr0, addr1, outbound, inbound)
+        }
+        _ => panic!("expected simnet connections"),
+    }
+}
+
+#[tokio::test]
+async fn latency_and_bandwidth() {
+    let clock = time::FakeClock::default();
+    let simnet = Simnet::new(clock.clock(), 2342);
+    let (addr0, addr1, mut outbound, mut inbound) = connect(&simnet).await;
+    simnet.apply(Change::SetLink {
+        from: addr0.ip(),
+        to: addr1.ip(),
+        link: LinkConfig {
+            latency: time::Duration::milliseconds(100),
+            bandwidth: Some(1000),
+            ..LinkConfig::default()
+        },
+    });
+
+    // Each message takes 100ms to transmit and 100ms to arrive.
+    outbound.send.send(vec![0; 100]).unwrap();
+    outbound.send.send(vec![1; 100]).unwrap();
+    clock.advance(time::Duration::milliseconds(199));
+    assert!(inbound.recv.recv().now_or_never().is_none());
+    clock.advance(time::Duration::milliseconds(1));
+    assert_eq!(vec![0; 100], inbound.recv.recv().await.unwrap());
+    clock.advance(time::Duration::milliseconds(99));
+    assert!(inbound.recv.recv().now_or_never().is_none());
+    clock.advance(time::Duration::milliseconds(1));
+    assert_eq!(vec![1; 100], inbound.recv.recv().await.unwrap());
+
+    // The other direction uses the default link.
+    inbound.send.send(vec![2]).unwrap();
+    assert_eq!(vec![2], outbound.recv.recv().await.unwrap());
+}
+
+#[tokio::test]
+async fn jitter_and_loss() {
+    let clock = time::FakeClock::default();
+    let simnet = Simnet::new(clock.clock(), 2342);
+    let (_, _, mut outbound, mut inbound) = connect(&simnet).await;
+    let jitter = time::Duration::milliseconds(50);
+    simnet.apply(Change::SetDefaultLink(LinkConfig { jitter, loss: 1., ..LinkConfig::default() }));
+
+    // Every message is lost once, and the messages are delivered in order.
+    for i in 0..10 {
+        outbound.send.send(vec![i]).unwrap();
+    }
+    clock.advance(RETRANSMISSION_TIMEOUT);
+    assert!(inbound.recv.recv().now_or_never().is_none());
+    clock.advance(jitter);
+    for i in 0..10 {
+        assert_eq!(vec![i], inbound.recv.recv().await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn partition() {
+    let clock = time::FakeClock::default();
+    let simnet = Simnet::new(clock.clock(), 2342);
+    let (addr0, addr1, mut outbound, mut inbound) = connect(&simnet).await;
+
+    // Partitioning the nodes closes the connection.
+    let recv = tokio::spawn(async move { inbound.recv.recv().await });
+    simnet.apply(Change::Partition(vec![vec![addr1.ip()]]));
+    assert!(recv.await.unwrap().is_err());
+    assert!(outbound.send.send(vec![0]).is_err());
+    assert!(outbound.recv.recv().await.is_err());
+
+    // The partitioned nodes can't connect.
+    let _listener = simnet.listen(*addr1).unwrap();
+    let peer_info =
+        PeerInfo { id: data::make_peer_id(&mut make_rng(1)), addr: Some(*addr1), account_id: None };
+    assert!(simnet.connect(addr0.ip(), &peer_info, tcp::Tier::T2).is_err());
+    simnet.apply(Change::Heal);
+    assert!(simnet.connect(addr0.ip(), &peer_info, tcp::Tier::T2).is_ok());
+}
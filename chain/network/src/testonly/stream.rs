@@ -29,10 +29,10 @@ impl Stream {
 
     pub async fn read(&mut self) -> Result<PeerMessage, std::io::Error> {
         'read: loop {
-            let n = self.stream.stream.read_u32_le().await? as usize;
+            let n = self.stream.stream.tcp_mut().read_u32_le().await? as usize;
             let mut buf = BytesMut::new();
             buf.resize(n, 0);
-            self.stream.stream.read_exact(&mut buf[..]).await?;
+            self.stream.stream.tcp_mut().read_exact(&mut buf[..]).await?;
             for enc in [Encoding::Proto, Encoding::Borsh] {
                 if let Ok(msg) = PeerMessage::deserialize(enc, &buf[..]) {
                     // If deserialize() succeeded but we expected different encoding, ignore the
@@ -61,8 +61,8 @@ impl Stream {
     }
 
     async fn write_encoded(&mut self, msg: &[u8]) {
-        self.stream.stream.write_u32_le(msg.len() as u32).await.unwrap();
-        self.stream.stream.write_all(msg).await.unwrap();
-        self.stream.stream.flush().await.unwrap();
+        self.stream.stream.tcp_mut().write_u32_le(msg.len() as u32).await.unwrap();
+        self.stream.stream.tcp_mut().write_all(msg).await.unwrap();
+        self.stream.stream.tcp_mut().flush().await.unwrap();
     }
 }
@@ -16,7 +16,7 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::PartialEncodedChunkWithArcReceipts;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::BlockHeight;
-use near_primitives::types::{AccountId, ShardId};
+use near_primitives::types::{AccountId, EpochId, ShardId};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::net::SocketAddr;
@@ -25,7 +25,7 @@ use std::sync::Arc;
 /// Exported types, which are part of network protocol.
 pub use crate::network_protocol::{
     Edge, PartialEdgeInfo, PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg,
-    PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerInfo, StateResponseInfo,
+    PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerInfo, SnapshotHostInfo, StateResponseInfo,
     StateResponseInfoV1, StateResponseInfoV2,
 };
 
@@ -124,6 +124,25 @@ pub struct ConnectionInfo {
     pub time_connected_until: time::Utc,
 }
 
+/// Reputation score of a peer, as of `updated_at`.  See
+/// `peer_manager::reputation_store` for how the score changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerReputation {
+    pub peer_id: PeerId,
+    pub score: f64,
+    pub updated_at: time::Utc,
+}
+
+/// Outcomes of the recent outbound connection attempts to an address.  See
+/// `peer_manager::peer_store` for how they affect the choice of peers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrQuality {
+    pub addr: SocketAddr,
+    pub successes: u32,
+    pub failures: u32,
+    pub last_attempt: time::Utc,
+}
+
 impl KnownPeerStatus {
     pub fn is_banned(&self) -> bool {
         matches!(self, KnownPeerStatus::Banned(_, _))
@@ -221,12 +240,16 @@ pub enum NetworkRequests {
     Approval { approval_message: ApprovalMessage },
     /// Request block with given hash from given peer.
     BlockRequest { hash: CryptoHash, peer_id: PeerId },
-    /// Request given block headers.
-    BlockHeadersRequest { hashes: Vec<CryptoHash>, peer_id: PeerId },
+    /// Request block headers following the locator `hashes`, at most `max_headers` of them if set.
+    BlockHeadersRequest { hashes: Vec<CryptoHash>, max_headers: Option<u64>, peer_id: PeerId },
     /// Request state header for given shard at given state root.
     StateRequestHeader { shard_id: ShardId, sync_hash: CryptoHash, peer_id: PeerId },
     /// Request state part for given shard at given state root.
     StateRequestPart { shard_id: ShardId, sync_hash: CryptoHash, part_id: u64, peer_id: PeerId },
+    /// Advertise to all peers the state parts which can be served from the state snapshot.
+    SnapshotHostInfo(SnapshotHostInfo),
+    /// Request the proof of the start of the epoch after the given one.
+    EpochSyncRequest { epoch_id: EpochId, peer_id: PeerId },
     /// Ban given peer.
     BanPeer { peer_id: PeerId, ban_reason: ReasonForBan },
     /// Announce account
@@ -278,6 +301,8 @@ pub struct HighestHeightPeerInfo {
     pub tracked_shards: Vec<ShardId>,
     /// Denote if a node is running in archival mode or not.
     pub archival: bool,
+    /// State parts the peer can serve from its state snapshot, if it advertised any.
+    pub snapshot_host_info: Option<SnapshotHostInfo>,
 }
 
 impl From<FullPeerInfo> for Option<HighestHeightPeerInfo> {
@@ -290,6 +315,7 @@ impl From<FullPeerInfo> for Option<HighestHeightPeerInfo> {
                 highest_block_hash: p.chain_info.last_block.unwrap().hash,
                 tracked_shards: p.chain_info.tracked_shards,
                 archival: p.chain_info.archival,
+                snapshot_host_info: p.chain_info.snapshot_host_info,
             })
         } else {
             None
@@ -316,6 +342,8 @@ pub struct PeerChainInfo {
     pub tracked_shards: Vec<ShardId>,
     /// Denote if a node is running in archival mode or not.
     pub archival: bool,
+    /// State parts the peer can serve from its state snapshot, if it advertised any.
+    pub snapshot_host_info: Option<SnapshotHostInfo>,
 }
 
 // Information about the connected peer that is shared with the rest of the system.
@@ -336,6 +364,8 @@ pub struct ConnectedPeerInfo {
     pub peer_type: PeerType,
     /// Nonce used for the connection with the peer.
     pub nonce: u64,
+    /// Current reputation score of the peer, 0 is neutral.
+    pub reputation: f64,
 }
 
 #[derive(Debug, Clone, actix::MessageResponse)]
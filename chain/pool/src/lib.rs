@@ -1,5 +1,5 @@
 use std::collections::btree_map::Entry;
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 use crate::types::{PoolIterator, PoolKey, TransactionGroup};
 use borsh::BorshSerialize;
@@ -8,47 +8,97 @@ use near_o11y::metrics::prometheus::core::{AtomicI64, GenericGauge};
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, Gas, Nonce};
 use std::ops::Bound;
 
 mod metrics;
 pub mod types;
 
+/// Maximum number of transactions per (account ID, signer public key) that are held back because
+/// their nonce follows a gap in the nonces of the transactions in the pool.
+pub const MAX_FUTURE_TRANSACTIONS_PER_KEY: usize = 16;
+
 #[derive(Debug, PartialEq)]
 pub enum InsertTransactionResult {
     /// Transaction was successfully inserted.
     Success,
+    /// Transaction was inserted in place of a transaction with the same signer and nonce.
+    Replaced,
     /// Transaction is already in the pool.
     Duplicate,
     /// Not enough space to fit the transaction.
     NoSpaceLeft,
+    /// A transaction with the same signer and nonce is in the pool and the new one doesn't attach
+    /// more gas than it.
+    ReplacementUnderpriced,
+    /// Too many transactions of the signer are already held back waiting for a gap in their nonces
+    /// to close.
+    TooManyFutureTransactions,
 }
 
 /// Transaction pool: keeps track of transactions that were not yet accepted into the block chain.
 pub struct TransactionPool {
     /// Transactions are grouped by a pair of (account ID, signer public key).
-    /// NOTE: It's more efficient on average to keep transactions unsorted and with potentially
-    /// conflicting nonce than to create a BTreeMap for every transaction.
+    /// NOTE: It's more efficient on average to keep transactions unsorted than to create a
+    /// BTreeMap for every transaction.
     transactions: BTreeMap<PoolKey, Vec<SignedTransaction>>,
-    /// Set of all hashes to quickly check if the given transaction is in the pool.
-    unique_transactions: HashSet<CryptoHash>,
+    /// Transactions whose nonce follows a gap in the nonces of their group, by group and nonce.
+    /// They are held back from the pool iterator until the gap closes, see `insert_transaction`.
+    future_transactions: HashMap<PoolKey, BTreeMap<Nonce, SignedTransaction>>,
+    /// For every group in the pool, the nonce following the largest nonce of its transactions
+    /// which are not held back. Transactions with a larger nonce are held back.
+    next_nonces: HashMap<PoolKey, Nonce>,
+    /// Hashes of all transactions in the pool, to quickly check if the given transaction is in
+    /// the pool, with their positions in `eviction_order`.
+    unique_transactions: HashMap<CryptoHash, EvictionRank>,
+    /// All transactions in the pool, in the order in which they are evicted when a limit is hit.
+    eviction_order: BTreeSet<EvictionRank>,
+    /// Number of transactions inserted so far, used to order transactions by age.
+    num_insertions: u64,
     /// A uniquely generated key seed to randomize PoolKey order.
     key_seed: RngSeed,
     /// The key after which the pool iterator starts. Doesn't have to be present in the pool.
     last_used_key: PoolKey,
-    /// If set, new transactions that bring the size of the pool over this limit will be rejected.
+    /// If set, new transactions that bring the size of the pool over this limit will either evict
+    /// transactions or be rejected, see `insert_transaction`.
     total_transaction_size_limit: Option<u64>,
+    /// Like `total_transaction_size_limit`, but for the number of transactions in the pool.
+    total_transaction_count_limit: Option<usize>,
     /// Total size of transactions in the pool measured in bytes.
     total_transaction_size: u64,
     /// Metrics tracked for transaction pool.
+    metrics_label: String,
     transaction_pool_count_metric: GenericGauge<AtomicI64>,
     transaction_pool_size_metric: GenericGauge<AtomicI64>,
 }
 
+/// Position of a transaction in the eviction order of the pool: transactions attaching the least
+/// gas are evicted first, and the oldest first among them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct EvictionRank {
+    prepaid_gas: Gas,
+    insertion: u64,
+    key: PoolKey,
+    nonce: Nonce,
+}
+
+/// Reason for which a transaction was evicted from the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+enum EvictionReason {
+    /// A transaction with the same signer and nonce attaching more gas replaced it.
+    Replaced,
+    /// The size limit of the pool was hit.
+    SizeLimit,
+    /// The limit of the number of transactions in the pool was hit.
+    CountLimit,
+}
+
 impl TransactionPool {
     pub fn new(
         key_seed: RngSeed,
         total_transaction_size_limit: Option<u64>,
+        total_transaction_count_limit: Option<usize>,
         metrics_label: &str,
     ) -> Self {
         let transaction_pool_count_metric =
@@ -62,10 +112,16 @@ impl TransactionPool {
         Self {
             key_seed,
             transactions: BTreeMap::new(),
-            unique_transactions: HashSet::new(),
+            future_transactions: HashMap::new(),
+            next_nonces: HashMap::new(),
+            unique_transactions: HashMap::new(),
+            eviction_order: BTreeSet::new(),
+            num_insertions: 0,
             last_used_key: CryptoHash::default(),
             total_transaction_size_limit,
+            total_transaction_count_limit,
             total_transaction_size: 0,
+            metrics_label: metrics_label.to_string(),
             transaction_pool_count_metric,
             transaction_pool_size_metric,
         }
@@ -79,40 +135,228 @@ impl TransactionPool {
     }
 
     /// Inserts a signed transaction that passed validation into the pool.
+    ///
+    /// A transaction with the same signer and nonce as a transaction in the pool replaces it if
+    /// it attaches more gas. The gas price is set by the chain rather than by the signer, so the
+    /// attached gas is what makes a replacement more expensive than the original and prevents
+    /// accounts from churning the pool with free replacements.
+    ///
+    /// If the transaction doesn't fit into the size or count limits of the pool, transactions of
+    /// other groups are evicted to make room for it, in the order of `EvictionRank`, as long as
+    /// they attach less gas than it. Otherwise the transaction is rejected.
+    ///
+    /// A transaction whose nonce leaves a gap after the nonces of the other transactions of its
+    /// group is held back, up to `MAX_FUTURE_TRANSACTIONS_PER_KEY` per group, so that it isn't
+    /// included before the transactions filling the gap arrive, which would make them invalid.
+    /// Held back transactions are released when the gap closes, or once all other transactions
+    /// of the group were pulled from the pool, as the chain doesn't require nonces to be
+    /// contiguous.
     #[must_use]
     pub fn insert_transaction(
         &mut self,
         signed_transaction: SignedTransaction,
     ) -> InsertTransactionResult {
-        if !self.unique_transactions.insert(signed_transaction.get_hash()) {
+        if self.unique_transactions.contains_key(&signed_transaction.get_hash()) {
             // The hash of this transaction was already seen, skip it.
             return InsertTransactionResult::Duplicate;
         }
+        let signer_id = &signed_transaction.transaction.signer_id;
+        let signer_public_key = &signed_transaction.transaction.public_key;
+        let key = self.key(signer_id, signer_public_key);
+        let nonce = signed_transaction.transaction.nonce;
+        let rank = EvictionRank {
+            prepaid_gas: prepaid_gas(&signed_transaction),
+            insertion: self.num_insertions,
+            key,
+            nonce,
+        };
+        let replaced_size = match self.get_by_nonce(&key, nonce) {
+            Some(tx) if rank.prepaid_gas <= prepaid_gas(tx) => {
+                return InsertTransactionResult::ReplacementUnderpriced;
+            }
+            Some(tx) => Some(tx.get_size()),
+            None => None,
+        };
         // We never expect the total size to go over `u64` during real operation as that would
         // be more than 10^9 GiB of RAM consumed for transaction pool, so panicing here is intended
         // to catch a logic error in estimation of transaction size.
         let new_total_transaction_size = self
             .total_transaction_size
             .checked_add(signed_transaction.get_size())
-            .expect("Total transaction size is too large");
-        if let Some(limit) = self.total_transaction_size_limit {
-            if new_total_transaction_size > limit {
-                return InsertTransactionResult::NoSpaceLeft;
-            }
+            .expect("Total transaction size is too large")
+            - replaced_size.unwrap_or(0);
+        let new_total_transaction_count =
+            self.unique_transactions.len() + usize::from(replaced_size.is_none());
+        let Some(evictions) =
+            self.plan_evictions(&rank, new_total_transaction_size, new_total_transaction_count)
+        else {
+            return InsertTransactionResult::NoSpaceLeft;
+        };
+        let is_future = self.next_nonces.get(&key).is_some_and(|next_nonce| nonce > *next_nonce);
+        if is_future
+            && replaced_size.is_none()
+            && self.future_transactions.get(&key).map_or(0, BTreeMap::len)
+                >= MAX_FUTURE_TRANSACTIONS_PER_KEY
+        {
+            return InsertTransactionResult::TooManyFutureTransactions;
         }
 
         // At this point transaction is accepted to the pool.
+        if replaced_size.is_some() {
+            self.remove_by_nonce(&key, nonce);
+            self.record_eviction(EvictionReason::Replaced);
+        }
+        let mut new_total_transaction_size = new_total_transaction_size;
+        for (victim, reason) in evictions {
+            let evicted = self.remove_by_nonce(&victim.key, victim.nonce).expect("victim in pool");
+            new_total_transaction_size -= evicted.get_size();
+            self.on_group_drained(victim.key);
+            self.record_eviction(reason);
+        }
         self.total_transaction_size = new_total_transaction_size;
-        let signer_id = &signed_transaction.transaction.signer_id;
-        let signer_public_key = &signed_transaction.transaction.public_key;
-        self.transactions
-            .entry(self.key(signer_id, signer_public_key))
-            .or_insert_with(Vec::new)
-            .push(signed_transaction);
+        self.unique_transactions.insert(signed_transaction.get_hash(), rank);
+        self.eviction_order.insert(rank);
+        self.num_insertions += 1;
+        if is_future {
+            self.future_transactions.entry(key).or_default().insert(nonce, signed_transaction);
+        } else {
+            let next_nonce = self.next_nonces.entry(key).or_insert(nonce + 1);
+            *next_nonce = (*next_nonce).max(nonce + 1);
+            self.transactions.entry(key).or_insert_with(Vec::new).push(signed_transaction);
+            self.release_future_transactions(key);
+        }
 
-        self.transaction_pool_count_metric.inc();
+        self.transaction_pool_count_metric.set(self.unique_transactions.len() as i64);
         self.transaction_pool_size_metric.set(self.total_transaction_size as i64);
-        InsertTransactionResult::Success
+        if replaced_size.is_some() {
+            InsertTransactionResult::Replaced
+        } else {
+            InsertTransactionResult::Success
+        }
+    }
+
+    /// Returns the transaction of the given group with the given nonce, if any.
+    fn get_by_nonce(&self, key: &PoolKey, nonce: Nonce) -> Option<&SignedTransaction> {
+        self.transactions
+            .get(key)
+            .and_then(|txs| txs.iter().find(|tx| tx.transaction.nonce == nonce))
+            .or_else(|| self.future_transactions.get(key).and_then(|txs| txs.get(&nonce)))
+    }
+
+    /// Returns the transactions to evict, with the reason why, for a transaction of the given
+    /// rank to fit into the limits of the pool, or `None` if it doesn't fit even with evictions.
+    fn plan_evictions(
+        &self,
+        rank: &EvictionRank,
+        mut total_size: u64,
+        mut total_count: usize,
+    ) -> Option<Vec<(EvictionRank, EvictionReason)>> {
+        let mut evictions = vec![];
+        let mut candidates = self.eviction_order.iter();
+        loop {
+            let reason = if self
+                .total_transaction_size_limit
+                .is_some_and(|limit| total_size > limit)
+            {
+                EvictionReason::SizeLimit
+            } else if self.total_transaction_count_limit.is_some_and(|limit| total_count > limit) {
+                EvictionReason::CountLimit
+            } else {
+                return Some(evictions);
+            };
+            // Transactions of the same group are never evicted, as the new transaction is likely
+            // to depend on them.
+            let victim = candidates.find(|victim| victim.key != rank.key)?;
+            if victim.prepaid_gas >= rank.prepaid_gas {
+                return None;
+            }
+            let victim_tx = self.get_by_nonce(&victim.key, victim.nonce).expect("victim in pool");
+            total_size -= victim_tx.get_size();
+            total_count -= 1;
+            evictions.push((*victim, reason));
+        }
+    }
+
+    fn record_eviction(&self, reason: EvictionReason) {
+        metrics::TRANSACTION_POOL_EVICTIONS
+            .with_label_values(&[&self.metrics_label, reason.into()])
+            .inc();
+    }
+
+    /// Forgets the transaction with the given hash, which was removed from its group. Returns
+    /// whether the transaction was in the pool.
+    fn forget_transaction(&mut self, hash: &CryptoHash) -> bool {
+        match self.unique_transactions.remove(hash) {
+            Some(rank) => {
+                self.eviction_order.remove(&rank);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the transaction of the given group with the given nonce from the pool, and returns
+    /// it. Doesn't update the total size.
+    fn remove_by_nonce(&mut self, key: &PoolKey, nonce: Nonce) -> Option<SignedTransaction> {
+        let removed = if let Some(txs) = self.transactions.get_mut(key) {
+            let removed =
+                txs.iter().position(|tx| tx.transaction.nonce == nonce).map(|i| txs.swap_remove(i));
+            if txs.is_empty() {
+                self.transactions.remove(key);
+            }
+            removed
+        } else {
+            None
+        };
+        let removed = removed.or_else(|| {
+            let txs = self.future_transactions.get_mut(key)?;
+            let removed = txs.remove(&nonce);
+            if txs.is_empty() {
+                self.future_transactions.remove(key);
+            }
+            removed
+        });
+        if let Some(tx) = &removed {
+            self.forget_transaction(&tx.get_hash());
+        }
+        removed
+    }
+
+    /// Moves the held back transactions of the given group which no longer follow a gap in the
+    /// nonces to the transactions available to the pool iterator.
+    fn release_future_transactions(&mut self, key: PoolKey) {
+        let Some(future) = self.future_transactions.get_mut(&key) else { return };
+        let next_nonce = self.next_nonces.get_mut(&key).expect("group has next nonce");
+        while let Some(entry) = future.first_entry() {
+            if *entry.key() > *next_nonce {
+                break;
+            }
+            *next_nonce = (*next_nonce).max(*entry.key() + 1);
+            self.transactions.entry(key).or_insert_with(Vec::new).push(entry.remove());
+        }
+        if future.is_empty() {
+            self.future_transactions.remove(&key);
+        }
+    }
+
+    /// Called when no transactions of the given group are available to the pool iterator anymore.
+    /// The held back transactions of the group, if any, are released, as there is no point in
+    /// waiting for the gap before them to close after the transactions preceding it are gone.
+    fn on_group_drained(&mut self, key: PoolKey) {
+        if self.transactions.contains_key(&key) {
+            return;
+        }
+        match self.future_transactions.remove(&key) {
+            Some(future) => {
+                let last_nonce =
+                    *future.keys().next_back().expect("held back groups are non-empty");
+                self.next_nonces.insert(key, last_nonce + 1);
+                self.transactions.insert(key, future.into_values().collect());
+            }
+            None => {
+                self.next_nonces.remove(&key);
+            }
+        }
     }
 
     /// Returns a pool iterator wrapper that implements an iterator-like trait to iterate over
@@ -130,7 +374,7 @@ impl TransactionPool {
         let mut grouped_transactions = HashMap::new();
         for tx in transactions {
             // If transaction is not present in the pool, skip it.
-            if !self.unique_transactions.remove(&tx.get_hash()) {
+            if !self.forget_transaction(&tx.get_hash()) {
                 continue;
             }
 
@@ -142,23 +386,31 @@ impl TransactionPool {
                 .insert(tx.get_hash());
         }
         for (key, hashes) in grouped_transactions {
+            let mut retain = |tx: &SignedTransaction| {
+                if !hashes.contains(&tx.get_hash()) {
+                    return true;
+                }
+                // See the comment above where we increase the size for reasoning why panicing
+                // here catches a logic error.
+                self.total_transaction_size = self
+                    .total_transaction_size
+                    .checked_sub(tx.get_size())
+                    .expect("Total transaction size dropped below zero");
+                false
+            };
             if let Entry::Occupied(mut entry) = self.transactions.entry(key) {
-                entry.get_mut().retain(|tx| {
-                    if !hashes.contains(&tx.get_hash()) {
-                        return true;
-                    }
-                    // See the comment above where we increase the size for reasoning why panicing
-                    // here catches a logic error.
-                    self.total_transaction_size = self
-                        .total_transaction_size
-                        .checked_sub(tx.get_size())
-                        .expect("Total transaction size dropped below zero");
-                    false
-                });
+                entry.get_mut().retain(|tx| retain(tx));
                 if entry.get().is_empty() {
                     entry.remove_entry();
                 }
             }
+            if let Some(future) = self.future_transactions.get_mut(&key) {
+                future.retain(|_, tx| retain(tx));
+                if future.is_empty() {
+                    self.future_transactions.remove(&key);
+                }
+            }
+            self.on_group_drained(key);
         }
 
         // We can update metrics only once for the whole batch of transactions.
@@ -175,6 +427,33 @@ impl TransactionPool {
     pub fn transaction_size(&self) -> u64 {
         self.total_transaction_size
     }
+
+    /// Returns the number of transactions held back because of gaps in the nonces.
+    pub fn num_future_transactions(&self) -> usize {
+        self.future_transactions.values().map(BTreeMap::len).sum()
+    }
+
+    /// Returns the number of (account ID, signer public key) pairs with transactions in the pool.
+    pub fn num_groups(&self) -> usize {
+        self.next_nonces.len()
+    }
+
+    pub fn transaction_size_limit(&self) -> Option<u64> {
+        self.total_transaction_size_limit
+    }
+
+    pub fn transaction_count_limit(&self) -> Option<usize> {
+        self.total_transaction_count_limit
+    }
+}
+
+/// Returns the gas attached to the function calls of the transaction.
+fn prepaid_gas(tx: &SignedTransaction) -> Gas {
+    tx.transaction
+        .actions
+        .iter()
+        .map(|action| action.get_prepaid_gas())
+        .fold(0, Gas::saturating_add)
 }
 
 /// PoolIterator is a structure to pull transactions from the pool.
@@ -186,11 +465,14 @@ pub struct PoolIteratorWrapper<'a> {
 
     /// Queue of transaction groups. Each group there is sorted by nonce.
     sorted_groups: VecDeque<TransactionGroup>,
+
+    /// Keys of the groups that were pulled entirely.
+    drained_keys: Vec<PoolKey>,
 }
 
 impl<'a> PoolIteratorWrapper<'a> {
     pub fn new(pool: &'a mut TransactionPool) -> Self {
-        Self { pool, sorted_groups: Default::default() }
+        Self { pool, sorted_groups: Default::default(), drained_keys: vec![] }
     }
 }
 
@@ -241,8 +523,9 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
         } else {
             while let Some(sorted_group) = self.sorted_groups.pop_front() {
                 if sorted_group.transactions.is_empty() {
+                    self.drained_keys.push(sorted_group.key);
                     for hash in sorted_group.removed_transaction_hashes {
-                        self.pool.unique_transactions.remove(&hash);
+                        self.pool.forget_transaction(&hash);
                     }
                     // See the comment in `insert_transaction` where we increase the size for reasoning
                     // why panicing here catches a logic error.
@@ -273,7 +556,7 @@ impl<'a> Drop for PoolIteratorWrapper<'a> {
     fn drop(&mut self) {
         for group in self.sorted_groups.drain(..) {
             for hash in group.removed_transaction_hashes {
-                self.pool.unique_transactions.remove(&hash);
+                self.pool.forget_transaction(&hash);
             }
             // See the comment in `insert_transaction` where we increase the size for reasoning
             // why panicing here catches a logic error.
@@ -285,8 +568,16 @@ impl<'a> Drop for PoolIteratorWrapper<'a> {
 
             if !group.transactions.is_empty() {
                 self.pool.transactions.insert(group.key, group.transactions);
+            } else {
+                self.drained_keys.push(group.key);
             }
         }
+        // Transactions held back in the drained groups are only released now, so that they are
+        // pulled by the next iterator at the earliest, giving the transactions filling the gap
+        // before them a chance to arrive.
+        for key in std::mem::take(&mut self.drained_keys) {
+            self.pool.on_group_drained(key);
+        }
         // We can update metrics only once for the whole batch of transactions.
         self.pool.transaction_pool_count_metric.set(self.pool.unique_transactions.len() as i64);
         self.pool.transaction_pool_size_metric.set(self.pool.transaction_size() as i64);
@@ -335,7 +626,7 @@ mod tests {
         mut transactions: Vec<SignedTransaction>,
         expected_weight: u32,
     ) -> (Vec<u64>, TransactionPool) {
-        let mut pool = TransactionPool::new(TEST_SEED, None, "");
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions {
@@ -446,7 +737,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
 
-        let mut pool = TransactionPool::new(TEST_SEED, None, "");
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions.clone() {
@@ -461,7 +752,11 @@ mod tests {
 
         assert_eq!(pool.len(), txs_to_check.len());
 
-        let mut pool_txs = prepare_transactions(&mut pool, txs_to_check.len() as u32);
+        // Transactions following nonce gaps are only pulled after the ones before them.
+        let mut pool_txs = vec![];
+        while pool.len() > 0 {
+            pool_txs.extend(prepare_transactions(&mut pool, txs_to_check.len() as u32));
+        }
         pool_txs.sort_by_key(|tx| tx.transaction.nonce);
         let mut expected_txs = txs_to_check.to_vec();
         expected_txs.sort_by_key(|tx| tx.transaction.nonce);
@@ -561,7 +856,7 @@ mod tests {
 
     #[test]
     fn test_transaction_pool_size() {
-        let mut pool = TransactionPool::new(TEST_SEED, None, "");
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
         let transactions = generate_transactions("alice.near", "alice.near", 1, 100);
         let mut total_transaction_size = 0;
         // Adding transactions increases the size.
@@ -585,7 +880,7 @@ mod tests {
         // Each transaction is at least 1 byte in size, so the last transaction will not fit.
         let pool_size_limit =
             transactions.iter().map(|tx| tx.get_size()).sum::<u64>().checked_sub(1).unwrap();
-        let mut pool = TransactionPool::new(TEST_SEED, Some(pool_size_limit), "");
+        let mut pool = TransactionPool::new(TEST_SEED, Some(pool_size_limit), None, "");
         for (i, tx) in transactions.iter().cloned().enumerate() {
             if i + 1 < transactions.len() {
                 assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
@@ -594,4 +889,133 @@ mod tests {
             }
         }
     }
+
+    fn function_call(nonce: u64, gas: Gas) -> SignedTransaction {
+        let signer_id: AccountId = "alice.near".parse().unwrap();
+        let signer = InMemorySigner::from_seed(signer_id.clone(), KeyType::ED25519, "alice.near");
+        SignedTransaction::call(
+            nonce,
+            signer_id,
+            "bob.near".parse().unwrap(),
+            &signer,
+            0,
+            "method".to_string(),
+            vec![],
+            gas,
+            CryptoHash::default(),
+        )
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
+        let original = function_call(1, 100);
+        assert_eq!(pool.insert_transaction(original.clone()), InsertTransactionResult::Success);
+        assert_eq!(
+            pool.insert_transaction(function_call(1, 100)),
+            InsertTransactionResult::Duplicate
+        );
+        // A replacement has to attach more gas.
+        let underpriced = function_call(1, 99);
+        assert_eq!(
+            pool.insert_transaction(underpriced),
+            InsertTransactionResult::ReplacementUnderpriced
+        );
+        let replacement = function_call(1, 200);
+        assert_eq!(pool.insert_transaction(replacement.clone()), InsertTransactionResult::Replaced);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.transaction_size(), replacement.get_size());
+        // The replaced transaction is gone and may be inserted again.
+        assert_eq!(
+            pool.insert_transaction(original),
+            InsertTransactionResult::ReplacementUnderpriced
+        );
+        assert_eq!(prepare_transactions(&mut pool, 10), vec![replacement]);
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut pool = TransactionPool::new(TEST_SEED, None, Some(2), "");
+        // Transfers attach no gas, so they are evicted first, the oldest one before the other.
+        for tx in generate_transactions("bob.near", "bob.near", 1, 2) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(
+            pool.insert_transaction(function_call(1, 100)),
+            InsertTransactionResult::Success
+        );
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.num_groups(), 2);
+        assert_eq!(pool.insert_transaction(function_call(2, 50)), InsertTransactionResult::Success);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.num_groups(), 1);
+        // Transactions of the same group are never evicted.
+        assert_eq!(
+            pool.insert_transaction(function_call(3, 100)),
+            InsertTransactionResult::NoSpaceLeft
+        );
+        let nonces: Vec<u64> =
+            prepare_transactions(&mut pool, 10).iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces, vec![1, 2]);
+        assert_eq!(pool.len(), 0);
+
+        // Transactions attaching as much gas as the new one are not evicted.
+        let mut pool = TransactionPool::new(TEST_SEED, None, Some(1), "");
+        for tx in generate_transactions("bob.near", "bob.near", 1, 1) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(
+            pool.insert_transaction(function_call(1, 0)),
+            InsertTransactionResult::NoSpaceLeft
+        );
+    }
+
+    #[test]
+    fn test_future_nonces() {
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 30);
+        assert_eq!(
+            pool.insert_transaction(transactions[0].clone()),
+            InsertTransactionResult::Success
+        );
+        // Transactions after the gap at nonce 2 are held back, up to the limit.
+        for tx in &transactions[2..2 + MAX_FUTURE_TRANSACTIONS_PER_KEY] {
+            assert_eq!(pool.insert_transaction(tx.clone()), InsertTransactionResult::Success);
+        }
+        assert_eq!(
+            pool.insert_transaction(transactions[2 + MAX_FUTURE_TRANSACTIONS_PER_KEY].clone()),
+            InsertTransactionResult::TooManyFutureTransactions
+        );
+        assert_eq!(pool.len(), MAX_FUTURE_TRANSACTIONS_PER_KEY + 1);
+
+        // Closing the gap releases the held back transactions.
+        assert_eq!(
+            pool.insert_transaction(transactions[1].clone()),
+            InsertTransactionResult::Success
+        );
+        assert_eq!(
+            pool.insert_transaction(transactions[2 + MAX_FUTURE_TRANSACTIONS_PER_KEY].clone()),
+            InsertTransactionResult::Success
+        );
+        let nonces: Vec<u64> =
+            prepare_transactions(&mut pool, 30).iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces, (1..=3 + MAX_FUTURE_TRANSACTIONS_PER_KEY as u64).collect::<Vec<_>>());
+
+        // Transactions after a gap are only released once the transactions before it are pulled,
+        // by the next pool iterator.
+        let mut pool = TransactionPool::new(TEST_SEED, None, None, "");
+        for nonce in [1, 2, 5, 6] {
+            let tx = transactions[nonce - 1].clone();
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        let nonces: Vec<u64> =
+            prepare_transactions(&mut pool, 10).iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces, vec![1, 2]);
+        let nonces: Vec<u64> =
+            prepare_transactions(&mut pool, 10).iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces, vec![5, 6]);
+        assert_eq!(pool.len(), 0);
+        assert_eq!(pool.transaction_size(), 0);
+    }
 }
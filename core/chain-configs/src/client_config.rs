@@ -1,7 +1,8 @@
 //! Chain Client Configuration
 use crate::MutableConfigValue;
+use near_crypto::PublicKey;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId,
+    AccountId, BlockHeight, BlockHeightDelta, EpochHeight, Gas, NumBlocks, NumSeats, ShardId,
 };
 use near_primitives::version::Version;
 use std::cmp::{max, min};
@@ -28,6 +29,10 @@ pub const DEFAULT_GC_NUM_EPOCHS_TO_KEEP: u64 = 5;
 pub const DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_EXTERNAL: u32 = 25;
 pub const DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_ON_CATCHUP_EXTERNAL: u32 = 5;
 
+/// Default number of failed attempts in a row to download a range of state
+/// parts from one source before falling back to the other source.
+pub const DEFAULT_STATE_SYNC_FALLBACK_AFTER_FAILURES: u32 = 3;
+
 /// Configuration for garbage collection.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct GCConfig {
@@ -44,6 +49,18 @@ pub struct GCConfig {
     /// Number of epochs for which we keep store data.
     #[serde(default = "default_gc_num_epochs_to_keep")]
     pub gc_num_epochs_to_keep: u64,
+
+    /// Maximum time to spend at every garbage collection call.  Once it's
+    /// exceeded the call stops after the block it's collecting and the next
+    /// call continues from there.  Unset means only `gc_blocks_limit` applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gc_time_limit: Option<Duration>,
+
+    /// Number of blocks behind the final head for which specific classes of
+    /// data are kept.  Such data is pruned earlier than the garbage collection
+    /// would remove it.
+    #[serde(default)]
+    pub retention: RetentionConfig,
 }
 
 impl Default for GCConfig {
@@ -52,6 +69,8 @@ impl Default for GCConfig {
             gc_blocks_limit: 2,
             gc_fork_clean_step: 100,
             gc_num_epochs_to_keep: DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
+            gc_time_limit: None,
+            retention: RetentionConfig::default(),
         }
     }
 }
@@ -74,6 +93,36 @@ impl GCConfig {
     }
 }
 
+/// Retention of data which isn't needed for processing blocks and can be
+/// pruned earlier than the garbage collection removes it, so that nodes which
+/// don't serve old data can run with smaller disks.  Unset values mean that the
+/// data is kept until garbage collection.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct RetentionConfig {
+    /// Number of blocks for which transactions are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<BlockHeightDelta>,
+    /// Number of blocks for which receipts are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipts: Option<BlockHeightDelta>,
+    /// Number of blocks for which execution outcomes are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcomes: Option<BlockHeightDelta>,
+    /// Number of blocks for which partial encoded chunks are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_parts: Option<BlockHeightDelta>,
+}
+
+impl RetentionConfig {
+    /// Whether any class of data is pruned before garbage collection.
+    pub fn is_enabled(&self) -> bool {
+        self.transactions.is_some()
+            || self.receipts.is_some()
+            || self.outcomes.is_some()
+            || self.chunk_parts.is_some()
+    }
+}
+
 fn default_num_concurrent_requests() -> u32 {
     DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_EXTERNAL
 }
@@ -82,6 +131,10 @@ fn default_num_concurrent_requests_during_catchup() -> u32 {
     DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_ON_CATCHUP_EXTERNAL
 }
 
+fn default_fallback_after_failures() -> u32 {
+    DEFAULT_STATE_SYNC_FALLBACK_AFTER_FAILURES
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct ExternalStorageConfig {
     /// Location of state parts.
@@ -94,6 +147,15 @@ pub struct ExternalStorageConfig {
     /// to reduce the performance impact of state sync.
     #[serde(default = "default_num_concurrent_requests_during_catchup")]
     pub num_concurrent_requests_during_catchup: u32,
+    /// If a range of state parts of a shard fails to download from one source
+    /// (external storage or peers) this many times in a row, it's downloaded
+    /// from the other source instead.  Zero disables the fallback.
+    #[serde(default = "default_fallback_after_failures")]
+    pub fallback_after_failures: u32,
+    /// If set, state parts are requested from peers first, and external
+    /// storage is the fallback.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prefer_peers: bool,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -153,6 +215,63 @@ pub struct StateSyncConfig {
     pub dump: Option<DumpConfig>,
     #[serde(skip_serializing_if = "SyncConfig::is_default", default = "SyncConfig::default")]
     pub sync: SyncConfig,
+    /// If set, every state part is applied as soon as it's downloaded, instead
+    /// of applying all the parts of a shard once all of them are downloaded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub apply_parts_while_downloading: bool,
+    /// If set, the state is synced to the latest final block of the current
+    /// epoch instead of the first block of the epoch, which leaves fewer
+    /// blocks to catch up on after state sync.  Only applies when syncing
+    /// from peers, since external storage only has the state of epoch starts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sync_to_latest_final_block: bool,
+    /// Limits of the bandwidth used to download and serve state parts.
+    #[serde(default, skip_serializing_if = "StateSyncBandwidthConfig::is_default")]
+    pub bandwidth: StateSyncBandwidthConfig,
+}
+
+/// Limits of the bandwidth used by state sync, so that a node syncing its
+/// state doesn't saturate the network it shares with other traffic.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct StateSyncBandwidthConfig {
+    /// Maximum average rate at which state parts are downloaded, from both
+    /// peers and external storage, per second.  Unset means unlimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_download_bytes_per_second: Option<bytesize::ByteSize>,
+    /// Maximum average rate at which state parts are served to peers, per
+    /// second.  Unset means unlimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_upload_bytes_per_second: Option<bytesize::ByteSize>,
+    /// Times of the day at which state parts are downloaded and served.  No
+    /// windows means any time.  Keep in mind that a validator which can't
+    /// catch up before the end of the epoch misses its chunks.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<StateSyncWindow>,
+}
+
+impl StateSyncBandwidthConfig {
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Time window of every day, in UTC.  A window whose end is before its start
+/// spans midnight.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateSyncWindow {
+    pub start: chrono::NaiveTime,
+    pub end: chrono::NaiveTime,
+}
+
+impl StateSyncWindow {
+    pub fn contains(&self, time: chrono::NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
 }
 
 impl SyncConfig {
@@ -162,6 +281,201 @@ impl SyncConfig {
     }
 }
 
+/// Configuration of building the state of the child shards during
+/// resharding.  It can be updated while the node is running, which also lets
+/// the operator pause and resume a running resharding job.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReshardingConfig {
+    /// Approximate size of the trie nodes and values written to the child
+    /// shards in a single batch.
+    pub batch_size: bytesize::ByteSize,
+    /// Time to wait after committing every batch.
+    pub batch_delay: Duration,
+    /// Maximum average rate at which trie nodes and values are written to the
+    /// child shards, per second.  Unset means unlimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes_per_second: Option<bytesize::ByteSize>,
+    /// If set, the resharding job stops after the current batch until it's
+    /// unset again.  Keep in mind that resharding must finish before the
+    /// epoch with the new shard layout starts.
+    pub paused: bool,
+}
+
+impl Default for ReshardingConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: bytesize::ByteSize::mib(300),
+            batch_delay: Duration::ZERO,
+            max_bytes_per_second: None,
+            paused: false,
+        }
+    }
+}
+
+/// Delays of the doomslug timer, which decides when to send approvals skipping
+/// heights at which no block was received.  The delay before skipping a height
+/// grows with the number of heights skipped since the last final block, up to
+/// `max_delay`.  It can be updated while the node is running.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DoomslugTimerConfig {
+    /// Delay before skipping the first height after the head.  Must be at
+    /// least twice `min_block_production_delay`, which is how long the node
+    /// waits before endorsing the head.
+    pub min_delay: Duration,
+    /// Increase of the delay for every further skipped height.
+    pub delay_step: Duration,
+    /// Maximum delay before skipping a height.
+    pub max_delay: Duration,
+}
+
+impl DoomslugTimerConfig {
+    /// Delays used when the doomslug timer isn't configured explicitly.
+    pub fn from_block_production_delays(
+        max_block_production_delay: Duration,
+        max_block_wait_delay: Duration,
+    ) -> Self {
+        Self {
+            min_delay: max_block_production_delay,
+            delay_step: max_block_production_delay / 10,
+            max_delay: max_block_wait_delay,
+        }
+    }
+}
+
+/// Capturing of diagnostic bundles when the node misses producing a block or
+/// a chunk assigned to it.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProductionDiagnosticsConfig {
+    /// Directory the bundles are written to.  Relative paths are relative to
+    /// the home directory.
+    pub dir: PathBuf,
+    /// Number of most recent bundles to keep, older bundles are deleted.
+    pub max_bundles: usize,
+    /// Minimum time between two bundles, so that a node which keeps missing
+    /// production doesn't keep writing bundles.
+    pub min_interval: Duration,
+}
+
+impl Default for ProductionDiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("production_diagnostics"),
+            max_bundles: 20,
+            min_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Rotation of the validator key to a successor key at a chosen epoch, without
+/// restarting the node.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ValidatorKeyRotationConfig {
+    /// File with the successor validator key.  It must belong to the same
+    /// account as the current validator key.  Relative paths are relative to
+    /// the home directory.
+    pub successor_key_file: PathBuf,
+    /// Height of the first epoch signed with the successor key.
+    pub epoch_height: EpochHeight,
+    /// File with an access key of the validator account, used to sign the
+    /// stake proposal with the successor key two epochs before the rotation.
+    /// If not set, the proposal has to be submitted manually.  Relative paths
+    /// are relative to the home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stake_proposal_key_file: Option<PathBuf>,
+}
+
+/// Signing with the validator key by an external signing service instead of a
+/// local key file.  An HSM can be used through a signing service in front of
+/// it.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RemoteSignerConfig {
+    /// Base URL of the signing service.
+    pub url: String,
+    /// Account of the validator.
+    pub account_id: AccountId,
+    /// Public key of the validator key held by the signing service.
+    pub public_key: PublicKey,
+    /// Time to wait for a signature before falling back.
+    #[serde(default = "default_remote_signer_timeout")]
+    pub timeout: Duration,
+    /// Certificates for mutual TLS with the signing service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<RemoteSignerTlsConfig>,
+    /// What to do when the signing service doesn't produce a signature.
+    #[serde(default)]
+    pub fallback: RemoteSignerFallback,
+}
+
+fn default_remote_signer_timeout() -> Duration {
+    Duration::from_millis(200)
+}
+
+/// Files of the certificates for mutual TLS, in PEM format.  Relative paths
+/// are relative to the home directory.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RemoteSignerTlsConfig {
+    /// Certificate of the authority which signed the certificate of the
+    /// signing service.
+    pub ca_cert_file: PathBuf,
+    /// Certificate the node authenticates itself with.
+    pub client_cert_file: PathBuf,
+    /// PKCS#8 private key of the client certificate.
+    pub client_key_file: PathBuf,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteSignerFallback {
+    /// Nothing is signed, and the block, chunk or approval isn't produced.
+    #[default]
+    Skip,
+    /// The signature is made with a local key, which must be the same key as
+    /// the one of the signing service.  Relative paths are relative to the
+    /// home directory.
+    LocalKey { key_file: PathBuf },
+}
+
+/// Scheduling of the background jobs of state sync, catchup and resharding.
+/// Jobs of block catchup take precedence over jobs applying state parts,
+/// which take precedence over jobs splitting the state for resharding, so a
+/// long resharding job can't stop catchup from making progress.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SyncJobsConfig {
+    /// Number of threads the jobs run on.  Should be greater than
+    /// `max_concurrent_state_splits`, so that there's always a thread left
+    /// for the other jobs.
+    pub num_threads: usize,
+    /// Maximum number of blocks applied by catchup at the same time.
+    pub max_concurrent_block_catchups: usize,
+    /// Maximum number of shards whose state parts are applied at the same
+    /// time.
+    pub max_concurrent_apply_state_parts: usize,
+    /// Maximum number of shards whose state is split at the same time.
+    pub max_concurrent_state_splits: usize,
+    /// Number of threads applying the state parts of a single shard, in
+    /// addition to the thread running the job.
+    pub apply_state_parts_parallelism: usize,
+    /// How long the node waits on shutdown for the running jobs to finish
+    /// their current batch.
+    pub drain_timeout: Duration,
+}
+
+impl Default for SyncJobsConfig {
+    fn default() -> Self {
+        Self {
+            num_threads: 3,
+            max_concurrent_block_catchups: 1,
+            max_concurrent_apply_state_parts: 1,
+            max_concurrent_state_splits: 1,
+            apply_state_parts_parallelism: 4,
+            drain_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
 /// ClientConfig where some fields can be updated at runtime.
 #[derive(Clone, serde::Serialize)]
 pub struct ClientConfig {
@@ -240,6 +554,10 @@ pub struct ClientConfig {
     /// - archive is true, cold_store is configured and migration to split_storage is finished - node
     /// working in split storage mode needs trie changes in order to do garbage collection on hot.
     pub save_trie_changes: bool,
+    /// Whether an archival node with split storage deletes transactions,
+    /// receipts and execution outcomes from the hot store as soon as they're
+    /// copied to cold storage, instead of waiting for garbage collection.
+    pub cold_store_eager_migration: bool,
     /// Number of threads for ViewClientActor pool.
     pub view_client_threads: usize,
     /// Run Epoch Sync on the start.
@@ -248,6 +566,9 @@ pub struct ClientConfig {
     pub view_client_throttle_period: Duration,
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     pub trie_viewer_state_size_limit: Option<u64>,
+    /// Upper bound of the byte size of a page of contract state returned by the paginated
+    /// `view_state` queries.
+    pub trie_viewer_page_size_limit: u64,
     /// Max burnt gas per view method.  If present, overrides value stored in
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
@@ -270,6 +591,32 @@ pub struct ClientConfig {
     /// Limit of the size of per-shard transaction pool measured in bytes. If not set, the size
     /// will be unbounded.
     pub transaction_pool_size_limit: Option<u64>,
+    /// Limit of the number of transactions in per-shard transaction pool. If not set, the number
+    /// will be unbounded.
+    pub transaction_pool_count_limit: Option<usize>,
+    /// Whether to warm up the storage caches for the receipts of the next
+    /// chunks of the tracked shards, on a background thread, once they are
+    /// known after a block is accepted.
+    pub prefetch_receipts: bool,
+    /// Fraction of `min_block_production_delay` which producing a chunk may
+    /// take. Selecting transactions stops once it's spent. If not set, chunk
+    /// production isn't limited in time.
+    pub chunk_production_time_budget: Option<f64>,
+    /// If set, replay artifacts of chunks whose outcome doesn't match the one
+    /// claimed by the next chunk are written to this directory.
+    pub chunk_replay_artifacts_dir: Option<PathBuf>,
+    /// If set, diagnostic bundles are captured when the node misses producing
+    /// a block or a chunk.
+    pub production_diagnostics: Option<ProductionDiagnosticsConfig>,
+    /// If set, the node switches to a successor validator key at the given
+    /// epoch.
+    pub validator_key_rotation: Option<ValidatorKeyRotationConfig>,
+    /// Throttling of resharding, can be updated while the node is running.
+    pub resharding_config: MutableConfigValue<ReshardingConfig>,
+    /// Delays of the doomslug timer, can be updated while the node is running.
+    pub doomslug_timer: MutableConfigValue<DoomslugTimerConfig>,
+    /// Scheduling of the background jobs of state sync, catchup and resharding.
+    pub sync_jobs: SyncJobsConfig,
     // Allows more detailed logging, for example a list of orphaned blocks.
     pub enable_multiline_logging: bool,
 }
@@ -332,11 +679,13 @@ impl ClientConfig {
             tracked_shard_schedule: vec![],
             archive,
             save_trie_changes,
+            cold_store_eager_migration: false,
             log_summary_style: LogSummaryStyle::Colored,
             view_client_threads: 1,
             epoch_sync_enabled,
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
+            trie_viewer_page_size_limit: 1024 * 1024,
             max_gas_burnt_view: None,
             enable_statistics_export: true,
             client_background_migration_threads: 1,
@@ -346,6 +695,24 @@ impl ClientConfig {
             state_sync: StateSyncConfig::default(),
             state_snapshot_every_n_blocks: None,
             transaction_pool_size_limit: None,
+            transaction_pool_count_limit: None,
+            prefetch_receipts: false,
+            chunk_production_time_budget: None,
+            chunk_replay_artifacts_dir: None,
+            production_diagnostics: None,
+            validator_key_rotation: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
+            doomslug_timer: MutableConfigValue::new(
+                DoomslugTimerConfig::from_block_production_delays(
+                    Duration::from_millis(max_block_prod_time),
+                    Duration::from_millis(3 * min_block_prod_time),
+                ),
+                "doomslug_timer",
+            ),
+            sync_jobs: SyncJobsConfig::default(),
             enable_multiline_logging: false,
         }
     }
@@ -6,9 +6,12 @@ mod metrics;
 mod updateable_config;
 
 pub use client_config::{
-    ClientConfig, DumpConfig, ExternalStorageConfig, ExternalStorageLocation, GCConfig,
-    LogSummaryStyle, StateSyncConfig, SyncConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
-    MIN_GC_NUM_EPOCHS_TO_KEEP, TEST_STATE_SYNC_TIMEOUT,
+    ClientConfig, DoomslugTimerConfig, DumpConfig, ExternalStorageConfig, ExternalStorageLocation,
+    GCConfig, LogSummaryStyle, ProductionDiagnosticsConfig, RemoteSignerConfig,
+    RemoteSignerFallback, RemoteSignerTlsConfig, ReshardingConfig, RetentionConfig,
+    StateSyncBandwidthConfig, StateSyncConfig, StateSyncWindow, SyncConfig, SyncJobsConfig,
+    ValidatorKeyRotationConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP, MIN_GC_NUM_EPOCHS_TO_KEEP,
+    TEST_STATE_SYNC_TIMEOUT,
 };
 pub use genesis_config::{
     get_initial_supply, stream_records_from_file, Genesis, GenesisChangeConfig, GenesisConfig,
@@ -1,3 +1,4 @@
+use crate::{DoomslugTimerConfig, ReshardingConfig};
 use near_primitives::types::BlockHeight;
 use serde::{Deserialize, Serialize, Serializer};
 use std::fmt::Debug;
@@ -83,9 +84,13 @@ impl<T: Copy + PartialEq + Debug> MutableConfigValue<T> {
     fn set_metric_value(&self, _value: T, _metric_value: i64) {}
 }
 
-#[derive(Default, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 /// A subset of Config that can be updated white the node is running.
 pub struct UpdateableClientConfig {
     /// Graceful shutdown at expected block height.
     pub expected_shutdown: Option<BlockHeight>,
+    /// Throttling of resharding, also used to pause and resume it.
+    pub resharding_config: ReshardingConfig,
+    /// Delays of the doomslug timer.
+    pub doomslug_timer: DoomslugTimerConfig,
 }
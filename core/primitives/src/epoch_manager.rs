@@ -1009,20 +1009,23 @@ pub enum SlashState {
     Other,
 }
 
-#[cfg(feature = "new_epoch_sync")]
 pub mod epoch_sync {
     use crate::block_header::BlockHeader;
+    use crate::epoch_manager::block_info::BlockInfo;
+    use crate::epoch_manager::epoch_info::EpochInfo;
+    use crate::merkle::PartialMerkleTree;
     use crate::types::validator_stake::ValidatorStake;
     use borsh::{BorshDeserialize, BorshSerialize};
+    use std::sync::Arc;
 
-    #[derive(BorshSerialize, BorshDeserialize)]
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
     pub struct BlockHeaderPair {
         pub header: BlockHeader,
         pub last_finalised_header: BlockHeader,
     }
 
     /// Struct to keep all the info that is transferred for one epoch during Epoch Sync.
-    #[derive(BorshSerialize, BorshDeserialize)]
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
     pub struct EpochSyncInfo {
         /// None is only used for corner case of the first epoch
         pub first: BlockHeaderPair,
@@ -1030,4 +1033,35 @@ pub mod epoch_sync {
         pub prev_last: BlockHeaderPair,
         pub block_producers: Vec<ValidatorStake>,
     }
+
+    /// Epoch manager data which the first block of an epoch commits to in its
+    /// `epoch_sync_data_hash`: infos of the first, second to last and last
+    /// blocks of the previous epoch, and infos of the previous, this and the
+    /// next epoch.
+    pub type EpochSyncData = (
+        Arc<BlockInfo>,
+        Arc<BlockInfo>,
+        Arc<BlockInfo>,
+        Arc<EpochInfo>,
+        Arc<EpochInfo>,
+        Arc<EpochInfo>,
+    );
+
+    /// Proof of the epoch manager data at the start of an epoch, which a node
+    /// doing epoch sync can verify knowing only the block producers of the
+    /// epoch.
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct EpochSyncProof {
+        /// Headers of the previous epoch.
+        pub prev_epoch_sync_info: EpochSyncInfo,
+        pub data: EpochSyncData,
+        /// Merkle tree of the blocks before the last block of the previous
+        /// epoch, which header sync continues from.
+        pub block_merkle_tree: PartialMerkleTree,
+        /// Header of the first block of the epoch.
+        pub first_header: BlockHeader,
+        /// Header of the block after the first one, whose approvals show that
+        /// the first block was accepted by the block producers.
+        pub next_header: BlockHeader,
+    }
 }
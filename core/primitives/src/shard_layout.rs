@@ -228,6 +228,15 @@ impl ShardLayout {
         }
     }
 
+    /// Returns the boundary accounts of the shard layout, None for V0 layouts
+    /// which map accounts by hash.
+    pub fn boundary_accounts(&self) -> Option<&[AccountId]> {
+        match self {
+            Self::V0(_) => None,
+            Self::V1(v1) => Some(&v1.boundary_accounts),
+        }
+    }
+
     /// Returns shard uids for all shards in the shard layout
     pub fn get_shard_uids(&self) -> Vec<ShardUId> {
         (0..self.num_shards()).map(|x| ShardUId::from_shard_id_and_layout(x, self)).collect()
@@ -99,4 +99,12 @@ impl FlatStateValue {
             Self::Inlined(value) => ValueRef::new(value),
         }
     }
+
+    /// Returns the length of the value without reading it from the trie.
+    pub fn value_len(&self) -> usize {
+        match self {
+            Self::Ref(value_ref) => value_ref.length as usize,
+            Self::Inlined(value) => value.len(),
+        }
+    }
 }
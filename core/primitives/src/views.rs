@@ -233,6 +233,51 @@ pub struct ViewStateResult {
     #[serde_as(as = "Vec<Base64>")]
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub proof: Vec<Arc<[u8]>>,
+    /// Set if the request was paginated and there are more values to fetch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<ContinuationToken>,
+}
+
+/// Position in a paginated response, returned with a page to fetch the next one.
+/// It is bound to the block of the first page, so that all the pages are consistent.
+/// Serialized as an opaque base64 string.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ContinuationToken {
+    pub block_hash: CryptoHash,
+    /// Meaning depends on the endpoint, e.g. the last key returned.
+    pub position: Vec<u8>,
+}
+
+impl serde::Serialize for ContinuationToken {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = self.try_to_vec().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&crate::serialize::to_base64(&bytes))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ContinuationToken {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = <String as serde::Deserialize>::deserialize(deserializer)?;
+        let bytes = crate::serialize::from_base64(&encoded)
+            .map_err(|_| serde::de::Error::custom("invalid continuation token"))?;
+        Self::try_from_slice(&bytes)
+            .map_err(|_| serde::de::Error::custom("invalid continuation token"))
+    }
+}
+
+/// Requests a paginated response.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct Pagination {
+    /// Token returned with the previous page, or None to fetch the first page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<ContinuationToken>,
+}
+
+impl Pagination {
+    /// Block to which the pagination is bound, if it is not the first page.
+    pub fn block_hash(&self) -> Option<CryptoHash> {
+        self.continuation_token.as_ref().map(|token| token.block_hash)
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone, Default)]
@@ -310,6 +355,10 @@ pub enum QueryRequest {
         prefix: StoreKey,
         #[serde(default, skip_serializing_if = "is_false")]
         include_proof: bool,
+        /// If set, the values are returned in pages of limited size.
+        /// Unlike the full response, a page is not limited by the size of the contract state.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pagination: Option<Pagination>,
     },
     ViewAccessKey {
         account_id: AccountId,
@@ -326,6 +375,15 @@ pub enum QueryRequest {
     },
 }
 
+impl QueryRequest {
+    pub fn pagination(&self) -> Option<&Pagination> {
+        match self {
+            QueryRequest::ViewState { pagination, .. } => pagination.as_ref(),
+            _ => None,
+        }
+    }
+}
+
 fn is_false(v: &bool) -> bool {
     !*v
 }
@@ -486,10 +544,96 @@ pub struct NetworkRoutesView {
     pub my_distances: HashMap<PeerId, u32>,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct PeerBandwidthView {
+    pub peer_id: PeerId,
+    pub addr: String,
+    pub tier: String,
+    /// Average over the last minute.
+    pub received_bytes_per_sec: u64,
+    /// Average over the last minute.
+    pub sent_bytes_per_sec: u64,
+    /// Bytes received since the connection was established, by message type.
+    pub received_bytes_by_type: HashMap<String, u64>,
+    /// Bytes sent since the connection was established, by message type.
+    pub sent_bytes_by_type: HashMap<String, u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct NetworkBandwidthView {
+    /// Connections using the most bandwidth first.
+    pub peers: Vec<PeerBandwidthView>,
+}
+
+/// Rule of the peer filter, which the node operator can change at runtime.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerFilterRuleView {
+    BanPeer(PeerId),
+    /// Bans the peer which announced the account.
+    BanAccount(AccountId),
+    /// IP address or a range of addresses in the CIDR notation, e.g. "192.0.2.0/24".
+    BanIpRange(String),
+    /// Allows the peer to connect even if the connection limit has been reached or its
+    /// IP address is banned.
+    AllowPeer(PeerId),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct PeerFilterEntryView {
+    pub rule: PeerFilterRuleView,
+    /// Unix timestamp in milliseconds, None if the rule doesn't expire.
+    pub expires_at_ms: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct PeerFilterView {
+    pub entries: Vec<PeerFilterEntryView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct RouteTraceHopView {
+    pub peer_id: PeerId,
+    /// Unix timestamp in milliseconds, according to the clock of the peer.
+    pub received_at_ms: i64,
+    /// Time since the traced message was sent, in milliseconds.
+    pub since_sent_ms: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct RouteTraceView {
+    pub msg_hash: CryptoHash,
+    pub target: PeerId,
+    /// Unix timestamp in milliseconds.
+    pub sent_at_ms: i64,
+    /// Hops of the route, starting with this node. None until the target echoes the trace.
+    pub hops: Option<Vec<RouteTraceHopView>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct RouteTracesView {
+    /// Most recent traces first.
+    pub traces: Vec<RouteTraceView>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
 pub struct ShardSyncDownloadView {
     pub downloads: Vec<DownloadStatusView>,
     pub status: String,
+    /// Progress of applying the state parts or splitting the state, if the
+    /// job has reported any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<SyncJobProgressView>,
+}
+
+/// Progress of a long running state sync job of a shard.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SyncJobProgressView {
+    /// Number of state parts applied, or trie nodes and delayed receipts
+    /// processed when splitting the state.
+    pub done: u64,
+    pub total: u64,
+    pub bytes_written: u64,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
@@ -517,6 +661,33 @@ pub struct RequestedStatePartsView {
     pub shard_requested_parts: HashMap<ShardId, Vec<PartElapsedTimeView>>,
 }
 
+/// Occupancy of the transaction pool of a shard.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct TransactionPoolView {
+    pub shard_uid: String,
+    pub num_transactions: u64,
+    // Number of transactions held back because of gaps in the nonces.
+    pub num_future_transactions: u64,
+    // Number of (account ID, signer public key) pairs with transactions in the pool.
+    pub num_groups: u64,
+    pub size_bytes: u64,
+    pub size_limit_bytes: Option<u64>,
+    pub count_limit: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct ColumnStatsView {
+    pub column: String,
+    // Estimated number of entries in the column.
+    pub estimated_num_keys: u64,
+    // Estimated size of live data in the column in bytes.
+    pub estimated_live_data_size: u64,
+    // Total size of all SST files of the column in bytes.
+    pub total_sst_files_size: u64,
+    // Number of SST files of the column across all levels.
+    pub num_sst_files: u64,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
 pub struct BlockStatusView {
     pub height: BlockHeight,
@@ -626,6 +797,63 @@ pub enum DroppedReason {
     TooManyProcessingBlocks,
 }
 
+/// Stage of processing a block, see [`BlockTimelineView`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BlockTimelineStage {
+    Preprocessing,
+    ApplyChunk(ShardId),
+    Postprocessing,
+    /// The block became the new head.  Recorded as a point in time.
+    HeadUpdate,
+    /// An approval endorsing the block was sent.  Recorded as a point in time.
+    ApprovalSent,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockTimelineStageView {
+    pub stage: BlockTimelineStage,
+    /// Time (in ms) between when the block was received and when the stage started.
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// Timeline of processing a block, from when it was received by the chain.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockTimelineView {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+    pub received_timestamp: DateTime<chrono::Utc>,
+    /// Stages in the order they were recorded.
+    pub stages: Vec<BlockTimelineStageView>,
+}
+
+/// A block which failed validation, see the `quarantined_blocks` debug API.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct QuarantinedBlockView {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+    pub prev_hash: CryptoHash,
+    pub error: String,
+    /// Peer the block was received from, if known.
+    pub peer_id: Option<PeerId>,
+    pub quarantined_timestamp: DateTime<chrono::Utc>,
+}
+
+/// A quarantined block together with its contents.
+#[serde_as]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct QuarantinedBlockDetailsView {
+    #[serde(flatten)]
+    pub entry: QuarantinedBlockView,
+    pub header: BlockHeaderView,
+    pub chunks: Vec<ChunkHeaderView>,
+    /// Borsh serialized block, so that it can be processed again e.g. in
+    /// a test.
+    #[serde(rename = "block_base64")]
+    #[serde_as(as = "Base64")]
+    pub block: Vec<u8>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct ChunkProcessingInfo {
     pub height_created: BlockHeight,
@@ -1769,6 +1997,44 @@ pub struct FinalExecutionOutcomeWithReceiptView {
     pub receipts: Vec<ReceiptView>,
 }
 
+/// Transaction with the tree of the receipts it caused, see `ReceiptExecutionTreeView`.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TransactionExecutionTreeView {
+    pub transaction: SignedTransactionView,
+    pub transaction_outcome: ExecutionOutcomeWithIdView,
+    /// Receipts created by the transaction, in the order of `transaction_outcome.receipt_ids`.
+    pub receipts: Vec<ReceiptExecutionTreeView>,
+}
+
+/// Receipt with its execution outcome and the receipts created by its execution.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ReceiptExecutionTreeView {
+    pub receipt_id: CryptoHash,
+    /// None for the receipts which are not stored by the node, e.g. the local receipts.
+    pub receipt: Option<ReceiptView>,
+    /// None if the receipt hasn't been executed yet.
+    pub outcome: Option<ExecutionOutcomeWithIdView>,
+    /// Receipts created by the execution, in the order of `outcome.receipt_ids`.
+    pub children: Vec<ReceiptExecutionTreeView>,
+}
+
+/// What to run in a simulation on top of the state of a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationRequest {
+    /// A view call, like `QueryRequest::CallFunction`.
+    CallFunction { account_id: AccountId, method_name: String, args: FunctionArgs },
+    /// A transaction, together with the receipts it causes.
+    Transaction(SignedTransaction),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(tag = "simulation_type", rename_all = "snake_case")]
+pub enum SimulationResultView {
+    CallFunction(CallResult),
+    /// The status is `Started` if the execution didn't finish within the simulated blocks.
+    Transaction(FinalExecutionOutcomeView),
+}
+
 pub mod validator_stake_view {
     pub use super::ValidatorStakeViewV1;
     use crate::types::validator_stake::ValidatorStake;
@@ -2101,17 +2367,90 @@ impl LightClientBlockLiteView {
     }
 }
 
+/// Proof of the value of a contract storage key in the state of a shard committed in a block
+/// header. The state root is a leaf of the `prev_state_root` of the header, so it is the state
+/// of the shard as of the last chunk included before the block.
+#[serde_as]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StateProofView {
+    pub shard_id: ShardId,
+    pub state_root: StateRoot,
+    /// None if the key doesn't exist.
+    #[serde(rename = "value_base64")]
+    pub value: Option<StoreValue>,
+    /// Trie nodes on the path from the state root to the key.
+    #[serde_as(as = "Vec<Base64>")]
+    pub proof: Vec<Arc<[u8]>>,
+    /// Path from the state root to the `prev_state_root` of the block header.
+    pub state_root_proof: MerklePath,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct GasPriceView {
     #[serde(with = "dec_format")]
     pub gas_price: Balance,
 }
 
+/// Gas usage of the shards in the recent blocks, with the gas price of the head block.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GasCongestionView {
+    /// Head block, the last of the counted blocks.
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    #[serde(with = "dec_format")]
+    pub gas_price: Balance,
+    /// Number of blocks counted.
+    pub num_blocks: NumBlocks,
+    pub shards: Vec<ShardCongestionView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardCongestionView {
+    pub shard_id: ShardId,
+    /// Number of new chunks of the shard in the counted blocks.
+    pub num_chunks: NumBlocks,
+    /// Gas used and gas limit, summed over the counted chunks.
+    pub gas_used: Gas,
+    pub gas_limit: Gas,
+    /// Number of transactions in the counted chunks, None if the node doesn't track the shard.
+    pub num_transactions: Option<u64>,
+    /// Transactions waiting in the pool of the node, None if the node doesn't track the shard.
+    pub pool: Option<TxPoolStatsView>,
+    /// Estimated number of blocks until a transaction sent now is included in a chunk.
+    /// None if it can't be estimated, or if the requested gas price is below the current one.
+    pub estimated_inclusion_delay: Option<NumBlocks>,
+}
+
+impl ShardCongestionView {
+    /// Estimates the inclusion delay assuming that the transactions already in the pool are
+    /// included first, at the rate of the counted chunks.
+    pub fn estimate_inclusion_delay(&self) -> Option<NumBlocks> {
+        if self.num_chunks == 0 {
+            return None;
+        }
+        // Chunks which are less than half full take all the pending transactions.
+        if self.gas_used.saturating_mul(2) < self.gas_limit {
+            return Some(1);
+        }
+        let per_chunk = (self.num_transactions? / self.num_chunks).max(1);
+        Some(1 + self.pool.as_ref()?.num_transactions / per_chunk)
+    }
+}
+
+/// Transactions of a shard waiting in the pool of the node.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxPoolStatsView {
+    pub shard_id: ShardId,
+    pub num_transactions: u64,
+    /// Total size of the transactions in bytes.
+    pub size: u64,
+}
+
 /// It is a [serializable view] of [`StateChangesRequest`].
 ///
 /// [serializable view]: ./index.html
 /// [`StateChangesRequest`]: ../types/struct.StateChangesRequest.html
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "changes_type", rename_all = "snake_case")]
 pub enum StateChangesRequestView {
     AccountChanges {
@@ -2962,3 +3301,27 @@ mod tests {
         insta::assert_json_snapshot!(view);
     }
 }
+
+/// Participation of validators in approving the blocks of an epoch, counted
+/// over the blocks of the epoch which became the head of the node while it
+/// was running.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochParticipationView {
+    pub epoch_id: EpochId,
+    /// Heights of the first and the last block counted.
+    pub first_height: BlockHeight,
+    pub last_height: BlockHeight,
+    /// Number of blocks counted.
+    pub num_blocks: NumBlocks,
+    /// Validators which could approve the blocks, the least participating first.
+    pub validators: Vec<ValidatorParticipationView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorParticipationView {
+    pub account_id: AccountId,
+    /// Number of counted blocks which could contain an approval of the validator.
+    pub num_expected_approvals: NumBlocks,
+    /// Number of counted blocks which contain an approval of the validator.
+    pub num_included_approvals: NumBlocks,
+}
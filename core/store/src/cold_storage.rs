@@ -1,9 +1,10 @@
 use crate::columns::DBKeyType;
-use crate::db::{ColdDB, COLD_HEAD_KEY, HEAD_KEY};
+use crate::db::ColdDB;
+use crate::intent_log::{write_cold_head, Intent, IntentLog};
 use crate::trie::TrieRefcountChange;
 use crate::{metrics, DBCol, DBTransaction, Database, Store, TrieChanges};
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use near_primitives::block::{Block, BlockHeader, Tip};
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardLayout;
@@ -11,6 +12,7 @@ use near_primitives::sharding::ShardChunk;
 use near_primitives::types::BlockHeight;
 use std::collections::HashMap;
 use std::io;
+use std::time::{Duration, Instant};
 use strum::IntoEnumIterator;
 
 type StoreKey = Vec<u8>;
@@ -34,6 +36,77 @@ struct BatchTransaction {
     threshold_transaction_size: usize,
 }
 
+/// Progress of copying a single column from hot to cold storage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColdColumnCopyProgress {
+    /// Number of heights for which the column was copied by `update_cold_db`.
+    pub heights_copied: u64,
+    /// Number of bytes of keys and values written to cold storage, including
+    /// the initial migration.
+    pub bytes_copied: u64,
+}
+
+/// Progress of copying data from hot to cold storage since the start of the
+/// node, see `ColdDB::copy_progress`.
+#[derive(Clone, Debug, Default)]
+pub struct ColdStoreCopyProgress {
+    pub columns: HashMap<DBCol, ColdColumnCopyProgress>,
+    /// Height of the cold head, if it was updated since the start of the node.
+    pub cold_head_height: Option<BlockHeight>,
+    /// Height up to which data has to be copied, usually the hot final head.
+    pub target_height: Option<BlockHeight>,
+    /// Number of heights copied by `update_cold_db`.
+    pub heights_copied: u64,
+    /// Time spent on copying these heights, including throttling.
+    pub copy_time: Duration,
+}
+
+impl ColdStoreCopyProgress {
+    pub fn total_bytes_copied(&self) -> u64 {
+        self.columns.values().map(|column| column.bytes_copied).sum()
+    }
+
+    /// Number of heights between the cold head and the target height.
+    pub fn remaining_heights(&self) -> Option<u64> {
+        Some(self.target_height?.saturating_sub(self.cold_head_height?))
+    }
+
+    /// Estimated time for the cold head to reach the target height, based on
+    /// the average time spent on copying a single height.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining_heights = self.remaining_heights()?;
+        if self.heights_copied == 0 {
+            return None;
+        }
+        Some(self.copy_time.mul_f64(remaining_heights as f64 / self.heights_copied as f64))
+    }
+}
+
+/// Limits the rate of writes to cold storage, so that copying data doesn't
+/// starve the hot storage of IO during peak traffic.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ColdCopyThrottle {
+    /// Maximum average number of bytes written per second. No limit if None.
+    max_bytes_per_second: Option<u64>,
+}
+
+impl ColdCopyThrottle {
+    pub fn new(max_bytes_per_second: Option<u64>) -> Self {
+        Self { max_bytes_per_second }
+    }
+
+    /// Returns how long to wait after writing `bytes` in `elapsed` time, so
+    /// that the write rate doesn't exceed the limit.
+    pub fn delay(&self, bytes: u64, elapsed: Duration) -> Duration {
+        match self.max_bytes_per_second {
+            None => Duration::ZERO,
+            Some(limit) => {
+                Duration::from_secs_f64(bytes as f64 / limit.max(1) as f64).saturating_sub(elapsed)
+            }
+        }
+    }
+}
+
 /// Updates provided cold database from provided hot store with information about block at `height`.
 /// Returns if the block was copied (false only if height is not present in `hot_store`).
 /// Block as `height` has to be final.
@@ -60,28 +133,78 @@ pub fn update_cold_db(
     shard_layout: &ShardLayout,
     height: &BlockHeight,
 ) -> io::Result<bool> {
+    Ok(copy_height_to_cold(cold_db, hot_store, shard_layout, height)?.is_some())
+}
+
+/// Same as `update_cold_db`, but after copying the block waits long enough
+/// for the rate of writes to cold storage to stay within `throttle` limit.
+pub fn update_cold_db_throttled(
+    cold_db: &ColdDB,
+    hot_store: &Store,
+    shard_layout: &ShardLayout,
+    height: &BlockHeight,
+    throttle: &ColdCopyThrottle,
+) -> io::Result<bool> {
+    let start = Instant::now();
+    let Some(bytes) = copy_height_to_cold(cold_db, hot_store, shard_layout, height)? else {
+        return Ok(false);
+    };
+    let delay = throttle.delay(bytes, start.elapsed());
+    if !delay.is_zero() {
+        tracing::debug!(target: "cold_store", height, bytes, ?delay, "Throttling copy to cold storage");
+        metrics::COLD_COPY_THROTTLE_DELAY.observe(delay.as_secs_f64());
+        std::thread::sleep(delay);
+        cold_db.update_copy_progress(|progress| progress.copy_time += delay);
+    }
+    Ok(true)
+}
+
+/// Implementation of `update_cold_db`.
+/// Returns the number of bytes written to cold db, or None if height is not present in `hot_store`.
+fn copy_height_to_cold(
+    cold_db: &ColdDB,
+    hot_store: &Store,
+    shard_layout: &ShardLayout,
+    height: &BlockHeight,
+) -> io::Result<Option<u64>> {
     let _span = tracing::debug_span!(target: "store", "update cold db", height = height);
     let _timer = metrics::COLD_COPY_DURATION.start_timer();
+    let start = Instant::now();
 
     let mut store_with_cache = StoreWithCache { store: hot_store, cache: StoreCache::new() };
 
     if store_with_cache.get(DBCol::BlockHeight, &height.to_le_bytes())?.is_none() {
-        return Ok(false);
+        return Ok(None);
     }
 
     let key_type_to_keys = get_keys_from_store(&mut store_with_cache, shard_layout, height)?;
+    let mut column_bytes = vec![];
     for col in DBCol::iter() {
         if col.is_cold() {
-            copy_from_store(
+            let bytes = copy_from_store(
                 cold_db,
                 &mut store_with_cache,
                 col,
                 combine_keys(&key_type_to_keys, &col.key_type()),
             )?;
+            column_bytes.push((col, bytes));
         }
     }
 
-    Ok(true)
+    cold_db.update_copy_progress(|progress| {
+        for &(col, bytes) in &column_bytes {
+            let column = progress.columns.entry(col).or_default();
+            column.heights_copied += 1;
+            column.bytes_copied += bytes;
+            let column_label = [<&str>::from(col)];
+            metrics::COLD_COPY_HEIGHTS.with_label_values(&column_label).inc();
+            metrics::COLD_COPY_BYTES.with_label_values(&column_label).inc_by(bytes);
+        }
+        progress.heights_copied += 1;
+        progress.copy_time += start.elapsed();
+    });
+
+    Ok(Some(column_bytes.iter().map(|(_, bytes)| bytes).sum()))
 }
 
 // Correctly set the key and value on DBTransaction, taking reference counting
@@ -112,15 +235,17 @@ fn rc_aware_set(
 /// Gets values for given keys in a column from provided hot_store.
 /// Creates a transaction based on that values with set DBOp s.
 /// Writes that transaction to cold_db.
+/// Returns the number of bytes written.
 fn copy_from_store(
     cold_db: &ColdDB,
     hot_store: &mut StoreWithCache,
     col: DBCol,
     keys: Vec<StoreKey>,
-) -> io::Result<()> {
+) -> io::Result<u64> {
     let _span = tracing::debug_span!(target: "store", "create and write transaction to cold db", col = %col);
 
     let mut transaction = DBTransaction::new();
+    let mut transaction_size = 0;
     for key in keys {
         // TODO: Look into using RocksDB’s multi_key function.  It
         // might speed things up.  Currently our Database abstraction
@@ -135,11 +260,11 @@ fn copy_from_store(
             // write raw bytes. This would also allow us to bypass stripping and
             // re-adding the reference count.
 
-            rc_aware_set(&mut transaction, col, key, value);
+            transaction_size += rc_aware_set(&mut transaction, col, key, value);
         }
     }
     cold_db.write(transaction)?;
-    return Ok(());
+    return Ok(transaction_size as u64);
 }
 
 /// This function sets the cold head to the Tip that reflect provided height in two places:
@@ -164,28 +289,13 @@ pub fn update_cold_head(
     let tip_header = &store.get_ser_or_err::<BlockHeader>(DBCol::BlockHeader, &block_hash_key)?;
     let tip = Tip::from_header(tip_header);
 
-    // Write HEAD to the cold db.
-    {
-        let mut transaction = DBTransaction::new();
-        transaction.set(DBCol::BlockMisc, HEAD_KEY.to_vec(), tip.try_to_vec()?);
-        cold_db.write(transaction)?;
-    }
-
-    // Write COLD_HEAD_KEY to the cold db.
-    {
-        let mut transaction = DBTransaction::new();
-        transaction.set(DBCol::BlockMisc, COLD_HEAD_KEY.to_vec(), tip.try_to_vec()?);
-        cold_db.write(transaction)?;
-    }
-
-    // Write COLD_HEAD to the hot db.
-    {
-        let mut transaction = DBTransaction::new();
-        transaction.set(DBCol::BlockMisc, COLD_HEAD_KEY.to_vec(), tip.try_to_vec()?);
-        hot_store.storage.write(transaction)?;
-
-        crate::metrics::COLD_HEAD_HEIGHT.set(*height as i64);
-    }
+    // The heads are written to both databases separately so record the update
+    // first to complete it after a crash.
+    let intent_log = IntentLog::new(hot_store.clone());
+    let intent = intent_log.begin(Intent::UpdateColdHead { tip: tip.clone() })?;
+    write_cold_head(cold_db, hot_store, &tip)?;
+    intent.finish()?;
+    cold_db.update_copy_progress(|progress| progress.cold_head_height = Some(*height));
 
     return Ok(());
 }
@@ -521,7 +631,8 @@ impl BatchTransaction {
             return Ok(());
         }
 
-        let column_label = [<&str>::from(self.transaction.ops[0].col())];
+        let col = self.transaction.ops[0].col();
+        let column_label = [<&str>::from(col)];
 
         crate::metrics::COLD_STORE_MIGRATION_BATCH_WRITE_COUNT
             .with_label_values(&column_label)
@@ -538,6 +649,11 @@ impl BatchTransaction {
 
         let transaction = std::mem::take(&mut self.transaction);
         self.cold_db.write(transaction)?;
+        let bytes = self.transaction_size as u64;
+        self.cold_db.update_copy_progress(|progress| {
+            progress.columns.entry(col).or_default().bytes_copied += bytes;
+        });
+        crate::metrics::COLD_COPY_BYTES.with_label_values(&column_label).inc_by(bytes);
         self.transaction_size = 0;
 
         Ok(())
@@ -546,9 +662,34 @@ impl BatchTransaction {
 
 #[cfg(test)]
 mod test {
-    use super::{combine_keys, StoreKey};
+    use super::{combine_keys, ColdCopyThrottle, ColdStoreCopyProgress, StoreKey};
     use crate::columns::DBKeyType;
     use std::collections::{HashMap, HashSet};
+    use std::time::Duration;
+
+    #[test]
+    fn test_cold_copy_throttle() {
+        let unlimited = ColdCopyThrottle::default();
+        assert_eq!(unlimited.delay(1 << 30, Duration::ZERO), Duration::ZERO);
+
+        let throttle = ColdCopyThrottle::new(Some(1000));
+        assert_eq!(throttle.delay(500, Duration::ZERO), Duration::from_millis(500));
+        assert_eq!(throttle.delay(500, Duration::from_millis(200)), Duration::from_millis(300));
+        assert_eq!(throttle.delay(500, Duration::from_secs(1)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_cold_copy_eta() {
+        let mut progress = ColdStoreCopyProgress::default();
+        assert_eq!(progress.eta(), None);
+        progress.cold_head_height = Some(10);
+        progress.target_height = Some(30);
+        assert_eq!(progress.remaining_heights(), Some(20));
+        assert_eq!(progress.eta(), None);
+        progress.heights_copied = 5;
+        progress.copy_time = Duration::from_secs(10);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(40)));
+    }
 
     #[test]
     fn test_combine_keys() {
@@ -277,6 +277,34 @@ pub enum DBCol {
     /// - *Rows*: arbitrary string, see `crate::db::FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS_KEY` for example
     /// - *Column type*: arbitrary bytes
     Misc,
+    /// Skip pointer of each block header, i.e. hash and height of an ancestor
+    /// further back than the previous block.  Used to find ancestors of blocks
+    /// in a logarithmic number of steps, see `ChainStore::get_block_hash_on_canonical_chain`.
+    /// - *Rows*: BlockHash (CryptoHash)
+    /// - *Column type*: (CryptoHash, BlockHeight)
+    BlockSkipPointers,
+    /// Blocks which failed validation together with the error and the peer
+    /// they were received from.  Only a bounded number of the most recent
+    /// ones is kept, see `ChainStore::quarantine_block`.
+    /// - *Rows*: BlockHash (CryptoHash)
+    /// - *Column type*: near-chain QuarantinedBlock
+    QuarantinedBlocks,
+    /// Progress of building the state of the child shards during resharding,
+    /// saved with every batch so that resharding resumes after a restart.
+    /// Removed once the state of the child shards is complete.
+    /// - *Rows*: child `shard_uid`
+    /// - *Column type*: near-chain ReshardingCheckpoint
+    ReshardingProgress,
+    /// Reputation scores of the peers, kept across restarts so that flaky
+    /// peers aren't trusted again right after a restart.
+    /// - *Rows*: single row (empty row name)
+    /// - *Content type*: Vec of network PeerReputation
+    PeerReputation,
+    /// Outcomes of the recent outbound connection attempts to each address,
+    /// used to prefer the addresses which were reachable in the past.
+    /// - *Rows*: single row (empty row name)
+    /// - *Content type*: Vec of network AddrQuality
+    PeerAddrQuality,
     /// Column to store data for Epoch Sync.
     /// Does not contain data for genesis epoch.
     /// - *Rows*: `epoch_id`
@@ -292,7 +320,8 @@ pub enum DBCol {
 /// Currently only used in cold storage continuous migration.
 #[derive(PartialEq, Copy, Clone, Debug, Hash, Eq, strum::EnumIter)]
 pub enum DBKeyType {
-    /// Empty row name. Used in DBCol::LastComponentNonce and DBCol::RecentOutboundConnections
+    /// Empty row name. Used in DBCol::LastComponentNonce, DBCol::RecentOutboundConnections,
+    /// DBCol::PeerReputation and DBCol::PeerAddrQuality
     Empty,
     /// Set of predetermined strings. Used, for example, in DBCol::BlockMisc
     StringLiteral,
@@ -449,13 +478,20 @@ impl DBCol {
             DBCol::ProcessedBlockHeights => false,
             // HeaderHashesByHeight is only needed for GC.
             DBCol::HeaderHashesByHeight => false,
+            // QuarantinedBlocks are only kept for debugging invalid blocks.
+            DBCol::QuarantinedBlocks => false,
+            // ReshardingProgress is only needed while resharding.
+            DBCol::ReshardingProgress => false,
 
             // Columns that are not GC-ed need not be copied to the cold storage.
             DBCol::BlockHeader
+            | DBCol::BlockSkipPointers
             | DBCol::_GCCount
             | DBCol::BlockHeight
             | DBCol::_Peers
             | DBCol::RecentOutboundConnections
+            | DBCol::PeerReputation
+            | DBCol::PeerAddrQuality
             | DBCol::BlockMerkleTree
             | DBCol::AccountAnnouncements
             | DBCol::EpochLightClientBlocks
@@ -487,6 +523,21 @@ impl DBCol {
         matches!(*self, DBCol::DbVersion | DBCol::BlockMisc) || self.is_cold()
     }
 
+    /// Time after which values written to this column are deleted by default,
+    /// if the column holds ephemeral data.  See [`crate::ttl`].
+    ///
+    /// Only values of columns with a default TTL expire.  The TTL can be
+    /// changed with [`crate::config::ColumnConfig::ttl`].
+    pub const fn default_ttl(&self) -> Option<std::time::Duration> {
+        match self {
+            // Parts are cached by nodes serving state sync and kept by nodes
+            // syncing state until they're applied.  A few epochs are enough
+            // for both.
+            DBCol::StateParts => Some(std::time::Duration::from_secs(3 * 24 * 60 * 60)),
+            _ => None,
+        }
+    }
+
     /// Vector of DBKeyType s concatenation of which results in key for the column.
     pub fn key_type(&self) -> &'static [DBKeyType] {
         match self {
@@ -503,6 +554,8 @@ impl DBCol {
             DBCol::IncomingReceipts => &[DBKeyType::BlockHash, DBKeyType::ShardId],
             DBCol::_Peers => &[DBKeyType::PeerId],
             DBCol::RecentOutboundConnections => &[DBKeyType::Empty],
+            DBCol::PeerReputation => &[DBKeyType::Empty],
+            DBCol::PeerAddrQuality => &[DBKeyType::Empty],
             DBCol::EpochInfo => &[DBKeyType::EpochId],
             DBCol::BlockInfo => &[DBKeyType::BlockHash],
             DBCol::Chunks => &[DBKeyType::ChunkHash],
@@ -547,6 +600,9 @@ impl DBCol {
             DBCol::FlatStateChanges => &[DBKeyType::ShardUId, DBKeyType::BlockHash],
             DBCol::FlatStateDeltaMetadata => &[DBKeyType::ShardUId, DBKeyType::BlockHash],
             DBCol::FlatStorageStatus => &[DBKeyType::ShardUId],
+            DBCol::BlockSkipPointers => &[DBKeyType::BlockHash],
+            DBCol::QuarantinedBlocks => &[DBKeyType::BlockHash],
+            DBCol::ReshardingProgress => &[DBKeyType::ShardUId],
             #[cfg(feature = "new_epoch_sync")]
             DBCol::EpochSyncInfo => &[DBKeyType::EpochId],
         }
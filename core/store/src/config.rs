@@ -1,4 +1,6 @@
 use near_primitives::shard_layout::ShardUId;
+use near_primitives::state::FlatStateValue;
+use near_primitives::types::BlockHeightDelta;
 use std::time::Duration;
 use std::{collections::HashMap, iter::FromIterator};
 
@@ -30,6 +32,31 @@ pub struct StoreConfig {
     /// Linux.
     pub max_open_files: u32,
 
+    /// Verify checksums of the whole database when opening it and, if RocksDB
+    /// reports corruption, repair the database instead of failing to start.
+    /// Metadata and damaged files are backed up to a `corruption-backup-*`
+    /// directory inside of the database directory, along with a report of
+    /// affected columns.
+    /// Verification reads the whole database, which may take hours, so this
+    /// should only be enabled to recover a node which fails because of
+    /// corruption.
+    pub corruption_recovery: bool,
+
+    /// Encrypt values stored in the database with a 256-bit key obtained from
+    /// given source.  Keys of the entries are not encrypted.  This also
+    /// covers checkpoints made from the database, e.g. state snapshots, and
+    /// values the cold storage keeps in object storage.
+    /// Encryption has to be configured when the database is created; an
+    /// unencrypted database can't be opened with encryption enabled and vice
+    /// versa.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionKeySource>,
+
+    /// Configuration specific to the cold storage.  Ignored when configuring
+    /// the hot storage.
+    #[serde(flatten)]
+    pub cold: ColdStoreConfig,
+
     /// Cache size for DBCol::State column.
     /// Default value: 512MiB.
     /// Increasing DBCol::State cache size helps making storage more efficient. On the other hand we
@@ -37,6 +64,12 @@ pub struct StoreConfig {
     /// default value for it.
     pub col_state_cache_size: bytesize::ByteSize,
 
+    /// RocksDB settings of individual columns, keyed by column name (e.g.
+    /// `"FlatState"`), overriding the global ones.  This allows, for example,
+    /// sizing block caches of `State` and `FlatState` independently.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub column_configs: HashMap<String, ColumnConfig>,
+
     /// Block size used internally in RocksDB.
     /// Default value: 16KiB.
     /// We're still experimenting with this parameter and it seems decreasing its value can improve
@@ -105,6 +138,40 @@ pub struct StoreConfig {
     // State Snapshot compaction usually is a good thing.
     // It makes state snapshots tiny (10GB) over the course of an epoch.
     pub state_snapshot_compaction_enabled: bool,
+
+    /// If set, state parts are generated from flat storage in batches of key-values
+    /// of at most this size, instead of reading all key-values of a part at once.
+    pub state_part_memory_limit: Option<bytesize::ByteSize>,
+
+    /// Memory placement and growth policy of the arenas backing in-memory
+    /// tries.
+    pub mem_trie_arena: MemTrieArenaConfig,
+
+    /// Periodic comparison of in-memory tries against the disk trie and
+    /// flat storage.
+    pub mem_trie_consistency_check: MemTrieConsistencyCheckConfig,
+
+    /// Shards whose state, as of the flat storage head, is loaded into
+    /// in-memory tries when the node starts, and kept up to date as chunks are
+    /// applied. Shards whose flat storage isn't ready yet, e.g. on the first
+    /// start of the node, are skipped.
+    pub load_mem_tries_for_shards: Vec<ShardUId>,
+
+    /// Save the in-memory tries to the `mem_tries` directory in the home
+    /// directory on shutdown, and load them from there on the next start
+    /// instead of rebuilding them from flat storage.
+    pub save_mem_tries_on_shutdown: bool,
+
+    /// Background job inlining small values referenced from flat storage.
+    pub flat_state_values_inlining: FlatStateValuesInliningConfig,
+
+    /// Removal of flat storage deltas which can no longer be applied,
+    /// independently of chain garbage collection.
+    pub flat_storage_delta_pruning: FlatStorageDeltaPruningConfig,
+
+    /// Limits on reads done on behalf of RPC requests, e.g. view calls, so
+    /// they don't slow down block and chunk processing.
+    pub rpc_read_limits: ReadLimitsConfig,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -163,14 +230,114 @@ impl StoreConfig {
         Self { max_open_files: 512, ..Self::default() }
     }
 
+    /// Returns settings overridden for given column, if any.
+    pub fn col_config(&self, col: crate::DBCol) -> Option<&ColumnConfig> {
+        self.column_configs.get(<&str>::from(col))
+    }
+
     /// Returns cache size for given column.
-    pub const fn col_cache_size(&self, col: crate::DBCol) -> bytesize::ByteSize {
+    pub fn col_cache_size(&self, col: crate::DBCol) -> bytesize::ByteSize {
+        if let Some(size) = self.col_config(col).and_then(|config| config.block_cache_size) {
+            return size;
+        }
         match col {
             crate::DBCol::State => self.col_state_cache_size,
             crate::DBCol::FlatState => self.col_state_cache_size,
             _ => bytesize::ByteSize::mib(32),
         }
     }
+
+    /// Returns time after which values written to given column are deleted or
+    /// `None` if values of the column don't expire.
+    pub fn col_ttl(&self, col: crate::DBCol) -> Option<Duration> {
+        let default = col.default_ttl()?;
+        Some(self.col_config(col).and_then(|config| config.ttl).unwrap_or(default))
+    }
+
+    /// Checks that per-column settings refer to existing columns and have
+    /// sensible values.  Returns description of every problem found.
+    pub fn validate_column_configs(&self) -> Vec<String> {
+        use strum::IntoEnumIterator;
+        let mut errors = Vec::new();
+        for (name, config) in &self.column_configs {
+            if !crate::DBCol::iter().any(|col| <&str>::from(col) == name) {
+                errors.push(format!("column_configs: unknown column {name:?}"));
+            }
+            if config.block_cache_size.map_or(false, |size| size.as_u64() == 0) {
+                errors.push(format!("column_configs.{name}.block_cache_size must be positive"));
+            }
+            if config.write_buffer_size.map_or(false, |size| size.as_u64() < MIN_WRITE_BUFFER_SIZE)
+            {
+                errors.push(format!(
+                    "column_configs.{name}.write_buffer_size must be at least {MIN_WRITE_BUFFER_SIZE} bytes"
+                ));
+            }
+            if let Some(bits) = config.bloom_filter_bits {
+                if !(0.0..=MAX_BLOOM_FILTER_BITS).contains(&bits) {
+                    errors.push(format!(
+                        "column_configs.{name}.bloom_filter_bits must be between 0 and {MAX_BLOOM_FILTER_BITS}, got {bits}"
+                    ));
+                }
+            }
+            if let Some(ttl) = config.ttl {
+                let col = crate::DBCol::iter().find(|col| <&str>::from(*col) == name);
+                if col.map_or(false, |col| col.default_ttl().is_none()) {
+                    errors.push(format!(
+                        "column_configs.{name}.ttl is not supported; column holds persistent data"
+                    ));
+                }
+                if ttl.is_zero() {
+                    errors.push(format!("column_configs.{name}.ttl must be positive"));
+                }
+            }
+        }
+        errors.sort();
+        errors
+    }
+}
+
+/// Smallest write buffer size accepted in per-column settings.  RocksDB
+/// flushes memtables constantly with smaller buffers.
+const MIN_WRITE_BUFFER_SIZE: u64 = bytesize::MIB;
+
+/// Largest number of bloom filter bits per key accepted in per-column
+/// settings.  More bits barely reduce the false positive rate.
+const MAX_BLOOM_FILTER_BITS: f64 = 32.0;
+
+/// RocksDB settings of a single column.  Unset fields use the values
+/// configured for all columns.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ColumnConfig {
+    /// Size of the block cache of the column.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_cache_size: Option<bytesize::ByteSize>,
+    /// Size of a single memtable of the column.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_buffer_size: Option<bytesize::ByteSize>,
+    /// Compression used on all levels of the column.  By default data is
+    /// compressed with LZ4, and with ZSTD on the bottommost level.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<ColumnCompression>,
+    /// Bits per key of the bloom filter.  Zero disables the filter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bloom_filter_bits: Option<f64>,
+    /// Time after which values written to the column are deleted.  Only
+    /// supported for columns holding ephemeral data, see
+    /// [`crate::DBCol::default_ttl`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<Duration>,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, strum::IntoStaticStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ColumnCompression {
+    None,
+    Lz4,
+    Zstd,
 }
 
 impl Default for StoreConfig {
@@ -188,6 +355,10 @@ impl Default for StoreConfig {
             // max_open_files led to performance improvement of ~11%.
             max_open_files: 10_000,
 
+            corruption_recovery: false,
+            encryption: None,
+            cold: ColdStoreConfig::default(),
+
             // We used to have the same cache size for all columns, 32 MiB.
             // When some RocksDB inefficiencies were found [`DBCol::State`]
             // cache size was increased up to 512 MiB.  This was done on 13th of
@@ -197,6 +368,8 @@ impl Default for StoreConfig {
             // improved performance of state viewer by 60%.
             col_state_cache_size: bytesize::ByteSize::mib(512),
 
+            column_configs: HashMap::new(),
+
             // This value was taken from the Openethereum default parameter and
             // we use it since then.
             block_size: bytesize::ByteSize::kib(16),
@@ -250,6 +423,22 @@ impl Default for StoreConfig {
 
             // Compaction involves a lot of IO and takes considerable amount of time.
             state_snapshot_compaction_enabled: false,
+
+            state_part_memory_limit: None,
+
+            mem_trie_arena: MemTrieArenaConfig::default(),
+
+            mem_trie_consistency_check: MemTrieConsistencyCheckConfig::default(),
+
+            load_mem_tries_for_shards: Vec::new(),
+
+            save_mem_tries_on_shutdown: false,
+
+            flat_state_values_inlining: FlatStateValuesInliningConfig::default(),
+
+            flat_storage_delta_pruning: FlatStorageDeltaPruningConfig::default(),
+
+            rpc_read_limits: ReadLimitsConfig::default(),
         }
     }
 }
@@ -314,3 +503,210 @@ impl Default for TrieCacheConfig {
         }
     }
 }
+
+/// Controls how the memory of an in-memory trie arena is placed and used.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ArenaMemoryConfig {
+    /// Ask the kernel to back the arena with transparent huge pages
+    /// (`madvise(MADV_HUGEPAGE)`). This reduces TLB misses when walking large
+    /// tries, at the cost of possibly higher resident memory. Only effective
+    /// on Linux with transparent huge pages set to `madvise` or `always`.
+    pub huge_pages: bool,
+    /// If set, the arena memory is preferably allocated on the given NUMA node
+    /// (`mbind(MPOL_PREFERRED)`), so that chunk application threads pinned to
+    /// the same socket avoid cross-socket memory traffic. Only effective on
+    /// Linux.
+    pub numa_node: Option<u32>,
+    /// Store identical inlined values only once in the arena, with nodes
+    /// pointing to a shared refcounted copy. Saves memory when many keys
+    /// hold the same value of at least 128 bytes, at the cost of hashing every inlined value
+    /// when nodes are created.
+    pub dedup_inlined_values: bool,
+}
+
+/// Controls how much memory an in-memory trie arena may use. The arena
+/// reserves `max_size_in_bytes` of virtual memory upfront, but only accounts
+/// for memory in chunks as the trie grows, and refuses to grow past the cap.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ArenaGrowthPolicy {
+    /// Memory accounted for when the arena is created.
+    pub initial_size_in_bytes: usize,
+    /// The arena grows by multiples of this many bytes.
+    pub chunk_size_in_bytes: usize,
+    /// Hard cap on the arena size; allocations beyond it fail.
+    pub max_size_in_bytes: usize,
+}
+
+impl ArenaGrowthPolicy {
+    /// A policy for an arena that is accounted at its full size from the
+    /// start and never grows.
+    pub fn fixed(size_in_bytes: usize) -> Self {
+        Self {
+            initial_size_in_bytes: size_in_bytes,
+            chunk_size_in_bytes: size_in_bytes,
+            max_size_in_bytes: size_in_bytes,
+        }
+    }
+}
+
+impl Default for ArenaGrowthPolicy {
+    fn default() -> Self {
+        Self {
+            initial_size_in_bytes: bytesize::GIB as usize,
+            chunk_size_in_bytes: bytesize::GIB as usize,
+            max_size_in_bytes: 64 * bytesize::GIB as usize,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MemTrieArenaConfig {
+    /// Memory placement used for shards without a specific override.
+    pub default: ArenaMemoryConfig,
+    /// Overwrites `default` for specific shards.
+    pub per_shard: HashMap<ShardUId, ArenaMemoryConfig>,
+    /// Growth policy of the arena of every shard.
+    pub growth: ArenaGrowthPolicy,
+}
+
+impl MemTrieArenaConfig {
+    /// Returns the memory placement configuration for the given shard.
+    pub fn for_shard(&self, shard_uid: ShardUId) -> &ArenaMemoryConfig {
+        self.per_shard.get(&shard_uid).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MemTrieConsistencyCheckConfig {
+    /// Once per epoch, read a random sample of keys through the in-memory
+    /// trie, the disk trie and flat storage, and report any divergence via
+    /// metrics and error logs. Meant for debugging; disabled by default.
+    pub enabled: bool,
+    /// Approximate number of keys checked per run.
+    pub num_keys: usize,
+}
+
+impl Default for MemTrieConsistencyCheckConfig {
+    fn default() -> Self {
+        Self { enabled: false, num_keys: 1000 }
+    }
+}
+
+/// Controls the background job rewriting `FlatState` entries that hold value
+/// references into entries with inlined values, so that reading them takes
+/// one lookup instead of two. The job is resumable across restarts.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FlatStateValuesInliningConfig {
+    /// Values up to this size are inlined. Values above
+    /// `FlatStateValue::INLINE_DISK_VALUE_THRESHOLD` are never inlined.
+    pub max_inlined_value_size: usize,
+    /// Number of `FlatState` entries processed in one batch. Flat head
+    /// updates are paused while the inlined values of a batch are written.
+    pub batch_size: usize,
+    /// Pause between batches, limiting the impact on block processing.
+    pub batch_delay: Duration,
+}
+
+impl Default for FlatStateValuesInliningConfig {
+    fn default() -> Self {
+        Self {
+            max_inlined_value_size: FlatStateValue::INLINE_DISK_VALUE_THRESHOLD,
+            batch_size: 50_000,
+            batch_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Controls pruning of flat storage deltas for blocks which are not on the
+/// chain of the last final block. Such deltas are otherwise only removed by
+/// chain garbage collection, which never happens on archival nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FlatStorageDeltaPruningConfig {
+    pub enabled: bool,
+    /// Number of blocks below the last final block for which deltas of
+    /// abandoned forks are kept.
+    pub retained_blocks: BlockHeightDelta,
+}
+
+impl Default for FlatStorageDeltaPruningConfig {
+    fn default() -> Self {
+        Self { enabled: true, retained_blocks: 100 }
+    }
+}
+
+/// Limits applied to reads of a given [`crate::ReadPriority`].  `None` means
+/// no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReadLimitsConfig {
+    /// Maximum number of reads executed at the same time.  Further reads
+    /// block until one of the running reads finishes.
+    pub max_concurrent_reads: Option<usize>,
+    /// Maximum number of reads started per second.
+    pub max_reads_per_second: Option<u64>,
+}
+
+/// Where to get the key used to encrypt the database from.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionKeySource {
+    /// Absolute path to a file containing hex-encoded key.
+    KeyFile(std::path::PathBuf),
+    /// Shell command printing hex-encoded key to its standard output, e.g.
+    /// invocation of a key management service CLI.
+    Command(String),
+}
+
+/// Cold storage specific configuration.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ColdStoreConfig {
+    /// Keep values of archival columns in object storage rather than in the
+    /// local database.  The local database still holds all the keys, serving
+    /// as an index, values smaller than
+    /// [`ObjectStorageConfig::min_value_size`] and the metadata columns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_storage: Option<ObjectStorageConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ObjectStorageConfig {
+    /// Bucket the values are stored in.
+    pub location: ObjectStorageLocation,
+    /// Values smaller than this are kept in the local database since the
+    /// overhead of a request outweighs the savings.
+    /// Default value: 4KiB.
+    #[serde(default = "default_object_min_value_size")]
+    pub min_value_size: bytesize::ByteSize,
+    /// Number of values read from object storage kept in memory.
+    /// Default value: 10000.
+    #[serde(default = "default_object_cache_capacity")]
+    pub cache_capacity: usize,
+}
+
+fn default_object_min_value_size() -> bytesize::ByteSize {
+    bytesize::ByteSize::kib(4)
+}
+
+fn default_object_cache_capacity() -> usize {
+    10_000
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ObjectStorageLocation {
+    /// Amazon S3 bucket.  Credentials are read from the environment, AWS
+    /// profile or instance metadata.
+    S3 { bucket: String, region: String },
+    /// Google Cloud Storage bucket.  Credentials are read from the
+    /// `SERVICE_ACCOUNT` or `GOOGLE_APPLICATION_CREDENTIALS` environment
+    /// variable.
+    GCS { bucket: String },
+    /// Local directory, e.g. a network file system mount.
+    Filesystem { root_dir: std::path::PathBuf },
+}
@@ -5,6 +5,7 @@ use std::io;
 pub(crate) mod rocksdb;
 
 mod colddb;
+mod objectdb;
 mod splitdb;
 
 pub mod refcount;
@@ -14,6 +15,7 @@ mod testdb;
 mod database_tests;
 
 pub use self::colddb::ColdDB;
+pub use self::objectdb::ObjectStorageDB;
 pub use self::rocksdb::RocksDB;
 pub use self::splitdb::SplitDB;
 
@@ -34,10 +36,13 @@ pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
 pub const COLD_HEAD_KEY: &[u8; 9] = b"COLD_HEAD";
 pub const STATE_SYNC_DUMP_KEY: &[u8; 15] = b"STATE_SYNC_DUMP";
 pub const STATE_SNAPSHOT_KEY: &[u8; 18] = b"STATE_SNAPSHOT_KEY";
+pub const PRUNE_TAILS_KEY: &[u8; 11] = b"PRUNE_TAILS";
 
 // `DBCol::Misc` keys
 pub const FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS_KEY: &[u8] =
     b"FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS";
+pub const FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS_KEY: &[u8] =
+    b"FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS";
 
 #[derive(Default, Debug)]
 pub struct DBTransaction {
@@ -72,6 +77,18 @@ impl DBOp {
             DBOp::DeleteRange { col, .. } => col,
         }
     }
+
+    /// Returns number of bytes of keys and values in the operation.
+    pub fn size(&self) -> usize {
+        match self {
+            DBOp::Set { key, value, .. }
+            | DBOp::Insert { key, value, .. }
+            | DBOp::UpdateRefcount { key, value, .. } => key.len() + value.len(),
+            DBOp::Delete { key, .. } => key.len(),
+            DBOp::DeleteAll { .. } => 0,
+            DBOp::DeleteRange { from, to, .. } => from.len() + to.len(),
+        }
+    }
 }
 
 impl std::fmt::Debug for DBOp {
@@ -144,10 +161,38 @@ impl DBTransaction {
     pub fn merge(&mut self, other: DBTransaction) {
         self.ops.extend(other.ops)
     }
+
+    /// Returns number of bytes of keys and values in the transaction.
+    pub fn size(&self) -> usize {
+        self.ops.iter().map(DBOp::size).sum()
+    }
+
+    /// Splits the transaction into transactions with at most `max_size` bytes
+    /// of keys and values each, preserving order of the operations.  An
+    /// operation larger than `max_size` gets a transaction of its own.
+    pub(crate) fn split(self, max_size: usize) -> Vec<DBTransaction> {
+        let mut chunks = vec![];
+        let mut chunk = DBTransaction::new();
+        let mut chunk_size = 0;
+        for op in self.ops {
+            let op_size = op.size();
+            if !chunk.ops.is_empty() && chunk_size + op_size > max_size {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_size = 0;
+            }
+            chunk_size += op_size;
+            chunk.ops.push(op);
+        }
+        if !chunk.ops.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
 }
 
 pub type DBIteratorItem = io::Result<(Box<[u8]>, Box<[u8]>)>;
 pub type DBIterator<'a> = Box<dyn Iterator<Item = DBIteratorItem> + 'a>;
+pub type DBStream = std::pin::Pin<Box<dyn futures::Stream<Item = DBIteratorItem> + Send>>;
 
 pub trait Database: Sync + Send {
     /// Returns raw bytes for given `key` ignoring any reference count decoding
@@ -214,6 +259,20 @@ pub trait Database: Sync + Send {
     /// Atomically apply all operations in given batch at once.
     fn write(&self, batch: DBTransaction) -> io::Result<()>;
 
+    /// Writes entries sorted by key into given column in bulk.
+    ///
+    /// Values are written as is, i.e. for reference-counted columns they must
+    /// include the reference count, and replace any existing values.  By
+    /// default this is equivalent to writing all entries in a single batch;
+    /// databases which support it can bypass the write path entirely.
+    fn ingest(&self, col: DBCol, entries: Vec<(Vec<u8>, Vec<u8>)>) -> io::Result<()> {
+        let mut transaction = DBTransaction::new();
+        for (key, value) in entries {
+            transaction.set(col, key, value);
+        }
+        self.write(transaction)
+    }
+
     /// Flush all in-memory data to disk.
     ///
     /// This is a no-op for in-memory databases.
@@ -228,8 +287,39 @@ pub trait Database: Sync + Send {
     /// Returns statistics about the database if available.
     fn get_store_statistics(&self) -> Option<StoreStatistics>;
 
+    /// Returns approximate size of every column of the database.
+    fn get_column_stats(&self) -> Vec<(DBCol, ColumnStats)>;
+
     /// Create checkpoint in provided path
     fn create_checkpoint(&self, path: &std::path::Path) -> anyhow::Result<()>;
+
+    /// Returns a read-only view of the database as of now.
+    ///
+    /// All reads through the snapshot, in all columns, observe the same state
+    /// of the database.  Writes done after the snapshot was taken, including
+    /// deletions done by garbage collection, are not visible through it.
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_>;
+}
+
+/// Read-only view of a database pinned at the time it was taken.
+///
+/// See [`Database::snapshot`].
+pub trait DatabaseSnapshot {
+    /// Returns raw bytes for given `key` ignoring any reference count decoding
+    /// if any.  See [`Database::get_raw_bytes`].
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>>;
+
+    /// Returns value for given `key` forcing a reference count decoding.
+    ///
+    /// **Panics** if the column is not reference counted.
+    fn get_with_rc_stripped(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        assert!(col.is_rc());
+        Ok(self.get_raw_bytes(col, key)?.and_then(DBSlice::strip_refcount))
+    }
+
+    /// Iterate over items in given column whose keys start with given prefix.
+    /// See [`Database::iter_prefix`].
+    fn iter_prefix<'a>(&'a self, col: DBCol, key_prefix: &'a [u8]) -> DBIterator<'a>;
 }
 
 fn assert_no_overwrite(col: DBCol, key: &[u8], value: &[u8], old_value: &[u8]) {
@@ -255,3 +345,20 @@ pub enum StatsValue {
 pub struct StoreStatistics {
     pub data: Vec<(String, Vec<StatsValue>)>,
 }
+
+/// Approximate size of a single column.
+///
+/// For RocksDB the values come from column family properties and are only
+/// estimates; in particular they don’t account for data still in memtables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    /// Estimated number of entries in the column.
+    pub estimated_num_keys: u64,
+    /// Estimated size of live data in the column in bytes.
+    pub estimated_live_data_size: u64,
+    /// Total size of all SST files of the column in bytes.  This includes
+    /// obsolete files which haven’t been deleted yet.
+    pub total_sst_files_size: u64,
+    /// Number of SST files of the column across all levels.
+    pub num_sst_files: u64,
+}
@@ -1,7 +1,8 @@
 use near_o11y::{log_assert, log_assert_fail};
 
+use crate::cold_storage::ColdStoreCopyProgress;
 use crate::db::refcount::set_refcount;
-use crate::db::{DBIterator, DBOp, DBSlice, DBTransaction, Database};
+use crate::db::{DBIterator, DBOp, DBSlice, DBTransaction, Database, DatabaseSnapshot};
 use crate::DBCol;
 
 /// A database which provides access to the cold storage.
@@ -15,11 +16,32 @@ use crate::DBCol;
 /// are enabled will cause a panic.
 pub struct ColdDB {
     cold: std::sync::Arc<dyn Database>,
+    /// Progress of copying data from hot storage, updated by functions from
+    /// `cold_storage` module.
+    copy_progress: std::sync::Mutex<ColdStoreCopyProgress>,
 }
 
 impl ColdDB {
     pub fn new(cold: std::sync::Arc<dyn Database>) -> Self {
-        Self { cold }
+        Self { cold, copy_progress: Default::default() }
+    }
+
+    /// Returns progress of copying data from hot storage to this database
+    /// since the start of the node.
+    pub fn copy_progress(&self) -> ColdStoreCopyProgress {
+        self.copy_progress.lock().unwrap().clone()
+    }
+
+    /// Sets the height up to which data has to be copied, usually the hot
+    /// final head. Used to estimate the time remaining to catch up.
+    pub fn set_copy_target_height(&self, height: near_primitives::types::BlockHeight) {
+        self.update_copy_progress(|progress| progress.target_height = Some(height));
+    }
+
+    pub(crate) fn update_copy_progress(&self, f: impl FnOnce(&mut ColdStoreCopyProgress)) {
+        let mut progress = self.copy_progress.lock().unwrap();
+        f(&mut progress);
+        crate::metrics::COLD_COPY_ETA.set(progress.eta().map_or(0, |eta| eta.as_secs() as i64));
     }
 
     fn err_msg(col: DBCol) -> String {
@@ -111,9 +133,17 @@ impl Database for ColdDB {
         self.cold.get_store_statistics()
     }
 
+    fn get_column_stats(&self) -> Vec<(DBCol, crate::db::ColumnStats)> {
+        self.cold.get_column_stats()
+    }
+
     fn create_checkpoint(&self, path: &std::path::Path) -> anyhow::Result<()> {
         self.cold.create_checkpoint(path)
     }
+
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_> {
+        self.cold.snapshot()
+    }
 }
 
 /// Adjust database operation to be performed on cold storage.
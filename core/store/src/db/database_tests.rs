@@ -42,4 +42,21 @@ mod tests {
             assert_eq!(keys, vec!["aa", "aa1"]);
         }
     }
+    #[test]
+    fn test_db_column_stats() {
+        for db in test_and_rocksdb() {
+            let mut transaction = DBTransaction::new();
+            transaction.insert(DBCol::Block, "a".into(), "val_a".into());
+            transaction.insert(DBCol::Block, "b".into(), "val_b".into());
+            transaction.insert(DBCol::Block, "c".into(), "val_c".into());
+            db.write(transaction).unwrap();
+            db.flush().unwrap();
+
+            let stats = db.get_column_stats();
+            let (_, block) = stats.iter().find(|(col, _)| *col == DBCol::Block).unwrap();
+            assert_eq!(block.estimated_num_keys, 3);
+            let (_, chunks) = stats.iter().find(|(col, _)| *col == DBCol::Chunks).unwrap();
+            assert_eq!(chunks.estimated_num_keys, 0);
+        }
+    }
 }
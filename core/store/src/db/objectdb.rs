@@ -0,0 +1,496 @@
+//! Cold storage backend keeping values in object storage.
+//!
+//! Archival nodes hold tens of terabytes of data which is rarely read.  Rather
+//! than keeping all of it on local disks, [`ObjectStorageDB`] uploads values
+//! of the cold columns into a bucket in object storage (S3, GCS or a directory
+//! e.g. on a network file system) and keeps only an index in the local
+//! RocksDB database.
+//!
+//! For every key of a cold column the local database holds an index entry
+//! which is either the value itself (for values smaller than
+//! [`ObjectStorageConfig::min_value_size`]) or the length of the value stored
+//! in object storage under `<column>/<hex key>` name.  Keeping all the keys
+//! locally means that checking for existence of a key and iterating over
+//! a column doesn’t require listing the bucket.  Metadata columns (i.e.
+//! `DbVersion` and `BlockMisc`) are stored in the local database as is.
+//!
+//! Since cold storage never overwrites nor deletes values, objects are
+//! uploaded before the index entries are written.  If the node crashes in
+//! between, the uploaded objects are simply uploaded again.
+//!
+//! If encryption at rest is enabled (see `StoreConfig::encryption`), objects
+//! are encrypted with the key of the local database before they are uploaded.
+//!
+//! S3 and GCS buckets are supported only with the `object_storage` feature.
+
+use crate::config::{ObjectStorageConfig, ObjectStorageLocation};
+use crate::db::rocksdb::{RocksDB, RocksDBIterator};
+use crate::db::{
+    refcount, ColumnStats, DBIterator, DBOp, DBSlice, DBTransaction, Database, DatabaseSnapshot,
+};
+use crate::{metrics, DBCol, StoreStatistics};
+use rayon::prelude::*;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Index entry tag of values stored in the local database.
+const INLINE: u8 = 0;
+/// Index entry tag of values stored in object storage.  The tag is followed by
+/// length of the value encoded as little-endian u64.
+const REMOTE: u8 = 1;
+
+/// Timeout of requests to S3.
+#[cfg(feature = "object_storage")]
+const S3_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct ObjectStorageDB {
+    /// Local database holding the index and the metadata columns.
+    local: RocksDB,
+    /// Bucket values are stored in.
+    storage: Box<dyn ObjectStorage>,
+    /// Values smaller than this are stored in the index.
+    min_value_size: usize,
+    /// Recently read values keyed by object name.
+    cache: Mutex<lru::LruCache<String, Vec<u8>>>,
+}
+
+impl ObjectStorageDB {
+    /// Wraps local cold database so that values of the cold columns are kept
+    /// in object storage at given location.
+    pub(crate) fn new(local: RocksDB, config: &ObjectStorageConfig) -> io::Result<Self> {
+        let storage: Box<dyn ObjectStorage> = match &config.location {
+            #[cfg(feature = "object_storage")]
+            ObjectStorageLocation::S3 { bucket, region } => {
+                let region = region.parse::<s3::Region>().map_err(other_error)?;
+                let creds = s3::creds::Credentials::default().map_err(other_error)?;
+                let mut bucket = s3::Bucket::new(bucket, region, creds).map_err(other_error)?;
+                bucket.set_request_timeout(Some(S3_REQUEST_TIMEOUT));
+                Box::new(S3Storage { bucket })
+            }
+            #[cfg(feature = "object_storage")]
+            ObjectStorageLocation::GCS { bucket } => Box::new(GcsStorage {
+                client: cloud_storage::sync::Client::new().map_err(other_error)?,
+                bucket: bucket.clone(),
+            }),
+            #[cfg(not(feature = "object_storage"))]
+            ObjectStorageLocation::S3 { .. } | ObjectStorageLocation::GCS { .. } => {
+                return Err(other_error(
+                    "S3 and GCS cold storage require neard built with the object_storage feature",
+                ));
+            }
+            ObjectStorageLocation::Filesystem { root_dir } => {
+                Box::new(FilesystemStorage { root_dir: root_dir.clone() })
+            }
+        };
+        Ok(Self {
+            local,
+            storage,
+            min_value_size: config.min_value_size.as_u64() as usize,
+            cache: Mutex::new(lru::LruCache::new(config.cache_capacity)),
+        })
+    }
+
+    /// Whether the local database holds index entries rather than values for
+    /// given column.
+    fn is_indexed(col: DBCol) -> bool {
+        col.is_cold()
+    }
+
+    /// Encodes value as an index entry.  Returns the entry and, if the value
+    /// is to be stored in object storage, the (encrypted) object.
+    fn encode(&self, col: DBCol, key: &[u8], value: Vec<u8>) -> (Vec<u8>, Option<Vec<u8>>) {
+        if value.len() < self.min_value_size {
+            // The local database encrypts the entry itself.
+            ([&[INLINE][..], &value].concat(), None)
+        } else {
+            let object = self.local.encrypt(col, key, value);
+            let entry = [&[REMOTE][..], &(object.len() as u64).to_le_bytes()].concat();
+            (entry, Some(object))
+        }
+    }
+
+    /// Returns value described by given index entry, fetching it from object
+    /// storage if necessary.
+    fn decode(&self, col: DBCol, key: &[u8], entry: &[u8]) -> io::Result<Vec<u8>> {
+        match entry.split_first() {
+            Some((&INLINE, value)) => Ok(value.to_vec()),
+            Some((&REMOTE, len)) => {
+                let len = len.try_into().map_err(|_| invalid_entry(col, key))?;
+                let object = self.fetch(&object_name(col, key), u64::from_le_bytes(len))?;
+                self.local.decrypt(col, key, object)
+            }
+            _ => Err(invalid_entry(col, key)),
+        }
+    }
+
+    /// Maps index entry read from the local database to the value.
+    fn decode_slice<'a>(
+        &self,
+        col: DBCol,
+        key: &[u8],
+        entry: Option<DBSlice<'a>>,
+    ) -> io::Result<Option<DBSlice<'a>>> {
+        if !Self::is_indexed(col) {
+            return Ok(entry);
+        }
+        entry.map(|entry| Ok(DBSlice::from_vec(self.decode(col, key, &entry)?))).transpose()
+    }
+
+    fn fetch(&self, name: &str, len: u64) -> io::Result<Vec<u8>> {
+        if let Some(value) = self.cache.lock().unwrap().get(name) {
+            metrics::COLD_OBJECT_CACHE.with_label_values(&["hit"]).inc();
+            return Ok(value.clone());
+        }
+        metrics::COLD_OBJECT_CACHE.with_label_values(&["miss"]).inc();
+        let value = timed("get", || self.storage.get(name))?;
+        if value.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("object {name} has {} bytes but {len} were expected", value.len()),
+            ));
+        }
+        metrics::COLD_OBJECT_STORAGE_BYTES.with_label_values(&["get"]).inc_by(len);
+        self.cache.lock().unwrap().put(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Maps index entries returned by the iterator to the values.
+    fn decode_iter<'a>(&'a self, col: DBCol, iter: RocksDBIterator<'a>) -> DBIterator<'a> {
+        if !Self::is_indexed(col) {
+            return Box::new(iter);
+        }
+        Box::new(iter.map(move |item| {
+            let (key, entry) = item?;
+            let value = self.decode(col, &key, &entry)?;
+            Ok((key, value.into_boxed_slice()))
+        }))
+    }
+
+    fn iter_internal<'a>(
+        &'a self,
+        col: DBCol,
+        prefix: Option<&[u8]>,
+        lower_bound: Option<&[u8]>,
+        upper_bound: Option<&[u8]>,
+    ) -> DBIterator<'a> {
+        let iter = self.local.iter_raw_bytes_internal(col, prefix, lower_bound, upper_bound, None);
+        refcount::iter_with_rc_logic(col, self.decode_iter(col, iter))
+    }
+}
+
+impl Database for ObjectStorageDB {
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        self.decode_slice(col, key, self.local.get_raw_bytes(col, key)?)
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> DBIterator<'a> {
+        self.iter_internal(col, None, None, None)
+    }
+
+    fn iter_prefix<'a>(&'a self, col: DBCol, key_prefix: &'a [u8]) -> DBIterator<'a> {
+        self.iter_internal(col, Some(key_prefix), None, None)
+    }
+
+    fn iter_range<'a>(
+        &'a self,
+        col: DBCol,
+        lower_bound: Option<&[u8]>,
+        upper_bound: Option<&[u8]>,
+    ) -> DBIterator<'a> {
+        self.iter_internal(col, None, lower_bound, upper_bound)
+    }
+
+    fn iter_raw_bytes<'a>(&'a self, col: DBCol) -> DBIterator<'a> {
+        self.decode_iter(col, self.local.iter_raw_bytes_internal(col, None, None, None, None))
+    }
+
+    /// Uploads values which go to object storage and then writes the index
+    /// entries to the local database.
+    ///
+    /// Cold storage only ever sets values, see [`crate::db::ColdDB`], so
+    /// refcount updates of cold columns are rejected.
+    fn write(&self, mut transaction: DBTransaction) -> io::Result<()> {
+        let mut uploads = Vec::new();
+        for op in transaction.ops.iter_mut() {
+            match op {
+                DBOp::Set { col, key, value } | DBOp::Insert { col, key, value }
+                    if Self::is_indexed(*col) =>
+                {
+                    let (entry, upload) = self.encode(*col, key, std::mem::take(value));
+                    *value = entry;
+                    if let Some(upload) = upload {
+                        uploads.push((object_name(*col, key), upload));
+                    }
+                }
+                DBOp::UpdateRefcount { col, .. } if Self::is_indexed(*col) => {
+                    return Err(other_error(format!(
+                        "refcount update of {col} not supported by object storage"
+                    )));
+                }
+                _ => {}
+            }
+        }
+        uploads.par_iter().try_for_each(|(name, value)| {
+            timed("put", || self.storage.put(name, value))?;
+            metrics::COLD_OBJECT_STORAGE_BYTES
+                .with_label_values(&["put"])
+                .inc_by(value.len() as u64);
+            Ok::<_, io::Error>(())
+        })?;
+        self.local.write(transaction)
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        self.local.compact()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.local.flush()
+    }
+
+    fn get_store_statistics(&self) -> Option<StoreStatistics> {
+        self.local.get_store_statistics()
+    }
+
+    fn get_column_stats(&self) -> Vec<(DBCol, ColumnStats)> {
+        self.local.get_column_stats()
+    }
+
+    /// Creates checkpoint of the local database.  The checkpoint refers to
+    /// the same objects in object storage.
+    fn create_checkpoint(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        self.local.create_checkpoint(path)
+    }
+
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_> {
+        Box::new(ObjectStorageDBSnapshot { db: self, snapshot: self.local.raw_snapshot() })
+    }
+}
+
+/// Snapshot of the local database.  Objects are never overwritten so reading
+/// them needs no snapshot.
+struct ObjectStorageDBSnapshot<'a> {
+    db: &'a ObjectStorageDB,
+    snapshot: ::rocksdb::Snapshot<'a>,
+}
+
+impl<'a> DatabaseSnapshot for ObjectStorageDBSnapshot<'a> {
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        let entry = self.db.local.get_raw_bytes_internal(col, key, Some(&self.snapshot))?;
+        self.db.decode_slice(col, key, entry)
+    }
+
+    fn iter_prefix<'b>(&'b self, col: DBCol, key_prefix: &'b [u8]) -> DBIterator<'b> {
+        let iter = self.db.local.iter_raw_bytes_internal(
+            col,
+            Some(key_prefix),
+            None,
+            None,
+            Some(&self.snapshot),
+        );
+        refcount::iter_with_rc_logic(col, self.db.decode_iter(col, iter))
+    }
+}
+
+/// Client of a bucket in object storage.
+///
+/// Database operations are synchronous so the requests block the calling
+/// thread.
+trait ObjectStorage: Send + Sync {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>>;
+    fn put(&self, name: &str, value: &[u8]) -> io::Result<()>;
+}
+
+#[cfg(feature = "object_storage")]
+struct S3Storage {
+    bucket: s3::Bucket,
+}
+
+#[cfg(feature = "object_storage")]
+impl ObjectStorage for S3Storage {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        let response = self.bucket.get_object_blocking(name).map_err(other_error)?;
+        match response.status_code() {
+            200 => Ok(response.bytes().to_vec()),
+            code => Err(other_error(format!("S3 request for {name} failed with status {code}"))),
+        }
+    }
+
+    fn put(&self, name: &str, value: &[u8]) -> io::Result<()> {
+        let response = self.bucket.put_object_blocking(name, value).map_err(other_error)?;
+        match response.status_code() {
+            200 => Ok(()),
+            code => Err(other_error(format!("S3 upload of {name} failed with status {code}"))),
+        }
+    }
+}
+
+#[cfg(feature = "object_storage")]
+struct GcsStorage {
+    client: cloud_storage::sync::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "object_storage")]
+impl ObjectStorage for GcsStorage {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.client.object().download(&self.bucket, name).map_err(other_error)
+    }
+
+    fn put(&self, name: &str, value: &[u8]) -> io::Result<()> {
+        self.client
+            .object()
+            .create(&self.bucket, value.to_vec(), name, "application/octet-stream")
+            .map_err(other_error)?;
+        Ok(())
+    }
+}
+
+struct FilesystemStorage {
+    root_dir: PathBuf,
+}
+
+impl ObjectStorage for FilesystemStorage {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.root_dir.join(name))
+    }
+
+    fn put(&self, name: &str, value: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+
+        let path = self.root_dir.join(name);
+        let dir = path.parent().unwrap();
+        std::fs::create_dir_all(dir)?;
+        // Write to a temporary file first so that readers never see a partial
+        // object.
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(value)?;
+        file.persist(&path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+fn object_name(col: DBCol, key: &[u8]) -> String {
+    format!("{}/{}", <&str>::from(col), hex::encode(key))
+}
+
+/// Performs object storage request recording its latency.
+fn timed<T>(op: &str, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let start = std::time::Instant::now();
+    let result = f();
+    let label = if result.is_ok() { "ok" } else { "error" };
+    metrics::COLD_OBJECT_STORAGE_ELAPSED
+        .with_label_values(&[op, label])
+        .observe(start.elapsed().as_secs_f64());
+    result
+}
+
+fn invalid_entry(col: DBCol, key: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid object storage index entry in {col} for key {}", hex::encode(key)),
+    )
+}
+
+fn other_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObjectStorageDB;
+    use crate::config::{ObjectStorageConfig, ObjectStorageLocation};
+    use crate::db::{ColdDB, DBTransaction, Database, RocksDB};
+    use crate::{DBCol, Mode, StoreConfig, Temperature};
+
+    #[test]
+    fn test_object_storage_db() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root_dir = tmp_dir.path().join("bucket");
+        let config = ObjectStorageConfig {
+            location: ObjectStorageLocation::Filesystem { root_dir: root_dir.clone() },
+            min_value_size: bytesize::ByteSize::b(16),
+            cache_capacity: 10,
+        };
+        let local = RocksDB::open(
+            &tmp_dir.path().join("cold"),
+            &StoreConfig::test_config(),
+            Mode::ReadWrite,
+            Temperature::Cold,
+        )
+        .unwrap();
+        let db = ColdDB::new(std::sync::Arc::new(ObjectStorageDB::new(local, &config).unwrap()));
+
+        let small = b"small".to_vec();
+        let large = vec![42; 100];
+        let mut transaction = DBTransaction::new();
+        transaction.insert(DBCol::Block, vec![1], small.clone());
+        transaction.insert(DBCol::Block, vec![2], large.clone());
+        transaction.update_refcount(
+            DBCol::State,
+            vec![3],
+            [&large[..], &[1, 0, 0, 0, 0, 0, 0, 0]].concat(),
+        );
+        transaction.set(DBCol::BlockMisc, vec![4], large.clone());
+        db.write(transaction).unwrap();
+
+        // Only large values of cold columns go to object storage.
+        assert!(!root_dir.join("Block/01").exists());
+        assert_eq!(std::fs::read(root_dir.join("Block/02")).unwrap(), large);
+        assert!(root_dir.join("State/03").exists());
+        assert!(!root_dir.join("BlockMisc").exists());
+
+        assert_eq!(db.get_raw_bytes(DBCol::Block, &[1]).unwrap().as_deref(), Some(&small[..]));
+        assert_eq!(db.get_raw_bytes(DBCol::Block, &[2]).unwrap().as_deref(), Some(&large[..]));
+        assert_eq!(
+            db.get_with_rc_stripped(DBCol::State, &[3]).unwrap().as_deref(),
+            Some(&large[..])
+        );
+        assert_eq!(db.get_raw_bytes(DBCol::BlockMisc, &[4]).unwrap().as_deref(), Some(&large[..]));
+        assert_eq!(db.get_raw_bytes(DBCol::Block, &[5]).unwrap(), None);
+
+        let items: Vec<_> = db.iter(DBCol::Block).map(Result::unwrap).collect();
+        assert_eq!(
+            items,
+            vec![
+                (vec![1].into_boxed_slice(), small.into_boxed_slice()),
+                (vec![2].into_boxed_slice(), large.into_boxed_slice())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_storage_db_encryption() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root_dir = tmp_dir.path().join("bucket");
+        let key_file = tmp_dir.path().join("key");
+        std::fs::write(&key_file, hex::encode([7; 32])).unwrap();
+        let mut store_config = StoreConfig::test_config();
+        store_config.encryption = Some(crate::config::EncryptionKeySource::KeyFile(key_file));
+        let config = ObjectStorageConfig {
+            location: ObjectStorageLocation::Filesystem { root_dir: root_dir.clone() },
+            min_value_size: bytesize::ByteSize::b(16),
+            cache_capacity: 10,
+        };
+        let local = RocksDB::open(
+            &tmp_dir.path().join("cold"),
+            &store_config,
+            Mode::Create,
+            Temperature::Cold,
+        )
+        .unwrap();
+        let db = ColdDB::new(std::sync::Arc::new(ObjectStorageDB::new(local, &config).unwrap()));
+
+        let large = vec![42; 100];
+        let mut transaction = DBTransaction::new();
+        transaction.insert(DBCol::Block, vec![1], large.clone());
+        db.write(transaction).unwrap();
+
+        // Objects are uploaded encrypted.
+        let object = std::fs::read(root_dir.join("Block/01")).unwrap();
+        assert!(!object.windows(large.len()).any(|window| window == large));
+        assert_eq!(db.get_raw_bytes(DBCol::Block, &[1]).unwrap().as_deref(), Some(&large[..]));
+    }
+}
@@ -1,5 +1,8 @@
-use crate::config::Mode;
-use crate::db::{refcount, DBIterator, DBOp, DBSlice, DBTransaction, Database, StatsValue};
+use crate::config::{ColumnCompression, Mode};
+use crate::db::{
+    refcount, ColumnStats, DBIterator, DBOp, DBSlice, DBTransaction, Database, DatabaseSnapshot,
+    StatsValue,
+};
 use crate::{metadata, metrics, DBCol, StoreConfig, StoreStatistics, Temperature};
 use ::rocksdb::{
     BlockBasedOptions, Cache, ColumnFamily, Env, IteratorMode, Options, ReadOptions, WriteBatch, DB,
@@ -11,7 +14,9 @@ use std::path::Path;
 use strum::IntoEnumIterator;
 use tracing::warn;
 
+mod encryption;
 mod instance_tracker;
+pub(crate) mod recovery;
 pub(crate) mod snapshot;
 
 /// List of integer RocskDB properties we’re reading when collecting statistics.
@@ -52,6 +57,10 @@ pub struct RocksDB {
     /// want.
     cf_handles: enum_map::EnumMap<DBCol, Option<std::ptr::NonNull<ColumnFamily>>>,
 
+    /// Cipher used to encrypt values if encryption at rest is enabled.  See
+    /// [`StoreConfig::encryption`].
+    cipher: Option<encryption::ValueCipher>,
+
     // RAII-style of keeping track of the number of instances of RocksDB and
     // counting total sum of max_open_files.
     _instance_tracker: instance_tracker::InstanceTracker,
@@ -117,7 +126,14 @@ impl RocksDB {
             .map_err(other_error)?;
         let (db, db_opt) = Self::open_db(path, store_config, mode, temp, columns)?;
         let cf_handles = Self::get_cf_handles(&db, columns);
-        Ok(Self { db, db_opt, cf_handles, _instance_tracker: counter })
+        let cipher =
+            store_config.encryption.as_ref().map(encryption::ValueCipher::load).transpose()?;
+        let this = Self { db, db_opt, cf_handles, cipher, _instance_tracker: counter };
+        if columns.contains(&DBCol::DbVersion) {
+            let cf_handle = this.cf_handle(DBCol::DbVersion)?;
+            encryption::check_key(&this.db, cf_handle, this.cipher.as_ref(), mode)?;
+        }
+        Ok(this)
     }
 
     /// Opens the database with given column families configured.
@@ -232,15 +248,20 @@ impl RocksDB {
     /// You can specify EITHER the prefix, OR lower/upper bounds.
     /// Upper bound value is not included in the iteration.
     /// Specifying both prefix and lower & upper bounds will result in undetermined behavior.
-    fn iter_raw_bytes_internal<'a>(
+    /// If `snapshot` is given, the iterator reads the database as of the snapshot.
+    pub(super) fn iter_raw_bytes_internal<'a>(
         &'a self,
         col: DBCol,
         prefix: Option<&[u8]>,
         lower_bound: Option<&[u8]>,
         upper_bound: Option<&[u8]>,
+        snapshot: Option<&'a ::rocksdb::Snapshot<'a>>,
     ) -> RocksDBIterator<'a> {
         let cf_handle = self.cf_handle(col).unwrap();
         let mut read_options = rocksdb_read_options();
+        if let Some(snapshot) = snapshot {
+            read_options.set_snapshot(snapshot);
+        }
         if prefix.is_some() && (lower_bound.is_some() || upper_bound.is_some()) {
             panic!("Cannot iterate both with prefix and lower/upper bounds at the same time.");
         }
@@ -259,17 +280,78 @@ impl RocksDB {
             read_options.set_iterate_upper_bound(upper_bound);
         }
         let iter = self.db.iterator_cf_opt(cf_handle, read_options, IteratorMode::Start);
-        RocksDBIterator(iter)
+        RocksDBIterator { iter, col, cipher: self.cipher.as_ref() }
+    }
+
+    /// Reads value of given key, optionally as of given snapshot.
+    pub(super) fn get_raw_bytes_internal(
+        &self,
+        col: DBCol,
+        key: &[u8],
+        snapshot: Option<&::rocksdb::Snapshot<'_>>,
+    ) -> io::Result<Option<DBSlice<'_>>> {
+        let timer =
+            metrics::DATABASE_OP_LATENCY_HIST.with_label_values(&["get", col.into()]).start_timer();
+        let mut read_options = rocksdb_read_options();
+        if let Some(snapshot) = snapshot {
+            read_options.set_snapshot(snapshot);
+        }
+        let result = self
+            .db
+            .get_pinned_cf_opt(self.cf_handle(col)?, key, &read_options)
+            .map_err(into_other)?;
+        let result = match (&self.cipher, result) {
+            (Some(cipher), Some(value)) => {
+                Some(DBSlice::from_vec(cipher.decrypt(col, key, &value)?))
+            }
+            (_, result) => result.map(DBSlice::from_rocksdb_slice),
+        };
+        timer.observe_duration();
+        Ok(result)
+    }
+
+    /// Takes a RocksDB snapshot of the whole database.
+    pub(super) fn raw_snapshot(&self) -> ::rocksdb::Snapshot<'_> {
+        self.db.snapshot()
+    }
+
+    /// Encrypts the value if encryption at rest is enabled.
+    pub(super) fn encrypt(&self, col: DBCol, key: &[u8], value: Vec<u8>) -> Vec<u8> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(col, key, value),
+            None => value,
+        }
+    }
+
+    /// Decrypts the value returned by [`Self::encrypt`].
+    pub(super) fn decrypt(&self, col: DBCol, key: &[u8], value: Vec<u8>) -> io::Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(col, key, &value),
+            None => Ok(value),
+        }
     }
 }
 
-struct RocksDBIterator<'a>(rocksdb::DBIteratorWithThreadMode<'a, DB>);
+pub(super) struct RocksDBIterator<'a> {
+    iter: rocksdb::DBIteratorWithThreadMode<'a, DB>,
+    col: DBCol,
+    cipher: Option<&'a encryption::ValueCipher>,
+}
 
 impl<'a> Iterator for RocksDBIterator<'a> {
     type Item = io::Result<(Box<[u8]>, Box<[u8]>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.0.next()?.map_err(into_other))
+        let (key, value) = match self.iter.next()? {
+            Ok(item) => item,
+            Err(err) => return Some(Err(into_other(err))),
+        };
+        Some(match self.cipher {
+            Some(cipher) => {
+                cipher.decrypt(self.col, &key, &value).map(|value| (key, value.into_boxed_slice()))
+            }
+            None => Ok((key, value)),
+        })
     }
 }
 
@@ -308,28 +390,19 @@ impl RocksDB {
 
 impl Database for RocksDB {
     fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
-        let timer =
-            metrics::DATABASE_OP_LATENCY_HIST.with_label_values(&["get", col.into()]).start_timer();
-        let read_options = rocksdb_read_options();
-        let result = self
-            .db
-            .get_pinned_cf_opt(self.cf_handle(col)?, key, &read_options)
-            .map_err(into_other)?
-            .map(DBSlice::from_rocksdb_slice);
-        timer.observe_duration();
-        Ok(result)
+        self.get_raw_bytes_internal(col, key, None)
     }
 
     fn iter_raw_bytes(&self, col: DBCol) -> DBIterator {
-        Box::new(self.iter_raw_bytes_internal(col, None, None, None))
+        Box::new(self.iter_raw_bytes_internal(col, None, None, None, None))
     }
 
     fn iter(&self, col: DBCol) -> DBIterator {
-        refcount::iter_with_rc_logic(col, self.iter_raw_bytes_internal(col, None, None, None))
+        refcount::iter_with_rc_logic(col, self.iter_raw_bytes_internal(col, None, None, None, None))
     }
 
     fn iter_prefix(&self, col: DBCol, key_prefix: &[u8]) -> DBIterator {
-        let iter = self.iter_raw_bytes_internal(col, Some(key_prefix), None, None);
+        let iter = self.iter_raw_bytes_internal(col, Some(key_prefix), None, None, None);
         refcount::iter_with_rc_logic(col, iter)
     }
 
@@ -339,7 +412,7 @@ impl Database for RocksDB {
         lower_bound: Option<&[u8]>,
         upper_bound: Option<&[u8]>,
     ) -> DBIterator<'a> {
-        let iter = self.iter_raw_bytes_internal(col, None, lower_bound, upper_bound);
+        let iter = self.iter_raw_bytes_internal(col, None, lower_bound, upper_bound, None);
         refcount::iter_with_rc_logic(col, iter)
     }
 
@@ -348,6 +421,7 @@ impl Database for RocksDB {
         for op in transaction.ops {
             match op {
                 DBOp::Set { col, key, value } => {
+                    let value = self.encrypt(col, &key, value);
                     batch.put_cf(self.cf_handle(col)?, key, value);
                 }
                 DBOp::Insert { col, key, value } => {
@@ -356,9 +430,11 @@ impl Database for RocksDB {
                             super::assert_no_overwrite(col, &key, &value, &*old_value)
                         }
                     }
+                    let value = self.encrypt(col, &key, value);
                     batch.put_cf(self.cf_handle(col)?, key, value);
                 }
                 DBOp::UpdateRefcount { col, key, value } => {
+                    let value = self.encrypt(col, &key, value);
                     batch.merge_cf(self.cf_handle(col)?, key, value);
                 }
                 DBOp::Delete { col, key } => {
@@ -381,6 +457,44 @@ impl Database for RocksDB {
         self.db.write(batch).map_err(into_other)
     }
 
+    /// Builds an SST file out of the entries and ingests it into the column
+    /// family so that they don't go through memtables and compaction of the
+    /// upper levels.
+    fn ingest(&self, col: DBCol, entries: Vec<(Vec<u8>, Vec<u8>)>) -> io::Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let _span =
+            tracing::debug_span!(target: "store", "ingest", %col, count = entries.len()).entered();
+        let _timer =
+            metrics::BULK_INGEST_ELAPSED.with_label_values(&[<&str>::from(col)]).start_timer();
+        let cf_handle = self.cf_handle(col)?;
+        // The file is created next to the database so that it can be moved
+        // into it rather than copied.
+        let dir = tempfile::Builder::new().prefix("ingest-").tempdir_in(self.db.path())?;
+        let path = dir.path().join(format!("{col}.sst"));
+
+        let mut opts = Options::default();
+        set_compression_options(&mut opts);
+        let mut writer = ::rocksdb::SstFileWriter::create(&opts);
+        writer.open(&path).map_err(into_other)?;
+        let mut bytes = 0;
+        for (key, value) in entries {
+            bytes += key.len() + value.len();
+            let value = self.encrypt(col, &key, value);
+            writer.put(key, value).map_err(into_other)?;
+        }
+        writer.finish().map_err(into_other)?;
+
+        let mut ingest_opts = ::rocksdb::IngestExternalFileOptions::default();
+        ingest_opts.set_move_files(true);
+        self.db
+            .ingest_external_file_cf_opts(cf_handle, &ingest_opts, vec![path])
+            .map_err(into_other)?;
+        metrics::BULK_INGEST_BYTES.with_label_values(&[<&str>::from(col)]).inc_by(bytes as u64);
+        Ok(())
+    }
+
     fn compact(&self) -> io::Result<()> {
         for col in DBCol::iter() {
             self.compact_column(col)?;
@@ -415,6 +529,26 @@ impl Database for RocksDB {
         }
     }
 
+    fn get_column_stats(&self) -> Vec<(DBCol, ColumnStats)> {
+        use ::rocksdb::properties;
+        self.cf_handles()
+            .map(|(col, handle)| {
+                let prop = |name: &std::ffi::CStr| {
+                    self.db.property_int_value_cf(handle, name).ok().flatten().unwrap_or(0)
+                };
+                let stats = ColumnStats {
+                    estimated_num_keys: prop(properties::ESTIMATE_NUM_KEYS),
+                    estimated_live_data_size: prop(properties::ESTIMATE_LIVE_DATA_SIZE),
+                    total_sst_files_size: prop(properties::TOTAL_SST_FILES_SIZE),
+                    num_sst_files: (0..=6)
+                        .map(|level| prop(&properties::num_files_at_level(level)))
+                        .sum(),
+                };
+                (col, stats)
+            })
+            .collect()
+    }
+
     fn create_checkpoint(&self, path: &std::path::Path) -> anyhow::Result<()> {
         let _span =
             tracing::info_span!(target: "state_snapshot", "create_checkpoint", ?path).entered();
@@ -422,6 +556,33 @@ impl Database for RocksDB {
         cp.create_checkpoint(path)?;
         Ok(())
     }
+
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_> {
+        Box::new(RocksDBSnapshot { db: self, snapshot: self.raw_snapshot() })
+    }
+}
+
+/// Snapshot of a RocksDB database; see [`Database::snapshot`].
+struct RocksDBSnapshot<'a> {
+    db: &'a RocksDB,
+    snapshot: ::rocksdb::Snapshot<'a>,
+}
+
+impl<'a> DatabaseSnapshot for RocksDBSnapshot<'a> {
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        self.db.get_raw_bytes_internal(col, key, Some(&self.snapshot))
+    }
+
+    fn iter_prefix<'b>(&'b self, col: DBCol, key_prefix: &'b [u8]) -> DBIterator<'b> {
+        let iter = self.db.iter_raw_bytes_internal(
+            col,
+            Some(key_prefix),
+            None,
+            None,
+            Some(&self.snapshot),
+        );
+        refcount::iter_with_rc_logic(col, iter)
+    }
 }
 
 /// DB level options
@@ -472,9 +633,13 @@ fn rocksdb_read_options() -> ReadOptions {
     read_options
 }
 
+/// Bits per key of bloom filters of columns which don't override it.
+const DEFAULT_BLOOM_FILTER_BITS: f64 = 10.0;
+
 fn rocksdb_block_based_options(
     block_size: bytesize::ByteSize,
     cache_size: bytesize::ByteSize,
+    bloom_filter_bits: f64,
 ) -> BlockBasedOptions {
     let mut block_opts = BlockBasedOptions::default();
     block_opts.set_block_size(block_size.as_u64().try_into().unwrap());
@@ -482,7 +647,9 @@ fn rocksdb_block_based_options(
     block_opts.set_block_cache(&Cache::new_lru_cache(cache_size.as_u64().try_into().unwrap()));
     block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
     block_opts.set_cache_index_and_filter_blocks(true);
-    block_opts.set_bloom_filter(10.0, true);
+    if bloom_filter_bits > 0.0 {
+        block_opts.set_bloom_filter(bloom_filter_bits, true);
+    }
     block_opts
 }
 
@@ -490,10 +657,13 @@ fn rocksdb_column_options(col: DBCol, store_config: &StoreConfig, temp: Temperat
     let mut opts = Options::default();
     set_compression_options(&mut opts);
     opts.set_level_compaction_dynamic_level_bytes(true);
+    let col_config = store_config.col_config(col).cloned().unwrap_or_default();
     let cache_size = store_config.col_cache_size(col);
+    let bloom_filter_bits = col_config.bloom_filter_bits.unwrap_or(DEFAULT_BLOOM_FILTER_BITS);
     opts.set_block_based_table_factory(&rocksdb_block_based_options(
         store_config.block_size,
         cache_size,
+        bloom_filter_bits,
     ));
 
     // Note that this function changes a lot of rustdb parameters including:
@@ -512,6 +682,37 @@ fn rocksdb_column_options(col: DBCol, store_config: &StoreConfig, temp: Temperat
     opts.optimize_level_style_compaction(memtable_memory_budget);
 
     opts.set_target_file_size_base(64 * bytesize::MIB);
+
+    let write_buffer_size = col_config
+        .write_buffer_size
+        .unwrap_or(bytesize::ByteSize::b(memtable_memory_budget as u64 / 4));
+    opts.set_write_buffer_size(write_buffer_size.as_u64().try_into().unwrap());
+    if let Some(compression) = col_config.compression {
+        // Clear per-level compression set by optimize_level_style_compaction
+        // so that the configured type applies to all levels.
+        opts.set_compression_per_level(&[]);
+        let compression_type = match compression {
+            ColumnCompression::None => rocksdb::DBCompressionType::None,
+            ColumnCompression::Lz4 => rocksdb::DBCompressionType::Lz4,
+            ColumnCompression::Zstd => rocksdb::DBCompressionType::Zstd,
+        };
+        opts.set_compression_type(compression_type);
+        opts.set_bottommost_compression_type(compression_type);
+    }
+
+    let labels = [<&str>::from(temp), <&str>::from(col)];
+    metrics::COLUMN_BLOCK_CACHE_SIZE.with_label_values(&labels).set(cache_size.as_u64() as i64);
+    metrics::COLUMN_WRITE_BUFFER_SIZE
+        .with_label_values(&labels)
+        .set(write_buffer_size.as_u64() as i64);
+    metrics::COLUMN_BLOOM_FILTER_BITS.with_label_values(&labels).set(bloom_filter_bits);
+    for option in [ColumnCompression::None, ColumnCompression::Lz4, ColumnCompression::Zstd] {
+        let value = (col_config.compression == Some(option)) as i64;
+        metrics::COLUMN_COMPRESSION
+            .with_label_values(&[labels[0], labels[1], option.into()])
+            .set(value);
+    }
+
     if temp == Temperature::Hot && col.is_rc() {
         opts.set_merge_operator("refcount merge", RocksDB::refcount_merge, RocksDB::refcount_merge);
         opts.set_compaction_filter("empty value filter", RocksDB::empty_value_compaction_filter);
@@ -825,4 +1026,121 @@ mod tests {
         assert_matches!(store.exists(column, &keys[2]), Ok(false));
         assert_matches!(store.exists(column, &keys[3]), Ok(true));
     }
+
+    #[test]
+    fn test_column_configs() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut config = StoreConfig::test_config();
+        config.column_configs.insert(
+            "FlatState".to_string(),
+            crate::config::ColumnConfig {
+                block_cache_size: Some(bytesize::ByteSize::mib(3)),
+                write_buffer_size: Some(bytesize::ByteSize::mib(4)),
+                compression: Some(ColumnCompression::None),
+                bloom_filter_bits: Some(0.0),
+                ttl: None,
+            },
+        );
+        assert_eq!(config.validate_column_configs(), Vec::<String>::new());
+        assert_eq!(config.col_cache_size(DBCol::FlatState), bytesize::ByteSize::mib(3));
+        assert_eq!(config.col_cache_size(DBCol::State), config.col_state_cache_size);
+
+        let db = RocksDB::open(tmp_dir.path(), &config, Mode::Create, Temperature::Hot).unwrap();
+        let cache_capacity = |col| {
+            db.db
+                .property_int_value_cf(
+                    db.cf_handle(col).unwrap(),
+                    ::rocksdb::properties::BLOCK_CACHE_CAPACITY,
+                )
+                .unwrap()
+        };
+        assert_eq!(cache_capacity(DBCol::FlatState), Some(bytesize::MIB * 3));
+        assert_eq!(cache_capacity(DBCol::State), Some(config.col_state_cache_size.as_u64()));
+
+        let mut transaction = DBTransaction::new();
+        transaction.set(DBCol::FlatState, vec![1], vec![2; 1000]);
+        db.write(transaction).unwrap();
+        db.flush().unwrap();
+        assert_eq!(
+            db.get_raw_bytes(DBCol::FlatState, &[1]).unwrap().as_deref(),
+            Some(&[2; 1000][..])
+        );
+    }
+
+    #[test]
+    fn test_encryption() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let key_file = tmp_dir.path().join("key");
+        std::fs::write(&key_file, hex::encode([7; 32])).unwrap();
+        let db_path = tmp_dir.path().join("data");
+        let mut config = StoreConfig::test_config();
+        config.encryption = Some(crate::config::EncryptionKeySource::KeyFile(key_file));
+
+        let db = RocksDB::open(&db_path, &config, Mode::Create, Temperature::Hot).unwrap();
+        let mut transaction = DBTransaction::new();
+        transaction.set(DBCol::Block, vec![1], b"secret".to_vec());
+        transaction.update_refcount(
+            DBCol::State,
+            vec![2],
+            [&b"trie node"[..], &1i64.to_le_bytes()].concat(),
+        );
+        db.write(transaction).unwrap();
+        assert_eq!(db.get_raw_bytes(DBCol::Block, &[1]).unwrap().as_deref(), Some(&b"secret"[..]));
+        assert_eq!(
+            db.get_with_rc_stripped(DBCol::State, &[2]).unwrap().as_deref(),
+            Some(&b"trie node"[..])
+        );
+        let items: Vec<_> = db.iter(DBCol::Block).collect::<io::Result<_>>().unwrap();
+        assert_eq!(items, vec![(vec![1].into(), b"secret".to_vec().into())]);
+
+        // Values hit the disk encrypted.
+        let raw = db.db.get_cf(db.cf_handle(DBCol::Block).unwrap(), [1]).unwrap().unwrap();
+        assert_ne!(raw, b"secret");
+        drop(db);
+
+        // Opening with a different key or without one fails.
+        let other_key_file = tmp_dir.path().join("other_key");
+        std::fs::write(&other_key_file, hex::encode([8; 32])).unwrap();
+        config.encryption = Some(crate::config::EncryptionKeySource::KeyFile(other_key_file));
+        assert!(RocksDB::open(&db_path, &config, Mode::ReadOnly, Temperature::Hot).is_err());
+        config.encryption = None;
+        assert!(RocksDB::open(&db_path, &config, Mode::ReadOnly, Temperature::Hot).is_err());
+    }
+
+    #[test]
+    fn test_invalid_column_configs() {
+        let mut config = StoreConfig::test_config();
+        config.column_configs.insert(
+            "State".to_string(),
+            crate::config::ColumnConfig {
+                block_cache_size: Some(bytesize::ByteSize::b(0)),
+                write_buffer_size: Some(bytesize::ByteSize::kib(4)),
+                compression: None,
+                bloom_filter_bits: Some(100.0),
+                ttl: Some(std::time::Duration::from_secs(60)),
+            },
+        );
+        config.column_configs.insert("Unknown".to_string(), Default::default());
+        assert_eq!(
+            config.validate_column_configs(),
+            vec![
+                "column_configs.State.block_cache_size must be positive".to_string(),
+                "column_configs.State.bloom_filter_bits must be between 0 and 32, got 100"
+                    .to_string(),
+                "column_configs.State.ttl is not supported; column holds persistent data"
+                    .to_string(),
+                "column_configs.State.write_buffer_size must be at least 1048576 bytes".to_string(),
+                "column_configs: unknown column \"Unknown\"".to_string(),
+            ]
+        );
+
+        let config: StoreConfig = serde_json::from_str(
+            r#"{"column_configs": {"FlatState": {"block_cache_size": 1073741824, "compression": "zstd"}}}"#,
+        )
+        .unwrap();
+        let col_config = config.col_config(DBCol::FlatState).unwrap();
+        assert_eq!(col_config.block_cache_size, Some(bytesize::ByteSize::gib(1)));
+        assert_eq!(col_config.compression, Some(ColumnCompression::Zstd));
+        assert_eq!(col_config.write_buffer_size, None);
+    }
 }
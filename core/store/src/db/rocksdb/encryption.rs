@@ -0,0 +1,239 @@
+//! Encryption of values stored in RocksDB.
+//!
+//! See `StoreConfig::encryption`.
+//!
+//! The Rust bindings don’t expose RocksDB’s encrypted environment so values
+//! are encrypted before they are handed to RocksDB.  Keys are left in plain
+//! text since RocksDB needs them to be ordered.
+//!
+//! Values are sealed with ChaCha20-Poly1305 using a nonce derived from the
+//! column, key and value (like in SIV mode).  The encryption is thus
+//! deterministic which is required by the refcount merge operator: it expects
+//! all operands for a given key to carry the same payload.  For reference
+//! counted columns only the payload is encrypted and the reference count is
+//! kept in plain text so that the merge operator and compaction filter keep
+//! working.
+
+use crate::config::{EncryptionKeySource, Mode};
+use crate::metadata::VERSION_KEY;
+use crate::DBCol;
+use ::rocksdb::{ColumnFamily, DB};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hmac;
+use std::io;
+
+const KEY_LEN: usize = 32;
+const RC_LEN: usize = std::mem::size_of::<i64>();
+
+/// Key in `DBCol::DbVersion` holding a known value encrypted with the key the
+/// database was created with.  Used to detect opening the database with
+/// a wrong key or without one.
+const CHECK_KEY: &[u8] = b"ENCRYPTION_CHECK";
+const CHECK_VALUE: &[u8] = b"near";
+
+pub(crate) struct ValueCipher {
+    key: LessSafeKey,
+    nonce_key: hmac::Key,
+}
+
+impl ValueCipher {
+    /// Reads the key from given source and creates the cipher.
+    pub(crate) fn load(source: &EncryptionKeySource) -> io::Result<Self> {
+        let encoded = match source {
+            EncryptionKeySource::KeyFile(path) => std::fs::read_to_string(path)?,
+            EncryptionKeySource::Command(command) => {
+                let output = std::process::Command::new("sh").arg("-c").arg(command).output()?;
+                if !output.status.success() {
+                    return Err(invalid_key(format!(
+                        "key command exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                String::from_utf8(output.stdout).map_err(|_| invalid_key("not UTF-8".into()))?
+            }
+        };
+        let key = hex::decode(encoded.trim()).map_err(|err| invalid_key(err.to_string()))?;
+        let key: [u8; KEY_LEN] = key
+            .try_into()
+            .map_err(|key: Vec<u8>| invalid_key(format!("expected 32 bytes, got {}", key.len())))?;
+        Ok(Self::new(&key))
+    }
+
+    fn new(master_key: &[u8; KEY_LEN]) -> Self {
+        let master_key = hmac::Key::new(hmac::HMAC_SHA256, master_key);
+        let derive = |purpose: &[u8]| hmac::sign(&master_key, purpose);
+        let key = derive(b"near-store value encryption");
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key.as_ref()[..KEY_LEN]).unwrap();
+        let nonce_key = derive(b"near-store nonce derivation");
+        Self {
+            key: LessSafeKey::new(key),
+            nonce_key: hmac::Key::new(hmac::HMAC_SHA256, nonce_key.as_ref()),
+        }
+    }
+
+    /// Encrypts value stored under given key in given column.
+    ///
+    /// `DBCol::DbVersion` is not encrypted so that database version and kind
+    /// can be read without a key.
+    pub(crate) fn encrypt(&self, col: DBCol, key: &[u8], mut value: Vec<u8>) -> Vec<u8> {
+        if col == DBCol::DbVersion {
+            return value;
+        }
+        let rc = split_refcount(col, &mut value);
+        if col.is_rc() && value.is_empty() {
+            return rc;
+        }
+        let mut sealed = self.seal(&associated_data(col, key), value);
+        sealed.extend_from_slice(&rc);
+        sealed
+    }
+
+    /// Decrypts value read from under given key in given column.
+    pub(crate) fn decrypt(&self, col: DBCol, key: &[u8], value: &[u8]) -> io::Result<Vec<u8>> {
+        let mut value = value.to_vec();
+        if col == DBCol::DbVersion {
+            return Ok(value);
+        }
+        let rc = split_refcount(col, &mut value);
+        if col.is_rc() && value.is_empty() {
+            return Ok(rc);
+        }
+        let mut value =
+            self.open(&associated_data(col, key), value).ok_or_else(|| decryption_failed(col))?;
+        value.extend_from_slice(&rc);
+        Ok(value)
+    }
+
+    /// Encrypts `value` and returns nonce followed by the ciphertext.
+    fn seal(&self, aad: &[u8], mut value: Vec<u8>) -> Vec<u8> {
+        let nonce = self.nonce(aad, &value);
+        self.key.seal_in_place_append_tag(nonce_from(&nonce), Aad::from(aad), &mut value).unwrap();
+        [&nonce[..], &value].concat()
+    }
+
+    /// Decrypts value returned by [`Self::seal`].  Returns `None` if the value
+    /// was not encrypted with this key and `aad`.
+    fn open(&self, aad: &[u8], mut value: Vec<u8>) -> Option<Vec<u8>> {
+        if value.len() < NONCE_LEN {
+            return None;
+        }
+        let mut sealed = value.split_off(NONCE_LEN);
+        let len =
+            self.key.open_in_place(nonce_from(&value), Aad::from(aad), &mut sealed).ok()?.len();
+        sealed.truncate(len);
+        Some(sealed)
+    }
+
+    fn nonce(&self, aad: &[u8], value: &[u8]) -> [u8; NONCE_LEN] {
+        let mut ctx = hmac::Context::with_key(&self.nonce_key);
+        ctx.update(&(aad.len() as u64).to_le_bytes());
+        ctx.update(aad);
+        ctx.update(value);
+        ctx.sign().as_ref()[..NONCE_LEN].try_into().unwrap()
+    }
+}
+
+/// Verifies that the database is opened with the key it was created with, or
+/// without one if it's not encrypted.  When a new database is opened with
+/// encryption in read-write mode, stores the value used for the verification.
+///
+/// `cf` must be the handle of `DBCol::DbVersion` column.
+pub(crate) fn check_key(
+    db: &DB,
+    cf: &ColumnFamily,
+    cipher: Option<&ValueCipher>,
+    mode: Mode,
+) -> io::Result<()> {
+    let get = |key| db.get_cf(cf, key).map_err(super::into_other);
+    match (get(CHECK_KEY)?, cipher) {
+        (None, None) => Ok(()),
+        (Some(_), None) => Err(invalid_key(
+            "database is encrypted but no encryption key is configured".to_string(),
+        )),
+        (Some(value), Some(cipher)) => match cipher.open(CHECK_KEY, value) {
+            Some(value) if value == CHECK_VALUE => Ok(()),
+            _ => Err(invalid_key("database was encrypted with a different key".to_string())),
+        },
+        (None, Some(cipher)) => {
+            if get(VERSION_KEY)?.is_some() {
+                return Err(invalid_key("database was created without encryption".to_string()));
+            }
+            if mode.read_write() {
+                let value = cipher.seal(CHECK_KEY, CHECK_VALUE.to_vec());
+                db.put_cf(cf, CHECK_KEY, value).map_err(super::into_other)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// For reference counted columns, removes the reference count from the value
+/// and returns it.  Values shorter than a reference count, i.e. empty values
+/// of deleted entries, are returned whole.  For other columns returns empty
+/// vector.
+fn split_refcount(col: DBCol, value: &mut Vec<u8>) -> Vec<u8> {
+    if !col.is_rc() {
+        return Vec::new();
+    }
+    let len = value.len().saturating_sub(RC_LEN);
+    value.split_off(len)
+}
+
+/// Binds the value to the column and key it's stored under so that values
+/// can't be swapped around undetected.
+fn associated_data(col: DBCol, key: &[u8]) -> Vec<u8> {
+    [super::col_name(col).as_bytes(), &[0u8], key].concat()
+}
+
+fn nonce_from(bytes: &[u8]) -> Nonce {
+    Nonce::try_assume_unique_for_key(bytes).unwrap()
+}
+
+fn invalid_key(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid database encryption key: {msg}"))
+}
+
+fn decryption_failed(col: DBCol) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("failed to decrypt value in {col}; is the encryption key correct?"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValueCipher;
+    use crate::DBCol;
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = ValueCipher::new(&[7; 32]);
+        let value = b"some value".to_vec();
+        let encrypted = cipher.encrypt(DBCol::Block, b"key", value.clone());
+        assert_ne!(encrypted, value);
+        // Encryption is deterministic.
+        assert_eq!(encrypted, cipher.encrypt(DBCol::Block, b"key", value.clone()));
+        assert_eq!(cipher.decrypt(DBCol::Block, b"key", &encrypted).unwrap(), value);
+        // Value is bound to its key and column.
+        assert!(cipher.decrypt(DBCol::Block, b"other", &encrypted).is_err());
+        assert!(cipher.decrypt(DBCol::BlockHeader, b"key", &encrypted).is_err());
+        // And can't be decrypted with other key.
+        assert!(ValueCipher::new(&[8; 32]).decrypt(DBCol::Block, b"key", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_refcount_kept_in_plain_text() {
+        let cipher = ValueCipher::new(&[7; 32]);
+        let value = [&b"payload"[..], &3i64.to_le_bytes()].concat();
+        let encrypted = cipher.encrypt(DBCol::State, b"key", value.clone());
+        assert_eq!(encrypted[encrypted.len() - 8..], 3i64.to_le_bytes());
+        assert_eq!(cipher.decrypt(DBCol::State, b"key", &encrypted).unwrap(), value);
+
+        // Decrements carry no payload and empty values mean deleted entry.
+        let decrement = (-1i64).to_le_bytes().to_vec();
+        assert_eq!(cipher.encrypt(DBCol::State, b"key", decrement.clone()), decrement);
+        assert_eq!(cipher.encrypt(DBCol::State, b"key", vec![]), Vec::<u8>::new());
+        assert_eq!(cipher.decrypt(DBCol::State, b"key", &[]).unwrap(), Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,319 @@
+//! Detection and repair of RocksDB corruption when opening the database.
+//!
+//! Detection is per column, but the repair isn't: RocksDB repair rebuilds the
+//! manifest of the whole database from the files found in its directory, so it
+//! can't be limited to the damaged columns.  Blocks which can't be salvaged
+//! from damaged files are dropped whatever column they belong to.  To tell the
+//! operator which part of the chain has to be synced again, the report lists
+//! heights whose block was lost.
+//!
+//! See `StoreConfig::corruption_recovery`.
+
+use super::{rocksdb_options, RocksDB};
+use crate::config::Mode;
+use crate::db::{Database, HEAD_KEY, TAIL_KEY};
+use crate::{DBCol, Store, StoreConfig, Temperature};
+use ::rocksdb::{IteratorMode, ReadOptions, DB};
+use near_primitives::block::Tip;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+use near_primitives::utils::index_to_bytes;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+/// SST file in which RocksDB detected corruption.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DamagedFile {
+    /// Name of the file, relative to the database directory.
+    pub name: String,
+    /// Column the file belongs to, if it is known.
+    pub column: Option<DBCol>,
+    /// Hex-encoded range of keys stored in the file, if it is known.
+    pub key_range: Option<(String, String)>,
+}
+
+/// Result of verifying and repairing a corrupted database.
+#[derive(Debug, Default)]
+pub struct CorruptionReport {
+    /// Columns in which reading data failed because of corruption.
+    pub columns: Vec<DBCol>,
+    /// Files in which corruption was detected.
+    pub damaged_files: Vec<DamagedFile>,
+    /// Errors reported by RocksDB.
+    pub errors: Vec<String>,
+    /// Files which could not be salvaged by repair and were moved to the
+    /// backup directory.
+    pub lost_files: Vec<String>,
+    /// Inclusive ranges of heights whose block is missing after the repair.
+    pub lost_block_heights: Vec<(BlockHeight, BlockHeight)>,
+    /// Directory with backup of database metadata and damaged files.
+    pub backup_dir: PathBuf,
+}
+
+fn is_corruption(err: &str) -> bool {
+    err.starts_with("Corruption:")
+}
+
+/// Extracts name of the SST file from a RocksDB error message, e.g.
+/// `Corruption: block checksum mismatch: ... in /data/000042.sst offset 0 size 4096`.
+fn sst_file_name(err: &str) -> Option<String> {
+    err.split_whitespace()
+        .find(|word| word.ends_with(".sst"))
+        .and_then(|path| Path::new(path).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Returns inclusive ranges of heights between the tail and the head of the
+/// chain whose block is indexed in `BlockHeight` but missing from `Block`.
+///
+/// Heights whose `BlockHeight` entry was lost as well aren't detected.  This
+/// walks the whole chain, which takes a while on archival nodes.
+fn lost_block_heights(store: &Store) -> io::Result<Vec<(BlockHeight, BlockHeight)>> {
+    let Some(head) = store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? else {
+        return Ok(vec![]);
+    };
+    let tail = store.get_ser::<BlockHeight>(DBCol::BlockMisc, TAIL_KEY)?.unwrap_or(0);
+    let mut ranges: Vec<(BlockHeight, BlockHeight)> = vec![];
+    // Whether no block was found since the start of the last range, so that
+    // skipped heights don't split it.
+    let mut extend = false;
+    for height in tail..=head.height {
+        let key = index_to_bytes(height);
+        let Some(hash) = store.get_ser::<CryptoHash>(DBCol::BlockHeight, &key)? else {
+            continue;
+        };
+        if store.exists(DBCol::Block, hash.as_ref())? {
+            extend = false;
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if extend => *end = height,
+            _ => ranges.push((height, height)),
+        }
+        extend = true;
+    }
+    Ok(ranges)
+}
+
+impl RocksDB {
+    /// Verifies checksums of all data in the database at `path` and repairs it
+    /// if RocksDB reports corruption.
+    ///
+    /// Returns `None` if the database is not corrupted.  Otherwise returns
+    /// report of the damage, which is also saved to the backup directory.
+    pub(crate) fn recover_if_corrupted(
+        path: &Path,
+        store_config: &StoreConfig,
+        temp: Temperature,
+    ) -> io::Result<Option<CorruptionReport>> {
+        let mut report = match Self::verify_checksums(path, store_config, temp)? {
+            Some(report) => report,
+            None => return Ok(None),
+        };
+        tracing::warn!(target: "db_opener", path = %path.display(), columns = ?report.columns, errors = ?report.errors, "Database is corrupted, repairing it");
+        Self::repair(path, store_config, &mut report)?;
+        let db = Self::open(path, store_config, Mode::ReadOnly, temp)?;
+        match lost_block_heights(&Store::new(Arc::new(db))) {
+            Ok(heights) => report.lost_block_heights = heights,
+            Err(err) => {
+                tracing::warn!(target: "db_opener", ?err, "Failed to find heights of lost blocks")
+            }
+        }
+        let report_path = report.backup_dir.join("report.txt");
+        std::fs::write(&report_path, format!("{report:#?}\n"))?;
+        tracing::warn!(target: "db_opener", path = %path.display(), report = %report_path.display(), lost_files = ?report.lost_files, lost_block_heights = ?report.lost_block_heights, "Repaired corrupted database");
+        Ok(Some(report))
+    }
+
+    /// Reads every column of the database verifying checksums.  Reading of a
+    /// column stops at the first corrupted block.
+    fn verify_checksums(
+        path: &Path,
+        store_config: &StoreConfig,
+        temp: Temperature,
+    ) -> io::Result<Option<CorruptionReport>> {
+        let db = match Self::open(path, store_config, Mode::ReadWriteExisting, temp) {
+            Ok(db) => db,
+            Err(err) if is_corruption(&err.to_string()) => {
+                let error = err.to_string();
+                let damaged_files = sst_file_name(&error)
+                    .map(|name| DamagedFile { name, ..Default::default() })
+                    .into_iter()
+                    .collect();
+                return Ok(Some(CorruptionReport {
+                    damaged_files,
+                    errors: vec![error],
+                    ..Default::default()
+                }));
+            }
+            Err(err) => return Err(err),
+        };
+        // Data in the write-ahead log has to be flushed because repair
+        // recovers it without the merge operator configured.
+        db.flush()?;
+
+        let mut report = CorruptionReport::default();
+        for col in DBCol::iter() {
+            tracing::info!(target: "db_opener", ?col, "Verifying checksums");
+            let mut read_options = ReadOptions::default();
+            read_options.set_verify_checksums(true);
+            read_options.fill_cache(false);
+            for item in db.db.iterator_cf_opt(db.cf_handle(col)?, read_options, IteratorMode::Start)
+            {
+                let Err(err) = item else { continue };
+                let error = err.into_string();
+                if !is_corruption(&error) {
+                    return Err(io::Error::new(io::ErrorKind::Other, error));
+                }
+                report.columns.push(col);
+                if let Some(name) = sst_file_name(&error) {
+                    report.damaged_files.push(Self::damaged_file(&db.db, col, name));
+                }
+                report.errors.push(error);
+                break;
+            }
+        }
+        Ok(if report.errors.is_empty() { None } else { Some(report) })
+    }
+
+    fn damaged_file(db: &DB, col: DBCol, name: String) -> DamagedFile {
+        let live_file = db.live_files().ok().and_then(|files| {
+            files.into_iter().find(|file| file.name.trim_start_matches('/') == name)
+        });
+        let key_range = live_file
+            .and_then(|file| Some((hex::encode(file.start_key?), hex::encode(file.end_key?))));
+        DamagedFile { name, column: Some(col), key_range }
+    }
+
+    /// Backs up database metadata and damaged files and runs RocksDB repair.
+    /// Files which couldn't be salvaged are moved to the backup directory.
+    fn repair(
+        path: &Path,
+        store_config: &StoreConfig,
+        report: &mut CorruptionReport,
+    ) -> io::Result<()> {
+        let timestamp =
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let backup_dir = path.join(format!("corruption-backup-{timestamp}"));
+        std::fs::create_dir_all(&backup_dir)?;
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_damaged = report.damaged_files.iter().any(|file| file.name == name);
+            if entry.file_type()?.is_file() && (!name.ends_with(".sst") || is_damaged) {
+                std::fs::copy(entry.path(), backup_dir.join(&name))?;
+            }
+        }
+        report.backup_dir = backup_dir.clone();
+
+        DB::repair(&rocksdb_options(store_config, Mode::ReadWrite), path)
+            .map_err(super::into_other)?;
+
+        // Repair moves files it couldn't salvage to the `lost` directory.
+        let lost_dir = path.join("lost");
+        if lost_dir.is_dir() {
+            let lost_backup_dir = backup_dir.join("lost");
+            std::fs::create_dir_all(&lost_backup_dir)?;
+            for entry in std::fs::read_dir(&lost_dir)? {
+                let entry = entry?;
+                std::fs::rename(entry.path(), lost_backup_dir.join(entry.file_name()))?;
+                report.lost_files.push(entry.file_name().to_string_lossy().into_owned());
+            }
+            std::fs::remove_dir(&lost_dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lost_block_heights, sst_file_name};
+    use crate::db::rocksdb::RocksDB;
+    use crate::db::{HEAD_KEY, TAIL_KEY};
+    use crate::test_utils::create_test_store;
+    use crate::{DBCol, NodeStorage, StoreConfig, Temperature};
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::EpochId;
+    use near_primitives::utils::index_to_bytes;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_sst_file_name() {
+        let err = "Corruption: block checksum mismatch: stored = 1, computed = 2, type = 1  in /home/near/data/000042.sst offset 0 size 4045";
+        assert_eq!(sst_file_name(err).as_deref(), Some("000042.sst"));
+        assert_eq!(sst_file_name("Corruption: bad WAL record"), None);
+    }
+
+    #[test]
+    fn test_lost_block_heights() {
+        let store = create_test_store();
+        assert_eq!(lost_block_heights(&store).unwrap(), vec![]);
+
+        // Blocks at heights 3, 5 and 8 are lost and height 4 was skipped.
+        let mut update = store.store_update();
+        for height in [1u64, 2, 3, 5, 6, 7, 8] {
+            let hash = CryptoHash::hash_bytes(&height.to_le_bytes());
+            update.set_ser(DBCol::BlockHeight, &index_to_bytes(height), &hash).unwrap();
+            if ![3, 5, 8].contains(&height) {
+                update.insert(DBCol::Block, hash.as_ref().to_vec(), b"block".to_vec());
+            }
+        }
+        let head = Tip {
+            height: 8,
+            last_block_hash: CryptoHash::default(),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        };
+        update.set_ser(DBCol::BlockMisc, HEAD_KEY, &head).unwrap();
+        update.set_ser(DBCol::BlockMisc, TAIL_KEY, &1u64).unwrap();
+        update.commit().unwrap();
+        assert_eq!(lost_block_heights(&store).unwrap(), vec![(3, 5), (8, 8)]);
+    }
+
+    #[test]
+    fn test_recover_corrupted_db() {
+        let (tmp_dir, opener) = NodeStorage::test_opener();
+        let path = opener.path().to_path_buf();
+        {
+            let storage = opener.open().unwrap();
+            let store = storage.get_hot_store();
+            let mut update = store.store_update();
+            for i in 0..1000u32 {
+                update.set(DBCol::RecentOutboundConnections, &i.to_be_bytes(), &[i as u8; 100]);
+            }
+            update.set(DBCol::BlockMisc, b"key", b"value");
+            update.commit().unwrap();
+            store.flush().unwrap();
+        }
+        let config = StoreConfig::test_config();
+        assert!(RocksDB::recover_if_corrupted(&path, &config, Temperature::Hot).unwrap().is_none());
+
+        // Corrupt the beginning of every SST file, where the data blocks are.
+        for entry in std::fs::read_dir(&path).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_name().to_string_lossy().ends_with(".sst") {
+                let mut file = std::fs::OpenOptions::new().write(true).open(entry.path()).unwrap();
+                file.seek(SeekFrom::Start(16)).unwrap();
+                file.write_all(&[0xff; 32]).unwrap();
+            }
+        }
+
+        let report = RocksDB::recover_if_corrupted(&path, &config, Temperature::Hot)
+            .unwrap()
+            .expect("corruption should be detected");
+        assert!(report.columns.contains(&DBCol::RecentOutboundConnections), "{report:?}");
+        assert!(report.backup_dir.starts_with(&path));
+        assert!(report.backup_dir.join("report.txt").is_file());
+        for file in &report.damaged_files {
+            assert!(report.backup_dir.join(&file.name).is_file());
+        }
+
+        // After repair the database opens and reads without errors.
+        assert!(RocksDB::recover_if_corrupted(&path, &config, Temperature::Hot).unwrap().is_none());
+        drop(tmp_dir);
+    }
+}
@@ -5,7 +5,10 @@ use std::sync::Arc;
 
 use near_o11y::log_assert_fail;
 
-use crate::db::{DBIterator, DBIteratorItem, DBSlice, DBTransaction, Database, StoreStatistics};
+use crate::db::{
+    ColumnStats, DBIterator, DBIteratorItem, DBSlice, DBTransaction, Database, DatabaseSnapshot,
+    StoreStatistics,
+};
 use crate::DBCol;
 
 /// A database that provides access to the hot and cold databases.
@@ -198,10 +201,65 @@ impl Database for SplitDB {
         None
     }
 
+    fn get_column_stats(&self) -> Vec<(DBCol, ColumnStats)> {
+        log_assert_fail!("get_column_stats is not allowed - the split storage has two stores");
+        vec![]
+    }
+
     fn create_checkpoint(&self, _path: &std::path::Path) -> anyhow::Result<()> {
         log_assert_fail!("create_checkpoint is not allowed - the split storage has two stores");
         Ok(())
     }
+
+    /// Takes snapshots of both databases.  The hot snapshot is taken first so
+    /// that data moved from hot to cold storage in between is visible in the
+    /// cold snapshot.
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_> {
+        let hot = self.hot.snapshot();
+        let cold = self.cold.snapshot();
+        Box::new(SplitDBSnapshot { hot, cold })
+    }
+}
+
+/// Snapshot of a [`SplitDB`], reading the same way the database does.
+struct SplitDBSnapshot<'a> {
+    hot: Box<dyn DatabaseSnapshot + 'a>,
+    cold: Box<dyn DatabaseSnapshot + 'a>,
+}
+
+impl<'a> DatabaseSnapshot for SplitDBSnapshot<'a> {
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        if let Some(hot_result) = self.hot.get_raw_bytes(col, key)? {
+            return Ok(Some(hot_result));
+        }
+        if col.is_cold() {
+            return self.cold.get_raw_bytes(col, key);
+        }
+        Ok(None)
+    }
+
+    fn get_with_rc_stripped(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        assert!(col.is_rc());
+
+        if let Some(hot_result) = self.hot.get_with_rc_stripped(col, key)? {
+            return Ok(Some(hot_result));
+        }
+        if col.is_cold() {
+            return self.cold.get_with_rc_stripped(col, key);
+        }
+        Ok(None)
+    }
+
+    fn iter_prefix<'b>(&'b self, col: DBCol, key_prefix: &'b [u8]) -> DBIterator<'b> {
+        if !col.is_cold() {
+            return self.hot.iter_prefix(col, key_prefix);
+        }
+
+        SplitDB::merge_iter(
+            self.hot.iter_prefix(col, key_prefix),
+            self.cold.iter_prefix(col, key_prefix),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -3,7 +3,9 @@ use std::io;
 use std::ops::Bound;
 use std::sync::{Arc, RwLock};
 
-use crate::db::{refcount, DBIterator, DBOp, DBSlice, DBTransaction, Database};
+use crate::db::{
+    refcount, ColumnStats, DBIterator, DBOp, DBSlice, DBTransaction, Database, DatabaseSnapshot,
+};
 use crate::{DBCol, StoreStatistics};
 
 /// An in-memory database intended for tests and IO-agnostic estimations.
@@ -128,7 +130,42 @@ impl Database for TestDB {
         self.stats.read().unwrap().clone()
     }
 
+    fn get_column_stats(&self) -> Vec<(DBCol, ColumnStats)> {
+        let db = self.db.read().unwrap();
+        db.iter()
+            .map(|(col, entries)| {
+                let size =
+                    entries.iter().map(|(key, value)| key.len() + value.len()).sum::<usize>();
+                let stats = ColumnStats {
+                    estimated_num_keys: entries.len() as u64,
+                    estimated_live_data_size: size as u64,
+                    ..ColumnStats::default()
+                };
+                (col, stats)
+            })
+            .collect()
+    }
+
     fn create_checkpoint(&self, _path: &std::path::Path) -> anyhow::Result<()> {
         Ok(())
     }
+
+    /// Copies the whole database.
+    fn snapshot(&self) -> Box<dyn DatabaseSnapshot + '_> {
+        let db = self.db.read().unwrap().clone();
+        Box::new(TestDBSnapshot(TestDB { db: RwLock::new(db), stats: RwLock::new(None) }))
+    }
+}
+
+/// Snapshot of a [`TestDB`] holding a copy of its data.
+struct TestDBSnapshot(TestDB);
+
+impl DatabaseSnapshot for TestDBSnapshot {
+    fn get_raw_bytes(&self, col: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        self.0.get_raw_bytes(col, key)
+    }
+
+    fn iter_prefix<'a>(&'a self, col: DBCol, key_prefix: &'a [u8]) -> DBIterator<'a> {
+        self.0.iter_prefix(col, key_prefix)
+    }
 }
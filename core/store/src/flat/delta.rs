@@ -118,6 +118,22 @@ impl FlatStateChanges {
         Self(delta)
     }
 
+    /// Approximate size in bytes of the changes stored on disk: keys and
+    /// values, or value references, without serialization overhead.
+    pub fn stored_size(&self) -> u64 {
+        self.0
+            .iter()
+            .map(|(key, value)| {
+                let value_size = match value {
+                    Some(FlatStateValue::Ref(_)) => std::mem::size_of::<ValueRef>(),
+                    Some(FlatStateValue::Inlined(value)) => value.len(),
+                    None => 0,
+                };
+                (key.len() + value_size) as u64
+            })
+            .sum()
+    }
+
     /// Applies delta to the flat state.
     pub fn apply_to_flat_state(self, store_update: &mut StoreUpdate, shard_uid: ShardUId) {
         for (key, value) in self.0.into_iter() {
@@ -135,6 +151,9 @@ pub struct CachedFlatStateChanges(HashMap<CryptoHash, Option<ValueRef>>);
 pub struct CachedFlatStateDelta {
     pub metadata: FlatStateDeltaMetadata,
     pub changes: Arc<CachedFlatStateChanges>,
+    /// Approximate size of the changes stored on disk, see
+    /// `FlatStateChanges::stored_size`.
+    pub stored_size: u64,
 }
 
 impl From<FlatStateChanges> for CachedFlatStateChanges {
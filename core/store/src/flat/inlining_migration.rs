@@ -12,15 +12,18 @@ use near_primitives::shard_layout::ShardUId;
 use near_primitives::state::FlatStateValue;
 use tracing::{debug, info};
 
+use crate::config::FlatStateValuesInliningConfig;
 use crate::flat::store_helper::set_flat_state_values_inlining_migration_status;
 use crate::metrics::flat_state_metrics::inlining_migration::{
-    FLAT_STATE_PAUSED_DURATION, INLINED_COUNT, INLINED_TOTAL_VALUES_SIZE, PROCESSED_COUNT,
-    PROCESSED_TOTAL_VALUES_SIZE, SKIPPED_COUNT,
+    CURRENT_SHARD_ID, FLAT_STATE_PAUSED_DURATION, INLINED_COUNT, INLINED_TOTAL_VALUES_SIZE,
+    PROCESSED_BATCH_COUNT, PROCESSED_COUNT, PROCESSED_TOTAL_VALUES_SIZE, SKIPPED_COUNT,
 };
 use crate::{DBCol, Store, TrieDBStorage, TrieStorage};
 
 use super::store_helper::{
-    decode_flat_state_db_key, get_flat_state_values_inlining_migration_status,
+    decode_flat_state_db_key, get_flat_state_values_inlining_migration_progress,
+    get_flat_state_values_inlining_migration_status,
+    set_flat_state_values_inlining_migration_progress,
 };
 use super::types::FlatStateValuesInliningMigrationStatus;
 use super::FlatStorageManager;
@@ -30,13 +33,12 @@ pub struct FlatStateValuesInliningMigrationHandle {
     keep_running: Arc<AtomicBool>,
 }
 
-const BACKGROUND_MIGRATION_BATCH_SIZE: usize = 50_000;
-
 impl FlatStateValuesInliningMigrationHandle {
     pub fn start_background_migration(
         store: Store,
         flat_storage_manager: FlatStorageManager,
         read_state_threads: usize,
+        config: FlatStateValuesInliningConfig,
     ) -> Self {
         let keep_running = Arc::new(AtomicBool::new(true));
         let keep_runnning_clone = keep_running.clone();
@@ -57,7 +59,7 @@ impl FlatStateValuesInliningMigrationHandle {
                 &flat_storage_manager,
                 &keep_running,
                 read_state_threads,
-                BACKGROUND_MIGRATION_BATCH_SIZE,
+                &config,
             );
             if completed {
                 set_flat_state_values_inlining_migration_status(
@@ -159,34 +161,55 @@ impl StateValueReader {
     }
 }
 
-/// Inlines all FlatState values having length below `FlatStateValue::INLINE_DISK_VALUE_THRESHOLD`.
+/// Inlines all FlatState values having length up to `config.max_inlined_value_size`,
+/// which is capped at `FlatStateValue::INLINE_DISK_VALUE_THRESHOLD`.
 /// Migration is safe to be executed in parallel with block processing, which
 /// is achieved by temporary preventing FlatState updates with
 /// `FlatStorageManager::set_flat_state_updates_mode`.
 ///
+/// The last processed key is stored after every batch, so an interrupted
+/// migration resumes where it stopped. Returns whether the migration completed.
+///
 /// * `read_state_threads` - number of threads for reading values from `State` in parallel.
-/// * `batch_size` - number of values to be processed for inlining in one batch.
+/// * `config` - inlining threshold, batch size and pause between batches.
 pub fn inline_flat_state_values(
     store: Store,
     flat_storage_manager: &FlatStorageManager,
     keep_running: &AtomicBool,
     read_state_threads: usize,
-    batch_size: usize,
+    config: &FlatStateValuesInliningConfig,
 ) -> bool {
-    info!(target: "store", %read_state_threads, %batch_size, "Starting FlatState value inlining migration");
+    let batch_size = config.batch_size.max(1);
+    let max_inlined_value_size =
+        config.max_inlined_value_size.min(FlatStateValue::INLINE_DISK_VALUE_THRESHOLD);
+    let resume_from = get_flat_state_values_inlining_migration_progress(&store)
+        .expect("failed to read fs migration progress")
+        .map(|mut key| {
+            // The stored key was processed already, start right after it.
+            key.push(0u8);
+            key
+        });
+    info!(target: "store", %read_state_threads, %batch_size, %max_inlined_value_size, batch_delay = ?config.batch_delay, resume_from = ?resume_from.as_deref().map(hex::encode), "Starting FlatState value inlining migration");
     let migration_start = std::time::Instant::now();
     let mut value_reader = StateValueReader::new(store.clone(), read_state_threads);
     let mut inlined_total_count = 0;
     let mut interrupted = false;
-    for (batch_index, batch) in
-        store.iter(DBCol::FlatState).chunks(batch_size).into_iter().enumerate()
+    for (batch_index, batch) in store
+        .iter_range(DBCol::FlatState, resume_from.as_deref(), None)
+        .chunks(batch_size)
+        .into_iter()
+        .enumerate()
     {
         if !keep_running.load(std::sync::atomic::Ordering::Relaxed) {
             info!(target: "store", %batch_index, "FlatState value inlining migration was interrupted");
             interrupted = true;
             break;
         }
+        if batch_index > 0 && !config.batch_delay.is_zero() {
+            std::thread::sleep(config.batch_delay);
+        }
         let (mut min_key, mut max_key) = (None, None);
+        let mut last_key = None;
         for entry in batch {
             PROCESSED_COUNT.inc();
             let (key, value) = match entry {
@@ -196,6 +219,7 @@ pub fn inline_flat_state_values(
                     continue;
                 }
             };
+            last_key = Some(key.clone());
             let shard_uid = match decode_flat_state_db_key(&key) {
                 Ok((shard_uid, _)) => shard_uid,
                 Err(err) => {
@@ -216,7 +240,7 @@ pub fn inline_flat_state_values(
             };
             PROCESSED_TOTAL_VALUES_SIZE.inc_by(value_size);
             if let FlatStateValue::Ref(value_ref) = fs_value {
-                if value_ref.length as usize <= FlatStateValue::INLINE_DISK_VALUE_THRESHOLD {
+                if value_ref.length as usize <= max_inlined_value_size {
                     if min_key.is_none() {
                         min_key = Some(key.to_vec());
                     }
@@ -274,9 +298,21 @@ pub fn inline_flat_state_values(
             batch_duration = batch_inlining_start.elapsed();
             FLAT_STATE_PAUSED_DURATION.observe(batch_duration.as_secs_f64());
         }
+        if let Some(last_key) = last_key {
+            set_flat_state_values_inlining_migration_progress(&store, Some(&last_key[..]))
+                .expect("failed to set fs migration progress");
+            if let Ok((shard_uid, _)) = decode_flat_state_db_key(&last_key) {
+                CURRENT_SHARD_ID.set(shard_uid.shard_id() as i64);
+            }
+        }
+        PROCESSED_BATCH_COUNT.inc();
         debug!(target: "store", %batch_index, %inlined_batch_count, %inlined_total_count, ?batch_duration, "Processed flat state value inlining batch");
     }
     value_reader.close();
+    if !interrupted {
+        set_flat_state_values_inlining_migration_progress(&store, None)
+            .expect("failed to clear fs migration progress");
+    }
     let migration_elapsed = migration_start.elapsed();
     info!(target: "store", %inlined_total_count, ?migration_elapsed, %interrupted, "Finished FlatState value inlining migration");
     !interrupted
@@ -310,7 +346,11 @@ fn log_skipped(reason: &str, err: impl std::error::Error) {
 #[cfg(test)]
 mod tests {
     use super::inline_flat_state_values;
-    use crate::flat::store_helper::encode_flat_state_db_key;
+    use crate::config::FlatStateValuesInliningConfig;
+    use crate::flat::store_helper::{
+        encode_flat_state_db_key, get_flat_state_values_inlining_migration_progress,
+        set_flat_state_values_inlining_migration_progress,
+    };
     use crate::flat::{FlatStateValuesInliningMigrationHandle, FlatStorageManager};
     use crate::{DBCol, NodeStorage, Store, TrieCachingStorage};
     use borsh::{BorshDeserialize, BorshSerialize};
@@ -340,7 +380,7 @@ mod tests {
             &flat_storage_manager,
             &AtomicBool::new(true),
             2,
-            4,
+            &FlatStateValuesInliningConfig { batch_size: 4, ..Default::default() },
         );
         assert_eq!(
             store
@@ -383,6 +423,7 @@ mod tests {
             store.clone(),
             flat_storage_manager.clone(),
             2,
+            FlatStateValuesInliningConfig::default(),
         );
 
         // Give it time and check that no progress was made on the migration.
@@ -422,6 +463,7 @@ mod tests {
             store.clone(),
             flat_storage_manager,
             2,
+            FlatStateValuesInliningConfig::default(),
         );
 
         // Give it time and check that no progress was made on the migration.
@@ -435,6 +477,49 @@ mod tests {
         assert_eq!(count_inlined_values(&store), 0);
     }
 
+    #[test]
+    fn resume_migration_and_threshold() {
+        init_test_logger();
+        let store = NodeStorage::test_opener().1.open().unwrap().get_hot_store();
+        let shard_uid = ShardLayout::v0_single_shard().get_shard_uids()[0];
+        let values = [vec![0], vec![1; 100], vec![2], vec![3; 100], vec![4]];
+        populate_flat_store(&store, shard_uid, &values);
+        let flat_storage_manager = create_flat_storage_for_genesis(&store, shard_uid);
+        // Pretend that an earlier run was interrupted after processing the
+        // first two keys.
+        set_flat_state_values_inlining_migration_progress(
+            &store,
+            Some(&encode_flat_state_db_key(shard_uid, &[1])),
+        )
+        .unwrap();
+        let config = FlatStateValuesInliningConfig {
+            max_inlined_value_size: 10,
+            batch_size: 2,
+            batch_delay: Duration::from_millis(1),
+        };
+        assert!(inline_flat_state_values(
+            store.clone(),
+            &flat_storage_manager,
+            &AtomicBool::new(true),
+            2,
+            &config,
+        ));
+        assert_eq!(
+            store
+                .iter(DBCol::FlatState)
+                .flat_map(|r| r.map(|(_, v)| FlatStateValue::try_from_slice(&v).unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                FlatStateValue::value_ref(&values[0]),
+                FlatStateValue::value_ref(&values[1]),
+                FlatStateValue::inlined(&values[2]),
+                FlatStateValue::value_ref(&values[3]),
+                FlatStateValue::inlined(&values[4]),
+            ]
+        );
+        assert_eq!(get_flat_state_values_inlining_migration_progress(&store).unwrap(), None);
+    }
+
     fn populate_flat_store(store: &Store, shard_uid: ShardUId, values: &[Vec<u8>]) {
         let mut store_update = store.store_update();
         for (i, value) in values.iter().enumerate() {
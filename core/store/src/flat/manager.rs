@@ -10,11 +10,13 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tracing::debug;
 
+use crate::config::{FlatStateValuesInliningConfig, FlatStorageDeltaPruningConfig};
 use crate::{get_genesis_hash, Store, StoreUpdate};
 
 use super::chunk_view::FlatStorageChunkView;
 use super::{
-    FlatStateChanges, FlatStateDelta, FlatStateDeltaMetadata, FlatStorage, FlatStorageError,
+    FlatStateChanges, FlatStateDelta, FlatStateDeltaMetadata,
+    FlatStateValuesInliningMigrationHandle, FlatStorage, FlatStorageError,
 };
 
 /// `FlatStorageManager` provides a way to construct new flat state to pass to new tries.
@@ -34,11 +36,20 @@ pub struct FlatStorageManagerInner {
     /// this epoch can share the same `head` and `tail`, similar for shards for the next epoch,
     /// but such overhead is negligible comparing the delta sizes, so we think it's ok.
     flat_storages: Mutex<HashMap<ShardUId, FlatStorage>>,
+    delta_pruning: FlatStorageDeltaPruningConfig,
 }
 
 impl FlatStorageManager {
     pub fn new(store: Store) -> Self {
-        Self(Arc::new(FlatStorageManagerInner { store, flat_storages: Default::default() }))
+        Self::with_delta_pruning(store, FlatStorageDeltaPruningConfig::default())
+    }
+
+    pub fn with_delta_pruning(store: Store, delta_pruning: FlatStorageDeltaPruningConfig) -> Self {
+        Self(Arc::new(FlatStorageManagerInner {
+            store,
+            flat_storages: Default::default(),
+            delta_pruning,
+        }))
     }
 
     /// When a node starts from an empty database, this function must be called to ensure
@@ -82,7 +93,8 @@ impl FlatStorageManager {
     /// Update flat storage for given processed or caught up block, which includes:
     /// - merge deltas from current flat storage head to new one;
     /// - update flat storage head to the hash of final block visible from given one;
-    /// - remove info about unreachable blocks from memory.
+    /// - remove info about unreachable blocks from memory;
+    /// - prune deltas of abandoned forks, if enabled, see `FlatStorage::prune_deltas`.
     pub fn update_flat_storage_for_shard(
         &self,
         shard_uid: ShardUId,
@@ -124,6 +136,10 @@ impl FlatStorageManager {
                     }
                 }
             });
+            let pruning = &self.0.delta_pruning;
+            if pruning.enabled {
+                flat_storage.prune_deltas(&new_flat_head, pruning.retained_blocks)?;
+            }
         }
         Ok(())
     }
@@ -259,4 +275,20 @@ impl FlatStorageManager {
             false
         }
     }
+
+    /// Starts the background job that inlines small values referenced from
+    /// flat state, see `inline_flat_state_values`. The job resumes from where
+    /// it stopped on a previous run, and does nothing if it has finished.
+    pub fn start_values_inlining(
+        &self,
+        read_state_threads: usize,
+        config: FlatStateValuesInliningConfig,
+    ) -> FlatStateValuesInliningMigrationHandle {
+        FlatStateValuesInliningMigrationHandle::start_background_migration(
+            self.0.store.clone(),
+            self.clone(),
+            read_state_threads,
+            config,
+        )
+    }
 }
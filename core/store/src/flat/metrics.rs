@@ -11,6 +11,8 @@ pub(crate) struct FlatStorageMetrics {
     cached_deltas: IntGauge,
     cached_changes_num_items: IntGauge,
     cached_changes_size: IntGauge,
+    stored_changes_size: IntGauge,
+    head_lag: IntGauge,
 }
 
 impl FlatStorageMetrics {
@@ -29,6 +31,10 @@ impl FlatStorageMetrics {
                 .with_label_values(&[&shard_id_label]),
             cached_changes_size: flat_state_metrics::FLAT_STORAGE_CACHED_CHANGES_SIZE
                 .with_label_values(&[&shard_id_label]),
+            stored_changes_size: flat_state_metrics::FLAT_STORAGE_STORED_CHANGES_SIZE
+                .with_label_values(&[&shard_id_label]),
+            head_lag: flat_state_metrics::FLAT_STORAGE_HEAD_LAG
+                .with_label_values(&[&shard_id_label]),
         }
     }
 
@@ -46,10 +52,18 @@ impl FlatStorageMetrics {
         cached_deltas: usize,
         cached_changes_num_items: usize,
         cached_changes_size: u64,
+        stored_changes_size: u64,
     ) {
         self.cached_deltas.set(cached_deltas as i64);
         self.cached_changes_num_items.set(cached_changes_num_items as i64);
         self.cached_changes_size.set(cached_changes_size as i64);
+        self.stored_changes_size.set(stored_changes_size as i64);
+    }
+
+    /// Sets the distance in blocks between the latest block added to flat
+    /// storage and flat storage head.
+    pub(crate) fn set_head_lag(&self, lag: BlockHeight) {
+        self.head_lag.set(lag as i64);
     }
 }
 
@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
 
 use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::state::FlatStateValue;
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{BlockHeight, BlockHeightDelta};
 use tracing::{debug, warn};
 
 use crate::flat::delta::{BlockWithChangesInfo, CachedFlatStateChanges};
@@ -146,16 +146,24 @@ impl FlatStorageInner {
         let cached_deltas = self.deltas.len();
         let mut cached_changes_num_items = 0;
         let mut cached_changes_size = 0;
-        for changes in self.deltas.values() {
-            cached_changes_num_items += changes.changes.len();
-            cached_changes_size += changes.changes.total_size();
+        let mut stored_changes_size = 0;
+        // Deltas are added for every processed block, so the highest one is
+        // normally at chain head.
+        let mut latest_height = self.flat_head.height;
+        for delta in self.deltas.values() {
+            cached_changes_num_items += delta.changes.len();
+            cached_changes_size += delta.changes.total_size();
+            stored_changes_size += delta.stored_size;
+            latest_height = latest_height.max(delta.metadata.block.height);
         }
 
         self.metrics.set_cached_deltas(
             cached_deltas,
             cached_changes_num_items,
             cached_changes_size,
+            stored_changes_size,
         );
+        self.metrics.set_head_lag(latest_height - self.flat_head.height);
 
         let cached_changes_size_bytes = bytesize::ByteSize(cached_changes_size);
         if cached_changes_size_bytes >= Self::CACHED_CHANGES_SIZE_LIMIT {
@@ -255,16 +263,20 @@ impl FlatStorage {
         let mut deltas = HashMap::new();
         for delta_metadata in deltas_metadata {
             let block_hash = delta_metadata.block.hash;
-            let changes: CachedFlatStateChanges =
-                store_helper::get_delta_changes(&store, shard_uid, block_hash)
-                    .expect("failed to read flat state delta changes")
-                    .unwrap_or_else(|| {
-                        panic!("cannot find block delta for block {block_hash:?} shard {shard_id}")
-                    })
-                    .into();
+            let changes = store_helper::get_delta_changes(&store, shard_uid, block_hash)
+                .expect("failed to read flat state delta changes")
+                .unwrap_or_else(|| {
+                    panic!("cannot find block delta for block {block_hash:?} shard {shard_id}")
+                });
+            let stored_size = changes.stored_size();
+            let changes: CachedFlatStateChanges = changes.into();
             deltas.insert(
                 block_hash,
-                CachedFlatStateDelta { metadata: delta_metadata, changes: Arc::new(changes) },
+                CachedFlatStateDelta {
+                    metadata: delta_metadata,
+                    changes: Arc::new(changes),
+                    stored_size,
+                },
             );
         }
 
@@ -425,16 +437,83 @@ impl FlatStorage {
         }
         let mut store_update = StoreUpdate::new(guard.store.storage.clone());
         store_helper::set_delta(&mut store_update, shard_uid, &delta);
+        let stored_size = delta.changes.stored_size();
         let cached_changes: CachedFlatStateChanges = delta.changes.into();
         guard.deltas.insert(
             block_hash,
-            CachedFlatStateDelta { metadata: delta.metadata, changes: Arc::new(cached_changes) },
+            CachedFlatStateDelta {
+                metadata: delta.metadata,
+                changes: Arc::new(cached_changes),
+                stored_size,
+            },
         );
         guard.update_delta_metrics();
 
         Ok(store_update)
     }
 
+    /// Removes deltas which can no longer be applied to flat storage: deltas
+    /// of blocks at or below flat head, and deltas of blocks on forks which
+    /// are not ancestors of `final_block_hash`. Deltas are kept if their
+    /// height is within `retained_blocks` of the final block. Unlike
+    /// `update_flat_head`, this also works while flat head updates are
+    /// disabled. Returns the number of removed deltas.
+    pub fn prune_deltas(
+        &self,
+        final_block_hash: &CryptoHash,
+        retained_blocks: BlockHeightDelta,
+    ) -> Result<usize, FlatStorageError> {
+        let mut guard = self.0.write().expect(super::POISONED_LOCK_ERR);
+        let flat_head = guard.flat_head;
+
+        // Blocks between the final block and flat head must be kept
+        // regardless of their height. If the final block is not known to
+        // flat storage, only deltas at or below flat head can be identified.
+        let mut final_chain = HashSet::new();
+        let mut final_height = flat_head.height;
+        if let Some(final_delta) = guard.deltas.get(final_block_hash) {
+            let mut block_hash = *final_block_hash;
+            while let Some(delta) = guard.deltas.get(&block_hash) {
+                final_chain.insert(block_hash);
+                block_hash = delta.metadata.block.prev_hash;
+            }
+            if block_hash == flat_head.hash {
+                final_height = final_delta.metadata.block.height;
+            } else {
+                final_chain.clear();
+            }
+        }
+
+        let prune_height = final_height.saturating_sub(retained_blocks);
+        let hashes_to_remove: Vec<_> = guard
+            .deltas
+            .iter()
+            .filter(|(block_hash, delta)| {
+                delta.metadata.block.height <= prune_height && !final_chain.contains(block_hash)
+            })
+            .map(|(block_hash, _)| *block_hash)
+            .collect();
+        if hashes_to_remove.is_empty() {
+            return Ok(0);
+        }
+
+        let shard_uid = guard.shard_uid;
+        let mut store_update = guard.store.store_update();
+        for block_hash in &hashes_to_remove {
+            store_helper::remove_delta(&mut store_update, shard_uid, *block_hash);
+        }
+        store_update
+            .commit()
+            .map_err(|err| FlatStorageError::StorageInternalError(err.to_string()))?;
+        for block_hash in &hashes_to_remove {
+            guard.deltas.remove(block_hash);
+        }
+        guard.update_delta_metrics();
+        debug!(target: "store", shard_id = shard_uid.shard_id(), num_deltas = hashes_to_remove.len(), "Pruned flat storage deltas");
+
+        Ok(hashes_to_remove.len())
+    }
+
     /// Clears all State key-value pairs from flat storage.
     pub fn clear_state(&self) -> Result<(), StorageError> {
         let guard = self.0.write().expect(super::POISONED_LOCK_ERR);
@@ -489,6 +568,7 @@ mod tests {
     use crate::flat::storage::FlatStorageInner;
     use crate::flat::types::{BlockInfo, FlatStorageError};
     use crate::flat::{store_helper, FlatStorageReadyStatus, FlatStorageStatus};
+    use crate::metrics::flat_state_metrics;
     use crate::test_utils::create_test_store;
     use crate::StorageError;
     use assert_matches::assert_matches;
@@ -890,6 +970,173 @@ mod tests {
         );
     }
 
+    #[test]
+    fn flat_storage_reads_at_recent_blocks() {
+        // Create a chain with two forks, where block i sets the value of key
+        // &[1] to &[i], except for block 3 which has no changes.
+        let chain = MockChain::chain_with_two_forks(7);
+        let shard_uid = ShardUId::single_shard();
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            shard_uid,
+            FlatStorageStatus::Ready(FlatStorageReadyStatus { flat_head: chain.get_block(0) }),
+        );
+        store_helper::set_flat_state_value(
+            &mut store_update,
+            shard_uid,
+            vec![1],
+            Some(FlatStateValue::value_ref(&[0])),
+        );
+        for i in 1..7 {
+            let changes = if i == 3 {
+                FlatStateChanges::default()
+            } else {
+                FlatStateChanges::from([(vec![1], Some(FlatStateValue::value_ref(&[i as u8])))])
+            };
+            let delta = FlatStateDelta {
+                changes,
+                metadata: FlatStateDeltaMetadata {
+                    block: chain.get_block(i),
+                    prev_block_with_changes: None,
+                },
+            };
+            store_helper::set_delta(&mut store_update, shard_uid, &delta);
+        }
+        store_update.commit().unwrap();
+
+        let flat_storage_manager = FlatStorageManager::new(store);
+        flat_storage_manager.create_flat_storage_for_shard(shard_uid).unwrap();
+        let flat_storage = flat_storage_manager.get_flat_storage_for_shard(shard_uid).unwrap();
+
+        let expected = |i: u8| Some(FlatStateValue::value_ref(&[i]));
+        for (block, value) in [(0, 0), (1, 1), (2, 2), (3, 1), (4, 4), (5, 5), (6, 6)] {
+            let block_hash = chain.get_block_hash(block);
+            assert_eq!(flat_storage.get_value(&block_hash, &[1]).unwrap(), expected(value));
+            assert_eq!(flat_storage.get_value(&block_hash, &[2]).unwrap(), None);
+        }
+
+        // After moving flat head to the fork with odd blocks, even blocks
+        // can no longer be read.
+        flat_storage.update_flat_head(&chain.get_block_hash(3), true).unwrap();
+        assert_eq!(flat_storage.get_value(&chain.get_block_hash(5), &[1]).unwrap(), expected(5));
+        for block in [2, 4, 6] {
+            assert_matches!(
+                flat_storage.get_value(&chain.get_block_hash(block), &[1]),
+                Err(StorageError::FlatStorageBlockNotSupported(_))
+            );
+        }
+        assert_matches!(
+            flat_storage.get_value(&hash(&[1, 2, 3]), &[1]),
+            Err(StorageError::FlatStorageBlockNotSupported(_))
+        );
+    }
+
+    #[test]
+    fn flat_storage_prune_deltas() {
+        let chain = MockChain::chain_with_two_forks(9);
+        let shard_uid = ShardUId::single_shard();
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            shard_uid,
+            FlatStorageStatus::Ready(FlatStorageReadyStatus { flat_head: chain.get_block(0) }),
+        );
+        for i in 1..9 {
+            let delta = FlatStateDelta {
+                changes: FlatStateChanges::from([(vec![1], Some(FlatStateValue::inlined(&[i])))]),
+                metadata: FlatStateDeltaMetadata {
+                    block: chain.get_block(i as BlockHeight),
+                    prev_block_with_changes: None,
+                },
+            };
+            store_helper::set_delta(&mut store_update, shard_uid, &delta);
+        }
+        store_update.commit().unwrap();
+
+        let flat_storage_manager = FlatStorageManager::new(store.clone());
+        flat_storage_manager.create_flat_storage_for_shard(shard_uid).unwrap();
+        let flat_storage = flat_storage_manager.get_flat_storage_for_shard(shard_uid).unwrap();
+        // Flat head doesn't move, e.g. while a state snapshot is taken.
+        assert!(flat_storage.set_flat_head_update_mode(false));
+        let stored_heights = || {
+            let mut heights: Vec<_> = store_helper::get_all_deltas_metadata(&store, shard_uid)
+                .unwrap()
+                .into_iter()
+                .map(|metadata| metadata.block.height)
+                .collect();
+            heights.sort();
+            heights
+        };
+
+        // Block 6 is final, so blocks 1 and 3 are on an abandoned fork.
+        let final_block_hash = chain.get_block_hash(6);
+        assert_eq!(flat_storage.prune_deltas(&final_block_hash, 2), Ok(2));
+        assert_eq!(stored_heights(), vec![2, 4, 5, 6, 7, 8]);
+        assert_eq!(flat_storage.prune_deltas(&final_block_hash, 0), Ok(1));
+        assert_eq!(stored_heights(), vec![2, 4, 6, 7, 8]);
+        assert_eq!(
+            flat_storage.get_value(&final_block_hash, &[1]),
+            Ok(Some(FlatStateValue::inlined(&[6])))
+        );
+        assert_matches!(
+            flat_storage.get_value(&chain.get_block_hash(5), &[1]),
+            Err(StorageError::FlatStorageBlockNotSupported(_))
+        );
+
+        // Nothing can be pruned if the final block is unknown.
+        assert_eq!(flat_storage.prune_deltas(&CryptoHash::default(), 0), Ok(0));
+        assert_eq!(stored_heights(), vec![2, 4, 6, 7, 8]);
+    }
+
+    #[test]
+    fn flat_storage_head_lag_metrics() {
+        // Use a separate shard id, as metrics are shared between tests.
+        let shard_uid = ShardUId { version: 1, shard_id: 101 };
+        let shard_id_label = shard_uid.shard_id().to_string();
+        let head_lag =
+            flat_state_metrics::FLAT_STORAGE_HEAD_LAG.with_label_values(&[&shard_id_label]);
+        let stored_changes_size = flat_state_metrics::FLAT_STORAGE_STORED_CHANGES_SIZE
+            .with_label_values(&[&shard_id_label]);
+        let chain = MockChain::linear_chain(6);
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            shard_uid,
+            FlatStorageStatus::Ready(FlatStorageReadyStatus { flat_head: chain.get_block(0) }),
+        );
+        store_update.commit().unwrap();
+
+        let flat_storage_manager = FlatStorageManager::new(store);
+        flat_storage_manager.create_flat_storage_for_shard(shard_uid).unwrap();
+        let flat_storage = flat_storage_manager.get_flat_storage_for_shard(shard_uid).unwrap();
+        assert_eq!(head_lag.get(), 0);
+        assert_eq!(stored_changes_size.get(), 0);
+
+        for i in 1..6 {
+            let changes =
+                FlatStateChanges::from([(vec![i as u8], Some(FlatStateValue::inlined(&[0; 10])))]);
+            assert_eq!(changes.stored_size(), 11);
+            let delta = FlatStateDelta {
+                changes,
+                metadata: FlatStateDeltaMetadata {
+                    block: chain.get_block(i),
+                    prev_block_with_changes: None,
+                },
+            };
+            flat_storage.add_delta(delta).unwrap().commit().unwrap();
+        }
+        assert_eq!(head_lag.get(), 5);
+        assert_eq!(stored_changes_size.get(), 55);
+
+        flat_storage.update_flat_head(&chain.get_block_hash(3), true).unwrap();
+        assert_eq!(head_lag.get(), 2);
+        assert_eq!(stored_changes_size.get(), 22);
+    }
+
     #[test]
     fn flat_storage_with_hops() {
         init_test_logger();
@@ -5,7 +5,10 @@ use super::delta::{FlatStateDelta, FlatStateDeltaMetadata};
 use super::types::{
     FlatStateIterator, FlatStateValuesInliningMigrationStatus, FlatStorageResult, FlatStorageStatus,
 };
-use crate::db::FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS_KEY;
+use crate::db::{
+    FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS_KEY,
+    FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS_KEY,
+};
 use crate::flat::delta::{BlockWithChangesInfo, FlatStateChanges, KeyForFlatStateDelta};
 use crate::flat::types::FlatStorageError;
 use crate::flat::FlatStorageReadyStatus;
@@ -178,6 +181,36 @@ pub fn set_flat_state_values_inlining_migration_status(
     })
 }
 
+/// Returns the last `FlatState` DB key processed by the values inlining
+/// migration, if it was interrupted in the middle.
+pub fn get_flat_state_values_inlining_migration_progress(
+    store: &Store,
+) -> FlatStorageResult<Option<Vec<u8>>> {
+    store.get_ser(DBCol::Misc, FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS_KEY).map_err(|err| {
+        FlatStorageError::StorageInternalError(format!(
+            "failed to read FlatState values inlining migration progress: {err}"
+        ))
+    })
+}
+
+pub fn set_flat_state_values_inlining_migration_progress(
+    store: &Store,
+    last_processed_key: Option<&[u8]>,
+) -> FlatStorageResult<()> {
+    let mut store_update = store.store_update();
+    match last_processed_key {
+        Some(key) => store_update
+            .set_ser(DBCol::Misc, FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS_KEY, &key.to_vec())
+            .expect("Borsh should not have failed here"),
+        None => store_update.delete(DBCol::Misc, FLAT_STATE_VALUES_INLINING_MIGRATION_PROGRESS_KEY),
+    }
+    store_update.commit().map_err(|err| {
+        FlatStorageError::StorageInternalError(format!(
+            "failed to commit FlatState values inlining migration progress: {err}"
+        ))
+    })
+}
+
 pub(crate) fn get_flat_state_value(
     store: &Store,
     shard_uid: ShardUId,
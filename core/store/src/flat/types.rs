@@ -124,5 +124,16 @@ pub struct FetchingStateStatus {
     pub num_parts: u64,
 }
 
+impl FetchingStateStatus {
+    /// Percentage of state parts which were already fetched, that is, belong
+    /// to the steps before the current one.
+    pub fn progress_percent(&self) -> f64 {
+        if self.num_parts == 0 {
+            return 100.0;
+        }
+        self.part_id as f64 * 100.0 / self.num_parts as f64
+    }
+}
+
 pub type FlatStateIterator<'a> =
     Box<dyn Iterator<Item = FlatStorageResult<(Vec<u8>, FlatStateValue)>> + 'a>;
@@ -0,0 +1,262 @@
+//! Write-ahead log of multi-step operations which can't be applied atomically.
+//!
+//! Some operations touch more than one database (e.g. hot and cold store) or
+//! the database and the file system (e.g. state snapshots).  A crash in the
+//! middle of such an operation leaves the node with inconsistent state.  To
+//! avoid that, the operation is first recorded as an [`Intent`] in the hot
+//! store and the record is removed once all steps are done.  Intents left
+//! over after a crash are rolled forward or rolled back by
+//! [`crate::NodeStorage::recover_intents`] when the storage is opened.
+
+use crate::db::{ColdDB, Database, COLD_HEAD_KEY, HEAD_KEY, STATE_SNAPSHOT_KEY};
+use crate::{DBCol, DBTransaction, Store};
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::block::Tip;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Prefix of keys of intent records in `DBCol::Misc`.  The prefix is followed
+/// by big-endian id of the intent so that records are iterated in the order
+/// they were created.
+const INTENT_KEY_PREFIX: &[u8] = b"INTENT_LOG:";
+
+/// A multi-step operation recorded before it is started.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum Intent {
+    /// Setting `HEAD` and `COLD_HEAD` in the cold store and `COLD_HEAD` in the
+    /// hot store to given tip.  Rolled forward on recovery.
+    UpdateColdHead { tip: Tip },
+    /// Replacing the state snapshot with a new one at given path and updating
+    /// `STATE_SNAPSHOT_KEY`.  Rolled back on recovery, i.e. the partially
+    /// created snapshot is deleted and the node makes a new one later.
+    ReplaceStateSnapshot { path: String },
+}
+
+/// Log of intents stored in the hot store.
+#[derive(Clone)]
+pub struct IntentLog {
+    store: Store,
+}
+
+/// A started intent.  Unless [`IntentGuard::finish`] is called, the intent
+/// stays in the log and is recovered the next time the storage is opened.
+#[must_use]
+pub struct IntentGuard<'a> {
+    log: &'a IntentLog,
+    id: u64,
+}
+
+static NEXT_INTENT_ID: AtomicU64 = AtomicU64::new(0);
+
+impl IntentLog {
+    /// Creates log backed by given hot store.
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    /// Records the intent and returns a guard which removes the record once
+    /// the operation is finished.
+    pub fn begin(&self, intent: Intent) -> io::Result<IntentGuard<'_>> {
+        // Ids only need to be unique among intents in the log, so it's enough
+        // to start past the largest one left over from before a restart.
+        let min_id = self.pending()?.last().map_or(0, |(id, _)| id + 1);
+        NEXT_INTENT_ID.fetch_max(min_id, Ordering::SeqCst);
+        let id = NEXT_INTENT_ID.fetch_add(1, Ordering::SeqCst);
+        tracing::debug!(target: "store", id, ?intent, "Starting intent");
+        let mut update = self.store.store_update();
+        update.set_ser(DBCol::Misc, &intent_key(id), &intent)?;
+        update.commit()?;
+        Ok(IntentGuard { log: self, id })
+    }
+
+    /// Returns intents which have been started but not finished, ordered by
+    /// their ids.
+    pub fn pending(&self) -> io::Result<Vec<(u64, Intent)>> {
+        self.store
+            .iter_prefix_ser::<Intent>(DBCol::Misc, INTENT_KEY_PREFIX)
+            .map(|item| {
+                let (key, intent) = item?;
+                let id = key[INTENT_KEY_PREFIX.len()..].try_into().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid intent log key")
+                })?;
+                Ok((u64::from_be_bytes(id), intent))
+            })
+            .collect()
+    }
+
+    /// Removes every pending intent without completing it.  Used for
+    /// checkpoints of the database, whose intents are recovered by the
+    /// database the checkpoint was made from.
+    pub(crate) fn discard_all(&self) -> io::Result<()> {
+        let pending = self.pending()?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let mut update = self.store.store_update();
+        for (id, intent) in pending {
+            tracing::debug!(target: "store", id, ?intent, "Discarding intent");
+            update.delete(DBCol::Misc, &intent_key(id));
+        }
+        update.commit()
+    }
+
+    fn remove(&self, id: u64) -> io::Result<()> {
+        let mut update = self.store.store_update();
+        update.delete(DBCol::Misc, &intent_key(id));
+        update.commit()
+    }
+
+    /// Completes every pending intent and removes it from the log.
+    ///
+    /// Intents which touch the cold store are skipped with a warning if
+    /// `cold_db` isn't given; they'll be recovered once the cold store is
+    /// opened together with the hot store.
+    pub(crate) fn recover(&self, cold_db: Option<&ColdDB>) -> io::Result<()> {
+        for (id, intent) in self.pending()? {
+            tracing::info!(target: "store", id, ?intent, "Recovering unfinished intent");
+            match &intent {
+                Intent::UpdateColdHead { tip } => {
+                    let Some(cold_db) = cold_db else {
+                        tracing::warn!(target: "store", id, "Cold store not opened; can't recover intent");
+                        continue;
+                    };
+                    write_cold_head(cold_db, &self.store, tip)?;
+                }
+                Intent::ReplaceStateSnapshot { path } => {
+                    let path = Path::new(path);
+                    if path.exists() {
+                        std::fs::remove_dir_all(path)?;
+                    }
+                    let mut update = self.store.store_update();
+                    update.delete(DBCol::BlockMisc, STATE_SNAPSHOT_KEY);
+                    update.commit()?;
+                }
+            }
+            self.remove(id)?;
+        }
+        Ok(())
+    }
+}
+
+impl IntentGuard<'_> {
+    /// Marks the operation as completed and removes it from the log.
+    pub fn finish(self) -> io::Result<()> {
+        tracing::debug!(target: "store", id = self.id, "Finished intent");
+        self.log.remove(self.id)
+    }
+}
+
+fn intent_key(id: u64) -> Vec<u8> {
+    [INTENT_KEY_PREFIX, &id.to_be_bytes()].concat()
+}
+
+/// Writes `HEAD` and `COLD_HEAD` to the cold store and `COLD_HEAD` to the hot
+/// store.  Each of the writes is idempotent so this may be repeated after
+/// a crash.
+pub(crate) fn write_cold_head(cold_db: &ColdDB, hot_store: &Store, tip: &Tip) -> io::Result<()> {
+    // Write HEAD to the cold db.
+    {
+        let mut transaction = DBTransaction::new();
+        transaction.set(DBCol::BlockMisc, HEAD_KEY.to_vec(), tip.try_to_vec()?);
+        cold_db.write(transaction)?;
+    }
+
+    // Write COLD_HEAD_KEY to the cold db.
+    {
+        let mut transaction = DBTransaction::new();
+        transaction.set(DBCol::BlockMisc, COLD_HEAD_KEY.to_vec(), tip.try_to_vec()?);
+        cold_db.write(transaction)?;
+    }
+
+    // Write COLD_HEAD to the hot db.
+    {
+        let mut transaction = DBTransaction::new();
+        transaction.set(DBCol::BlockMisc, COLD_HEAD_KEY.to_vec(), tip.try_to_vec()?);
+        hot_store.storage.write(transaction)?;
+
+        crate::metrics::COLD_HEAD_HEIGHT.set(tip.height as i64);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Intent, IntentLog};
+    use crate::db::{TestDB, COLD_HEAD_KEY, HEAD_KEY, STATE_SNAPSHOT_KEY};
+    use crate::{DBCol, NodeStorage};
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::EpochId;
+
+    fn tip(height: u64) -> Tip {
+        Tip {
+            height,
+            last_block_hash: CryptoHash::hash_bytes(&height.to_le_bytes()),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        }
+    }
+
+    #[test]
+    fn test_finished_intent_is_removed() {
+        let storage = NodeStorage::new(TestDB::new());
+        let log = IntentLog::new(storage.get_hot_store());
+        let first = log.begin(Intent::UpdateColdHead { tip: tip(1) }).unwrap();
+        let second = log.begin(Intent::UpdateColdHead { tip: tip(2) }).unwrap();
+        assert_eq!(
+            log.pending().unwrap().into_iter().map(|(_, intent)| intent).collect::<Vec<_>>(),
+            vec![Intent::UpdateColdHead { tip: tip(1) }, Intent::UpdateColdHead { tip: tip(2) }]
+        );
+        first.finish().unwrap();
+        second.finish().unwrap();
+        assert_eq!(log.pending().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_recover_update_cold_head() {
+        let storage = NodeStorage::new_with_cold(TestDB::new(), TestDB::new());
+        let hot_store = storage.get_hot_store();
+        let cold_store = storage.get_cold_store().unwrap();
+        // Simulate crash right after the intent was recorded.
+        std::mem::forget(
+            IntentLog::new(hot_store.clone())
+                .begin(Intent::UpdateColdHead { tip: tip(10) })
+                .unwrap(),
+        );
+
+        storage.recover_intents().unwrap();
+        assert_eq!(hot_store.get_ser(DBCol::BlockMisc, COLD_HEAD_KEY).unwrap(), Some(tip(10)));
+        assert_eq!(cold_store.get_ser(DBCol::BlockMisc, HEAD_KEY).unwrap(), Some(tip(10)));
+        assert_eq!(cold_store.get_ser(DBCol::BlockMisc, COLD_HEAD_KEY).unwrap(), Some(tip(10)));
+        assert_eq!(IntentLog::new(hot_store).pending().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_recover_replace_state_snapshot() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("snapshot");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("CURRENT"), b"").unwrap();
+
+        let storage = NodeStorage::new(TestDB::new());
+        let hot_store = storage.get_hot_store();
+        let mut update = hot_store.store_update();
+        update.set_ser(DBCol::BlockMisc, STATE_SNAPSHOT_KEY, &CryptoHash::default()).unwrap();
+        update.commit().unwrap();
+        std::mem::forget(
+            IntentLog::new(hot_store.clone())
+                .begin(Intent::ReplaceStateSnapshot { path: path.display().to_string() })
+                .unwrap(),
+        );
+
+        storage.recover_intents().unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            hot_store.get_ser::<CryptoHash>(DBCol::BlockMisc, STATE_SNAPSHOT_KEY).unwrap(),
+            None
+        );
+        assert_eq!(IntentLog::new(hot_store).pending().unwrap(), vec![]);
+    }
+}
@@ -1,5 +1,6 @@
 extern crate core;
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::Path;
 use std::str::FromStr;
@@ -13,9 +14,10 @@ use strum;
 
 pub use columns::DBCol;
 pub use db::{
-    CHUNK_TAIL_KEY, COLD_HEAD_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, GENESIS_JSON_HASH_KEY,
-    GENESIS_STATE_ROOTS_KEY, HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY,
-    LATEST_KNOWN_KEY, STATE_SNAPSHOT_KEY, STATE_SYNC_DUMP_KEY, TAIL_KEY,
+    ColumnStats, DBStream, CHUNK_TAIL_KEY, COLD_HEAD_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
+    GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, PRUNE_TAILS_KEY, STATE_SNAPSHOT_KEY,
+    STATE_SYNC_DUMP_KEY, TAIL_KEY,
 };
 use near_crypto::PublicKey;
 use near_fmt::{AbbrBytes, StorageKey};
@@ -29,14 +31,16 @@ use near_primitives::types::{AccountId, StateRoot};
 use near_vm_runner::logic::{CompiledContract, CompiledContractCache};
 use near_vm_runner::ContractCode;
 
-use crate::db::{refcount, DBIterator, DBOp, DBSlice, DBTransaction, Database, StoreStatistics};
+use crate::db::{
+    refcount, DBIterator, DBOp, DBSlice, DBTransaction, Database, DatabaseSnapshot, StoreStatistics,
+};
 pub use crate::trie::iterator::{TrieIterator, TrieTraversalItem};
 pub use crate::trie::update::{TrieUpdate, TrieUpdateIterator, TrieUpdateValuePtr};
 pub use crate::trie::{
     estimator, split_state, ApplyStatePartResult, KeyForStateChanges, KeyLookupMode, NibbleSlice,
     PartialStorage, PrefetchApi, PrefetchError, RawTrieNode, RawTrieNodeWithSize, ShardTries,
     StateSnapshot, StateSnapshotConfig, Trie, TrieAccess, TrieCache, TrieCachingStorage,
-    TrieChanges, TrieConfig, TrieDBStorage, TrieStorage, WrappedTrieChanges,
+    TrieChanges, TrieConfig, TrieDBStorage, TrieReadStats, TrieStorage, WrappedTrieChanges,
 };
 
 pub mod cold_storage;
@@ -45,19 +49,23 @@ pub mod config;
 pub mod db;
 pub mod flat;
 pub mod genesis;
+pub mod intent_log;
 pub mod metadata;
 pub mod metrics;
 pub mod migrations;
 mod opener;
+mod read_limiter;
 mod rocksdb_metrics;
 mod sync_utils;
 pub mod test_utils;
 pub mod trie;
+pub mod ttl;
 
-pub use crate::config::{Mode, StoreConfig};
+pub use crate::config::{EncryptionKeySource, Mode, StoreConfig};
 pub use crate::opener::{
     checkpoint_hot_storage_and_cleanup_columns, StoreMigrator, StoreOpener, StoreOpenerError,
 };
+pub use crate::read_limiter::{ReadLimiter, ReadPriority};
 
 /// Specifies temperature of a storage.
 ///
@@ -94,6 +102,8 @@ const STATE_FILE_END_MARK: u8 = 255;
 pub struct NodeStorage {
     hot_storage: Arc<dyn Database>,
     cold_storage: Option<Arc<crate::db::ColdDB>>,
+    /// Limiter of RPC reads shared by all stores of the node.
+    read_limiter: Arc<ReadLimiter>,
 }
 
 /// Node’s single storage source.
@@ -105,6 +115,9 @@ pub struct NodeStorage {
 #[derive(Clone)]
 pub struct Store {
     storage: Arc<dyn Database>,
+    read_limiter: Arc<ReadLimiter>,
+    /// Priority of reads done through this store, see [`Store::with_priority`].
+    priority: ReadPriority,
 }
 
 impl NodeStorage {
@@ -122,10 +135,10 @@ impl NodeStorage {
     /// Constructs new object backed by given database.
     fn from_rocksdb(
         hot_storage: crate::db::RocksDB,
-        cold_storage: Option<crate::db::RocksDB>,
+        cold_storage: Option<Arc<dyn Database>>,
+        rpc_read_limits: config::ReadLimitsConfig,
     ) -> Self {
         let hot_storage = Arc::new(hot_storage);
-        let cold_storage = cold_storage.map(|storage| Arc::new(storage));
 
         let cold_db = if let Some(cold_storage) = cold_storage {
             Some(Arc::new(crate::db::ColdDB::new(cold_storage)))
@@ -133,7 +146,8 @@ impl NodeStorage {
             None
         };
 
-        Self { hot_storage, cold_storage: cold_db }
+        let read_limiter = Arc::new(ReadLimiter::new(rpc_read_limits));
+        Self { hot_storage, cold_storage: cold_db, read_limiter }
     }
 
     /// Initialises an opener for a new temporary test store.
@@ -161,7 +175,7 @@ impl NodeStorage {
     /// possibly [`crate::test_utils::create_test_store`] (depending whether you
     /// need [`NodeStorage`] or [`Store`] object.
     pub fn new(storage: Arc<dyn Database>) -> Self {
-        Self { hot_storage: storage, cold_storage: None }
+        Self { hot_storage: storage, cold_storage: None, read_limiter: ReadLimiter::unlimited() }
     }
 }
 
@@ -180,7 +194,7 @@ impl NodeStorage {
     /// store, the view client should use the split store and the cold store
     /// loop should use cold store.
     pub fn get_hot_store(&self) -> Store {
-        Store { storage: self.hot_storage.clone() }
+        self.store(self.hot_storage.clone())
     }
 
     /// Returns the cold store. The cold store is only available in archival
@@ -192,7 +206,7 @@ impl NodeStorage {
     /// loop should use cold store.
     pub fn get_cold_store(&self) -> Option<Store> {
         match &self.cold_storage {
-            Some(cold_storage) => Some(Store { storage: cold_storage.clone() }),
+            Some(cold_storage) => Some(self.store(cold_storage.clone())),
             None => None,
         }
     }
@@ -206,9 +220,9 @@ impl NodeStorage {
     /// loop should use cold store.
     pub fn get_split_store(&self) -> Option<Store> {
         match &self.cold_storage {
-            Some(cold_storage) => Some(Store {
-                storage: crate::db::SplitDB::new(self.hot_storage.clone(), cold_storage.clone()),
-            }),
+            Some(cold_storage) => Some(
+                self.store(crate::db::SplitDB::new(self.hot_storage.clone(), cold_storage.clone())),
+            ),
             None => None,
         }
     }
@@ -256,7 +270,31 @@ impl NodeStorage {
     }
 
     pub fn new_with_cold(hot: Arc<dyn Database>, cold: Arc<dyn Database>) -> Self {
-        Self { hot_storage: hot, cold_storage: Some(Arc::new(crate::db::ColdDB::new(cold))) }
+        Self {
+            hot_storage: hot,
+            cold_storage: Some(Arc::new(crate::db::ColdDB::new(cold))),
+            read_limiter: ReadLimiter::unlimited(),
+        }
+    }
+
+    fn store(&self, storage: Arc<dyn Database>) -> Store {
+        Store {
+            storage,
+            read_limiter: self.read_limiter.clone(),
+            priority: ReadPriority::default(),
+        }
+    }
+
+    /// Returns log of multi-step operations spanning several databases or the
+    /// database and the file system, see [`crate::intent_log`].
+    pub fn intent_log(&self) -> intent_log::IntentLog {
+        intent_log::IntentLog::new(self.get_hot_store())
+    }
+
+    /// Completes operations which were interrupted, e.g. by a crash, before
+    /// they could be applied to all databases.
+    pub fn recover_intents(&self) -> io::Result<()> {
+        self.intent_log().recover(self.cold_storage.as_deref())
     }
 
     pub fn cold_db(&self) -> Option<&Arc<crate::db::ColdDB>> {
@@ -265,6 +303,26 @@ impl NodeStorage {
 }
 
 impl Store {
+    /// Returns store whose reads are not limited.
+    pub(crate) fn new(storage: Arc<dyn Database>) -> Self {
+        Self { storage, read_limiter: ReadLimiter::unlimited(), priority: ReadPriority::default() }
+    }
+
+    /// Returns a handle to the same store whose reads have given priority.
+    ///
+    /// Reads with [`ReadPriority::Rpc`] priority, e.g. those done for view
+    /// calls, are limited according to `StoreConfig::rpc_read_limits` so
+    /// that they don't compete for disk with reads needed for block and chunk
+    /// processing.  Iterators hold their permit until they're dropped, so
+    /// they shouldn't be kept around while doing other reads.
+    pub fn with_priority(&self, priority: ReadPriority) -> Self {
+        Self { priority, ..self.clone() }
+    }
+
+    pub fn priority(&self) -> ReadPriority {
+        self.priority
+    }
+
     /// Fetches value from given column.
     ///
     /// If the key does not exist in the column returns `None`.  Otherwise
@@ -272,6 +330,7 @@ impl Store {
     /// a slice, for cases when caller doesn’t need to own the value, and
     /// provides conversion into a vector or an Arc.
     pub fn get(&self, column: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        let _permit = self.read_limiter.acquire(self.priority);
         let value = if column.is_rc() {
             self.storage.get_with_rc_stripped(column, key)
         } else {
@@ -300,7 +359,7 @@ impl Store {
     }
 
     pub fn iter<'a>(&'a self, col: DBCol) -> DBIterator<'a> {
-        self.storage.iter(col)
+        self.read_limiter.acquire(self.priority).hold_during(self.storage.iter(col))
     }
 
     /// Fetches raw key/value pairs from the database.
@@ -314,7 +373,41 @@ impl Store {
     }
 
     pub fn iter_prefix<'a>(&'a self, col: DBCol, key_prefix: &'a [u8]) -> DBIterator<'a> {
-        self.storage.iter_prefix(col, key_prefix)
+        let permit = self.read_limiter.acquire(self.priority);
+        permit.hold_during(self.storage.iter_prefix(col, key_prefix))
+    }
+
+    /// Returns an async stream over key-value pairs in the column whose keys
+    /// have given prefix.
+    ///
+    /// The column is scanned on a dedicated thread which reads at most
+    /// `ITER_STREAM_READAHEAD` items ahead of the consumer, so large columns
+    /// can be scanned from async code without blocking the executor or
+    /// loading the whole range into memory.  The scan stops once the stream
+    /// is dropped.
+    pub fn iter_stream(&self, col: DBCol, key_prefix: &[u8]) -> DBStream {
+        const ITER_STREAM_READAHEAD: usize = 1024;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(ITER_STREAM_READAHEAD);
+        let storage = self.storage.clone();
+        let key_prefix = key_prefix.to_vec();
+        let spawned =
+            std::thread::Builder::new().name("store_iter_stream".to_string()).spawn(move || {
+                for item in storage.iter_prefix(col, &key_prefix) {
+                    let is_err = item.is_err();
+                    // Sending fails only if the stream was dropped.
+                    if sender.blocking_send(item).is_err() || is_err {
+                        break;
+                    }
+                }
+            });
+        let receiver = match spawned {
+            Ok(_) => receiver,
+            Err(err) => return Box::pin(futures::stream::once(futures::future::ready(Err(err)))),
+        };
+        Box::pin(futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|item| (item, receiver))
+        }))
     }
 
     /// Iterates over a range of keys. Upper bound key is not included.
@@ -324,7 +417,8 @@ impl Store {
         lower_bound: Option<&[u8]>,
         upper_bound: Option<&[u8]>,
     ) -> DBIterator<'a> {
-        self.storage.iter_range(col, lower_bound, upper_bound)
+        let permit = self.read_limiter.acquire(self.priority);
+        permit.hold_during(self.storage.iter_range(col, lower_bound, upper_bound))
     }
 
     pub fn iter_prefix_ser<'a, T: BorshDeserialize>(
@@ -332,8 +426,7 @@ impl Store {
         col: DBCol,
         key_prefix: &'a [u8],
     ) -> impl Iterator<Item = io::Result<(Box<[u8]>, T)>> + 'a {
-        self.storage
-            .iter_prefix(col, key_prefix)
+        self.iter_prefix(col, key_prefix)
             .map(|item| item.and_then(|(key, value)| Ok((key, T::try_from_slice(value.as_ref())?))))
     }
 
@@ -388,6 +481,71 @@ impl Store {
     pub fn get_store_statistics(&self) -> Option<StoreStatistics> {
         self.storage.get_store_statistics()
     }
+
+    /// Returns approximate number of entries, size and number of SST files of
+    /// every column in the storage.
+    pub fn column_stats(&self) -> Vec<(DBCol, ColumnStats)> {
+        self.storage.get_column_stats()
+    }
+
+    /// Returns a read-only view of the store as of now.
+    ///
+    /// Reads through the snapshot observe the same state of the database in
+    /// all columns, e.g. a block and its chunks, even if garbage collection
+    /// or other writers modify the store in the meantime.  The snapshot pins
+    /// the data it refers to so it should not be held for long.
+    pub fn snapshot(&self) -> StoreSnapshot<'_> {
+        StoreSnapshot { store: self, snapshot: self.storage.snapshot() }
+    }
+}
+
+/// Read-only view of a [`Store`] pinned at the time it was taken; see
+/// [`Store::snapshot`].
+pub struct StoreSnapshot<'a> {
+    store: &'a Store,
+    snapshot: Box<dyn DatabaseSnapshot + 'a>,
+}
+
+impl<'a> StoreSnapshot<'a> {
+    /// Fetches value from given column.  See [`Store::get`].
+    pub fn get(&self, column: DBCol, key: &[u8]) -> io::Result<Option<DBSlice<'_>>> {
+        let _permit = self.store.read_limiter.acquire(self.store.priority);
+        let value = if column.is_rc() {
+            self.snapshot.get_with_rc_stripped(column, key)
+        } else {
+            self.snapshot.get_raw_bytes(column, key)
+        }?;
+        tracing::trace!(
+            target: "store",
+            db_op = "snapshot_get",
+            col = %column,
+            key = %StorageKey(key),
+            size = value.as_deref().map(<[u8]>::len)
+        );
+        Ok(value)
+    }
+
+    pub fn get_ser<T: BorshDeserialize>(&self, column: DBCol, key: &[u8]) -> io::Result<Option<T>> {
+        self.get(column, key)?.as_deref().map(T::try_from_slice).transpose()
+    }
+
+    pub fn exists(&self, column: DBCol, key: &[u8]) -> io::Result<bool> {
+        self.get(column, key).map(|value| value.is_some())
+    }
+
+    pub fn iter_prefix<'b>(&'b self, col: DBCol, key_prefix: &'b [u8]) -> DBIterator<'b> {
+        let permit = self.store.read_limiter.acquire(self.store.priority);
+        permit.hold_during(self.snapshot.iter_prefix(col, key_prefix))
+    }
+
+    pub fn iter_prefix_ser<'b, T: BorshDeserialize>(
+        &'b self,
+        col: DBCol,
+        key_prefix: &'b [u8],
+    ) -> impl Iterator<Item = io::Result<(Box<[u8]>, T)>> + 'b {
+        self.iter_prefix(col, key_prefix)
+            .map(|item| item.and_then(|(key, value)| Ok((key, T::try_from_slice(value.as_ref())?))))
+    }
 }
 
 impl Store {
@@ -412,9 +570,27 @@ impl Store {
     }
 }
 
+/// Number of keys, and bytes of keys and values, deleted from a column, see
+/// [`StoreUpdate::estimate_deletions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeletionStats {
+    pub keys: u64,
+    pub bytes: u64,
+}
+
+impl DeletionStats {
+    fn add(&mut self, bytes: usize) {
+        self.keys += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
 /// Keeps track of current changes to the database and can commit all of them to the database.
 pub struct StoreUpdate {
     transaction: DBTransaction,
+    /// Sorted entries to be ingested into columns, see
+    /// [`StoreUpdate::bulk_ingest`].
+    ingests: Vec<(DBCol, Vec<(Vec<u8>, Vec<u8>)>)>,
     storage: Arc<dyn Database>,
 }
 
@@ -425,7 +601,7 @@ impl StoreUpdate {
     };
 
     pub(crate) fn new(db: Arc<dyn Database>) -> Self {
-        StoreUpdate { transaction: DBTransaction::new(), storage: db }
+        StoreUpdate { transaction: DBTransaction::new(), ingests: Vec::new(), storage: db }
     }
 
     /// Inserts a new value into the database.
@@ -571,10 +747,91 @@ impl StoreUpdate {
     /// Panics if `self`’s and `other`’s storage are incompatible.
     pub fn merge(&mut self, other: StoreUpdate) {
         assert!(same_db(&self.storage, &other.storage));
-        self.transaction.merge(other.transaction)
+        self.transaction.merge(other.transaction);
+        self.ingests.extend(other.ingests);
     }
 
-    pub fn commit(self) -> io::Result<()> {
+    /// Writes large number of entries sorted by key into the column on
+    /// commit.
+    ///
+    /// Unlike regular writes, the entries bypass the write-ahead log and
+    /// memtables: they are written into a separate SST file which is then
+    /// ingested into the database.  This avoids compaction storms when
+    /// writing millions of keys, e.g. during state sync or resharding.
+    ///
+    /// Keys must be strictly increasing.  For reference-counted columns the
+    /// values are stored with reference count of one.  Existing entries with
+    /// the same keys are replaced rather than merged so the caller must make
+    /// sure the keys are new.  The ingestion is not atomic with the rest of
+    /// the update and happens before other operations are written.
+    pub fn bulk_ingest(
+        &mut self,
+        column: DBCol,
+        sorted_kvs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) {
+        let entries = sorted_kvs
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if column.is_rc() {
+                    refcount::add_positive_refcount(&value, Self::ONE)
+                } else {
+                    value
+                };
+                (key, value)
+            })
+            .collect::<Vec<_>>();
+        debug_assert!(
+            entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "bulk_ingest: keys of {column} are not strictly increasing"
+        );
+        self.ingests.push((column, entries));
+    }
+
+    /// Returns how many keys, and how many bytes of keys and stored values,
+    /// committing the update would delete from each column, without
+    /// committing it.  Values of reference-counted columns count as deleted
+    /// if their reference count would drop to zero.  Deletions of whole
+    /// columns and key ranges aren't counted.
+    pub fn estimate_deletions(&self) -> io::Result<HashMap<DBCol, DeletionStats>> {
+        let mut deleted_keys = HashSet::<(DBCol, &[u8])>::new();
+        let mut refcount_changes = HashMap::<(DBCol, &[u8]), i64>::new();
+        for op in &self.transaction.ops {
+            match op {
+                DBOp::Delete { col, key } => {
+                    deleted_keys.insert((*col, key.as_slice()));
+                }
+                DBOp::Set { col, key, .. } | DBOp::Insert { col, key, .. } => {
+                    deleted_keys.remove(&(*col, key.as_slice()));
+                }
+                DBOp::UpdateRefcount { col, key, value } => {
+                    let (_, rc) = refcount::decode_value_with_rc(value);
+                    *refcount_changes.entry((*col, key.as_slice())).or_default() += rc;
+                }
+                DBOp::DeleteAll { .. } | DBOp::DeleteRange { .. } => {}
+            }
+        }
+
+        let mut stats = HashMap::<DBCol, DeletionStats>::new();
+        for (col, key) in deleted_keys {
+            if let Some(value) = self.storage.get_raw_bytes(col, key)? {
+                stats.entry(col).or_default().add(key.len() + value.len());
+            }
+        }
+        for ((col, key), change) in refcount_changes {
+            if change >= 0 {
+                continue;
+            }
+            if let Some(value) = self.storage.get_raw_bytes(col, key)? {
+                let (_, rc) = refcount::decode_value_with_rc(&value);
+                if rc + change <= 0 {
+                    stats.entry(col).or_default().add(key.len() + value.len());
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn commit(mut self) -> io::Result<()> {
         debug_assert!(
             {
                 let non_refcount_keys = self
@@ -619,8 +876,52 @@ impl StoreUpdate {
                 }
             }
         }
+        ttl::add_index_entries(&mut self.transaction, &self.ingests);
+        for (col, entries) in self.ingests {
+            tracing::trace!(target: "store", db_op = "bulk_ingest", col = %col, count = entries.len());
+            self.storage.ingest(col, entries)?;
+        }
+        metrics::STORE_UPDATE_BATCH_SIZE.observe(self.transaction.size() as f64);
         self.storage.write(self.transaction)
     }
+
+    /// Commits the update in chunks with at most `max_batch_size` bytes of
+    /// keys and values each.
+    ///
+    /// Meant for updates too large to be written in a single batch, e.g.
+    /// during resharding or garbage collection.  The chunks are written in
+    /// order of the operations but, unlike with [`Self::commit`], the update
+    /// as a whole is not atomic: if writing a chunk fails, the chunks before
+    /// it stay committed.  A single operation larger than `max_batch_size` is
+    /// written in a chunk of its own.
+    ///
+    /// `on_chunk_committed` is called with index of every committed chunk
+    /// but the last one, e.g. to throttle the writes.  If it returns an
+    /// error, the remaining chunks are not written.
+    pub fn commit_in_chunks(
+        mut self,
+        max_batch_size: usize,
+        mut on_chunk_committed: impl FnMut(usize) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut chunks = std::mem::take(&mut self.transaction).split(max_batch_size);
+        if chunks.is_empty() {
+            chunks.push(DBTransaction::new());
+        }
+        let num_chunks = chunks.len();
+        let mut ingests = std::mem::take(&mut self.ingests);
+        for (index, transaction) in chunks.into_iter().enumerate() {
+            let update = StoreUpdate {
+                transaction,
+                ingests: std::mem::take(&mut ingests),
+                storage: self.storage.clone(),
+            };
+            update.commit()?;
+            if index + 1 < num_chunks {
+                on_chunk_committed(index)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 fn same_db(lhs: &Arc<dyn Database>, rhs: &Arc<dyn Database>) -> bool {
@@ -633,6 +934,9 @@ fn same_db(lhs: &Arc<dyn Database>, rhs: &Arc<dyn Database>) -> bool {
 impl fmt::Debug for StoreUpdate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Store Update {{")?;
+        for (col, entries) in self.ingests.iter() {
+            writeln!(f, "  ⇊ {col} ({} entries)", entries.len())?;
+        }
         for op in self.transaction.ops.iter() {
             match op {
                 DBOp::Insert { col, key, .. } => writeln!(f, "  + {col} {}", StorageKey(key))?,
@@ -956,6 +1260,150 @@ mod tests {
         test_clear_column(crate::test_utils::create_test_store());
     }
 
+    fn test_bulk_ingest(store: Store) {
+        {
+            let mut store_update = store.store_update();
+            store_update.set(DBCol::FlatState, &[0], &[0]);
+            store_update.commit().unwrap();
+        }
+        {
+            let mut store_update = store.store_update();
+            store_update.bulk_ingest(DBCol::State, (1..=3u8).map(|i| (vec![i], vec![i; 2])));
+            store_update.bulk_ingest(DBCol::FlatState, (0..=2u8).map(|i| (vec![i], vec![i + 10])));
+            store_update.commit().unwrap();
+        }
+        assert_eq!(store.get(DBCol::State, &[2]).unwrap().as_deref(), Some(&[2, 2][..]));
+        let flat_state = store.iter(DBCol::FlatState).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            flat_state,
+            (0..=2u8)
+                .map(|i| (vec![i].into_boxed_slice(), vec![i + 10].into_boxed_slice()))
+                .collect::<Vec<_>>()
+        );
+        // Ingested values of reference-counted columns can be decremented.
+        {
+            let mut store_update = store.store_update();
+            store_update.decrement_refcount(DBCol::State, &[2]);
+            store_update.commit().unwrap();
+        }
+        assert_eq!(store.get(DBCol::State, &[2]).unwrap(), None);
+    }
+
+    #[test]
+    fn bulk_ingest_rocksdb() {
+        let (_tmp_dir, opener) = NodeStorage::test_opener();
+        test_bulk_ingest(opener.open().unwrap().get_hot_store());
+    }
+
+    #[test]
+    fn bulk_ingest_testdb() {
+        test_bulk_ingest(crate::test_utils::create_test_store());
+    }
+
+    fn test_snapshot(store: Store) {
+        {
+            let mut store_update = store.store_update();
+            store_update.increment_refcount(DBCol::State, &[1], &[1]);
+            store_update.set(DBCol::BlockMisc, &[1], &[1]);
+            store_update.set(DBCol::BlockMisc, &[2], &[2]);
+            store_update.commit().unwrap();
+        }
+        let snapshot = store.snapshot();
+        {
+            let mut store_update = store.store_update();
+            store_update.decrement_refcount(DBCol::State, &[1]);
+            store_update.set(DBCol::BlockMisc, &[1], &[10]);
+            store_update.delete(DBCol::BlockMisc, &[2]);
+            store_update.set(DBCol::BlockMisc, &[3], &[3]);
+            store_update.commit().unwrap();
+        }
+        assert_eq!(store.get(DBCol::State, &[1]).unwrap(), None);
+        assert_eq!(store.get(DBCol::BlockMisc, &[1]).unwrap().as_deref(), Some(&[10][..]));
+
+        // The snapshot doesn’t see writes done after it was taken.
+        assert_eq!(snapshot.get(DBCol::State, &[1]).unwrap().as_deref(), Some(&[1][..]));
+        assert_eq!(snapshot.get(DBCol::BlockMisc, &[1]).unwrap().as_deref(), Some(&[1][..]));
+        assert!(snapshot.exists(DBCol::BlockMisc, &[2]).unwrap());
+        assert!(!snapshot.exists(DBCol::BlockMisc, &[3]).unwrap());
+        let block_misc = snapshot.iter_prefix(DBCol::BlockMisc, &[]).map(Result::unwrap);
+        assert_eq!(
+            block_misc.collect::<Vec<_>>(),
+            (1..=2u8)
+                .map(|i| (vec![i].into_boxed_slice(), vec![i].into_boxed_slice()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn snapshot_rocksdb() {
+        let (_tmp_dir, opener) = NodeStorage::test_opener();
+        test_snapshot(opener.open().unwrap().get_hot_store());
+    }
+
+    #[test]
+    fn snapshot_testdb() {
+        test_snapshot(crate::test_utils::create_test_store());
+    }
+
+    #[test]
+    fn test_commit_in_chunks() {
+        let store = crate::test_utils::create_test_store();
+        let mut store_update = store.store_update();
+        for i in 0..10u8 {
+            store_update.set(DBCol::BlockMisc, &[i], &[i; 9]);
+        }
+        let mut committed = vec![];
+        store_update
+            .commit_in_chunks(25, |index| {
+                // Chunks before this one must already be visible.
+                let last_key = 2 * index as u8 + 1;
+                assert!(store.exists(DBCol::BlockMisc, &[last_key]).unwrap());
+                assert!(!store.exists(DBCol::BlockMisc, &[last_key + 1]).unwrap());
+                committed.push(index);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(committed, vec![0, 1, 2, 3]);
+        assert_eq!(store.iter(DBCol::BlockMisc).count(), 10);
+
+        // An error returned by the callback stops the commit.
+        let mut store_update = store.store_update();
+        for i in 10..20u8 {
+            store_update.set(DBCol::BlockMisc, &[i], &[i; 9]);
+        }
+        store_update
+            .commit_in_chunks(25, |_| Err(std::io::Error::from(std::io::ErrorKind::Interrupted)))
+            .unwrap_err();
+        assert_eq!(store.iter(DBCol::BlockMisc).count(), 12);
+    }
+
+    #[test]
+    fn test_iter_stream() {
+        use futures::StreamExt;
+
+        const COLUMN: DBCol = DBCol::RecentOutboundConnections;
+        let store = crate::test_utils::create_test_store();
+        let mut update = store.store_update();
+        for i in 0..3000u32 {
+            update.set(COLUMN, &[b"a".as_slice(), &i.to_be_bytes()].concat(), &[1]);
+        }
+        update.set(COLUMN, b"b", &[2]);
+        update.commit().unwrap();
+
+        let want: Vec<_> = store.iter_prefix(COLUMN, b"a").map(Result::unwrap).collect();
+        let got: Vec<_> = futures::executor::block_on(
+            store.iter_stream(COLUMN, b"a").map(Result::unwrap).collect::<Vec<_>>(),
+        );
+        assert_eq!(want.len(), 3000);
+        assert_eq!(want, got);
+
+        // Dropping the stream before it is consumed stops the scan.
+        let mut stream = store.iter_stream(COLUMN, b"");
+        let first = futures::executor::block_on(stream.next()).unwrap().unwrap();
+        assert_eq!(first.0.as_ref(), &[b"a".as_slice(), &0u32.to_be_bytes()].concat());
+        drop(stream);
+    }
+
     /// Asserts that elements in the vector are sorted.
     #[track_caller]
     fn assert_sorted(want_count: usize, keys: Vec<Box<[u8]>>) {
@@ -1108,4 +1556,30 @@ mod tests {
             store.load_state_from_file(tmp.path()).unwrap_err().kind()
         );
     }
+
+    #[test]
+    fn test_estimate_deletions() {
+        let store = crate::test_utils::create_test_store();
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::BlockMisc, &[1], &[1; 10]);
+        store_update.set(DBCol::BlockMisc, &[2], &[2; 10]);
+        store_update.increment_refcount(DBCol::State, &[1], &[1; 20]);
+        store_update.increment_refcount(DBCol::State, &[2], &[2; 20]);
+        store_update.increment_refcount(DBCol::State, &[2], &[2; 20]);
+        store_update.commit().unwrap();
+
+        let mut store_update = store.store_update();
+        store_update.delete(DBCol::BlockMisc, &[1]);
+        store_update.delete(DBCol::BlockMisc, &[3]);
+        // Refcount of the second value only drops to one.
+        store_update.decrement_refcount(DBCol::State, &[1]);
+        store_update.decrement_refcount(DBCol::State, &[2]);
+        let stats = store_update.estimate_deletions().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[&DBCol::BlockMisc], super::DeletionStats { keys: 1, bytes: 11 });
+        // Values of reference-counted columns are stored with the refcount.
+        assert_eq!(stats[&DBCol::State], super::DeletionStats { keys: 1, bytes: 29 });
+        // Nothing is deleted.
+        assert!(store.exists(DBCol::BlockMisc, &[1]).unwrap());
+    }
 }
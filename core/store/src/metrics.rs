@@ -2,9 +2,10 @@ use crate::rocksdb_metrics::export_stats_as_metrics;
 use crate::{NodeStorage, Store, Temperature};
 use actix_rt::ArbiterHandle;
 use near_o11y::metrics::{
-    exponential_buckets, try_create_histogram, try_create_histogram_vec,
+    exponential_buckets, try_create_gauge_vec, try_create_histogram, try_create_histogram_vec,
     try_create_histogram_with_buckets, try_create_int_counter_vec, try_create_int_gauge,
-    try_create_int_gauge_vec, Histogram, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    try_create_int_gauge_vec, GaugeVec, Histogram, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -239,6 +240,158 @@ pub static COLD_COPY_DURATION: Lazy<Histogram> = Lazy::new(|| {
     .unwrap()
 });
 
+pub static COLD_COPY_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_cold_copy_bytes",
+        "Number of bytes of keys and values written to cold storage for every column.",
+        &["col"],
+    )
+    .unwrap()
+});
+pub static COLD_COPY_HEIGHTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_cold_copy_heights",
+        "Number of heights copied to cold storage for every column.",
+        &["col"],
+    )
+    .unwrap()
+});
+pub static COLD_COPY_ETA: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_cold_copy_eta_seconds",
+        "Estimated time for cold storage head to reach the hot final head, in seconds",
+    )
+    .unwrap()
+});
+pub static COLD_COPY_THROTTLE_DELAY: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram(
+        "near_cold_copy_throttle_delay",
+        "Time the copy of one height to cold storage was delayed to respect the IO limit",
+    )
+    .unwrap()
+});
+
+pub(crate) static COLUMN_BLOCK_CACHE_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_column_block_cache_size",
+        "Effective size of the RocksDB block cache of a column, in bytes",
+        &["temperature", "col"],
+    )
+    .unwrap()
+});
+pub(crate) static COLUMN_WRITE_BUFFER_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_column_write_buffer_size",
+        "Effective size of a RocksDB memtable of a column, in bytes",
+        &["temperature", "col"],
+    )
+    .unwrap()
+});
+pub(crate) static COLUMN_BLOOM_FILTER_BITS: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "near_rocksdb_column_bloom_filter_bits",
+        "Effective bits per key of the RocksDB bloom filter of a column",
+        &["temperature", "col"],
+    )
+    .unwrap()
+});
+pub(crate) static COLUMN_COMPRESSION: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_column_compression",
+        "Set to 1 for the compression overriding defaults of a RocksDB column",
+        &["temperature", "col", "compression"],
+    )
+    .unwrap()
+});
+
+pub(crate) static STORE_READS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_store_reads",
+        "Number of reads from the store by their priority",
+        &["priority"],
+    )
+    .unwrap()
+});
+pub(crate) static STORE_READ_LIMITER_WAIT: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_store_read_limiter_wait_sec",
+        "Time reads waited for the read limiter before starting, by their priority",
+        &["priority"],
+        Some(exponential_buckets(0.0001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static BULK_INGEST_ELAPSED: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_store_bulk_ingest_elapsed_sec",
+        "Time spent building and ingesting SST files by column",
+        &["column"],
+        Some(exponential_buckets(0.01, 2.0, 14).unwrap()),
+    )
+    .unwrap()
+});
+pub(crate) static BULK_INGEST_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_store_bulk_ingest_bytes",
+        "Size of keys and values ingested in bulk by column",
+        &["column"],
+    )
+    .unwrap()
+});
+
+pub(crate) static COLD_OBJECT_STORAGE_ELAPSED: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_cold_object_storage_elapsed_sec",
+        "Latency of cold store object storage requests by operation and result",
+        &["op", "result"],
+        Some(exponential_buckets(0.001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+pub(crate) static COLD_OBJECT_STORAGE_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_cold_object_storage_bytes",
+        "Size of values transferred to and from cold store object storage by operation",
+        &["op"],
+    )
+    .unwrap()
+});
+pub(crate) static COLD_OBJECT_CACHE: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_cold_object_cache",
+        "Lookups of values in cold store object storage cache by result (hit or miss)",
+        &["result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static STORE_UPDATE_BATCH_SIZE: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "near_store_update_batch_size_bytes",
+        "Size of keys and values in batches written to the store",
+        exponential_buckets(1024.0, 4.0, 12).unwrap(),
+    )
+    .unwrap()
+});
+
+pub(crate) static TTL_EXPIRED_VALUES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_store_ttl_expired_values",
+        "Number of values of ephemeral columns deleted after their TTL passed, by column",
+        &["column"],
+    )
+    .unwrap()
+});
+pub(crate) static TTL_DELETE_EXPIRED_ELAPSED: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "near_store_ttl_delete_expired_elapsed_sec",
+        "Time spent deleting expired values of ephemeral columns",
+        exponential_buckets(0.01, 2.0, 14).unwrap(),
+    )
+    .unwrap()
+});
+
 pub(crate) static HAS_STATE_SNAPSHOT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_has_state_snapshot", "Whether a node has a state snapshot open")
         .unwrap()
@@ -386,6 +539,106 @@ pub(crate) static GET_STATE_PART_WITH_FS_NODES: Lazy<IntCounterVec> = Lazy::new(
     .unwrap()
 });
 
+pub(crate) static MEM_TRIE_NODE_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_mem_trie_node_count",
+        "Number of in-memory trie nodes, by node kind",
+        &["shard_uid", "kind"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_NODE_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_mem_trie_node_bytes",
+        "Total arena bytes used by in-memory trie nodes, by node kind",
+        &["shard_uid", "kind"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_VALUES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_mem_trie_values",
+        "Number of values in the in-memory trie, by whether they are inlined or referenced",
+        &["shard_uid", "kind"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_ARENA_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_mem_trie_arena_size",
+        "Size of the in-memory trie arena: allocated by the growth policy, used, freed but not yet reused, and the cap",
+        &["shard_uid", "type"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_ARENA_FRAGMENTATION: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "near_mem_trie_arena_fragmentation",
+        "Fraction of the used in-memory trie arena that was freed and is not yet reused",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_VALUE_DEDUP: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_mem_trie_value_dedup",
+        "Deduplication of inlined values in the in-memory trie arena: number of hits and misses of the interning table, and bytes saved",
+        &["shard_uid", "type"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_CONSISTENCY_CHECK_KEYS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_mem_trie_consistency_check_keys",
+        "Number of keys compared between the in-memory trie, the disk trie and flat storage",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static MEM_TRIE_CONSISTENCY_CHECK_DIVERGENCES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_mem_trie_consistency_check_divergences",
+        "Number of keys for which the in-memory trie, the disk trie and flat storage disagree",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static REFCOUNT_AUDIT_VISITED_NODES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_refcount_audit_visited_nodes_total",
+        "Number of trie nodes visited by the audit of reference counts",
+        &["shard_uid"],
+    )
+    .unwrap()
+});
+
+pub(crate) static REFCOUNT_AUDIT_ENTRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_refcount_audit_entries_total",
+        "Number of sampled trie nodes and values whose stored reference count matches the recounted one, drifts from it, or which are missing",
+        &["shard_uid", "result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static REFCOUNT_AUDIT_DRIFT: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_refcount_audit_drift",
+        "Difference between the stored and the recounted reference count of sampled trie nodes and values which drift",
+        &["shard_uid"],
+        Some(vec![-100.0, -10.0, -1.0, 1.0, 2.0, 5.0, 10.0, 100.0, 1000.0]),
+    )
+    .unwrap()
+});
+
 pub mod flat_state_metrics {
     use super::*;
 
@@ -461,6 +714,22 @@ pub mod flat_state_metrics {
         )
         .unwrap()
     });
+    pub static FLAT_STORAGE_STORED_CHANGES_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+        try_create_int_gauge_vec(
+            "near_flat_storage_stored_changes_size",
+            "Approximate size of changes in flat storage deltas stored on disk, which are all deltas not yet applied to flat storage head",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+    pub static FLAT_STORAGE_HEAD_LAG: Lazy<IntGaugeVec> = Lazy::new(|| {
+        try_create_int_gauge_vec(
+            "near_flat_storage_head_lag",
+            "Height distance between the latest block added to flat storage, normally the chain head, and flat storage head",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
     pub static FLAT_STORAGE_DISTANCE_TO_HEAD: Lazy<IntGaugeVec> = Lazy::new(|| {
         try_create_int_gauge_vec(
             "flat_storage_distance_to_head",
@@ -480,7 +749,8 @@ pub mod flat_state_metrics {
 
     pub mod inlining_migration {
         use near_o11y::metrics::{
-            try_create_histogram, try_create_int_counter, Histogram, IntCounter,
+            try_create_histogram, try_create_int_counter, try_create_int_gauge, Histogram,
+            IntCounter, IntGauge,
         };
         use once_cell::sync::Lazy;
 
@@ -526,6 +796,20 @@ pub mod flat_state_metrics {
             )
             .unwrap()
         });
+        pub static PROCESSED_BATCH_COUNT: Lazy<IntCounter> = Lazy::new(|| {
+            try_create_int_counter(
+                "near_flat_state_inlining_migration_processed_batch_count",
+                "Total number of FlatState batches processed since the node start.",
+            )
+            .unwrap()
+        });
+        pub static CURRENT_SHARD_ID: Lazy<IntGauge> = Lazy::new(|| {
+            try_create_int_gauge(
+                "near_flat_state_inlining_migration_current_shard_id",
+                "Shard id of the FlatState rows currently processed. Rows are processed shard by shard, in key order.",
+            )
+            .unwrap()
+        });
     }
 }
 pub static COLD_STORE_MIGRATION_BATCH_WRITE_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
@@ -1,5 +1,7 @@
 use crate::db::rocksdb::snapshot::{Snapshot, SnapshotError, SnapshotRemoveError};
 use crate::db::rocksdb::RocksDB;
+use crate::db::{Database, ObjectStorageDB};
+use crate::intent_log::IntentLog;
 use crate::metadata::{DbKind, DbMetadata, DbVersion, DB_VERSION};
 use crate::{DBCol, DBTransaction, Mode, NodeStorage, Store, StoreConfig, Temperature};
 use std::sync::Arc;
@@ -175,6 +177,10 @@ pub struct StoreOpener<'a> {
     /// A migrator which performs database migration if the database has old
     /// version.
     migrator: Option<&'a dyn StoreMigrator>,
+
+    /// Whether unfinished intents are recovered when opening in read-write
+    /// mode, see [`crate::intent_log`].
+    recover_intents: bool,
 }
 
 /// Opener for a single RocksDB instance.
@@ -209,6 +215,7 @@ impl<'a> StoreOpener<'a> {
             cold: cold_config.map(|config| DBOpener::new(home_dir, config, Temperature::Cold)),
             archive: archive,
             migrator: None,
+            recover_intents: true,
         }
     }
 
@@ -222,6 +229,16 @@ impl<'a> StoreOpener<'a> {
         self
     }
 
+    /// Configures the opener not to recover unfinished intents.
+    ///
+    /// This is needed for checkpoints of a database, which contain the
+    /// intents of the database they were made from.  Recovering those would
+    /// e.g. delete the state snapshot the checkpoint was made for.
+    pub(crate) fn without_intent_recovery(mut self) -> Self {
+        self.recover_intents = false;
+        self
+    }
+
     /// Returns path to the underlying RocksDB database.
     ///
     /// Does not check whether the database actually exists.
@@ -259,12 +276,14 @@ impl<'a> StoreOpener<'a> {
         }
 
         let hot_snapshot = {
+            Self::ensure_not_corrupted(mode, &self.hot)?;
             Self::ensure_created(mode, &self.hot)?;
             Self::ensure_kind(mode, &self.hot, self.archive, Temperature::Hot)?;
             Self::ensure_version(mode, &self.hot, &self.migrator)?
         };
 
         let cold_snapshot = if let Some(cold) = &self.cold {
+            Self::ensure_not_corrupted(mode, cold)?;
             Self::ensure_created(mode, cold)?;
             Self::ensure_kind(mode, cold, self.archive, Temperature::Cold)?;
             Self::ensure_version(mode, cold, &self.migrator)?
@@ -273,14 +292,12 @@ impl<'a> StoreOpener<'a> {
         };
 
         let (hot_db, _) = self.hot.open(mode, DB_VERSION)?;
-        let cold_db = self
-            .cold
-            .as_ref()
-            .map(|cold| cold.open(mode, DB_VERSION))
-            .transpose()?
-            .map(|(db, _)| db);
+        let cold_db = self.cold.as_ref().map(|cold| cold.open_cold(mode)).transpose()?;
 
-        let storage = NodeStorage::from_rocksdb(hot_db, cold_db);
+        let storage = NodeStorage::from_rocksdb(hot_db, cold_db, self.hot.config.rpc_read_limits);
+        if mode.read_write() && self.recover_intents {
+            storage.recover_intents()?;
+        }
 
         hot_snapshot.remove()?;
         cold_snapshot.remove()?;
@@ -325,6 +342,22 @@ impl<'a> StoreOpener<'a> {
         Ok((hot_snapshot, cold_snapshot))
     }
 
+    /// Repairs the DB if it exists and is corrupted, if corruption recovery is
+    /// enabled in the config and the mode allows writing.
+    fn ensure_not_corrupted(mode: Mode, opener: &DBOpener) -> Result<(), StoreOpenerError> {
+        if !opener.config.corruption_recovery || !mode.read_write() {
+            return Ok(());
+        }
+        if !opener.path.join("CURRENT").is_file() {
+            return Ok(());
+        }
+        tracing::info!(target: "db_opener", path=%opener.path.display(), "Checking the database for corruption.");
+        if RocksDB::recover_if_corrupted(&opener.path, opener.config, opener.temp)?.is_none() {
+            tracing::info!(target: "db_opener", path=%opener.path.display(), "The database is not corrupted.");
+        }
+        Ok(())
+    }
+
     // Creates the DB if it doesn't exist.
     fn ensure_created(mode: Mode, opener: &DBOpener) -> Result<(), StoreOpenerError> {
         let meta = opener.get_metadata()?;
@@ -340,7 +373,7 @@ impl<'a> StoreOpener<'a> {
                 tracing::info!(target: "db_opener", path=%opener.path.display(), "The database doesn't exist, creating it.");
 
                 let db = opener.create()?;
-                let store = Store { storage: Arc::new(db) };
+                let store = Store::new(Arc::new(db));
                 store.set_db_version(DB_VERSION)?;
                 return Ok(());
             }
@@ -468,13 +501,13 @@ impl<'a> StoreOpener<'a> {
         version: DbVersion,
     ) -> Result<Store, StoreOpenerError> {
         let (db, _) = opener.open(mode, version)?;
-        let store = Store { storage: Arc::new(db) };
+        let store = Store::new(Arc::new(db));
         Ok(store)
     }
 
     fn open_store_unsafe(mode: Mode, opener: &DBOpener) -> Result<Store, StoreOpenerError> {
         let db = opener.open_unsafe(mode)?;
-        let store = Store { storage: Arc::new(db) };
+        let store = Store::new(Arc::new(db));
         Ok(store)
     }
 }
@@ -530,6 +563,16 @@ impl<'a> DBOpener<'a> {
         }
     }
 
+    /// Opens the cold database.  If object storage is configured, wraps it in
+    /// [`ObjectStorageDB`] so that values are kept in the bucket.
+    fn open_cold(&self, mode: Mode) -> std::io::Result<Arc<dyn Database>> {
+        let (db, _) = self.open(mode, DB_VERSION)?;
+        Ok(match &self.config.cold.object_storage {
+            Some(config) => Arc::new(ObjectStorageDB::new(db, config)?),
+            None => Arc::new(db),
+        })
+    }
+
     /// Opens the database in given mode without checking the expected version and kind.
     ///
     /// This is only suitable when creating the database or setting the version
@@ -583,10 +626,13 @@ pub trait StoreMigrator {
 ///
 /// Returns NodeStorage of checkpoint db.
 /// `archive` -- is hot storage archival (needed to open checkpoint).
+/// `encryption` -- key the hot storage is encrypted with, if any.  The
+/// checkpoint is encrypted with the same key.
 pub fn checkpoint_hot_storage_and_cleanup_columns(
     hot_store: &Store,
     checkpoint_base_path: &std::path::Path,
     columns_to_keep: Option<Vec<DBCol>>,
+    encryption: Option<&crate::config::EncryptionKeySource>,
 ) -> Result<NodeStorage, StoreOpenerError> {
     let _span =
         tracing::info_span!(target: "state_snapshot", "checkpoint_hot_storage_and_cleanup_columns")
@@ -599,12 +645,18 @@ pub fn checkpoint_hot_storage_and_cleanup_columns(
         .create_checkpoint(&checkpoint_path)
         .map_err(StoreOpenerError::CheckpointError)?;
 
-    // As only path from config is used in StoreOpener, default config with custom path will do.
+    // As only path and encryption from config are used in StoreOpener,
+    // default config with those set will do.
     let mut config = StoreConfig::default();
     config.path = Some(checkpoint_path);
+    config.encryption = encryption.cloned();
     let archive = hot_store.get_db_kind()? == Some(DbKind::Archive);
-    let opener = StoreOpener::new(checkpoint_base_path, archive, &config, None);
+    let opener =
+        StoreOpener::new(checkpoint_base_path, archive, &config, None).without_intent_recovery();
     let node_storage = opener.open_in_mode(Mode::ReadWriteExisting)?;
+    // Intents in the checkpoint belong to the source database, which recovers
+    // them itself.  The one making the checkpoint is still pending.
+    IntentLog::new(node_storage.get_hot_store()).discard_all()?;
 
     if let Some(columns_to_keep) = columns_to_keep {
         let columns_to_keep_set: std::collections::HashSet<DBCol> =
@@ -634,6 +686,7 @@ pub fn checkpoint_hot_storage_and_cleanup_columns(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::intent_log::Intent;
     use std::path::PathBuf;
 
     fn check_keys_existence(store: &Store, column: &DBCol, keys: &Vec<Vec<u8>>, expected: bool) {
@@ -646,7 +699,7 @@ mod tests {
     fn test_checkpoint_hot_storage_and_cleanup_columns() {
         let (home_dir, opener) = NodeStorage::test_opener();
         let node_storage = opener.open().unwrap();
-        let hot_store = Store { storage: node_storage.hot_storage.clone() };
+        let hot_store = Store::new(node_storage.hot_storage.clone());
 
         let keys = vec![vec![0], vec![1], vec![2], vec![3]];
         let columns = vec![DBCol::Block, DBCol::Chunks, DBCol::BlockHeader];
@@ -663,6 +716,7 @@ mod tests {
             &hot_store,
             &home_dir.path().join(PathBuf::from("checkpoint_none")),
             None,
+            None,
         )
         .unwrap();
         check_keys_existence(&store.get_hot_store(), &DBCol::Block, &keys, true);
@@ -673,6 +727,7 @@ mod tests {
             &hot_store,
             &home_dir.path().join(PathBuf::from("checkpoint_some")),
             Some(vec![DBCol::Block]),
+            None,
         )
         .unwrap();
         check_keys_existence(&store.get_hot_store(), &DBCol::Block, &keys, true);
@@ -683,6 +738,7 @@ mod tests {
             &hot_store,
             &home_dir.path().join(PathBuf::from("checkpoint_all")),
             Some(vec![DBCol::Block, DBCol::Chunks, DBCol::BlockHeader]),
+            None,
         )
         .unwrap();
         check_keys_existence(&store.get_hot_store(), &DBCol::Block, &keys, true);
@@ -693,10 +749,46 @@ mod tests {
             &hot_store,
             &home_dir.path().join(PathBuf::from("checkpoint_empty")),
             Some(vec![]),
+            None,
         )
         .unwrap();
         check_keys_existence(&store.get_hot_store(), &DBCol::Block, &keys, false);
         check_keys_existence(&store.get_hot_store(), &DBCol::Chunks, &keys, false);
         check_keys_existence(&store.get_hot_store(), &DBCol::BlockHeader, &keys, false);
     }
+
+    #[test]
+    fn test_checkpoint_does_not_recover_intents() {
+        let (home_dir, opener) = NodeStorage::test_opener();
+        let node_storage = opener.open().unwrap();
+        let hot_store = node_storage.get_hot_store();
+        let mut store_update = hot_store.store_update();
+        store_update.insert(DBCol::Block, vec![1], vec![42]);
+        store_update.commit().unwrap();
+
+        // Like `make_state_snapshot`, record the intent before checkpointing.
+        let checkpoint_path = home_dir.path().join("checkpoint");
+        let intent_log = IntentLog::new(hot_store.clone());
+        let intent = intent_log
+            .begin(Intent::ReplaceStateSnapshot { path: checkpoint_path.display().to_string() })
+            .unwrap();
+        let checkpoint =
+            checkpoint_hot_storage_and_cleanup_columns(&hot_store, &checkpoint_path, None, None)
+                .unwrap();
+        assert!(checkpoint_path.join("data").exists());
+        check_keys_existence(&checkpoint.get_hot_store(), &DBCol::Block, &vec![vec![1]], true);
+        assert_eq!(IntentLog::new(checkpoint.get_hot_store()).pending().unwrap(), vec![]);
+        assert_eq!(intent_log.pending().unwrap().len(), 1);
+        intent.finish().unwrap();
+        drop(checkpoint);
+
+        // Reopening the checkpoint later doesn't find the intent either.
+        let mut config = StoreConfig::default();
+        config.path = Some(checkpoint_path.join("data"));
+        let checkpoint = StoreOpener::new(&checkpoint_path, false, &config, None)
+            .open_in_mode(Mode::ReadWriteExisting)
+            .unwrap();
+        assert!(checkpoint_path.join("data").exists());
+        check_keys_existence(&checkpoint.get_hot_store(), &DBCol::Block, &vec![vec![1]], true);
+    }
 }
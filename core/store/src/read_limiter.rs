@@ -0,0 +1,240 @@
+//! Limiting of low priority reads so that they don't compete with reads
+//! needed for block and chunk processing.
+//!
+//! See [`crate::Store::with_priority`].
+
+use crate::config::ReadLimitsConfig;
+use crate::db::{DBIterator, DBIteratorItem};
+use crate::metrics;
+use near_o11y::metrics::IntCounter;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+/// Priority of reads done through a [`crate::Store`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, strum::IntoStaticStr, strum::EnumIter)]
+pub enum ReadPriority {
+    /// Reads needed for block and chunk processing.  They are never limited.
+    #[default]
+    Consensus,
+    /// Reads done on behalf of RPC requests, e.g. view calls.  They are
+    /// limited according to `StoreConfig::rpc_read_limits`.
+    Rpc,
+}
+
+/// Limits the number of concurrent RPC reads and the rate at which they are
+/// started.
+pub struct ReadLimiter {
+    limits: ReadLimitsConfig,
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+struct LimiterState {
+    /// Number of RPC reads being executed.
+    in_flight: usize,
+    /// Number of RPC reads which may be started without waiting.  Refilled
+    /// at `max_reads_per_second` rate, up to one second worth of reads.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Guard of a started read.  Dropping it allows another read to start.
+#[must_use]
+pub struct ReadPermit<'a> {
+    limiter: Option<&'a ReadLimiter>,
+}
+
+/// Iterator holding the permit of the read until it's dropped.
+struct LimitedIterator<'a> {
+    iter: DBIterator<'a>,
+    _permit: ReadPermit<'a>,
+}
+
+static UNLIMITED: Lazy<Arc<ReadLimiter>> =
+    Lazy::new(|| Arc::new(ReadLimiter::new(ReadLimitsConfig::default())));
+
+/// `STORE_READS` counters by priority, so that reads don't look up the label
+/// values each time.
+static READS: Lazy<Vec<IntCounter>> = Lazy::new(|| {
+    ReadPriority::iter()
+        .map(|priority| metrics::STORE_READS.with_label_values(&[priority.into()]))
+        .collect()
+});
+
+impl ReadLimiter {
+    pub fn new(limits: ReadLimitsConfig) -> Self {
+        let tokens = limits.max_reads_per_second.unwrap_or(0) as f64;
+        Self {
+            limits,
+            state: Mutex::new(LimiterState { in_flight: 0, tokens, last_refill: Instant::now() }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Returns limiter which doesn't limit any reads.
+    pub fn unlimited() -> Arc<Self> {
+        UNLIMITED.clone()
+    }
+
+    /// Blocks until a read with given priority may be started.
+    pub fn acquire(&self, priority: ReadPriority) -> ReadPermit<'_> {
+        READS[priority as usize].inc();
+        let is_limited = self.limits.max_concurrent_reads.is_some()
+            || self.limits.max_reads_per_second.is_some();
+        if priority == ReadPriority::Consensus || !is_limited {
+            return ReadPermit { limiter: None };
+        }
+
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let wait = self.time_until_allowed(&mut state);
+            if wait.is_zero() {
+                break;
+            }
+            state = self.released.wait_timeout(state, wait).unwrap().0;
+        }
+        state.in_flight += 1;
+        if self.limits.max_reads_per_second.is_some() {
+            state.tokens -= 1.0;
+        }
+        drop(state);
+        metrics::STORE_READ_LIMITER_WAIT
+            .with_label_values(&[priority.into()])
+            .observe(start.elapsed().as_secs_f64());
+        ReadPermit { limiter: Some(self) }
+    }
+
+    /// Returns how long to wait before a read may be started, or zero if it
+    /// may be started right away.  Waits for a finished read are bounded so
+    /// that token refills are noticed.
+    fn time_until_allowed(&self, state: &mut LimiterState) -> Duration {
+        let mut wait = Duration::ZERO;
+        if let Some(max_reads_per_second) = self.limits.max_reads_per_second {
+            let rate = max_reads_per_second.max(1) as f64;
+            let now = Instant::now();
+            let refill = now.duration_since(state.last_refill).as_secs_f64() * rate;
+            state.tokens = (state.tokens + refill).min(rate);
+            state.last_refill = now;
+            if state.tokens < 1.0 {
+                wait = Duration::from_secs_f64((1.0 - state.tokens) / rate);
+            }
+        }
+        if let Some(max_concurrent_reads) = self.limits.max_concurrent_reads {
+            if state.in_flight >= max_concurrent_reads.max(1) {
+                wait = wait.max(Duration::from_millis(100));
+            }
+        }
+        wait
+    }
+}
+
+impl<'a> ReadPermit<'a> {
+    /// Makes the iterator hold the permit until it's dropped, so that a scan
+    /// counts as a read in flight for as long as it lasts.
+    pub(crate) fn hold_during(self, iter: DBIterator<'a>) -> DBIterator<'a> {
+        Box::new(LimitedIterator { iter, _permit: self })
+    }
+}
+
+impl Iterator for LimitedIterator<'_> {
+    type Item = DBIteratorItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl Drop for ReadPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter {
+            limiter.state.lock().unwrap().in_flight -= 1;
+            limiter.released.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadLimiter, ReadPriority};
+    use crate::config::ReadLimitsConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_concurrent_reads_limit() {
+        let limiter = Arc::new(ReadLimiter::new(ReadLimitsConfig {
+            max_concurrent_reads: Some(2),
+            max_reads_per_second: None,
+        }));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                std::thread::spawn(move || {
+                    let _permit = limiter.acquire(ReadPriority::Rpc);
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        // Consensus reads don't wait for RPC reads.
+        let _permit = limiter.acquire(ReadPriority::Consensus);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn test_iterator_holds_permit() {
+        let limiter = Arc::new(ReadLimiter::new(ReadLimitsConfig {
+            max_concurrent_reads: Some(1),
+            max_reads_per_second: None,
+        }));
+        let iter = limiter.acquire(ReadPriority::Rpc).hold_during(Box::new(std::iter::empty()));
+        let start = Instant::now();
+        let thread = {
+            let limiter = limiter.clone();
+            std::thread::spawn(move || {
+                let _permit = limiter.acquire(ReadPriority::Rpc);
+                start.elapsed()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        drop(iter);
+        // The read waits until the iterator is dropped.
+        let waited = thread.join().unwrap();
+        assert!(waited >= Duration::from_millis(200), "{waited:?}");
+    }
+
+    #[test]
+    fn test_reads_per_second_limit() {
+        let limiter = ReadLimiter::new(ReadLimitsConfig {
+            max_concurrent_reads: None,
+            max_reads_per_second: Some(100),
+        });
+        let start = Instant::now();
+        // First second worth of reads is allowed right away.
+        for _ in 0..150 {
+            let _permit = limiter.acquire(ReadPriority::Rpc);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{elapsed:?}");
+
+        let start = Instant::now();
+        for _ in 0..1000 {
+            let _permit = limiter.acquire(ReadPriority::Consensus);
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::config::TrieCacheConfig;
+use crate::config::{
+    FlatStorageDeltaPruningConfig, MemTrieArenaConfig, MemTrieConsistencyCheckConfig,
+    TrieCacheConfig,
+};
 use crate::StoreConfig;
 use near_primitives::types::AccountId;
 use std::str::FromStr;
@@ -30,6 +33,18 @@ pub struct TrieConfig {
     pub sweat_prefetch_receivers: Vec<AccountId>,
     /// List of allowed predecessor accounts for SWEAT prefetching.
     pub sweat_prefetch_senders: Vec<AccountId>,
+
+    pub flat_storage_delta_pruning: FlatStorageDeltaPruningConfig,
+
+    /// Memory limit in bytes for key-values read at once when generating a
+    /// state part from flat storage. If not set, all of them are read at once.
+    pub state_part_memory_limit: Option<usize>,
+
+    /// Memory placement and growth policy of the arenas of in-memory tries.
+    pub mem_trie_arena: MemTrieArenaConfig,
+
+    /// Periodic comparison of in-memory tries against the disk state.
+    pub mem_trie_consistency_check: MemTrieConsistencyCheckConfig,
 }
 
 impl TrieConfig {
@@ -53,6 +68,11 @@ impl TrieConfig {
                 Err(e) => error!(target: "config", "invalid account id {account}: {e}"),
             }
         }
+        this.flat_storage_delta_pruning = config.flat_storage_delta_pruning;
+        this.state_part_memory_limit =
+            config.state_part_memory_limit.map(|limit| limit.as_u64() as usize);
+        this.mem_trie_arena = config.mem_trie_arena.clone();
+        this.mem_trie_consistency_check = config.mem_trie_consistency_check.clone();
 
         this
     }
@@ -1,11 +1,20 @@
 use super::{ArenaMemory, ArenaSliceMut};
+use borsh::{BorshDeserialize, BorshSerialize};
 
 /// Simple bump allocator with freelists. Allocations are rounded up to its
 /// allocation class, so that deallocated memory can be reused by a similarly
 /// sized allocation.
+///
+/// The allocator state is borsh-serializable so that it can be persisted
+/// together with the arena memory it manages.
+#[derive(BorshSerialize, BorshDeserialize)]
 pub struct Allocator {
     freelists: [usize; NUM_ALLOCATION_CLASSES],
     next_ptr: usize,
+    /// Total size of the allocations in the freelists. Not persisted; see
+    /// `recount_free_size`.
+    #[borsh_skip]
+    free_size: usize,
 }
 
 const MAX_ALLOC_SIZE: usize = 16 * 1024;
@@ -40,7 +49,54 @@ const NUM_ALLOCATION_CLASSES: usize = allocation_class(MAX_ALLOC_SIZE) + 1;
 
 impl Allocator {
     pub fn new() -> Self {
-        Self { freelists: [usize::MAX; NUM_ALLOCATION_CLASSES], next_ptr: 0 }
+        Self { freelists: [usize::MAX; NUM_ALLOCATION_CLASSES], next_ptr: 0, free_size: 0 }
+    }
+
+    /// The number of bytes at the beginning of the arena that have ever been
+    /// handed out; everything beyond this position is untouched.
+    pub fn used_size(&self) -> usize {
+        self.next_ptr
+    }
+
+    /// Total number of bytes currently sitting in the freelists, i.e. memory
+    /// that has been allocated before, then freed, and not yet reused.
+    pub fn free_size(&self) -> usize {
+        self.free_size
+    }
+
+    /// Computes `free_size` by walking the freelists, which is needed after
+    /// deserializing the allocator. Fails if a freelist points outside of the
+    /// used part of the arena or is longer than the arena could hold.
+    pub fn recount_free_size(&mut self, arena: &ArenaMemory) -> Result<(), String> {
+        let mut total = 0;
+        for (size_class, head) in self.freelists.iter().enumerate() {
+            let size = allocation_size(size_class);
+            let mut pos = *head;
+            while pos != usize::MAX {
+                if pos.checked_add(size).map_or(true, |end| end > self.next_ptr) {
+                    return Err(format!("Freelist entry at {} is outside of the arena", pos));
+                }
+                total += size;
+                if total > self.next_ptr {
+                    return Err("Freelists are larger than the arena".to_string());
+                }
+                pos = arena.ptr(pos).read_usize();
+            }
+        }
+        self.free_size = total;
+        Ok(())
+    }
+
+    /// What `used_size` would be after allocating the given size; unchanged
+    /// if the allocation can be served from a freelist.
+    pub fn used_size_after_allocating(&self, size: usize) -> usize {
+        assert!(size <= MAX_ALLOC_SIZE, "Cannot allocate {} bytes", size);
+        let size_class = allocation_class(size);
+        if self.freelists[size_class] == usize::MAX {
+            self.next_ptr + allocation_size(size_class)
+        } else {
+            self.next_ptr
+        }
     }
 
     /// Allocates a slice of the given size in the arena.
@@ -64,18 +120,21 @@ impl Allocator {
         } else {
             let pos = self.freelists[size_class];
             self.freelists[size_class] = arena.ptr(pos).read_usize();
+            self.free_size -= allocation_size;
             arena.slice_mut(pos, size)
         }
     }
 
     /// Deallocates the given slice from the arena; the slice's `pos` and `len`
-    /// must be the same as an allocation that was returned earlier.
-    pub fn deallocate(&mut self, arena: &mut ArenaMemory, pos: usize, len: usize) {
+    /// must be the same as an allocation that was returned earlier. Returns
+    /// the number of bytes returned to the freelists.
+    pub fn deallocate(&mut self, arena: &mut ArenaMemory, pos: usize, len: usize) -> usize {
         let size_class = allocation_class(len);
-        arena
-            .slice_mut(pos, allocation_size(size_class))
-            .write_usize_at(0, self.freelists[size_class]);
+        let allocation_size = allocation_size(size_class);
+        arena.slice_mut(pos, allocation_size).write_usize_at(0, self.freelists[size_class]);
         self.freelists[size_class] = pos;
+        self.free_size += allocation_size;
+        allocation_size
     }
 }
 
@@ -0,0 +1,85 @@
+use near_primitives::hash::{hash, CryptoHash};
+use std::collections::HashMap;
+
+/// Size of the header in front of every interned value: a u32 refcount
+/// followed by the u32 length of the value.
+pub const INTERNED_VALUE_HEADER_SIZE: usize = 2 * std::mem::size_of::<u32>();
+
+/// Memory an interned value takes on top of its bytes: the 8-byte pointer
+/// stored in the node, the header of its allocation and its entry in the
+/// lookup table. A table entry is the 40-byte key and position plus a control
+/// byte, and the table is up to 7/8 full, so the total is about 62 bytes.
+pub const INTERNED_VALUE_OVERHEAD: usize = std::mem::size_of::<u64>()
+    + INTERNED_VALUE_HEADER_SIZE
+    + (std::mem::size_of::<(CryptoHash, usize)>() + 1) * 8 / 7;
+
+/// Inlined values shorter than this are never interned. Interning a value
+/// held by a single node costs `INTERNED_VALUE_OVERHEAD` bytes, while each
+/// further node holding it saves the length of the value minus a pointer, so
+/// small values would mostly cost memory. At this size a value shared by two
+/// nodes already saves more than the overhead.
+pub const MIN_INTERNED_VALUE_SIZE: usize = 128;
+
+/// Lookup table used to deduplicate inlined values stored in the arena.
+///
+/// An interned value is a separate arena allocation laid out as
+/// `[refcount: u32][length: u32][bytes]`, shared by all nodes holding the
+/// same value. The table maps the hash of the value to the position of its
+/// allocation. The refcounts live in the arena itself, so interned values
+/// can be released even if the table is not present (e.g. when an arena
+/// written with deduplication enabled is loaded with it disabled).
+#[derive(Default)]
+pub struct ValueInterner {
+    table: HashMap<CryptoHash, usize>,
+    stats: ValueInternerStats,
+}
+
+/// Effectiveness of value deduplication in an arena.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ValueInternerStats {
+    /// Number of values that were found already interned.
+    pub hits: u64,
+    /// Number of values that had to be interned anew.
+    pub misses: u64,
+    /// Bytes of value data currently not stored thanks to deduplication.
+    pub saved_bytes: u64,
+}
+
+impl ValueInterner {
+    pub fn get(&self, value: &[u8]) -> Option<usize> {
+        self.table.get(&hash(value)).copied()
+    }
+
+    pub fn insert(&mut self, value: &[u8], pos: usize) {
+        self.table.insert(hash(value), pos);
+    }
+
+    /// Removes the entry for the given value, if it points to `pos`.
+    pub fn remove(&mut self, value: &[u8], pos: usize) {
+        let key = hash(value);
+        if self.table.get(&key) == Some(&pos) {
+            self.table.remove(&key);
+        }
+    }
+
+    pub fn stats(&self) -> ValueInternerStats {
+        self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut ValueInternerStats {
+        &mut self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{INTERNED_VALUE_OVERHEAD, MIN_INTERNED_VALUE_SIZE};
+
+    #[test]
+    fn test_min_interned_value_size() {
+        assert_eq!(INTERNED_VALUE_OVERHEAD, 62);
+        // A value shared by two nodes saves more than it costs.
+        let pointer = std::mem::size_of::<u64>();
+        assert!(MIN_INTERNED_VALUE_SIZE - pointer > INTERNED_VALUE_OVERHEAD);
+    }
+}
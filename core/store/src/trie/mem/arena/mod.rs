@@ -1,9 +1,17 @@
 mod alloc;
+mod interner;
 use self::alloc::Allocator;
+pub use self::interner::ValueInternerStats;
+pub(crate) use self::interner::INTERNED_VALUE_HEADER_SIZE;
+use self::interner::{ValueInterner, MIN_INTERNED_VALUE_SIZE};
+use super::node::MemTrieNodeId;
+use super::stats::MemTrieStats;
+use crate::config::{ArenaGrowthPolicy, ArenaMemoryConfig};
 use borsh::{BorshDeserialize, BorshSerialize};
 use memmap2::{MmapMut, MmapOptions};
 use std::fmt::{Debug, Formatter};
 use std::hash::Hash;
+use std::io::{Read, Write};
 use std::mem::size_of;
 
 /// Arena to store in-memory trie nodes.
@@ -14,6 +22,24 @@ use std::mem::size_of;
 pub struct Arena {
     memory: ArenaMemory,
     allocator: Allocator,
+    growth: ArenaGrowthPolicy,
+    /// Number of bytes the arena is currently allowed to use; grows in
+    /// chunks according to the growth policy, up to its cap.
+    allocated_size: usize,
+    /// Present if inlined values are deduplicated.
+    interner: Option<ValueInterner>,
+    /// Statistics of the trie nodes currently allocated in the arena.
+    node_stats: MemTrieStats,
+}
+
+/// Error returned when the arena cannot satisfy an allocation.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ArenaError {
+    #[error(
+        "In-memory trie arena out of memory; capped at {max_size} bytes, \
+         tried to allocate {requested} bytes when {used_size} bytes already used"
+    )]
+    OutOfMemory { max_size: usize, used_size: usize, requested: usize },
 }
 
 /// Mmap-ed memory to host the in-memory trie nodes.
@@ -28,8 +54,26 @@ pub struct ArenaMemory {
 }
 
 impl ArenaMemory {
+    #[cfg(test)]
     fn new(max_size_in_bytes: usize) -> Self {
+        Self::new_with_config(max_size_in_bytes, &ArenaMemoryConfig::default())
+    }
+
+    fn new_with_config(max_size_in_bytes: usize, config: &ArenaMemoryConfig) -> Self {
         let mmap = MmapOptions::new().len(max_size_in_bytes).map_anon().expect("mmap failed");
+        // Both settings only affect how pages are placed when they are first
+        // touched, which is why they must be applied before any allocation.
+        // Failures are not fatal; the arena simply works with regular pages.
+        if config.huge_pages {
+            if let Err(err) = advise_huge_pages(&mmap) {
+                tracing::warn!(target: "memtrie", ?err, "Failed to enable huge pages for arena");
+            }
+        }
+        if let Some(numa_node) = config.numa_node {
+            if let Err(err) = bind_to_numa_node(&mmap, numa_node) {
+                tracing::warn!(target: "memtrie", ?err, numa_node, "Failed to bind arena to NUMA node");
+            }
+        }
         Self { mmap }
     }
 
@@ -62,24 +106,300 @@ impl ArenaMemory {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn advise_huge_pages(mmap: &MmapMut) -> std::io::Result<()> {
+    mmap.advise(memmap2::Advice::HugePage)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_huge_pages(_mmap: &MmapMut) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "huge pages require Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_numa_node(mmap: &MmapMut, numa_node: u32) -> std::io::Result<()> {
+    const MPOL_PREFERRED: libc::c_long = 1;
+    const NODEMASK_BITS: u32 = u64::BITS;
+    if numa_node >= NODEMASK_BITS {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("NUMA node {} is out of supported range", numa_node),
+        ));
+    }
+    let nodemask: u64 = 1 << numa_node;
+    // SAFETY: the address range is exactly the mapping we own, and the node
+    // mask points to a live local of the advertised size.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            mmap.as_ptr(),
+            mmap.len(),
+            MPOL_PREFERRED,
+            &nodemask as *const u64,
+            NODEMASK_BITS as libc::c_ulong + 1,
+            0 as libc::c_uint,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_to_numa_node(_mmap: &MmapMut, _numa_node: u32) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "NUMA binding requires Linux"))
+}
+
 impl Arena {
     /// Creates a new memory region of the given size to store trie nodes.
     /// The `max_size_in_bytes` can be conservatively large as long as it
     /// can fit into virtual memory (which there are terabytes of). The actual
     /// memory usage will only be as much as is needed.
     pub fn new(max_size_in_bytes: usize) -> Self {
-        Self { memory: ArenaMemory::new(max_size_in_bytes), allocator: Allocator::new() }
-    }
-
-    /// Allocates a slice of the given size in the arena.
+        Self::new_with_config(max_size_in_bytes, &ArenaMemoryConfig::default())
+    }
+
+    /// Like `new`, but also controls the placement of the arena memory (huge
+    /// pages, NUMA node).
+    pub fn new_with_config(max_size_in_bytes: usize, config: &ArenaMemoryConfig) -> Self {
+        Self::new_with_growth_policy(&ArenaGrowthPolicy::fixed(max_size_in_bytes), config)
+    }
+
+    /// Creates an arena that starts at the policy's initial size and grows in
+    /// chunks as needed, failing allocations beyond the policy's cap.
+    pub fn new_with_growth_policy(growth: &ArenaGrowthPolicy, config: &ArenaMemoryConfig) -> Self {
+        Self {
+            memory: ArenaMemory::new_with_config(growth.max_size_in_bytes, config),
+            allocator: Allocator::new(),
+            growth: growth.clone(),
+            allocated_size: growth.initial_size_in_bytes.min(growth.max_size_in_bytes),
+            interner: config.dedup_inlined_values.then(ValueInterner::default),
+            node_stats: MemTrieStats::default(),
+        }
+    }
+
+    /// Makes sure that a subsequent allocation of the given size succeeds,
+    /// growing the arena if needed. Fails if that would exceed the cap.
+    pub fn try_reserve(&mut self, size: usize) -> Result<(), ArenaError> {
+        let required = self.allocator.used_size_after_allocating(size);
+        if required <= self.allocated_size {
+            return Ok(());
+        }
+        let max_size = self.growth.max_size_in_bytes;
+        if required > max_size {
+            return Err(ArenaError::OutOfMemory {
+                max_size,
+                used_size: self.allocator.used_size(),
+                requested: size,
+            });
+        }
+        let new_size = self.round_up_to_chunk(required);
+        tracing::debug!(target: "memtrie", old_size = self.allocated_size, new_size, "Growing arena");
+        self.allocated_size = new_size;
+        Ok(())
+    }
+
+    /// Rounds the size up to a whole number of growth chunks, within the cap.
+    fn round_up_to_chunk(&self, size: usize) -> usize {
+        let chunk = self.growth.chunk_size_in_bytes.max(1);
+        ((size + chunk - 1) / chunk * chunk).min(self.growth.max_size_in_bytes)
+    }
+
+    /// Allocates a slice of the given size in the arena, growing the arena
+    /// if needed.
+    pub fn try_alloc<'a>(&'a mut self, size: usize) -> Result<ArenaSliceMut<'a>, ArenaError> {
+        self.try_reserve(size)?;
+        Ok(self.allocator.allocate(&mut self.memory, size))
+    }
+
+    /// Like `try_alloc`, but panics if the arena is out of memory.
     pub fn alloc<'a>(&'a mut self, size: usize) -> ArenaSliceMut<'a> {
-        self.allocator.allocate(&mut self.memory, size)
+        self.try_alloc(size).unwrap_or_else(|err| panic!("{}", err))
     }
 
     /// Deallocates the given slice from the arena; the slice's `pos` and `len`
-    /// must be the same as an allocation that was returned earlier.
-    pub fn dealloc(&mut self, pos: usize, len: usize) {
-        self.allocator.deallocate(&mut self.memory, pos, len);
+    /// must be the same as an allocation that was returned earlier. Returns
+    /// the number of bytes reclaimed, which includes rounding up to the
+    /// allocation class.
+    pub fn dealloc(&mut self, pos: usize, len: usize) -> usize {
+        self.allocator.deallocate(&mut self.memory, pos, len)
+    }
+
+    /// Whether an inlined value of the given size should be interned rather
+    /// than stored in the node itself.
+    pub(crate) fn should_intern(&self, size: usize) -> bool {
+        self.interner.is_some() && size >= MIN_INTERNED_VALUE_SIZE
+    }
+
+    /// Returns the position of the interned copy of the given value, taking
+    /// a reference to it; the value is interned first if it is not yet.
+    pub(crate) fn intern_value(&mut self, value: &[u8]) -> Result<usize, ArenaError> {
+        let interner = self.interner.as_mut().expect("Value deduplication is disabled");
+        if let Some(pos) = interner.get(value) {
+            interner.stats_mut().hits += 1;
+            interner.stats_mut().saved_bytes += value.len() as u64;
+            let (refcount, _) = self.interned_value_header(pos);
+            self.memory.raw_slice_mut(pos, 4).copy_from_slice(&(refcount + 1).to_le_bytes());
+            return Ok(pos);
+        }
+        let mut slice = self.try_alloc(INTERNED_VALUE_HEADER_SIZE + value.len())?;
+        let pos = slice.raw_offset();
+        let data = slice.raw_slice_mut();
+        data[0..4].copy_from_slice(&1u32.to_le_bytes());
+        data[4..8].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        data[INTERNED_VALUE_HEADER_SIZE..].copy_from_slice(value);
+        let interner = self.interner.as_mut().unwrap();
+        interner.insert(value, pos);
+        interner.stats_mut().misses += 1;
+        Ok(pos)
+    }
+
+    /// Drops a reference to the interned value at the given position,
+    /// deallocating it if that was the last one. Returns the number of bytes
+    /// reclaimed.
+    pub(crate) fn release_interned_value(&mut self, pos: usize) -> usize {
+        let (refcount, length) = self.interned_value_header(pos);
+        let refcount = refcount - 1;
+        if refcount > 0 {
+            self.memory.raw_slice_mut(pos, 4).copy_from_slice(&refcount.to_le_bytes());
+            if let Some(interner) = &mut self.interner {
+                let stats = interner.stats_mut();
+                stats.saved_bytes = stats.saved_bytes.saturating_sub(length as u64);
+            }
+            return 0;
+        }
+        if let Some(interner) = &mut self.interner {
+            interner.remove(self.memory.raw_slice(pos + INTERNED_VALUE_HEADER_SIZE, length), pos);
+        }
+        self.dealloc(pos, INTERNED_VALUE_HEADER_SIZE + length)
+    }
+
+    /// Adds an interned value that is already in the arena to the lookup
+    /// table, so that new identical values are deduplicated against it. Used
+    /// after loading an arena, as the table itself is not persisted.
+    pub(crate) fn register_interned_value(&mut self, pos: usize) {
+        let (refcount, length) = self.interned_value_header(pos);
+        let Some(interner) = &mut self.interner else {
+            return;
+        };
+        let value = self.memory.raw_slice(pos + INTERNED_VALUE_HEADER_SIZE, length);
+        if interner.get(value).is_none() {
+            interner.insert(value, pos);
+            interner.stats_mut().saved_bytes += (refcount as u64 - 1) * length as u64;
+        }
+    }
+
+    /// Checks that an interned value of the given length at `pos` lies within
+    /// the used part of the arena and has a consistent header. Used for
+    /// arenas read from a file, before `register_interned_value`.
+    pub(crate) fn check_interned_value(&self, pos: usize, length: usize) -> Result<(), String> {
+        let end =
+            pos.checked_add(INTERNED_VALUE_HEADER_SIZE).and_then(|end| end.checked_add(length));
+        if end.map_or(true, |end| end > self.used_size()) {
+            return Err(format!("Interned value at {} is outside of the arena", pos));
+        }
+        match self.interned_value_header(pos) {
+            (refcount, header_length) if refcount > 0 && header_length == length => Ok(()),
+            (refcount, header_length) => Err(format!(
+                "Interned value at {} has refcount {} and length {}, expected length {}",
+                pos, refcount, header_length, length
+            )),
+        }
+    }
+
+    fn interned_value_header(&self, pos: usize) -> (u32, usize) {
+        let header = self.memory.raw_slice(pos, INTERNED_VALUE_HEADER_SIZE);
+        let refcount = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        (refcount, length as usize)
+    }
+
+    /// Statistics of value deduplication, if enabled.
+    pub fn value_interner_stats(&self) -> Option<ValueInternerStats> {
+        self.interner.as_ref().map(|interner| interner.stats())
+    }
+
+    /// Writes the allocator state followed by the used portion of the arena
+    /// memory to the given writer. Since nodes refer to each other by their
+    /// offsets in the arena, the result can be loaded back with `load_from`
+    /// without any pointer fixups.
+    pub fn save_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        self.allocator.serialize(writer)?;
+        writer.write_all(self.memory.raw_slice(0, self.allocator.used_size()))
+    }
+
+    /// Restores an arena previously written with `save_to`. The `growth` and
+    /// `config` have the same meaning as in `new_with_growth_policy`, and the
+    /// cap must be at least as large as the used portion of the saved arena.
+    pub fn load_from(
+        reader: &mut impl Read,
+        growth: &ArenaGrowthPolicy,
+        config: &ArenaMemoryConfig,
+    ) -> std::io::Result<Self> {
+        let allocator = Allocator::deserialize_reader(reader)?;
+        let used_size = allocator.used_size();
+        if used_size > growth.max_size_in_bytes {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Saved arena uses {} bytes, more than the maximum of {} bytes",
+                    used_size, growth.max_size_in_bytes
+                ),
+            ));
+        }
+        let mut arena = Self::new_with_growth_policy(growth, config);
+        reader.read_exact(arena.memory.raw_slice_mut(0, used_size))?;
+        arena.allocator = allocator;
+        arena
+            .allocator
+            .recount_free_size(&arena.memory)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        arena.allocated_size = arena.allocated_size.max(arena.round_up_to_chunk(used_size));
+        Ok(arena)
+    }
+
+    /// Number of bytes of the arena that have been handed out by the
+    /// allocator so far, including memory that has since been freed.
+    pub fn used_size(&self) -> usize {
+        self.allocator.used_size()
+    }
+
+    /// Number of bytes the arena is currently allowed to use, according to
+    /// its growth policy. Always at least `used_size`.
+    pub fn allocated_size(&self) -> usize {
+        self.allocated_size
+    }
+
+    /// The cap on the size of the arena.
+    pub fn max_size(&self) -> usize {
+        self.growth.max_size_in_bytes
+    }
+
+    /// Number of bytes that were handed out and then freed, but are not
+    /// reused yet. Together with `used_size` this measures fragmentation.
+    pub fn free_size(&self) -> usize {
+        self.allocator.free_size()
+    }
+
+    pub(crate) fn node_stats(&self) -> &MemTrieStats {
+        &self.node_stats
+    }
+
+    /// Replaces the node statistics, for an arena whose nodes were not
+    /// allocated through `MemTrieNodeId::try_new`, i.e. loaded from a file.
+    pub(crate) fn set_node_stats(&mut self, stats: MemTrieStats) {
+        self.node_stats = stats;
+    }
+
+    /// Accounts for a node that was just encoded in the arena.
+    pub(crate) fn record_node_alloc(&mut self, id: MemTrieNodeId) {
+        self.node_stats.add_node(id.as_ptr(&self.memory));
+    }
+
+    /// Accounts for a node that is about to be deallocated.
+    pub(crate) fn record_node_dealloc(&mut self, id: MemTrieNodeId) {
+        self.node_stats.remove_node(id.as_ptr(&self.memory));
     }
 
     pub fn memory(&self) -> &ArenaMemory {
@@ -280,6 +600,62 @@ mod tests {
         assert!(arena5.raw_slice(size_100gb - 100, 100).iter().all(|x| *x == 5));
     }
 
+    #[test]
+    fn test_arena_mmap_with_config() {
+        // Placement hints may be unsupported in the test environment, but the
+        // arena must remain usable either way.
+        let config = crate::config::ArenaMemoryConfig {
+            huge_pages: true,
+            numa_node: Some(0),
+            ..Default::default()
+        };
+        let mut arena = super::ArenaMemory::new_with_config(1 << 22, &config);
+        arena.raw_slice_mut(0, 1 << 22).fill(7);
+        assert!(arena.raw_slice(0, 1 << 22).iter().all(|x| *x == 7));
+    }
+
+    #[test]
+    fn test_arena_growth_policy() {
+        let growth = crate::config::ArenaGrowthPolicy {
+            initial_size_in_bytes: 1000,
+            chunk_size_in_bytes: 4096,
+            max_size_in_bytes: 3 * 4096,
+        };
+        let mut arena = super::Arena::new_with_growth_policy(
+            &growth,
+            &crate::config::ArenaMemoryConfig::default(),
+        );
+        assert_eq!(arena.allocated_size(), 1000);
+        let mut allocations = Vec::new();
+        loop {
+            match arena.try_alloc(512) {
+                Ok(slice) => allocations.push((slice.raw_offset(), slice.len())),
+                Err(err) => {
+                    assert_eq!(
+                        err,
+                        super::ArenaError::OutOfMemory {
+                            max_size: 3 * 4096,
+                            used_size: 3 * 4096,
+                            requested: 512
+                        }
+                    );
+                    break;
+                }
+            }
+            // The arena grows from its initial size in whole chunks.
+            assert!(arena.allocated_size() >= arena.used_size());
+            assert!(arena.allocated_size() == 1000 || arena.allocated_size() % 4096 == 0);
+        }
+        assert_eq!(allocations.len(), 3 * 4096 / 512);
+        assert_eq!(arena.allocated_size(), 3 * 4096);
+
+        // Freed memory can still be reused at the cap.
+        let (pos, len) = allocations.pop().unwrap();
+        arena.dealloc(pos, len);
+        assert!(arena.try_alloc(512).is_ok());
+        assert!(arena.try_alloc(512).is_err());
+    }
+
     #[test]
     fn test_arena_ptr_and_slice() {
         let mut arena = super::ArenaMemory::new(10 * 4096);
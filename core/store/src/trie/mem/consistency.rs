@@ -0,0 +1,246 @@
+use super::iter::MemTrieIterator;
+use super::lookup::memtrie_lookup;
+use super::node::{MemTrieNodePtr, MemTrieNodeView};
+use super::MemTries;
+use crate::config::MemTrieConsistencyCheckConfig;
+use crate::flat::POISONED_LOCK_ERR;
+use crate::metrics::{MEM_TRIE_CONSISTENCY_CHECK_DIVERGENCES, MEM_TRIE_CONSISTENCY_CHECK_KEYS};
+use crate::trie::nibble_slice::NibbleSlice;
+use crate::{KeyLookupMode, ShardTries, StorageError, Trie};
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::state::ValueRef;
+use near_primitives::types::StateRoot;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+
+/// A key for which the in-memory trie, the disk trie and flat storage do not
+/// agree on the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemTrieDivergence {
+    pub key: Vec<u8>,
+    pub mem_trie: Option<ValueRef>,
+    pub disk_trie: Option<ValueRef>,
+    /// `None` if the trie used for the check had no flat storage.
+    pub flat_storage: Option<Option<ValueRef>>,
+}
+
+/// Result of a consistency check of an in-memory trie.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemTrieConsistencyReport {
+    pub keys_checked: usize,
+    pub divergences: Vec<MemTrieDivergence>,
+}
+
+impl MemTrieConsistencyReport {
+    /// Exports the result as metrics and logs an error for every divergence,
+    /// with enough context to reproduce the lookup.
+    pub fn report(&self, shard_uid: ShardUId, state_root: &StateRoot, block_hash: &CryptoHash) {
+        let shard_uid_label = shard_uid.to_string();
+        MEM_TRIE_CONSISTENCY_CHECK_KEYS
+            .with_label_values(&[&shard_uid_label])
+            .inc_by(self.keys_checked as u64);
+        MEM_TRIE_CONSISTENCY_CHECK_DIVERGENCES
+            .with_label_values(&[&shard_uid_label])
+            .inc_by(self.divergences.len() as u64);
+        for divergence in &self.divergences {
+            tracing::error!(
+                target: "memtrie",
+                %shard_uid,
+                %state_root,
+                %block_hash,
+                key = %hex::encode(&divergence.key),
+                mem_trie = ?divergence.mem_trie,
+                disk_trie = ?divergence.disk_trie,
+                flat_storage = ?divergence.flat_storage,
+                "In-memory trie diverges from disk state"
+            );
+        }
+    }
+}
+
+/// Picks a random key present in the trie by walking down from the root and
+/// choosing a random child at every branch.
+fn random_key(root: MemTrieNodePtr<'_>, rng: &mut impl Rng) -> Vec<u8> {
+    let mut nibbles = Vec::new();
+    let mut node = root;
+    loop {
+        match node.view() {
+            MemTrieNodeView::Leaf { extension, .. } => {
+                nibbles.extend(NibbleSlice::from_encoded(extension.raw_slice()).0.iter());
+                break;
+            }
+            MemTrieNodeView::Extension { extension, child, .. } => {
+                nibbles.extend(NibbleSlice::from_encoded(extension.raw_slice()).0.iter());
+                node = child;
+            }
+            MemTrieNodeView::Branch { children, .. } => {
+                let present: Vec<_> = (0..16).filter(|&i| children.get(i).is_some()).collect();
+                let idx = *present.choose(rng).expect("Branch without children");
+                nibbles.push(idx as u8);
+                node = children.get(idx).unwrap();
+            }
+            MemTrieNodeView::BranchWithValue { children, .. } => {
+                let present: Vec<_> = (0..16).filter(|&i| children.get(i).is_some()).collect();
+                if rng.gen_range(0..=present.len()) == present.len() {
+                    break;
+                }
+                let idx = *present.choose(rng).unwrap();
+                nibbles.push(idx as u8);
+                node = children.get(idx).unwrap();
+            }
+        }
+    }
+    NibbleSlice::nibbles_to_bytes(&nibbles)
+}
+
+impl MemTries {
+    /// Compares the in-memory trie for `trie.get_root()` against the disk
+    /// trie and, if `trie` has one, its flat storage, on a random sample of
+    /// roughly `num_keys` keys.
+    ///
+    /// Keys are sampled from the in-memory trie, and for every sampled key
+    /// the next keys in both the in-memory and the disk trie are checked too,
+    /// so that keys missing from either side are also caught.
+    pub fn check_consistency(
+        &self,
+        trie: &Trie,
+        num_keys: usize,
+        rng: &mut impl Rng,
+    ) -> Result<MemTrieConsistencyReport, StorageError> {
+        let state_root = *trie.get_root();
+        let mem_root = if state_root == Trie::EMPTY_ROOT {
+            None
+        } else {
+            Some(self.get_root(&state_root).ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Mem-trie for shard {} does not have root {}",
+                    self.shard_uid, state_root
+                ))
+            })?)
+        };
+
+        let mut seeds = vec![vec![]];
+        if let Some(root) = mem_root {
+            seeds.extend((0..num_keys / 2).map(|_| random_key(root, rng)));
+        }
+        let mut keys = BTreeSet::new();
+        for seed in &seeds {
+            let mut mem_iter = MemTrieIterator::new(mem_root);
+            mem_iter.seek_nibble_slice(NibbleSlice::new(seed), false);
+            keys.extend(mem_iter.take(2).map(|(key, _)| key));
+            let mut disk_iter = trie.iter()?;
+            disk_iter.seek_nibble_slice(NibbleSlice::new(seed), false)?;
+            for item in disk_iter.take(2) {
+                keys.insert(item?.0);
+            }
+        }
+
+        let mut report = MemTrieConsistencyReport::default();
+        for key in keys {
+            let mem_trie = mem_root
+                .and_then(|root| memtrie_lookup(root, &key))
+                .map(|value| value.to_value_ref());
+            let disk_trie = trie.get_ref(&key, KeyLookupMode::Trie)?;
+            let flat_storage = match &trie.flat_storage_chunk_view {
+                Some(view) => Some(view.get_value(&key)?.map(|value| value.to_value_ref())),
+                None => None,
+            };
+            report.keys_checked += 1;
+            if mem_trie != disk_trie || flat_storage.as_ref().is_some_and(|flat| *flat != disk_trie)
+            {
+                report.divergences.push(MemTrieDivergence {
+                    key,
+                    mem_trie,
+                    disk_trie,
+                    flat_storage,
+                });
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Runs `MemTries::check_consistency` for the given state root on a
+/// background thread and reports the result, which the thread also returns
+/// unless the check failed. Meant to be called once per epoch; returns
+/// `None` without doing anything if the check is disabled.
+///
+/// The read lock on `mem_tries` is held while the check runs, which is short
+/// for the sample sizes this is intended for.
+pub fn spawn_mem_trie_consistency_check(
+    config: &MemTrieConsistencyCheckConfig,
+    mem_tries: Arc<RwLock<MemTries>>,
+    shard_tries: ShardTries,
+    state_root: StateRoot,
+    block_hash: CryptoHash,
+) -> Option<std::thread::JoinHandle<Option<MemTrieConsistencyReport>>> {
+    if !config.enabled {
+        return None;
+    }
+    let num_keys = config.num_keys;
+    Some(std::thread::spawn(move || {
+        let mem_tries = mem_tries.read().expect(POISONED_LOCK_ERR);
+        let shard_uid = mem_tries.shard_uid();
+        let trie = shard_tries.get_trie_with_block_hash_for_shard(
+            shard_uid,
+            state_root,
+            &block_hash,
+            true,
+        );
+        match mem_tries.check_consistency(&trie, num_keys, &mut rand::thread_rng()) {
+            Ok(report) => {
+                report.report(shard_uid, &state_root, &block_hash);
+                Some(report)
+            }
+            Err(err) => {
+                tracing::warn!(target: "memtrie", %shard_uid, %state_root, %block_hash, ?err, "Mem-trie consistency check failed");
+                None
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::trie::mem::MemTries;
+    use crate::trie::Trie;
+    use near_primitives::shard_layout::ShardUId;
+
+    #[test]
+    fn test_mem_trie_consistency_check() {
+        let shard_uid = ShardUId::single_shard();
+        let mut rng = rand::thread_rng();
+        let tries = create_tries();
+        let mut changes = gen_changes(&mut rng, 50);
+        changes.push((b"always present".to_vec(), Some(b"v".to_vec())));
+        let changes = simplify_changes(&changes);
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.clone());
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let mut mem_tries = MemTries::new(1 << 24, shard_uid);
+        mem_tries.load_root_from_trie(&trie, 0).unwrap();
+
+        let report = mem_tries.check_consistency(&trie, 100, &mut rng).unwrap();
+        assert!(report.keys_checked > 0);
+        assert_eq!(report.divergences, vec![]);
+
+        // Corrupt the in-memory trie by registering a different trie under the
+        // same state root.
+        let other_changes = vec![(b"only in memory".to_vec(), Some(b"x".to_vec()))];
+        let other_root = test_populate_trie(&tries, &root, shard_uid, other_changes);
+        let other_trie = tries.get_trie_for_shard(shard_uid, other_root);
+        let mut corrupted = MemTries::new(1 << 24, shard_uid);
+        corrupted.load_root_from_trie(&other_trie, 0).unwrap();
+        let ids = corrupted.roots.remove(&other_root).unwrap();
+        corrupted.roots.insert(root, ids);
+
+        let report = corrupted.check_consistency(&trie, 100, &mut rng).unwrap();
+        assert!(report
+            .divergences
+            .iter()
+            .any(|d| d.key == b"only in memory" && d.mem_trie.is_some() && d.disk_trie.is_none()));
+    }
+}
@@ -0,0 +1,295 @@
+use super::arena::{Arena, ArenaError};
+use super::node::{InputMemTrieNode, MemTrieNodeId, MemTrieNodePtr, MemTrieNodeView};
+use super::MemTries;
+use crate::trie::nibble_slice::NibbleSlice;
+use borsh::BorshSerialize;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::state::FlatStateValue;
+use near_primitives::types::{BlockHeight, StateRoot};
+
+/// A branch on the path to the last added key, which may still get more
+/// children.
+struct PendingBranch {
+    /// Number of nibbles of the key above the branch.
+    depth: usize,
+    children: [Option<MemTrieNodeId>; 16],
+    /// Value of the key which ends at the branch, if any.
+    value: Option<FlatStateValue>,
+}
+
+/// A finished part of the path to the last added key, which is yet to be
+/// attached to the branch above it.
+enum Subtree {
+    /// The end of the last added key, with its value.
+    Leaf(FlatStateValue),
+    /// A branch at the given depth.
+    Branch(usize, MemTrieNodeId),
+}
+
+/// Constructs a trie in the arena from keys given in increasing order.
+///
+/// Everything to the left of the path to the last added key is final, so only
+/// the branches on that path are kept aside. Once a key diverges from that
+/// path, the branches below the divergence point can't get any more children
+/// and are written to the arena.
+struct TrieConstructor {
+    branches: Vec<PendingBranch>,
+    /// Nibbles and value of the last added key.
+    last: Option<(Vec<u8>, FlatStateValue)>,
+}
+
+impl TrieConstructor {
+    fn new() -> Self {
+        Self { branches: Vec::new(), last: None }
+    }
+
+    fn add_leaf(
+        &mut self,
+        arena: &mut Arena,
+        key: &[u8],
+        value: FlatStateValue,
+    ) -> Result<(), ArenaError> {
+        let nibbles: Vec<u8> = NibbleSlice::new(key).iter().collect();
+        let Some((last_nibbles, last_value)) = self.last.take() else {
+            self.last = Some((nibbles, value));
+            return Ok(());
+        };
+        assert!(last_nibbles < nibbles, "Keys must be added in increasing order");
+        let common = last_nibbles.iter().zip(&nibbles).take_while(|(a, b)| a == b).count();
+        if common == last_nibbles.len() {
+            // The last key is a prefix of the new one, so it ends at a branch
+            // which the new key continues from.
+            self.branches.push(PendingBranch {
+                depth: common,
+                children: [None; 16],
+                value: Some(last_value),
+            });
+        } else {
+            let subtree =
+                self.finish_branches(arena, &last_nibbles, Subtree::Leaf(last_value), common + 1)?;
+            let child = new_node(arena, &last_nibbles, common + 1, subtree)?;
+            match self.branches.last_mut() {
+                Some(branch) if branch.depth == common => {
+                    branch.children[last_nibbles[common] as usize] = Some(child);
+                }
+                _ => {
+                    let mut children = [None; 16];
+                    children[last_nibbles[common] as usize] = Some(child);
+                    self.branches.push(PendingBranch { depth: common, children, value: None });
+                }
+            }
+        }
+        self.last = Some((nibbles, value));
+        Ok(())
+    }
+
+    /// Writes the pending branches at `min_depth` or deeper to the arena,
+    /// attaching `subtree` to the deepest of them, and returns the topmost.
+    /// `nibbles` are the nibbles of the last added key.
+    fn finish_branches(
+        &mut self,
+        arena: &mut Arena,
+        nibbles: &[u8],
+        mut subtree: Subtree,
+        min_depth: usize,
+    ) -> Result<Subtree, ArenaError> {
+        while self.branches.last().map_or(false, |branch| branch.depth >= min_depth) {
+            let mut branch = self.branches.pop().unwrap();
+            let child = new_node(arena, nibbles, branch.depth + 1, subtree)?;
+            branch.children[nibbles[branch.depth] as usize] = Some(child);
+            let input = match branch.value {
+                Some(value) => {
+                    InputMemTrieNode::BranchWithValue { children: branch.children, value }
+                }
+                None => InputMemTrieNode::Branch { children: branch.children },
+            };
+            subtree = Subtree::Branch(branch.depth, MemTrieNodeId::try_new(arena, input)?);
+        }
+        Ok(subtree)
+    }
+
+    /// Writes the rest of the trie to the arena and returns its root, or None
+    /// if no keys were added.
+    fn finalize(mut self, arena: &mut Arena) -> Result<Option<MemTrieNodeId>, ArenaError> {
+        let Some((nibbles, value)) = self.last.take() else {
+            return Ok(None);
+        };
+        let subtree = self.finish_branches(arena, &nibbles, Subtree::Leaf(value), 0)?;
+        new_node(arena, &nibbles, 0, subtree).map(Some)
+    }
+}
+
+/// Creates the node for `subtree` starting at nibble `start` of `nibbles`,
+/// which is the subtree itself or an extension leading to it.
+fn new_node(
+    arena: &mut Arena,
+    nibbles: &[u8],
+    start: usize,
+    subtree: Subtree,
+) -> Result<MemTrieNodeId, ArenaError> {
+    let input = match subtree {
+        Subtree::Leaf(value) => InputMemTrieNode::Leaf {
+            value,
+            extension: NibbleSlice::encode_nibbles(&nibbles[start..], true).to_vec().into(),
+        },
+        Subtree::Branch(depth, node) if depth == start => return Ok(node),
+        Subtree::Branch(depth, node) => InputMemTrieNode::Extension {
+            extension: NibbleSlice::encode_nibbles(&nibbles[start..depth], false).to_vec().into(),
+            child: node,
+        },
+    };
+    MemTrieNodeId::try_new(arena, input)
+}
+
+/// Builds a trie in `MemTries` from key-values added in increasing order of
+/// keys, much faster than applying them as trie changes, since every node is
+/// created exactly once and no node is read from disk.
+pub struct MemTrieBuilder {
+    mem_tries: MemTries,
+    constructor: TrieConstructor,
+}
+
+impl MemTrieBuilder {
+    pub fn new(mem_tries: MemTries) -> Self {
+        Self { mem_tries, constructor: TrieConstructor::new() }
+    }
+
+    /// Adds a key-value to the trie. Panics if the key isn't greater than all
+    /// the keys added before. Fails if the arena reaches the cap of its growth
+    /// policy, after which the builder must be dropped.
+    pub fn add(&mut self, key: &[u8], value: FlatStateValue) -> Result<(), ArenaError> {
+        self.constructor.add_leaf(&mut self.mem_tries.arena, key, value)
+    }
+
+    /// Computes the hashes of the trie and inserts its root at the given
+    /// height. Returns the tries along with the state root.
+    pub fn finish(self, block_height: BlockHeight) -> Result<(MemTries, StateRoot), ArenaError> {
+        let Self { mut mem_tries, constructor } = self;
+        let state_root =
+            mem_tries.construct_root(block_height, |arena| constructor.finalize(arena))?;
+        Ok((mem_tries, state_root))
+    }
+}
+
+/// A node of an in-memory trie encoded the way it's stored in `DBCol::State`.
+pub struct SerializedTrieNode {
+    pub hash: CryptoHash,
+    pub data: Vec<u8>,
+    /// The value of the node, which is stored in `DBCol::State` separately.
+    pub value: Option<FlatStateValue>,
+}
+
+impl MemTries {
+    /// Returns the nodes of the trie with the given state root, encoded the
+    /// way they are stored on disk. Nodes are visited depth first, in the same
+    /// order for all tries with the same contents, and a node shared by
+    /// multiple parents is returned once for each of them, as many times as
+    /// its refcount on disk would be incremented.
+    pub fn iter_serialized_nodes<'a>(
+        &'a self,
+        state_root: &StateRoot,
+    ) -> impl Iterator<Item = SerializedTrieNode> + 'a {
+        let mut stack: Vec<MemTrieNodePtr<'a>> = self.get_root(state_root).into_iter().collect();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let view = node.view();
+            stack.extend(view.iter_children());
+            let value = match &view {
+                MemTrieNodeView::Leaf { value, .. }
+                | MemTrieNodeView::BranchWithValue { value, .. } => Some(value.to_flat_value()),
+                MemTrieNodeView::Extension { .. } | MemTrieNodeView::Branch { .. } => None,
+            };
+            let data = view.to_raw_trie_node_with_size().try_to_vec().unwrap();
+            Some(SerializedTrieNode { hash: hash(&data), data, value })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemTrieBuilder;
+    use crate::config::{ArenaGrowthPolicy, ArenaMemoryConfig};
+    use crate::test_utils::{create_tries, test_populate_trie};
+    use crate::trie::mem::{ArenaError, MemTries};
+    use crate::trie::Trie;
+    use crate::{RawTrieNodeWithSize, TrieDBStorage, TrieStorage};
+    use assert_matches::assert_matches;
+    use borsh::BorshDeserialize;
+    use near_primitives::hash::hash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::FlatStateValue;
+    use rand::Rng;
+    use std::collections::BTreeMap;
+
+    fn check_construction(entries: BTreeMap<Vec<u8>, Vec<u8>>) {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let changes = entries.iter().map(|(key, value)| (key.clone(), Some(value.clone())));
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.collect());
+
+        let mut builder = MemTrieBuilder::new(MemTries::new(1 << 20, shard_uid));
+        for (key, value) in &entries {
+            builder.add(key, FlatStateValue::on_disk(value)).unwrap();
+        }
+        let (mem_tries, state_root) = builder.finish(1).unwrap();
+        assert_eq!(state_root, root);
+
+        // Every serialized node is the same as the one on disk.
+        let storage = TrieDBStorage::new(tries.get_store(), shard_uid);
+        let mut num_values = 0;
+        for node in mem_tries.iter_serialized_nodes(&state_root) {
+            assert_eq!(hash(&node.data), node.hash);
+            assert_eq!(storage.retrieve_raw_bytes(&node.hash).unwrap().as_ref(), &node.data[..]);
+            RawTrieNodeWithSize::try_from_slice(&node.data).unwrap();
+            num_values += node.value.is_some() as usize;
+        }
+        assert_eq!(num_values, entries.len());
+    }
+
+    #[test]
+    fn test_construction() {
+        check_construction(BTreeMap::new());
+        check_construction([(b"a".to_vec(), b"1".to_vec())].into_iter().collect());
+        check_construction(
+            [
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"ab".to_vec(), b"2".to_vec()),
+                (b"abc".to_vec(), vec![3; 5000]),
+                (b"abd".to_vec(), b"4".to_vec()),
+                (b"b".to_vec(), b"5".to_vec()),
+                (b"xyz".to_vec(), b"6".to_vec()),
+                (b"xyzzy".to_vec(), b"7".to_vec()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+    }
+
+    #[test]
+    fn test_construction_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let num_keys = rng.gen_range(1..200);
+            let entries = (0..num_keys)
+                .map(|_| {
+                    let key_len = rng.gen_range(1..6);
+                    let key = (0..key_len).map(|_| rng.gen_range(0..4) * 0x11).collect();
+                    (key, vec![rng.gen(); rng.gen_range(1..10)])
+                })
+                .collect();
+            check_construction(entries);
+        }
+    }
+
+    #[test]
+    fn test_construction_out_of_memory() {
+        let shard_uid = ShardUId::single_shard();
+        let growth = ArenaGrowthPolicy::fixed(1 << 12);
+        let mem_tries =
+            MemTries::new_with_growth_policy(&growth, &ArenaMemoryConfig::default(), shard_uid);
+        let mut builder = MemTrieBuilder::new(mem_tries);
+        let result = (0..1000u32)
+            .try_for_each(|i| builder.add(&i.to_be_bytes(), FlatStateValue::Inlined(vec![1; 10])));
+        assert_matches!(result, Err(ArenaError::OutOfMemory { .. }));
+    }
+}
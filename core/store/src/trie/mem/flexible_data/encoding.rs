@@ -1,4 +1,6 @@
-use crate::trie::mem::arena::{Arena, ArenaPtr, ArenaPtrMut, ArenaSliceMut};
+use crate::trie::mem::arena::{
+    Arena, ArenaError, ArenaPtr, ArenaPtrMut, ArenaSlice, ArenaSliceMut,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 use std::io::Write;
 
@@ -35,9 +37,10 @@ impl<'a> Write for RawEncoder<'a> {
 impl<'a> RawEncoder<'a> {
     /// Creates a new arena allocation of the given size, returning an encoder
     /// that can be used to initialize the allocated memory.
-    pub fn new(arena: &'a mut Arena, n: usize) -> RawEncoder<'a> {
-        let data = arena.alloc(n);
-        RawEncoder { data, pos: 0 }
+    /// Fails if the arena cannot grow enough to fit the allocation.
+    pub fn try_new(arena: &'a mut Arena, n: usize) -> Result<RawEncoder<'a>, ArenaError> {
+        let data = arena.try_alloc(n)?;
+        Ok(RawEncoder { data, pos: 0 })
     }
 
     /// Encodes the given fixed-size field to the current encoder position,
@@ -103,6 +106,15 @@ impl<'a> RawDecoder<'a> {
         self.pos += length;
         view
     }
+
+    /// Like `decode_flexible`, but returns the raw encoded flexibly-sized
+    /// part instead of decoding it.
+    pub fn decode_flexible_raw<T: FlexibleDataHeader>(&mut self, header: &T) -> ArenaSlice<'a> {
+        let length = header.flexible_data_length();
+        let slice = self.data.slice(self.pos, length);
+        self.pos += length;
+        slice
+    }
 }
 
 /// Provides ability to decode, but also to overwrite some data.
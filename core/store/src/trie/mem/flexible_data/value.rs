@@ -1,4 +1,4 @@
-use crate::trie::mem::arena::{ArenaSlice, ArenaSliceMut};
+use crate::trie::mem::arena::{ArenaSlice, ArenaSliceMut, INTERNED_VALUE_HEADER_SIZE};
 
 use super::encoding::BorshFixedSize;
 use super::FlexibleDataHeader;
@@ -6,14 +6,25 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use near_primitives::hash::CryptoHash;
 use near_primitives::state::{FlatStateValue, ValueRef};
 
+/// A value to be encoded into a node: either the value itself, or a
+/// reference to a deduplicated inlined value already interned in the arena
+/// (see `Arena::intern_value`).
+pub enum EncodedValueInput {
+    Value(FlatStateValue),
+    Interned { pos: usize, length: u32 },
+}
+
 /// Flexibly-sized data header for a trie value, representing either an inline
-/// value, or a reference to a value stored in the State column.
+/// value, an interned inline value, or a reference to a value stored in the
+/// State column.
 ///
 /// The flexible part of the data is either the inlined value as a byte array,
-/// or a CryptoHash representing the reference hash.
+/// the position of the interned value in the arena, or a CryptoHash
+/// representing the reference hash.
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize)]
 pub struct EncodedValueHeader {
     // The high bit is 1 if the value is inlined, 0 if it is a reference.
+    // The next bit is 1 if the inlined value is interned.
     // The lower bits are the length of the value.
     length_and_inlined: u32,
 }
@@ -24,60 +35,88 @@ impl BorshFixedSize for EncodedValueHeader {
 
 impl EncodedValueHeader {
     const INLINED_MASK: u32 = 0x80000000;
+    const INTERNED_MASK: u32 = 0x40000000;
 
     fn decode(&self) -> (u32, bool) {
         (
-            self.length_and_inlined & !Self::INLINED_MASK,
+            self.length_and_inlined & !(Self::INLINED_MASK | Self::INTERNED_MASK),
             self.length_and_inlined & Self::INLINED_MASK != 0,
         )
     }
+
+    fn is_interned(&self) -> bool {
+        self.length_and_inlined & Self::INTERNED_MASK != 0
+    }
+
+    /// Returns the arena position of the interned value, if the value is
+    /// interned. `source` must be the flexible data of this header.
+    pub fn interned_pos(&self, source: &ArenaSlice<'_>) -> Option<usize> {
+        self.is_interned().then(|| source.read_ptr_at(0).raw_offset())
+    }
 }
 
 impl FlexibleDataHeader for EncodedValueHeader {
-    type InputData = FlatStateValue;
+    type InputData = EncodedValueInput;
     type View<'a> = ValueView<'a>;
 
-    fn from_input(value: &FlatStateValue) -> Self {
+    fn from_input(value: &EncodedValueInput) -> Self {
         match value {
-            FlatStateValue::Ref(value_ref) => {
-                debug_assert!(value_ref.length < Self::INLINED_MASK);
+            EncodedValueInput::Value(FlatStateValue::Ref(value_ref)) => {
+                debug_assert!(value_ref.length < Self::INTERNED_MASK);
                 EncodedValueHeader { length_and_inlined: value_ref.length }
             }
-            FlatStateValue::Inlined(v) => {
-                assert!(v.len() < Self::INLINED_MASK as usize);
+            EncodedValueInput::Value(FlatStateValue::Inlined(v)) => {
+                assert!(v.len() < Self::INTERNED_MASK as usize);
                 EncodedValueHeader { length_and_inlined: Self::INLINED_MASK | v.len() as u32 }
             }
+            EncodedValueInput::Interned { length, .. } => {
+                assert!(*length < Self::INTERNED_MASK);
+                EncodedValueHeader {
+                    length_and_inlined: Self::INLINED_MASK | Self::INTERNED_MASK | length,
+                }
+            }
         }
     }
 
     fn flexible_data_length(&self) -> usize {
         let (length, inlined) = self.decode();
-        if inlined {
+        if self.is_interned() {
+            std::mem::size_of::<usize>()
+        } else if inlined {
             length as usize
         } else {
             std::mem::size_of::<CryptoHash>()
         }
     }
 
-    fn encode_flexible_data(&self, value: FlatStateValue, target: &mut ArenaSliceMut<'_>) {
+    fn encode_flexible_data(&self, value: EncodedValueInput, target: &mut ArenaSliceMut<'_>) {
         let (length, inlined) = self.decode();
         match value {
-            FlatStateValue::Ref(value_ref) => {
+            EncodedValueInput::Value(FlatStateValue::Ref(value_ref)) => {
                 assert!(!inlined);
                 assert_eq!(length, value_ref.length);
                 target.raw_slice_mut().copy_from_slice(&value_ref.hash.0);
             }
-            FlatStateValue::Inlined(v) => {
-                assert!(inlined);
+            EncodedValueInput::Value(FlatStateValue::Inlined(v)) => {
+                assert!(inlined && !self.is_interned());
                 assert_eq!(length, v.len() as u32);
                 target.raw_slice_mut().copy_from_slice(&v);
             }
+            EncodedValueInput::Interned { pos, length: interned_length } => {
+                assert!(self.is_interned());
+                assert_eq!(length, interned_length);
+                target.write_usize_at(0, pos);
+            }
         }
     }
 
     fn decode_flexible_data<'a>(&self, source: &ArenaSlice<'a>) -> ValueView<'a> {
         let (length, inlined) = self.decode();
-        if inlined {
+        if let Some(pos) = self.interned_pos(source) {
+            ValueView::Inlined(
+                source.ptr().arena().ptr(pos).slice(INTERNED_VALUE_HEADER_SIZE, length as usize),
+            )
+        } else if inlined {
             ValueView::Inlined(source.clone())
         } else {
             ValueView::Ref { length, hash: CryptoHash::try_from_slice(source.raw_slice()).unwrap() }
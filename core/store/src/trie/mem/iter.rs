@@ -0,0 +1,304 @@
+use super::node::{MemTrieNodePtr, MemTrieNodeView};
+use crate::trie::iterator::CrumbStatus;
+use crate::trie::nibble_slice::NibbleSlice;
+use near_primitives::state::FlatStateValue;
+
+/// A piece of the in-memory trie iteration state, analogous to the `Crumb`
+/// used by the disk `TrieIterator`.
+#[derive(Debug)]
+struct Crumb<'a> {
+    node: MemTrieNodePtr<'a>,
+    status: CrumbStatus,
+    prefix_boundary: bool,
+}
+
+impl<'a> Crumb<'a> {
+    fn increment(&mut self) {
+        if self.prefix_boundary {
+            self.status = CrumbStatus::Exiting;
+            return;
+        }
+        self.status = match (self.status, self.node.view()) {
+            (CrumbStatus::Entering, _) => CrumbStatus::At,
+            (
+                CrumbStatus::At,
+                MemTrieNodeView::Branch { .. } | MemTrieNodeView::BranchWithValue { .. },
+            ) => CrumbStatus::AtChild(0),
+            (
+                CrumbStatus::AtChild(x),
+                MemTrieNodeView::Branch { .. } | MemTrieNodeView::BranchWithValue { .. },
+            ) if x < 15 => CrumbStatus::AtChild(x + 1),
+            _ => CrumbStatus::Exiting,
+        }
+    }
+}
+
+/// Iterator over the key/value pairs of an in-memory trie, in key order.
+///
+/// It behaves like the disk `TrieIterator`, including `seek_prefix`, but
+/// never touches the database: values are returned as `FlatStateValue`s, so
+/// inlined values are available directly, and only referenced values need to
+/// be looked up by the caller.
+pub struct MemTrieIterator<'a> {
+    root: Option<MemTrieNodePtr<'a>>,
+    trail: Vec<Crumb<'a>>,
+    key_nibbles: Vec<u8>,
+}
+
+enum IterStep<'a> {
+    Continue,
+    PopTrail,
+    Descend(MemTrieNodePtr<'a>),
+    Value(FlatStateValue),
+}
+
+impl<'a> MemTrieIterator<'a> {
+    /// Creates an iterator over the trie with the given root, or an empty
+    /// iterator if the root is `None` (the empty trie).
+    pub fn new(root: Option<MemTrieNodePtr<'a>>) -> Self {
+        let mut r =
+            Self { root, trail: Vec::with_capacity(8), key_nibbles: Vec::with_capacity(64) };
+        if let Some(root) = root {
+            r.descend_into_node(root);
+        }
+        r
+    }
+
+    /// Position the iterator on the first element with key >= `key`.
+    pub fn seek_prefix<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.seek_nibble_slice(NibbleSlice::new(key.as_ref()), true)
+    }
+
+    pub(crate) fn seek_nibble_slice(&mut self, mut key: NibbleSlice<'_>, is_prefix_seek: bool) {
+        self.trail.clear();
+        self.key_nibbles.clear();
+        // See `TrieIterator::seek_nibble_slice` for the meaning of this check.
+        let check_ext_key = |key: &NibbleSlice, ext_key: &NibbleSlice| {
+            if is_prefix_seek {
+                ext_key.starts_with(key)
+            } else {
+                ext_key >= key
+            }
+        };
+
+        let Some(mut node) = self.root else {
+            return;
+        };
+        let mut prev_prefix_boundary = &mut false;
+        loop {
+            *prev_prefix_boundary = is_prefix_seek;
+            self.descend_into_node(node);
+            let Crumb { status, node: current, prefix_boundary } = self.trail.last_mut().unwrap();
+            prev_prefix_boundary = prefix_boundary;
+            match current.view() {
+                MemTrieNodeView::Leaf { extension, .. } => {
+                    let existing_key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                    if !check_ext_key(&key, &existing_key) {
+                        self.key_nibbles.extend(existing_key.iter());
+                        *status = CrumbStatus::Exiting;
+                    }
+                    break;
+                }
+                MemTrieNodeView::Branch { children, .. }
+                | MemTrieNodeView::BranchWithValue { children, .. } => {
+                    if key.is_empty() {
+                        break;
+                    }
+                    let idx = key.at(0);
+                    self.key_nibbles.push(idx);
+                    *status = CrumbStatus::AtChild(idx);
+                    if let Some(child) = children.get(idx as usize) {
+                        node = child;
+                        key = key.mid(1);
+                    } else {
+                        *prefix_boundary = is_prefix_seek;
+                        break;
+                    }
+                }
+                MemTrieNodeView::Extension { extension, child, .. } => {
+                    let existing_key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                    if key.starts_with(&existing_key) {
+                        key = key.mid(existing_key.len());
+                        node = child;
+                        *status = CrumbStatus::At;
+                        self.key_nibbles.extend(existing_key.iter());
+                    } else {
+                        if !check_ext_key(&key, &existing_key) {
+                            *status = CrumbStatus::Exiting;
+                            self.key_nibbles.extend(existing_key.iter());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn descend_into_node(&mut self, node: MemTrieNodePtr<'a>) {
+        self.trail.push(Crumb { node, status: CrumbStatus::Entering, prefix_boundary: false });
+    }
+
+    fn key(&self) -> Vec<u8> {
+        let mut result = <Vec<u8>>::with_capacity(self.key_nibbles.len() / 2);
+        for i in (1..self.key_nibbles.len()).step_by(2) {
+            result.push(self.key_nibbles[i - 1] * 16 + self.key_nibbles[i]);
+        }
+        result
+    }
+
+    fn iter_step(&mut self) -> Option<IterStep<'a>> {
+        let last = self.trail.last_mut()?;
+        last.increment();
+        Some(match (last.status, last.node.view()) {
+            (CrumbStatus::Exiting, view) => {
+                match view {
+                    MemTrieNodeView::Leaf { extension, .. }
+                    | MemTrieNodeView::Extension { extension, .. } => {
+                        let existing_key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                        let l = self.key_nibbles.len();
+                        self.key_nibbles.truncate(l - existing_key.len());
+                    }
+                    MemTrieNodeView::Branch { .. } | MemTrieNodeView::BranchWithValue { .. } => {
+                        self.key_nibbles.pop();
+                    }
+                }
+                IterStep::PopTrail
+            }
+            (CrumbStatus::At, MemTrieNodeView::BranchWithValue { value, .. }) => {
+                IterStep::Value(value.to_flat_value())
+            }
+            (CrumbStatus::At, MemTrieNodeView::Branch { .. }) => IterStep::Continue,
+            (CrumbStatus::At, MemTrieNodeView::Leaf { extension, value }) => {
+                let key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                self.key_nibbles.extend(key.iter());
+                IterStep::Value(value.to_flat_value())
+            }
+            (CrumbStatus::At, MemTrieNodeView::Extension { extension, child, .. }) => {
+                let key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                self.key_nibbles.extend(key.iter());
+                IterStep::Descend(child)
+            }
+            (
+                CrumbStatus::AtChild(i),
+                MemTrieNodeView::Branch { children, .. }
+                | MemTrieNodeView::BranchWithValue { children, .. },
+            ) => {
+                if i == 0 {
+                    self.key_nibbles.push(0);
+                }
+                if let Some(child) = children.get(i as usize) {
+                    if i != 0 {
+                        *self.key_nibbles.last_mut().expect("Pushed child value before") = i;
+                    }
+                    IterStep::Descend(child)
+                } else {
+                    IterStep::Continue
+                }
+            }
+            _ => panic!("Should never see Entering or AtChild without a Branch here."),
+        })
+    }
+}
+
+impl<'a> Iterator for MemTrieIterator<'a> {
+    type Item = (Vec<u8>, FlatStateValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.iter_step()? {
+                IterStep::Continue => {}
+                IterStep::PopTrail => {
+                    self.trail.pop();
+                }
+                IterStep::Descend(node) => self.descend_into_node(node),
+                IterStep::Value(value) => return Some((self.key(), value)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemTrieIterator;
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::trie::mem::MemTries;
+    use crate::trie::Trie;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::FlatStateValue;
+    use rand::Rng;
+
+    fn check_against_disk_trie(changes: Vec<(Vec<u8>, Option<Vec<u8>>)>, seeks: &[Vec<u8>]) {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let mut mem_tries = MemTries::new(1 << 24, shard_uid);
+        mem_tries.load_root_from_trie(&trie, 0).unwrap();
+
+        let resolve = |(key, value): (Vec<u8>, FlatStateValue)| {
+            let value = match value {
+                FlatStateValue::Inlined(value) => value,
+                FlatStateValue::Ref(value_ref) => trie.retrieve_value(&value_ref.hash).unwrap(),
+            };
+            (key, value)
+        };
+
+        let expected: Vec<_> = trie.iter().unwrap().map(Result::unwrap).collect();
+        let actual: Vec<_> = MemTrieIterator::new(mem_tries.get_root(&root)).map(resolve).collect();
+        assert_eq!(actual, expected);
+
+        for seek in seeks {
+            let mut disk_iter = trie.iter().unwrap();
+            disk_iter.seek_prefix(seek).unwrap();
+            let expected: Vec<_> = disk_iter.map(Result::unwrap).collect();
+            let mut mem_iter = MemTrieIterator::new(mem_tries.get_root(&root));
+            mem_iter.seek_prefix(seek);
+            let actual: Vec<_> = mem_iter.map(resolve).collect();
+            assert_eq!(actual, expected, "seek_prefix({:?})", seek);
+        }
+    }
+
+    #[test]
+    fn test_mem_trie_iterator_basic() {
+        let changes = vec![
+            (b"aaa".to_vec(), Some(b"1".to_vec())),
+            (b"aab".to_vec(), Some(b"2".to_vec())),
+            (b"ab".to_vec(), Some(b"3".to_vec())),
+            (b"abc".to_vec(), Some(vec![4; 5000])),
+            (b"b".to_vec(), Some(b"5".to_vec())),
+            (b"xyz".to_vec(), Some(b"6".to_vec())),
+        ];
+        let seeks = [
+            b"".to_vec(),
+            b"a".to_vec(),
+            b"aa".to_vec(),
+            b"ab".to_vec(),
+            b"abcd".to_vec(),
+            b"c".to_vec(),
+            b"x".to_vec(),
+            b"xz".to_vec(),
+        ];
+        check_against_disk_trie(changes, &seeks);
+    }
+
+    #[test]
+    fn test_mem_trie_iterator_empty() {
+        let mut iter = MemTrieIterator::new(None);
+        assert!(iter.next().is_none());
+        iter.seek_prefix(b"a");
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_mem_trie_iterator_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let changes = simplify_changes(&gen_changes(&mut rng, 30));
+            let seeks: Vec<Vec<u8>> = changes
+                .iter()
+                .map(|(key, _)| key[..rng.gen_range(0..=key.len())].to_vec())
+                .collect();
+            check_against_disk_trie(changes, &seeks);
+        }
+    }
+}
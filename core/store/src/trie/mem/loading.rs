@@ -0,0 +1,109 @@
+use super::arena::Arena;
+use super::node::{InputMemTrieNode, MemTrieNodeId};
+use super::{MemTrieError, MemTries};
+use crate::trie::Trie;
+use crate::{RawTrieNode, StorageError};
+use near_primitives::hash::CryptoHash;
+use near_primitives::state::{FlatStateValue, ValueRef};
+use near_primitives::types::{BlockHeight, StateRoot};
+
+impl MemTries {
+    /// Loads the trie rooted at `trie.get_root()` into memory by reading it
+    /// node by node through the given `Trie`, and registers it as a root at
+    /// the given block height.
+    ///
+    /// This reads every trie node and every value from disk, so it is much
+    /// slower than loading from flat storage; it is mostly useful for tests
+    /// and for verification tooling.
+    pub fn load_root_from_trie(
+        &mut self,
+        trie: &Trie,
+        block_height: BlockHeight,
+    ) -> Result<StateRoot, MemTrieError> {
+        let state_root = *trie.get_root();
+        self.construct_root(block_height, |arena| {
+            if state_root == Trie::EMPTY_ROOT {
+                return Ok(None);
+            }
+            load_subtree_from_trie(trie, arena, &state_root).map(Some)
+        })
+    }
+}
+
+fn load_value_from_trie(trie: &Trie, value_ref: &ValueRef) -> Result<FlatStateValue, StorageError> {
+    let value = trie.retrieve_value(&value_ref.hash)?;
+    Ok(FlatStateValue::on_disk(&value))
+}
+
+pub(super) fn load_subtree_from_trie(
+    trie: &Trie,
+    arena: &mut Arena,
+    hash: &CryptoHash,
+) -> Result<MemTrieNodeId, MemTrieError> {
+    let Some((_, raw_node)) = trie.retrieve_raw_node(hash, false)? else {
+        return Err(StorageError::StorageInconsistentState(format!(
+            "Unexpected empty node {} while loading trie into memory",
+            hash
+        ))
+        .into());
+    };
+    let input = match raw_node.node {
+        RawTrieNode::Leaf(extension, value_ref) => InputMemTrieNode::Leaf {
+            value: load_value_from_trie(trie, &value_ref)?,
+            extension: extension.into_boxed_slice(),
+        },
+        RawTrieNode::Extension(extension, child) => InputMemTrieNode::Extension {
+            extension: extension.into_boxed_slice(),
+            child: load_subtree_from_trie(trie, arena, &child)?,
+        },
+        RawTrieNode::BranchNoValue(children) => {
+            let mut mem_children = [None; 16];
+            for (idx, child) in children.iter() {
+                mem_children[idx as usize] = Some(load_subtree_from_trie(trie, arena, child)?);
+            }
+            InputMemTrieNode::Branch { children: mem_children }
+        }
+        RawTrieNode::BranchWithValue(value_ref, children) => {
+            let mut mem_children = [None; 16];
+            for (idx, child) in children.iter() {
+                mem_children[idx as usize] = Some(load_subtree_from_trie(trie, arena, child)?);
+            }
+            InputMemTrieNode::BranchWithValue {
+                children: mem_children,
+                value: load_value_from_trie(trie, &value_ref)?,
+            }
+        }
+    };
+    Ok(MemTrieNodeId::try_new(arena, input)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{create_tries, test_populate_trie};
+    use crate::trie::mem::MemTries;
+    use crate::trie::Trie;
+    use near_primitives::shard_layout::ShardUId;
+
+    #[test]
+    fn test_load_root_from_trie() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let changes = vec![
+            (b"a".to_vec(), Some(b"1".to_vec())),
+            (b"ab".to_vec(), Some(b"2".to_vec())),
+            (b"abc".to_vec(), Some(vec![3; 5000])),
+            (b"b".to_vec(), Some(b"4".to_vec())),
+            (b"xyz".to_vec(), Some(b"5".to_vec())),
+        ];
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+
+        let mut mem_tries = MemTries::new(1 << 20, shard_uid);
+        assert_eq!(mem_tries.load_root_from_trie(&trie, 1).unwrap(), root);
+        assert!(mem_tries.get_root(&root).is_some());
+
+        let empty = tries.get_trie_for_shard(shard_uid, Trie::EMPTY_ROOT);
+        assert_eq!(mem_tries.load_root_from_trie(&empty, 2).unwrap(), Trie::EMPTY_ROOT);
+        assert_eq!(mem_tries.num_roots(), 1);
+    }
+}
@@ -0,0 +1,115 @@
+use super::node::{MemTrieNodePtr, MemTrieNodeView};
+use super::MemTries;
+use crate::trie::nibble_slice::NibbleSlice;
+use crate::StorageError;
+use near_primitives::state::FlatStateValue;
+use near_primitives::types::StateRoot;
+
+/// Looks up the given key in the in-memory trie with the given root.
+/// Returns the value if the key is present, without touching the database;
+/// referenced values still need to be read from the State column by the
+/// caller.
+pub fn memtrie_lookup(root: MemTrieNodePtr<'_>, key: &[u8]) -> Option<FlatStateValue> {
+    memtrie_lookup_visiting(root, key, |_| {})
+}
+
+/// Like `memtrie_lookup`, but calls `visit` on every node along the path to
+/// the key, starting from the root.
+pub(super) fn memtrie_lookup_visiting<'a>(
+    root: MemTrieNodePtr<'a>,
+    key: &[u8],
+    mut visit: impl FnMut(&MemTrieNodeView<'a>),
+) -> Option<FlatStateValue> {
+    let mut nibbles = NibbleSlice::new(key);
+    let mut node = root;
+    loop {
+        let view = node.view();
+        visit(&view);
+        match view {
+            MemTrieNodeView::Leaf { extension, value } => {
+                let leaf_key = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                return (leaf_key == nibbles).then(|| value.to_flat_value());
+            }
+            MemTrieNodeView::Extension { extension, child, .. } => {
+                let extension = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                if !nibbles.starts_with(&extension) {
+                    return None;
+                }
+                nibbles = nibbles.mid(extension.len());
+                node = child;
+            }
+            MemTrieNodeView::Branch { children, .. } => {
+                if nibbles.is_empty() {
+                    return None;
+                }
+                node = children.get(nibbles.at(0) as usize)?;
+                nibbles = nibbles.mid(1);
+            }
+            MemTrieNodeView::BranchWithValue { children, value, .. } => {
+                if nibbles.is_empty() {
+                    return Some(value.to_flat_value());
+                }
+                node = children.get(nibbles.at(0) as usize)?;
+                nibbles = nibbles.mid(1);
+            }
+        }
+    }
+}
+
+impl MemTries {
+    /// Looks up the given key in the trie with the given state root. Fails if
+    /// the state root is not held by this `MemTries`.
+    pub fn lookup(
+        &self,
+        state_root: &StateRoot,
+        key: &[u8],
+    ) -> Result<Option<FlatStateValue>, StorageError> {
+        if *state_root == StateRoot::default() {
+            return Ok(None);
+        }
+        let Some(root) = self.get_root(state_root) else {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "Mem-trie for shard {} does not have root {}",
+                self.shard_uid, state_root
+            )));
+        };
+        Ok(memtrie_lookup(root, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::trie::mem::MemTries;
+    use crate::trie::Trie;
+    use crate::KeyLookupMode;
+    use near_primitives::shard_layout::ShardUId;
+
+    #[test]
+    fn test_mem_trie_lookup_random() {
+        let shard_uid = ShardUId::single_shard();
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let tries = create_tries();
+            let changes = simplify_changes(&gen_changes(&mut rng, 30));
+            let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.clone());
+            let trie = tries.get_trie_for_shard(shard_uid, root);
+            let mut mem_tries = MemTries::new(1 << 24, shard_uid);
+            mem_tries.load_root_from_trie(&trie, 0).unwrap();
+
+            // Check present keys, and also their prefixes and extensions,
+            // which are mostly absent.
+            for (key, _) in &changes {
+                let mut extended = key.clone();
+                extended.push(0);
+                for key in [key.as_slice(), &key[..key.len() / 2], &extended] {
+                    let expected = trie.get_ref(key, KeyLookupMode::Trie).unwrap();
+                    let actual = mem_tries.lookup(&root, key).unwrap().map(|v| v.to_value_ref());
+                    assert_eq!(actual, expected, "key {:?}", key);
+                }
+            }
+        }
+        let mem_tries = MemTries::new(1 << 16, shard_uid);
+        assert_eq!(mem_tries.lookup(&Trie::EMPTY_ROOT, b"a").unwrap(), None);
+    }
+}
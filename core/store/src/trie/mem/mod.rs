@@ -1,8 +1,288 @@
+use self::arena::Arena;
+use self::node::{MemTrieNodeId, MemTrieNodePtr};
+use crate::config::{ArenaGrowthPolicy, ArenaMemoryConfig};
+use crate::metrics::{MEM_TRIE_ARENA_FRAGMENTATION, MEM_TRIE_ARENA_SIZE, MEM_TRIE_VALUE_DEDUP};
+use crate::StorageError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{BlockHeight, StateRoot};
+use std::collections::{BTreeMap, HashMap};
+
+pub use self::arena::ArenaError;
+pub use self::consistency::{
+    spawn_mem_trie_consistency_check, MemTrieConsistencyReport, MemTrieDivergence,
+};
+pub use self::construction::{MemTrieBuilder, SerializedTrieNode};
+pub use self::iter::MemTrieIterator;
+pub use self::lookup::memtrie_lookup;
+pub use self::snapshot::{MemTrieRecorder, MemTrieSnapshot};
+pub use self::stats::{MemTrieArenaStats, MemTrieCountAndBytes, MemTrieStats};
+
 mod arena;
+mod consistency;
+mod construction;
 mod flexible_data;
+mod iter;
+mod loading;
+mod lookup;
 pub mod node;
+mod persistence;
+mod snapshot;
+mod stats;
+mod updating;
+
+/// Error while building an in-memory trie from storage: either reading the
+/// storage failed, or the arena reached the cap of its growth policy.
+#[derive(thiserror::Error, Debug)]
+pub enum MemTrieError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Arena(#[from] ArenaError),
+}
 
 /// Check this, because in the code we conveniently assume usize is 8 bytes.
 /// In-memory trie can't possibly work under 32-bit anyway.
 #[cfg(not(target_pointer_width = "64"))]
 compile_error!("In-memory trie requires a 64 bit platform");
+
+/// When hashing a trie in parallel, the trie is split into roughly this many
+/// subtrees per thread, so that uneven subtrees still balance out.
+const PARALLEL_HASHING_SUBTREES_PER_THREAD: u64 = 16;
+/// Subtrees smaller than this (in trie memory usage terms) are never split
+/// further for parallel hashing, as the overhead would outweigh the gain.
+const MIN_PARALLEL_HASHING_SUBTREE_MEMORY_USAGE: u64 = 1 << 20;
+
+/// `MemTries` (logically) owns the memory of multiple tries.
+/// Tries may share nodes with each other via refcounting. The way the
+/// refcounting works is that each root node is referenced by `MemTries`
+/// once per `insert_root` call, and each non-root node is referenced by
+/// its parents. When a node's refcount drops to zero, it is deallocated.
+pub struct MemTries {
+    arena: Arena,
+    /// Maps a state root to a list of nodes that have the same root hash.
+    /// The reason why this is a list is because we do not have a node
+    /// deduplication mechanism so we can't guarantee that nodes of the
+    /// same hash are unique. During lookup, any of these nodes can be provided
+    /// as they all have the same hash.
+    roots: HashMap<StateRoot, Vec<MemTrieNodeId>>,
+    /// Maps a block height to the state roots inserted at that height, so
+    /// that old roots can be garbage collected by height. A state root
+    /// appears here once per reference held in `roots`.
+    heights: BTreeMap<BlockHeight, Vec<StateRoot>>,
+    /// Shard UID, for logging and persistence.
+    shard_uid: ShardUId,
+    /// Number of outstanding `MemTrieSnapshot`s, each holding a reference to
+    /// its root node.
+    num_snapshots: usize,
+}
+
+impl MemTries {
+    pub fn new(arena_size_in_bytes: usize, shard_uid: ShardUId) -> Self {
+        Self::new_with_arena_config(arena_size_in_bytes, &ArenaMemoryConfig::default(), shard_uid)
+    }
+
+    /// Like `new`, but with the given memory placement for the arena, which is
+    /// normally `StoreConfig::mem_trie_arena.for_shard(shard_uid)`.
+    pub fn new_with_arena_config(
+        arena_size_in_bytes: usize,
+        arena_config: &ArenaMemoryConfig,
+        shard_uid: ShardUId,
+    ) -> Self {
+        Self::new_with_growth_policy(
+            &ArenaGrowthPolicy::fixed(arena_size_in_bytes),
+            arena_config,
+            shard_uid,
+        )
+    }
+
+    /// Creates `MemTries` whose arena grows according to the given policy,
+    /// normally `StoreConfig::mem_trie_arena.growth`.
+    pub fn new_with_growth_policy(
+        growth: &ArenaGrowthPolicy,
+        arena_config: &ArenaMemoryConfig,
+        shard_uid: ShardUId,
+    ) -> Self {
+        let tries = Self {
+            arena: Arena::new_with_growth_policy(growth, arena_config),
+            roots: HashMap::new(),
+            heights: BTreeMap::new(),
+            shard_uid,
+            num_snapshots: 0,
+        };
+        tries.report_metrics();
+        tries
+    }
+
+    /// Constructs a root node using the given closure, which is expected to
+    /// build the whole trie in the arena and return its root (or None if the
+    /// trie is empty). Hashes are computed for the returned root, and the
+    /// root is then inserted into the set of roots at the given height.
+    pub fn construct_root<Error>(
+        &mut self,
+        block_height: BlockHeight,
+        f: impl FnOnce(&mut Arena) -> Result<Option<MemTrieNodeId>, Error>,
+    ) -> Result<StateRoot, Error> {
+        let root = f(&mut self.arena)?;
+        Ok(match root {
+            Some(root) => self.insert_root(root, block_height),
+            None => CryptoHash::default(),
+        })
+    }
+
+    /// Computes the hash of the given node if needed, and then registers it
+    /// as a root at the given block height, incrementing its refcount.
+    /// Returns the state root.
+    ///
+    /// Hashing is done in parallel for large tries; only nodes that do not
+    /// have their hashes computed yet are visited, so inserting a root that
+    /// shares most of its nodes with existing roots is cheap.
+    pub(crate) fn insert_root(
+        &mut self,
+        root: MemTrieNodeId,
+        block_height: BlockHeight,
+    ) -> StateRoot {
+        let memory_usage = root.as_ptr(self.arena.memory()).view().memory_usage();
+        let threshold = std::cmp::max(
+            memory_usage
+                / (rayon::current_num_threads() as u64 * PARALLEL_HASHING_SUBTREES_PER_THREAD),
+            MIN_PARALLEL_HASHING_SUBTREE_MEMORY_USAGE,
+        );
+        root.as_ptr_mut(self.arena.memory_mut()).compute_hash_recursively_parallel(threshold);
+        let state_root = root.as_ptr(self.arena.memory()).view().node_hash();
+        root.add_ref(&mut self.arena);
+        self.roots.entry(state_root).or_default().push(root);
+        self.heights.entry(block_height).or_default().push(state_root);
+        self.report_metrics();
+        state_root
+    }
+
+    /// Removes one reference to the given state root, deallocating nodes that
+    /// are no longer referenced. Returns the number of bytes reclaimed.
+    ///
+    /// This does not update `heights`, so outside of tests roots are only
+    /// deleted via `delete_until_height`.
+    fn delete_root(&mut self, state_root: &StateRoot) -> usize {
+        let Some(ids) = self.roots.get_mut(state_root) else {
+            tracing::warn!(target: "memtrie", shard_uid=%self.shard_uid, %state_root, "Attempted to delete unknown root");
+            return 0;
+        };
+        let root = ids.pop().unwrap();
+        if ids.is_empty() {
+            self.roots.remove(state_root);
+        }
+        root.remove_ref(&mut self.arena)
+    }
+
+    /// Deletes all roots inserted at heights below the given height, which is
+    /// normally the GC stop height of the chain. Returns the number of bytes
+    /// of arena memory reclaimed.
+    pub fn delete_until_height(&mut self, height: BlockHeight) -> usize {
+        let retained = self.heights.split_off(&height);
+        let deleted = std::mem::replace(&mut self.heights, retained);
+        let mut reclaimed = 0;
+        for state_root in deleted.into_values().flatten() {
+            reclaimed += self.delete_root(&state_root);
+        }
+        self.report_metrics();
+        reclaimed
+    }
+
+    /// Returns the root node corresponding to the given state root, if any.
+    pub fn get_root<'a>(&'a self, state_root: &StateRoot) -> Option<MemTrieNodePtr<'a>> {
+        self.roots.get(state_root).map(|ids| ids[0].as_ptr(self.arena.memory()))
+    }
+
+    pub fn num_roots(&self) -> usize {
+        self.roots.values().map(|ids| ids.len()).sum()
+    }
+
+    pub fn shard_uid(&self) -> ShardUId {
+        self.shard_uid
+    }
+
+    /// Updates the gauges for the arena size and fragmentation and the node
+    /// statistics, all of which are kept up to date by the arena.
+    fn report_metrics(&self) {
+        let shard_uid = self.shard_uid.to_string();
+        for (kind, size) in [
+            ("allocated", self.arena.allocated_size()),
+            ("used", self.arena.used_size()),
+            ("free", self.arena.free_size()),
+            ("max", self.arena.max_size()),
+        ] {
+            MEM_TRIE_ARENA_SIZE.with_label_values(&[&shard_uid, kind]).set(size as i64);
+        }
+        MEM_TRIE_ARENA_FRAGMENTATION
+            .with_label_values(&[&shard_uid])
+            .set(self.arena_stats().fragmentation());
+        self.stats().report_metrics(self.shard_uid);
+        if let Some(stats) = self.arena.value_interner_stats() {
+            for (kind, value) in
+                [("hits", stats.hits), ("misses", stats.misses), ("saved_bytes", stats.saved_bytes)]
+            {
+                MEM_TRIE_VALUE_DEDUP.with_label_values(&[&shard_uid, kind]).set(value as i64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArenaError, MemTries};
+    use crate::config::ArenaGrowthPolicy;
+    use crate::flat::FlatStorageManager;
+    use crate::test_utils::{create_test_store, create_tries, test_populate_trie};
+    use crate::trie::{ShardTries, Trie, TrieConfig};
+    use assert_matches::assert_matches;
+    use near_primitives::shard_layout::ShardUId;
+
+    #[test]
+    fn test_delete_until_height() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let mut mem_tries = MemTries::new(1 << 20, shard_uid);
+        let mut root = Trie::EMPTY_ROOT;
+        let mut roots = Vec::new();
+        for height in 1..=5u64 {
+            let key = format!("key{}", height).into_bytes();
+            root = test_populate_trie(&tries, &root, shard_uid, vec![(key, Some(vec![1, 2, 3]))]);
+            mem_tries
+                .load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root), height)
+                .unwrap();
+            roots.push(root);
+        }
+        assert_eq!(mem_tries.num_roots(), 5);
+
+        assert_eq!(mem_tries.delete_until_height(1), 0);
+        assert!(mem_tries.delete_until_height(4) > 0);
+        assert_eq!(mem_tries.num_roots(), 2);
+        assert!(mem_tries.get_root(&roots[2]).is_none());
+        assert!(mem_tries.get_root(&roots[3]).is_some());
+
+        let reclaimed = mem_tries.delete_until_height(10);
+        assert!(reclaimed > 0);
+        assert_eq!(mem_tries.num_roots(), 0);
+        assert_eq!(mem_tries.arena.free_size(), mem_tries.arena.used_size());
+    }
+
+    #[test]
+    fn test_new_mem_tries_growth_policy() {
+        let shard_uid = ShardUId::single_shard();
+        let store = create_test_store();
+        let mut trie_config = TrieConfig::default();
+        trie_config.mem_trie_arena.growth = ArenaGrowthPolicy::fixed(1 << 16);
+        let tries = ShardTries::new(
+            store.clone(),
+            trie_config,
+            &[shard_uid],
+            FlatStorageManager::new(store),
+        );
+        let mut mem_tries = tries.new_mem_tries(shard_uid);
+        assert!(mem_tries.arena.try_alloc(1 << 10).is_ok());
+        assert_matches!(
+            mem_tries.arena.try_alloc(1 << 16).err(),
+            Some(ArenaError::OutOfMemory { max_size, .. }) if max_size == 1 << 16
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use super::{InputMemTrieNode, MemTrieNodeId, MemTrieNodePtr, MemTrieNodeView};
-use crate::trie::mem::arena::Arena;
+use crate::trie::mem::arena::{Arena, ArenaError};
 use crate::trie::mem::flexible_data::children::EncodedChildrenHeader;
 use crate::trie::mem::flexible_data::encoding::{BorshFixedSize, RawDecoder, RawEncoder};
 use crate::trie::mem::flexible_data::extension::EncodedExtensionHeader;
-use crate::trie::mem::flexible_data::value::EncodedValueHeader;
+use crate::trie::mem::flexible_data::value::{EncodedValueHeader, EncodedValueInput};
 use crate::trie::mem::flexible_data::FlexibleDataHeader;
 use crate::trie::TRIE_COSTS;
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -102,29 +102,44 @@ impl BorshFixedSize for BranchWithValueHeader {
         + EncodedChildrenHeader::SERIALIZED_SIZE;
 }
 
-impl MemTrieNodeId {
-    /// Encodes the data.
-    pub(crate) fn new_impl(arena: &mut Arena, node: InputMemTrieNode) -> Self {
-        // We add reference to all the children when creating the node.
-        // As for the refcount of this newly created node, it starts at 0.
-        // It is expected that either our parent will increment our own
-        // refcount when it is created, or that this node is a root node,
-        // and the refcount will be incremented by `MemTries`.
-        match &node {
-            InputMemTrieNode::Extension { child, .. } => {
-                child.add_ref(arena);
-            }
-            InputMemTrieNode::Branch { children }
-            | InputMemTrieNode::BranchWithValue { children, .. } => {
-                for child in children {
-                    if let Some(child) = child {
-                        child.add_ref(arena);
-                    }
-                }
-            }
-            _ => {}
+/// Prepares a value for encoding, interning it if it is an inlined value that
+/// the arena deduplicates.
+fn encoded_value_input(
+    arena: &mut Arena,
+    value: FlatStateValue,
+) -> Result<EncodedValueInput, ArenaError> {
+    match value {
+        FlatStateValue::Inlined(value) if arena.should_intern(value.len()) => {
+            let pos = arena.intern_value(&value)?;
+            Ok(EncodedValueInput::Interned { pos, length: value.len() as u32 })
         }
-        // Let's also compute the memory usage of the node. We only do this for
+        value => Ok(EncodedValueInput::Value(value)),
+    }
+}
+
+fn interned_pos(value: &EncodedValueInput) -> Option<usize> {
+    match value {
+        EncodedValueInput::Interned { pos, .. } => Some(*pos),
+        EncodedValueInput::Value(_) => None,
+    }
+}
+
+/// Undoes `encoded_value_input` when the node could not be allocated.
+fn release_interned(arena: &mut Arena, interned: Option<usize>, err: ArenaError) -> ArenaError {
+    if let Some(pos) = interned {
+        arena.release_interned_value(pos);
+    }
+    err
+}
+
+impl MemTrieNodeId {
+    /// Encodes the data. Fails without any side effects if the arena is out
+    /// of memory.
+    pub(crate) fn try_new_impl(
+        arena: &mut Arena,
+        node: InputMemTrieNode,
+    ) -> Result<Self, ArenaError> {
+        // Let's compute the memory usage of the node. We only do this for
         // non-leaf nodes, because for leaf node it is very easy to just
         // compute it on demand, so there's no need to store it.
         let memory_usage = match &node {
@@ -159,18 +174,30 @@ impl MemTrieNodeId {
                 memory_usage
             }
         };
-        // Finally, encode the data. We're still leaving the hash empty; that
-        // will be computed later in parallel.
-        let data = match node {
+        let children_to_ref: Vec<MemTrieNodeId> = match &node {
+            InputMemTrieNode::Leaf { .. } => Vec::new(),
+            InputMemTrieNode::Extension { child, .. } => vec![*child],
+            InputMemTrieNode::Branch { children }
+            | InputMemTrieNode::BranchWithValue { children, .. } => {
+                children.iter().flatten().copied().collect()
+            }
+        };
+        // Encode the data. We're still leaving the hash empty; that will be
+        // computed later in parallel. If the value is interned but the node
+        // itself cannot be allocated, the interned reference is released.
+        let pos = match node {
             InputMemTrieNode::Leaf { value, extension } => {
+                let value = encoded_value_input(arena, value)?;
+                let interned = interned_pos(&value);
                 let extension_header = EncodedExtensionHeader::from_input(&extension);
                 let value_header = EncodedValueHeader::from_input(&value);
-                let mut data = RawEncoder::new(
-                    arena,
-                    LeafHeader::SERIALIZED_SIZE
-                        + extension_header.flexible_data_length()
-                        + value_header.flexible_data_length(),
-                );
+                let size = LeafHeader::SERIALIZED_SIZE
+                    + extension_header.flexible_data_length()
+                    + value_header.flexible_data_length();
+                let mut data = match RawEncoder::try_new(arena, size) {
+                    Ok(data) => data,
+                    Err(err) => return Err(release_interned(arena, interned, err)),
+                };
                 data.encode(LeafHeader {
                     common: CommonHeader { refcount: 0, kind: NodeKind::Leaf },
                     extension: extension_header,
@@ -178,14 +205,14 @@ impl MemTrieNodeId {
                 });
                 data.encode_flexible(&extension_header, extension);
                 data.encode_flexible(&value_header, value);
-                data.finish()
+                data.finish().raw_offset()
             }
             InputMemTrieNode::Extension { extension, child } => {
                 let extension_header = EncodedExtensionHeader::from_input(&extension);
-                let mut data = RawEncoder::new(
+                let mut data = RawEncoder::try_new(
                     arena,
                     ExtensionHeader::SERIALIZED_SIZE + extension_header.flexible_data_length(),
-                );
+                )?;
                 data.encode(ExtensionHeader {
                     common: CommonHeader { refcount: 0, kind: NodeKind::Extension },
                     nonleaf: NonLeafHeader::new(memory_usage),
@@ -193,31 +220,34 @@ impl MemTrieNodeId {
                     extension: extension_header,
                 });
                 data.encode_flexible(&extension_header, extension);
-                data.finish()
+                data.finish().raw_offset()
             }
             InputMemTrieNode::Branch { children } => {
                 let children_header = EncodedChildrenHeader::from_input(&children);
-                let mut data = RawEncoder::new(
+                let mut data = RawEncoder::try_new(
                     arena,
                     BranchHeader::SERIALIZED_SIZE + children_header.flexible_data_length(),
-                );
+                )?;
                 data.encode(BranchHeader {
                     common: CommonHeader { refcount: 0, kind: NodeKind::Branch },
                     nonleaf: NonLeafHeader::new(memory_usage),
                     children: children_header,
                 });
                 data.encode_flexible(&children_header, children);
-                data.finish()
+                data.finish().raw_offset()
             }
             InputMemTrieNode::BranchWithValue { children, value } => {
+                let value = encoded_value_input(arena, value)?;
+                let interned = interned_pos(&value);
                 let children_header = EncodedChildrenHeader::from_input(&children);
                 let value_header = EncodedValueHeader::from_input(&value);
-                let mut data = RawEncoder::new(
-                    arena,
-                    BranchWithValueHeader::SERIALIZED_SIZE
-                        + children_header.flexible_data_length()
-                        + value_header.flexible_data_length(),
-                );
+                let size = BranchWithValueHeader::SERIALIZED_SIZE
+                    + children_header.flexible_data_length()
+                    + value_header.flexible_data_length();
+                let mut data = match RawEncoder::try_new(arena, size) {
+                    Ok(data) => data,
+                    Err(err) => return Err(release_interned(arena, interned, err)),
+                };
                 data.encode(BranchWithValueHeader {
                     common: CommonHeader { refcount: 0, kind: NodeKind::BranchWithValue },
                     nonleaf: NonLeafHeader::new(memory_usage),
@@ -226,10 +256,19 @@ impl MemTrieNodeId {
                 });
                 data.encode_flexible(&children_header, children);
                 data.encode_flexible(&value_header, value);
-                data.finish()
+                data.finish().raw_offset()
             }
         };
-        Self { pos: data.raw_offset() }
+        // We add reference to all the children when creating the node.
+        // As for the refcount of this newly created node, it starts at 0.
+        // It is expected that either our parent will increment our own
+        // refcount when it is created, or that this node is a root node,
+        // and the refcount will be incremented by `MemTries`.
+        for child in children_to_ref.iter() {
+            child.add_ref(arena);
+        }
+        arena.record_node_alloc(Self { pos });
+        Ok(Self { pos })
     }
 
     /// Increments the refcount.
@@ -242,7 +281,8 @@ impl MemTrieNodeId {
     }
 
     /// Decrements the refcount, deallocating the node if it reaches zero.
-    pub(crate) fn remove_ref(&self, arena: &mut Arena) {
+    /// Returns the number of bytes deallocated, including descendants.
+    pub(crate) fn remove_ref(&self, arena: &mut Arena) -> usize {
         let mut ptr = self.as_ptr_mut(arena.memory_mut());
         let mut decoder = ptr.decoder_mut();
         let mut header = decoder.peek::<CommonHeader>();
@@ -256,10 +296,18 @@ impl MemTrieNodeId {
                 children_to_unref.push(child.id().pos);
             }
             let alloc_size = node_ptr.size_of_allocation();
-            arena.dealloc(self.pos, alloc_size);
+            let interned_value = node_ptr.interned_value_pos();
+            arena.record_node_dealloc(*self);
+            let mut freed = arena.dealloc(self.pos, alloc_size);
+            if let Some(pos) = interned_value {
+                freed += arena.release_interned_value(pos);
+            }
             for child in children_to_unref.iter() {
-                MemTrieNodeId { pos: *child }.remove_ref(arena);
+                freed += MemTrieNodeId { pos: *child }.remove_ref(arena);
             }
+            freed
+        } else {
+            0
         }
     }
 }
@@ -315,7 +363,7 @@ impl<'a> MemTrieNodePtr<'a> {
 
     /// Calculates the size of the allocation with only a pointer to the start
     /// of the trie node's allocation.
-    fn size_of_allocation(&self) -> usize {
+    pub(crate) fn size_of_allocation(&self) -> usize {
         let mut decoder = self.decoder();
         let kind = decoder.peek::<CommonHeader>().kind;
         match kind {
@@ -341,4 +389,50 @@ impl<'a> MemTrieNodePtr<'a> {
             }
         }
     }
+
+    /// Checks that the node lies entirely within the first `used_size` bytes
+    /// of the arena and has a valid kind, so that it can be decoded without
+    /// panicking. Used for nodes read from a file; children and interned
+    /// values are not checked.
+    pub(crate) fn check_bounds(&self, used_size: usize) -> Result<(), String> {
+        let pos = self.ptr.raw_offset();
+        let fits = |len: usize| pos.checked_add(len).map_or(false, |end| end <= used_size);
+        if !fits(CommonHeader::SERIALIZED_SIZE) {
+            return Err(format!("Node at {} is outside of the arena", pos));
+        }
+        let kind = self.ptr.slice(std::mem::size_of::<u32>(), 1).raw_slice()[0];
+        let header_size = match kind {
+            0 => LeafHeader::SERIALIZED_SIZE,
+            1 => ExtensionHeader::SERIALIZED_SIZE,
+            2 => BranchHeader::SERIALIZED_SIZE,
+            3 => BranchWithValueHeader::SERIALIZED_SIZE,
+            _ => return Err(format!("Node at {} has invalid kind {}", pos, kind)),
+        };
+        if !fits(header_size) || !fits(self.size_of_allocation()) {
+            return Err(format!("Node at {} extends past the end of the arena", pos));
+        }
+        Ok(())
+    }
+
+    /// Returns the arena position of the node's value, if the value is an
+    /// interned inlined value (see `Arena::intern_value`).
+    pub(crate) fn interned_value_pos(&self) -> Option<usize> {
+        let mut decoder = self.decoder();
+        let kind = decoder.peek::<CommonHeader>().kind;
+        match kind {
+            NodeKind::Leaf => {
+                let header = decoder.decode::<LeafHeader>();
+                decoder.decode_flexible_raw(&header.extension);
+                let value = decoder.decode_flexible_raw(&header.value);
+                header.value.interned_pos(&value)
+            }
+            NodeKind::BranchWithValue => {
+                let header = decoder.decode::<BranchWithValueHeader>();
+                decoder.decode_flexible_raw(&header.children);
+                let value = decoder.decode_flexible_raw(&header.value);
+                header.value.interned_pos(&value)
+            }
+            NodeKind::Extension | NodeKind::Branch => None,
+        }
+    }
 }
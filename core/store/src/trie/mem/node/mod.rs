@@ -6,7 +6,7 @@ mod mutation;
 mod tests;
 mod view;
 
-use super::arena::{Arena, ArenaMemory, ArenaPtr, ArenaPtrMut, ArenaSlice};
+use super::arena::{Arena, ArenaError, ArenaMemory, ArenaPtr, ArenaPtrMut, ArenaSlice};
 use super::flexible_data::children::ChildrenView;
 use super::flexible_data::value::ValueView;
 use near_primitives::hash::CryptoHash;
@@ -29,7 +29,13 @@ pub struct MemTrieNodeId {
 
 impl MemTrieNodeId {
     pub fn new(arena: &mut Arena, input: InputMemTrieNode) -> Self {
-        Self::new_impl(arena, input)
+        Self::try_new_impl(arena, input).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `new`, but fails instead of panicking if the arena has reached
+    /// the cap of its growth policy.
+    pub fn try_new(arena: &mut Arena, input: InputMemTrieNode) -> Result<Self, ArenaError> {
+        Self::try_new_impl(arena, input)
     }
 
     pub fn as_ptr<'a>(&self, arena: &'a ArenaMemory) -> MemTrieNodePtr<'a> {
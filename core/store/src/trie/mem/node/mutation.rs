@@ -5,12 +5,19 @@ use super::encoding::{CommonHeader, NodeKind, NonLeafHeader};
 use super::{MemTrieNodePtr, MemTrieNodePtrMut};
 use borsh::BorshSerialize;
 use near_primitives::hash::{hash, CryptoHash};
+use rayon::prelude::*;
 
 impl<'a> MemTrieNodePtrMut<'a> {
     fn as_const<'b>(&'b self) -> MemTrieNodePtr<'b> {
         MemTrieNodePtr { ptr: self.ptr.ptr() }
     }
 
+    /// Makes a shorter-lived mutable copy of the pointer, so that consuming
+    /// methods can be called without giving up this pointer.
+    fn reborrow<'b>(&'b mut self) -> MemTrieNodePtrMut<'b> {
+        MemTrieNodePtrMut { ptr: self.ptr.ptr_mut() }
+    }
+
     pub(crate) fn decoder_mut<'b>(&'b mut self) -> RawDecoderMut<'b> {
         RawDecoderMut::new(self.ptr.ptr_mut())
     }
@@ -85,15 +92,33 @@ impl<'a> MemTrieNodePtrMut<'a> {
         self.compute_hash();
     }
 
+    /// Like `compute_hash_recursively`, but first splits the subtree into
+    /// disjoint subtrees whose memory usage is below the given threshold, and
+    /// hashes those in parallel on the rayon thread pool. The remaining nodes
+    /// near the top are then hashed sequentially. Subtrees whose hashes are
+    /// already computed are skipped entirely.
+    pub(crate) fn compute_hash_recursively_parallel(&mut self, threshold_memory_usage: u64) {
+        if self.is_hash_computed() {
+            return;
+        }
+        let mut subtrees = Vec::new();
+        self.reborrow().take_small_subtrees(threshold_memory_usage, &mut subtrees);
+        subtrees.into_par_iter().for_each(|mut subtree| subtree.compute_hash_recursively());
+        self.compute_hash_recursively();
+    }
+
     /// Recursively expand the current subtree until we arrive at subtrees
     /// that are small enough (by memory usage); we store these subtrees in
     /// the provided vector. The returned subtrees cover all leaves but are
-    /// disjoint.
+    /// disjoint. Subtrees whose hashes are already computed are omitted.
     pub(crate) fn take_small_subtrees(
         self,
         threshold_memory_usage: u64,
         trees: &mut Vec<MemTrieNodePtrMut<'a>>,
     ) {
+        if self.is_hash_computed() {
+            return;
+        }
         if self.as_const().view().memory_usage() < threshold_memory_usage {
             trees.push(self);
         } else {
@@ -1,10 +1,14 @@
+use crate::test_utils::{create_tries, test_populate_trie};
 use crate::trie::mem::arena::Arena;
-use crate::trie::mem::node::{InputMemTrieNode, MemTrieNodeId, MemTrieNodeView};
-use crate::trie::Children;
+use crate::trie::mem::node::{InputMemTrieNode, MemTrieNodeId, MemTrieNodePtr, MemTrieNodeView};
+use crate::trie::nibble_slice::NibbleSlice;
+use crate::trie::{Children, Trie};
 use crate::{RawTrieNode, RawTrieNodeWithSize};
 use borsh::BorshSerialize;
 use near_primitives::hash::hash;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::state::{FlatStateValue, ValueRef};
+use std::collections::BTreeMap;
 
 #[test]
 fn test_basic_leaf_node_inlined() {
@@ -296,3 +300,244 @@ fn test_basic_branch_with_value_node() {
         _ => panic!("Unexpected view type: {:?}", node_ptr.view()),
     }
 }
+
+/// Builds a complete 16-ary trie of the given depth, where each branch also
+/// holds a value, returning the root node.
+fn build_full_trie(arena: &mut Arena, depth: usize, seed: u8) -> MemTrieNodeId {
+    if depth == 0 {
+        return MemTrieNodeId::new(
+            arena,
+            InputMemTrieNode::Leaf {
+                extension: vec![seed].into_boxed_slice(),
+                value: FlatStateValue::Inlined(vec![seed; 10]),
+            },
+        );
+    }
+    let mut children = [None; 16];
+    for (i, child) in children.iter_mut().enumerate() {
+        *child =
+            Some(build_full_trie(arena, depth - 1, seed.wrapping_mul(16).wrapping_add(i as u8)));
+    }
+    MemTrieNodeId::new(
+        arena,
+        InputMemTrieNode::BranchWithValue { children, value: FlatStateValue::Inlined(vec![seed]) },
+    )
+}
+
+#[test]
+fn test_parallel_hashing_matches_sequential() {
+    let mut arena = Arena::new(1 << 24);
+    let sequential = build_full_trie(&mut arena, 3, 1);
+    let parallel = build_full_trie(&mut arena, 3, 1);
+    sequential.as_ptr_mut(arena.memory_mut()).compute_hash_recursively();
+    // Use a small threshold so that the trie is actually split up.
+    parallel.as_ptr_mut(arena.memory_mut()).compute_hash_recursively_parallel(1000);
+    let expected = sequential.as_ptr(arena.memory()).view().node_hash();
+    assert_eq!(parallel.as_ptr(arena.memory()).view().node_hash(), expected);
+
+    // Hashing again is a no-op, and a new root on top of an already hashed
+    // subtree only needs to hash the new node.
+    parallel.as_ptr_mut(arena.memory_mut()).compute_hash_recursively_parallel(1000);
+    assert_eq!(parallel.as_ptr(arena.memory()).view().node_hash(), expected);
+    let extension = MemTrieNodeId::new(
+        &mut arena,
+        InputMemTrieNode::Extension { extension: vec![0].into_boxed_slice(), child: parallel },
+    );
+    extension.as_ptr_mut(arena.memory_mut()).compute_hash_recursively_parallel(1000);
+    let extension_view = extension.as_ptr(arena.memory()).view();
+    assert_eq!(
+        extension_view.node_hash(),
+        hash(&extension_view.to_raw_trie_node_with_size().try_to_vec().unwrap())
+    );
+}
+
+#[test]
+fn test_dedup_inlined_values() {
+    let config =
+        crate::config::ArenaMemoryConfig { dedup_inlined_values: true, ..Default::default() };
+    let mut arena = Arena::new_with_config(1 << 16, &config);
+    let mut plain_arena = Arena::new(1 << 16);
+    let value = FlatStateValue::Inlined(vec![42; 200]);
+    let leaf = |extension: Vec<u8>| InputMemTrieNode::Leaf {
+        extension: extension.into_boxed_slice(),
+        value: value.clone(),
+    };
+
+    let mut nodes = vec![];
+    for extension in [vec![0x31, 1], vec![0x32, 2]] {
+        let node = MemTrieNodeId::new(&mut arena, leaf(extension.clone()));
+        let plain_node = MemTrieNodeId::new(&mut plain_arena, leaf(extension));
+        node.as_ptr_mut(arena.memory_mut()).compute_hash_recursively();
+        plain_node.as_ptr_mut(plain_arena.memory_mut()).compute_hash_recursively();
+        let view = node.as_ptr(arena.memory()).view();
+        let plain_view = plain_node.as_ptr(plain_arena.memory()).view();
+        assert_eq!(view.to_raw_trie_node_with_size(), plain_view.to_raw_trie_node_with_size());
+        assert_eq!(view.node_hash(), plain_view.node_hash());
+        assert!(node.as_ptr(arena.memory()).interned_value_pos().is_some());
+        nodes.push(node);
+    }
+    let stats = arena.value_interner_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.saved_bytes), (1, 1, 200));
+    assert!(plain_arena.value_interner_stats().is_none());
+
+    // Small values are not worth interning.
+    let small = MemTrieNodeId::new(
+        &mut arena,
+        InputMemTrieNode::Leaf {
+            extension: vec![0x20].into_boxed_slice(),
+            value: FlatStateValue::Inlined(vec![1; 100]),
+        },
+    );
+    assert!(small.as_ptr(arena.memory()).interned_value_pos().is_none());
+    nodes.push(small);
+
+    // Releasing all nodes also releases the interned value.
+    for node in nodes {
+        node.add_ref(&mut arena);
+        node.remove_ref(&mut arena);
+    }
+    assert_eq!(arena.free_size(), arena.used_size());
+    assert_eq!(arena.value_interner_stats().unwrap().saved_bytes, 0);
+}
+
+/// Builds the in-memory trie for the given entries, whose keys are given as
+/// nibbles and are sorted and unique, independently of the disk trie
+/// implementation. `depth` is the number of nibbles already consumed.
+fn build_trie_from_entries(
+    arena: &mut Arena,
+    entries: &[(Vec<u8>, FlatStateValue)],
+    depth: usize,
+) -> MemTrieNodeId {
+    if let [(key, value)] = entries {
+        let extension = NibbleSlice::encode_nibbles(&key[depth..], true);
+        return MemTrieNodeId::new(
+            arena,
+            InputMemTrieNode::Leaf {
+                extension: extension.to_vec().into_boxed_slice(),
+                value: value.clone(),
+            },
+        );
+    }
+    let first = &entries[0].0;
+    let common_len = entries
+        .iter()
+        .map(|(key, _)| {
+            key[depth..].iter().zip(&first[depth..]).take_while(|(a, b)| a == b).count()
+        })
+        .min()
+        .unwrap();
+    if common_len > 0 {
+        let extension = NibbleSlice::encode_nibbles(&first[depth..depth + common_len], false);
+        let child = build_trie_from_entries(arena, entries, depth + common_len);
+        return MemTrieNodeId::new(
+            arena,
+            InputMemTrieNode::Extension { extension: extension.to_vec().into_boxed_slice(), child },
+        );
+    }
+    // Keys are sorted, so the key ending here, if any, comes first.
+    let (value, rest) = if first.len() == depth {
+        (Some(entries[0].1.clone()), &entries[1..])
+    } else {
+        (None, entries)
+    };
+    let mut children = [None; 16];
+    for nibble in 0..16u8 {
+        let group: Vec<_> = rest.iter().filter(|(key, _)| key[depth] == nibble).cloned().collect();
+        if !group.is_empty() {
+            children[nibble as usize] = Some(build_trie_from_entries(arena, &group, depth + 1));
+        }
+    }
+    let input = match value {
+        Some(value) => InputMemTrieNode::BranchWithValue { children, value },
+        None => InputMemTrieNode::Branch { children },
+    };
+    MemTrieNodeId::new(arena, input)
+}
+
+/// Checks that every node of the in-memory trie encodes to exactly the node
+/// stored under its hash by the disk trie.
+fn assert_matches_disk_trie(node: MemTrieNodePtr<'_>, trie: &Trie) {
+    let view = node.view();
+    let raw_node = view.to_raw_trie_node_with_size();
+    assert_eq!(view.node_hash(), hash(&raw_node.try_to_vec().unwrap()));
+    assert_eq!(view.memory_usage(), raw_node.memory_usage);
+    let (_, disk_node) = trie.retrieve_raw_node(&view.node_hash(), false).unwrap().unwrap();
+    assert_eq!(raw_node, disk_node);
+    for child in view.iter_children() {
+        assert_matches_disk_trie(child, trie);
+    }
+}
+
+/// Builds the in-memory trie for the given key/value set and compares it
+/// against the trie built on disk from the same set.
+fn check_trie_against_disk(entries: BTreeMap<Vec<u8>, Vec<u8>>) {
+    if entries.is_empty() {
+        return;
+    }
+    let shard_uid = ShardUId::single_shard();
+    let tries = create_tries();
+    let changes = entries.iter().map(|(key, value)| (key.clone(), Some(value.clone()))).collect();
+    let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+    let trie = tries.get_trie_for_shard(shard_uid, root);
+
+    let nibble_entries: Vec<_> = entries
+        .iter()
+        .map(|(key, value)| {
+            (NibbleSlice::new(key).iter().collect(), FlatStateValue::on_disk(value))
+        })
+        .collect();
+    let mut arena = Arena::new(1 << 28);
+    let node = build_trie_from_entries(&mut arena, &nibble_entries, 0);
+    node.as_ptr_mut(arena.memory_mut()).compute_hash_recursively();
+    assert_eq!(node.as_ptr(arena.memory()).view().node_hash(), root);
+    assert_matches_disk_trie(node.as_ptr(arena.memory()), &trie);
+}
+
+#[test]
+fn test_arbitrary_tries_match_disk_trie() {
+    bolero::check!().with_type::<Vec<(Vec<u8>, Vec<u8>)>>().for_each(|entries| {
+        // Mix in values above the inlining threshold, which are stored
+        // as references.
+        let entries = entries
+            .iter()
+            .map(|(key, value)| {
+                let value = if value.first() == Some(&0xff) {
+                    value.repeat(FlatStateValue::INLINE_DISK_VALUE_THRESHOLD + 1)
+                } else {
+                    value.clone()
+                };
+                (key.clone(), value)
+            })
+            .collect();
+        check_trie_against_disk(entries);
+    });
+}
+
+#[test]
+fn test_max_depth_extensions_match_disk_trie() {
+    // Keys sharing a very long prefix produce extensions with thousands of
+    // nibbles, and leaves hanging off branches at the maximum depth.
+    let prefix = vec![0xab; 2048];
+    let mut entries = BTreeMap::new();
+    for suffix in [&[][..], &[0x01], &[0x01, 0x02], &[0xf0], &[0xf0, 0x00, 0x00]] {
+        let key = [&prefix[..], suffix].concat();
+        entries.insert(key, suffix.to_vec());
+    }
+    check_trie_against_disk(entries.clone());
+    entries.insert(vec![], vec![1]);
+    check_trie_against_disk(entries);
+}
+
+#[test]
+fn test_full_branches_match_disk_trie() {
+    // Every branch has all 16 children, both with and without values, for
+    // the first three levels.
+    let mut entries = BTreeMap::new();
+    for first in 0..=255u8 {
+        entries.insert(vec![first], vec![first]);
+        for second in (0..=255u8).step_by(17) {
+            entries.insert(vec![first, second], vec![second; (first as usize % 3) * 2001]);
+        }
+    }
+    check_trie_against_disk(entries);
+}
@@ -0,0 +1,299 @@
+use super::arena::Arena;
+use super::node::{MemTrieNodeId, MemTrieNodeView};
+use super::MemTries;
+use crate::config::{ArenaGrowthPolicy, ArenaMemoryConfig};
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{BlockHeight, StateRoot};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Identifies a file written by `MemTries::save_to_file`.
+const MEM_TRIES_FILE_MAGIC: [u8; 8] = *b"NEARMEMT";
+/// Bumped whenever the file format or the in-arena node encoding changes.
+/// Files with a different version are rejected, and the caller is expected
+/// to fall back to loading the trie from flat storage.
+const MEM_TRIES_FILE_VERSION: u32 = 3;
+
+/// Header of a persisted `MemTries`. It is followed by the arena (allocator
+/// state plus the used portion of the arena memory, see `Arena::save_to`).
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
+struct MemTriesFileHeader {
+    magic: [u8; 8],
+    version: u32,
+    shard_uid: ShardUId,
+    /// Number of bytes of the arena that are stored in the file.
+    arena_used_size: u64,
+    /// Every root held by the `MemTries`, as the state root along with the
+    /// offset of the root node in the arena.
+    roots: Vec<(StateRoot, u64)>,
+    /// The block heights at which the roots were inserted, used for garbage
+    /// collection.
+    heights: Vec<(BlockHeight, Vec<StateRoot>)>,
+}
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+impl MemTries {
+    /// Writes the whole in-memory trie, including all of its roots, to the
+    /// given file, so that a restarting node can load it with
+    /// `load_from_file` instead of rebuilding it from flat storage.
+    ///
+    /// Fails if there are outstanding snapshots, as the references they hold
+    /// would otherwise be persisted and never released.
+    pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
+        if self.num_snapshots > 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Cannot save mem-trie with {} outstanding snapshots", self.num_snapshots),
+            ));
+        }
+        let mut roots = Vec::new();
+        for (state_root, ids) in &self.roots {
+            for id in ids {
+                roots.push((*state_root, id.pos as u64));
+            }
+        }
+        roots.sort();
+        let header = MemTriesFileHeader {
+            magic: MEM_TRIES_FILE_MAGIC,
+            version: MEM_TRIES_FILE_VERSION,
+            shard_uid: self.shard_uid,
+            arena_used_size: self.arena.used_size() as u64,
+            roots,
+            heights: self.heights.iter().map(|(h, roots)| (*h, roots.clone())).collect(),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        header.serialize(&mut writer)?;
+        self.arena.save_to(&mut writer)?;
+        writer.flush()?;
+        writer.into_inner().map_err(|err| err.into_error())?.sync_all()
+    }
+
+    /// Loads an in-memory trie previously written by `save_to_file`.
+    /// Fails if the file was written by an incompatible version, or for a
+    /// different shard.
+    pub fn load_from_file(
+        path: &Path,
+        growth: &ArenaGrowthPolicy,
+        arena_config: &ArenaMemoryConfig,
+        shard_uid: ShardUId,
+    ) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = MemTriesFileHeader::deserialize_reader(&mut reader)?;
+        if header.magic != MEM_TRIES_FILE_MAGIC {
+            return Err(invalid_data(format!("{} is not a mem-trie file", path.display())));
+        }
+        if header.version != MEM_TRIES_FILE_VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported mem-trie file version {}, expected {}",
+                header.version, MEM_TRIES_FILE_VERSION
+            )));
+        }
+        if header.shard_uid != shard_uid {
+            return Err(invalid_data(format!(
+                "Mem-trie file is for shard {}, expected {}",
+                header.shard_uid, shard_uid
+            )));
+        }
+        let arena = Arena::load_from(&mut reader, growth, arena_config)?;
+        if arena.used_size() as u64 != header.arena_used_size {
+            return Err(invalid_data(format!(
+                "Mem-trie file header says {} bytes are used but the arena has {}",
+                header.arena_used_size,
+                arena.used_size()
+            )));
+        }
+        let mut roots: HashMap<StateRoot, Vec<MemTrieNodeId>> = HashMap::new();
+        for (state_root, pos) in header.roots {
+            if pos >= header.arena_used_size {
+                return Err(invalid_data(format!(
+                    "Root {} points outside of the arena at {}",
+                    state_root, pos
+                )));
+            }
+            roots.entry(state_root).or_default().push(MemTrieNodeId { pos: pos as usize });
+        }
+        let heights: BTreeMap<BlockHeight, Vec<StateRoot>> = header.heights.into_iter().collect();
+        for state_root in heights.values().flatten() {
+            if !roots.contains_key(state_root) {
+                return Err(invalid_data(format!(
+                    "Mem-trie file lists unknown root {} in heights",
+                    state_root
+                )));
+            }
+        }
+        let mut tries = Self { arena, roots, heights, shard_uid, num_snapshots: 0 };
+        tries.register_interned_values().map_err(invalid_data)?;
+        tries.arena.set_node_stats(tries.compute_stats());
+        tries.report_metrics();
+        for (state_root, ids) in &tries.roots {
+            for id in ids {
+                let actual = id.as_ptr(tries.arena.memory()).view().node_hash();
+                if actual != *state_root {
+                    return Err(invalid_data(format!(
+                        "Root node at {} has hash {}, expected {}",
+                        id.pos, actual, state_root
+                    )));
+                }
+            }
+        }
+        Ok(tries)
+    }
+
+    /// Rebuilds the table of interned values of a loaded arena, which is not
+    /// persisted, by visiting every node reachable from the roots. As the
+    /// file may be truncated or corrupted, every node and interned value is
+    /// bounds-checked before it is decoded.
+    fn register_interned_values(&mut self) -> Result<(), String> {
+        let used_size = self.arena.used_size();
+        let mut interned = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack: Vec<_> = self.roots.values().flatten().copied().collect();
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let node = id.as_ptr(self.arena.memory());
+            node.check_bounds(used_size)?;
+            let view = node.view();
+            if let Some(pos) = node.interned_value_pos() {
+                let length = match &view {
+                    MemTrieNodeView::Leaf { value, .. }
+                    | MemTrieNodeView::BranchWithValue { value, .. } => value.len(),
+                    _ => unreachable!("only nodes with values have interned values"),
+                };
+                self.arena.check_interned_value(pos, length)?;
+                interned.push(pos);
+            }
+            stack.extend(view.iter_children().map(|child| child.id()));
+        }
+        for pos in interned {
+            self.arena.register_interned_value(pos);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemTriesFileHeader;
+    use crate::config::{ArenaGrowthPolicy, ArenaMemoryConfig};
+    use crate::trie::mem::node::{InputMemTrieNode, MemTrieNodeId};
+    use crate::trie::mem::MemTries;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::FlatStateValue;
+
+    fn leaf(tries: &mut MemTries, extension: Vec<u8>, value: Vec<u8>) -> MemTrieNodeId {
+        MemTrieNodeId::new(
+            &mut tries.arena,
+            InputMemTrieNode::Leaf {
+                extension: extension.into_boxed_slice(),
+                value: FlatStateValue::Inlined(value),
+            },
+        )
+    }
+
+    #[test]
+    fn test_save_and_load_mem_tries() {
+        let shard_uid = ShardUId::single_shard();
+        let mut tries = MemTries::new(1 << 20, shard_uid);
+        let child1 = leaf(&mut tries, vec![], vec![1, 2, 3]);
+        let child2 = leaf(&mut tries, vec![4], vec![5, 6]);
+        let mut children = [None; 16];
+        children[2] = Some(child1);
+        children[7] = Some(child2);
+        let branch = MemTrieNodeId::new(&mut tries.arena, InputMemTrieNode::Branch { children });
+        let root1 = tries.insert_root(branch, 1);
+        let single = leaf(&mut tries, vec![1, 2], vec![3]);
+        let root2 = tries.insert_root(single, 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memtrie");
+        tries.save_to_file(&path).unwrap();
+
+        let loaded = MemTries::load_from_file(
+            &path,
+            &ArenaGrowthPolicy::fixed(1 << 20),
+            &ArenaMemoryConfig::default(),
+            shard_uid,
+        )
+        .unwrap();
+        assert_eq!(loaded.num_roots(), 2);
+        for root in [root1, root2] {
+            let expected = tries.get_root(&root).unwrap().view().to_raw_trie_node_with_size();
+            let actual = loaded.get_root(&root).unwrap().view().to_raw_trie_node_with_size();
+            assert_eq!(expected, actual);
+        }
+        assert_eq!(loaded.stats(), tries.stats());
+        assert_eq!(loaded.arena_stats(), tries.arena_stats());
+
+        // Loaded tries should continue to be usable, including deallocation.
+        let mut loaded = loaded;
+        assert!(loaded.delete_until_height(2) > 0);
+        assert!(loaded.get_root(&root1).is_none());
+        assert!(loaded.get_root(&root2).is_some());
+        assert_eq!(loaded.stats(), &loaded.compute_stats());
+    }
+
+    #[test]
+    fn test_load_mem_tries_wrong_shard() {
+        let mut tries = MemTries::new(1 << 16, ShardUId::single_shard());
+        let node = leaf(&mut tries, vec![1], vec![2]);
+        tries.insert_root(node, 1);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memtrie");
+        tries.save_to_file(&path).unwrap();
+        let other_shard = ShardUId { version: 1, shard_id: 3 };
+        assert!(MemTries::load_from_file(
+            &path,
+            &ArenaGrowthPolicy::fixed(1 << 16),
+            &ArenaMemoryConfig::default(),
+            other_shard
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_load_corrupted_mem_tries() {
+        let shard_uid = ShardUId::single_shard();
+        let mut tries = MemTries::new(1 << 16, shard_uid);
+        let child = leaf(&mut tries, vec![], vec![1, 2, 3]);
+        let mut children = [None; 16];
+        children[5] = Some(child);
+        let branch = MemTrieNodeId::new(&mut tries.arena, InputMemTrieNode::Branch { children });
+        tries.insert_root(branch, 1);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memtrie");
+        tries.save_to_file(&path).unwrap();
+        let load = |path: &std::path::Path| {
+            MemTries::load_from_file(
+                path,
+                &ArenaGrowthPolicy::fixed(1 << 16),
+                &ArenaMemoryConfig::default(),
+                shard_uid,
+            )
+        };
+        assert!(load(&path).is_ok());
+
+        // Move the root close to the end of the arena, so that the node would
+        // extend past it.
+        let data = std::fs::read(&path).unwrap();
+        let mut header = MemTriesFileHeader::try_from_slice(&data).unwrap();
+        let header_len = header.try_to_vec().unwrap().len();
+        header.roots[0].1 = header.arena_used_size - 1;
+        let mut corrupted = header.try_to_vec().unwrap();
+        corrupted.extend_from_slice(&data[header_len..]);
+        std::fs::write(&path, &corrupted).unwrap();
+        assert!(load(&path).is_err());
+
+        // Truncating the file must fail as well.
+        std::fs::write(&path, &data[..data.len() - 10]).unwrap();
+        assert!(load(&path).is_err());
+    }
+}
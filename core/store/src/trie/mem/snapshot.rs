@@ -0,0 +1,253 @@
+use super::iter::MemTrieIterator;
+use super::lookup::{memtrie_lookup, memtrie_lookup_visiting};
+use super::node::{MemTrieNodeId, MemTrieNodePtr};
+use super::MemTries;
+use crate::{PartialStorage, StorageError, TrieStorage};
+use borsh::BorshSerialize;
+use near_primitives::challenge::PartialState;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::state::FlatStateValue;
+use near_primitives::types::StateRoot;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A logical snapshot of one version of the trie held by `MemTries`.
+///
+/// Taking a snapshot only increments the refcount of the root node, so the
+/// whole version stays alive (trie nodes are never modified after they are
+/// created) even after the chain deletes the corresponding root, without
+/// copying any memory. This allows RPC view calls or state witness
+/// generation to keep reading a consistent version, under a shared borrow of
+/// `MemTries`, while the chain keeps inserting and deleting roots.
+///
+/// The snapshot must be returned with `MemTries::release_snapshot`, otherwise
+/// the memory of this version is never freed.
+#[must_use]
+#[derive(Debug)]
+pub struct MemTrieSnapshot {
+    state_root: StateRoot,
+    shard_uid: ShardUId,
+    /// None for the empty trie.
+    root: Option<MemTrieNodeId>,
+}
+
+impl MemTrieSnapshot {
+    pub fn state_root(&self) -> &StateRoot {
+        &self.state_root
+    }
+
+    /// Returns the root node of the snapshot, given the `MemTries` it was
+    /// taken from.
+    pub fn root<'a>(&self, tries: &'a MemTries) -> Option<MemTrieNodePtr<'a>> {
+        assert_eq!(self.shard_uid, tries.shard_uid, "Snapshot used with a different MemTries");
+        self.root.map(|id| id.as_ptr(tries.arena.memory()))
+    }
+
+    /// Looks up the given key in the snapshot.
+    pub fn lookup(&self, tries: &MemTries, key: &[u8]) -> Option<FlatStateValue> {
+        self.root(tries).and_then(|root| memtrie_lookup(root, key))
+    }
+
+    /// Iterates over the key/value pairs of the snapshot.
+    pub fn iter<'a>(&self, tries: &'a MemTries) -> MemTrieIterator<'a> {
+        MemTrieIterator::new(self.root(tries))
+    }
+
+    /// Looks up the given key in the snapshot, recording the trie nodes on the
+    /// path to it and its value into `recorder`, so that the lookup can later
+    /// be replayed from the recorded storage proof alone.
+    pub fn record_lookup(
+        &self,
+        tries: &MemTries,
+        key: &[u8],
+        recorder: &mut MemTrieRecorder,
+    ) -> Option<FlatStateValue> {
+        let root = self.root(tries)?;
+        let value = memtrie_lookup_visiting(root, key, |view| {
+            let node: Arc<[u8]> = view.to_raw_trie_node_with_size().try_to_vec().unwrap().into();
+            recorder.nodes.entry(hash(&node)).or_insert(node);
+        });
+        match &value {
+            Some(FlatStateValue::Inlined(value)) => {
+                let value: Arc<[u8]> = value.as_slice().into();
+                recorder.nodes.entry(hash(&value)).or_insert(value);
+            }
+            Some(FlatStateValue::Ref(value_ref)) => recorder.value_hashes.push(value_ref.hash),
+            None => {}
+        }
+        value
+    }
+}
+
+/// Accumulates a storage proof for lookups done with
+/// `MemTrieSnapshot::record_lookup`.
+///
+/// Values which are not inlined into the mem-trie are only fetched from the
+/// storage by `into_partial_storage`, which is meant to be called after the
+/// lock on `MemTries` is released, so that recording never reads the database
+/// while holding it.
+#[derive(Default)]
+pub struct MemTrieRecorder {
+    nodes: HashMap<CryptoHash, Arc<[u8]>>,
+    /// Hashes of the values to be fetched from the storage.
+    value_hashes: Vec<CryptoHash>,
+}
+
+impl MemTrieRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of trie nodes and values recorded so far, not counting the
+    /// values yet to be fetched from the storage.
+    pub fn num_recorded(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Fetches the values which weren't inlined from `storage` and returns the
+    /// recorded storage proof, in the same format as `Trie::recorded_storage`.
+    pub fn into_partial_storage(
+        mut self,
+        storage: &dyn TrieStorage,
+    ) -> Result<PartialStorage, StorageError> {
+        for value_hash in std::mem::take(&mut self.value_hashes) {
+            if !self.nodes.contains_key(&value_hash) {
+                let value = storage.retrieve_raw_bytes(&value_hash)?;
+                self.nodes.insert(value_hash, value);
+            }
+        }
+        let mut nodes: Vec<_> = self.nodes.into_values().collect();
+        nodes.sort();
+        Ok(PartialStorage { nodes: PartialState::TrieValues(nodes) })
+    }
+}
+
+impl MemTries {
+    /// Takes a snapshot of the trie with the given state root, which stays
+    /// readable until it is released, even if the root itself is deleted.
+    pub fn take_snapshot(
+        &mut self,
+        state_root: &StateRoot,
+    ) -> Result<MemTrieSnapshot, StorageError> {
+        let root = if *state_root == StateRoot::default() {
+            None
+        } else {
+            let Some(ids) = self.roots.get(state_root) else {
+                return Err(StorageError::StorageInconsistentState(format!(
+                    "Mem-trie for shard {} does not have root {}",
+                    self.shard_uid, state_root
+                )));
+            };
+            let id = ids[0];
+            id.add_ref(&mut self.arena);
+            Some(id)
+        };
+        self.num_snapshots += 1;
+        Ok(MemTrieSnapshot { state_root: *state_root, shard_uid: self.shard_uid, root })
+    }
+
+    /// Releases a snapshot, deallocating the nodes that are no longer
+    /// referenced by any root or other snapshot.
+    pub fn release_snapshot(&mut self, snapshot: MemTrieSnapshot) {
+        assert_eq!(snapshot.shard_uid, self.shard_uid, "Snapshot released to a different MemTries");
+        if let Some(id) = snapshot.root {
+            id.remove_ref(&mut self.arena);
+        }
+        self.num_snapshots -= 1;
+    }
+
+    /// Number of snapshots that have been taken and not yet released.
+    pub fn num_snapshots(&self) -> usize {
+        self.num_snapshots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{create_tries, test_populate_trie};
+    use crate::trie::mem::{MemTrieRecorder, MemTries};
+    use crate::trie::Trie;
+    use crate::TrieDBStorage;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::FlatStateValue;
+
+    #[test]
+    fn test_snapshot_outlives_root() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let changes = vec![
+            (b"a".to_vec(), Some(b"1".to_vec())),
+            (b"ab".to_vec(), Some(b"2".to_vec())),
+            (b"b".to_vec(), Some(b"3".to_vec())),
+        ];
+        let root1 = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+        let root2 = test_populate_trie(
+            &tries,
+            &root1,
+            shard_uid,
+            vec![(b"a".to_vec(), Some(b"4".to_vec())), (b"b".to_vec(), None)],
+        );
+
+        let mut mem_tries = MemTries::new(1 << 20, shard_uid);
+        mem_tries.load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root1), 1).unwrap();
+        let snapshot = mem_tries.take_snapshot(&root1).unwrap();
+
+        // The chain moves on to the next root and deletes the old one.
+        mem_tries.load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root2), 2).unwrap();
+        mem_tries.delete_until_height(2);
+        assert!(mem_tries.get_root(&root1).is_none());
+
+        // The snapshot still sees the old version.
+        assert_eq!(snapshot.lookup(&mem_tries, b"a"), Some(FlatStateValue::inlined(b"1")));
+        assert_eq!(snapshot.lookup(&mem_tries, b"b"), Some(FlatStateValue::inlined(b"3")));
+        let keys: Vec<_> = snapshot.iter(&mem_tries).map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"ab".to_vec(), b"b".to_vec()]);
+        assert_eq!(mem_tries.num_snapshots(), 1);
+
+        // Releasing the snapshot frees the old version.
+        mem_tries.release_snapshot(snapshot);
+        assert_eq!(mem_tries.num_snapshots(), 0);
+        mem_tries.delete_until_height(3);
+        assert_eq!(mem_tries.compute_stats().total_nodes().count, 0);
+
+        let empty = mem_tries.take_snapshot(&Trie::EMPTY_ROOT).unwrap();
+        assert_eq!(empty.lookup(&mem_tries, b"a"), None);
+        mem_tries.release_snapshot(empty);
+        assert!(mem_tries.take_snapshot(&root1).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_record_lookup() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let large_value = vec![7; FlatStateValue::INLINE_DISK_VALUE_THRESHOLD + 1];
+        let changes = vec![
+            (b"a".to_vec(), Some(b"1".to_vec())),
+            (b"ab".to_vec(), Some(large_value.clone())),
+            (b"b".to_vec(), Some(b"3".to_vec())),
+            (b"c".to_vec(), Some(b"4".to_vec())),
+        ];
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+        let mut mem_tries = MemTries::new(1 << 20, shard_uid);
+        mem_tries.load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root), 1).unwrap();
+        let snapshot = mem_tries.take_snapshot(&root).unwrap();
+
+        let keys: [&[u8]; 3] = [b"a", b"ab", b"bx"];
+        let mut recorder = MemTrieRecorder::new();
+        for key in keys {
+            snapshot.record_lookup(&mem_tries, key, &mut recorder);
+        }
+        mem_tries.release_snapshot(snapshot);
+        let storage = TrieDBStorage::new(tries.get_store(), shard_uid);
+        let proof = recorder.into_partial_storage(&storage).unwrap();
+
+        // The recorded proof is enough to replay the lookups, including the
+        // one of a missing key, but not the lookups of other keys.
+        let trie = Trie::from_recorded_storage(proof, root, false);
+        assert_eq!(trie.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(trie.get(b"ab").unwrap(), Some(large_value));
+        assert_eq!(trie.get(b"bx").unwrap(), None);
+        assert!(trie.get(b"c").is_err());
+    }
+}
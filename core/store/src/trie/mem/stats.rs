@@ -0,0 +1,242 @@
+use super::flexible_data::value::ValueView;
+use super::node::{MemTrieNodePtr, MemTrieNodeView};
+use super::MemTries;
+use crate::metrics::{MEM_TRIE_NODE_BYTES, MEM_TRIE_NODE_COUNT, MEM_TRIE_VALUES};
+use near_primitives::shard_layout::ShardUId;
+use std::collections::HashSet;
+
+/// A number of items together with the total number of bytes they occupy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemTrieCountAndBytes {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl MemTrieCountAndBytes {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes as u64;
+    }
+
+    fn remove(&mut self, bytes: usize) {
+        self.count -= 1;
+        self.bytes -= bytes as u64;
+    }
+}
+
+/// Physical memory usage of the arena backing the in-memory tries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemTrieArenaStats {
+    /// Bytes handed out by the allocator so far; this is the resident size
+    /// of the arena.
+    pub used_bytes: u64,
+    /// Bytes that were freed and sit in the freelists waiting to be reused.
+    pub free_bytes: u64,
+}
+
+impl MemTrieArenaStats {
+    /// Fraction of the used arena memory that is currently not holding any
+    /// live node, from 0 (no fragmentation) to 1.
+    pub fn fragmentation(&self) -> f64 {
+        if self.used_bytes == 0 {
+            0.0
+        } else {
+            self.free_bytes as f64 / self.used_bytes as f64
+        }
+    }
+}
+
+/// Breakdown of the memory used by the nodes of `MemTries`. Nodes shared
+/// between multiple roots are counted only once. Node bytes are the encoded
+/// sizes of nodes in the arena, including any inlined values.
+///
+/// The arena keeps these up to date as nodes are allocated and deallocated,
+/// see `MemTries::stats`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemTrieStats {
+    pub leaf: MemTrieCountAndBytes,
+    pub extension: MemTrieCountAndBytes,
+    pub branch: MemTrieCountAndBytes,
+    pub branch_with_value: MemTrieCountAndBytes,
+    /// Values stored directly in the arena; bytes are the value lengths.
+    pub inlined_values: MemTrieCountAndBytes,
+    /// Values stored as references to the State column; bytes are the
+    /// lengths of the referenced values, which do not occupy arena memory.
+    pub referenced_values: MemTrieCountAndBytes,
+}
+
+impl MemTrieStats {
+    pub fn total_nodes(&self) -> MemTrieCountAndBytes {
+        let kinds = [self.leaf, self.extension, self.branch, self.branch_with_value];
+        MemTrieCountAndBytes {
+            count: kinds.iter().map(|k| k.count).sum(),
+            bytes: kinds.iter().map(|k| k.bytes).sum(),
+        }
+    }
+
+    /// Returns the counter of the node's kind and, if the node has a value,
+    /// the counter of the value's kind together with the value length.
+    fn counters(
+        &mut self,
+        node: MemTrieNodePtr<'_>,
+    ) -> (&mut MemTrieCountAndBytes, Option<(&mut MemTrieCountAndBytes, usize)>) {
+        let (node_counter, value) = match node.view() {
+            MemTrieNodeView::Leaf { value, .. } => (&mut self.leaf, Some(value)),
+            MemTrieNodeView::Extension { .. } => (&mut self.extension, None),
+            MemTrieNodeView::Branch { .. } => (&mut self.branch, None),
+            MemTrieNodeView::BranchWithValue { value, .. } => {
+                (&mut self.branch_with_value, Some(value))
+            }
+        };
+        let value_counter = match value {
+            Some(value @ ValueView::Inlined(_)) => Some((&mut self.inlined_values, value.len())),
+            Some(value @ ValueView::Ref { .. }) => Some((&mut self.referenced_values, value.len())),
+            None => None,
+        };
+        (node_counter, value_counter)
+    }
+
+    pub(crate) fn add_node(&mut self, node: MemTrieNodePtr<'_>) {
+        let size = node.size_of_allocation();
+        let (node_counter, value_counter) = self.counters(node);
+        node_counter.add(size);
+        if let Some((value_counter, len)) = value_counter {
+            value_counter.add(len);
+        }
+    }
+
+    pub(crate) fn remove_node(&mut self, node: MemTrieNodePtr<'_>) {
+        let size = node.size_of_allocation();
+        let (node_counter, value_counter) = self.counters(node);
+        node_counter.remove(size);
+        if let Some((value_counter, len)) = value_counter {
+            value_counter.remove(len);
+        }
+    }
+
+    /// Exports the statistics as Prometheus gauges for the given shard.
+    pub fn report_metrics(&self, shard_uid: ShardUId) {
+        let shard_uid = shard_uid.to_string();
+        for (kind, stats) in [
+            ("leaf", self.leaf),
+            ("extension", self.extension),
+            ("branch", self.branch),
+            ("branch_with_value", self.branch_with_value),
+        ] {
+            MEM_TRIE_NODE_COUNT.with_label_values(&[&shard_uid, kind]).set(stats.count as i64);
+            MEM_TRIE_NODE_BYTES.with_label_values(&[&shard_uid, kind]).set(stats.bytes as i64);
+        }
+        for (kind, stats) in [("inlined", self.inlined_values), ("ref", self.referenced_values)] {
+            MEM_TRIE_VALUES.with_label_values(&[&shard_uid, kind]).set(stats.count as i64);
+        }
+    }
+}
+
+impl MemTries {
+    /// Breakdown of the memory used by the nodes, as accounted by the arena.
+    /// This is cheap; unlike `compute_stats` it does not walk the tries.
+    ///
+    /// The accounting covers every node allocated in the arena, which are
+    /// exactly the nodes reachable from the roots, unless building a trie
+    /// failed halfway and left some unreferenced nodes behind.
+    pub fn stats(&self) -> &MemTrieStats {
+        self.arena.node_stats()
+    }
+
+    pub fn arena_stats(&self) -> MemTrieArenaStats {
+        MemTrieArenaStats {
+            used_bytes: self.arena.used_size() as u64,
+            free_bytes: self.arena.free_size() as u64,
+        }
+    }
+
+    /// Walks every node reachable from any root and computes the same
+    /// breakdown as `stats`. This touches the whole arena, so it is only used
+    /// to rebuild the accounting of an arena loaded from a file, and in tests.
+    pub fn compute_stats(&self) -> MemTrieStats {
+        let mut stats = MemTrieStats::default();
+        let mut visited = HashSet::new();
+        let mut stack = Vec::new();
+        for ids in self.roots.values() {
+            for id in ids {
+                stack.push(id.as_ptr(self.arena.memory()));
+            }
+        }
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node.id()) {
+                continue;
+            }
+            stats.add_node(node);
+            stack.extend(node.view().iter_children());
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trie::mem::node::{InputMemTrieNode, MemTrieNodeId};
+    use crate::trie::mem::MemTries;
+    use near_primitives::hash::hash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::{FlatStateValue, ValueRef};
+
+    #[test]
+    fn test_mem_trie_stats() {
+        let mut tries = MemTries::new(1 << 20, ShardUId::single_shard());
+        let inlined = MemTrieNodeId::new(
+            &mut tries.arena,
+            InputMemTrieNode::Leaf {
+                extension: vec![1].into_boxed_slice(),
+                value: FlatStateValue::Inlined(vec![1, 2, 3]),
+            },
+        );
+        let referenced = MemTrieNodeId::new(
+            &mut tries.arena,
+            InputMemTrieNode::Leaf {
+                extension: vec![2].into_boxed_slice(),
+                value: FlatStateValue::Ref(ValueRef { hash: hash(&[4; 1000]), length: 1000 }),
+            },
+        );
+        let mut children = [None; 16];
+        children[0] = Some(inlined);
+        children[1] = Some(referenced);
+        let branch = MemTrieNodeId::new(&mut tries.arena, InputMemTrieNode::Branch { children });
+        let extension = MemTrieNodeId::new(
+            &mut tries.arena,
+            InputMemTrieNode::Extension { extension: vec![5, 6].into_boxed_slice(), child: branch },
+        );
+        let root1 = tries.insert_root(extension, 1);
+        // A second root sharing the branch; shared nodes must be counted once.
+        let root2 = tries.insert_root(branch, 2);
+
+        let stats = tries.compute_stats();
+        assert_eq!(&stats, tries.stats());
+        assert_eq!(stats.leaf.count, 2);
+        assert_eq!(stats.extension.count, 1);
+        assert_eq!(stats.branch.count, 1);
+        assert_eq!(stats.branch_with_value.count, 0);
+        assert_eq!(stats.inlined_values.count, 1);
+        assert_eq!(stats.inlined_values.bytes, 3);
+        assert_eq!(stats.referenced_values.count, 1);
+        assert_eq!(stats.referenced_values.bytes, 1000);
+        assert!(stats.total_nodes().bytes <= tries.arena_stats().used_bytes);
+        assert_eq!(tries.arena_stats().free_bytes, 0);
+
+        // Removing the extension frees exactly one node.
+        tries.delete_root(&root1);
+        let stats = tries.compute_stats();
+        assert_eq!(&stats, tries.stats());
+        assert_eq!(stats.extension.count, 0);
+        assert_eq!(stats.leaf.count, 2);
+        assert!(tries.arena_stats().free_bytes > 0);
+        assert!(tries.arena_stats().fragmentation() > 0.0);
+
+        tries.delete_root(&root2);
+        let stats = tries.compute_stats();
+        assert_eq!(&stats, tries.stats());
+        assert_eq!(stats.total_nodes().count, 0);
+        assert_eq!(tries.arena_stats().free_bytes, tries.arena_stats().used_bytes);
+        stats.report_metrics(ShardUId::single_shard());
+    }
+}
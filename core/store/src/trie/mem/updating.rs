@@ -0,0 +1,272 @@
+use super::arena::{Arena, ArenaMemory};
+use super::node::{InputMemTrieNode, MemTrieNodeId, MemTrieNodeView};
+use super::{MemTrieError, MemTries};
+use crate::trie::nibble_slice::NibbleSlice;
+use crate::trie::{Trie, TrieChanges};
+use crate::{RawTrieNode, RawTrieNodeWithSize, StorageError};
+use borsh::BorshDeserialize;
+use near_primitives::hash::CryptoHash;
+use near_primitives::state::{FlatStateValue, ValueRef};
+use near_primitives::types::{BlockHeight, StateRoot};
+use std::collections::HashMap;
+
+impl MemTries {
+    /// Constructs the root `trie_changes.new_root` from the root
+    /// `trie_changes.old_root` and registers it at the given block height, so
+    /// that the in-memory trie follows the chain as chunks are applied.
+    ///
+    /// Subtrees that did not change are shared with the old root. New nodes
+    /// and values are taken from the insertions of the changes; anything
+    /// else is read through the given `Trie`.
+    ///
+    /// Returns None, without doing anything, if the old root is not held.
+    pub fn apply_trie_changes(
+        &mut self,
+        trie: &Trie,
+        trie_changes: &TrieChanges,
+        block_height: BlockHeight,
+    ) -> Result<Option<StateRoot>, MemTrieError> {
+        let old_root = if trie_changes.old_root == Trie::EMPTY_ROOT {
+            None
+        } else {
+            match self.roots.get(&trie_changes.old_root) {
+                Some(ids) => Some(OldPosition { node: ids[0], consumed: 0 }),
+                None => return Ok(None),
+            }
+        };
+        if trie_changes.new_root == Trie::EMPTY_ROOT {
+            return Ok(Some(Trie::EMPTY_ROOT));
+        }
+        let mut updater = MemTrieUpdater {
+            trie,
+            arena: &mut self.arena,
+            inserted: trie_changes
+                .insertions()
+                .iter()
+                .map(|change| (*change.hash(), change.payload()))
+                .collect(),
+        };
+        let root = updater.build(&trie_changes.new_root, old_root)?;
+        let state_root = self.insert_root(root, block_height);
+        if state_root != trie_changes.new_root {
+            self.heights.get_mut(&block_height).and_then(|roots| roots.pop());
+            self.delete_root(&state_root);
+            return Err(StorageError::StorageInconsistentState(format!(
+                "In-memory trie updated to {}, expected {}",
+                state_root, trie_changes.new_root
+            ))
+            .into());
+        }
+        Ok(Some(state_root))
+    }
+}
+
+/// Position in the old trie corresponding to the path of a node of the new
+/// trie: `node` is reached by following the same nibbles from the old root,
+/// and `consumed` nibbles of its extension are matched on top of that. Only
+/// a position with nothing consumed is an old node at exactly the same path.
+#[derive(Clone, Copy)]
+struct OldPosition {
+    node: MemTrieNodeId,
+    consumed: usize,
+}
+
+impl OldPosition {
+    /// Follows one nibble down the old trie, if the old trie has that path.
+    fn step(self, memory: &ArenaMemory, nibble: u8) -> Option<OldPosition> {
+        match self.node.as_ptr(memory).view() {
+            MemTrieNodeView::Leaf { extension, .. } => {
+                let extension = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                (self.consumed < extension.len() && extension.at(self.consumed) == nibble)
+                    .then(|| OldPosition { node: self.node, consumed: self.consumed + 1 })
+            }
+            MemTrieNodeView::Extension { extension, child, .. } => {
+                let extension = NibbleSlice::from_encoded(extension.raw_slice()).0;
+                if extension.at(self.consumed) != nibble {
+                    None
+                } else if self.consumed + 1 == extension.len() {
+                    Some(OldPosition { node: child.id(), consumed: 0 })
+                } else {
+                    Some(OldPosition { node: self.node, consumed: self.consumed + 1 })
+                }
+            }
+            MemTrieNodeView::Branch { children, .. }
+            | MemTrieNodeView::BranchWithValue { children, .. } => children
+                .get(nibble as usize)
+                .map(|child| OldPosition { node: child.id(), consumed: 0 }),
+        }
+    }
+
+    /// Follows the given nibbles down the old trie.
+    fn descend(self, memory: &ArenaMemory, nibbles: NibbleSlice<'_>) -> Option<OldPosition> {
+        (0..nibbles.len()).try_fold(self, |pos, i| pos.step(memory, nibbles.at(i)))
+    }
+
+    /// Returns the old node at exactly this position if it has the given hash.
+    fn reusable(self, memory: &ArenaMemory, hash: &CryptoHash) -> Option<MemTrieNodeId> {
+        (self.consumed == 0 && self.node.as_ptr(memory).view().node_hash() == *hash)
+            .then_some(self.node)
+    }
+}
+
+struct MemTrieUpdater<'a> {
+    trie: &'a Trie,
+    arena: &'a mut Arena,
+    /// Serialized trie nodes and values inserted by the changes, by hash.
+    inserted: HashMap<CryptoHash, &'a [u8]>,
+}
+
+impl<'a> MemTrieUpdater<'a> {
+    fn build(
+        &mut self,
+        hash: &CryptoHash,
+        old: Option<OldPosition>,
+    ) -> Result<MemTrieNodeId, MemTrieError> {
+        if let Some(id) = old.and_then(|old| old.reusable(self.arena.memory(), hash)) {
+            return Ok(id);
+        }
+        let Some(&bytes) = self.inserted.get(hash) else {
+            // The node already existed, but elsewhere in the old trie, so the
+            // whole subtree is unchanged and can be read as is.
+            return super::loading::load_subtree_from_trie(self.trie, self.arena, hash);
+        };
+        let raw_node = RawTrieNodeWithSize::try_from_slice(bytes).map_err(|err| {
+            StorageError::StorageInconsistentState(format!(
+                "Failed to decode inserted trie node {}: {}",
+                hash, err
+            ))
+        })?;
+        let input = match raw_node.node {
+            RawTrieNode::Leaf(extension, value_ref) => InputMemTrieNode::Leaf {
+                value: self.value(&value_ref)?,
+                extension: extension.into_boxed_slice(),
+            },
+            RawTrieNode::Extension(extension, child) => {
+                let child_old = old.and_then(|old| {
+                    old.descend(self.arena.memory(), NibbleSlice::from_encoded(&extension).0)
+                });
+                InputMemTrieNode::Extension {
+                    child: self.build(&child, child_old)?,
+                    extension: extension.into_boxed_slice(),
+                }
+            }
+            RawTrieNode::BranchNoValue(children) => {
+                let mut mem_children = [None; 16];
+                for (idx, child) in children.iter() {
+                    let child_old = old.and_then(|old| old.step(self.arena.memory(), idx));
+                    mem_children[idx as usize] = Some(self.build(child, child_old)?);
+                }
+                InputMemTrieNode::Branch { children: mem_children }
+            }
+            RawTrieNode::BranchWithValue(value_ref, children) => {
+                let mut mem_children = [None; 16];
+                for (idx, child) in children.iter() {
+                    let child_old = old.and_then(|old| old.step(self.arena.memory(), idx));
+                    mem_children[idx as usize] = Some(self.build(child, child_old)?);
+                }
+                InputMemTrieNode::BranchWithValue {
+                    children: mem_children,
+                    value: self.value(&value_ref)?,
+                }
+            }
+        };
+        Ok(MemTrieNodeId::try_new(self.arena, input)?)
+    }
+
+    fn value(&self, value_ref: &ValueRef) -> Result<FlatStateValue, StorageError> {
+        match self.inserted.get(&value_ref.hash) {
+            Some(value) => Ok(FlatStateValue::on_disk(value)),
+            None => Ok(FlatStateValue::on_disk(&self.trie.retrieve_value(&value_ref.hash)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::trie::mem::{MemTrieIterator, MemTries};
+    use crate::trie::Trie;
+    use crate::ShardTries;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::types::StateRoot;
+    use rand::Rng;
+
+    /// Applies the changes both on disk and to the in-memory trie, and checks
+    /// that the in-memory trie has the same contents as one loaded from disk.
+    fn apply_and_check(
+        tries: &ShardTries,
+        mem_tries: &mut MemTries,
+        root: StateRoot,
+        changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+        height: u64,
+    ) -> StateRoot {
+        let shard_uid = ShardUId::single_shard();
+        let trie_changes = tries.get_trie_for_shard(shard_uid, root).update(changes).unwrap();
+        let trie = tries.get_trie_for_shard(shard_uid, trie_changes.new_root);
+        let new_root = mem_tries.apply_trie_changes(&trie, &trie_changes, height).unwrap();
+        assert_eq!(new_root, Some(trie_changes.new_root));
+        let mut store_update = tries.store_update();
+        let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+        store_update.commit().unwrap();
+
+        let mut expected = MemTries::new(1 << 24, shard_uid);
+        expected.load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root), height).unwrap();
+        let expected: Vec<_> = MemTrieIterator::new(expected.get_root(&root)).collect();
+        let actual: Vec<_> = MemTrieIterator::new(mem_tries.get_root(&root)).collect();
+        assert_eq!(actual, expected);
+        // Updates keep the accounting of the arena in line with its contents.
+        assert_eq!(mem_tries.stats(), &mem_tries.compute_stats());
+        root
+    }
+
+    #[test]
+    fn test_apply_trie_changes() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = create_tries();
+        let initial = vec![
+            (b"a".to_vec(), Some(b"1".to_vec())),
+            (b"ab".to_vec(), Some(b"2".to_vec())),
+            (b"abc".to_vec(), Some(vec![3; 5000])),
+            (b"b".to_vec(), Some(b"4".to_vec())),
+            (b"xyz".to_vec(), Some(b"5".to_vec())),
+        ];
+        let mut root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, initial);
+        let mut mem_tries = MemTries::new(1 << 24, shard_uid);
+        mem_tries.load_root_from_trie(&tries.get_trie_for_shard(shard_uid, root), 1).unwrap();
+
+        // Updates, insertions that split extensions, and deletions that merge
+        // nodes back together.
+        let updates = vec![
+            vec![(b"ab".to_vec(), Some(b"6".to_vec())), (b"xyw".to_vec(), Some(b"7".to_vec()))],
+            vec![(b"a".to_vec(), None), (b"xyz".to_vec(), None)],
+            vec![(b"abc".to_vec(), Some(vec![8; 100])), (b"c".to_vec(), Some(vec![9; 5000]))],
+            vec![(b"ab".to_vec(), None), (b"abc".to_vec(), None), (b"b".to_vec(), None)],
+            vec![(b"c".to_vec(), None), (b"xyw".to_vec(), None)],
+            vec![(b"d".to_vec(), Some(b"10".to_vec()))],
+        ];
+        for (i, changes) in updates.into_iter().enumerate() {
+            root = apply_and_check(&tries, &mut mem_tries, root, changes, i as u64 + 2);
+        }
+
+        // Changes on top of a root that is not held are skipped.
+        mem_tries.delete_until_height(100);
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let trie_changes = trie.update(vec![(b"e".to_vec(), Some(b"1".to_vec()))]).unwrap();
+        assert_eq!(mem_tries.apply_trie_changes(&trie, &trie_changes, 100).unwrap(), None);
+        assert_eq!(mem_tries.num_roots(), 0);
+    }
+
+    #[test]
+    fn test_apply_trie_changes_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let tries = create_tries();
+            let mut mem_tries = MemTries::new(1 << 24, ShardUId::single_shard());
+            let mut root = Trie::EMPTY_ROOT;
+            for height in 1..10 {
+                let changes = simplify_changes(&gen_changes(&mut rng, rng.gen_range(1..30)));
+                root = apply_and_check(&tries, &mut mem_tries, root, changes, height);
+            }
+        }
+    }
+}
@@ -29,6 +29,7 @@ use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 use std::str;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub mod accounting_cache;
 mod config;
@@ -39,6 +40,7 @@ pub mod mem;
 mod nibble_slice;
 mod prefetching_trie_storage;
 mod raw_node;
+pub mod refcount_audit;
 mod shard_tries;
 pub mod split_state;
 mod state_parts;
@@ -51,7 +53,7 @@ pub mod update;
 
 use self::accounting_cache::TrieAccountingCache;
 use self::trie_recording::TrieRecorder;
-use self::trie_storage::TrieMemoryPartialStorage;
+use self::trie_storage::{TrieMemoryPartialStorage, TrieOverlayStorage};
 pub use from_flat::construct_trie_from_flat;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
@@ -352,6 +354,21 @@ pub struct Trie {
     /// no matter what.) This allows us to accurately calculate storage gas
     /// costs even with only a state proof.
     skip_accounting_cache_for_trie_nodes: bool,
+    /// Counts the reads served by this trie, see `TrieReadStats`.
+    read_stats: RefCell<TrieReadStats>,
+}
+
+/// Number of value reads served by a `Trie`, and the total size of the values
+/// found, split by whether they were served from flat storage or by
+/// traversing trie nodes. Lookups of absent keys count as reads of zero bytes.
+/// `read_time` is the total time spent looking up values and retrieving them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrieReadStats {
+    pub flat_storage_reads: u64,
+    pub flat_storage_bytes: u64,
+    pub trie_reads: u64,
+    pub trie_bytes: u64,
+    pub read_time: Duration,
 }
 
 /// Trait for reading data from a trie.
@@ -476,6 +493,7 @@ impl Trie {
             accounting_cache,
             recorder: None,
             skip_accounting_cache_for_trie_nodes: false,
+            read_stats: Default::default(),
         }
     }
 
@@ -959,7 +977,9 @@ impl Trie {
     /// Returns the raw bytes corresponding to a ValueRef that came from a node with
     /// value (either Leaf or BranchWithValue).
     pub fn retrieve_value(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
+        let start = Instant::now();
         let bytes = self.internal_retrieve_trie_node(hash, true)?;
+        self.read_stats.borrow_mut().read_time += start.elapsed();
         Ok(bytes.to_vec())
     }
 
@@ -978,6 +998,7 @@ impl Trie {
         key: &[u8],
         mode: KeyLookupMode,
     ) -> Result<Option<ValueRef>, StorageError> {
+        let start = Instant::now();
         let use_flat_storage =
             matches!(mode, KeyLookupMode::FlatStorage) && self.flat_storage_chunk_view.is_some();
 
@@ -997,18 +1018,25 @@ impl Trie {
                 let value_from_trie = self.lookup(key_nibbles, false)?;
                 assert_eq!(&value_from_flat_storage, &value_from_trie);
             }
+            let mut read_stats = self.read_stats.borrow_mut();
+            read_stats.flat_storage_reads += 1;
+            read_stats.flat_storage_bytes += value_length(&value_from_flat_storage);
+            read_stats.read_time += start.elapsed();
             Ok(value_from_flat_storage)
         } else {
             let key_nibbles = NibbleSlice::new(key);
-            self.lookup(key_nibbles, !self.skip_accounting_cache_for_trie_nodes)
+            let value = self.lookup(key_nibbles, !self.skip_accounting_cache_for_trie_nodes)?;
+            let mut read_stats = self.read_stats.borrow_mut();
+            read_stats.trie_reads += 1;
+            read_stats.trie_bytes += value_length(&value);
+            read_stats.read_time += start.elapsed();
+            Ok(value)
         }
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
         match self.get_ref(key, KeyLookupMode::FlatStorage)? {
-            Some(ValueRef { hash, .. }) => {
-                self.internal_retrieve_trie_node(&hash, true).map(|bytes| Some(bytes.to_vec()))
-            }
+            Some(ValueRef { hash, .. }) => self.retrieve_value(&hash).map(Some),
             None => Ok(None),
         }
     }
@@ -1039,6 +1067,18 @@ impl Trie {
         (insertions, deletions)
     }
 
+    /// Returns the trie after the consecutive changes, which are kept in memory rather than
+    /// written to the storage. The returned trie doesn't use flat storage.
+    pub fn with_changes_in_memory(&self, changes: &[TrieChanges]) -> Trie {
+        let mut root = self.root;
+        for changes in changes {
+            assert_eq!(root, changes.old_root, "trie changes must be consecutive");
+            root = changes.new_root;
+        }
+        let storage = TrieOverlayStorage::new(self.storage.clone(), changes);
+        Trie::new(Rc::new(storage), root, None)
+    }
+
     pub fn update<I>(&self, changes: I) -> Result<TrieChanges, StorageError>
     where
         I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
@@ -1084,6 +1124,15 @@ impl Trie {
     pub fn get_trie_nodes_count(&self) -> TrieNodesCount {
         self.accounting_cache.borrow().get_trie_nodes_count()
     }
+
+    /// Returns the number and volume of reads served by this trie so far.
+    pub fn get_read_stats(&self) -> TrieReadStats {
+        *self.read_stats.borrow()
+    }
+}
+
+fn value_length(value: &Option<ValueRef>) -> u64 {
+    value.as_ref().map_or(0, |value| value.length as u64)
 }
 
 impl TrieAccess for Trie {
@@ -1122,11 +1171,13 @@ mod tests {
     use assert_matches::assert_matches;
     use rand::Rng;
 
+    use crate::flat::store_helper;
     use crate::test_utils::{
-        create_test_store, create_tries, create_tries_complex, gen_changes, simplify_changes,
-        test_populate_trie,
+        create_test_store, create_tries, create_tries_complex, create_tries_with_flat_storage,
+        gen_changes, simplify_changes, test_populate_trie,
     };
     use crate::{DBCol, MissingTrieValueContext};
+    use near_primitives::state::FlatStateValue;
 
     use super::*;
 
@@ -1174,6 +1225,36 @@ mod tests {
         assert_eq!(trie.iter().unwrap().fold(0, |acc, _| acc + 1), 0);
     }
 
+    #[test]
+    fn test_trie_with_changes_in_memory() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let root = test_populate_trie(
+            &tries,
+            &Trie::EMPTY_ROOT,
+            shard_uid,
+            vec![
+                (b"doge".to_vec(), Some(b"coin".to_vec())),
+                (b"dog".to_vec(), Some(b"puppy".to_vec())),
+            ],
+        );
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let changes = trie
+            .update(vec![(b"doge".to_vec(), None), (b"do".to_vec(), Some(b"verb".to_vec()))])
+            .unwrap();
+        let changed = trie.with_changes_in_memory(&[changes.clone()]);
+        assert_eq!(changed.get(b"doge"), Ok(None));
+        assert_eq!(changed.get(b"dog"), Ok(Some(b"puppy".to_vec())));
+        assert_eq!(changed.get(b"do"), Ok(Some(b"verb".to_vec())));
+        let more_changes = changed.update(vec![(b"dog".to_vec(), None)]).unwrap();
+        let changed = trie.with_changes_in_memory(&[changes.clone(), more_changes]);
+        assert_eq!(changed.get(b"dog"), Ok(None));
+        assert_eq!(changed.get(b"do"), Ok(Some(b"verb".to_vec())));
+        // The changes are not written to the storage.
+        assert_eq!(trie.get(b"doge"), Ok(Some(b"coin".to_vec())));
+        assert!(tries.get_trie_for_shard(shard_uid, changes.new_root).get(b"do").is_err());
+    }
+
     #[test]
     fn test_trie_iter() {
         let tries = create_tries_complex(SHARD_VERSION, 2);
@@ -1470,6 +1551,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_trie_read_stats() {
+        let tries = create_tries_with_flat_storage();
+        let shard_uid = ShardUId::single_shard();
+        let changes = vec![
+            (b"dog".to_vec(), Some(b"puppy".to_vec())),
+            (b"horse".to_vec(), Some(b"stallion".to_vec())),
+        ];
+        let root = test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.clone());
+        let mut store_update = tries.store_update();
+        for (key, value) in changes {
+            let value = value.map(|value| FlatStateValue::value_ref(&value));
+            store_helper::set_flat_state_value(&mut store_update, shard_uid, key, value);
+        }
+        store_update.commit().unwrap();
+
+        let trie = tries.get_trie_with_block_hash_for_shard(
+            shard_uid,
+            root,
+            &CryptoHash::default(),
+            false,
+        );
+        assert!(trie.has_flat_storage_chunk_view());
+        assert_eq!(trie.get(b"dog"), Ok(Some(b"puppy".to_vec())));
+        assert_eq!(trie.get(b"cat"), Ok(None));
+        assert_eq!(
+            trie.get_ref(b"horse", KeyLookupMode::Trie),
+            Ok(Some(ValueRef::new(b"stallion")))
+        );
+        let read_stats = trie.get_read_stats();
+        assert!(read_stats.read_time > Duration::ZERO);
+        assert_eq!(
+            read_stats,
+            TrieReadStats {
+                flat_storage_reads: 2,
+                flat_storage_bytes: 5,
+                trie_reads: 1,
+                trie_bytes: 8,
+                read_time: read_stats.read_time,
+            }
+        );
+    }
+
     #[test]
     fn test_trie_recording_reads_update() {
         let store = create_test_store();
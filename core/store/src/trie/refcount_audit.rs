@@ -0,0 +1,342 @@
+//! Audit of reference counts of state trie nodes.
+//!
+//! Trie nodes and values are stored in `DBCol::State` with a reference count
+//! which is incremented when the state changes of a block insert them and
+//! decremented once garbage collection applies the deletions of an old block.
+//! A bug in the bookkeeping either leaks nodes, whose count never drops to
+//! zero, or deletes nodes which are still referenced, which corrupts the state.
+//!
+//! `RefcountAudit` traverses the trie of one state root in steps of bounded
+//! size, so that it can run next to block processing.  For a sample of nodes
+//! and values, chosen deterministically by their hash, it counts how many
+//! times they are referenced in the trie and compares that with the stored
+//! reference count once the traversal is finished.
+//!
+//! A referenced node without a positive reference count has been deleted
+//! prematurely, which is always a bug.  A stored count which differs from the
+//! recounted one (drift) is expected for nodes touched by blocks which haven't
+//! been garbage collected yet, since their deletions are only applied by
+//! garbage collection.  Drift which persists across audits of later state
+//! roots points to a leak.
+
+use crate::db::refcount::decode_value_with_rc;
+use crate::trie::raw_node::{RawTrieNode, RawTrieNodeWithSize};
+use crate::{metrics, DBCol, Store, TrieCachingStorage};
+use borsh::BorshDeserialize;
+use near_primitives::errors::StorageError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::state::ValueRef;
+use near_primitives::types::StateRoot;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefcountAuditResult {
+    Match,
+    /// The stored reference count differs from the recounted one.
+    Drift,
+    /// The node is referenced but it isn't stored or its reference count isn't
+    /// positive.
+    Missing,
+}
+
+impl RefcountAuditResult {
+    fn label(&self) -> &'static str {
+        match self {
+            RefcountAuditResult::Match => "match",
+            RefcountAuditResult::Drift => "drift",
+            RefcountAuditResult::Missing => "missing",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RefcountAuditEntry {
+    pub hash: CryptoHash,
+    /// Whether the entry is a value rather than a trie node.
+    pub is_value: bool,
+    /// Number of references to the entry in the audited trie.
+    pub recounted: u64,
+    /// Reference count in the store, 0 if the entry isn't stored.
+    pub stored: i64,
+}
+
+impl RefcountAuditEntry {
+    pub fn result(&self) -> RefcountAuditResult {
+        if self.stored <= 0 {
+            RefcountAuditResult::Missing
+        } else if self.stored as u64 != self.recounted {
+            RefcountAuditResult::Drift
+        } else {
+            RefcountAuditResult::Match
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RefcountAuditReport {
+    pub shard_uid: ShardUId,
+    pub root: StateRoot,
+    pub visited_nodes: u64,
+    /// Sampled entries as well as all referenced nodes which turned out to be
+    /// missing, sorted by hash.
+    pub entries: Vec<RefcountAuditEntry>,
+}
+
+impl RefcountAuditReport {
+    pub fn count(&self, result: RefcountAuditResult) -> usize {
+        self.entries.iter().filter(|entry| entry.result() == result).count()
+    }
+
+    /// Writes entries which don't match to a file in given directory and
+    /// returns its path.
+    pub fn write_to_dir(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}_{}.txt", self.shard_uid, self.root));
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        writeln!(file, "shard uid: {}", self.shard_uid)?;
+        writeln!(file, "state root: {}", self.root)?;
+        writeln!(file, "visited nodes: {}", self.visited_nodes)?;
+        for result in
+            [RefcountAuditResult::Match, RefcountAuditResult::Drift, RefcountAuditResult::Missing]
+        {
+            writeln!(file, "{}: {}", result.label(), self.count(result))?;
+        }
+        writeln!(file)?;
+        writeln!(file, "{:<44} {:<6} {:>10} {:>10} result", "hash", "kind", "recounted", "stored")?;
+        for entry in &self.entries {
+            let result = entry.result();
+            if result == RefcountAuditResult::Match {
+                continue;
+            }
+            writeln!(
+                file,
+                "{:<44} {:<6} {:>10} {:>10} {}",
+                entry.hash.to_string(),
+                if entry.is_value { "value" } else { "node" },
+                entry.recounted,
+                entry.stored,
+                result.label(),
+            )?;
+        }
+        file.flush()?;
+        Ok(path)
+    }
+
+    fn update_metrics(&self) {
+        let shard_uid = self.shard_uid.to_string();
+        for entry in &self.entries {
+            let result = entry.result();
+            metrics::REFCOUNT_AUDIT_ENTRIES.with_label_values(&[&shard_uid, result.label()]).inc();
+            if result == RefcountAuditResult::Drift {
+                metrics::REFCOUNT_AUDIT_DRIFT
+                    .with_label_values(&[&shard_uid])
+                    .observe((entry.stored - entry.recounted as i64) as f64);
+            }
+        }
+    }
+}
+
+/// Incremental recount of references of sampled nodes in the trie of one
+/// state root, see the module documentation.
+pub struct RefcountAudit {
+    store: Store,
+    shard_uid: ShardUId,
+    root: StateRoot,
+    /// Entries whose hash, read as a number from its first 8 bytes, is below
+    /// the threshold are sampled.
+    sample_threshold: u64,
+    /// Nodes which are referenced but haven't been visited yet.
+    pending: Vec<CryptoHash>,
+    /// Number of references of sampled nodes and values (`true`) found so far.
+    recounted: HashMap<CryptoHash, (u64, bool)>,
+    /// Referenced nodes which couldn't be visited since they're missing.
+    missing: HashMap<CryptoHash, u64>,
+    visited_nodes: u64,
+}
+
+impl RefcountAudit {
+    /// Prepares an audit of the trie with given root.  `sample_rate` is the
+    /// fraction of nodes and values whose references are recounted.
+    pub fn new(store: Store, shard_uid: ShardUId, root: StateRoot, sample_rate: f64) -> Self {
+        let pending = if root == StateRoot::default() { vec![] } else { vec![root] };
+        Self {
+            store,
+            shard_uid,
+            root,
+            sample_threshold: (sample_rate.clamp(0.0, 1.0) * u64::MAX as f64) as u64,
+            pending,
+            recounted: HashMap::new(),
+            missing: HashMap::new(),
+            visited_nodes: 0,
+        }
+    }
+
+    pub fn shard_uid(&self) -> ShardUId {
+        self.shard_uid
+    }
+
+    pub fn root(&self) -> StateRoot {
+        self.root
+    }
+
+    /// Visits at most `max_nodes` nodes of the trie.  Once all nodes have been
+    /// visited, reads the stored reference counts of the sampled nodes,
+    /// exports the results as metrics and returns the report.
+    pub fn step(&mut self, max_nodes: u64) -> Result<Option<RefcountAuditReport>, StorageError> {
+        let mut remaining = max_nodes;
+        while remaining > 0 {
+            let Some(hash) = self.pending.pop() else { break };
+            remaining -= 1;
+            self.visit_node(hash)?;
+        }
+        metrics::REFCOUNT_AUDIT_VISITED_NODES
+            .with_label_values(&[&self.shard_uid.to_string()])
+            .inc_by(max_nodes - remaining);
+        if !self.pending.is_empty() {
+            return Ok(None);
+        }
+        let report = self.finish()?;
+        report.update_metrics();
+        Ok(Some(report))
+    }
+
+    fn visit_node(&mut self, hash: CryptoHash) -> Result<(), StorageError> {
+        self.visited_nodes += 1;
+        self.count_reference(hash, false);
+        let (bytes, rc) = self.read(&hash)?;
+        let Some(bytes) = bytes.filter(|_| rc > 0) else {
+            *self.missing.entry(hash).or_default() += 1;
+            return Ok(());
+        };
+        let node = RawTrieNodeWithSize::try_from_slice(&bytes)
+            .map_err(|err| StorageError::StorageInconsistentState(err.to_string()))?;
+        match node.node {
+            RawTrieNode::Leaf(_, value) => self.count_value(&value),
+            RawTrieNode::BranchNoValue(children) => {
+                self.pending.extend(children.iter().map(|(_, child)| *child));
+            }
+            RawTrieNode::BranchWithValue(value, children) => {
+                self.count_value(&value);
+                self.pending.extend(children.iter().map(|(_, child)| *child));
+            }
+            RawTrieNode::Extension(_, child) => self.pending.push(child),
+        }
+        Ok(())
+    }
+
+    fn count_value(&mut self, value: &ValueRef) {
+        self.count_reference(value.hash, true);
+    }
+
+    fn count_reference(&mut self, hash: CryptoHash, is_value: bool) {
+        let prefix = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        if prefix < self.sample_threshold {
+            self.recounted.entry(hash).or_insert((0, is_value)).0 += 1;
+        }
+    }
+
+    /// Returns the raw value of the node and its reference count.
+    fn read(&self, hash: &CryptoHash) -> Result<(Option<Vec<u8>>, i64), StorageError> {
+        let key = TrieCachingStorage::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
+        let raw = self
+            .store
+            .storage
+            .get_raw_bytes(DBCol::State, &key)
+            .map_err(|err| StorageError::StorageInconsistentState(err.to_string()))?;
+        Ok(match raw {
+            Some(raw) => {
+                let (value, rc) = decode_value_with_rc(&raw);
+                (value.map(<[u8]>::to_vec), rc)
+            }
+            None => (None, 0),
+        })
+    }
+
+    fn finish(&mut self) -> Result<RefcountAuditReport, StorageError> {
+        let mut entries = vec![];
+        for (hash, (recounted, is_value)) in std::mem::take(&mut self.recounted) {
+            // Missing nodes are added below.
+            if self.missing.contains_key(&hash) {
+                continue;
+            }
+            let (_, stored) = self.read(&hash)?;
+            entries.push(RefcountAuditEntry { hash, is_value, recounted, stored });
+        }
+        for (hash, recounted) in std::mem::take(&mut self.missing) {
+            let (_, stored) = self.read(&hash)?;
+            entries.push(RefcountAuditEntry { hash, is_value: false, recounted, stored });
+        }
+        entries.sort_by_key(|entry| entry.hash);
+        Ok(RefcountAuditReport {
+            shard_uid: self.shard_uid,
+            root: self.root,
+            visited_nodes: self.visited_nodes,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RefcountAudit, RefcountAuditResult};
+    use crate::test_utils::{create_tries, test_populate_trie};
+    use crate::{DBCol, TrieCachingStorage};
+    use near_primitives::hash::hash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::types::StateRoot;
+
+    #[test]
+    fn test_refcount_audit() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let changes = (0..100u32)
+            .map(|i| (i.to_le_bytes().to_vec(), Some(vec![(i % 10) as u8; 40])))
+            .collect();
+        let root = test_populate_trie(&tries, &StateRoot::default(), shard_uid, changes);
+        let store = tries.get_store();
+
+        let mut audit = RefcountAudit::new(store.clone(), shard_uid, root, 1.0);
+        let mut steps = 0;
+        let report = loop {
+            steps += 1;
+            if let Some(report) = audit.step(10).unwrap() {
+                break report;
+            }
+        };
+        assert!(steps > 1);
+        // Values are shared by 10 keys each.
+        let value = hash(&[3; 40]);
+        let entry = report.entries.iter().find(|entry| entry.hash == value).unwrap();
+        assert!(entry.is_value);
+        assert_eq!(entry.recounted, 10);
+        assert_eq!(report.count(RefcountAuditResult::Match), report.entries.len());
+
+        // Leak a reference to one of the values.
+        let mut store_update = store.store_update();
+        let value_key = TrieCachingStorage::get_key_from_shard_uid_and_hash(shard_uid, &value);
+        store_update.increment_refcount(DBCol::State, &value_key, &[3; 40]);
+        store_update.commit().unwrap();
+        let report =
+            RefcountAudit::new(store.clone(), shard_uid, root, 1.0).step(1000).unwrap().unwrap();
+        assert_eq!(report.count(RefcountAuditResult::Drift), 1);
+        let entry = report.entries.iter().find(|entry| entry.hash == value).unwrap();
+        assert_eq!((entry.recounted, entry.stored), (10, 11));
+
+        // Delete the root prematurely.
+        let mut store_update = store.store_update();
+        let root_key = TrieCachingStorage::get_key_from_shard_uid_and_hash(shard_uid, &root);
+        store_update.decrement_refcount(DBCol::State, &root_key);
+        store_update.commit().unwrap();
+        let report = RefcountAudit::new(store, shard_uid, root, 1.0).step(1000).unwrap().unwrap();
+        assert_eq!(report.visited_nodes, 1);
+        assert_eq!(report.count(RefcountAuditResult::Missing), 1);
+        assert_eq!(report.entries[0].hash, root);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = report.write_to_dir(dir.path()).unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().contains(&root.to_string()));
+    }
+}
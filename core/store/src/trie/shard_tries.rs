@@ -1,10 +1,14 @@
-use crate::flat::FlatStorageManager;
+use crate::flat::{store_helper, FlatStorageManager};
 use crate::trie::config::TrieConfig;
+use crate::trie::mem::{
+    spawn_mem_trie_consistency_check, MemTrieBuilder, MemTrieConsistencyReport, MemTrieError,
+    MemTries,
+};
 use crate::trie::prefetching_trie_storage::PrefetchingThreadsHandle;
 use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
 use crate::{metrics, DBCol, PrefetchApi};
-use crate::{Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
+use crate::{ReadPriority, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
 use borsh::BorshSerialize;
 use near_primitives::borsh::maybestd::collections::HashMap;
 use near_primitives::errors::StorageError;
@@ -12,8 +16,9 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::{self, ShardUId, ShardVersion};
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
-    NumShards, RawStateChange, RawStateChangesWithTrieKey, StateChangeCause, StateRoot,
+    BlockHeight, NumShards, RawStateChange, RawStateChangesWithTrieKey, StateChangeCause, StateRoot,
 };
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
@@ -36,6 +41,8 @@ struct ShardTriesInner {
     state_snapshot: Arc<RwLock<Option<StateSnapshot>>>,
     /// Configures how to make state snapshots.
     state_snapshot_config: StateSnapshotConfig,
+    /// In-memory tries, for the shards that have them loaded.
+    mem_tries: RwLock<HashMap<ShardUId, Arc<RwLock<MemTries>>>>,
 }
 
 #[derive(Clone)]
@@ -61,6 +68,7 @@ impl ShardTries {
             prefetchers: Default::default(),
             state_snapshot: Arc::new(RwLock::new(None)),
             state_snapshot_config,
+            mem_tries: Default::default(),
         }))
     }
 
@@ -108,6 +116,12 @@ impl ShardTries {
             .collect()
     }
 
+    /// Memory limit for generating state parts from flat storage, see
+    /// `TrieConfig::state_part_memory_limit`.
+    pub fn state_part_memory_limit(&self) -> Option<usize> {
+        self.0.trie_config.state_part_memory_limit
+    }
+
     pub fn new_trie_update(&self, shard_uid: ShardUId, state_root: StateRoot) -> TrieUpdate {
         TrieUpdate::new(self.get_trie_for_shard(shard_uid, state_root))
     }
@@ -158,13 +172,15 @@ impl ShardTries {
                 .clone()
         });
 
-        let storage = Rc::new(TrieCachingStorage::new(
-            self.0.store.clone(),
-            cache,
-            shard_uid,
-            is_view,
-            prefetch_api,
-        ));
+        // View tries serve RPC requests, so their reads must not compete with
+        // block and chunk processing.
+        let store = if is_view {
+            self.0.store.with_priority(ReadPriority::Rpc)
+        } else {
+            self.0.store.clone()
+        };
+        let storage =
+            Rc::new(TrieCachingStorage::new(store, cache, shard_uid, is_view, prefetch_api));
         let flat_storage_chunk_view = block_hash
             .and_then(|block_hash| self.0.flat_storage_manager.chunk_view(shard_uid, block_hash));
 
@@ -189,6 +205,7 @@ impl ShardTries {
                 .or_insert_with(|| TrieCache::new(&self.0.trie_config, shard_uid, true))
                 .clone()
         };
+        let store = store.with_priority(ReadPriority::Rpc);
         let storage = Rc::new(TrieCachingStorage::new(store, cache, shard_uid, true, None));
         let flat_storage_chunk_view = flat_storage_manager.chunk_view(shard_uid, *block_hash);
 
@@ -205,6 +222,30 @@ impl ShardTries {
         self.get_trie_for_shard_internal(shard_uid, state_root, is_view, Some(*block_hash))
     }
 
+    /// Returns a trie which reads through the same shard cache as the tries
+    /// used for applying chunks, so that reading from it warms up that cache
+    /// ahead of applying a chunk. Unlike those tries it doesn't use the
+    /// prefetcher, which assumes a single trie of a shard is read at a time.
+    pub fn get_warm_up_trie_with_block_hash_for_shard(
+        &self,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+        block_hash: &CryptoHash,
+    ) -> Trie {
+        let cache = {
+            let mut caches = self.0.caches.write().expect(POISONED_LOCK_ERR);
+            caches
+                .entry(shard_uid)
+                .or_insert_with(|| TrieCache::new(&self.0.trie_config, shard_uid, false))
+                .clone()
+        };
+        let storage =
+            Rc::new(TrieCachingStorage::new(self.0.store.clone(), cache, shard_uid, false, None));
+        let flat_storage_chunk_view =
+            self.0.flat_storage_manager.chunk_view(shard_uid, *block_hash);
+        Trie::new(storage, state_root, flat_storage_chunk_view)
+    }
+
     pub fn get_view_trie_for_shard(&self, shard_uid: ShardUId, state_root: StateRoot) -> Trie {
         self.get_trie_for_shard_internal(shard_uid, state_root, true, None)
     }
@@ -225,6 +266,175 @@ impl ShardTries {
         self.0.flat_storage_manager.clone()
     }
 
+    /// Creates an empty in-memory trie for the shard, with the arena configured
+    /// by `TrieConfig::mem_trie_arena`.
+    pub fn new_mem_tries(&self, shard_uid: ShardUId) -> MemTries {
+        let config = &self.0.trie_config.mem_trie_arena;
+        MemTries::new_with_growth_policy(&config.growth, config.for_shard(shard_uid), shard_uid)
+    }
+
+    /// Makes the given in-memory trie available for its shard, replacing any
+    /// previously set one.
+    pub fn set_mem_tries(&self, mem_tries: MemTries) -> Arc<RwLock<MemTries>> {
+        let shard_uid = mem_tries.shard_uid();
+        let mem_tries = Arc::new(RwLock::new(mem_tries));
+        self.0.mem_tries.write().expect(POISONED_LOCK_ERR).insert(shard_uid, mem_tries.clone());
+        mem_tries
+    }
+
+    pub fn get_mem_tries(&self, shard_uid: ShardUId) -> Option<Arc<RwLock<MemTries>>> {
+        self.0.mem_tries.read().expect(POISONED_LOCK_ERR).get(&shard_uid).cloned()
+    }
+
+    /// Loads the in-memory trie of the shard with the state as of the flat
+    /// storage head, which must be `state_root` at `block_height`.
+    ///
+    /// If `saved_dir` holds a file written by `save_mem_tries` that has
+    /// `state_root`, the trie is read from it. Otherwise, or if the file can't
+    /// be read, the trie is built from flat storage, which is much slower.
+    pub fn load_mem_tries(
+        &self,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+        block_height: BlockHeight,
+        saved_dir: Option<&Path>,
+    ) -> Result<Arc<RwLock<MemTries>>, MemTrieError> {
+        if let Some(path) = saved_dir.map(|dir| mem_tries_file(dir, shard_uid)) {
+            match self.load_mem_tries_from_file(&path, shard_uid, state_root) {
+                Ok(mem_tries) => {
+                    tracing::info!(target: "memtrie", %shard_uid, %state_root, path = %path.display(), "Loaded mem-trie from file");
+                    return Ok(self.set_mem_tries(mem_tries));
+                }
+                Err(err) => {
+                    tracing::warn!(target: "memtrie", %shard_uid, %state_root, path = %path.display(), %err, "Cannot load mem-trie from file, loading from flat storage")
+                }
+            }
+        }
+        let mut builder = MemTrieBuilder::new(self.new_mem_tries(shard_uid));
+        for entry in store_helper::iter_flat_state_entries(shard_uid, &self.0.store, None, None) {
+            let (key, value) = entry?;
+            builder.add(&key, value)?;
+        }
+        let (mem_tries, loaded_root) = builder.finish(block_height)?;
+        if loaded_root != state_root {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "Mem-trie loaded from flat storage of shard {shard_uid} has root {loaded_root}, expected {state_root}"
+            ))
+            .into());
+        }
+        tracing::info!(target: "memtrie", %shard_uid, %state_root, "Loaded mem-trie from flat storage");
+        Ok(self.set_mem_tries(mem_tries))
+    }
+
+    fn load_mem_tries_from_file(
+        &self,
+        path: &Path,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+    ) -> std::io::Result<MemTries> {
+        let config = &self.0.trie_config.mem_trie_arena;
+        let mem_tries =
+            MemTries::load_from_file(path, &config.growth, config.for_shard(shard_uid), shard_uid)?;
+        if state_root != Trie::EMPTY_ROOT && mem_tries.get_root(&state_root).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the saved mem-trie doesn't have the state root",
+            ));
+        }
+        Ok(mem_tries)
+    }
+
+    /// Saves the in-memory tries of all shards to the given directory, so that
+    /// `load_mem_tries` can read them instead of rebuilding them from flat
+    /// storage after a restart.
+    pub fn save_mem_tries(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (shard_uid, mem_tries) in self.0.mem_tries.read().expect(POISONED_LOCK_ERR).iter() {
+            let path = mem_tries_file(dir, *shard_uid);
+            mem_tries.read().expect(POISONED_LOCK_ERR).save_to_file(&path)?;
+            tracing::info!(target: "memtrie", %shard_uid, path = %path.display(), "Saved mem-trie");
+        }
+        Ok(())
+    }
+
+    /// Applies the trie changes of a chunk to the in-memory trie of the shard,
+    /// if it has one, adding the new state root at the given height. Changes
+    /// on top of a root the in-memory trie doesn't hold are skipped, e.g.
+    /// right after state sync.
+    ///
+    /// If the arena of the in-memory trie is full, the in-memory trie can no
+    /// longer follow the chain, so it is dropped with an error logged.
+    pub fn apply_mem_trie_changes(
+        &self,
+        shard_uid: ShardUId,
+        trie_changes: &TrieChanges,
+        block_height: BlockHeight,
+    ) -> Result<(), StorageError> {
+        let Some(mem_tries) = self.get_mem_tries(shard_uid) else {
+            return Ok(());
+        };
+        let trie = self.get_trie_for_shard(shard_uid, trie_changes.new_root);
+        let result = mem_tries.write().expect(POISONED_LOCK_ERR).apply_trie_changes(
+            &trie,
+            trie_changes,
+            block_height,
+        );
+        match result {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                tracing::debug!(target: "memtrie", %shard_uid, old_root = %trie_changes.old_root, block_height, "Mem-trie doesn't have the old state root, skipping trie changes");
+            }
+            Err(MemTrieError::Arena(err)) => {
+                tracing::error!(target: "memtrie", %shard_uid, block_height, %err, "Cannot apply trie changes to mem-trie, unloading it");
+                self.0.mem_tries.write().expect(POISONED_LOCK_ERR).remove(&shard_uid);
+            }
+            Err(MemTrieError::Storage(err)) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Drops the in-memory trie roots of all shards inserted below the given
+    /// height, normally the GC stop height. Returns the total number of bytes
+    /// reclaimed.
+    pub fn delete_mem_tries_until_height(&self, height: BlockHeight) -> usize {
+        let mem_tries = self.0.mem_tries.read().expect(POISONED_LOCK_ERR);
+        let mut reclaimed = 0;
+        for (shard_uid, tries) in mem_tries.iter() {
+            let shard_reclaimed =
+                tries.write().expect(POISONED_LOCK_ERR).delete_until_height(height);
+            tracing::debug!(target: "memtrie", %shard_uid, height, reclaimed = shard_reclaimed, "Deleted old mem-trie roots");
+            reclaimed += shard_reclaimed;
+        }
+        reclaimed
+    }
+
+    /// Starts comparing the in-memory trie of the shard at `state_root`
+    /// against the disk state at `block_hash` in the background, see
+    /// `spawn_mem_trie_consistency_check`. Returns `None` if the check is
+    /// disabled by `TrieConfig::mem_trie_consistency_check`, or if the shard
+    /// has no in-memory trie with that root.
+    pub fn spawn_mem_trie_consistency_check(
+        &self,
+        shard_uid: ShardUId,
+        state_root: StateRoot,
+        block_hash: CryptoHash,
+    ) -> Option<std::thread::JoinHandle<Option<MemTrieConsistencyReport>>> {
+        let mem_tries = self.get_mem_tries(shard_uid)?;
+        if state_root != Trie::EMPTY_ROOT
+            && mem_tries.read().expect(POISONED_LOCK_ERR).get_root(&state_root).is_none()
+        {
+            tracing::debug!(target: "memtrie", %shard_uid, %state_root, %block_hash, "Mem-trie doesn't have the state root, skipping consistency check");
+            return None;
+        }
+        spawn_mem_trie_consistency_check(
+            &self.0.trie_config.mem_trie_consistency_check,
+            mem_tries,
+            self.clone(),
+            state_root,
+            block_hash,
+        )
+    }
+
     pub(crate) fn state_snapshot_config(&self) -> &StateSnapshotConfig {
         &self.0.state_snapshot_config
     }
@@ -353,12 +563,18 @@ impl ShardTries {
     }
 }
 
+/// File in which `ShardTries::save_mem_tries` saves the in-memory trie of the shard.
+fn mem_tries_file(dir: &Path, shard_uid: ShardUId) -> PathBuf {
+    dir.join(format!("{shard_uid}.memtrie"))
+}
+
 pub struct WrappedTrieChanges {
     tries: ShardTries,
     shard_uid: ShardUId,
     trie_changes: TrieChanges,
     state_changes: Vec<RawStateChangesWithTrieKey>,
     block_hash: CryptoHash,
+    block_height: BlockHeight,
 }
 
 // Partial implementation. Skips `tries` due to its complexity and
@@ -371,6 +587,7 @@ impl std::fmt::Debug for WrappedTrieChanges {
             .field("trie_changes", &"<not shown>")
             .field("state_changes", &"<not shown>")
             .field("block_hash", &self.block_hash)
+            .field("block_height", &self.block_height)
             .finish()
     }
 }
@@ -382,14 +599,35 @@ impl WrappedTrieChanges {
         trie_changes: TrieChanges,
         state_changes: Vec<RawStateChangesWithTrieKey>,
         block_hash: CryptoHash,
+        block_height: BlockHeight,
     ) -> Self {
-        WrappedTrieChanges { tries, shard_uid, trie_changes, state_changes, block_hash }
+        WrappedTrieChanges {
+            tries,
+            shard_uid,
+            trie_changes,
+            state_changes,
+            block_hash,
+            block_height,
+        }
     }
 
     pub fn state_changes(&self) -> &[RawStateChangesWithTrieKey] {
         &self.state_changes
     }
 
+    pub fn shard_uid(&self) -> ShardUId {
+        self.shard_uid
+    }
+
+    pub fn block_height(&self) -> BlockHeight {
+        self.block_height
+    }
+
+    /// Applies the trie changes to the in-memory trie of the shard, if any.
+    pub fn apply_mem_changes(&self) -> Result<(), StorageError> {
+        self.tries.apply_mem_trie_changes(self.shard_uid, &self.trie_changes, self.block_height)
+    }
+
     /// Save insertions of trie nodes into Store.
     pub fn insertions_into(&self, store_update: &mut StoreUpdate) {
         self.tries.apply_insertions(&self.trie_changes, self.shard_uid, store_update)
@@ -591,11 +829,15 @@ impl KeyForStateChanges {
 #[cfg(test)]
 mod test {
     use crate::{
-        config::TrieCacheConfig, test_utils::create_test_store,
-        trie::DEFAULT_SHARD_CACHE_TOTAL_SIZE_LIMIT, TrieConfig,
+        config::{ArenaGrowthPolicy, TrieCacheConfig},
+        test_utils::create_test_store,
+        trie::DEFAULT_SHARD_CACHE_TOTAL_SIZE_LIMIT,
+        TrieConfig,
     };
 
     use super::*;
+    use assert_matches::assert_matches;
+    use near_primitives::state::FlatStateValue;
     use std::{assert_eq, str::FromStr};
 
     #[test]
@@ -677,6 +919,8 @@ mod test {
             enable_receipt_prefetching: false,
             sweat_prefetch_receivers: Vec::new(),
             sweat_prefetch_senders: Vec::new(),
+            flat_storage_delta_pruning: Default::default(),
+            state_part_memory_limit: None,
         };
         let shard_uids = Vec::from([ShardUId { shard_id: 0, version: 0 }]);
         let shard_uid = *shard_uids.first().unwrap();
@@ -726,6 +970,8 @@ mod test {
             enable_receipt_prefetching: false,
             sweat_prefetch_receivers: Vec::new(),
             sweat_prefetch_senders: Vec::new(),
+            flat_storage_delta_pruning: Default::default(),
+            state_part_memory_limit: None,
         };
         let shard_uids = Vec::from([ShardUId { shard_id: 0, version: 0 }]);
         let shard_uid = *shard_uids.first().unwrap();
@@ -756,4 +1002,117 @@ mod test {
         trie.update_cache(insert_ops, shard_uid);
         assert!(trie_caches.read().unwrap().get(&shard_uid).unwrap().get(&key).is_none());
     }
+
+    /// Writes the same key-values to the trie and to flat storage of the
+    /// shard. Returns the state root.
+    fn populate_trie_and_flat_state(tries: &ShardTries, shard_uid: ShardUId) -> StateRoot {
+        let changes: Vec<_> = (0..100u32)
+            .map(|i| (i.to_be_bytes().to_vec(), Some(vec![i as u8; 1 + i as usize])))
+            .collect();
+        let state_root = crate::test_utils::test_populate_trie(
+            tries,
+            &Trie::EMPTY_ROOT,
+            shard_uid,
+            changes.clone(),
+        );
+        let mut store_update = tries.store_update();
+        for (key, value) in changes {
+            let value = value.map(|value| FlatStateValue::on_disk(&value));
+            store_helper::set_flat_state_value(&mut store_update, shard_uid, key, value);
+        }
+        store_update.commit().unwrap();
+        state_root
+    }
+
+    #[test]
+    fn test_load_mem_tries() {
+        let shard_uid = ShardUId::single_shard();
+        let tries = ShardTries::test(create_test_store(), 1);
+        let state_root = populate_trie_and_flat_state(&tries, shard_uid);
+
+        // Nothing was saved yet, so the trie is built from flat storage.
+        let dir = tempfile::tempdir().unwrap();
+        tries.load_mem_tries(shard_uid, state_root, 1, Some(dir.path())).unwrap();
+        assert!(tries
+            .get_mem_tries(shard_uid)
+            .unwrap()
+            .read()
+            .unwrap()
+            .get_root(&state_root)
+            .is_some());
+        assert!(tries.load_mem_tries(shard_uid, CryptoHash::hash_bytes(b"x"), 1, None).is_err());
+        tries.save_mem_tries(dir.path()).unwrap();
+
+        // The saved trie doesn't need flat storage.
+        let empty_tries = ShardTries::test(create_test_store(), 1);
+        let mem_tries =
+            empty_tries.load_mem_tries(shard_uid, state_root, 1, Some(dir.path())).unwrap();
+        assert!(mem_tries.read().unwrap().get_root(&state_root).is_some());
+        // A saved trie without the requested root is ignored.
+        let other_root = CryptoHash::hash_bytes(b"other root");
+        assert!(empty_tries.load_mem_tries(shard_uid, other_root, 1, Some(dir.path())).is_err());
+
+        // A corrupted file is ignored.
+        std::fs::write(mem_tries_file(dir.path(), shard_uid), b"garbage").unwrap();
+        assert!(empty_tries.load_mem_tries(shard_uid, state_root, 1, Some(dir.path())).is_err());
+        tries.load_mem_tries(shard_uid, state_root, 1, Some(dir.path())).unwrap();
+    }
+
+    #[test]
+    fn test_mem_tries_metrics() {
+        // A shard not used by other tests, since the metrics are global.
+        let shard_uid = ShardUId { version: 0, shard_id: 3 };
+        let tries = ShardTries::test(create_test_store(), 4);
+        let state_root = populate_trie_and_flat_state(&tries, shard_uid);
+        let leaf_count = || {
+            crate::metrics::MEM_TRIE_NODE_COUNT
+                .with_label_values(&[&shard_uid.to_string(), "leaf"])
+                .get()
+        };
+
+        let mem_tries = tries.load_mem_tries(shard_uid, state_root, 1, None).unwrap();
+        let stats = mem_tries.read().unwrap().stats().clone();
+        assert_eq!(stats, mem_tries.read().unwrap().compute_stats());
+        assert_eq!(stats.leaf.count, 100);
+        assert_eq!(leaf_count(), 100);
+
+        // The metrics follow the accounting as the roots are garbage collected.
+        assert!(tries.delete_mem_tries_until_height(2) > 0);
+        assert_eq!(mem_tries.read().unwrap().stats().total_nodes().count, 0);
+        assert_eq!(leaf_count(), 0);
+        let fragmentation = crate::metrics::MEM_TRIE_ARENA_FRAGMENTATION
+            .with_label_values(&[&shard_uid.to_string()])
+            .get();
+        assert_eq!(fragmentation, 1.0);
+    }
+
+    #[test]
+    fn test_mem_tries_out_of_memory() {
+        let shard_uid = ShardUId::single_shard();
+        let store = create_test_store();
+        let mut trie_config = TrieConfig::default();
+        trie_config.mem_trie_arena.growth = ArenaGrowthPolicy::fixed(1 << 12);
+        let tries = ShardTries::new(
+            store.clone(),
+            trie_config,
+            &[shard_uid],
+            FlatStorageManager::new(store),
+        );
+        let state_root = populate_trie_and_flat_state(&tries, shard_uid);
+
+        // Loading fails instead of panicking when the arena is full.
+        assert_matches!(
+            tries.load_mem_tries(shard_uid, state_root, 1, None),
+            Err(MemTrieError::Arena(_))
+        );
+
+        // A mem-trie that fills up while following the chain is unloaded.
+        tries.set_mem_tries(tries.new_mem_tries(shard_uid));
+        let trie_changes = tries
+            .get_trie_for_shard(shard_uid, Trie::EMPTY_ROOT)
+            .update((0..100u32).map(|i| (i.to_be_bytes().to_vec(), Some(vec![1; 100]))))
+            .unwrap();
+        tries.apply_mem_trie_changes(shard_uid, &trie_changes, 1).unwrap();
+        assert!(tries.get_mem_tries(shard_uid).is_none());
+    }
 }
@@ -307,6 +307,135 @@ impl Trie {
         Ok(final_state_part_nodes)
     }
 
+    /// Creates state part using only the flat storage, like
+    /// `get_trie_nodes_for_part_with_flat_storage`, but without holding all
+    /// key-values of the part in memory at once.
+    ///
+    /// Key-values are read from flat storage in increasing order of keys and
+    /// added to a local trie in batches of at most `memory_limit` bytes, so
+    /// that only the nodes of the local trie are kept for the whole part.
+    pub fn get_trie_nodes_for_part_with_flat_storage_streaming(
+        &self,
+        part_id: PartId,
+        partial_state: PartialState,
+        nibbles_begin: Vec<u8>,
+        nibbles_end: Vec<u8>,
+        state_trie: &Trie,
+        memory_limit: usize,
+    ) -> Result<PartialState, StorageError> {
+        let shard_id: ShardId = self.flat_storage_chunk_view.as_ref().map_or(
+            ShardId::MAX, // Fake value for metrics.
+            |chunk_view| chunk_view.shard_uid().shard_id as ShardId,
+        );
+        let _span = tracing::debug_span!(
+            target: "state-parts",
+            "get_trie_nodes_for_part_with_flat_storage_streaming",
+            ?shard_id,
+            part_id = part_id.idx,
+            num_parts = part_id.total)
+        .entered();
+        let _timer = metrics::GET_STATE_PART_NODES_WITH_FS_ELAPSED
+            .with_label_values(&[&shard_id.to_string()])
+            .start_timer();
+
+        let PartialState::TrieValues(path_boundary_nodes) = partial_state;
+
+        // 1. Read key-values from flat storage, looking up referenced values
+        // in State, and add them to the local trie batch by batch.
+        let local_trie_creation_timer = metrics::GET_STATE_PART_CREATE_TRIE_ELAPSED
+            .with_label_values(&[&shard_id.to_string()])
+            .start_timer();
+        let mut local_nodes = HashMap::new();
+        let mut local_root = StateRoot::new();
+        let mut disk_read_hashes = HashSet::new();
+        let mut values_inlined = 0;
+        let mut values_ref = 0;
+        let mut num_batches = 0;
+        let mut batch = vec![];
+        let mut batch_size = 0;
+        for item in self.iter_flat_state_entries(nibbles_begin, nibbles_end)? {
+            let (key, value) = item?;
+            let value = match value {
+                FlatStateValue::Ref(value_ref) => {
+                    values_ref += 1;
+                    disk_read_hashes.insert(value_ref.hash);
+                    state_trie.retrieve_value(&value_ref.hash)?.to_vec()
+                }
+                FlatStateValue::Inlined(value) => {
+                    values_inlined += 1;
+                    value
+                }
+            };
+            batch_size += key.len() + value.len();
+            batch.push((key, Some(value)));
+            if batch_size >= memory_limit {
+                local_root =
+                    update_local_trie(&mut local_nodes, local_root, std::mem::take(&mut batch))?;
+                batch_size = 0;
+                num_batches += 1;
+            }
+        }
+        if !batch.is_empty() {
+            local_root = update_local_trie(&mut local_nodes, local_root, batch)?;
+            num_batches += 1;
+        }
+        let local_trie_creation_duration = local_trie_creation_timer.stop_and_record();
+
+        // 2. Unite all nodes in memory, traverse trie based on them, return set of visited nodes.
+        let final_part_creation_timer = metrics::GET_STATE_PART_COMBINE_ELAPSED
+            .with_label_values(&[&shard_id.to_string()])
+            .start_timer();
+        let mut all_nodes = local_nodes;
+        for entry in path_boundary_nodes.iter() {
+            let node_hash = hash(entry);
+            disk_read_hashes.insert(node_hash);
+            all_nodes.insert(node_hash, entry.clone());
+        }
+        let final_trie =
+            Trie::new(Rc::new(TrieMemoryPartialStorage::new(all_nodes)), self.root, None);
+
+        final_trie.visit_nodes_for_state_part(part_id)?;
+        let final_trie_storage = final_trie.storage.as_partial_storage().unwrap();
+        let final_state_part_nodes = final_trie_storage.partial_state();
+        let PartialState::TrieValues(trie_values) = &final_state_part_nodes;
+        let final_part_creation_duration = final_part_creation_timer.stop_and_record();
+
+        // Compute how many nodes were recreated from memory.
+        let state_part_num_nodes = trie_values.len();
+        let in_memory_created_nodes =
+            trie_values.iter().filter(|entry| !disk_read_hashes.contains(&hash(*entry))).count();
+        tracing::debug!(
+            target: "state-parts",
+            ?part_id,
+            %values_ref,
+            %values_inlined,
+            %num_batches,
+            %in_memory_created_nodes,
+            %state_part_num_nodes,
+            ?local_trie_creation_duration,
+            ?final_part_creation_duration,
+            "Created state part",
+        );
+
+        metrics::GET_STATE_PART_WITH_FS_VALUES_INLINED
+            .with_label_values(&[&shard_id.to_string()])
+            .inc_by(values_inlined);
+        metrics::GET_STATE_PART_WITH_FS_VALUES_REF
+            .with_label_values(&[&shard_id.to_string()])
+            .inc_by(values_ref);
+        metrics::GET_STATE_PART_WITH_FS_NODES_FROM_DISK
+            .with_label_values(&[&shard_id.to_string()])
+            .inc_by(disk_read_hashes.len() as u64);
+        metrics::GET_STATE_PART_WITH_FS_NODES_IN_MEMORY
+            .with_label_values(&[&shard_id.to_string()])
+            .inc_by(in_memory_created_nodes as u64);
+        metrics::GET_STATE_PART_WITH_FS_NODES
+            .with_label_values(&[&shard_id.to_string()])
+            .inc_by(state_part_num_nodes as u64);
+
+        Ok(final_state_part_nodes)
+    }
+
     /// Assume we lay out all trie nodes in dfs order visiting children after the parent.
     /// We take all node sizes (memory_usage_direct()) and take all nodes intersecting with
     /// [size_start, size_end) interval, also all nodes necessary to prove it and some
@@ -502,6 +631,27 @@ impl Trie {
     }
 }
 
+/// Adds key-values to the local trie with the given root whose nodes are all
+/// in `nodes`, and returns its new root. Nodes which are no longer reachable
+/// from the new root are kept, as there are only a few of them per batch and
+/// they are never visited when creating the part.
+fn update_local_trie(
+    nodes: &mut HashMap<CryptoHash, Arc<[u8]>>,
+    root: StateRoot,
+    changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+) -> Result<StateRoot, StorageError> {
+    let storage = Rc::new(TrieMemoryPartialStorage::new(std::mem::take(nodes)));
+    let trie_changes = Trie::new(storage.clone(), root, None).update(changes)?;
+    *nodes = Rc::try_unwrap(storage).ok().expect("local trie must be dropped").recorded_storage;
+    nodes.extend(
+        trie_changes
+            .insertions
+            .iter()
+            .map(|entry| (*entry.hash(), entry.payload().to_vec().into())),
+    );
+    Ok(trie_changes.new_root)
+}
+
 #[cfg(test)]
 mod tests {
     use assert_matches::assert_matches;
@@ -1265,4 +1415,63 @@ mod tests {
             ))
         );
     }
+
+    /// Checks that generating state part from flat storage in batches gives
+    /// the same part regardless of the memory limit.
+    #[test]
+    fn get_trie_nodes_for_part_with_flat_storage_streaming() {
+        let tries = create_tries_with_flat_storage();
+        let shard_uid = ShardUId::single_shard();
+        let block_hash = CryptoHash::default();
+        let trie = tries.get_trie_for_shard(shard_uid, Trie::EMPTY_ROOT);
+
+        let mut rng = rand::thread_rng();
+        let state_items: Vec<_> = (0..200)
+            .map(|_| {
+                let key_len = rng.gen_range(1..6);
+                let key: Vec<u8> = (0..key_len).map(|_| rng.gen_range(0..4) * 0x11).collect();
+                (key, vec![rng.gen(); rng.gen_range(1..300)])
+            })
+            .collect::<HashMap<_, _>>()
+            .into_iter()
+            .collect();
+        let changes_for_trie = state_items.iter().cloned().map(|(k, v)| (k, Some(v)));
+        let trie_changes = trie.update(changes_for_trie).unwrap();
+        let mut store_update = tries.store_update();
+        let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+        let changes_for_delta = state_items.into_iter().map(|(k, v)| {
+            let value = if v.len() > 100 {
+                FlatStateValue::on_disk(&v)
+            } else {
+                FlatStateValue::inlined(&v)
+            };
+            (k, Some(value))
+        });
+        FlatStateChanges::from(changes_for_delta).apply_to_flat_state(&mut store_update, shard_uid);
+        store_update.commit().unwrap();
+
+        let trie_without_flat = tries.get_view_trie_for_shard(shard_uid, root);
+        let view_chunk_trie =
+            tries.get_trie_with_block_hash_for_shard(shard_uid, root, &block_hash, true);
+        let num_parts = 5;
+        for part_id in 0..num_parts {
+            let part_id = PartId::new(part_id, num_parts);
+            let state_part =
+                trie_without_flat.get_trie_nodes_for_part_without_flat_storage(part_id).unwrap();
+            let (partial_state, nibbles_begin, nibbles_end) =
+                trie_without_flat.get_state_part_boundaries(part_id).unwrap();
+            for memory_limit in [1, 1000, usize::MAX] {
+                let state_part_with_flat = view_chunk_trie
+                    .get_trie_nodes_for_part_with_flat_storage_streaming(
+                        part_id,
+                        partial_state.clone(),
+                        nibbles_begin.clone(),
+                        nibbles_end.clone(),
+                        &trie_without_flat,
+                        memory_limit,
+                    );
+                assert_eq!(state_part_with_flat, Ok(state_part.clone()));
+            }
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use crate::db::STATE_SNAPSHOT_KEY;
 use crate::flat::FlatStorageManager;
+use crate::intent_log::{Intent, IntentLog};
 use crate::Mode;
 use crate::{checkpoint_hot_storage_and_cleanup_columns, metrics, DBCol, NodeStorage};
 use crate::{option_to_not_found, ShardTries};
-use crate::{Store, StoreConfig};
+use crate::{EncryptionKeySource, Store, StoreConfig};
 use near_primitives::block::Block;
 use near_primitives::errors::EpochError;
 use near_primitives::errors::StorageError;
@@ -79,6 +80,9 @@ pub enum StateSnapshotConfig {
         hot_store_path: PathBuf,
         state_snapshot_subdir: PathBuf,
         compaction_enabled: bool,
+        /// Key the hot store is encrypted with, if any.  Snapshots are
+        /// encrypted with the same key.
+        encryption: Option<EncryptionKeySource>,
     },
 }
 
@@ -135,6 +139,7 @@ impl ShardTries {
                 hot_store_path,
                 state_snapshot_subdir,
                 compaction_enabled: _,
+                encryption,
             } => {
                 let _timer = metrics::MAKE_STATE_SNAPSHOT_ELAPSED.start_timer();
                 // `write()` lock is held for the whole duration of this function.
@@ -191,14 +196,22 @@ impl ShardTries {
                     }
                 }
 
+                let snapshot_path = Self::get_state_snapshot_base_dir(
+                    prev_block_hash,
+                    home_dir,
+                    hot_store_path,
+                    state_snapshot_subdir,
+                );
+                // The snapshot on the file system and STATE_SNAPSHOT_KEY can't
+                // be updated atomically.  If the node crashes in between, the
+                // new snapshot is deleted when the storage is opened again.
+                let intent_log = IntentLog::new(self.get_store());
+                let intent = intent_log.begin(Intent::ReplaceStateSnapshot {
+                    path: snapshot_path.display().to_string(),
+                })?;
                 let storage = checkpoint_hot_storage_and_cleanup_columns(
                     &self.get_store(),
-                    &Self::get_state_snapshot_base_dir(
-                        prev_block_hash,
-                        home_dir,
-                        hot_store_path,
-                        state_snapshot_subdir,
-                    ),
+                    &snapshot_path,
                     // TODO: Cleanup Changes and DeltaMetadata to avoid extra memory usage.
                     // Can't be cleaned up now because these columns are needed to `update_flat_head()`.
                     Some(vec![
@@ -211,6 +224,7 @@ impl ShardTries {
                         DBCol::FlatStateDeltaMetadata,
                         DBCol::FlatStorageStatus,
                     ]),
+                    encryption.as_ref(),
                 )?;
                 let store = storage.get_hot_store();
                 // It is fine to create a separate FlatStorageManager, because
@@ -240,6 +254,8 @@ impl ShardTries {
                     }
                 }
 
+                intent.finish()?;
+
                 metrics::HAS_STATE_SNAPSHOT.set(1);
                 tracing::info!(target: "state_snapshot", ?prev_block_hash, "Made a checkpoint");
                 Ok(())
@@ -338,6 +354,7 @@ impl ShardTries {
                 hot_store_path,
                 state_snapshot_subdir,
                 compaction_enabled: _,
+                encryption,
             } => {
                 // directly return error if no snapshot is found
                 let snapshot_hash: CryptoHash = self.get_state_snapshot_hash()?;
@@ -353,7 +370,8 @@ impl ShardTries {
                     .ok_or(anyhow::anyhow!("{snapshot_path:?} needs to have a parent dir"))?;
                 tracing::debug!(target: "state_snapshot", ?snapshot_path, ?parent_path);
 
-                let store_config = StoreConfig::default();
+                let mut store_config = StoreConfig::default();
+                store_config.encryption = encryption.clone();
 
                 let opener = NodeStorage::opener(&snapshot_path, false, &store_config, None);
                 let storage = opener.open_in_mode(Mode::ReadOnly)?;
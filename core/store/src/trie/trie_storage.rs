@@ -1,6 +1,6 @@
 use crate::trie::config::TrieConfig;
 use crate::trie::prefetching_trie_storage::PrefetcherResult;
-use crate::trie::POISONED_LOCK_ERR;
+use crate::trie::{TrieChanges, POISONED_LOCK_ERR};
 use crate::{metrics, DBCol, MissingTrieValueContext, PrefetchApi, StorageError, Store};
 use lru::LruCache;
 use near_o11y::log_assert;
@@ -12,6 +12,7 @@ use near_primitives::shard_layout::ShardUId;
 use near_primitives::types::ShardId;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 pub(crate) struct BoundedQueue<T> {
@@ -296,6 +297,36 @@ pub trait TrieStorage {
     }
 }
 
+/// Storage with the nodes and values inserted by trie changes, which are not written to
+/// the database, on top of another storage. See `Trie::with_changes_in_memory`.
+pub(crate) struct TrieOverlayStorage {
+    base: Rc<dyn TrieStorage>,
+    insertions: HashMap<CryptoHash, Arc<[u8]>>,
+}
+
+impl TrieOverlayStorage {
+    pub(crate) fn new<'a>(
+        base: Rc<dyn TrieStorage>,
+        changes: impl IntoIterator<Item = &'a TrieChanges>,
+    ) -> Self {
+        let insertions = changes
+            .into_iter()
+            .flat_map(|changes| changes.insertions())
+            .map(|insertion| (*insertion.hash(), insertion.payload().into()))
+            .collect();
+        Self { base, insertions }
+    }
+}
+
+impl TrieStorage for TrieOverlayStorage {
+    fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        match self.insertions.get(hash) {
+            Some(bytes) => Ok(bytes.clone()),
+            None => self.base.retrieve_raw_bytes(hash),
+        }
+    }
+}
+
 /// Storage for validating recorded partial storage.
 /// visited_nodes are to validate that partial storage doesn't contain unnecessary nodes.
 #[derive(Default)]
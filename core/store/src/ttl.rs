@@ -0,0 +1,220 @@
+//! Expiry of values in columns holding ephemeral data.
+//!
+//! Values written to columns with [`DBCol::default_ttl`] set are only useful
+//! for a limited time, e.g. state parts cached for state sync.  Whenever such
+//! value is written, [`crate::StoreUpdate::commit`] records the time of the
+//! write in an index kept in `DBCol::Misc`.  [`delete_expired`], run
+//! periodically by [`spawn_ttl_loop`], deletes values written longer than
+//! the column’s TTL ago together with their index entries.
+//!
+//! The lifetime of a value counts from its latest write, i.e. rewriting a
+//! value, e.g. caching the same state part again, extends it.  Values may also
+//! be deleted explicitly before they expire; their index entries are then
+//! removed once they expire.
+
+use crate::db::{DBOp, DBTransaction};
+use crate::{metrics, DBCol, NodeStorage, Store, StoreConfig};
+use actix_rt::ArbiterHandle;
+use std::collections::HashSet;
+use std::io;
+use std::time::Duration;
+use strum::IntoEnumIterator;
+
+/// Prefix of keys of the index entries in `DBCol::Misc`.  The prefix is
+/// followed by column name, a colon, big-endian write time in seconds since
+/// Unix epoch and the key of the value.
+const TTL_KEY_PREFIX: &[u8] = b"TTL:";
+
+/// How often [`spawn_ttl_loop`] deletes expired values.
+const DELETE_EXPIRED_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum size of a batch of deletions written to the database.
+const DELETE_BATCH_SIZE: usize = 16 * bytesize::MIB as usize;
+
+fn index_prefix(col: DBCol) -> Vec<u8> {
+    [TTL_KEY_PREFIX, <&str>::from(col).as_bytes(), b":"].concat()
+}
+
+/// Returns the upper bound of the keys of the index entries of the column.
+fn index_end(col: DBCol) -> Vec<u8> {
+    [TTL_KEY_PREFIX, <&str>::from(col).as_bytes(), b";"].concat()
+}
+
+/// Returns the key of the value the index entry is for.
+fn indexed_key<'a>(index_key: &'a [u8], prefix: &[u8]) -> &'a [u8] {
+    &index_key[prefix.len() + std::mem::size_of::<u64>()..]
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Time returned by [`unix_time_secs`] in tests.
+    static FAKE_TIME_SECS: std::cell::Cell<u64> = std::cell::Cell::new(0);
+}
+
+#[cfg(not(test))]
+fn unix_time_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+fn unix_time_secs() -> u64 {
+    FAKE_TIME_SECS.with(|time| time.get())
+}
+
+/// Adds index entries of values written to ephemeral columns by the
+/// transaction or ingested with it.
+pub(crate) fn add_index_entries(
+    transaction: &mut DBTransaction,
+    ingests: &[(DBCol, Vec<(Vec<u8>, Vec<u8>)>)],
+) {
+    let mut entries = Vec::new();
+    let mut now = None;
+    let mut add_entry = |col: DBCol, key: &[u8]| {
+        let now = *now.get_or_insert_with(unix_time_secs);
+        entries.push([&index_prefix(col)[..], &now.to_be_bytes(), key].concat());
+    };
+    for op in &transaction.ops {
+        match op {
+            DBOp::Set { col, key, .. } | DBOp::Insert { col, key, .. }
+                if col.default_ttl().is_some() =>
+            {
+                add_entry(*col, key)
+            }
+            _ => {}
+        }
+    }
+    for (col, ingested) in ingests {
+        if col.default_ttl().is_some() {
+            for (key, _) in ingested {
+                add_entry(*col, key);
+            }
+        }
+    }
+    for key in entries {
+        transaction.set(DBCol::Misc, key, Vec::new());
+    }
+}
+
+/// Deletes values of ephemeral columns written longer than the column’s TTL
+/// ago.  Returns number of deleted values.
+pub fn delete_expired(store: &Store, config: &StoreConfig) -> io::Result<u64> {
+    let _timer = metrics::TTL_DELETE_EXPIRED_ELAPSED.start_timer();
+    let now = unix_time_secs();
+    let mut total = 0;
+    for col in DBCol::iter() {
+        let Some(ttl) = config.col_ttl(col) else { continue };
+        let prefix = index_prefix(col);
+        let cutoff = now.saturating_sub(ttl.as_secs());
+        let upper_bound = [&prefix[..], &cutoff.to_be_bytes()].concat();
+
+        let mut update = store.store_update();
+        // The same key may have multiple index entries if it was rewritten.
+        let mut deleted = HashSet::new();
+        for item in store.iter_range(DBCol::Misc, Some(&prefix), Some(&upper_bound)) {
+            let (index_key, _) = item?;
+            deleted.insert(indexed_key(&index_key, &prefix).to_vec());
+            update.delete(DBCol::Misc, &index_key);
+        }
+        if !deleted.is_empty() {
+            // Values rewritten since the cutoff expire with their latest write.
+            let end = index_end(col);
+            for item in store.iter_range(DBCol::Misc, Some(&upper_bound), Some(&end)) {
+                let (index_key, _) = item?;
+                deleted.remove(indexed_key(&index_key, &prefix));
+            }
+        }
+        for key in &deleted {
+            update.delete(col, key);
+        }
+        update.commit_in_chunks(DELETE_BATCH_SIZE, |_| Ok(()))?;
+
+        if !deleted.is_empty() {
+            tracing::debug!(target: "store", %col, count = deleted.len(), "Deleted expired values");
+        }
+        metrics::TTL_EXPIRED_VALUES
+            .with_label_values(&[<&str>::from(col)])
+            .inc_by(deleted.len() as u64);
+        total += deleted.len() as u64;
+    }
+    Ok(total)
+}
+
+/// Spawns a loop periodically deleting expired values from the hot store.
+pub fn spawn_ttl_loop(storage: &NodeStorage, config: StoreConfig) -> ArbiterHandle {
+    tracing::debug!(target: "store", "Spawning the TTL loop.");
+    let arbiter = actix_rt::Arbiter::new();
+
+    let start = tokio::time::Instant::now();
+    let mut interval = actix_rt::time::interval_at(start, DELETE_EXPIRED_PERIOD);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let store = storage.get_hot_store();
+    arbiter.spawn(async move {
+        loop {
+            interval.tick().await;
+            if let Err(err) = delete_expired(&store, &config) {
+                tracing::error!(target: "store", ?err, "Failed to delete expired values");
+            }
+        }
+    });
+
+    arbiter.handle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_expired, index_prefix, FAKE_TIME_SECS};
+    use crate::config::ColumnConfig;
+    use crate::db::TestDB;
+    use crate::{DBCol, NodeStorage, StoreConfig};
+    use std::time::Duration;
+
+    fn set_time(secs: u64) {
+        FAKE_TIME_SECS.with(|time| time.set(secs));
+    }
+
+    #[test]
+    fn test_delete_expired() {
+        set_time(1000);
+        let store = NodeStorage::new(TestDB::new()).get_hot_store();
+        let mut update = store.store_update();
+        update.set(DBCol::StateParts, &[1], &[1]);
+        update.set(DBCol::BlockMisc, &[2], &[2]);
+        update.bulk_ingest(DBCol::StateParts, vec![(vec![3], vec![3])]);
+        update.set(DBCol::StateParts, &[4], &[4]);
+        update.commit().unwrap();
+        let index = || store.iter_prefix(DBCol::Misc, &index_prefix(DBCol::StateParts)).count();
+        // Ingested values are indexed as well.
+        assert_eq!(index(), 3);
+
+        // Nothing has expired yet.
+        let mut config = StoreConfig::test_config();
+        config.column_configs.insert(
+            "StateParts".to_string(),
+            ColumnConfig { ttl: Some(Duration::from_secs(60)), ..Default::default() },
+        );
+        set_time(1030);
+        assert_eq!(delete_expired(&store, &config).unwrap(), 0);
+        assert!(store.exists(DBCol::StateParts, &[1]).unwrap());
+
+        // Rewriting a value extends its lifetime.
+        let mut update = store.store_update();
+        update.set(DBCol::StateParts, &[4], &[4]);
+        update.commit().unwrap();
+        assert_eq!(index(), 4);
+
+        // Only values of ephemeral columns expire.
+        set_time(1070);
+        assert_eq!(delete_expired(&store, &config).unwrap(), 2);
+        assert!(!store.exists(DBCol::StateParts, &[1]).unwrap());
+        assert!(!store.exists(DBCol::StateParts, &[3]).unwrap());
+        assert!(store.exists(DBCol::StateParts, &[4]).unwrap());
+        assert!(store.exists(DBCol::BlockMisc, &[2]).unwrap());
+        assert_eq!(index(), 1);
+
+        set_time(1100);
+        assert_eq!(delete_expired(&store, &config).unwrap(), 1);
+        assert!(!store.exists(DBCol::StateParts, &[4]).unwrap());
+        assert_eq!(index(), 0);
+    }
+}
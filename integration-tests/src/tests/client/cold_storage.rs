@@ -375,6 +375,17 @@ fn test_cold_db_copy_with_height_skips() {
             );
         }
     }
+
+    // Skipped heights are not counted in copy progress.
+    let progress = storage.cold_db().unwrap().copy_progress();
+    let num_copied_heights = max_height - 1 - skips.len() as u64;
+    assert_eq!(progress.heights_copied, num_copied_heights);
+    for col in DBCol::iter() {
+        if col.is_cold() {
+            assert_eq!(progress.columns[&col].heights_copied, num_copied_heights);
+        }
+    }
+    assert!(progress.columns[&DBCol::Block].bytes_copied > 0);
 }
 
 /// Producing 4 epochs of blocks with some transactions.
@@ -1,3 +1,4 @@
+use near_chain::epoch_sync::validate_epoch_sync_proof;
 use near_chain::{ChainGenesis, Provenance};
 use near_chain_configs::Genesis;
 use near_client::test_utils::TestEnv;
@@ -7,6 +8,7 @@ use near_o11y::testonly::init_test_logger;
 use near_primitives::transaction::{
     Action, DeployContractAction, FunctionCallAction, SignedTransaction,
 };
+use near_primitives::types::EpochId;
 use near_primitives_core::hash::CryptoHash;
 use near_primitives_core::types::BlockHeight;
 use nearcore::config::GenesisExt;
@@ -96,3 +98,56 @@ fn test_continuous_epoch_sync_info_population() {
         }
     }
 }
+
+/// Produce 4 epochs and check that the proofs of the starts of epochs are
+/// valid one after another, starting from the genesis epoch info, and that
+/// another node can continue header sync from the last one.
+#[test]
+fn test_epoch_sync_proofs() {
+    init_test_logger();
+
+    let epoch_length = 5;
+    let max_height = epoch_length * 4 + 3;
+
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    for h in 1..max_height {
+        let block = env.clients[0].produce_block(h).unwrap().unwrap();
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+
+    let genesis_header = env.clients[0].chain.genesis().clone();
+    let mut prev_epoch_id = genesis_header.epoch_id().clone();
+    let mut epoch_id = EpochId(*genesis_header.hash());
+    let mut epoch_info = env.clients[0].epoch_manager.get_epoch_info(&epoch_id).unwrap();
+    let mut proofs = vec![];
+    while let Some(proof) = env.clients[0].chain.get_epoch_sync_proof(&prev_epoch_id).unwrap() {
+        validate_epoch_sync_proof(&proof, &epoch_id, &epoch_info).unwrap();
+
+        let mut forged = proof.clone();
+        forged.next_header = forged.first_header.clone();
+        assert!(validate_epoch_sync_proof(&forged, &epoch_id, &epoch_info).is_err());
+
+        prev_epoch_id = epoch_id;
+        epoch_id = proof.first_header.next_epoch_id().clone();
+        epoch_info = proof.data.5.clone();
+        proofs.push(proof);
+    }
+    // The block after the first block of the last epoch isn't final yet.
+    assert_eq!(proofs.len(), 3);
+
+    let proof = proofs.pop().unwrap();
+    let last_hash = *proof.prev_epoch_sync_info.last.header.hash();
+    env.clients[1].chain.apply_epoch_sync_proof(proof).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().last_block_hash, last_hash);
+    assert_eq!(env.clients[1].chain.final_head().unwrap().last_block_hash, last_hash);
+}
@@ -1,5 +1,6 @@
 /// Tests which check correctness of background flat storage creation.
 use assert_matches::assert_matches;
+use near_chain::flat_storage_creator::FlatStorageShardCreator;
 use near_chain::{ChainGenesis, Provenance};
 use near_chain_configs::Genesis;
 use near_client::test_utils::TestEnv;
@@ -8,6 +9,7 @@ use near_crypto::{InMemorySigner, KeyType};
 use near_o11y::testonly::init_test_logger;
 use near_primitives::errors::StorageError;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
+use near_primitives::state::FlatStateValue;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::AccountId;
@@ -17,7 +19,7 @@ use near_store::flat::{
     FlatStorageReadyStatus, FlatStorageStatus, NUM_PARTS_IN_ONE_STEP,
 };
 use near_store::test_utils::create_test_store;
-use near_store::{KeyLookupMode, Store, TrieTraversalItem};
+use near_store::{DBCol, KeyLookupMode, Store, TrieTraversalItem};
 use near_vm_runner::logic::TrieNodesCount;
 use nearcore::config::GenesisExt;
 use nearcore::test_utils::TestEnvNightshadeSetupExt;
@@ -397,6 +399,68 @@ fn test_flat_storage_creation_start_from_state_part() {
     }
 }
 
+/// Check that flat storage with corrupted status can be rebuilt, and that the rebuild resumes after restart.
+#[test]
+fn test_flat_storage_rebuild_after_corruption() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let shard_uid = genesis.config.shard_layout.get_shard_uids()[0];
+    let store = create_test_store();
+
+    {
+        let mut env = setup_env(&genesis, store.clone());
+        for height in 1..START_HEIGHT {
+            env.produce_block(0, height);
+        }
+
+        // Corrupt flat storage status and some flat state value.
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::FlatStorageStatus, &shard_uid.to_bytes(), &[255, 255, 255]);
+        store_helper::set_flat_state_value(
+            &mut store_update,
+            shard_uid,
+            vec![1, 2, 3],
+            Some(FlatStateValue::inlined(&[4, 5, 6])),
+        );
+        store_update.commit().unwrap();
+        assert!(store_helper::get_flat_storage_status(&store, shard_uid).is_err());
+
+        let client = &env.clients[0];
+        let creator = FlatStorageShardCreator::new(
+            shard_uid,
+            START_HEIGHT - 1,
+            client.epoch_manager.clone(),
+            client.runtime_adapter.clone(),
+        );
+        let status = creator.start_rebuild(client.chain.store()).unwrap();
+        assert_eq!(status.part_id, 0);
+        assert_eq!(status.progress_percent(), 0.0);
+        assert_eq!(
+            store_helper::get_flat_storage_status(&store, shard_uid),
+            Ok(FlatStorageStatus::Creation(FlatStorageCreationStatus::FetchingState(status)))
+        );
+        let db_key = store_helper::encode_flat_state_db_key(shard_uid, &[1, 2, 3]);
+        assert!(store.get(DBCol::FlatState, &db_key).unwrap().is_none());
+    }
+
+    // Restart the node, check that the rebuild is resumed and finished.
+    let mut env = setup_env(&genesis, store);
+    assert!(get_flat_storage_manager(&env).get_flat_storage_for_shard(shard_uid).is_none());
+    let next_height = wait_for_flat_storage_creation(&mut env, START_HEIGHT, shard_uid, true);
+
+    let block_hash = env.clients[0].chain.get_block_hash_by_height(next_height - 1).unwrap();
+    let state_root =
+        *env.clients[0].chain.get_chunk_extra(&block_hash, &shard_uid).unwrap().state_root();
+    let trie = env.clients[0]
+        .chain
+        .runtime_adapter
+        .get_trie_for_shard(0, &block_hash, state_root, true)
+        .unwrap();
+    let key = TrieKey::Account { account_id: "test0".parse().unwrap() }.to_vec();
+    assert_matches!(trie.get_ref(&key, KeyLookupMode::FlatStorage), Ok(Some(_)));
+    assert_eq!(trie.get_trie_nodes_count(), TrieNodesCount { db_reads: 0, mem_reads: 0 });
+}
+
 /// Tests the scenario where we start flat storage migration, and get just a few new blocks.
 /// (in this test we still generate 3 blocks in order to generate deltas).
 #[test]
@@ -13,7 +13,8 @@ use near_chain::test_utils::ValidatorSchedule;
 use near_chain::types::{LatestKnown, RuntimeAdapter};
 use near_chain::validate::validate_chunk_with_chunk_extra;
 use near_chain::{
-    Block, BlockProcessingArtifact, ChainGenesis, ChainStore, ChainStoreAccess, Error, Provenance,
+    replay_chunk, Block, BlockProcessingArtifact, ChainGenesis, ChainStore, ChainStoreAccess,
+    ChunkReplayArtifact, Error, Provenance,
 };
 use near_chain_configs::{Genesis, DEFAULT_GC_NUM_EPOCHS_TO_KEEP};
 use near_chunks::test_utils::MockClientAdapterForShardsManager;
@@ -1034,7 +1035,7 @@ fn client_sync_headers() {
             false,
             false,
             Box::new(move |msg, _ctx, _client_actor| match msg.as_network_requests_ref() {
-                NetworkRequests::BlockHeadersRequest { hashes, peer_id } => {
+                NetworkRequests::BlockHeadersRequest { hashes, peer_id, .. } => {
                     assert_eq!(*peer_id, peer_info1.id);
                     assert_eq!(hashes.len(), 1);
                     // TODO: check it requests correct hashes.
@@ -1055,6 +1056,7 @@ fn client_sync_headers() {
                             last_block: Some(BlockInfo { height: 5, hash: hash(&[5]) }),
                             tracked_shards: vec![],
                             archival: false,
+                            snapshot_host_info: None,
                         },
                     },
                     received_bytes_per_sec: 0,
@@ -1064,6 +1066,7 @@ fn client_sync_headers() {
                     connection_established_time: near_async::time::Instant::now(),
                     peer_type: PeerType::Outbound,
                     nonce: 1,
+                    reputation: 0.,
                 }],
                 num_connected_peers: 1,
                 peer_max_count: 1,
@@ -1074,6 +1077,7 @@ fn client_sync_headers() {
                     highest_block_hash: hash(&[5]),
                     tracked_shards: vec![],
                     archival: false,
+                    snapshot_host_info: None,
                 }],
                 sent_bytes_per_sec: 0,
                 received_bytes_per_sec: 0,
@@ -2034,6 +2038,55 @@ fn test_not_resync_old_blocks() {
     }
 }
 
+/// Chunk replay artifacts are self-contained: replaying them reproduces the
+/// recorded outcome without access to the node's storage.
+#[test]
+fn test_replay_chunk() {
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    assert_eq!(env.clients[0].process_tx(tx, false, false), ProcessTxResponse::ValidTx);
+    for i in 1..5 {
+        env.produce_block(0, i);
+    }
+
+    let client = &env.clients[0];
+    let artifact = (1..5)
+        .map(|height| {
+            let block_hash = client.chain.get_block_hash_by_height(height).unwrap();
+            ChunkReplayArtifact::new(
+                client.chain.store(),
+                client.epoch_manager.as_ref(),
+                client.runtime_adapter.as_ref(),
+                &block_hash,
+                0,
+                Default::default(),
+            )
+            .unwrap()
+        })
+        .find(|artifact| !artifact.transactions.is_empty())
+        .unwrap();
+    let dir = tempfile::Builder::new().prefix("chunk_replay").tempdir().unwrap();
+    let path = artifact.save(dir.path()).unwrap();
+    let artifact = ChunkReplayArtifact::load(&path).unwrap();
+
+    let result = replay_chunk(client.runtime_adapter.as_ref(), &artifact).unwrap();
+    assert_eq!(&result.new_root, artifact.chunk_extra.state_root());
+    assert_ne!(result.new_root, artifact.prev_state_root);
+}
+
 #[test]
 fn test_gc_tail_update() {
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
@@ -2362,6 +2415,31 @@ fn test_sync_hash_validity() {
     }
 }
 
+/// Final blocks in the middle of the current epoch can be synced to, as well as
+/// the epoch starts.
+#[test]
+fn test_sync_hash_validity_mid_epoch() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+    for i in 1..15 {
+        env.produce_block(0, i);
+    }
+    let final_height = env.clients[0].chain.final_head().unwrap().height;
+    assert!(final_height > 11 && final_height < 14);
+    for i in 0..15 {
+        let block_hash = *env.clients[0].chain.get_block_header_by_height(i).unwrap().hash();
+        let res = env.clients[0].chain.check_sync_hash_validity(&block_hash).unwrap();
+        assert_eq!(res, i == 6 || (11..=final_height).contains(&i), "height {i}");
+    }
+}
+
 #[test]
 fn test_block_height_processed_orphan() {
     let mut env = TestEnv::builder(ChainGenesis::test()).build();
@@ -9,6 +9,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::transaction::SignedTransaction;
 use near_store::flat::FlatStorageManager;
+use near_store::intent_log::IntentLog;
 use near_store::{
     config::TrieCacheConfig, test_utils::create_test_store, Mode, ShardTries, StateSnapshotConfig,
     StoreConfig, TrieConfig,
@@ -44,6 +45,8 @@ impl StateSnaptshotTestEnv {
             enable_receipt_prefetching: false,
             sweat_prefetch_receivers: Vec::new(),
             sweat_prefetch_senders: Vec::new(),
+            flat_storage_delta_pruning: Default::default(),
+            state_part_memory_limit: None,
         };
         let flat_storage_manager = FlatStorageManager::new(store.clone());
         let shard_uids = [ShardUId::single_shard()];
@@ -52,6 +55,7 @@ impl StateSnaptshotTestEnv {
             hot_store_path: hot_store_path.clone(),
             state_snapshot_subdir: state_snapshot_subdir.clone(),
             compaction_enabled: true,
+            encryption: None,
         };
         let shard_tries = ShardTries::new_with_state_snapshot(
             store.clone(),
@@ -158,7 +162,15 @@ fn verify_make_snapshot(
     // check that the stored snapshot in file system is an actual snapshot
     let store_config = StoreConfig::default();
     let opener = NodeStorage::opener(&snapshot_path, false, &store_config, None);
-    let _storage = opener.open_in_mode(Mode::ReadOnly)?;
+    let storage = opener.open_in_mode(Mode::ReadOnly)?;
+    // check that the snapshot doesn't carry the intent of making it, which
+    // would delete the snapshot the next time it's opened for writing
+    if !IntentLog::new(storage.get_hot_store()).pending()?.is_empty() {
+        return Err(anyhow::Error::msg("the state snapshot has pending intents"));
+    }
+    if !snapshot_path.exists() {
+        return Err(anyhow::Error::msg("the state snapshot was deleted"));
+    }
     // check that there's only one snapshot at the parent directory of snapshot path
     let parent_path = snapshot_path
         .parent()
@@ -496,6 +496,8 @@ fn sync_state_dump() {
                                         },
                                         num_concurrent_requests: 1,
                                         num_concurrent_requests_during_catchup: 1,
+                                        fallback_after_failures: 3,
+                                        prefer_peers: false,
                                     });
 
                                 let nearcore::NearNode {
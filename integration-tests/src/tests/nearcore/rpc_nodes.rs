@@ -13,16 +13,24 @@ use near_jsonrpc::client::new_client;
 use near_network::test_utils::WaitOrTimeoutActor;
 use near_o11y::testonly::init_integration_logger;
 use near_o11y::WithSpanContextExt;
+use near_primitives::account::Account;
+use near_primitives::challenge::PartialState;
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::merkle::{compute_root_from_path_and_item, verify_path};
+use near_primitives::merkle::{compute_root_from_path_and_item, verify_hash, verify_path};
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::serialize::to_base64;
+use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{PartialExecutionStatus, SignedTransaction};
+use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
     BlockId, BlockReference, EpochId, EpochReference, Finality, TransactionOrReceiptId,
 };
 use near_primitives::version::ProtocolVersion;
-use near_primitives::views::{ExecutionOutcomeView, ExecutionStatusView, RuntimeConfigView};
+use near_primitives::views::{
+    ExecutionOutcomeView, ExecutionStatusView, FinalExecutionStatus, RuntimeConfigView,
+    SimulationResultView,
+};
+use near_store::{PartialStorage, Trie};
 use std::time::Duration;
 
 #[test]
@@ -681,3 +689,172 @@ fn test_validators_by_epoch_id_current_epoch_not_fails() {
         });
     });
 }
+
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn test_simulate_transaction_with_state_overrides() {
+    init_integration_logger();
+
+    let cluster = NodeCluster::default()
+        .set_num_shards(1)
+        .set_num_validator_seats(1)
+        .set_num_lightclients(0)
+        .set_epoch_length(10)
+        .set_genesis_height(0);
+
+    cluster.exec_until_stop(|genesis, rpc_addrs, clients| async move {
+        let view_client = clients[0].1.clone();
+        let genesis_hash = *genesis_block(&genesis).hash();
+        let signer =
+            InMemorySigner::from_seed("near.0".parse().unwrap(), KeyType::ED25519, "near.0");
+        let amount = 1100000000000000000000000000000000;
+        let transaction = SignedTransaction::send_money(
+            1,
+            "near.0".parse().unwrap(),
+            "near.1".parse().unwrap(),
+            &signer,
+            amount,
+            genesis_hash,
+        );
+        let client = new_client(&format!("http://{}", rpc_addrs[0]));
+
+        spawn_interruptible(async move {
+            let block = loop {
+                let res = view_client.send(GetBlock::latest().with_span_context()).await;
+                if let Ok(Ok(block)) = res {
+                    if block.header.height > 10 {
+                        break block;
+                    }
+                }
+                sleep(std::time::Duration::from_millis(500)).await;
+            };
+            let block_reference = BlockReference::BlockId(BlockId::Hash(block.header.hash));
+            let view_account = || {
+                client.query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
+                    block_reference: block_reference.clone(),
+                    request: near_primitives::views::QueryRequest::ViewAccount {
+                        account_id: "near.0".parse().unwrap(),
+                    },
+                })
+            };
+            let account = match view_account().await.unwrap().kind {
+                near_jsonrpc_primitives::types::query::QueryResponseKind::ViewAccount(account) => {
+                    account
+                }
+                kind => panic!("expected an account view, got {:?}", kind),
+            };
+            let simulate = |state_overrides: Vec<StateRecord>| {
+                client.EXPERIMENTAL_simulate(serde_json::json!({
+                    "block_id": block.header.hash,
+                    "state_overrides": state_overrides,
+                    "simulation_type": "transaction",
+                    "signed_tx_base64": to_base64(&transaction.try_to_vec().unwrap()),
+                }))
+            };
+
+            // The signer can't afford the transfer.
+            let err = simulate(vec![]).await.unwrap_err();
+            let error_struct = serde_json::to_value(err.error_struct).unwrap().to_string();
+            assert!(error_struct.contains("NotEnoughBalance"), "{}", error_struct);
+
+            // Unless its balance is overridden.
+            let mut rich_account = Account::from(&account);
+            rich_account.set_amount(amount * 2);
+            let response = simulate(vec![StateRecord::Account {
+                account_id: "near.0".parse().unwrap(),
+                account: rich_account,
+            }])
+            .await
+            .unwrap();
+            assert_eq!(response.block_hash, block.header.hash);
+            match response.result {
+                SimulationResultView::Transaction(outcome) => {
+                    assert_matches!(outcome.status, FinalExecutionStatus::SuccessValue(_));
+                    assert!(!outcome.receipts_outcome.is_empty());
+                }
+                result => panic!("expected a transaction outcome, got {:?}", result),
+            }
+
+            // Nothing is persisted.
+            assert_matches!(
+                view_account().await.unwrap().kind,
+                near_jsonrpc_primitives::types::query::QueryResponseKind::ViewAccount(view)
+                    if view.amount == account.amount
+            );
+            System::current().stop();
+        });
+    });
+}
+
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn test_light_client_state_proof() {
+    init_integration_logger();
+
+    let cluster = NodeCluster::default()
+        .set_num_shards(1)
+        .set_num_validator_seats(1)
+        .set_num_lightclients(0)
+        .set_epoch_length(10)
+        .set_genesis_height(0);
+
+    cluster.exec_until_stop(|_, rpc_addrs, clients| async move {
+        let view_client = clients[0].1.clone();
+        let client = new_client(&format!("http://{}", rpc_addrs[0]));
+
+        spawn_interruptible(async move {
+            let head = loop {
+                let res = view_client
+                    .send(GetBlock(BlockReference::Finality(Finality::Final)).with_span_context())
+                    .await;
+                if let Ok(Ok(block)) = res {
+                    if block.header.height > 5 {
+                        break block;
+                    }
+                }
+                sleep(std::time::Duration::from_millis(500)).await;
+            };
+            let block_hash = head.header.prev_hash;
+            let key = TrieKey::ContractData {
+                account_id: "near.0".parse().unwrap(),
+                key: b"missing".to_vec(),
+            };
+            let response = client
+                .EXPERIMENTAL_light_client_state_proof(
+                    near_jsonrpc_primitives::types::light_client::RpcLightClientStateProofRequest {
+                        account_id: "near.0".parse().unwrap(),
+                        key: b"missing".to_vec().into(),
+                        block_hash,
+                        light_client_head: head.header.hash,
+                    },
+                )
+                .await
+                .unwrap();
+            let state_proof = response.state_proof;
+            assert_eq!(state_proof.value, None);
+
+            // The proof is enough to read the key from the state root.
+            let partial_storage = |proof| PartialStorage { nodes: PartialState::TrieValues(proof) };
+            let trie = Trie::from_recorded_storage(
+                partial_storage(state_proof.proof),
+                state_proof.state_root,
+                false,
+            );
+            assert_eq!(trie.get(&key.to_vec()).unwrap(), None);
+            let trie =
+                Trie::from_recorded_storage(partial_storage(vec![]), state_proof.state_root, false);
+            assert!(trie.get(&key.to_vec()).is_err());
+
+            // The state root is committed in the block header, which is in the light client head.
+            let header = response.block_header_lite;
+            assert!(verify_path(
+                header.inner_lite.prev_state_root,
+                &state_proof.state_root_proof,
+                &state_proof.state_root
+            ));
+            assert_eq!(header.hash(), block_hash);
+            assert!(verify_hash(head.header.block_merkle_root, &response.block_proof, block_hash));
+            System::current().stop();
+        });
+    });
+}
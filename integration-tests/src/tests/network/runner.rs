@@ -88,6 +88,7 @@ fn setup_network_node(
         None,
         adv.clone(),
         None,
+        None,
     )
     .0;
     let view_client_actor = start_view_client(
@@ -99,6 +100,7 @@ fn setup_network_node(
         network_adapter.clone().into(),
         client_config.clone(),
         adv,
+        None,
     );
     let (shards_manager_actor, _) = start_shards_manager(
         epoch_manager,
@@ -367,6 +367,55 @@ fn test_view_state_too_large() {
     assert!(matches!(result, Err(errors::ViewStateError::AccountStateTooLarge { .. })));
 }
 
+#[test]
+fn test_view_state_page() {
+    let (_, tries, root) = get_runtime_and_trie();
+    let mut state_update = tries.new_trie_update(TEST_SHARD_UID, root);
+    set_account(
+        &mut state_update,
+        alice_account(),
+        &Account::new(0, 0, CryptoHash::default(), 50_001),
+    );
+    for key in ["key1", "key2", "key3", "key4", "key5", "other"] {
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: key.as_bytes().to_vec() },
+            b"val".to_vec(),
+        );
+    }
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().1;
+    let mut db_changes = tries.store_update();
+    let new_root = tries.apply_all(&trie_changes, TEST_SHARD_UID, &mut db_changes);
+    db_changes.commit().unwrap();
+
+    let state_update = tries.new_trie_update(TEST_SHARD_UID, new_root);
+    // The pages are not limited by the size of the whole state.
+    let trie_viewer = TrieViewer::new(Some(50_000), None).with_page_size_limit(10);
+    let mut pages = vec![];
+    let mut start_after = None;
+    loop {
+        let (result, last_key) = trie_viewer
+            .view_state_page(&state_update, &alice_account(), b"key", false, start_after.as_deref())
+            .unwrap();
+        let keys: Vec<_> = result.values.iter().map(|item| item.key.to_vec()).collect();
+        pages.push(keys);
+        match last_key {
+            Some(last_key) => start_after = Some(last_key),
+            None => break,
+        }
+    }
+    let expected: Vec<Vec<Vec<u8>>> = vec![
+        vec![b"key1".to_vec(), b"key2".to_vec()],
+        vec![b"key3".to_vec(), b"key4".to_vec()],
+        vec![b"key5".to_vec()],
+    ];
+    assert_eq!(expected, pages);
+
+    let missing_account = "missing.near".parse().unwrap();
+    let result = trie_viewer.view_state_page(&state_update, &missing_account, b"", false, None);
+    assert!(matches!(result, Err(errors::ViewStateError::AccountDoesNotExist { .. })));
+}
+
 #[test]
 fn test_view_state_with_large_contract() {
     let (_, tries, root) = get_runtime_and_trie();
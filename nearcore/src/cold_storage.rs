@@ -3,9 +3,9 @@ use std::sync::{atomic::AtomicBool, Arc};
 use near_chain::types::Tip;
 use near_epoch_manager::{EpochManagerAdapter, EpochManagerHandle};
 use near_primitives::{hash::CryptoHash, types::BlockHeight};
-use near_store::cold_storage::{copy_all_data_to_cold, CopyAllDataToColdStatus};
+use near_store::cold_storage::{copy_all_data_to_cold, ColdCopyThrottle, CopyAllDataToColdStatus};
 use near_store::{
-    cold_storage::{update_cold_db, update_cold_head},
+    cold_storage::{update_cold_db_throttled, update_cold_head},
     db::ColdDB,
     DBCol, NodeStorage, Store, FINAL_HEAD_KEY, HEAD_KEY, TAIL_KEY,
 };
@@ -54,6 +54,7 @@ fn cold_store_copy(
     cold_db: &Arc<ColdDB>,
     genesis_height: BlockHeight,
     epoch_manager: &EpochManagerHandle,
+    throttle: &ColdCopyThrottle,
 ) -> anyhow::Result<ColdStoreCopyResult> {
     // If COLD_HEAD is not set for hot storage we default it to genesis_height.
     let cold_head = cold_store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)?;
@@ -70,6 +71,7 @@ fn cold_store_copy(
     let hot_tail_height = hot_tail.unwrap_or(genesis_height);
 
     tracing::debug!(target: "cold_store", "cold store loop, cold_head {}, hot_final_head {}, hot_tail {}", cold_head_height, hot_final_head_height, hot_tail_height);
+    cold_db.set_copy_target_height(hot_final_head_height);
 
     if cold_head_height > hot_final_head_height {
         return Err(anyhow::anyhow!(
@@ -106,7 +108,7 @@ fn cold_store_copy(
     let shard_layout = epoch_manager.get_shard_layout(&epoch_id)?;
 
     let mut next_height = cold_head_height + 1;
-    while !update_cold_db(cold_db, hot_store, &shard_layout, &next_height)? {
+    while !update_cold_db_throttled(cold_db, hot_store, &shard_layout, &next_height, throttle)? {
         next_height += 1;
         if next_height > hot_final_head_height {
             return Err(anyhow::anyhow!(
@@ -119,6 +121,8 @@ fn cold_store_copy(
     }
 
     update_cold_head(cold_db, hot_store, &next_height)?;
+    let progress = cold_db.copy_progress();
+    tracing::debug!(target: "cold_store", remaining_heights = ?progress.remaining_heights(), eta = ?progress.eta(), "cold store copy progress");
 
     if next_height >= hot_final_head_height {
         Ok(ColdStoreCopyResult::LatestBlockCopied)
@@ -254,14 +258,21 @@ fn cold_store_loop(
     epoch_manager: &EpochManagerHandle,
 ) {
     tracing::info!(target : "cold_store", "Starting the cold store loop");
+    let throttle = ColdCopyThrottle::new(split_storage_config.cold_store_loop_max_bytes_per_second);
 
     loop {
         if !keep_going.load(std::sync::atomic::Ordering::Relaxed) {
             tracing::debug!(target : "cold_store", "Stopping the cold store loop");
             break;
         }
-        let result =
-            cold_store_copy(&hot_store, &cold_store, &cold_db, genesis_height, epoch_manager);
+        let result = cold_store_copy(
+            &hot_store,
+            &cold_store,
+            &cold_db,
+            genesis_height,
+            epoch_manager,
+            &throttle,
+        );
 
         metrics::COLD_STORE_COPY_RESULT
             .with_label_values(&[cold_store_copy_result_to_string(&result)])
@@ -2,11 +2,16 @@ use crate::download_file::{run_download_file, FileDownloadError};
 use crate::dyn_config::LOG_CONFIG_FILENAME;
 use anyhow::{anyhow, bail, Context};
 use near_chain_configs::{
-    get_initial_supply, ClientConfig, GCConfig, Genesis, GenesisConfig, GenesisValidationMode,
-    LogSummaryStyle, MutableConfigValue, StateSyncConfig,
+    get_initial_supply, ClientConfig, DoomslugTimerConfig, GCConfig, Genesis, GenesisConfig,
+    GenesisValidationMode, LogSummaryStyle, MutableConfigValue, ProductionDiagnosticsConfig,
+    RemoteSignerConfig, RemoteSignerFallback, ReshardingConfig, StateSyncConfig, SyncJobsConfig,
+    ValidatorKeyRotationConfig,
 };
+use near_client::RemoteValidatorSigner;
 use near_config_utils::{ValidationError, ValidationErrors};
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
+#[cfg(feature = "grpc")]
+use near_grpc::GrpcConfig;
 #[cfg(feature = "json_rpc")]
 use near_jsonrpc::RpcConfig;
 use near_network::config::NetworkConfig;
@@ -34,7 +39,7 @@ use num_rational::Rational32;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -194,10 +199,22 @@ fn default_trie_viewer_state_size_limit() -> Option<u64> {
     Some(50_000)
 }
 
+fn default_trie_viewer_page_size_limit() -> u64 {
+    node_runtime::state_viewer::DEFAULT_STATE_PAGE_SIZE_LIMIT
+}
+
 fn default_transaction_pool_size_limit() -> Option<u64> {
     Some(100_000_000) // 100 MB.
 }
 
+fn default_production_diagnostics() -> Option<ProductionDiagnosticsConfig> {
+    Some(ProductionDiagnosticsConfig::default())
+}
+
+fn default_chunk_production_time_budget() -> Option<f64> {
+    Some(0.5)
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -246,6 +263,23 @@ pub struct Consensus {
     pub doomslug_step_period: Duration,
     #[serde(default = "default_sync_height_threshold")]
     pub sync_height_threshold: u64,
+    /// Delays of the doomslug timer.  If not set, they're derived from
+    /// `max_block_production_delay` and `max_block_wait_delay`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doomslug_timer: Option<DoomslugTimerConfig>,
+}
+
+impl Consensus {
+    /// Delays of the doomslug timer, either configured or derived from the
+    /// block production delays.
+    pub fn doomslug_timer(&self) -> DoomslugTimerConfig {
+        self.doomslug_timer.unwrap_or_else(|| {
+            DoomslugTimerConfig::from_block_production_delays(
+                self.max_block_production_delay,
+                self.max_block_wait_delay,
+            )
+        })
+    }
 }
 
 impl Default for Consensus {
@@ -271,6 +305,7 @@ impl Default for Consensus {
             sync_step_period: default_sync_step_period(),
             doomslug_step_period: default_doomslug_step_period(),
             sync_height_threshold: default_sync_height_threshold(),
+            doomslug_timer: None,
         }
     }
 }
@@ -288,6 +323,9 @@ pub struct Config {
     #[cfg(feature = "rosetta_rpc")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rosetta_rpc: Option<RosettaRpcConfig>,
+    #[cfg(feature = "grpc")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<GrpcConfig>,
     pub telemetry: TelemetryConfig,
     pub network: near_network::config_json::Config,
     pub consensus: Consensus,
@@ -320,6 +358,10 @@ pub struct Config {
     pub view_client_throttle_period: Duration,
     #[serde(default = "default_trie_viewer_state_size_limit")]
     pub trie_viewer_state_size_limit: Option<u64>,
+    /// Upper bound of the byte size of a page of contract state returned by the paginated
+    /// `view_state` queries.
+    #[serde(default = "default_trie_viewer_page_size_limit")]
+    pub trie_viewer_page_size_limit: u64,
     /// If set, overrides value in genesis configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_gas_burnt_view: Option<Gas>,
@@ -344,12 +386,65 @@ pub struct Config {
     pub state_sync: Option<StateSyncConfig>,
     /// Limit of the size of per-shard transaction pool measured in bytes. If not set, the size
     /// will be unbounded.
-    /// New transactions that bring the size of the pool over this limit will be rejected. This
-    /// guarantees that the node will use bounded resources to store incoming transactions.
+    /// New transactions that bring the size of the pool over this limit will evict transactions
+    /// attaching less gas from other accounts, lowest gas and oldest first, or be rejected if
+    /// there are none. This guarantees that the node will use bounded resources to store
+    /// incoming transactions.
     /// Setting this value too low (<1MB) on the validator might lead to production of smaller
     /// chunks and underutilizing the capacity of the network.
     #[serde(default = "default_transaction_pool_size_limit")]
     pub transaction_pool_size_limit: Option<u64>,
+    /// Limit of the number of transactions in per-shard transaction pool, enforced like
+    /// `transaction_pool_size_limit`. If not set, the number will be unbounded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_pool_count_limit: Option<usize>,
+    /// Whether a validator warms up the storage caches for applying the
+    /// receipts of the next chunks of its shards, by reading the accounts and
+    /// contracts they touch ahead of time. Enabled by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefetch_receipts: Option<bool>,
+    /// Fraction of `consensus.min_block_production_delay` which producing a
+    /// chunk may take. Once selecting transactions for a chunk takes that
+    /// long, the chunk is produced with the transactions selected so far,
+    /// rather than delaying the block. If set to null, chunk production isn't
+    /// limited in time.
+    #[serde(default = "default_chunk_production_time_budget")]
+    pub chunk_production_time_budget: Option<f64>,
+    /// If set, the node re-applies a sample of chunks in final blocks in the
+    /// background and checks that the outcome matches the recorded one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reexecution_check: Option<ReexecutionCheckConfig>,
+    /// If set, the node audits reference counts of state trie nodes in the
+    /// background.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refcount_audit: Option<RefcountAuditConfig>,
+    /// If set, replay artifacts of chunks whose outcome doesn't match the one
+    /// claimed by the next chunk of the shard are written to this directory.
+    /// Relative paths are relative to the home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_replay_artifacts_dir: Option<PathBuf>,
+    /// Capturing of diagnostic bundles when the node misses producing a block
+    /// or a chunk assigned to it.  Set to `null` to disable.
+    #[serde(default = "default_production_diagnostics", skip_serializing_if = "Option::is_none")]
+    pub production_diagnostics: Option<ProductionDiagnosticsConfig>,
+    /// Rotation of the validator key to a successor key at a chosen epoch.
+    /// The node submits the stake proposal with the successor key and
+    /// switches to it without a restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator_key_rotation: Option<ValidatorKeyRotationConfig>,
+    /// If set, the validator key is held by an external signing service
+    /// instead of `validator_key_file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<RemoteSignerConfig>,
+    /// Throttling of building the state of the child shards during
+    /// resharding.  Can be changed while the node is running, which also
+    /// allows to pause and resume resharding.
+    #[serde(default)]
+    pub resharding_config: ReshardingConfig,
+    /// Scheduling of the background jobs of state sync, catchup and
+    /// resharding.
+    #[serde(default)]
+    pub sync_jobs: SyncJobsConfig,
 }
 
 fn is_false(value: &bool) -> bool {
@@ -366,6 +461,8 @@ impl Default for Config {
             rpc: Some(RpcConfig::default()),
             #[cfg(feature = "rosetta_rpc")]
             rosetta_rpc: None,
+            #[cfg(feature = "grpc")]
+            grpc: None,
             telemetry: TelemetryConfig::default(),
             network: Default::default(),
             consensus: Consensus::default(),
@@ -381,6 +478,7 @@ impl Default for Config {
             view_client_threads: default_view_client_threads(),
             view_client_throttle_period: default_view_client_throttle_period(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
+            trie_viewer_page_size_limit: default_trie_viewer_page_size_limit(),
             max_gas_burnt_view: None,
             store: near_store::StoreConfig::default(),
             cold_store: None,
@@ -389,7 +487,18 @@ impl Default for Config {
             state_sync: None,
             state_sync_enabled: None,
             transaction_pool_size_limit: default_transaction_pool_size_limit(),
+            transaction_pool_count_limit: None,
+            prefetch_receipts: None,
+            chunk_production_time_budget: default_chunk_production_time_budget(),
             enable_multiline_logging: None,
+            reexecution_check: None,
+            refcount_audit: None,
+            chunk_replay_artifacts_dir: None,
+            production_diagnostics: default_production_diagnostics(),
+            validator_key_rotation: None,
+            remote_signer: None,
+            resharding_config: ReshardingConfig::default(),
+            sync_jobs: SyncJobsConfig::default(),
         }
     }
 }
@@ -422,6 +531,17 @@ pub struct SplitStorageConfig {
 
     #[serde(default = "default_cold_store_loop_sleep_duration")]
     pub cold_store_loop_sleep_duration: Duration,
+    /// Limits the average rate of writes to cold storage done by the cold
+    /// store loop, so that it doesn't starve the hot store of IO. Doesn't
+    /// apply to the initial migration. No limit if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cold_store_loop_max_bytes_per_second: Option<u64>,
+    /// Delete transactions, receipts and execution outcomes from the hot
+    /// store as soon as the cold store loop copies them, instead of once
+    /// garbage collection reaches them.  Reads of them fall back to cold
+    /// storage, so the view client always uses the split store then.
+    #[serde(default)]
+    pub cold_store_eager_migration: bool,
 }
 
 impl Default for SplitStorageConfig {
@@ -433,6 +553,74 @@ impl Default for SplitStorageConfig {
             cold_store_initial_migration_loop_sleep_duration:
                 default_cold_store_initial_migration_loop_sleep_duration(),
             cold_store_loop_sleep_duration: default_cold_store_loop_sleep_duration(),
+            cold_store_loop_max_bytes_per_second: None,
+            cold_store_eager_migration: false,
+        }
+    }
+}
+
+fn default_reexecution_check_sample_rate() -> f64 {
+    0.01
+}
+
+fn default_reexecution_check_sleep_duration() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Configuration of the background re-execution of chunks, see
+/// `crate::reexecution_check`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ReexecutionCheckConfig {
+    /// Fraction of new chunks in final blocks which are re-applied.
+    #[serde(default = "default_reexecution_check_sample_rate")]
+    pub sample_rate: f64,
+    /// How long the loop sleeps when all final blocks have been checked.
+    #[serde(default = "default_reexecution_check_sleep_duration")]
+    pub sleep_duration: Duration,
+}
+
+impl Default for ReexecutionCheckConfig {
+    fn default() -> Self {
+        ReexecutionCheckConfig {
+            sample_rate: default_reexecution_check_sample_rate(),
+            sleep_duration: default_reexecution_check_sleep_duration(),
+        }
+    }
+}
+
+fn default_refcount_audit_sample_rate() -> f64 {
+    0.001
+}
+
+fn default_refcount_audit_nodes_per_step() -> u64 {
+    10_000
+}
+
+fn default_refcount_audit_step_period() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// Configuration of the background audit of reference counts of state trie
+/// nodes, see `crate::refcount_audit`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RefcountAuditConfig {
+    /// Fraction of trie nodes and values whose references are recounted.
+    #[serde(default = "default_refcount_audit_sample_rate")]
+    pub sample_rate: f64,
+    /// Number of trie nodes visited at a time.
+    #[serde(default = "default_refcount_audit_nodes_per_step")]
+    pub nodes_per_step: u64,
+    /// How long the loop sleeps between steps.
+    #[serde(default = "default_refcount_audit_step_period")]
+    pub step_period: Duration,
+}
+
+impl Default for RefcountAuditConfig {
+    fn default() -> Self {
+        RefcountAuditConfig {
+            sample_rate: default_refcount_audit_sample_rate(),
+            nodes_per_step: default_refcount_audit_nodes_per_step(),
+            step_period: default_refcount_audit_step_period(),
         }
     }
 }
@@ -617,6 +805,8 @@ pub struct NearConfig {
     pub rpc_config: Option<RpcConfig>,
     #[cfg(feature = "rosetta_rpc")]
     pub rosetta_rpc_config: Option<RosettaRpcConfig>,
+    #[cfg(feature = "grpc")]
+    pub grpc_config: Option<GrpcConfig>,
     pub telemetry_config: TelemetryConfig,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
@@ -671,12 +861,17 @@ impl NearConfig {
                 tracked_shard_schedule: config.tracked_shard_schedule.unwrap_or(vec![]),
                 archive: config.archive,
                 save_trie_changes: config.save_trie_changes.unwrap_or(!config.archive),
+                cold_store_eager_migration: config
+                    .split_storage
+                    .as_ref()
+                    .map_or(false, |c| c.cold_store_eager_migration),
                 log_summary_style: config.log_summary_style,
                 gc: config.gc,
                 view_client_threads: config.view_client_threads,
                 epoch_sync_enabled: config.epoch_sync_enabled,
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
+                trie_viewer_page_size_limit: config.trie_viewer_page_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
                 enable_statistics_export: config.store.enable_statistics_export,
                 client_background_migration_threads: config.store.background_migration_threads,
@@ -686,6 +881,21 @@ impl NearConfig {
                 state_sync: config.state_sync.unwrap_or_default(),
                 state_snapshot_every_n_blocks: None,
                 transaction_pool_size_limit: config.transaction_pool_size_limit,
+                transaction_pool_count_limit: config.transaction_pool_count_limit,
+                prefetch_receipts: config.prefetch_receipts.unwrap_or(true),
+                chunk_production_time_budget: config.chunk_production_time_budget,
+                chunk_replay_artifacts_dir: config.chunk_replay_artifacts_dir,
+                production_diagnostics: config.production_diagnostics,
+                validator_key_rotation: config.validator_key_rotation,
+                resharding_config: MutableConfigValue::new(
+                    config.resharding_config,
+                    "resharding_config",
+                ),
+                doomslug_timer: MutableConfigValue::new(
+                    config.consensus.doomslug_timer(),
+                    "doomslug_timer",
+                ),
+                sync_jobs: config.sync_jobs,
                 enable_multiline_logging: config.enable_multiline_logging.unwrap_or(true),
             },
             network_config: NetworkConfig::new(
@@ -699,6 +909,8 @@ impl NearConfig {
             rpc_config: config.rpc,
             #[cfg(feature = "rosetta_rpc")]
             rosetta_rpc_config: config.rosetta_rpc,
+            #[cfg(feature = "grpc")]
+            grpc_config: config.grpc,
             genesis,
             validator_signer,
         })
@@ -1381,7 +1593,25 @@ pub fn load_config(
     };
 
     let validator_file = dir.join(&config.validator_key_file);
-    let validator_signer = if validator_file.exists() {
+    let validator_signer = if let Some(remote_signer) = &config.remote_signer {
+        let mut remote_signer = remote_signer.clone();
+        if let Some(tls) = &mut remote_signer.tls {
+            tls.ca_cert_file = dir.join(&tls.ca_cert_file);
+            tls.client_cert_file = dir.join(&tls.client_cert_file);
+            tls.client_key_file = dir.join(&tls.client_key_file);
+        }
+        if let RemoteSignerFallback::LocalKey { key_file } = &mut remote_signer.fallback {
+            *key_file = dir.join(&*key_file);
+        }
+        match RemoteValidatorSigner::new(&remote_signer) {
+            Ok(signer) => Some(Arc::new(signer) as Arc<dyn ValidatorSigner>),
+            Err(err) => {
+                let error_message = format!("Failed initializing remote signer: {err:#}");
+                validation_errors.push_validator_key_file_error(error_message);
+                None
+            }
+        }
+    } else if validator_file.exists() {
         match InMemoryValidatorSigner::from_file(&validator_file) {
             Ok(signer) => Some(Arc::new(signer) as Arc<dyn ValidatorSigner>),
             Err(_) => {
@@ -1396,6 +1626,28 @@ pub fn load_config(
     } else {
         None
     };
+    if let Some(rotation) = &config.validator_key_rotation {
+        let successor_file = dir.join(&rotation.successor_key_file);
+        match InMemoryValidatorSigner::from_file(&successor_file) {
+            Ok(successor) => {
+                let account_id = validator_signer.as_ref().map(|signer| signer.validator_id());
+                if account_id != Some(successor.validator_id()) {
+                    let error_message = format!(
+                        "Successor validator key {} is not of the account of the validator key",
+                        successor_file.display()
+                    );
+                    validation_errors.push_validator_key_file_error(error_message);
+                }
+            }
+            Err(_) => {
+                let error_message = format!(
+                    "Failed initializing successor validator signer from {}",
+                    successor_file.display()
+                );
+                validation_errors.push_validator_key_file_error(error_message);
+            }
+        }
+    }
 
     let node_key_path = dir.join(&config.node_key_file);
     let network_signer_result = NodeKeyFile::from_file(&node_key_path);
@@ -1448,12 +1700,22 @@ pub fn load_config(
     if genesis.is_none() || network_signer.is_none() {
         panic!("Genesis and network_signer should not be None by now.")
     }
-    let near_config = NearConfig::new(
+    let mut near_config = NearConfig::new(
         config,
         genesis.unwrap(),
         network_signer.unwrap().into(),
         validator_signer,
     )?;
+    near_config.client_config.chunk_replay_artifacts_dir =
+        near_config.client_config.chunk_replay_artifacts_dir.take().map(|path| dir.join(path));
+    if let Some(production_diagnostics) = &mut near_config.client_config.production_diagnostics {
+        production_diagnostics.dir = dir.join(&production_diagnostics.dir);
+    }
+    if let Some(rotation) = &mut near_config.client_config.validator_key_rotation {
+        rotation.successor_key_file = dir.join(&rotation.successor_key_file);
+        rotation.stake_proposal_key_file =
+            rotation.stake_proposal_key_file.take().map(|path| dir.join(path));
+    }
     Ok(near_config)
 }
 
@@ -1465,6 +1727,8 @@ pub fn load_test_config(seed: &str, addr: tcp::ListenerAddr, genesis: Genesis) -
         Duration::from_millis(FAST_MIN_BLOCK_PRODUCTION_DELAY);
     config.consensus.max_block_production_delay =
         Duration::from_millis(FAST_MAX_BLOCK_PRODUCTION_DELAY);
+    // There's no home directory to write the bundles to.
+    config.production_diagnostics = None;
     let (signer, validator_signer) = if seed.is_empty() {
         let signer =
             Arc::new(InMemorySigner::from_random("node".parse().unwrap(), KeyType::ED25519));
@@ -1615,9 +1879,21 @@ fn test_config_from_file_skip_validation() {
         // values is probably not worth it but there may be some other defaults
         // we want to ensure that they happen.
         let want_gc = if has_gc {
-            GCConfig { gc_blocks_limit: 42, gc_fork_clean_step: 420, gc_num_epochs_to_keep: 24 }
+            GCConfig {
+                gc_blocks_limit: 42,
+                gc_fork_clean_step: 420,
+                gc_num_epochs_to_keep: 24,
+                gc_time_limit: None,
+                retention: Default::default(),
+            }
         } else {
-            GCConfig { gc_blocks_limit: 2, gc_fork_clean_step: 100, gc_num_epochs_to_keep: 5 }
+            GCConfig {
+                gc_blocks_limit: 2,
+                gc_fork_clean_step: 100,
+                gc_num_epochs_to_keep: 5,
+                gc_time_limit: None,
+                retention: Default::default(),
+            }
         };
         assert_eq!(want_gc, config.gc);
 
@@ -43,6 +43,107 @@ impl<'a> ConfigValidator<'a> {
             self.validation_errors.push_config_semantics_error(error_message);
         }
 
+        for error in self.config.store.validate_column_configs() {
+            let error_message = format!("config.store.{error}");
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+        if let Some(cold_store) = &self.config.cold_store {
+            for error in cold_store.validate_column_configs() {
+                let error_message = format!("config.cold_store.{error}");
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        #[cfg(feature = "json_rpc")]
+        if let Some(rate_limits) = self.config.rpc.as_ref().and_then(|rpc| rpc.rate_limits.as_ref())
+        {
+            for error in rate_limits.validate() {
+                let error_message = format!("config.rpc.rate_limits.{error}");
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        if let Some(reexecution_check) = &self.config.reexecution_check {
+            if !(0.0..=1.0).contains(&reexecution_check.sample_rate) {
+                let error_message = format!(
+                    "'config.reexecution_check.sample_rate' should be between 0 and 1, but is {}.",
+                    reexecution_check.sample_rate
+                );
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        if let Some(refcount_audit) = &self.config.refcount_audit {
+            if !(0.0..=1.0).contains(&refcount_audit.sample_rate) {
+                let error_message = format!(
+                    "'config.refcount_audit.sample_rate' should be between 0 and 1, but is {}.",
+                    refcount_audit.sample_rate
+                );
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+            if refcount_audit.nodes_per_step == 0 {
+                let error_message =
+                    "'config.refcount_audit.nodes_per_step' should be greater than 0.".to_string();
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        if let Some(budget) = self.config.chunk_production_time_budget {
+            if budget <= 0.0 || budget > 1.0 {
+                let error_message = format!(
+                    "'config.chunk_production_time_budget' should be greater than 0 and at most 1, but is {}.",
+                    budget
+                );
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        if let Some(production_diagnostics) = &self.config.production_diagnostics {
+            if production_diagnostics.max_bundles == 0 {
+                let error_message =
+                    "'config.production_diagnostics.max_bundles' should be greater than 0."
+                        .to_string();
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
+        if self.config.resharding_config.batch_size.as_u64() == 0 {
+            let error_message =
+                "'config.resharding_config.batch_size' should be greater than 0.".to_string();
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+        if self
+            .config
+            .resharding_config
+            .max_bytes_per_second
+            .map_or(false, |rate| rate.as_u64() == 0)
+        {
+            let error_message =
+                "'config.resharding_config.max_bytes_per_second' should be greater than 0."
+                    .to_string();
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+
+        let sync_jobs = &self.config.sync_jobs;
+        for (name, value) in [
+            ("max_concurrent_block_catchups", sync_jobs.max_concurrent_block_catchups),
+            ("max_concurrent_apply_state_parts", sync_jobs.max_concurrent_apply_state_parts),
+            ("max_concurrent_state_splits", sync_jobs.max_concurrent_state_splits),
+            ("apply_state_parts_parallelism", sync_jobs.apply_state_parts_parallelism),
+        ] {
+            if value == 0 {
+                let error_message = format!("'config.sync_jobs.{name}' should be greater than 0.");
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+        if sync_jobs.num_threads <= sync_jobs.max_concurrent_state_splits {
+            let error_message = format!(
+                "'config.sync_jobs.num_threads' should be greater than 'config.sync_jobs.max_concurrent_state_splits', but {} <= {}.",
+                sync_jobs.num_threads, sync_jobs.max_concurrent_state_splits
+            );
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+
         if self.config.consensus.min_block_production_delay
             > self.config.consensus.max_block_production_delay
         {
@@ -65,6 +166,25 @@ impl<'a> ConfigValidator<'a> {
             self.validation_errors.push_config_semantics_error(error_message);
         }
 
+        if let Some(doomslug_timer) = &self.config.consensus.doomslug_timer {
+            // The head is endorsed after `min_block_production_delay`, and the
+            // doomslug timer needs to leave time for that before skipping.
+            if doomslug_timer.min_delay < 2 * self.config.consensus.min_block_production_delay {
+                let error_message = format!(
+                    "consensus.doomslug_timer.min_delay: {:?} should be at least twice min_block_production_delay: {:?}",
+                    doomslug_timer.min_delay, self.config.consensus.min_block_production_delay
+                );
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+            if doomslug_timer.min_delay > doomslug_timer.max_delay {
+                let error_message = format!(
+                    "consensus.doomslug_timer.min_delay: {:?} is greater than consensus.doomslug_timer.max_delay: {:?}",
+                    doomslug_timer.min_delay, doomslug_timer.max_delay
+                );
+                self.validation_errors.push_config_semantics_error(error_message);
+            }
+        }
+
         if self.config.consensus.header_sync_expected_height_per_second == 0 {
             let error_message =
                 "consensus.header_sync_expected_height_per_second should not be 0".to_string();
@@ -79,6 +199,22 @@ impl<'a> ConfigValidator<'a> {
             self.validation_errors.push_config_semantics_error(error_message);
         }
 
+        let retention = &self.config.gc.retention;
+        if retention.is_enabled() && self.config.archive {
+            let error_message =
+                "gc.retention should not be set on archival nodes which keep all data".to_string();
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+        if [retention.transactions, retention.receipts, retention.outcomes, retention.chunk_parts]
+            .contains(&Some(0))
+        {
+            let error_message = format!(
+                "gc.retention values should all be greater than 0, but they are {:?}.",
+                retention
+            );
+            self.validation_errors.push_config_semantics_error(error_message);
+        }
+
         if let Some(state_sync) = &self.config.state_sync {
             if let Some(dump_config) = &state_sync.dump {
                 if let Some(restart_dump_for_shards) = &dump_config.restart_dump_for_shards {
@@ -117,6 +253,12 @@ impl<'a> ConfigValidator<'a> {
                     }
                 }
             }
+            for window in &state_sync.bandwidth.windows {
+                if window.start == window.end {
+                    let error_message = format!("'config.state_sync.bandwidth.windows' can't contain an empty window, but the window starting at {} ends at the same time.", window.start);
+                    self.validation_errors.push_config_semantics_error(error_message);
+                }
+            }
             match &state_sync.sync {
                 SyncConfig::Peers => {}
                 SyncConfig::ExternalStorage(config) => {
@@ -162,6 +304,8 @@ impl<'a> ConfigValidator<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use near_chain_configs::DoomslugTimerConfig;
+    use std::time::Duration;
 
     #[test]
     #[should_panic(expected = "gc config values should all be greater than 0")]
@@ -220,4 +364,82 @@ mod test {
         config.save_trie_changes = Some(false);
         validate_config(&config).unwrap();
     }
+
+    #[test]
+    #[should_panic(expected = "config.store.column_configs: unknown column \\\"NoSuchColumn\\\"")]
+    fn test_store_column_configs() {
+        let mut config = Config::default();
+        config.store.column_configs.insert(
+            "FlatState".to_string(),
+            near_store::config::ColumnConfig {
+                bloom_filter_bits: Some(16.0),
+                ..Default::default()
+            },
+        );
+        validate_config(&config).unwrap();
+        config.store.column_configs.insert("NoSuchColumn".to_string(), Default::default());
+        validate_config(&config).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "json_rpc")]
+    #[should_panic(
+        expected = "config.rpc.rate_limits.per_client.capacity 5 is less than 20, the max cost of a request"
+    )]
+    fn test_rpc_rate_limits() {
+        let mut config = Config::default();
+        let bucket = |capacity| near_jsonrpc::TokenBucketConfig { refill_per_second: 1., capacity };
+        let mut rate_limits: near_jsonrpc::RpcRateLimitsConfig =
+            serde_json::from_value(serde_json::json!({ "per_client": bucket(20.) })).unwrap();
+        config.rpc.as_mut().unwrap().rate_limits = Some(rate_limits.clone());
+        validate_config(&config).unwrap();
+        rate_limits.per_client = bucket(5.);
+        config.rpc.as_mut().unwrap().rate_limits = Some(rate_limits);
+        validate_config(&config).unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "'config.chunk_production_time_budget' should be greater than 0 and at most 1, but is 1.5."
+    )]
+    fn test_chunk_production_time_budget() {
+        let mut config = Config::default();
+        config.chunk_production_time_budget = None;
+        validate_config(&config).unwrap();
+        config.chunk_production_time_budget = Some(1.0);
+        validate_config(&config).unwrap();
+        config.chunk_production_time_budget = Some(1.5);
+        validate_config(&config).unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "'config.production_diagnostics.max_bundles' should be greater than 0."
+    )]
+    fn test_production_diagnostics() {
+        let mut config = Config::default();
+        validate_config(&config).unwrap();
+        config.production_diagnostics.as_mut().unwrap().max_bundles = 0;
+        validate_config(&config).unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "consensus.doomslug_timer.min_delay: 1s should be at least twice min_block_production_delay: 600ms"
+    )]
+    fn test_doomslug_timer() {
+        let mut config = Config::default();
+        config.consensus.doomslug_timer = Some(DoomslugTimerConfig {
+            min_delay: Duration::from_secs(2),
+            delay_step: Duration::from_millis(200),
+            max_delay: Duration::from_secs(6),
+        });
+        validate_config(&config).unwrap();
+        config.consensus.doomslug_timer = Some(DoomslugTimerConfig {
+            min_delay: Duration::from_secs(1),
+            delay_step: Duration::from_millis(200),
+            max_delay: Duration::from_secs(6),
+        });
+        validate_config(&config).unwrap();
+    }
 }
@@ -1,9 +1,13 @@
 pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
 use crate::entity_debug::EntityDebugHandlerImpl;
+use crate::mem_tries::{load_mem_tries, MemTriesHandle};
 use crate::metrics::spawn_trie_metrics_loop;
 pub use crate::runtime::NightshadeRuntime;
+pub use near_chain::{ChainEvent, ChainEventBus};
 
 use crate::cold_storage::spawn_cold_store_loop;
+use crate::reexecution_check::{spawn_reexecution_check_loop, ReexecutionCheckHandle};
+use crate::refcount_audit::{spawn_refcount_audit_loop, RefcountAuditHandle};
 use crate::state_sync::{spawn_state_sync_dump, StateSyncDumpHandle};
 use actix::{Actor, Addr};
 use actix_rt::ArbiterHandle;
@@ -25,7 +29,7 @@ use near_store::flat::FlatStateValuesInliningMigrationHandle;
 use near_store::genesis::initialize_genesis_state;
 use near_store::metadata::DbKind;
 use near_store::metrics::spawn_db_metrics_loop;
-use near_store::{DBCol, Mode, NodeStorage, Store, StoreOpenerError};
+use near_store::{DBCol, Mode, NodeStorage, ReadPriority, Store, StoreOpenerError};
 use near_telemetry::TelemetryActor;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -41,8 +45,11 @@ pub mod dyn_config;
 #[cfg(feature = "json_rpc")]
 mod entity_debug;
 mod entity_debug_serializer;
+mod mem_tries;
 mod metrics;
 pub mod migrations;
+mod reexecution_check;
+mod refcount_audit;
 mod runtime;
 pub mod state_sync;
 pub mod test_utils;
@@ -181,8 +188,15 @@ fn get_split_store(config: &NearConfig, storage: &NodeStorage) -> anyhow::Result
         return Ok(None);
     }
 
-    // SplitStore should only be used in the view client if it is enabled.
-    if !config.config.split_storage.as_ref().map_or(false, |c| c.enable_split_storage_view_client) {
+    // SplitStore should only be used in the view client if it is enabled.  It
+    // is also needed if data is deleted from hot store as soon as it's copied
+    // to cold store since it isn't in the hot store anymore.
+    if !config
+        .config
+        .split_storage
+        .as_ref()
+        .map_or(false, |c| c.enable_split_storage_view_client || c.cold_store_eager_migration)
+    {
         return Ok(None);
     }
 
@@ -201,14 +215,25 @@ pub struct NearNode {
     pub view_client: Addr<ViewClientActor>,
     pub arbiters: Vec<ArbiterHandle>,
     pub rpc_servers: Vec<(&'static str, actix_web::dev::ServerHandle)>,
+    /// Events of blocks processed by the client are published here.
+    pub chain_event_bus: ChainEventBus,
     /// The cold_store_loop_handle will only be set if the cold store is configured.
     /// It's a handle to a background thread that copies data from the hot store to the cold store.
     pub cold_store_loop_handle: Option<ColdStoreLoopHandle>,
     /// Contains handles to background threads that may be dumping state to S3.
     pub state_sync_dump_handle: Option<StateSyncDumpHandle>,
+    /// Handle to the background thread re-applying final chunks, only set if
+    /// `reexecution_check` is configured.
+    pub reexecution_check_handle: Option<ReexecutionCheckHandle>,
+    /// Handle to the background thread auditing reference counts of trie
+    /// nodes, only set if `refcount_audit` is configured.
+    pub refcount_audit_handle: Option<RefcountAuditHandle>,
     /// A handle to control background flat state values inlining migration.
     /// Needed temporarily, will be removed after the migration is completed.
     pub flat_state_migration_handle: FlatStateValuesInliningMigrationHandle,
+    /// Handle to the in-memory tries loaded at startup, only set if
+    /// `store.load_mem_tries_for_shards` is configured.
+    pub mem_tries_handle: Option<MemTriesHandle>,
 }
 
 pub fn start_with_config(home_dir: &Path, config: NearConfig) -> anyhow::Result<NearNode> {
@@ -232,6 +257,8 @@ pub fn start_with_config_and_synchronization(
         None
     };
 
+    let ttl_arbiter = near_store::ttl::spawn_ttl_loop(&storage, config.config.store.clone());
+
     let trie_metrics_arbiter = spawn_trie_metrics_loop(
         config.clone(),
         storage.get_hot_store(),
@@ -259,15 +286,16 @@ pub fn start_with_config_and_synchronization(
     let split_store = get_split_store(&config, &storage)?;
     let (view_epoch_manager, view_shard_tracker, view_runtime) =
         if let Some(split_store) = &split_store {
+            let view_store = split_store.with_priority(ReadPriority::Rpc);
             let view_epoch_manager =
-                EpochManager::new_arc_handle(split_store.clone(), &config.genesis.config);
+                EpochManager::new_arc_handle(view_store.clone(), &config.genesis.config);
             let view_shard_tracker = ShardTracker::new(
                 TrackedConfig::from_config(&config.client_config),
                 epoch_manager.clone(),
             );
             let view_runtime = NightshadeRuntime::from_config(
                 home_dir,
-                split_store.clone(),
+                view_store,
                 &config,
                 view_epoch_manager.clone(),
             );
@@ -277,6 +305,18 @@ pub fn start_with_config_and_synchronization(
         };
 
     let cold_store_loop_handle = spawn_cold_store_loop(&config, &storage, epoch_manager.clone())?;
+    let reexecution_check_handle = spawn_reexecution_check_loop(
+        home_dir,
+        &config,
+        &storage,
+        epoch_manager.clone(),
+        runtime.clone(),
+    )?;
+    let refcount_audit_handle =
+        spawn_refcount_audit_loop(home_dir, &config, &storage, epoch_manager.clone())?;
+    // Loaded before the client starts, so that the flat storage heads don't move meanwhile.
+    let mem_tries_handle =
+        load_mem_tries(home_dir, &config, runtime.get_tries()).context("load_mem_tries")?;
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::new(&config.genesis);
@@ -301,6 +341,13 @@ pub fn start_with_config_and_synchronization(
         None
     };
 
+    // Transactions, receipts and outcomes are deleted from the hot store as
+    // soon as they're copied to cold storage, so reads have to fall back to it.
+    let cold_store = if config.client_config.cold_store_eager_migration {
+        storage.get_cold_store()
+    } else {
+        None
+    };
     let view_client = start_view_client(
         config.validator_signer.as_ref().map(|signer| signer.validator_id().clone()),
         chain_genesis.clone(),
@@ -310,6 +357,7 @@ pub fn start_with_config_and_synchronization(
         network_adapter.clone().into(),
         config.client_config.clone(),
         adv.clone(),
+        cold_store.clone(),
     );
     let make_state_snapshot_callback = if let Some(state_snapshot_actor) = state_snapshot_actor {
         Some(get_make_snapshot_callback(
@@ -320,7 +368,7 @@ pub fn start_with_config_and_synchronization(
     } else {
         None
     };
-    let (client_actor, client_arbiter_handle) = start_client(
+    let (client_actor, client_arbiter_handle, chain_event_bus) = start_client(
         config.client_config.clone(),
         chain_genesis.clone(),
         epoch_manager.clone(),
@@ -335,6 +383,7 @@ pub fn start_with_config_and_synchronization(
         shutdown_signal,
         adv,
         config_updater,
+        cold_store,
     );
     client_adapter_for_shards_manager.bind(client_actor.clone().with_auto_span_context());
     let (shards_manager_actor, shards_manager_arbiter_handle) = start_shards_manager(
@@ -348,12 +397,10 @@ pub fn start_with_config_and_synchronization(
     );
     shards_manager_adapter.bind(shards_manager_actor);
 
-    let flat_state_migration_handle =
-        FlatStateValuesInliningMigrationHandle::start_background_migration(
-            storage.get_hot_store(),
-            runtime.get_flat_storage_manager(),
-            config.client_config.client_background_migration_threads,
-        );
+    let flat_state_migration_handle = runtime.get_flat_storage_manager().start_values_inlining(
+        config.client_config.client_background_migration_threads,
+        config.config.store.flat_state_values_inlining.clone(),
+    );
 
     let state_sync_dump_handle = spawn_state_sync_dump(
         &config.client_config,
@@ -392,6 +439,7 @@ pub fn start_with_config_and_synchronization(
             view_client.clone(),
             Some(network_actor),
             Arc::new(entity_debug_handler),
+            Some(chain_event_bus.clone()),
         ));
     }
 
@@ -409,12 +457,22 @@ pub fn start_with_config_and_synchronization(
         ));
     }
 
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_config) = config.grpc_config {
+        near_grpc::start_grpc(grpc_config, view_client.clone(), &chain_event_bus)
+            .context("start_grpc()")?;
+    }
+
     rpc_servers.shrink_to_fit();
 
     tracing::trace!(target: "diagnostic", key = "log", "Starting NEAR node with diagnostic activated");
 
-    let mut arbiters =
-        vec![client_arbiter_handle, shards_manager_arbiter_handle, trie_metrics_arbiter];
+    let mut arbiters = vec![
+        client_arbiter_handle,
+        shards_manager_arbiter_handle,
+        trie_metrics_arbiter,
+        ttl_arbiter,
+    ];
     if let Some(db_metrics_arbiter) = db_metrics_arbiter {
         arbiters.push(db_metrics_arbiter);
     }
@@ -423,10 +481,14 @@ pub fn start_with_config_and_synchronization(
         client: client_actor,
         view_client,
         rpc_servers,
+        chain_event_bus,
         arbiters,
         cold_store_loop_handle,
         state_sync_dump_handle,
+        reexecution_check_handle,
+        refcount_audit_handle,
         flat_state_migration_handle,
+        mem_tries_handle,
     })
 }
 
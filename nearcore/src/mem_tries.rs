@@ -0,0 +1,77 @@
+//! Loading of in-memory tries when the node starts.
+//!
+//! The shards listed in `config.store.load_mem_tries_for_shards` are loaded
+//! with the state as of their flat storage head, and then follow the chain
+//! as the trie changes of applied chunks are committed. If
+//! `config.store.save_mem_tries_on_shutdown` is set, the tries are saved to
+//! the `mem_tries` directory in the home directory on shutdown, and loaded
+//! from there on the next start instead of being rebuilt from flat storage.
+
+use crate::NearConfig;
+use anyhow::Context;
+use near_primitives::shard_layout::get_block_shard_uid;
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_store::flat::{store_helper, FlatStorageStatus};
+use near_store::{DBCol, ShardTries};
+use std::path::{Path, PathBuf};
+
+/// Directory, relative to the home directory, the in-memory tries are saved
+/// to.
+const MEM_TRIES_DIR: &str = "mem_tries";
+
+/// A handle to the loaded in-memory tries, used to save them on shutdown.
+pub struct MemTriesHandle {
+    tries: ShardTries,
+    save_dir: Option<PathBuf>,
+}
+
+impl MemTriesHandle {
+    /// Saves the in-memory tries if `config.store.save_mem_tries_on_shutdown`
+    /// is set. Must be called once blocks are no longer processed.
+    pub fn save(&self) {
+        let Some(dir) = &self.save_dir else {
+            return;
+        };
+        if let Err(err) = self.tries.save_mem_tries(dir) {
+            tracing::warn!(target: "memtrie", ?err, dir = %dir.display(), "Failed to save mem-tries");
+        }
+    }
+}
+
+/// Loads the in-memory tries of the configured shards. Returns `None` if no
+/// shard is configured.
+pub fn load_mem_tries(
+    home_dir: &Path,
+    config: &NearConfig,
+    tries: ShardTries,
+) -> anyhow::Result<Option<MemTriesHandle>> {
+    let store_config = &config.config.store;
+    if store_config.load_mem_tries_for_shards.is_empty() {
+        return Ok(None);
+    }
+    let save_dir = store_config.save_mem_tries_on_shutdown.then(|| home_dir.join(MEM_TRIES_DIR));
+    let store = tries.get_store();
+    for &shard_uid in &store_config.load_mem_tries_for_shards {
+        let FlatStorageStatus::Ready(status) =
+            store_helper::get_flat_storage_status(&store, shard_uid)?
+        else {
+            tracing::warn!(target: "memtrie", %shard_uid, "Flat storage is not ready, not loading mem-trie");
+            continue;
+        };
+        let flat_head = status.flat_head;
+        let chunk_extra: ChunkExtra = store
+            .get_ser(DBCol::ChunkExtra, &get_block_shard_uid(&flat_head.hash, &shard_uid))?
+            .with_context(|| {
+                format!("no chunk extra of shard {shard_uid} at flat head {}", flat_head.hash)
+            })?;
+        tries
+            .load_mem_tries(
+                shard_uid,
+                *chunk_extra.state_root(),
+                flat_head.height,
+                save_dir.as_deref(),
+            )
+            .with_context(|| format!("failed to load mem-trie of shard {shard_uid}"))?;
+    }
+    Ok(Some(MemTriesHandle { tries, save_dir }))
+}
@@ -72,6 +72,32 @@ pub(crate) static COLD_STORE_COPY_RESULT: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static REEXECUTION_CHECK_RESULT: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_reexecution_check_result",
+        "Results of re-applying chunks of final blocks by the re-execution check. A mismatch means that applying the chunk again gave a different outcome than the recorded one",
+        &["shard_id", "result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static REEXECUTION_CHECK_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_reexecution_check_height",
+        "Height of the last final block checked by the re-execution check",
+    )
+    .unwrap()
+});
+
+pub(crate) static REFCOUNT_AUDIT_ROUNDS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_refcount_audit_rounds_total",
+        "Number of state roots whose trie was audited by the audit of reference counts, by whether a referenced node was found to be missing",
+        &["shard_uid", "result"],
+    )
+    .unwrap()
+});
+
 pub(crate) static STATE_SYNC_DUMP_ITERATION_ELAPSED: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_state_sync_dump_iteration_elapsed_sec",
@@ -118,6 +144,25 @@ pub(crate) static STATE_SYNC_DUMP_EPOCH_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(||
     .unwrap()
 });
 
+pub(crate) static PREFETCH_RECEIPTS_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_prefetch_receipts_delay_sec",
+        "Latency of warming up the storage caches for the receipts of the next chunk",
+        &["shard_id"],
+        Some(exponential_buckets(0.001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static PREFETCH_RECEIPTS_CONTRACTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_prefetch_receipts_contracts_total",
+        "Number of contracts whose code was read ahead of applying the receipts calling them",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static STATE_SYNC_APPLY_PART_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_state_sync_apply_part_delay_sec",
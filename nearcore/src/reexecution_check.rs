@@ -0,0 +1,270 @@
+//! Background re-execution of chunks in final blocks.
+//!
+//! When `config.reexecution_check` is set, a sample of new chunks in final
+//! blocks is applied again on top of the state root they were originally
+//! applied to and the result is compared with the recorded `ChunkExtra`.
+//! A mismatch means that either the state in the store got corrupted or that
+//! applying chunks isn't deterministic.  Mismatches are counted in the
+//! `near_reexecution_check_result` metric and their details are written to
+//! the `reexecution_check` directory in the home directory.
+//!
+//! Only chunks of tracked shards are checked since the node doesn't have the
+//! state of other shards.
+
+use crate::config::ReexecutionCheckConfig;
+use crate::{metrics, NearConfig};
+use anyhow::Context;
+use near_chain::chain::collect_receipts_from_response;
+use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
+use near_chain::types::{ApplyTransactionResult, RuntimeAdapter, Tip};
+use near_chain::{Block, ChainStore, ChainStoreAccess};
+use near_epoch_manager::{EpochManagerAdapter, EpochManagerHandle};
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::{DBCol, NodeStorage, Store, FINAL_HEAD_KEY};
+use rand::Rng;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Directory, relative to the home directory, the details of mismatches are
+/// written to.
+const DUMP_DIR: &str = "reexecution_check";
+
+/// A handle to the re-execution check loop which can be used to stop it.
+pub struct ReexecutionCheckHandle {
+    join_handle: std::thread::JoinHandle<()>,
+    keep_going: Arc<AtomicBool>,
+}
+
+impl ReexecutionCheckHandle {
+    pub fn stop(self) {
+        self.keep_going.store(false, Ordering::Relaxed);
+        if self.join_handle.join().is_err() {
+            tracing::error!(target: "reexecution_check", "Failed to join the re-execution check thread");
+        }
+    }
+}
+
+enum CheckResult {
+    Match,
+    Mismatch,
+    /// The shard isn't tracked by the node.
+    NotTracked,
+}
+
+impl CheckResult {
+    fn label(&self) -> &'static str {
+        match self {
+            CheckResult::Match => "match",
+            CheckResult::Mismatch => "mismatch",
+            CheckResult::NotTracked => "not_tracked",
+        }
+    }
+}
+
+struct ReexecutionChecker {
+    config: ReexecutionCheckConfig,
+    hot_store: Store,
+    chain_store: ChainStore,
+    epoch_manager: Arc<EpochManagerHandle>,
+    runtime: Arc<dyn RuntimeAdapter>,
+    dump_dir: PathBuf,
+    /// Height of the last final block which has been checked.  The loop
+    /// starts with blocks finalised after the node started.
+    last_checked_height: Option<BlockHeight>,
+}
+
+impl ReexecutionChecker {
+    /// Checks sampled chunks of blocks finalised since the last call.
+    fn check_new_final_blocks(&mut self, keep_going: &AtomicBool) -> anyhow::Result<()> {
+        let Some(final_head) = self.hot_store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?
+        else {
+            return Ok(());
+        };
+        let start_height = self.last_checked_height.map_or(final_head.height, |height| height + 1);
+        for height in start_height..=final_head.height {
+            if !keep_going.load(Ordering::Relaxed) {
+                break;
+            }
+            // There may be no block at some heights.
+            if let Ok(block_hash) = self.chain_store.get_block_hash_by_height(height) {
+                let block = self.chain_store.get_block(&block_hash)?;
+                self.check_block(&block);
+            }
+            self.last_checked_height = Some(height);
+            metrics::REEXECUTION_CHECK_HEIGHT.set(height as i64);
+        }
+        Ok(())
+    }
+
+    fn check_block(&self, block: &Block) {
+        let height = block.header().height();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height
+                || !rand::thread_rng().gen_bool(self.config.sample_rate)
+            {
+                continue;
+            }
+            let shard_id = chunk_header.shard_id();
+            let result = match self.check_chunk(block, shard_id) {
+                Ok(result) => result.label(),
+                Err(err) => {
+                    tracing::warn!(target: "reexecution_check", height, shard_id, ?err, "Failed to re-apply chunk");
+                    "error"
+                }
+            };
+            metrics::REEXECUTION_CHECK_RESULT
+                .with_label_values(&[&shard_id.to_string(), result])
+                .inc();
+        }
+    }
+
+    /// Applies the new chunk of given shard in given block again and compares
+    /// the outcome with the recorded one.
+    fn check_chunk(&self, block: &Block, shard_id: ShardId) -> anyhow::Result<CheckResult> {
+        let block_hash = block.hash();
+        let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+        // Chunk extra is only saved for tracked shards.
+        let Ok(expected) = self.chain_store.get_chunk_extra(block_hash, &shard_uid) else {
+            return Ok(CheckResult::NotTracked);
+        };
+        let chunk_hash = block.chunks()[shard_id as usize].chunk_hash();
+        let chunk = self.chain_store.get_chunk(&chunk_hash)?;
+        let prev_block = self.chain_store.get_block(block.header().prev_hash())?;
+        let receipts = self.chain_store.get_incoming_receipts_for_shard(
+            self.epoch_manager.as_ref(),
+            shard_id,
+            *block_hash,
+            prev_block.chunks()[shard_id as usize].height_included(),
+        )?;
+        let receipts = collect_receipts_from_response(&receipts);
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            &self.chain_store,
+            self.epoch_manager.as_ref(),
+            prev_block.hash(),
+            shard_id,
+        )?;
+
+        let chunk_inner = chunk.cloned_header().take_inner();
+        // Flat storage only has the state as of recent blocks so the state
+        // is read from the trie.
+        let apply_result = self.runtime.apply_transactions_with_optional_storage_proof(
+            shard_id,
+            chunk_inner.prev_state_root(),
+            block.header().height(),
+            block.header().raw_timestamp(),
+            prev_block.hash(),
+            block_hash,
+            &receipts,
+            chunk.transactions(),
+            chunk_inner.prev_validator_proposals(),
+            prev_block.header().gas_price(),
+            chunk_inner.gas_limit(),
+            block.header().challenges_result(),
+            *block.header().random_value(),
+            false,
+            true,
+            is_first_block_with_chunk_of_version,
+            Default::default(),
+            false,
+        )?;
+        let (outcome_root, _) =
+            ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
+        let actual = ChunkExtra::new(
+            &apply_result.new_root,
+            outcome_root,
+            apply_result.validator_proposals.clone(),
+            apply_result.total_gas_burnt,
+            chunk_inner.gas_limit(),
+            apply_result.total_balance_burnt,
+        );
+        if expected.state_root() == actual.state_root()
+            && expected.outcome_root() == actual.outcome_root()
+            && expected.gas_used() == actual.gas_used()
+            && expected.balance_burnt() == actual.balance_burnt()
+        {
+            return Ok(CheckResult::Match);
+        }
+
+        tracing::error!(
+            target: "reexecution_check",
+            height = block.header().height(),
+            %block_hash,
+            shard_id,
+            ?expected,
+            ?actual,
+            "Re-applying chunk gave a different outcome than the recorded one");
+        let recorded_outcomes = self
+            .chain_store
+            .get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?
+            .into_iter()
+            .map(|id| self.chain_store.get_outcome_by_id_and_block_hash(&id, block_hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dump = format!(
+            "block height: {}\nblock hash: {}\nshard id: {}\nchunk hash: {:?}\n\n\
+             recorded chunk extra: {:#?}\n\nre-applied chunk extra: {:#?}\n\n\
+             recorded outcomes: {:#?}\n\nre-applied outcomes: {:#?}\n",
+            block.header().height(),
+            block_hash,
+            shard_id,
+            chunk_hash,
+            expected,
+            actual,
+            recorded_outcomes,
+            apply_result.outcomes,
+        );
+        let path = self.dump_dir.join(format!("{}_{}.txt", block.header().height(), shard_id));
+        std::fs::create_dir_all(&self.dump_dir)
+            .and_then(|()| std::fs::write(&path, dump))
+            .with_context(|| format!("failed to write mismatch details to {}", path.display()))?;
+        Ok(CheckResult::Mismatch)
+    }
+}
+
+/// Spawns the re-execution check loop in a background thread.  Like the cold
+/// store loop, it's a native thread since it mostly does blocking IO.  Does
+/// nothing if the check isn't configured.
+pub fn spawn_reexecution_check_loop(
+    home_dir: &Path,
+    config: &NearConfig,
+    storage: &NodeStorage,
+    epoch_manager: Arc<EpochManagerHandle>,
+    runtime: Arc<dyn RuntimeAdapter>,
+) -> anyhow::Result<Option<ReexecutionCheckHandle>> {
+    let Some(check_config) = config.config.reexecution_check.clone() else {
+        return Ok(None);
+    };
+    let hot_store = storage.get_hot_store();
+    let mut chain_store =
+        ChainStore::new(hot_store.clone(), config.genesis.config.genesis_height, false);
+    if config.client_config.cold_store_eager_migration {
+        if let Some(cold_store) = storage.get_cold_store() {
+            chain_store.set_cold_store(cold_store);
+        }
+    }
+    let mut checker = ReexecutionChecker {
+        config: check_config,
+        chain_store,
+        hot_store,
+        epoch_manager,
+        runtime,
+        dump_dir: home_dir.join(DUMP_DIR),
+        last_checked_height: None,
+    };
+    let keep_going = Arc::new(AtomicBool::new(true));
+    let keep_going_clone = keep_going.clone();
+
+    tracing::info!(target: "reexecution_check", sample_rate = checker.config.sample_rate, "Spawning the re-execution check loop");
+    let join_handle =
+        std::thread::Builder::new().name("reexecution_check".to_string()).spawn(move || {
+            while keep_going_clone.load(Ordering::Relaxed) {
+                if let Err(err) = checker.check_new_final_blocks(&keep_going_clone) {
+                    tracing::error!(target: "reexecution_check", ?err, "Failed to check final blocks");
+                }
+                std::thread::sleep(checker.config.sleep_duration);
+            }
+        })?;
+
+    Ok(Some(ReexecutionCheckHandle { join_handle, keep_going }))
+}
@@ -0,0 +1,160 @@
+//! Background audit of reference counts of state trie nodes.
+//!
+//! When `config.refcount_audit` is set, the trie of every tracked shard at the
+//! final head is traversed in small steps, one shard after another, and the
+//! references of a sample of its nodes are recounted, see
+//! `near_store::trie::refcount_audit`.  Results are exported as the
+//! `near_refcount_audit_*` metrics and the details of every audit which found
+//! a difference are written to the `refcount_audit` directory in the home
+//! directory.
+
+use crate::config::RefcountAuditConfig;
+use crate::{metrics, NearConfig};
+use near_chain::types::Tip;
+use near_epoch_manager::{EpochManagerAdapter, EpochManagerHandle};
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::get_block_shard_uid;
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_store::trie::refcount_audit::{RefcountAudit, RefcountAuditResult};
+use near_store::{DBCol, NodeStorage, Store, FINAL_HEAD_KEY};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Directory, relative to the home directory, reports are written to.
+const REPORT_DIR: &str = "refcount_audit";
+
+/// A handle to the refcount audit loop which can be used to stop it.
+pub struct RefcountAuditHandle {
+    join_handle: std::thread::JoinHandle<()>,
+    keep_going: Arc<AtomicBool>,
+}
+
+impl RefcountAuditHandle {
+    pub fn stop(self) {
+        self.keep_going.store(false, Ordering::Relaxed);
+        if self.join_handle.join().is_err() {
+            tracing::error!(target: "refcount_audit", "Failed to join the refcount audit thread");
+        }
+    }
+}
+
+struct RefcountAuditor {
+    config: RefcountAuditConfig,
+    hot_store: Store,
+    epoch_manager: Arc<EpochManagerHandle>,
+    report_dir: PathBuf,
+    /// Audits of shards which haven't been started yet, with the hash of the
+    /// block whose state they audit.
+    queue: VecDeque<(CryptoHash, RefcountAudit)>,
+    current: Option<(CryptoHash, RefcountAudit)>,
+}
+
+impl RefcountAuditor {
+    /// Advances the current audit by one step, starting audits of the state
+    /// at the final head once all previous ones are done.
+    fn step(&mut self) -> anyhow::Result<()> {
+        if self.current.is_none() {
+            if self.queue.is_empty() {
+                self.queue_final_head_audits()?;
+            }
+            self.current = self.queue.pop_front();
+        }
+        let Some((block_hash, audit)) = &mut self.current else { return Ok(()) };
+        let Some(report) = audit.step(self.config.nodes_per_step)? else { return Ok(()) };
+        let block_hash = *block_hash;
+        self.current = None;
+
+        // Nodes of the audited state are deleted once garbage collection
+        // reaches the block, which makes them look missing.
+        if !self.hot_store.exists(DBCol::Block, block_hash.as_ref())? {
+            tracing::debug!(target: "refcount_audit", shard_uid = %report.shard_uid, %block_hash, "Discarding audit of garbage collected state");
+            return Ok(());
+        }
+        let missing = report.count(RefcountAuditResult::Missing);
+        let drift = report.count(RefcountAuditResult::Drift);
+        metrics::REFCOUNT_AUDIT_ROUNDS
+            .with_label_values(&[
+                &report.shard_uid.to_string(),
+                if missing > 0 { "missing" } else { "ok" },
+            ])
+            .inc();
+        if missing == 0 && drift == 0 {
+            tracing::debug!(target: "refcount_audit", shard_uid = %report.shard_uid, root = %report.root, sampled = report.entries.len(), "Reference counts match");
+            return Ok(());
+        }
+        let path = report.write_to_dir(&self.report_dir)?;
+        if missing > 0 {
+            tracing::error!(target: "refcount_audit", shard_uid = %report.shard_uid, root = %report.root, missing, drift, path = %path.display(), "Referenced trie nodes are missing");
+        } else {
+            tracing::info!(target: "refcount_audit", shard_uid = %report.shard_uid, root = %report.root, drift, path = %path.display(), "Reference counts drift");
+        }
+        Ok(())
+    }
+
+    /// Queues audits of the state of all shards at the final head which the
+    /// node has the state of.
+    fn queue_final_head_audits(&mut self) -> anyhow::Result<()> {
+        let Some(final_head) = self.hot_store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?
+        else {
+            return Ok(());
+        };
+        let block_hash = final_head.last_block_hash;
+        let shard_layout = self.epoch_manager.get_shard_layout(&final_head.epoch_id)?;
+        for shard_uid in shard_layout.get_shard_uids() {
+            let key = get_block_shard_uid(&block_hash, &shard_uid);
+            let Some(chunk_extra) =
+                self.hot_store.get_ser::<ChunkExtra>(DBCol::ChunkExtra, &key)?
+            else {
+                continue;
+            };
+            let audit = RefcountAudit::new(
+                self.hot_store.clone(),
+                shard_uid,
+                *chunk_extra.state_root(),
+                self.config.sample_rate,
+            );
+            self.queue.push_back((block_hash, audit));
+        }
+        Ok(())
+    }
+}
+
+/// Spawns the refcount audit loop in a background thread.  Like the cold
+/// store loop, it's a native thread since it only does blocking IO.  Does
+/// nothing if the audit isn't configured.
+pub fn spawn_refcount_audit_loop(
+    home_dir: &Path,
+    config: &NearConfig,
+    storage: &NodeStorage,
+    epoch_manager: Arc<EpochManagerHandle>,
+) -> anyhow::Result<Option<RefcountAuditHandle>> {
+    let Some(audit_config) = config.config.refcount_audit.clone() else {
+        return Ok(None);
+    };
+    let mut auditor = RefcountAuditor {
+        config: audit_config,
+        hot_store: storage.get_hot_store(),
+        epoch_manager,
+        report_dir: home_dir.join(REPORT_DIR),
+        queue: VecDeque::new(),
+        current: None,
+    };
+    let keep_going = Arc::new(AtomicBool::new(true));
+    let keep_going_clone = keep_going.clone();
+
+    tracing::info!(target: "refcount_audit", sample_rate = auditor.config.sample_rate, "Spawning the refcount audit loop");
+    let join_handle =
+        std::thread::Builder::new().name("refcount_audit".to_string()).spawn(move || {
+            while keep_going_clone.load(Ordering::Relaxed) {
+                if let Err(err) = auditor.step() {
+                    tracing::error!(target: "refcount_audit", ?err, "Failed to audit reference counts");
+                    auditor.current = None;
+                }
+                std::thread::sleep(auditor.config.step_period);
+            }
+        })?;
+
+    Ok(Some(RefcountAuditHandle { join_handle, keep_going }))
+}
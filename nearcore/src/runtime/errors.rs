@@ -108,4 +108,22 @@ impl QueryError {
     ) -> Self {
         Self::InternalError { error_message: error.to_string(), block_height, block_hash }
     }
+
+    pub fn from_state_override_error(
+        error: node_runtime::state_viewer::errors::StateOverrideError,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    ) -> Self {
+        match error {
+            node_runtime::state_viewer::errors::StateOverrideError::AccountDoesNotExist {
+                requested_account_id,
+            } => Self::UnknownAccount { requested_account_id, block_height, block_hash },
+            error @ node_runtime::state_viewer::errors::StateOverrideError::UnsupportedRecord => {
+                Self::InternalError { error_message: error.to_string(), block_height, block_hash }
+            }
+            node_runtime::state_viewer::errors::StateOverrideError::InternalError {
+                error_message,
+            } => Self::InternalError { error_message, block_height, block_hash },
+        }
+    }
 }
@@ -4,7 +4,9 @@ use crate::NearConfig;
 use borsh::ser::BorshSerialize;
 use borsh::BorshDeserialize;
 use errors::FromStateViewerErrors;
-use near_chain::types::{ApplySplitStateResult, ApplyTransactionResult, RuntimeAdapter, Tip};
+use near_chain::types::{
+    ApplySplitStateResult, ApplyTransactionResult, ChunkApplyStats, RuntimeAdapter, Tip,
+};
 use near_chain::Error;
 use near_chain_configs::{
     GenesisConfig, ProtocolConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP, MIN_GC_NUM_EPOCHS_TO_KEEP,
@@ -18,7 +20,7 @@ use near_primitives::config::ActionCosts;
 use near_primitives::config::ExtCosts;
 use near_primitives::errors::{InvalidTxError, RuntimeError, StorageError};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::receipt::{DelayedReceiptIndices, Receipt};
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum};
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
 use near_primitives::sandbox::state_patch::SandboxStatePatch;
@@ -26,7 +28,8 @@ use near_primitives::shard_layout::{
     account_id_to_shard_id, account_id_to_shard_uid, ShardLayout, ShardUId,
 };
 use near_primitives::state_part::PartId;
-use near_primitives::transaction::SignedTransaction;
+use near_primitives::state_record::StateRecord;
+use near_primitives::transaction::{Action, SignedTransaction};
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::validator_stake::ValidatorStakeIter;
 use near_primitives::types::{
@@ -35,8 +38,8 @@ use near_primitives::types::{
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
-    AccessKeyInfoView, CallResult, QueryRequest, QueryResponse, QueryResponseKind, ViewApplyState,
-    ViewStateResult,
+    AccessKeyInfoView, CallResult, ContinuationToken, QueryRequest, QueryResponse,
+    QueryResponseKind, SimulationRequest, SimulationResultView, ViewApplyState, ViewStateResult,
 };
 use near_store::flat::FlatStorageManager;
 use near_store::metadata::DbKind;
@@ -48,18 +51,19 @@ use near_vm_runner::logic::CompiledContractCache;
 use near_vm_runner::precompile_contract;
 use near_vm_runner::ContractCode;
 use node_runtime::adapter::ViewRuntimeAdapter;
-use node_runtime::state_viewer::TrieViewer;
+use node_runtime::state_viewer::{TrieViewer, DEFAULT_STATE_PAGE_SIZE_LIMIT};
 use node_runtime::{
     validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
     ValidatorAccountsUpdate,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info};
 
 pub mod errors;
+mod simulation;
 
 /// Defines Nightshade state transition and validator rotation.
 /// TODO: this possibly should be merged with the runtime cargo or at least reconciled on the interfaces.
@@ -89,6 +93,7 @@ impl NightshadeRuntime {
                 hot_store_path: config.config.store.path.clone().unwrap_or(PathBuf::from("data")),
                 state_snapshot_subdir: PathBuf::from("state_snapshot"),
                 compaction_enabled: config.config.store.state_snapshot_compaction_enabled,
+                encryption: config.config.store.encryption.clone(),
             }
         } else {
             StateSnapshotConfig::Disabled
@@ -98,6 +103,7 @@ impl NightshadeRuntime {
             &config.genesis.config,
             epoch_manager,
             config.client_config.trie_viewer_state_size_limit,
+            config.client_config.trie_viewer_page_size_limit,
             config.client_config.max_gas_burnt_view,
             None,
             config.config.gc.gc_num_epochs_to_keep(),
@@ -111,6 +117,7 @@ impl NightshadeRuntime {
         genesis_config: &GenesisConfig,
         epoch_manager: Arc<EpochManagerHandle>,
         trie_viewer_state_size_limit: Option<u64>,
+        trie_viewer_page_size_limit: u64,
         max_gas_burnt_view: Option<Gas>,
         runtime_config_store: Option<RuntimeConfigStore>,
         gc_num_epochs_to_keep: u64,
@@ -123,8 +130,12 @@ impl NightshadeRuntime {
         };
 
         let runtime = Runtime::new();
-        let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view);
-        let flat_storage_manager = FlatStorageManager::new(store.clone());
+        let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view)
+            .with_page_size_limit(trie_viewer_page_size_limit);
+        let flat_storage_manager = FlatStorageManager::with_delta_pruning(
+            store.clone(),
+            trie_config.flat_storage_delta_pruning,
+        );
         let tries = ShardTries::new_with_state_snapshot(
             store.clone(),
             trie_config,
@@ -167,6 +178,7 @@ impl NightshadeRuntime {
             genesis_config,
             epoch_manager,
             None,
+            DEFAULT_STATE_PAGE_SIZE_LIMIT,
             None,
             Some(runtime_config_store),
             DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
@@ -176,6 +188,7 @@ impl NightshadeRuntime {
                 hot_store_path: PathBuf::from("data"),
                 state_snapshot_subdir: PathBuf::from("state_snapshot"),
                 compaction_enabled: false,
+                encryption: None,
             },
         )
     }
@@ -397,8 +410,14 @@ impl NightshadeRuntime {
         metrics::DELAYED_RECEIPTS_COUNT
             .with_label_values(&[&shard_label])
             .set(apply_result.delayed_receipts_count as i64);
+        let mut apply_stats = ChunkApplyStats::default();
         if let Some(metrics) = apply_result.metrics {
             metrics.report(&shard_label);
+            apply_stats = ChunkApplyStats {
+                storage_read_time: metrics.storage_read_time(),
+                wasm_time: metrics.wasm_time(),
+                receipts_processed: metrics.receipts_processed(),
+            };
         }
 
         let total_balance_burnt = apply_result
@@ -419,6 +438,7 @@ impl NightshadeRuntime {
                 apply_result.trie_changes,
                 apply_result.state_changes,
                 *block_hash,
+                block_height,
             ),
             new_root: apply_result.state_root,
             outcomes: apply_result.outcomes,
@@ -428,6 +448,7 @@ impl NightshadeRuntime {
             total_balance_burnt,
             proof: apply_result.proof,
             processed_delayed_receipts: apply_result.processed_delayed_receipts,
+            apply_stats,
         };
 
         Ok(result)
@@ -543,7 +564,25 @@ impl NightshadeRuntime {
             .tries
             .get_trie_with_block_hash_for_shard_from_snapshot(shard_uid, *state_root, &prev_hash)
             .map_err(|err| Error::Other(err.to_string()))?;
-        let state_part = match snapshot_trie.get_trie_nodes_for_part_with_flat_storage(part_id, partial_state, nibbles_begin, nibbles_end, &trie_with_state) {
+        let state_part = match self.tries.state_part_memory_limit() {
+            Some(memory_limit) => snapshot_trie
+                .get_trie_nodes_for_part_with_flat_storage_streaming(
+                    part_id,
+                    partial_state,
+                    nibbles_begin,
+                    nibbles_end,
+                    &trie_with_state,
+                    memory_limit,
+                ),
+            None => snapshot_trie.get_trie_nodes_for_part_with_flat_storage(
+                part_id,
+                partial_state,
+                nibbles_begin,
+                nibbles_end,
+                &trie_with_state,
+            ),
+        };
+        let state_part = match state_part {
             Ok(partial_state) => partial_state,
             Err(err) => {
                 error!(target: "runtime", ?err, part_id.idx, part_id.total, %prev_hash, %state_root, %shard_id, "Can't get trie nodes for state part");
@@ -669,7 +708,9 @@ impl RuntimeAdapter for NightshadeRuntime {
         pool_iterator: &mut dyn PoolIterator,
         chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
         current_protocol_version: ProtocolVersion,
+        time_limit: Option<Duration>,
     ) -> Result<Vec<SignedTransaction>, Error> {
+        let start_time = Instant::now();
         let shard_uid = self.get_shard_uid_from_epoch_id(shard_id, epoch_id)?;
         let mut state_update = self.tries.new_trie_update(shard_uid, state_root);
 
@@ -721,6 +762,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         while total_gas_burnt < transactions_gas_limit
             && total_size < size_limit
             && transactions.len() < new_receipt_count_limit
+            && time_limit.map_or(true, |time_limit| start_time.elapsed() < time_limit)
         {
             if let Some(iter) = pool_iterator.next() {
                 while let Some(tx) = iter.next() {
@@ -764,7 +806,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 break;
             }
         }
-        debug!(target: "runtime", "Transaction filtering results {} valid out of {} pulled from the pool", transactions.len(), num_checked_transactions);
+        debug!(target: "runtime", elapsed = ?start_time.elapsed(), "Transaction filtering results {} valid out of {} pulled from the pool", transactions.len(), num_checked_transactions);
         metrics::PREPARE_TX_SIZE
             .with_label_values(&[&shard_id.to_string()])
             .observe(total_size as f64);
@@ -840,6 +882,59 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn prefetch_receipts(
+        &self,
+        shard_id: ShardId,
+        prev_hash: &CryptoHash,
+        state_root: StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<(), Error> {
+        let shard_label = shard_id.to_string();
+        let _timer =
+            metrics::PREFETCH_RECEIPTS_DELAY.with_label_values(&[&shard_label]).start_timer();
+        let _span = tracing::debug_span!(
+            target: "runtime",
+            "prefetch_receipts",
+            shard_id,
+            num_receipts = receipts.len())
+        .entered();
+        // Receivers of the receipts, and whether any of the receipts calls their contract.
+        let mut receivers: BTreeMap<&AccountId, bool> = BTreeMap::new();
+        for receipt in receipts {
+            if let ReceiptEnum::Action(action_receipt) = &receipt.receipt {
+                let calls_contract = action_receipt
+                    .actions
+                    .iter()
+                    .any(|action| matches!(action, Action::FunctionCall(_)));
+                *receivers.entry(&receipt.receiver_id).or_default() |= calls_contract;
+            }
+        }
+        if receivers.is_empty() {
+            return Ok(());
+        }
+
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(prev_hash)?;
+        let shard_uid = self.get_shard_uid_from_prev_hash(shard_id, prev_hash)?;
+        let trie =
+            self.tries.get_warm_up_trie_with_block_hash_for_shard(shard_uid, state_root, prev_hash);
+        let mut contract_codes = vec![];
+        for (account_id, calls_contract) in receivers {
+            let Some(account) = near_store::get_account(&trie, account_id)? else { continue };
+            if !calls_contract || account.code_hash() == CryptoHash::default() {
+                continue;
+            }
+            if let Some(code) = near_store::get_code(&trie, account_id, Some(account.code_hash()))?
+            {
+                contract_codes.push(code);
+            }
+        }
+        metrics::PREFETCH_RECEIPTS_CONTRACTS
+            .with_label_values(&[&shard_label])
+            .inc_by(contract_codes.len() as u64);
+        // Contracts which are already in the compiled contract cache are skipped.
+        self.precompile_contracts(&epoch_id, contract_codes)
+    }
+
     fn check_state_transition(
         &self,
         partial_storage: PartialStorage,
@@ -959,22 +1054,41 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
-            QueryRequest::ViewState { account_id, prefix, include_proof } => {
-                let view_state_result = self
-                    .view_state(
+            QueryRequest::ViewState { account_id, prefix, include_proof, pagination } => {
+                let view_state_result = match pagination {
+                    None => self.view_state(
                         &shard_uid,
                         *state_root,
                         account_id,
                         prefix.as_ref(),
                         *include_proof,
-                    )
-                    .map_err(|err| {
-                        near_chain::near_chain_primitives::error::QueryError::from_view_state_error(
-                            err,
-                            block_height,
-                            *block_hash,
+                    ),
+                    Some(pagination) => self
+                        .view_state_page(
+                            &shard_uid,
+                            *state_root,
+                            account_id,
+                            prefix.as_ref(),
+                            *include_proof,
+                            pagination
+                                .continuation_token
+                                .as_ref()
+                                .map(|token| token.position.as_slice()),
                         )
-                    })?;
+                        .map(|(mut result, last_key)| {
+                            result.continuation_token = last_key.map(|position| {
+                                ContinuationToken { block_hash: *block_hash, position }
+                            });
+                            result
+                        }),
+                }
+                .map_err(|err| {
+                    near_chain::near_chain_primitives::error::QueryError::from_view_state_error(
+                        err,
+                        block_height,
+                        *block_hash,
+                    )
+                })?;
                 Ok(QueryResponse {
                     kind: QueryResponseKind::ViewState(view_state_result),
                     block_height,
@@ -1024,6 +1138,31 @@ impl RuntimeAdapter for NightshadeRuntime {
     }
 
     // Wrapper to get the metrics.
+    fn simulate(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        block_height: BlockHeight,
+        block_timestamp: u64,
+        prev_block_hash: &CryptoHash,
+        block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        gas_price: Balance,
+        state_overrides: &[StateRecord],
+        request: &SimulationRequest,
+    ) -> Result<SimulationResultView, near_chain::near_chain_primitives::error::QueryError> {
+        self.simulate_impl(
+            state_roots,
+            block_height,
+            block_timestamp,
+            prev_block_hash,
+            block_hash,
+            epoch_id,
+            gas_price,
+            state_overrides,
+            request,
+        )
+    }
+
     fn obtain_state_part(
         &self,
         shard_id: ShardId,
@@ -1073,6 +1212,7 @@ impl RuntimeAdapter for NightshadeRuntime {
     fn apply_update_to_split_states(
         &self,
         block_hash: &CryptoHash,
+        block_height: BlockHeight,
         state_roots: HashMap<ShardUId, StateRoot>,
         next_epoch_shard_layout: &ShardLayout,
         state_changes_for_split_states: StateChangesForSplitStates,
@@ -1100,6 +1240,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 trie_changes,
                 state_changes,
                 *block_hash,
+                block_height,
             );
             applied_split_state_results.push(ApplySplitStateResult {
                 shard_uid,
@@ -1288,6 +1429,28 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         let state_update = self.tries.new_trie_update_view(*shard_uid, state_root);
         self.trie_viewer.view_state(&state_update, account_id, prefix, include_proof)
     }
+
+    fn view_state_page(
+        &self,
+        shard_uid: &ShardUId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+        prefix: &[u8],
+        include_proof: bool,
+        start_after: Option<&[u8]>,
+    ) -> Result<
+        (ViewStateResult, Option<Vec<u8>>),
+        node_runtime::state_viewer::errors::ViewStateError,
+    > {
+        let state_update = self.tries.new_trie_update_view(*shard_uid, state_root);
+        self.trie_viewer.view_state_page(
+            &state_update,
+            account_id,
+            prefix,
+            include_proof,
+            start_after,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -1309,7 +1472,11 @@ mod test {
     use near_o11y::testonly::init_test_logger;
     use near_primitives::block::Tip;
     use near_primitives::challenge::SlashedValidator;
-    use near_primitives::transaction::{Action, DeleteAccountAction, StakeAction, TransferAction};
+    use near_primitives::receipt::ActionReceipt;
+    use near_primitives::transaction::{
+        Action, DeleteAccountAction, DeployContractAction, FunctionCallAction, StakeAction,
+        TransferAction,
+    };
     use near_primitives::types::{
         BlockHeightDelta, Nonce, NumShards, ValidatorId, ValidatorInfoIdentifier,
         ValidatorKickoutReason,
@@ -1320,6 +1487,7 @@ mod test {
         ValidatorKickoutView,
     };
     use near_store::{get_genesis_state_roots, NodeStorage};
+    use near_vm_runner::get_contract_cache_key;
 
     use super::*;
 
@@ -1471,6 +1639,7 @@ mod test {
                 &genesis.config,
                 epoch_manager.clone(),
                 None,
+                DEFAULT_STATE_PAGE_SIZE_LIMIT,
                 None,
                 Some(RuntimeConfigStore::free()),
                 DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
@@ -1480,6 +1649,7 @@ mod test {
                     hot_store_path: PathBuf::from("data"),
                     state_snapshot_subdir: PathBuf::from("state_snapshot"),
                     compaction_enabled: false,
+                    encryption: None,
                 },
             );
             let state_roots = get_genesis_state_roots(&store).unwrap().unwrap();
@@ -2761,6 +2931,63 @@ mod test {
         assert_eq!(state_value, view_state_value);
     }
 
+    /// Check that prefetching receipts which call a contract compiles the contract ahead of
+    /// applying them.
+    #[test]
+    fn test_prefetch_receipts() {
+        let validators: Vec<AccountId> = vec!["test1".parse().unwrap(), "test2".parse().unwrap()];
+        let mut env = TestEnv::new(vec![validators.clone()], 4, false);
+        let signer = InMemorySigner::from_seed(
+            validators[1].clone(),
+            KeyType::ED25519,
+            validators[1].as_ref(),
+        );
+        let code = near_test_contracts::trivial_contract().to_vec();
+        let deploy_tx = SignedTransaction::from_actions(
+            4,
+            validators[1].clone(),
+            validators[1].clone(),
+            &signer as &dyn Signer,
+            vec![Action::DeployContract(DeployContractAction { code: code.clone() })],
+            // runtime does not validate block history
+            CryptoHash::default(),
+        );
+        env.step_default(vec![deploy_tx]);
+        env.step_default(vec![]);
+
+        let runtime_config = env
+            .runtime
+            .runtime_config_store
+            .get_config(env.runtime.genesis_config.protocol_version);
+        let cache_key =
+            get_contract_cache_key(&ContractCode::new(code, None), &runtime_config.wasm_config);
+        let cache = StoreCompiledContractCache::new(&env.runtime.store);
+        assert!(!cache.has(&cache_key).unwrap());
+
+        let receipt = Receipt {
+            predecessor_id: validators[0].clone(),
+            receiver_id: validators[1].clone(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: validators[0].clone(),
+                signer_public_key: signer.public_key(),
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                    method_name: "main".to_string(),
+                    args: vec![],
+                    gas: 10u64.pow(12),
+                    deposit: 0,
+                }))],
+            }),
+        };
+        env.runtime
+            .prefetch_receipts(0, &env.head.last_block_hash, env.state_roots[0], &[receipt])
+            .unwrap();
+        assert!(cache.has(&cache_key).unwrap());
+    }
+
     /// Check that mainnet genesis hash still matches, to make sure that we're still backwards compatible.
     #[test]
     fn test_genesis_hash() {
@@ -0,0 +1,340 @@
+//! Simulation of view calls and transactions on top of the state of a block, with some of the
+//! state records overridden, see `RuntimeAdapter::simulate`.
+//!
+//! The overrides and the changes made by the simulation are kept in memory, on top of the
+//! tries of the block, and are dropped once the simulation is over.
+use super::errors::FromStateViewerErrors;
+use super::NightshadeRuntime;
+use near_chain::near_chain_primitives::error::QueryError;
+use near_primitives::errors::RuntimeError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::runtime::migration_data::MigrationFlags;
+use near_primitives::sandbox::state_patch::SandboxStatePatch;
+use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout, ShardUId};
+use near_primitives::state_record::{state_record_to_account_id, StateRecord};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{
+    Balance, BlockHeight, EpochHeight, EpochId, StateChangeCause, StateRoot,
+};
+use near_primitives::version::ProtocolVersion;
+use near_primitives::views::{
+    CallResult, ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
+    FinalExecutionStatus, SimulationRequest, SimulationResultView, ViewApplyState,
+};
+use near_store::{StoreCompiledContractCache, Trie, TrieChanges, TrieUpdate};
+use node_runtime::state_viewer::apply_state_overrides;
+use node_runtime::ApplyState;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Max number of blocks over which the receipts of a simulated transaction are executed.
+/// The receipts which didn't get executed by then are left out of the result.
+const MAX_SIMULATED_BLOCKS: u64 = 16;
+
+/// Block on top of which the simulation runs.
+struct SimulatedBlock<'a> {
+    height: BlockHeight,
+    timestamp: u64,
+    prev_block_hash: &'a CryptoHash,
+    hash: &'a CryptoHash,
+    epoch_id: &'a EpochId,
+    epoch_height: EpochHeight,
+    protocol_version: ProtocolVersion,
+    gas_price: Balance,
+}
+
+impl SimulatedBlock<'_> {
+    fn internal_error(&self, error_message: String) -> QueryError {
+        QueryError::InternalError {
+            error_message,
+            block_height: self.height,
+            block_hash: *self.hash,
+        }
+    }
+}
+
+/// Trie of a shard in the simulation, with the changes made on top of it so far.
+struct SimulatedShard {
+    base: Trie,
+    changes: Vec<TrieChanges>,
+}
+
+impl SimulatedShard {
+    fn trie(&self) -> Trie {
+        self.base.with_changes_in_memory(&self.changes)
+    }
+}
+
+impl NightshadeRuntime {
+    pub(super) fn simulate_impl(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        block_height: BlockHeight,
+        block_timestamp: u64,
+        prev_block_hash: &CryptoHash,
+        block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        gas_price: Balance,
+        state_overrides: &[StateRecord],
+        request: &SimulationRequest,
+    ) -> Result<SimulationResultView, QueryError> {
+        let (epoch_height, protocol_version) = {
+            let epoch_info = self
+                .epoch_manager
+                .get_epoch_info(epoch_id)
+                .map_err(|err| QueryError::from_epoch_error(err, block_height, *block_hash))?;
+            (epoch_info.epoch_height(), epoch_info.protocol_version())
+        };
+        let shard_layout = self
+            .epoch_manager
+            .get_shard_layout(epoch_id)
+            .map_err(|err| QueryError::from_epoch_error(err, block_height, *block_hash))?;
+        let block = SimulatedBlock {
+            height: block_height,
+            timestamp: block_timestamp,
+            prev_block_hash,
+            hash: block_hash,
+            epoch_id,
+            epoch_height,
+            protocol_version,
+            gas_price,
+        };
+        let mut shards =
+            self.override_state(&block, &shard_layout, state_roots, state_overrides)?;
+        match request {
+            SimulationRequest::CallFunction { account_id, method_name, args } => {
+                let shard_uid = account_id_to_shard_uid(account_id, &shard_layout);
+                let trie =
+                    self.simulated_shard(&block, state_roots, &mut shards, shard_uid)?.trie();
+                let view_state = ViewApplyState {
+                    block_height,
+                    prev_block_hash: *prev_block_hash,
+                    block_hash: *block_hash,
+                    epoch_id: epoch_id.clone(),
+                    epoch_height,
+                    block_timestamp,
+                    current_protocol_version: protocol_version,
+                    cache: Some(Box::new(StoreCompiledContractCache::new(&self.store))),
+                };
+                let mut logs = vec![];
+                let result = self
+                    .trie_viewer
+                    .call_function(
+                        TrieUpdate::new(trie),
+                        view_state,
+                        account_id,
+                        method_name,
+                        args.as_ref(),
+                        &mut logs,
+                        self.epoch_manager.as_ref(),
+                    )
+                    .map_err(|err| {
+                        QueryError::from_call_function_error(err, block_height, *block_hash)
+                    })?;
+                Ok(SimulationResultView::CallFunction(CallResult { result, logs }))
+            }
+            SimulationRequest::Transaction(transaction) => self
+                .simulate_transaction(&block, &shard_layout, state_roots, shards, transaction)
+                .map(SimulationResultView::Transaction),
+        }
+    }
+
+    /// Returns the shards affected by the overrides, with the overrides applied.
+    fn override_state(
+        &self,
+        block: &SimulatedBlock,
+        shard_layout: &ShardLayout,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        state_overrides: &[StateRecord],
+    ) -> Result<HashMap<ShardUId, SimulatedShard>, QueryError> {
+        let mut overrides_by_shard: HashMap<ShardUId, Vec<StateRecord>> = HashMap::new();
+        for record in state_overrides {
+            let shard_uid =
+                account_id_to_shard_uid(state_record_to_account_id(record), shard_layout);
+            overrides_by_shard.entry(shard_uid).or_default().push(record.clone());
+        }
+        let mut shards = HashMap::new();
+        for (shard_uid, overrides) in overrides_by_shard {
+            let shard = self.simulated_shard(block, state_roots, &mut shards, shard_uid)?;
+            let mut state_update = TrieUpdate::new(shard.trie());
+            apply_state_overrides(&mut state_update, &overrides).map_err(|err| {
+                QueryError::from_state_override_error(err, block.height, *block.hash)
+            })?;
+            state_update.commit(StateChangeCause::InitialState);
+            let (_, changes, _) =
+                state_update.finalize().map_err(|err| block.internal_error(err.to_string()))?;
+            shard.changes.push(changes);
+        }
+        Ok(shards)
+    }
+
+    /// Returns the shard of the simulation, starting from the state after the block.
+    fn simulated_shard<'a>(
+        &self,
+        block: &SimulatedBlock,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        shards: &'a mut HashMap<ShardUId, SimulatedShard>,
+        shard_uid: ShardUId,
+    ) -> Result<&'a mut SimulatedShard, QueryError> {
+        match shards.entry(shard_uid) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let state_root = state_roots.get(&shard_uid).ok_or_else(|| {
+                    block.internal_error(format!(
+                        "The node does not track shard {}",
+                        shard_uid.shard_id
+                    ))
+                })?;
+                let base = self.tries.get_view_trie_for_shard(shard_uid, *state_root);
+                Ok(entry.insert(SimulatedShard { base, changes: vec![] }))
+            }
+        }
+    }
+
+    /// Applies the transaction, and then its receipts over the following blocks.
+    /// The receipts are applied together with the delayed receipts of their shards, but
+    /// only the outcomes of the transaction and its receipts are returned.
+    fn simulate_transaction(
+        &self,
+        block: &SimulatedBlock,
+        shard_layout: &ShardLayout,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        mut shards: HashMap<ShardUId, SimulatedShard>,
+        transaction: &SignedTransaction,
+    ) -> Result<FinalExecutionOutcomeView, QueryError> {
+        let tx_hash = transaction.get_hash();
+        let signer_shard_uid =
+            account_id_to_shard_uid(&transaction.transaction.signer_id, shard_layout);
+        let mut tracked_ids = HashSet::from([tx_hash]);
+        let mut tracked_data_ids = HashSet::new();
+        let mut outcomes: Vec<ExecutionOutcomeWithIdView> = vec![];
+        let mut receipts: HashMap<ShardUId, Vec<Receipt>> = HashMap::new();
+        for round in 0..MAX_SIMULATED_BLOCKS {
+            let mut shards: Vec<ShardUId> = receipts.keys().copied().collect();
+            if round == 0 {
+                shards.push(signer_shard_uid);
+            }
+            if shards.is_empty() {
+                break;
+            }
+            shards.sort();
+            let mut outgoing_receipts = vec![];
+            for shard_uid in shards {
+                let incoming_receipts = receipts.remove(&shard_uid).unwrap_or_default();
+                let transactions = if round == 0 { std::slice::from_ref(transaction) } else { &[] };
+                let shard = self.simulated_shard(block, state_roots, &mut shards, shard_uid)?;
+                let apply_state = self.simulated_apply_state(block, block.height + 1 + round);
+                let apply_result = self
+                    .runtime
+                    .apply(
+                        shard.trie(),
+                        &None,
+                        &apply_state,
+                        &incoming_receipts,
+                        transactions,
+                        self.epoch_manager.as_ref(),
+                        SandboxStatePatch::default(),
+                    )
+                    .map_err(|err| match err {
+                        RuntimeError::InvalidTxError(context) => QueryError::InvalidTransaction {
+                            context,
+                            block_height: block.height,
+                            block_hash: *block.hash,
+                        },
+                        err => block.internal_error(err.to_string()),
+                    })?;
+                shard.changes.push(apply_result.trie_changes);
+                for outcome in apply_result.outcomes {
+                    if tracked_ids.contains(&outcome.id) {
+                        tracked_ids.extend(outcome.outcome.receipt_ids.iter().copied());
+                        outcomes.push(ExecutionOutcomeWithIdView {
+                            proof: vec![],
+                            block_hash: *block.hash,
+                            id: outcome.id,
+                            outcome: outcome.outcome.into(),
+                        });
+                    }
+                }
+                outgoing_receipts.extend(apply_result.outgoing_receipts);
+            }
+            for receipt in outgoing_receipts {
+                let tracked = match &receipt.receipt {
+                    ReceiptEnum::Action(action_receipt) => {
+                        let tracked = tracked_ids.contains(&receipt.receipt_id);
+                        if tracked {
+                            tracked_data_ids.extend(action_receipt.input_data_ids.iter().copied());
+                            tracked_data_ids.extend(
+                                action_receipt.output_data_receivers.iter().map(|r| r.data_id),
+                            );
+                        }
+                        tracked
+                    }
+                    ReceiptEnum::Data(data_receipt) => {
+                        tracked_data_ids.contains(&data_receipt.data_id)
+                    }
+                };
+                if tracked {
+                    let shard_uid = account_id_to_shard_uid(&receipt.receiver_id, shard_layout);
+                    receipts.entry(shard_uid).or_default().push(receipt);
+                }
+            }
+        }
+
+        if outcomes.is_empty() {
+            return Err(block.internal_error(format!("Transaction {} was not applied", tx_hash)));
+        }
+        let mut looking_for_id = tx_hash;
+        let status = outcomes
+            .iter()
+            .find_map(|outcome_with_id| {
+                if outcome_with_id.id != looking_for_id {
+                    return None;
+                }
+                match &outcome_with_id.outcome.status {
+                    ExecutionStatusView::Unknown => Some(FinalExecutionStatus::Started),
+                    ExecutionStatusView::Failure(e) => {
+                        Some(FinalExecutionStatus::Failure(e.clone()))
+                    }
+                    ExecutionStatusView::SuccessValue(v) => {
+                        Some(FinalExecutionStatus::SuccessValue(v.clone()))
+                    }
+                    ExecutionStatusView::SuccessReceiptId(id) => {
+                        looking_for_id = *id;
+                        None
+                    }
+                }
+            })
+            // The receipts didn't finish within the simulated blocks.
+            .unwrap_or(FinalExecutionStatus::Started);
+        let receipts_outcome = outcomes.split_off(1);
+        let transaction_outcome = outcomes.pop().unwrap();
+        Ok(FinalExecutionOutcomeView {
+            status,
+            transaction: transaction.clone().into(),
+            transaction_outcome,
+            receipts_outcome,
+        })
+    }
+
+    fn simulated_apply_state(&self, block: &SimulatedBlock, height: BlockHeight) -> ApplyState {
+        ApplyState {
+            block_height: height,
+            prev_block_hash: *block.prev_block_hash,
+            block_hash: *block.hash,
+            epoch_id: block.epoch_id.clone(),
+            epoch_height: block.epoch_height,
+            gas_price: block.gas_price,
+            block_timestamp: block.timestamp,
+            gas_limit: None,
+            random_seed: *block.hash,
+            current_protocol_version: block.protocol_version,
+            config: self.runtime_config_store.get_config(block.protocol_version).clone(),
+            cache: Some(Box::new(StoreCompiledContractCache::new(&self.store))),
+            is_new_chunk: true,
+            migration_data: Arc::clone(&self.migration_data),
+            migration_flags: MigrationFlags::default(),
+        }
+    }
+}
@@ -2,7 +2,7 @@
 use anyhow::Context;
 use near_amend_genesis::AmendGenesisCommand;
 use near_chain_configs::GenesisValidationMode;
-use near_client::ConfigUpdater;
+use near_client::{ConfigUpdater, DrainSyncJobs};
 use near_cold_store_tool::ColdStoreCommand;
 use near_database_tool::commands::DatabaseCommand;
 use near_dyn_configs::{UpdateableConfigLoader, UpdateableConfigLoaderError, UpdateableConfigs};
@@ -14,7 +14,7 @@ use near_network::tcp;
 use near_o11y::tracing_subscriber::EnvFilter;
 use near_o11y::{
     default_subscriber, default_subscriber_with_opentelemetry, BuildEnvFilterError,
-    EnvFilterBuilder,
+    EnvFilterBuilder, WithSpanContextExt,
 };
 use near_ping::PingCommand;
 use near_primitives::hash::CryptoHash;
@@ -527,12 +527,17 @@ impl RunCmd {
             let mut updateable_config_loader =
                 UpdateableConfigLoader::new(updateable_configs.clone(), tx_config_update);
             let config_updater = ConfigUpdater::new(rx_config_update);
+            let sync_jobs_drain_timeout = near_config.client_config.sync_jobs.drain_timeout;
 
             let nearcore::NearNode {
+                client,
                 rpc_servers,
                 cold_store_loop_handle,
                 state_sync_dump_handle,
+                reexecution_check_handle,
+                refcount_audit_handle,
                 flat_state_migration_handle,
+                mem_tries_handle,
                 ..
             } = nearcore::start_with_config_and_synchronization(
                 home_dir,
@@ -559,12 +564,31 @@ impl RunCmd {
             if let Some(handle) = state_sync_dump_handle {
                 handle.stop()
             }
+            if let Some(handle) = reexecution_check_handle {
+                handle.stop()
+            }
+            if let Some(handle) = refcount_audit_handle {
+                handle.stop()
+            }
             flat_state_migration_handle.stop();
+            // Lets state sync and resharding jobs save their progress instead
+            // of being killed in the middle of a batch.
+            match client
+                .send(DrainSyncJobs { timeout: sync_jobs_drain_timeout }.with_span_context())
+                .await
+            {
+                Ok(true) => debug!(target: "neard", "Sync jobs drained"),
+                Ok(false) => warn!(target: "neard", "Sync jobs didn't finish in time"),
+                Err(err) => warn!(target: "neard", ?err, "Failed to drain sync jobs"),
+            }
             futures::future::join_all(rpc_servers.iter().map(|(name, server)| async move {
                 server.stop(true).await;
                 debug!(target: "neard", "{} server stopped", name);
             }))
             .await;
+            if let Some(handle) = mem_tries_handle {
+                handle.save()
+            }
             actix::System::current().stop();
             // Disable the subscriber to properly shutdown the tracer.
             near_o11y::reload(Some("error"), None, Some(near_o11y::OpenTelemetryLevel::OFF))
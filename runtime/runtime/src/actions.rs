@@ -108,6 +108,7 @@ pub(crate) fn execute_function_call(
     if checked_feature!("stable", ChunkNodesCache, protocol_version) {
         runtime_ext.set_trie_cache_mode(TrieCacheMode::CachingChunk);
     }
+    let start = std::time::Instant::now();
     let result = near_vm_runner::run(
         &code,
         &function_call.method_name,
@@ -118,6 +119,7 @@ pub(crate) fn execute_function_call(
         promise_results,
         apply_state.cache.as_deref(),
     );
+    metrics::record_wasm_time(start.elapsed());
 
     if checked_feature!("stable", ChunkNodesCache, protocol_version) {
         runtime_ext.set_trie_cache_mode(TrieCacheMode::CachingShard);
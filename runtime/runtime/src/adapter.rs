@@ -66,4 +66,15 @@ pub trait ViewRuntimeAdapter {
         prefix: &[u8],
         include_proof: bool,
     ) -> Result<ViewStateResult, crate::state_viewer::errors::ViewStateError>;
+
+    /// Returns a page of the contract state, see `TrieViewer::view_state_page`.
+    fn view_state_page(
+        &self,
+        shard_uid: &ShardUId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+        prefix: &[u8],
+        include_proof: bool,
+        start_after: Option<&[u8]>,
+    ) -> Result<(ViewStateResult, Option<Vec<u8>>), crate::state_viewer::errors::ViewStateError>;
 }
@@ -1251,7 +1251,7 @@ impl Runtime {
         // limit
         let mut total_gas_burnt = gas_used_for_migrations;
         let mut total_compute_usage = total_gas_burnt;
-        let mut metrics = metrics::ApplyMetrics::default();
+        let mut metrics = metrics::ApplyMetrics::new();
 
         for signed_transaction in transactions {
             let (receipt, outcome_with_id) = self.process_transaction(
@@ -1286,6 +1286,7 @@ impl Runtime {
         }
         metrics.tx_processing_done(total_gas_burnt, total_compute_usage);
 
+        let mut receipts_processed = 0;
         let mut process_receipt = |receipt: &Receipt,
                                    state_update: &mut TrieUpdate,
                                    total_gas_burnt: &mut Gas,
@@ -1300,6 +1301,7 @@ impl Runtime {
                 id = %receipt.receipt_id,
             )
             .entered();
+            receipts_processed += 1;
             let node_counter_before = state_update.trie().get_trie_nodes_count();
             let result = self.process_receipt(
                 state_update,
@@ -1433,6 +1435,7 @@ impl Runtime {
             }
         }
         metrics.incoming_receipts_done(total_gas_burnt, total_compute_usage);
+        metrics.execution_done(receipts_processed);
 
         // No more receipts are executed on this trie, stop any pending prefetches on it.
         if let Some(prefetcher) = &prefetcher {
@@ -1456,6 +1459,7 @@ impl Runtime {
         state_update.commit(StateChangeCause::UpdatedDelayedReceipts);
         self.apply_state_patch(&mut state_update, state_patch);
         let (trie, trie_changes, state_changes) = state_update.finalize()?;
+        metrics.state_reads_done(trie.get_read_stats());
 
         // Dedup proposals from the same account.
         // The order is deterministically changed.
@@ -1,8 +1,11 @@
 use near_o11y::metrics::{
-    try_create_histogram_vec, try_create_int_counter, try_create_int_counter_vec, HistogramVec,
-    IntCounter, IntCounterVec,
+    exponential_buckets, try_create_histogram_vec, try_create_int_counter,
+    try_create_int_counter_vec, HistogramVec, IntCounter, IntCounterVec,
 };
+use near_store::TrieReadStats;
 use once_cell::sync::Lazy;
+use std::cell::Cell;
+use std::time::Duration;
 
 pub static ACTION_CALLED_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
@@ -115,6 +118,24 @@ static CHUNK_COMPUTE: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+static CHUNK_STATE_READS: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_state_reads",
+        "Number of state reads by chunk, by whether they were served from flat storage or by traversing trie nodes. Reported for all applied chunks, even when not included in a block.",
+        &["shard_id", "source"],
+        Some(exponential_buckets(1., 2., 20).unwrap()),
+    )
+    .unwrap()
+});
+static CHUNK_STATE_READ_BYTES: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_state_read_bytes",
+        "Total size of values read by chunk, by whether they were served from flat storage or by traversing trie nodes. Reported for all applied chunks, even when not included in a block.",
+        &["shard_id", "source"],
+        Some(exponential_buckets(64., 2., 20).unwrap()),
+    )
+    .unwrap()
+});
 static CHUNK_TGAS: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_chunk_tgas",
@@ -220,6 +241,16 @@ fn buckets_for_compute() -> Option<Vec<f64>> {
     ])
 }
 
+thread_local! {
+    /// Total time spent executing contracts on the current thread.
+    static WASM_TIME: Cell<Duration> = Cell::new(Duration::ZERO);
+}
+
+/// Adds time spent executing a contract on the current thread.
+pub(crate) fn record_wasm_time(elapsed: Duration) {
+    WASM_TIME.with(|wasm_time| wasm_time.set(wasm_time.get() + elapsed));
+}
+
 /// Helper struct to collect partial costs of `Runtime::apply` and reporting it
 /// atomically.
 #[derive(Debug, Default)]
@@ -234,9 +265,19 @@ pub struct ApplyMetrics {
     delayed_receipts_gas: u64,
     incoming_receipts_compute_usage: u64,
     incoming_receipts_gas: u64,
+    state_reads: TrieReadStats,
+    /// Value of `WASM_TIME` when the apply started.
+    wasm_time_at_start: Duration,
+    wasm_time: Duration,
+    receipts_processed: u64,
 }
 
 impl ApplyMetrics {
+    /// Creates metrics of an apply which starts now on the current thread.
+    pub fn new() -> Self {
+        Self { wasm_time_at_start: WASM_TIME.with(Cell::get), ..Self::default() }
+    }
+
     /// Updates the internal accumulated counters and returns the difference to
     /// the old counters.
     fn update_accumulated(&mut self, gas: u64, compute: u64) -> (u64, u64) {
@@ -270,6 +311,33 @@ impl ApplyMetrics {
             self.update_accumulated(accumulated_gas, accumulated_compute);
     }
 
+    pub fn state_reads_done(&mut self, state_reads: TrieReadStats) {
+        self.state_reads = state_reads;
+    }
+
+    /// Records the number of receipts executed and the time spent executing
+    /// contracts since the apply started.
+    pub fn execution_done(&mut self, receipts_processed: u64) {
+        self.receipts_processed = receipts_processed;
+        self.wasm_time = WASM_TIME.with(Cell::get).saturating_sub(self.wasm_time_at_start);
+    }
+
+    /// Time spent reading state, from flat storage or the trie.
+    pub fn storage_read_time(&self) -> Duration {
+        self.state_reads.read_time
+    }
+
+    /// Time spent executing contracts.  It includes the host functions called
+    /// by the contracts, so it overlaps with [`Self::storage_read_time`].
+    pub fn wasm_time(&self) -> Duration {
+        self.wasm_time
+    }
+
+    /// Number of receipts executed, including local and delayed ones.
+    pub fn receipts_processed(&self) -> u64 {
+        self.receipts_processed
+    }
+
     /// Report statistics
     pub fn report(&self, shard_id: &str) {
         const TERA: f64 = 1_000_000_000_000_f64;
@@ -304,5 +372,14 @@ impl ApplyMetrics {
         CHUNK_COMPUTE
             .with_label_values(&[shard_id])
             .observe(self.accumulated_compute as f64 / TERA);
+
+        let reads = &self.state_reads;
+        for (source, count, bytes) in [
+            ("flat_storage", reads.flat_storage_reads, reads.flat_storage_bytes),
+            ("trie", reads.trie_reads, reads.trie_bytes),
+        ] {
+            CHUNK_STATE_READS.with_label_values(&[shard_id, source]).observe(count as f64);
+            CHUNK_STATE_READ_BYTES.with_label_values(&[shard_id, source]).observe(bytes as f64);
+        }
     }
 }
@@ -54,6 +54,16 @@ pub enum CallFunctionError {
     VMError { error_message: String },
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum StateOverrideError {
+    #[error("Account ID #{requested_account_id} does not exist")]
+    AccountDoesNotExist { requested_account_id: near_primitives::types::AccountId },
+    #[error("Only Account, AccessKey, Contract and Data state records can be overridden")]
+    UnsupportedRecord,
+    #[error("Internal error: #{error_message}")]
+    InternalError { error_message: String },
+}
+
 impl From<ViewAccountError> for ViewContractCodeError {
     fn from(view_account_error: ViewAccountError) -> Self {
         match view_account_error {
@@ -94,6 +104,12 @@ impl From<near_primitives::errors::StorageError> for ViewStateError {
     }
 }
 
+impl From<near_primitives::errors::StorageError> for StateOverrideError {
+    fn from(storage_error: near_primitives::errors::StorageError) -> Self {
+        Self::InternalError { error_message: storage_error.to_string() }
+    }
+}
+
 impl From<near_primitives::errors::StorageError> for CallFunctionError {
     fn from(storage_error: near_primitives::errors::StorageError) -> Self {
         Self::InternalError { error_message: storage_error.to_string() }
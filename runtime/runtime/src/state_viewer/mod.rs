@@ -9,12 +9,15 @@ use near_primitives::receipt::ActionReceipt;
 use near_primitives::runtime::apply_state::ApplyState;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::FunctionCallAction;
-use near_primitives::trie_key::trie_key_parsers;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{AccountId, EpochInfoProvider, Gas};
 use near_primitives::views::{StateItem, ViewApplyState, ViewStateResult};
 use near_primitives_core::config::ViewConfig;
-use near_store::{get_access_key, get_account, get_code, TrieUpdate};
+use near_store::{
+    get_access_key, get_account, get_code, set_access_key, set_account, set_code, TrieUpdate,
+};
 use near_vm_runner::logic::ReturnData;
 use near_vm_runner::ContractCode;
 use std::{str, sync::Arc, time::Instant};
@@ -22,9 +25,14 @@ use tracing::debug;
 
 pub mod errors;
 
+/// Default upper bound of the byte size of a page of contract state.
+pub const DEFAULT_STATE_PAGE_SIZE_LIMIT: u64 = 1024 * 1024;
+
 pub struct TrieViewer {
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     state_size_limit: Option<u64>,
+    /// Upper bound of the byte size of keys and values in a page of contract state.
+    page_size_limit: u64,
     /// Gas limit used when when handling call_function queries.
     max_gas_burnt_view: Gas,
 }
@@ -34,7 +42,11 @@ impl Default for TrieViewer {
         let config_store = RuntimeConfigStore::new(None);
         let latest_runtime_config = config_store.get_config(PROTOCOL_VERSION);
         let max_gas_burnt = latest_runtime_config.wasm_config.limit_config.max_gas_burnt;
-        Self { state_size_limit: None, max_gas_burnt_view: max_gas_burnt }
+        Self {
+            state_size_limit: None,
+            page_size_limit: DEFAULT_STATE_PAGE_SIZE_LIMIT,
+            max_gas_burnt_view: max_gas_burnt,
+        }
     }
 }
 
@@ -42,7 +54,16 @@ impl TrieViewer {
     pub fn new(state_size_limit: Option<u64>, max_gas_burnt_view: Option<Gas>) -> Self {
         let max_gas_burnt_view =
             max_gas_burnt_view.unwrap_or_else(|| TrieViewer::default().max_gas_burnt_view);
-        Self { state_size_limit, max_gas_burnt_view }
+        Self {
+            state_size_limit,
+            page_size_limit: DEFAULT_STATE_PAGE_SIZE_LIMIT,
+            max_gas_burnt_view,
+        }
+    }
+
+    pub fn with_page_size_limit(mut self, page_size_limit: u64) -> Self {
+        self.page_size_limit = page_size_limit;
+        self
     }
 
     pub fn view_account(
@@ -149,7 +170,57 @@ impl TrieViewer {
             values.push(StateItem { key: key[acc_sep_len..].to_vec().into(), value: value.into() });
         }
         let proof = iter.into_visited_nodes();
-        Ok(ViewStateResult { values, proof })
+        Ok(ViewStateResult { values, proof, continuation_token: None })
+    }
+
+    /// Returns a page of the contract state: the values with keys greater than `start_after`,
+    /// up to `page_size_limit` bytes of keys and values. Unlike `view_state`, works for contracts
+    /// of any size. If there are more values, returns also the last key of the page.
+    pub fn view_state_page(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        prefix: &[u8],
+        include_proof: bool,
+        start_after: Option<&[u8]>,
+    ) -> Result<(ViewStateResult, Option<Vec<u8>>), errors::ViewStateError> {
+        if get_account(state_update, account_id)?.is_none() {
+            return Err(errors::ViewStateError::AccountDoesNotExist {
+                requested_account_id: account_id.clone(),
+            });
+        }
+
+        let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, prefix);
+        let acc_sep_len = query.len() - prefix.len();
+        let mut iter = state_update.trie().iter()?;
+        iter.remember_visited_nodes(include_proof);
+        match start_after {
+            Some(start_after) => {
+                // The smallest key greater than `start_after`.
+                let mut start = query[..acc_sep_len].to_vec();
+                start.extend_from_slice(start_after);
+                start.push(0);
+                iter.seek(std::cmp::max(start, query.clone()))?;
+            }
+            None => iter.seek_prefix(&query)?,
+        }
+        let mut values = vec![];
+        let mut size = 0;
+        let mut last_key = None;
+        for item in &mut iter {
+            let (key, value) = item?;
+            if !key.starts_with(&query) {
+                break;
+            }
+            if size >= self.page_size_limit {
+                last_key = values.last().map(|item: &StateItem| item.key.to_vec());
+                break;
+            }
+            size += (key.len() - acc_sep_len + value.len()) as u64;
+            values.push(StateItem { key: key[acc_sep_len..].to_vec().into(), value: value.into() });
+        }
+        let proof = iter.into_visited_nodes();
+        Ok((ViewStateResult { values, proof, continuation_token: None }, last_key))
     }
 
     pub fn call_function(
@@ -254,3 +325,46 @@ impl TrieViewer {
         }
     }
 }
+
+/// Applies the overrides of the state of a simulation, in order. A contract override also
+/// updates the code hash of the account, which has to exist by then. The storage usage of
+/// the accounts is not updated.
+pub fn apply_state_overrides(
+    state_update: &mut TrieUpdate,
+    overrides: &[StateRecord],
+) -> Result<(), errors::StateOverrideError> {
+    for record in overrides {
+        match record {
+            StateRecord::Account { account_id, account } => {
+                set_account(state_update, account_id.clone(), account);
+            }
+            StateRecord::Data { account_id, data_key, value } => {
+                let key = TrieKey::ContractData {
+                    account_id: account_id.clone(),
+                    key: data_key.clone().into(),
+                };
+                state_update.set(key, value.clone().into());
+            }
+            StateRecord::Contract { account_id, code } => {
+                let mut account = get_account(state_update, account_id)?.ok_or_else(|| {
+                    errors::StateOverrideError::AccountDoesNotExist {
+                        requested_account_id: account_id.clone(),
+                    }
+                })?;
+                let code = ContractCode::new(code.clone(), None);
+                account.set_code_hash(*code.hash());
+                set_account(state_update, account_id.clone(), &account);
+                set_code(state_update, account_id.clone(), &code);
+            }
+            StateRecord::AccessKey { account_id, public_key, access_key } => {
+                set_access_key(state_update, account_id.clone(), public_key.clone(), access_key);
+            }
+            StateRecord::PostponedReceipt(_)
+            | StateRecord::ReceivedData { .. }
+            | StateRecord::DelayedReceipt(_) => {
+                return Err(errors::StateOverrideError::UnsupportedRecord)
+            }
+        }
+    }
+    Ok(())
+}
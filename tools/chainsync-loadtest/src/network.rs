@@ -13,6 +13,7 @@ use near_network::types::{
 };
 use near_primitives::block::{Approval, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+use near_primitives::epoch_manager::epoch_sync::EpochSyncProof;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::ChunkHash;
@@ -161,6 +162,7 @@ impl Network {
             s.spawn_bg(async {
                 self.keep_sending(|peer| NetworkRequests::BlockHeadersRequest {
                     hashes: vec![hash],
+                    max_headers: None,
                     peer_id: peer.peer_info.id,
                 })
                 .await
@@ -262,7 +264,11 @@ impl near_network::client::Client for Network {
         None
     }
 
-    async fn block_headers_request(&self, _hashes: Vec<CryptoHash>) -> Option<Vec<BlockHeader>> {
+    async fn block_headers_request(
+        &self,
+        _hashes: Vec<CryptoHash>,
+        _max_headers: Option<u64>,
+    ) -> Option<Vec<BlockHeader>> {
         None
     }
 
@@ -284,6 +290,18 @@ impl near_network::client::Client for Network {
 
     async fn challenge(&self, _challenge: Challenge) {}
 
+    async fn epoch_sync_request(&self, _prev_epoch_id: EpochId) -> Option<Box<EpochSyncProof>> {
+        None
+    }
+
+    async fn epoch_sync_response(
+        &self,
+        _proof: EpochSyncProof,
+        _peer_id: PeerId,
+    ) -> Result<(), ReasonForBan> {
+        Ok(())
+    }
+
     async fn network_info(&self, info: NetworkInfo) {
         let mut n = self.data.lock().unwrap();
         n.info_ = Arc::new(info);
@@ -1,9 +1,11 @@
 use crate::adjust_database::ChangeDbKindCommand;
 use crate::analyse_data_size_distribution::AnalyseDataSizeDistributionCommand;
 use crate::compact::RunCompactionCommand;
+use crate::estimate_gc::EstimateGcCommand;
 use crate::make_snapshot::MakeSnapshotCommand;
 use crate::run_migrations::RunMigrationsCommand;
 use crate::state_perf::StatePerfCommand;
+use crate::stats::StatsCommand;
 use clap::Parser;
 use std::path::PathBuf;
 
@@ -25,6 +27,10 @@ enum SubCommand {
     /// Run SST file compaction on database
     CompactDatabase(RunCompactionCommand),
 
+    /// Estimate how many keys and bytes garbage collection would delete per
+    /// column, without deleting anything
+    EstimateGc(EstimateGcCommand),
+
     /// Make snapshot of the database
     MakeSnapshot(MakeSnapshotCommand),
 
@@ -34,6 +40,10 @@ enum SubCommand {
     /// Run performance test for State column reads.
     /// Uses RocksDB data specified via --home argument.
     StatePerf(StatePerfCommand),
+
+    /// Print approximate number of entries and size of every column and
+    /// optionally their checksums
+    Stats(StatsCommand),
 }
 
 impl DatabaseCommand {
@@ -42,6 +52,7 @@ impl DatabaseCommand {
             SubCommand::AnalyseDataSizeDistribution(cmd) => cmd.run(home),
             SubCommand::ChangeDbKind(cmd) => cmd.run(home),
             SubCommand::CompactDatabase(cmd) => cmd.run(home),
+            SubCommand::EstimateGc(cmd) => cmd.run(home),
             SubCommand::MakeSnapshot(cmd) => {
                 let near_config = nearcore::config::load_config(
                     &home,
@@ -52,6 +63,7 @@ impl DatabaseCommand {
             }
             SubCommand::RunMigrations(cmd) => cmd.run(home),
             SubCommand::StatePerf(cmd) => cmd.run(home),
+            SubCommand::Stats(cmd) => cmd.run(home),
         }
     }
 }
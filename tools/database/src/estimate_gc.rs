@@ -0,0 +1,66 @@
+use near_chain::types::RuntimeAdapter;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::types::BlockHeightDelta;
+use nearcore::NightshadeRuntime;
+use std::path::Path;
+
+#[derive(clap::Args)]
+pub(crate) struct EstimateGcCommand {
+    /// Maximum number of heights above the tail to include in the estimate.
+    #[arg(long, default_value_t = 1000)]
+    height_limit: BlockHeightDelta,
+}
+
+impl EstimateGcCommand {
+    pub(crate) fn run(&self, home_dir: &Path) -> anyhow::Result<()> {
+        let mut near_config = nearcore::config::load_config(
+            &home_dir,
+            near_chain_configs::GenesisValidationMode::UnsafeFast,
+        )
+        .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+        let store = nearcore::open_storage(home_dir, &mut near_config)?.get_hot_store();
+        let epoch_manager =
+            EpochManager::new_arc_handle(store.clone(), &near_config.genesis.config);
+        let runtime = NightshadeRuntime::from_config(
+            home_dir,
+            store.clone(),
+            &near_config,
+            epoch_manager.clone(),
+        );
+        let mut chain_store = ChainStore::new(
+            store,
+            near_config.genesis.config.genesis_height,
+            near_config.client_config.save_trie_changes,
+        );
+        let head = chain_store.head()?;
+        let gc_stop_height = runtime.get_gc_stop_height(&head.last_block_hash);
+        let estimate = chain_store.estimate_gc(
+            epoch_manager.as_ref(),
+            runtime.get_tries(),
+            gc_stop_height,
+            self.height_limit,
+        )?;
+
+        println!(
+            "Tail: {}, GC stop height: {}, estimated until height: {}",
+            estimate.tail, estimate.gc_stop_height, estimate.estimated_until_height
+        );
+        println!(
+            "Blocks: {} canonical, {} on forks",
+            estimate.canonical_blocks, estimate.fork_blocks
+        );
+        println!("{:<40} {:>15} {:>15}", "Column", "Keys", "Size");
+        for (col, stats) in &estimate.columns {
+            println!(
+                "{:<40} {:>15} {:>15}",
+                col.to_string(),
+                stats.keys,
+                bytesize::ByteSize(stats.bytes).to_string(),
+            );
+        }
+        let total = estimate.total();
+        println!("Total: {} keys, {}", total.keys, bytesize::ByteSize(total.bytes));
+        Ok(())
+    }
+}
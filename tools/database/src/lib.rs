@@ -2,7 +2,9 @@ mod adjust_database;
 mod analyse_data_size_distribution;
 pub mod commands;
 mod compact;
+mod estimate_gc;
 mod make_snapshot;
 mod run_migrations;
 mod state_perf;
+mod stats;
 mod utils;
@@ -21,6 +21,7 @@ impl MakeSnapshotCommand {
             &node_storage.get_hot_store(),
             &self.destination,
             None,
+            store_config.encryption.as_ref(),
         )?;
         Ok(())
     }
@@ -0,0 +1,86 @@
+use crate::utils::{open_rocksdb, resolve_column};
+use clap::Parser;
+use near_primitives::hash::CryptoHash;
+use near_store::db::Database;
+use near_store::DBCol;
+use sha2::Digest;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+pub(crate) struct StatsCommand {
+    /// If specified only stats of this column will be printed
+    #[arg(short, long)]
+    column: Option<String>,
+
+    /// Read every entry of the column(s) to count exact number of entries and
+    /// their size and to compute a checksum of the contents.  This may take
+    /// a long time on big databases.
+    #[arg(long)]
+    checksum: bool,
+}
+
+impl StatsCommand {
+    pub(crate) fn run(&self, home: &PathBuf) -> anyhow::Result<()> {
+        let db = open_rocksdb(home, near_store::Mode::ReadOnly)?;
+        let column = self.column.as_deref().map(resolve_column).transpose()?;
+        let mut stats = db.get_column_stats();
+        stats.retain(|(col, _)| column.map_or(true, |column| *col == column));
+        stats.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total_sst_files_size));
+
+        println!(
+            "{:<40} {:>15} {:>15} {:>15} {:>10}",
+            "Column", "Est. keys", "Est. live size", "SST files size", "SST files"
+        );
+        for (col, stats) in &stats {
+            println!(
+                "{:<40} {:>15} {:>15} {:>15} {:>10}",
+                col.to_string(),
+                stats.estimated_num_keys,
+                bytesize::ByteSize(stats.estimated_live_data_size).to_string(),
+                bytesize::ByteSize(stats.total_sst_files_size).to_string(),
+                stats.num_sst_files,
+            );
+        }
+        let total: u64 = stats.iter().map(|(_, stats)| stats.total_sst_files_size).sum();
+        println!("Total size of SST files: {}", bytesize::ByteSize(total));
+
+        if self.checksum {
+            println!();
+            println!("{:<40} {:>15} {:>15} {:<44}", "Column", "Keys", "Size", "Checksum");
+            for (col, _) in &stats {
+                let (num_keys, size, checksum) = checksum_column(&db, *col)?;
+                println!(
+                    "{:<40} {:>15} {:>15} {:<44}",
+                    col.to_string(),
+                    num_keys,
+                    bytesize::ByteSize(size).to_string(),
+                    checksum,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads all entries of the column and returns their number, their total size
+/// and SHA-256 checksum of the column contents.
+///
+/// Raw values are read so for reference counted columns the checksum covers
+/// the reference counts as well.
+fn checksum_column(db: &dyn Database, col: DBCol) -> anyhow::Result<(u64, u64, CryptoHash)> {
+    let mut hasher = sha2::Sha256::new();
+    let mut num_keys = 0;
+    let mut size = 0;
+    for item in db.iter_raw_bytes(col) {
+        let (key, value) = item?;
+        // Length prefixes make the checksum unambiguous with respect to where
+        // keys end and values begin.
+        hasher.update((key.len() as u64).to_le_bytes());
+        hasher.update(&key);
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(&value);
+        num_keys += 1;
+        size += (key.len() + value.len()) as u64;
+    }
+    Ok((num_keys, size, CryptoHash(hasher.finalize().into())))
+}
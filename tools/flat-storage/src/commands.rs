@@ -2,15 +2,17 @@
 use borsh::BorshDeserialize;
 use clap::Parser;
 use near_chain::flat_storage_creator::FlatStorageShardCreator;
+use near_chain::resharding::{estimate_split_shards, get_split_shard_layout, ShardSizeEstimate};
 use near_chain::types::RuntimeAdapter;
 use near_chain::{ChainStore, ChainStoreAccess};
 use near_chain_configs::GenesisValidationMode;
 use near_epoch_manager::{EpochManager, EpochManagerAdapter, EpochManagerHandle};
 use near_primitives::shard_layout::ShardVersion;
-use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+use near_store::config::FlatStateValuesInliningConfig;
 use near_store::flat::{
     inline_flat_state_values, store_helper, FlatStateDelta, FlatStateDeltaMetadata,
-    FlatStorageManager, FlatStorageStatus,
+    FlatStorageCreationStatus, FlatStorageManager, FlatStorageStatus,
 };
 use near_store::{DBCol, Mode, NodeStorage, ShardUId, Store, StoreOpener};
 use nearcore::{load_config, NearConfig, NightshadeRuntime};
@@ -36,6 +38,10 @@ enum SubCommand {
     /// Init the flat storage state, by copying from trie
     Init(InitCmd),
 
+    /// Rebuild flat storage state from scratch, e.g. if it is corrupted. The rebuild is resumable:
+    /// if it is interrupted, running the command again or restarting the node continues it.
+    Rebuild(RebuildCmd),
+
     /// Verify flat storage state (it can take up to couple hours if flat storage is very large)
     Verify(VerifyCmd),
 
@@ -52,6 +58,10 @@ enum SubCommand {
 
     /// Move flat head forward.
     MoveFlatHead(MoveFlatHeadCmd),
+
+    /// Estimate the number of keys and size of the child shards of a shard if it was split at
+    /// the given boundary accounts, without writing anything.
+    EstimateSplit(EstimateSplitCmd),
 }
 
 #[derive(Parser)]
@@ -88,6 +98,14 @@ pub struct InitCmd {
     num_threads: usize,
 }
 
+#[derive(Parser)]
+pub struct RebuildCmd {
+    shard_id: ShardId,
+
+    #[clap(default_value = "3")]
+    num_threads: usize,
+}
+
 #[derive(Parser)]
 pub struct VerifyCmd {
     shard_id: ShardId,
@@ -112,6 +130,15 @@ pub struct MoveFlatHeadCmd {
     new_flat_head_height: BlockHeight,
 }
 
+#[derive(Parser)]
+pub struct EstimateSplitCmd {
+    #[clap(long)]
+    shard_id: ShardId,
+    /// Boundary accounts added to the current shard layout, separated by commas.
+    #[clap(long, value_delimiter = ',', required = true)]
+    new_boundary_accounts: Vec<AccountId>,
+}
+
 fn print_delta(store: &Store, shard_uid: ShardUId, metadata: FlatStateDeltaMetadata) {
     let changes =
         store_helper::get_delta_changes(store, shard_uid, metadata.block.hash).unwrap().unwrap();
@@ -260,6 +287,52 @@ impl FlatStorageCommand {
         Ok(())
     }
 
+    fn rebuild(
+        &self,
+        cmd: &RebuildCmd,
+        home_dir: &PathBuf,
+        near_config: &NearConfig,
+        opener: StoreOpener,
+    ) -> anyhow::Result<()> {
+        let (_, epoch_manager, rw_hot_runtime, rw_chain_store, rw_hot_store) =
+            Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadWriteExisting);
+
+        let tip = rw_chain_store.final_head()?;
+        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
+        let mut creator =
+            FlatStorageShardCreator::new(shard_uid, tip.height - 1, epoch_manager, rw_hot_runtime);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(cmd.num_threads).build()?;
+
+        // Status which can't be read is considered corrupted, so rebuild is started over.
+        match store_helper::get_flat_storage_status(&rw_hot_store, shard_uid) {
+            Ok(FlatStorageStatus::Creation(FlatStorageCreationStatus::FetchingState(status))) => {
+                println!("Resuming flat storage rebuild: {:.1}%", status.progress_percent());
+            }
+            status => {
+                println!("Starting flat storage rebuild, previous status: {status:?}");
+                creator.start_rebuild(&rw_chain_store)?;
+            }
+        }
+
+        loop {
+            let status = creator.update_status(&rw_chain_store, &pool)?;
+            if status {
+                break;
+            }
+            match store_helper::get_flat_storage_status(&rw_hot_store, shard_uid)? {
+                FlatStorageStatus::Creation(FlatStorageCreationStatus::FetchingState(status)) => {
+                    println!("Fetching state: {:.1}%", status.progress_percent());
+                }
+                status => println!("Status: {:?}", status),
+            }
+
+            std::thread::sleep(Duration::from_secs(1));
+        }
+
+        println!("Flat storage rebuild finished.");
+        Ok(())
+    }
+
     fn verify(
         &self,
         cmd: &VerifyCmd,
@@ -365,12 +438,18 @@ impl FlatStorageCommand {
         let store =
             Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadWriteExisting).4;
         let flat_storage_manager = FlatStorageManager::new(store.clone());
+        // Run at full speed, there is no block processing to protect.
+        let config = FlatStateValuesInliningConfig {
+            batch_size: cmd.batch_size,
+            batch_delay: Duration::ZERO,
+            ..near_config.config.store.flat_state_values_inlining.clone()
+        };
         inline_flat_state_values(
             store,
             &flat_storage_manager,
             &AtomicBool::new(true),
             cmd.num_threads,
-            cmd.batch_size,
+            &config,
         );
         Ok(())
     }
@@ -417,6 +496,50 @@ impl FlatStorageCommand {
         Ok(())
     }
 
+    fn estimate_split(
+        &self,
+        cmd: &EstimateSplitCmd,
+        home_dir: &PathBuf,
+        near_config: &NearConfig,
+        opener: StoreOpener,
+    ) -> anyhow::Result<()> {
+        let (_, epoch_manager, _, chain_store, hot_store) =
+            Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadOnly);
+        let tip = chain_store.final_head()?;
+        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
+
+        let head_hash = match store_helper::get_flat_storage_status(&hot_store, shard_uid)? {
+            FlatStorageStatus::Ready(ready_status) => ready_status.flat_head.hash,
+            status => {
+                anyhow::bail!("Flat storage is not ready for shard {:?}: {status:?}", cmd.shard_id);
+            }
+        };
+        let block_header = chain_store.get_block_header(&head_hash)?;
+        let shard_layout = epoch_manager.get_shard_layout(block_header.epoch_id())?;
+        let split_layout = get_split_shard_layout(&shard_layout, &cmd.new_boundary_accounts)?;
+
+        println!(
+            "Estimating split of shard {:?} - flat head @{:?} ({:?}), boundary accounts {:?}",
+            cmd.shard_id,
+            block_header.height(),
+            block_header.hash(),
+            split_layout.boundary_accounts().unwrap_or_default(),
+        );
+        let estimate = estimate_split_shards(&hot_store, shard_uid, &split_layout)?;
+        let print_row = |name: &str, shard: &ShardSizeEstimate| {
+            println!(
+                "{:<20} {:>15} {:>15} {:>15}",
+                name, shard.num_keys, shard.key_bytes, shard.value_bytes
+            );
+        };
+        println!("{:<20} {:>15} {:>15} {:>15}", "Shard", "Keys", "Key bytes", "Value bytes");
+        for (child_shard_uid, shard) in &estimate.child_shards {
+            print_row(&child_shard_uid.to_string(), shard);
+        }
+        print_row("delayed receipts", &estimate.delayed_receipts);
+        Ok(())
+    }
+
     pub fn run(
         &self,
         home_dir: &PathBuf,
@@ -435,6 +558,7 @@ impl FlatStorageCommand {
             SubCommand::SetStoreVersion(cmd) => self.set_store_version(cmd, opener),
             SubCommand::Reset(cmd) => self.reset(cmd, home_dir, &near_config, opener),
             SubCommand::Init(cmd) => self.init(cmd, home_dir, &near_config, opener),
+            SubCommand::Rebuild(cmd) => self.rebuild(cmd, home_dir, &near_config, opener),
             SubCommand::Verify(cmd) => self.verify(cmd, home_dir, &near_config, opener),
             SubCommand::MigrateValueInlining(cmd) => {
                 self.migrate_value_inlining(cmd, home_dir, &near_config, opener)
@@ -445,6 +569,9 @@ impl FlatStorageCommand {
             SubCommand::MoveFlatHead(cmd) => {
                 self.move_flat_head(cmd, home_dir, &near_config, opener)
             }
+            SubCommand::EstimateSplit(cmd) => {
+                self.estimate_split(cmd, home_dir, &near_config, opener)
+            }
         }
     }
 }
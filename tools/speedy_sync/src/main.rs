@@ -1,7 +1,7 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_chain::types::{ChainConfig, Tip};
 use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
-use near_chain_configs::GenesisValidationMode;
+use near_chain_configs::{GenesisValidationMode, MutableConfigValue, ReshardingConfig};
 use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
 use near_epoch_manager::types::EpochInfoAggregator;
 use near_epoch_manager::EpochManager;
@@ -244,6 +244,11 @@ fn load_snapshot(load_cmd: LoadCmd) {
             save_trie_changes: config.client_config.save_trie_changes,
             background_migration_threads: 1,
             state_snapshot_every_n_blocks: None,
+            chunk_replay_artifacts_dir: None,
+            resharding_config: MutableConfigValue::new(
+                ReshardingConfig::default(),
+                "resharding_config",
+            ),
         },
         None,
     )
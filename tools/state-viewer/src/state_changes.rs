@@ -189,6 +189,7 @@ fn apply_state_changes(
                 trie_update,
                 state_changes,
                 *block_hash,
+                block_height,
             );
             let mut store_update = chain_store.store_update();
             store_update.save_trie_changes(wrapped_trie_changes);